@@ -0,0 +1,191 @@
+//! --bigkeys 和 --memkeys 模式，分析键空间
+//! 用 SCAN 遍历所有键，对每批键以管道方式查询类型和大小，找出每种类型最大的键并统计各类型的键数和平均大小。
+//! --bigkeys 按长度比较：字符串为字节数，集合类型为元素数，JSON 文档和时间序列没有长度，按 MEMORY USAGE 估算的字节数比较；
+//! --memkeys 所有类型都按 MEMORY USAGE 估算的字节数比较。
+
+use crate::args::CliArgs;
+use crate::connect;
+use crate::scan::parse_cursor;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::{quote, text, Command, Protocol, RedoxValue, Response};
+use std::process::ExitCode;
+
+/// 每次 SCAN 检查的键数，也是每批以管道方式查询的键数
+const SCAN_COUNT: usize = 100;
+
+/// 已知的类型：TYPE 返回的名称、汇总中的复数名称和 --bigkeys 中长度的单位
+const TYPES: &[(&str, &str, &str)] = &[
+    ("string", "strings", "bytes"),
+    ("list", "lists", "items"),
+    ("set", "sets", "members"),
+    ("hash", "hashes", "fields"),
+    ("zset", "zsets", "members"),
+    ("ReJSON-RL", "JSON documents", "bytes"),
+    ("TSDB-TYPE", "time series", "bytes"),
+];
+
+/// 一种类型的统计
+struct TypeStats {
+    /// TYPE 返回的名称
+    name: String,
+    /// 键数
+    keys: u64,
+    /// 所有键的大小之和
+    total: u64,
+    /// 最大的键和它的大小
+    biggest: Option<(Bytes, u64)>,
+}
+
+/// 遍历键空间并输出每种类型最大的键和汇总
+/// --memkeys 时按估算的内存比较，否则按长度比较
+pub async fn bigkeys(args: &CliArgs) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (_, mut framed) = connect(args).await?;
+    let pattern = args.pattern.as_ref().map(|pattern| Bytes::copy_from_slice(pattern.as_bytes()));
+    let mut stats: Vec<TypeStats> = TYPES.iter().map(|(name, ..)| TypeStats::new(name)).collect();
+    let mut sampled = 0u64;
+    let mut key_bytes = 0u64;
+    let mut cursor = 0;
+
+    if args.memkeys {
+        println!("# Scanning the entire keyspace to find the keys using the most memory per type");
+    } else {
+        println!("# Scanning the entire keyspace to find the biggest keys per type");
+    }
+    println!();
+    loop {
+        framed.send(&Command::Scan { cursor, pattern: pattern.clone(), count: Some(SCAN_COUNT) }).await?;
+        let reply = framed.next().await.ok_or("Connection closed by server")??;
+        if let Response::Error(e) = reply {
+            eprint!("{}", Protocol::encode_response(&Response::Error(e)));
+            return Ok(ExitCode::FAILURE);
+        }
+        let (next, keys) = parse_cursor(reply).ok_or("Unexpected reply to SCAN")?;
+
+        // 先以管道方式查询这一批键的类型，再查询大小，遍历期间被删除的键跳过
+        for key in &keys {
+            framed.feed(&Command::Type { key: key.clone() }).await?;
+        }
+        framed.flush().await?;
+        let mut typed = Vec::with_capacity(keys.len());
+        for key in keys {
+            let reply = framed.next().await.ok_or("Connection closed by server")??;
+            match reply {
+                Response::Value(RedoxValue::String(name)) if name.as_ref() != b"none" => {
+                    typed.push((key, text(&name).into_owned()));
+                }
+                _ => {}
+            }
+        }
+        for (key, name) in &typed {
+            framed.feed(&size_command(args, key, name)).await?;
+        }
+        framed.flush().await?;
+        for (key, name) in typed {
+            let reply = framed.next().await.ok_or("Connection closed by server")??;
+            let Some(size) = integer(&reply) else {
+                continue;
+            };
+            sampled += 1;
+            key_bytes += key.len() as u64;
+            let index = match stats.iter().position(|stats| stats.name == name) {
+                Some(index) => index,
+                None => {
+                    stats.push(TypeStats::new(&name));
+                    stats.len() - 1
+                }
+            };
+            let stats = &mut stats[index];
+            stats.keys += 1;
+            stats.total += size;
+            if stats.biggest.as_ref().is_none_or(|(_, biggest)| size > *biggest) {
+                println!("Biggest {:<9} found so far {} with {} {}", name, quote(&key), size, unit(args, &name));
+                stats.biggest = Some((key, size));
+            }
+        }
+
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+
+    println!();
+    println!("-------- summary -------");
+    println!();
+    println!("Sampled {} keys in the keyspace", sampled);
+    println!("Total key length in bytes is {} (avg len {:.2})", key_bytes, average(key_bytes, sampled));
+    println!();
+    for stats in &stats {
+        if let Some((key, size)) = &stats.biggest {
+            println!("Biggest {:>9} found {} has {} {}", stats.name, quote(key), size, unit(args, &stats.name));
+        }
+    }
+    println!();
+    for stats in &stats {
+        println!(
+            "{} {} with {} {} ({:.2}% of keys, avg size {:.2})",
+            stats.keys,
+            plural(&stats.name),
+            stats.total,
+            unit(args, &stats.name),
+            average(stats.keys * 100, sampled),
+            average(stats.total, stats.keys),
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+impl TypeStats {
+    fn new(name: &str) -> Self {
+        TypeStats { name: name.to_string(), keys: 0, total: 0, biggest: None }
+    }
+}
+
+/// 查询键的大小的命令
+///
+/// # Arguments
+/// * `args` - 命令行参数，--memkeys 时查询估算的内存
+/// * `key` - 键
+/// * `name` - TYPE 返回的类型名称
+fn size_command(args: &CliArgs, key: &Bytes, name: &str) -> Command {
+    let key = key.clone();
+    if args.memkeys {
+        return Command::MemoryUsage { key, samples: args.memkeys_samples };
+    }
+    match name {
+        "string" => Command::StrLen { key },
+        "list" => Command::LLen { key },
+        "set" => Command::SCard { key },
+        "hash" => Command::HLen { key },
+        "zset" => Command::ZCard { key },
+        _ => Command::MemoryUsage { key, samples: None },
+    }
+}
+
+/// 大小的单位
+fn unit(args: &CliArgs, name: &str) -> &'static str {
+    if args.memkeys {
+        return "bytes";
+    }
+    TYPES.iter().find(|(known, ..)| *known == name).map_or("bytes", |(.., unit)| unit)
+}
+
+/// 汇总中类型的复数名称
+fn plural(name: &str) -> String {
+    TYPES.iter().find(|(known, ..)| *known == name).map_or_else(|| name.to_string(), |(_, plural, _)| plural.to_string())
+}
+
+/// 回复中的非负整数，二进制格式为整数，行协议为数字字符串；键不存在或出错时为 None
+fn integer(reply: &Response) -> Option<u64> {
+    match reply {
+        Response::Integer(n) => u64::try_from(*n).ok(),
+        Response::Value(RedoxValue::String(s)) => text(s).parse().ok(),
+        _ => None,
+    }
+}
+
+/// 平均值，没有样本时为 0
+fn average(total: u64, count: u64) -> f64 {
+    if count == 0 { 0.0 } else { total as f64 / count as f64 }
+}
@@ -0,0 +1,328 @@
+//! 集群支持
+//! -c 时跟随 MOVED 和 ASK 重定向：连接到负责该槽的节点并重新发送命令，与 redis-cli -c 相同；
+//! --cluster info 汇总各主节点的键数、槽数和副本数，--cluster check 检查各节点的槽配置是否一致、
+//! 是否有正在迁移的槽以及所有槽是否都有节点负责。
+
+use crate::args::CliArgs;
+use crate::{connect, connect_node, Transport};
+use clap::ValueEnum;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::slot::SLOTS;
+use redox_protocol::{text, Command, RedoxError, RedoxValue, Response};
+use std::process::ExitCode;
+
+/// 一个命令最多跟随的重定向次数，避免槽迁移期间节点之间来回重定向
+const MAX_REDIRECTS: usize = 16;
+
+/// --cluster 的子命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ClusterCommand {
+    /// Keys, slots and replicas of each master
+    Info,
+    /// Check slot coverage, open slots and whether all nodes agree about the configuration
+    Check,
+}
+
+/// 回复是 MOVED 或 ASK 时连接到负责该槽的节点并重新发送命令，直到得到其他回复
+/// 没有指定 -c 时原样返回回复；之后的命令也发送到新的节点
+///
+/// # Arguments
+/// * `args` - 命令行参数
+/// * `framed` - 当前的连接，重定向时替换为新节点的连接
+/// * `cmd` - 已发送的命令
+/// * `response` - 命令的回复
+pub async fn follow(
+    args: &CliArgs,
+    framed: &mut Transport,
+    cmd: &Command,
+    mut response: Response,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    if !args.follow_redirects {
+        return Ok(response);
+    }
+    for _ in 0..MAX_REDIRECTS {
+        let (slot, addr, asking) = match &response {
+            Response::Error(RedoxError::Moved { slot, addr }) => (*slot, addr.clone(), false),
+            Response::Error(RedoxError::Ask { slot, addr }) => (*slot, addr.clone(), true),
+            _ => return Ok(response),
+        };
+        eprintln!("-> Redirected to slot [{}] located at {}", slot, addr);
+        let (host, port) = split_addr(&addr).ok_or_else(|| format!("Invalid redirect address: {}", addr))?;
+        *framed = connect_node(args, host, port).await?;
+        // ASK 只对紧接着的一个命令有效，先发送 ASKING
+        if asking {
+            framed.send(&Command::Asking).await?;
+            if let Response::Error(e) = framed.next().await.ok_or("Connection closed by server")?? {
+                return Ok(Response::Error(e));
+            }
+        }
+        framed.send(cmd).await?;
+        response = framed.next().await.ok_or("Connection closed by server")??;
+    }
+    Ok(response)
+}
+
+/// 拆分 `host:port`，IPv6 地址可以带方括号
+fn split_addr(addr: &str) -> Option<(&str, u16)> {
+    let (host, port) = addr.rsplit_once(':')?;
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    Some((host, port.parse().ok()?))
+}
+
+/// CLUSTER NODES 中的一个节点
+struct Node {
+    id: String,
+    host: String,
+    port: u16,
+    /// 是否是回复 CLUSTER NODES 的节点自己
+    myself: bool,
+    /// 被标记为下线
+    failed: bool,
+    /// 副本复制的主节点，主节点为 None
+    primary: Option<String>,
+    /// 负责的槽范围
+    slots: Vec<(u16, u16)>,
+    /// 正在迁出的槽
+    migrating: Vec<u16>,
+    /// 正在迁入的槽
+    importing: Vec<u16>,
+}
+
+impl Node {
+    fn addr(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    fn slot_count(&self) -> usize {
+        self.slots.iter().map(|(start, end)| usize::from(end - start) + 1).sum()
+    }
+}
+
+/// 解析 CLUSTER NODES 的一行：`id host:port@bus flags primary ping pong epoch link slot...`
+fn parse_node(line: &str) -> Option<Node> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [id, addr, flags, primary, _, _, _, _, slots @ ..] = fields.as_slice() else {
+        return None;
+    };
+    let (host, port) = split_addr(addr.split('@').next()?)?;
+    let flags: Vec<&str> = flags.split(',').collect();
+    let mut node = Node {
+        id: id.to_string(),
+        host: host.to_string(),
+        port,
+        myself: flags.contains(&"myself"),
+        failed: flags.contains(&"fail"),
+        primary: (*primary != "-").then(|| primary.to_string()),
+        slots: Vec::new(),
+        migrating: Vec::new(),
+        importing: Vec::new(),
+    };
+    for slot in slots {
+        // 迁移中的槽写作 [slot->-target] 和 [slot-<-source]
+        if let Some(open) = slot.strip_prefix('[').and_then(|slot| slot.strip_suffix(']')) {
+            if let Some((slot, _)) = open.split_once("->-") {
+                node.migrating.push(slot.parse().ok()?);
+            } else if let Some((slot, _)) = open.split_once("-<-") {
+                node.importing.push(slot.parse().ok()?);
+            }
+            continue;
+        }
+        let (start, end) = slot.split_once('-').unwrap_or((slot, slot));
+        node.slots.push((start.parse().ok()?, end.parse().ok()?));
+    }
+    Some(node)
+}
+
+/// 在连接上执行 CLUSTER NODES 并解析所有节点，按地址排序
+async fn cluster_nodes(framed: &mut Transport) -> Result<Vec<Node>, Box<dyn std::error::Error>> {
+    framed.send(&Command::ClusterNodes).await?;
+    let lines: Vec<String> = match framed.next().await.ok_or("Connection closed by server")?? {
+        Response::Error(e) => return Err(e.into()),
+        Response::Array(lines) => lines.into_iter().flatten().map(|line| text(&line).into_owned()).collect(),
+        Response::Value(RedoxValue::String(s)) => text(&s).lines().map(str::to_string).collect(),
+        _ => return Err("Unexpected reply to CLUSTER NODES".into()),
+    };
+    let mut nodes = lines
+        .iter()
+        .map(|line| parse_node(line).ok_or_else(|| format!("Invalid CLUSTER NODES line: {}", line)))
+        .collect::<Result<Vec<_>, _>>()?;
+    nodes.sort_by_key(Node::addr);
+    Ok(nodes)
+}
+
+/// 执行 --cluster 的子命令，发现问题时退出码为 1
+pub async fn cluster(args: &CliArgs, command: ClusterCommand) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (addr, mut framed) = connect(args).await?;
+    let nodes = cluster_nodes(&mut framed).await?;
+    match command {
+        ClusterCommand::Info => info(args, &nodes).await,
+        ClusterCommand::Check => check(args, &addr, &nodes).await,
+    }
+}
+
+/// 输出每个主节点的键数、槽数和副本数
+async fn info(args: &CliArgs, nodes: &[Node]) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let mut total_keys = 0u64;
+    let mut masters = 0;
+    let mut unreachable = false;
+    for node in nodes.iter().filter(|node| node.primary.is_none()) {
+        masters += 1;
+        let replicas = nodes.iter().filter(|replica| replica.primary.as_ref() == Some(&node.id)).count();
+        let keys = match node_keys(args, node).await {
+            Ok(keys) => keys,
+            Err(e) => {
+                println!("[ERR] {} ({}): {}", node.addr(), short_id(&node.id), e);
+                unreachable = true;
+                continue;
+            }
+        };
+        total_keys += keys;
+        println!(
+            "{} ({}...) -> {} keys | {} slots | {} replicas.",
+            node.addr(),
+            short_id(&node.id),
+            keys,
+            node.slot_count(),
+            replicas
+        );
+    }
+    println!("[OK] {} keys in {} masters.", total_keys, masters);
+    println!("{:.2} keys per slot on average.", total_keys as f64 / f64::from(SLOTS));
+    Ok(if unreachable { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// 节点上的键数，来自 INFO 的 keys 字段
+async fn node_keys(args: &CliArgs, node: &Node) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut framed = connect_node(args, &node.host, node.port).await?;
+    framed.send(&Command::Info).await?;
+    match framed.next().await.ok_or("Connection closed by server")?? {
+        Response::Info(info) => Ok(info.get("keys").and_then(|keys| keys.parse().ok()).unwrap_or(0)),
+        Response::Error(e) => Err(e.into()),
+        _ => Err("Unexpected reply to INFO".into()),
+    }
+}
+
+/// 检查集群的配置，输出格式与 redis-cli --cluster check 相似
+async fn check(args: &CliArgs, addr: &str, nodes: &[Node]) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let mut errors = 0;
+    println!(">>> Performing Cluster Check (using node {})", addr);
+    for node in nodes.iter().filter(|node| node.primary.is_none()) {
+        println!("M: {} {}", node.id, node.addr());
+        println!("   slots:{} ({} slots) master", format_slots(&node.slots), node.slot_count());
+        let replicas = nodes.iter().filter(|replica| replica.primary.as_ref() == Some(&node.id)).count();
+        println!("   {} additional replica(s)", replicas);
+    }
+    for node in nodes.iter().filter(|node| node.primary.is_some()) {
+        println!("S: {} {}", node.id, node.addr());
+        println!("   slots: (0 slots) slave");
+        println!("   replicates {}", node.primary.as_deref().unwrap_or_default());
+    }
+    for node in nodes.iter().filter(|node| node.failed) {
+        println!("[ERR] Node {} is marked as failed", node.addr());
+        errors += 1;
+    }
+
+    // 每个节点各自的视图中槽的分配应当相同；迁移中的槽只在节点自己的视图中列出，
+    // 槽的覆盖也按每个节点自己声明的槽计算，无法连接的节点使用第一个节点的视图
+    let expected = slot_owners(nodes);
+    let mut agree = true;
+    let mut open = Vec::new();
+    let mut covered = vec![false; usize::from(SLOTS)];
+    for node in nodes.iter().filter(|node| !node.failed) {
+        let view = match connect_node(args, &node.host, node.port).await {
+            Ok(mut framed) => cluster_nodes(&mut framed).await,
+            Err(e) => Err(e),
+        };
+        match view {
+            Ok(view) => {
+                if slot_owners(&view) != expected {
+                    agree = false;
+                }
+                if let Some(myself) = view.into_iter().find(|node| node.myself) {
+                    cover(&mut covered, &myself.slots);
+                    open.push((node.addr(), myself.migrating, myself.importing));
+                }
+            }
+            Err(e) => {
+                cover(&mut covered, &node.slots);
+                println!("[ERR] {}: {}", node.addr(), e);
+                errors += 1;
+            }
+        }
+    }
+    if agree {
+        println!("[OK] All nodes agree about slots configuration.");
+    } else {
+        println!("[ERR] Nodes don't agree about configuration!");
+        errors += 1;
+    }
+
+    println!(">>> Check for open slots...");
+    for (addr, migrating, importing) in &open {
+        if !migrating.is_empty() {
+            println!("[WARNING] Node {} has slots in migrating state {}.", addr, join(migrating));
+        }
+        if !importing.is_empty() {
+            println!("[WARNING] Node {} has slots in importing state {}.", addr, join(importing));
+        }
+    }
+
+    println!(">>> Check slots coverage...");
+    let covered = covered.iter().filter(|covered| **covered).count();
+    if covered == usize::from(SLOTS) {
+        println!("[OK] All {} slots covered.", SLOTS);
+    } else {
+        println!("[ERR] Not all {} slots are covered by nodes ({} uncovered).", SLOTS, usize::from(SLOTS) - covered);
+        errors += 1;
+    }
+    Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+/// 每个槽由哪个节点负责
+fn slot_owners(nodes: &[Node]) -> Vec<Option<&str>> {
+    let mut owners = vec![None; usize::from(SLOTS)];
+    for node in nodes {
+        for (start, end) in &node.slots {
+            for slot in *start..=*end {
+                if let Some(owner) = owners.get_mut(usize::from(slot)) {
+                    *owner = Some(node.id.as_str());
+                }
+            }
+        }
+    }
+    owners
+}
+
+/// 把槽范围标记为有节点负责
+fn cover(covered: &mut [bool], slots: &[(u16, u16)]) {
+    for (start, end) in slots {
+        for slot in *start..=*end {
+            if let Some(covered) = covered.get_mut(usize::from(slot)) {
+                *covered = true;
+            }
+        }
+    }
+}
+
+/// 槽范围写作 `[0-5460],[10923]`
+fn format_slots(slots: &[(u16, u16)]) -> String {
+    slots
+        .iter()
+        .map(|(start, end)| if start == end { format!("[{}]", start) } else { format!("[{}-{}]", start, end) })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 以逗号分隔的槽
+fn join(slots: &[u16]) -> String {
+    slots.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// 节点 ID 的前 8 个字符
+fn short_id(id: &str) -> &str {
+    id.get(..8).unwrap_or(id)
+}
@@ -0,0 +1,193 @@
+//! 交互模式的 Tab 补全
+//! 第一个词补全命令名，带子命令的命令（如 CONFIG）的第二个词补全子命令名，两者都来自 redox-protocol 的命令表，
+//! HELP 之后同样补全命令名、子命令名和 `@类别`；
+//! 命令表中标为键的参数通过 SCAN 向服务器查找以已输入部分开头的键，只在按下 Tab 时查找，不预先加载键名。
+
+use crate::scan::parse_cursor;
+use crate::Transport;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::meta::{self, Category, CommandSpec};
+use redox_protocol::{quote, text, Command};
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::Mutex;
+
+/// 与服务器的连接，执行命令和补全键名共用
+pub type Connection = Arc<Mutex<Transport>>;
+
+/// 补全键名时每次 SCAN 检查的键数
+const SCAN_COUNT: usize = 1000;
+
+/// 补全键名时最多列出的键数
+const MAX_KEY_CANDIDATES: usize = 100;
+
+/// 一次补全最多发送的 SCAN 次数，键很多时只在前一部分中查找，避免长时间没有响应
+const MAX_SCAN_CALLS: usize = 100;
+
+/// rustyline 的补全器，其余的 Hinter、Highlighter 和 Validator 使用默认实现
+pub struct RedoxHelper {
+    /// 与服务器的连接
+    connection: Connection,
+    /// 补全在 rustyline 的同步回调中进行，通过它执行 SCAN
+    runtime: Handle,
+}
+
+impl RedoxHelper {
+    /// 创建补全器，需要在 tokio 运行时中调用
+    ///
+    /// # Arguments
+    /// * `connection` - 与服务器的连接
+    pub fn new(connection: Connection) -> Self {
+        Self { connection, runtime: Handle::current() }
+    }
+
+    /// 查找以 prefix 开头的键，按字典序排列
+    /// readline 在 `block_in_place` 中调用，这里可以阻塞地等待服务器的回复
+    fn keys(&self, prefix: &str) -> Vec<Pair> {
+        self.runtime
+            .block_on(self.scan(prefix))
+            .into_iter()
+            .map(|key| Pair { display: text(&key).into_owned(), replacement: quote(&key).into_owned() })
+            .collect()
+    }
+
+    /// 用 SCAN 遍历键，找到足够的键、遍历结束或达到次数上限时停止，出错时返回已经找到的键
+    async fn scan(&self, prefix: &str) -> Vec<Bytes> {
+        // 服务器的通配符不支持转义，前缀中有通配符时只在本地过滤
+        let pattern = (!prefix.contains(['*', '?'])).then(|| Bytes::from(format!("{}*", prefix)));
+        let mut connection = self.connection.lock().await;
+        let mut keys = BTreeSet::new();
+        let mut cursor = 0;
+        for _ in 0..MAX_SCAN_CALLS {
+            let cmd = Command::Scan { cursor, pattern: pattern.clone(), count: Some(SCAN_COUNT) };
+            if connection.send(&cmd).await.is_err() {
+                break;
+            }
+            let Some((next, batch)) = connection.next().await.and_then(Result::ok).and_then(parse_cursor) else {
+                break;
+            };
+            keys.extend(batch.into_iter().filter(|key| key.starts_with(prefix.as_bytes())));
+            cursor = next;
+            if cursor == 0 || keys.len() >= MAX_KEY_CANDIDATES {
+                break;
+            }
+        }
+        keys.into_iter().take(MAX_KEY_CANDIDATES).collect()
+    }
+}
+
+impl Completer for RedoxHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let head = &line[..pos];
+        let start = head.rfind(|c: char| c.is_ascii_whitespace()).map_or(0, |i| i + 1);
+        let word = &head[start..];
+        // 正在输入带引号的参数时不补全
+        if word.starts_with(['"', '\'']) {
+            return Ok((start, Vec::new()));
+        }
+        let words: Vec<&str> = head[..start].split_ascii_whitespace().collect();
+        let candidates = match words.as_slice() {
+            [] => plain(command_names(word)),
+            [help] if help.eq_ignore_ascii_case("help") => plain(help_topics(word)),
+            [help, container] if help.eq_ignore_ascii_case("help") && has_subcommands(container) => {
+                plain(subcommand_names(container, word))
+            }
+            [container] if has_subcommands(container) => plain(subcommand_names(container, word)),
+            [name, rest @ ..] => {
+                // 带子命令的命令按 `容器|子命令` 查找，键的位置包括子命令名
+                let spec = rest.first()
+                    .and_then(|sub| meta::lookup(&format!("{}|{}", name, sub)))
+                    .or_else(|| meta::lookup(name));
+                match spec {
+                    Some(spec) if is_key_arg(spec, words.len()) => self.keys(word),
+                    _ => Vec::new(),
+                }
+            }
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for RedoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for RedoxHelper {}
+
+impl Validator for RedoxHelper {}
+
+impl Helper for RedoxHelper {}
+
+/// 以 prefix 开头的命令名，带子命令的命令只列出容器名
+fn command_names(prefix: &str) -> Vec<String> {
+    let lower = prefix.to_lowercase();
+    let names: BTreeSet<&str> = meta::COMMANDS.iter()
+        .map(|spec| spec.name.split('|').next().unwrap_or(spec.name))
+        .filter(|name| name.starts_with(&lower))
+        .collect();
+    names.into_iter().map(|name| match_case(name, prefix)).collect()
+}
+
+/// HELP 的主题：以 `@` 开头时为类别，否则为命令名
+fn help_topics(prefix: &str) -> Vec<String> {
+    match prefix.strip_prefix('@') {
+        Some(name) => Category::ALL.iter()
+            .map(|category| category.as_str())
+            .filter(|category| category.starts_with(&name.to_lowercase()))
+            .map(|category| format!("@{}", category))
+            .collect(),
+        None => command_names(prefix),
+    }
+}
+
+/// 命令是否带有子命令，如 CONFIG
+fn has_subcommands(name: &str) -> bool {
+    let container = format!("{}|", name.to_lowercase());
+    meta::COMMANDS.iter().any(|spec| spec.name.starts_with(&container))
+}
+
+/// 容器命令中以 prefix 开头的子命令名
+fn subcommand_names(container: &str, prefix: &str) -> Vec<String> {
+    let container = format!("{}|", container.to_lowercase());
+    let lower = prefix.to_lowercase();
+    meta::COMMANDS.iter()
+        .filter_map(|spec| spec.name.strip_prefix(&container))
+        .filter(|sub| sub.starts_with(&lower))
+        .map(|sub| match_case(sub, prefix))
+        .collect()
+}
+
+/// 第 index 个词（命令名为第 0 个）是否是命令的键
+fn is_key_arg(spec: &CommandSpec, index: usize) -> bool {
+    if spec.first_key <= 0 || index < spec.first_key as usize {
+        return false;
+    }
+    // last_key 为负数时键一直到最后一个参数
+    if spec.last_key > 0 && index > spec.last_key as usize {
+        return false;
+    }
+    (index - spec.first_key as usize).is_multiple_of(spec.step.max(1) as usize)
+}
+
+/// 按已输入部分的大小写输出名称：输入的全是小写时用小写，否则用大写
+fn match_case(name: &str, typed: &str) -> String {
+    if !typed.is_empty() && typed.chars().all(|c| !c.is_uppercase()) {
+        name.to_string()
+    } else {
+        name.to_uppercase()
+    }
+}
+
+/// 显示和替换内容相同的候选
+fn plain(names: Vec<String>) -> Vec<Pair> {
+    names.into_iter().map(|name| Pair { display: name.clone(), replacement: name }).collect()
+}
@@ -0,0 +1,66 @@
+//! 交互模式的 HELP
+//! `help` 显示用法，`help <命令>` 显示命令的写法、简介、加入的版本和类别，带子命令的命令（如 `help config`）列出所有子命令，
+//! `help @<类别>` 列出类别中的所有命令。内容来自 redox-protocol 的命令表，不需要向服务器发送命令。
+
+use redox_protocol::meta::{self, Category, CommandSpec};
+
+/// 输入是否是 HELP 命令，HELP 在本地处理，不发送给服务器
+pub fn is_help(words: &[&str]) -> bool {
+    words.first().is_some_and(|name| name.eq_ignore_ascii_case("help"))
+}
+
+/// HELP 的输出
+///
+/// # Arguments
+/// * `topic` - help 之后的词，为空时显示用法
+pub fn help(topic: &[&str]) -> String {
+    let specs: Vec<&CommandSpec> = match topic {
+        [] => return usage(),
+        [word] if word.starts_with('@') => match Category::parse(&word[1..]) {
+            Some(category) => meta::COMMANDS.iter().filter(|spec| spec.category == category).collect(),
+            None => return format!("Unknown category '{}'. Type 'help' for the list of categories.\n", word),
+        },
+        [name] => match meta::lookup(name) {
+            Some(spec) => vec![spec],
+            None => subcommands(name),
+        },
+        [name, sub, ..] => meta::lookup(&format!("{}|{}", name, sub)).or_else(|| meta::lookup(name)).into_iter().collect(),
+    };
+    if specs.is_empty() {
+        return format!("No help found for '{}'. Type 'help' for usage.\n", topic.join(" "));
+    }
+    specs.into_iter().map(describe).collect()
+}
+
+/// 容器命令的所有子命令，如 CONFIG 的 GET 和 SET
+fn subcommands(container: &str) -> Vec<&'static CommandSpec> {
+    let prefix = format!("{}|", container.to_lowercase());
+    meta::COMMANDS.iter().filter(|spec| spec.name.starts_with(&prefix)).collect()
+}
+
+/// 一个命令的帮助，与 redis-cli 的格式相同
+fn describe(spec: &CommandSpec) -> String {
+    format!(
+        "\n  {}\n  summary: {}\n  since: {}\n  category: {}\n",
+        spec.usage(),
+        spec.summary,
+        spec.since,
+        spec.category.as_str()
+    )
+}
+
+/// 不带参数的 help 显示的用法
+fn usage() -> String {
+    let categories: Vec<String> = Category::ALL.iter().map(|category| format!("@{}", category.as_str())).collect();
+    format!(
+        "redox-cli {}\n\
+         To get help about Redox commands type:\n      \
+         \"help @<category>\" to get a list of commands in <category>\n      \
+         \"help <command>\" for help on <command>\n      \
+         \"help <tab>\" to get a list of possible help topics\n      \
+         \"quit\" to exit\n\n\
+         Categories: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        categories.join(", ")
+    )
+}
@@ -0,0 +1,94 @@
+//! --latency 和 --latency-history 模式，测量往返延迟
+//! 持续发送 PING 并测量从发送到收到回复的时间，按 Ctrl-C 停止。--latency 在同一行不断更新从开始以来的统计，
+//! --latency-history 每隔 --interval 秒输出一行这段时间的统计并重新开始统计。
+//! PING 在服务器上几乎不需要执行时间，测得的延迟主要来自网络和服务器的排队，与应用中命令的延迟比较可以区分网络慢还是命令慢。
+
+use crate::args::CliArgs;
+use crate::connect;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::{Command, Protocol, Response};
+use std::io::{self, IsTerminal, Write};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// 两次 PING 之间的间隔
+const PING_INTERVAL: Duration = Duration::from_millis(10);
+
+/// --latency-history 默认每隔多少秒输出一行
+const DEFAULT_HISTORY_INTERVAL: f64 = 15.0;
+
+/// --latency 输出到终端时刷新统计的间隔，输出到管道或文件时每秒输出一行
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+const PIPE_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 持续测量延迟并输出统计
+pub async fn latency(args: &CliArgs) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (_, mut framed) = connect(args).await?;
+    let terminal = io::stdout().is_terminal();
+    let history = args
+        .latency_history
+        .then(|| Duration::from_secs_f64(args.interval.unwrap_or(DEFAULT_HISTORY_INTERVAL)));
+    let refresh = if terminal { REFRESH_INTERVAL } else { PIPE_REFRESH_INTERVAL };
+    let mut samples = Vec::new();
+    let mut started = Instant::now();
+    let mut refreshed = Instant::now();
+
+    loop {
+        let sent = Instant::now();
+        framed.send(&Command::Ping { message: None }).await?;
+        let reply = framed.next().await.ok_or("Connection closed by server")??;
+        // 往返时间以微秒保存
+        samples.push(sent.elapsed().as_micros() as u64);
+        if let Response::Error(e) = reply {
+            eprint!("{}", Protocol::encode_response(&Response::Error(e)));
+            return Ok(ExitCode::FAILURE);
+        }
+
+        match history {
+            Some(interval) if started.elapsed() >= interval => {
+                println!("{} -- {:.2} seconds range", summary(&mut samples), started.elapsed().as_secs_f64());
+                samples.clear();
+                started = Instant::now();
+            }
+            Some(_) => {}
+            None if refreshed.elapsed() >= refresh => {
+                // 在终端中清除当前行后重新输出，否则每次输出一行
+                if terminal {
+                    print!("\r\x1b[2K{}", summary(&mut samples));
+                    io::stdout().flush()?;
+                } else {
+                    println!("{}", summary(&mut samples));
+                }
+                refreshed = Instant::now();
+            }
+            None => {}
+        }
+        sleep(PING_INTERVAL).await;
+    }
+}
+
+/// 一组样本的最小值、最大值、平均值和 p99，单位为毫秒
+/// 计算 p99 时会重新排列样本的顺序
+fn summary(samples: &mut [u64]) -> String {
+    let (Some(min), Some(max)) = (samples.iter().min().copied(), samples.iter().max().copied()) else {
+        return "no samples".to_string();
+    };
+    let avg = samples.iter().sum::<u64>() as f64 / samples.len() as f64;
+    // 最近秩法：第 ceil(0.99 * n) 小的样本
+    let rank = (samples.len() * 99).div_ceil(100).max(1) - 1;
+    let p99 = *samples.select_nth_unstable(rank).1;
+    format!(
+        "min: {:.2}, max: {:.2}, avg: {:.2}, p99: {:.2} ({} samples)",
+        millis(min),
+        millis(max),
+        avg / 1000.0,
+        millis(p99),
+        samples.len()
+    )
+}
+
+/// 微秒换算为毫秒
+fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
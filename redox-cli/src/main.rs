@@ -1,37 +1,150 @@
-use std::env;
-use std::io::{self, Write};
-use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+mod args;
+mod bigkeys;
+mod cluster;
+mod completion;
+mod help;
+mod latency;
+mod monitor;
+mod output;
+mod pipe;
+mod scan;
+mod tls;
+mod transfer;
+
+use args::{CliArgs, ConnectionInfo};
+use bytes::Bytes;
+use clap::Parser;
+use completion::{Connection, RedoxHelper};
+use futures::{SinkExt, StreamExt};
+use redox_client::{is_idempotent, RetryPolicy};
+use redox_protocol::codec::{ClientCodec, CodecError};
+use redox_protocol::{Command, Protocol, RedoxError, Response};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tls::Stream;
 use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::task;
 use tokio::time::sleep;
+use tokio_util::codec::Framed;
+
+/// 与服务器的连接
+pub type Transport = Framed<Stream, ClientCodec>;
 
-const MAX_RETRIES: u32 = 3;
-const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// 不执行命令的运行模式，每次只能使用其中一个
+const MODES: &str = "--pipe, --scan, --monitor, --bigkeys, --memkeys, --latency, --latency-history and --cluster";
 
 /// 客户端入口函数
+/// 单次执行命令时，命令出错、回复是错误或连接失败的退出码为 1
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let port = env::args()
-        .nth(1)
-        .and_then(|p| p.parse::<u16>().ok())
-        .unwrap_or(2001);
+async fn main() -> ExitCode {
+    let args = CliArgs::parse();
+    let latency = args.latency || args.latency_history;
+    let modes = [args.pipe, args.scan, args.monitor, args.bigkeys, args.memkeys, latency, args.cluster.is_some()]
+        .into_iter()
+        .filter(|mode| *mode)
+        .count();
+    let result = match args.command() {
+        _ if modes > 1 => Err(format!("{} cannot be combined", MODES).into()),
+        [] if args.pipe => pipe::pipe(&args).await,
+        [] if args.scan => scan::scan(&args).await,
+        [] if args.monitor => monitor::monitor(&args).await,
+        [] if args.bigkeys || args.memkeys => bigkeys::bigkeys(&args).await,
+        [] if latency => latency::latency(&args).await,
+        [] if let Some(command) = args.cluster => cluster::cluster(&args, command).await,
+        [] => interactive(&args).await,
+        _ if modes > 0 => Err(format!("{} cannot be combined with a command", MODES).into()),
+        command @ [name, ..] if name == "export" => transfer::export(&args, command).await,
+        command @ [name, ..] if name == "import" => transfer::import(&args, command).await,
+        command => one_shot(&args, command).await,
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let addr = format!("127.0.0.1:{}", port);
-    let stream = connect_with_retry(&addr).await?;
-    let (reader, writer) = stream.into_split();
+/// 连接服务器，指定了二进制格式时使用二进制格式代替行协议
+/// 指定了 TLS 时先完成 TLS 握手；指定了密码时连接后先发送 AUTH，认证失败时返回错误
+async fn connect(args: &CliArgs) -> Result<(String, Transport), Box<dyn std::error::Error>> {
+    let info = args.connection()?;
+    let framed = connect_node(args, &info.host, info.port).await?;
+    Ok((info.addr(), framed))
+}
+
+/// 连接指定的节点，认证信息、TLS 和传输格式与命令行指定的服务器相同，用于跟随集群的重定向和检查集群的各个节点
+///
+/// # Arguments
+/// * `args` - 命令行参数
+/// * `host` - 节点的主机名或地址
+/// * `port` - 节点的端口
+async fn connect_node(args: &CliArgs, host: &str, port: u16) -> Result<Transport, Box<dyn std::error::Error>> {
+    let info = ConnectionInfo { host: host.to_string(), port, ..args.connection()? };
+    let addr = info.addr();
+    let socket = connect_with_retry(&addr).await?;
+    let stream = if info.tls { tls::handshake(args, &info.host, socket).await? } else { Stream::Plain(socket) };
+    let codec = args.format().map(ClientCodec::binary).unwrap_or_default();
+    let mut framed = Framed::new(stream, codec);
+    if let Some(password) = info.password {
+        framed.send(&Command::Auth { username: info.username, password }).await?;
+        if let Response::Error(e) = framed.next().await.ok_or("Connection closed by server")?? {
+            return Err(format!("AUTH failed: {}", e).into());
+        }
+    }
+    Ok(framed)
+}
+
+/// 执行一条命令，把回复原样输出到标准输出
+/// 每个命令行参数是命令的一个参数，不需要再加引号
+async fn one_shot(args: &CliArgs, command: &[String]) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let command: Vec<Bytes> = command.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect();
+    let cmd = Protocol::decode_args(&command)
+        .map_err(|e| Protocol::encode_response(&Response::Error(RedoxError::Syntax(e))).trim_end().to_string())?;
+    let (_, mut framed) = connect(args).await?;
+    if monitor::is_streaming(&cmd) {
+        return monitor::stream(&mut framed, &cmd, args.output()).await;
+    }
+    framed.send(&cmd).await?;
+    let response = framed.next().await.ok_or("Connection closed by server")??;
+    let response = cluster::follow(args, &mut framed, &cmd, response).await?;
+    if matches!(response, Response::Error(_)) {
+        eprint!("{}", Protocol::encode_response(&response));
+        return Ok(ExitCode::FAILURE);
+    }
+    print!("{}", output::render(&response, args.output()));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// 交互模式，逐行读取命令并输出回复，直到输入 quit 或 EOF
+async fn interactive(args: &CliArgs) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (addr, framed) = connect(args).await?;
+    let connection: Connection = Arc::new(Mutex::new(framed));
+    // 输入时可以用 Tab 补全命令名和键名，上下方向键浏览本次输入过的命令
+    let mut editor = Editor::<RedoxHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(RedoxHelper::new(connection.clone())));
     
     println!("Connected to Redox server at {}. Type your commands (e.g., 'SET key value' or 'GET key'):", addr);
-    println!("Type 'quit' to exit.");
-    
-    let mut reader = BufReader::new(reader);
-    let mut writer = writer;
+    println!("Type 'help' for help on commands, 'quit' to exit.");
     
     loop {
-        print!("> ");
-        io::stdout().flush()?;
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
+        // 补全键名时需要在回调中等待服务器的回复，读取输入期间不占用运行时的工作线程
+        let input = match task::block_in_place(|| editor.readline("> ")) {
+            Ok(input) => input,
+            // Ctrl-C 清空当前输入
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => {
+                println!("Goodbye!");
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
         
         let trimmed = input.trim();
         if trimmed.eq_ignore_ascii_case("quit") {
@@ -42,39 +155,94 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         if trimmed.is_empty() {
             continue;
         }
+        let _ = editor.add_history_entry(trimmed);
 
-        if let Err(e) = writer.write_all(input.as_bytes()).await {
-            eprintln!("Error sending command: {}", e);
-            break;
+        let words: Vec<&str> = trimmed.split_ascii_whitespace().collect();
+        if help::is_help(&words) {
+            print!("{}", help::help(&words[1..]));
+            continue;
         }
-        writer.flush().await?;
-        
-        let mut response = String::new();
-        if let Err(e) = reader.read_line(&mut response).await {
-            eprintln!("Error reading response: {}", e);
-            break;
+
+        // 先在本地解析命令，格式错误的命令不发送给服务器
+        let cmd = match Protocol::decode_command(trimmed) {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                print!("< {}", Protocol::encode_response(&Response::Error(RedoxError::Syntax(e))));
+                continue;
+            }
+        };
+
+        let mut framed = connection.lock().await;
+        // MONITOR 和订阅的回复不会结束，之后这个连接只能输出服务器推送的命令或消息
+        if monitor::is_streaming(&cmd) {
+            return monitor::stream(&mut framed, &cmd, args.output()).await;
+        }
+        // 服务器重启等原因导致连接断开时重新连接并认证；断开前服务器可能已经执行了这个命令，
+        // 幂等的命令直接再发送一次，其他命令在交互时先询问；标准输入不是终端时无法询问，再发送一次并给出警告。
+        // 协议错误不说明连接已断开，只报告错误
+        let response = match request(&mut framed, &cmd).await {
+            Ok(response) => response,
+            Err(CodecError::Io(e)) => {
+                eprintln!("Connection lost: {}. Reconnecting...", e);
+                let (_, reconnected) = connect(args).await?;
+                *framed = reconnected;
+                if is_idempotent(&cmd) {
+                    eprintln!("Reconnected, sending the command again");
+                } else if !io::stdin().is_terminal() {
+                    eprintln!("Warning: reconnected, sending the command again; the server may have executed it before the connection was lost");
+                } else if !confirm("Reconnected. The server may have executed the command before the connection was lost, send it again? [y/N] ")? {
+                    continue;
+                }
+                request(&mut framed, &cmd).await?
+            }
+            Err(CodecError::Protocol(e)) => {
+                eprintln!("Protocol error: {}", e);
+                continue;
+            }
+        };
+        let response = cluster::follow(args, &mut framed, &cmd, response).await?;
+        // 多行的回复从第二行起缩进，与第一行的 "< " 之后对齐
+        let reply = output::render(&response, args.output());
+        for (i, line) in reply.lines().enumerate() {
+            println!("{}{}", if i == 0 { "< " } else { "  " }, line);
         }
-        print!("< {}", response);
     }
     
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+/// 询问是否继续，从标准输入读取一行，输入 y 或 yes 时返回 true
+fn confirm(prompt: &str) -> io::Result<bool> {
+    eprint!("{}", prompt);
+    let mut answer = String::new();
+    task::block_in_place(|| io::stdin().read_line(&mut answer))?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
 }
 
-/// 尝试连接服务器，带重试机制
+/// 发送一个命令并读取回复
+/// 连接断开时返回 `CodecError::Io`，调用者可以重新连接后再次发送
+async fn request(framed: &mut Transport, cmd: &Command) -> Result<Response, CodecError> {
+    framed.send(cmd).await?;
+    match framed.next().await {
+        Some(response) => response,
+        None => Err(CodecError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server"))),
+    }
+}
+
+/// 尝试连接服务器，失败时按默认的重试策略以指数退避重试
 async fn connect_with_retry(addr: &str) -> Result<TcpStream, Box<dyn std::error::Error>> {
-    let mut retries = 0;
+    let retry = RetryPolicy::new();
+    let mut attempt = 0;
     loop {
         match TcpStream::connect(addr).await {
             Ok(stream) => return Ok(stream),
-            Err(e) => {
-                retries += 1;
-                if retries >= MAX_RETRIES {
-                    return Err(format!("Failed to connect after {} attempts: {}", MAX_RETRIES, e).into());
-                }
-                eprintln!("Connection attempt {} failed: {}. Retrying in {} seconds...", 
-                    retries, e, RETRY_DELAY.as_secs());
-                sleep(RETRY_DELAY).await;
+            Err(e) if attempt < retry.retries() => {
+                attempt += 1;
+                let delay = retry.delay(attempt);
+                eprintln!("Connection attempt {} failed: {}. Retrying in {} ms...", attempt, e, delay.as_millis());
+                sleep(delay).await;
             }
+            Err(e) => return Err(format!("Failed to connect after {} attempts: {}", attempt + 1, e).into()),
         }
     }
-} 
\ No newline at end of file
+}
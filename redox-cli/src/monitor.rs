@@ -0,0 +1,42 @@
+//! --monitor 模式
+//! 发送 MONITOR 后服务器持续推送其他连接执行的命令，每个命令一行，直到连接断开或按下 Ctrl-C。
+//! 交互模式中输入 MONITOR 时同样进入这个模式，SUBSCRIBE 和 PSUBSCRIBE 也以同样的方式逐条输出收到的消息。
+
+use crate::args::CliArgs;
+use crate::{connect, Transport};
+use crate::output::{self, OutputMode};
+use futures::{SinkExt, StreamExt};
+use redox_protocol::{Command, Protocol, Response};
+use std::process::ExitCode;
+
+/// 连接服务器并输出执行的命令
+pub async fn monitor(args: &CliArgs) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (_, mut framed) = connect(args).await?;
+    stream(&mut framed, &Command::Monitor, args.output()).await
+}
+
+/// 回复不会结束的命令：MONITOR、SUBSCRIBE 和 PSUBSCRIBE
+pub fn is_streaming(cmd: &Command) -> bool {
+    matches!(cmd, Command::Monitor | Command::Subscribe(_) | Command::PSubscribe(_))
+}
+
+/// 在连接上发送 MONITOR 或订阅命令，逐条输出服务器推送的回复，直到连接断开
+///
+/// # Arguments
+/// * `framed` - 与服务器的连接，返回后不能再用于执行命令
+/// * `cmd` - MONITOR、SUBSCRIBE 或 PSUBSCRIBE
+/// * `mode` - 输出方式
+pub async fn stream(framed: &mut Transport, cmd: &Command, mode: OutputMode) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    framed.send(cmd).await?;
+    match framed.next().await.ok_or("Connection closed by server")?? {
+        Response::Error(e) => {
+            eprint!("{}", Protocol::encode_response(&Response::Error(e)));
+            return Ok(ExitCode::FAILURE);
+        }
+        reply => print!("{}", output::render(&reply, mode)),
+    }
+    while let Some(line) = framed.next().await {
+        print!("{}", output::render(&line?, mode));
+    }
+    Ok(ExitCode::SUCCESS)
+}
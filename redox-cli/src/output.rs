@@ -0,0 +1,217 @@
+//! 回复的输出格式
+//! 交互式终端中把回复显示为编号的列表和对齐的表格，如 HGETALL 显示为字段和值两列、ZRANGE 显示为成员和分数两列；
+//! --json 把回复转换为 JSON，哈希、集合和有序集合保留结构，方便交给 jq 等工具处理；
+//! 输出不是终端或指定了 --raw 时输出行协议的原始文本，便于脚本处理。
+
+use bytes::Bytes;
+use redox_protocol::{quote, text, Protocol, RedoxValue, Response};
+use serde_json::{json, Map, Value};
+use unicode_width::UnicodeWidthStr;
+
+/// 回复的输出方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// 行协议的原始文本
+    Raw,
+    /// 一行 JSON
+    Json,
+    /// 编号的列表和对齐的表格
+    Table,
+}
+
+/// 把回复转换为要输出的文本，以换行符结尾
+///
+/// # Arguments
+/// * `response` - 服务器的回复
+/// * `mode` - 输出方式
+pub fn render(response: &Response, mode: OutputMode) -> String {
+    match mode {
+        OutputMode::Raw => Protocol::encode_response(response),
+        OutputMode::Json => format!("{}\n", to_json(response)),
+        OutputMode::Table => table(response).into_iter().map(|line| line + "\n").collect(),
+    }
+}
+
+/// 把回复转换为表格形式的各行
+/// 集合中的元素按命令参数的规则加引号，空字符串和包含空白的元素也能分辨；哈希和集合按字典序排列
+fn table(response: &Response) -> Vec<String> {
+    match response {
+        Response::Ok => vec!["OK".to_string()],
+        Response::Nil => vec!["(nil)".to_string()],
+        Response::Error(e) => vec![format!("(error) {}", e)],
+        Response::Integer(n) => vec![format!("(integer) {}", n)],
+        Response::Array(items) => numbered(items.iter().map(|item| item.as_deref().map_or("(nil)".to_string(), cell)).collect()),
+        Response::Info(info) => {
+            let mut fields: Vec<Vec<String>> = info.iter().map(|(key, value)| vec![format!("{}:", key), value.clone()]).collect();
+            fields.sort();
+            aligned(fields)
+        }
+        Response::Map(fields) => map(fields),
+        Response::Cursor(cursor, items) => {
+            let mut lines = vec![format!("cursor: {}", cursor)];
+            lines.extend(numbered(items.iter().map(|item| cell(item)).collect()));
+            lines
+        }
+        Response::Replies(replies) => numbered_replies(replies),
+        Response::Push { kind, items } => {
+            let mut lines = vec![format!("(push) {}", kind)];
+            lines.extend(numbered_replies(items));
+            lines
+        }
+        Response::Value(value) => match value {
+            RedoxValue::String(s) => text(s).lines().map(str::to_string).collect(),
+            RedoxValue::Json(json) => serde_json::to_string_pretty(json).unwrap_or_default().lines().map(str::to_string).collect(),
+            RedoxValue::List(list) => numbered(list.iter().map(|item| cell(item)).collect()),
+            RedoxValue::Set(set) => {
+                let mut members: Vec<&Bytes> = set.iter().collect();
+                members.sort();
+                numbered(members.into_iter().map(|member| cell(member)).collect())
+            }
+            RedoxValue::Hash(hash) => {
+                let mut fields: Vec<(&Bytes, &Bytes)> = hash.iter().collect();
+                fields.sort();
+                numbered_rows(fields.into_iter().map(|(field, value)| vec![cell(field), cell(value)]).collect())
+            }
+            RedoxValue::SortedSet(zset) => numbered_rows(zset.iter().map(|(member, score)| vec![cell(member), score.to_string()]).collect()),
+            RedoxValue::TimeSeries(ts) => {
+                numbered_rows(ts.samples.iter().map(|(timestamp, value)| vec![timestamp.to_string(), value.to_string()]).collect())
+            }
+        },
+    }
+}
+
+/// 字段名和值的映射，值只有一行时与字段名对齐显示在同一行，有多行时缩进显示在字段名之后
+fn map(fields: &[(String, Response)]) -> Vec<String> {
+    if fields.is_empty() {
+        return vec!["(empty)".to_string()];
+    }
+    let width = fields.iter().map(|(name, _)| name.width() + 1).max().unwrap_or(0);
+    let mut lines = Vec::new();
+    for (name, value) in fields {
+        let name = format!("{}:", name);
+        match table(value).as_slice() {
+            [line] if !is_collection(value) => lines.push(format!("{}{} {}", name, pad(&name, width), line)),
+            inner => {
+                lines.push(name);
+                lines.extend(inner.iter().map(|line| format!("  {}", line)));
+            }
+        }
+    }
+    lines
+}
+
+/// 多个命令的回复（EXEC），每个回复编号，有多行的回复从第二行起与第一行对齐
+fn numbered_replies(replies: &[Response]) -> Vec<String> {
+    if replies.is_empty() {
+        return vec!["(empty)".to_string()];
+    }
+    let width = replies.len().to_string().len();
+    let mut lines = Vec::new();
+    for (i, reply) in replies.iter().enumerate() {
+        for (j, line) in table(reply).into_iter().enumerate() {
+            if j == 0 {
+                lines.push(format!("{:>width$}) {}", i + 1, line));
+            } else {
+                lines.push(format!("{}  {}", " ".repeat(width), line));
+            }
+        }
+    }
+    lines
+}
+
+/// 回复是否是集合，只有一个元素的集合也显示在单独的行
+fn is_collection(response: &Response) -> bool {
+    match response {
+        Response::Array(_) | Response::Info(_) | Response::Map(_) | Response::Cursor(..) | Response::Replies(_) | Response::Push { .. } => true,
+        Response::Value(value) => !matches!(value, RedoxValue::String(_)),
+        _ => false,
+    }
+}
+
+/// 编号的列表，如 `1) a`，编号右对齐
+fn numbered(items: Vec<String>) -> Vec<String> {
+    numbered_rows(items.into_iter().map(|item| vec![item]).collect())
+}
+
+/// 编号的多列表格，各列左对齐
+fn numbered_rows(rows: Vec<Vec<String>>) -> Vec<String> {
+    if rows.is_empty() {
+        return vec!["(empty)".to_string()];
+    }
+    let width = rows.len().to_string().len();
+    let rows = rows.into_iter()
+        .enumerate()
+        .map(|(i, row)| std::iter::once(format!("{:>width$})", i + 1)).chain(row).collect())
+        .collect();
+    aligned(rows)
+}
+
+/// 把各行的单元格按列对齐，单元格之间以一个空格分隔，最后一列不补空格
+/// 按显示宽度对齐，中文等全角字符占两列
+fn aligned(rows: Vec<Vec<String>>) -> Vec<String> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| rows.iter().filter_map(|row| row.get(column)).map(|cell| cell.width()).max().unwrap_or(0))
+        .collect();
+    rows.into_iter()
+        .map(|row| {
+            let last = row.len().saturating_sub(1);
+            row.iter()
+                .enumerate()
+                .map(|(column, cell)| if column == last { cell.clone() } else { format!("{}{}", cell, pad(cell, widths[column])) })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+/// 把 cell 补齐到 width 列需要的空格
+fn pad(cell: &str, width: usize) -> String {
+    " ".repeat(width.saturating_sub(cell.width()))
+}
+
+/// 集合中的一个元素，必要时加引号
+fn cell(bytes: &[u8]) -> String {
+    quote(bytes).into_owned()
+}
+
+/// 把回复转换为 JSON
+/// 不存在的值为 null，错误为 `{"error": "..."}`，哈希为对象，有序集合按分数升序为 `{"member", "score"}` 对象的数组，
+/// 集合按字典序排列；无法解码为 UTF-8 的字节替换为 U+FFFD
+pub fn to_json(response: &Response) -> Value {
+    match response {
+        Response::Ok => json!("OK"),
+        Response::Nil => Value::Null,
+        Response::Error(e) => json!({ "error": e.to_string() }),
+        Response::Integer(n) => json!(n),
+        Response::Array(items) => items.iter().map(|item| item.as_deref().map_or(Value::Null, string)).collect(),
+        Response::Info(info) => info.iter().map(|(key, value)| (key.clone(), json!(value))).collect::<Map<_, _>>().into(),
+        Response::Map(fields) => fields.iter().map(|(name, value)| (name.clone(), to_json(value))).collect::<Map<_, _>>().into(),
+        Response::Cursor(cursor, items) => json!({ "cursor": cursor, "items": strings(items) }),
+        Response::Replies(replies) => replies.iter().map(to_json).collect(),
+        Response::Push { kind, items } => json!({ "push": kind, "items": items.iter().map(to_json).collect::<Value>() }),
+        Response::Value(value) => match value {
+            RedoxValue::String(s) => string(s),
+            RedoxValue::Json(json) => json.clone(),
+            RedoxValue::List(list) => list.iter().map(|item| string(item)).collect(),
+            RedoxValue::Set(set) => {
+                let mut members: Vec<&Bytes> = set.iter().collect();
+                members.sort();
+                members.into_iter().map(|member| string(member)).collect()
+            }
+            RedoxValue::Hash(hash) => hash.iter().map(|(field, value)| (text(field).into_owned(), string(value))).collect::<Map<_, _>>().into(),
+            RedoxValue::SortedSet(zset) => zset.iter().map(|(member, score)| json!({ "member": text(member), "score": score })).collect(),
+            RedoxValue::TimeSeries(ts) => ts.samples.iter().map(|(timestamp, value)| json!({ "timestamp": timestamp, "value": value })).collect(),
+        },
+    }
+}
+
+/// 字节串转换为 JSON 字符串
+fn string(bytes: &[u8]) -> Value {
+    Value::String(text(bytes).into_owned())
+}
+
+/// 字节串数组转换为 JSON 字符串数组
+fn strings(items: &[Bytes]) -> Value {
+    items.iter().map(|item| string(item)).collect()
+}
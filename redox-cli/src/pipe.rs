@@ -0,0 +1,78 @@
+//! --pipe 模式，批量导入数据
+//! 从标准输入读取命令并以管道方式发送，不等待每条命令的回复，结束时报告成功和出错的回复数。
+//! 输入可以是每行一条的行协议命令，也可以是 RESP 格式的请求（与 redis-cli --pipe 相同），两者可以混合。
+
+use crate::args::CliArgs;
+use crate::connect;
+use bytes::{BufMut, BytesMut};
+use futures::{SinkExt, StreamExt};
+use redox_protocol::{ParseError, Protocol, Response};
+use std::process::ExitCode;
+use tokio::io::{self, AsyncReadExt};
+
+/// 每批发送的命令数，发送一批后读取这一批的回复，限制服务器和客户端缓冲的回复数量
+const PIPE_BATCH: usize = 1000;
+
+/// 每次从标准输入读取的字节数
+const READ_SIZE: usize = 64 * 1024;
+
+/// 从标准输入导入命令，有错误回复或无法解析的命令时退出码为 1
+pub async fn pipe(args: &CliArgs) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (_, mut framed) = connect(args).await?;
+    let mut stdin = io::stdin();
+    let mut buf = BytesMut::with_capacity(READ_SIZE);
+    let mut eof = false;
+    let mut done = false;
+    let mut ok = 0u64;
+    let mut errors = 0u64;
+    while !done {
+        let mut sent = 0;
+        while sent < PIPE_BATCH {
+            // 命令之间的空行和空白不是命令
+            let blank = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+            let _ = buf.split_to(blank);
+            match Protocol::parse(&mut buf) {
+                Ok(Some(cmd)) => {
+                    framed.feed(&cmd).await?;
+                    sent += 1;
+                }
+                Ok(None) if eof => {
+                    done = true;
+                    break;
+                }
+                Ok(None) => {
+                    buf.reserve(READ_SIZE);
+                    if stdin.read_buf(&mut buf).await? == 0 {
+                        eof = true;
+                        // 最后一行没有换行符时补上，RESP 请求不完整时保留，结束时报告
+                        if buf.first().is_some_and(|b| *b != b'*') && !buf.ends_with(b"\n") {
+                            buf.put_u8(b'\n');
+                        }
+                    }
+                }
+                // 无法解析的命令不发送，计为错误
+                Err(ParseError::Invalid(e)) => {
+                    eprintln!("{}", e);
+                    errors += 1;
+                }
+                Err(ParseError::Protocol(e)) => return Err(e.into()),
+            }
+        }
+        framed.flush().await?;
+        for _ in 0..sent {
+            match framed.next().await.ok_or("Connection closed by server")?? {
+                Response::Error(e) => {
+                    eprintln!("{}", e);
+                    errors += 1;
+                }
+                _ => ok += 1,
+            }
+        }
+    }
+    if !buf.is_empty() {
+        eprintln!("Incomplete RESP request at the end of the input");
+        errors += 1;
+    }
+    println!("All data transferred. Commands: {}, OK: {}, errors: {}", ok + errors, ok, errors);
+    Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
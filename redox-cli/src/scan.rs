@@ -0,0 +1,67 @@
+//! --scan 模式，遍历键
+//! 用 SCAN 遍历所有键，每行输出一个键，可以用 --pattern 只输出匹配的键，输出可以交给 xargs 等工具处理。
+
+use crate::args::CliArgs;
+use crate::connect;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::{split_args, text, Command, Protocol, RedoxValue, Response};
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+
+/// 遍历所有键并输出，输出被关闭时（如 `| head`）停止遍历
+pub async fn scan(args: &CliArgs) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let (_, mut framed) = connect(args).await?;
+    let pattern = args.pattern.as_ref().map(|pattern| Bytes::copy_from_slice(pattern.as_bytes()));
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut cursor = 0;
+    loop {
+        framed.send(&Command::Scan { cursor, pattern: pattern.clone(), count: args.count }).await?;
+        let reply = framed.next().await.ok_or("Connection closed by server")??;
+        if let Response::Error(e) = reply {
+            eprint!("{}", Protocol::encode_response(&Response::Error(e)));
+            return Ok(ExitCode::FAILURE);
+        }
+        let (next, keys) = parse_cursor(reply).ok_or("Unexpected reply to SCAN")?;
+        for key in keys {
+            if let Err(e) = out.write_all(&key).and_then(|_| out.write_all(b"\n")) {
+                return broken_pipe(e);
+            }
+        }
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    if let Err(e) = out.flush() {
+        return broken_pipe(e);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// 输出被关闭时正常结束，其他写入错误返回错误
+fn broken_pipe(e: io::Error) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Err(e.into())
+    }
+}
+
+/// 解析 SCAN 的回复
+/// 二进制格式的连接直接得到游标和键；行协议的回复是一行以空格分隔的游标和键，没有键时解析为整数
+///
+/// # Returns
+/// (下一次的游标, 这一批的键)，回复是错误或格式不对时为 None
+pub fn parse_cursor(reply: Response) -> Option<(u64, Vec<Bytes>)> {
+    match reply {
+        Response::Cursor(cursor, keys) => Some((cursor, keys)),
+        Response::Integer(cursor) => Some((u64::try_from(cursor).ok()?, Vec::new())),
+        Response::Value(RedoxValue::String(line)) => {
+            let mut parts = split_args(&text(&line)).ok()?.into_iter();
+            let cursor = text(&parts.next()?).parse().ok()?;
+            Some((cursor, parts.collect()))
+        }
+        _ => None,
+    }
+}
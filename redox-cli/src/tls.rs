@@ -0,0 +1,164 @@
+//! TLS 支持
+//! 使用 rustls 连接启用了 TLS 的服务器。默认用系统的根证书验证服务器证书，--cacert 指定 CA 证书时只信任该文件中的证书；
+//! 服务器要求客户端证书时用 --cert 和 --key 指定。证书中的名称默认与主机名比较，--sni 指定其他名称，--insecure 跳过验证。
+
+use crate::args::CliArgs;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, Error, RootCertStore, SignatureScheme};
+use tokio_rustls::TlsConnector;
+
+/// 与服务器的连接，普通 TCP 连接或 TLS 连接
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// 在已建立的 TCP 连接上进行 TLS 握手
+///
+/// # Arguments
+/// * `args` - 命令行参数，包含证书和名称验证的选项
+/// * `host` - 连接的主机，没有指定 --sni 时用于 SNI 和证书名称的验证
+/// * `socket` - 已建立的 TCP 连接
+///
+/// # Returns
+/// * `Ok(Stream)` - 握手成功后的 TLS 连接
+/// * `Err(String)` - 证书文件无法读取或握手失败
+pub async fn handshake(args: &CliArgs, host: &str, socket: TcpStream) -> Result<Stream, String> {
+    let connector = TlsConnector::from(Arc::new(client_config(args)?));
+    let name = args.sni.as_deref().unwrap_or(host);
+    let server_name = ServerName::try_from(name.to_string()).map_err(|e| format!("Invalid TLS server name {}: {}", name, e))?;
+    let stream = connector
+        .connect(server_name, socket)
+        .await
+        .map_err(|e| format!("TLS handshake with {} failed: {}", host, e))?;
+    Ok(Stream::Tls(Box::new(stream)))
+}
+
+/// 根据命令行参数创建 TLS 客户端配置
+fn client_config(args: &CliArgs) -> Result<ClientConfig, String> {
+    let builder = if args.insecure {
+        ClientConfig::builder().dangerous().with_custom_certificate_verifier(Arc::new(NoVerification(crypto::ring::default_provider())))
+    } else {
+        ClientConfig::builder().with_root_certificates(root_store(args.cacert.as_deref())?)
+    };
+    match (&args.cert, &args.key) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = CertificateDer::pem_file_iter(cert_path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| format!("Error reading TLS certificate {}: {}", cert_path, e))?;
+            if certs.is_empty() {
+                return Err(format!("No certificates found in {}", cert_path));
+            }
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .map_err(|e| format!("Error reading TLS private key {}: {}", key_path, e))?;
+            builder.with_client_auth_cert(certs, key).map_err(|e| format!("Invalid TLS certificate or key: {}", e))
+        }
+        _ => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// 验证服务器证书使用的根证书
+/// 指定了 CA 文件时只使用该文件中的证书，否则使用系统的根证书
+fn root_store(cacert: Option<&str>) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    match cacert {
+        Some(path) => {
+            for cert in CertificateDer::pem_file_iter(path).map_err(|e| format!("Error reading CA certificate {}: {}", path, e))? {
+                let cert = cert.map_err(|e| format!("Error reading CA certificate {}: {}", path, e))?;
+                roots.add(cert).map_err(|e| format!("Invalid CA certificate in {}: {}", path, e))?;
+            }
+        }
+        None => {
+            roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+        }
+    }
+    if roots.is_empty() {
+        return Err(match cacert {
+            Some(path) => format!("No certificates found in {}", path),
+            None => "No system CA certificates found, use --cacert to specify one".to_string(),
+        });
+    }
+    Ok(roots)
+}
+
+/// 不验证服务器证书，用于 --insecure
+/// 握手消息的签名仍然检查，防止握手被篡改，但无法防止中间人
+#[derive(Debug)]
+struct NoVerification(CryptoProvider);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
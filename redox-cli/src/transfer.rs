@@ -0,0 +1,376 @@
+//! export 和 import 子命令，以 CSV 或 JSON 导出和导入数据
+//! 导出时用 SCAN 遍历键，按类型读取每个键的值和剩余过期时间；导入时按类型用普通的写命令重建键，不需要访问服务器的数据文件。
+//! JSON 格式每行一个键：`{"key": "user:1", "type": "hash", "value": {"name": "alice"}, "ttl": 5000}`，
+//! 值的表示与 --json 输出相同，ttl 为剩余的毫秒数，没有过期时间时省略；
+//! CSV 格式的列为 key、type、ttl 和 value，字符串的值原样保存，其他类型的值为 JSON 文本，可以直接在电子表格中编辑。
+//! 键和值按 UTF-8 文本保存，适合在环境之间移动较小的数据集；二进制数据请使用 DUMP 和 RESTORE。
+
+use crate::args::CliArgs;
+use crate::scan::parse_cursor;
+use crate::{connect, output, Transport};
+use bytes::Bytes;
+use clap::{Parser, ValueEnum};
+use futures::{SinkExt, StreamExt};
+use redox_protocol::{text, Command, Protocol, RedoxValue, Response};
+use serde_json::{json, Map, Value};
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+use tokio::io::AsyncReadExt;
+
+/// 导出时每次 SCAN 检查的键数，也是每批以管道方式读取的键数
+const SCAN_COUNT: usize = 100;
+
+/// 导出文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FileFormat {
+    /// One JSON object per line
+    Json,
+    /// Columns key, type, ttl and value, with a header row
+    Csv,
+}
+
+/// export 子命令的参数
+#[derive(Parser)]
+#[command(name = "redox-cli export", about = "Export keys as CSV or JSON lines to stdout")]
+struct ExportArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = FileFormat::Json)]
+    format: FileFormat,
+
+    /// Only export keys matching this glob-style pattern, e.g. 'user:*'
+    #[arg(long)]
+    pattern: Option<String>,
+}
+
+/// import 子命令的参数
+#[derive(Parser)]
+#[command(name = "redox-cli import", about = "Import keys from a file written by export")]
+struct ImportArgs {
+    /// Input format
+    #[arg(long, value_enum, default_value_t = FileFormat::Json)]
+    format: FileFormat,
+
+    /// Replace keys that already exist instead of skipping them
+    #[arg(long)]
+    replace: bool,
+
+    /// File to read, stdin when omitted
+    file: Option<String>,
+}
+
+/// 一个键的导出记录
+struct Record {
+    /// 键
+    key: String,
+    /// TYPE 返回的类型名称
+    kind: String,
+    /// 剩余的过期毫秒数，None 表示没有过期时间
+    ttl: Option<u64>,
+    /// 值，表示方式与 --json 输出相同
+    value: Value,
+}
+
+/// 导出匹配的键，输出到标准输出
+///
+/// # Arguments
+/// * `args` - 连接服务器的参数
+/// * `command` - 子命令及其参数，第一个元素为 `export`
+pub async fn export(args: &CliArgs, command: &[String]) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let export = ExportArgs::parse_from(subcommand_args(command));
+    let (_, mut framed) = connect(args).await?;
+    let pattern = export.pattern.as_ref().map(|pattern| Bytes::copy_from_slice(pattern.as_bytes()));
+    let mut out = BufWriter::new(io::stdout().lock());
+    if export.format == FileFormat::Csv {
+        out.write_all(&csv_row(["key", "type", "ttl", "value"])?)?;
+    }
+    let mut exported = 0u64;
+    let mut cursor = 0;
+    loop {
+        framed.send(&Command::Scan { cursor, pattern: pattern.clone(), count: Some(SCAN_COUNT) }).await?;
+        let reply = framed.next().await.ok_or("Connection closed by server")??;
+        if let Response::Error(e) = reply {
+            eprint!("{}", Protocol::encode_response(&Response::Error(e)));
+            return Ok(ExitCode::FAILURE);
+        }
+        let (next, keys) = parse_cursor(reply).ok_or("Unexpected reply to SCAN")?;
+        for record in read_records(&mut framed, keys).await? {
+            match export.format {
+                FileFormat::Csv => {
+                    let value = match &record.value {
+                        Value::String(s) if record.kind == "string" => s.clone(),
+                        value => value.to_string(),
+                    };
+                    let ttl = record.ttl.map(|ttl| ttl.to_string()).unwrap_or_default();
+                    out.write_all(&csv_row([record.key.as_str(), record.kind.as_str(), ttl.as_str(), value.as_str()])?)?;
+                }
+                FileFormat::Json => {
+                    let mut line = json!({ "key": record.key, "type": record.kind, "value": record.value });
+                    if let Some(ttl) = record.ttl {
+                        line["ttl"] = json!(ttl);
+                    }
+                    writeln!(out, "{}", line)?;
+                }
+            }
+            exported += 1;
+        }
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    out.flush()?;
+    eprintln!("Exported {} keys", exported);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// 子命令的参数，帮助和错误信息中的程序名为 `redox-cli export` 等
+fn subcommand_args(command: &[String]) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(format!("redox-cli {}", command[0])).chain(command[1..].iter().cloned())
+}
+
+/// 一行 CSV，包含逗号、引号或换行的字段加引号
+fn csv_row<const N: usize>(fields: [&str; N]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(fields)?;
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// 以管道方式读取一批键的类型、值和剩余过期时间，读取期间被删除的键跳过
+async fn read_records(framed: &mut Transport, keys: Vec<Bytes>) -> Result<Vec<Record>, Box<dyn std::error::Error>> {
+    for key in &keys {
+        framed.feed(&Command::Type { key: key.clone() }).await?;
+    }
+    framed.flush().await?;
+    let mut typed = Vec::with_capacity(keys.len());
+    for key in keys {
+        match framed.next().await.ok_or("Connection closed by server")?? {
+            Response::Value(RedoxValue::String(name)) if name.as_ref() != b"none" => typed.push((key, text(&name).into_owned())),
+            _ => {}
+        }
+    }
+
+    let mut fetched = Vec::with_capacity(typed.len());
+    for (key, kind) in typed {
+        let Some(cmd) = read_command(&key, &kind) else {
+            eprintln!("Skipping {}: unsupported type {}", text(&key), kind);
+            continue;
+        };
+        framed.feed(&cmd).await?;
+        framed.feed(&Command::PTTL { key: key.clone() }).await?;
+        fetched.push((key, kind));
+    }
+    framed.flush().await?;
+    let mut records = Vec::with_capacity(fetched.len());
+    for (key, kind) in fetched {
+        let value = framed.next().await.ok_or("Connection closed by server")??;
+        let ttl = framed.next().await.ok_or("Connection closed by server")??;
+        let value = match value {
+            Response::Error(e) => {
+                eprintln!("Skipping {}: {}", text(&key), e);
+                continue;
+            }
+            Response::Nil => continue,
+            // TS.RANGE 的回复是交替的时间戳和数值
+            Response::Array(items) if kind == "TSDB-TYPE" => items
+                .chunks(2)
+                .filter_map(|sample| match sample {
+                    [Some(timestamp), Some(value)] => Some(json!({
+                        "timestamp": text(timestamp).parse::<u64>().ok()?,
+                        "value": text(value).parse::<f64>().ok()?,
+                    })),
+                    _ => None,
+                })
+                .collect(),
+            value => output::to_json(&value),
+        };
+        let ttl = match ttl {
+            Response::Integer(ttl) => u64::try_from(ttl).ok(),
+            Response::Value(RedoxValue::String(ttl)) => text(&ttl).parse().ok(),
+            _ => None,
+        };
+        records.push(Record { key: text(&key).into_owned(), kind, ttl, value });
+    }
+    Ok(records)
+}
+
+/// 读取某种类型的整个值的命令
+fn read_command(key: &Bytes, kind: &str) -> Option<Command> {
+    let key = key.clone();
+    Some(match kind {
+        "string" => Command::Get { key },
+        "list" => Command::LRange { key, start: 0, stop: -1 },
+        "set" => Command::SMembers { key },
+        "hash" => Command::HGetAll { key },
+        "zset" => Command::ZRange { key, start: 0, stop: -1 },
+        "ReJSON-RL" => Command::JsonGet { key, path: "$".to_string() },
+        "TSDB-TYPE" => Command::TsRange { key, from: 0, to: u64::MAX, aggregation: None },
+        _ => return None,
+    })
+}
+
+/// 导入 export 输出的文件，有键导入失败时退出码为 1
+///
+/// # Arguments
+/// * `args` - 连接服务器的参数
+/// * `command` - 子命令及其参数，第一个元素为 `import`
+pub async fn import(args: &CliArgs, command: &[String]) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let import = ImportArgs::parse_from(subcommand_args(command));
+    let input = match &import.file {
+        Some(path) => tokio::fs::read_to_string(path).await.map_err(|e| format!("Error reading {}: {}", path, e))?,
+        None => {
+            let mut input = String::new();
+            tokio::io::stdin().read_to_string(&mut input).await?;
+            input
+        }
+    };
+    let records = match import.format {
+        FileFormat::Json => parse_json(&input)?,
+        FileFormat::Csv => parse_csv(&input)?,
+    };
+
+    let (_, mut framed) = connect(args).await?;
+    let mut imported = 0u64;
+    let mut skipped = 0u64;
+    let mut errors = 0u64;
+    for record in records {
+        let key = Bytes::from(record.key.clone());
+        let commands = match write_commands(&record) {
+            Ok(commands) => commands,
+            Err(e) => {
+                eprintln!("{}: {}", record.key, e);
+                errors += 1;
+                continue;
+            }
+        };
+        framed.send(&Command::Type { key: key.clone() }).await?;
+        let exists = !matches!(
+            framed.next().await.ok_or("Connection closed by server")??,
+            Response::Value(RedoxValue::String(name)) if name.as_ref() == b"none"
+        );
+        if exists && !import.replace {
+            eprintln!("{}: key already exists, use --replace to overwrite it", record.key);
+            skipped += 1;
+            continue;
+        }
+        let delete = exists.then(|| Command::Del(vec![key]));
+        let count = commands.len() + usize::from(delete.is_some());
+        for cmd in delete.iter().chain(&commands) {
+            framed.feed(cmd).await?;
+        }
+        framed.flush().await?;
+        let mut failed = None;
+        for _ in 0..count {
+            if let Response::Error(e) = framed.next().await.ok_or("Connection closed by server")?? {
+                failed.get_or_insert(e);
+            }
+        }
+        match failed {
+            Some(e) => {
+                eprintln!("{}: {}", record.key, e);
+                errors += 1;
+            }
+            None => imported += 1,
+        }
+    }
+    println!("Imported {} keys, skipped: {}, errors: {}", imported, skipped, errors);
+    Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+/// 解析每行一个对象的 JSON，空行忽略
+fn parse_json(input: &str) -> Result<Vec<Record>, String> {
+    let mut records = Vec::new();
+    for (number, line) in input.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let invalid = |e: &str| format!("Line {}: {}", number + 1, e);
+        let object: Map<String, Value> = serde_json::from_str(line).map_err(|e| invalid(&e.to_string()))?;
+        let field = |name: &str| object.get(name).and_then(Value::as_str).map(str::to_string).ok_or_else(|| invalid(&format!("missing {}", name)));
+        records.push(Record {
+            key: field("key")?,
+            kind: field("type")?,
+            ttl: match object.get("ttl") {
+                None | Some(Value::Null) => None,
+                Some(ttl) => Some(ttl.as_u64().ok_or_else(|| invalid("ttl must be a non-negative integer"))?),
+            },
+            value: object.get("value").cloned().ok_or_else(|| invalid("missing value"))?,
+        });
+    }
+    Ok(records)
+}
+
+/// 解析带标题行的 CSV，列的顺序不限
+fn parse_csv(input: &str) -> Result<Vec<Record>, String> {
+    let mut reader = csv::Reader::from_reader(input.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name).ok_or_else(|| format!("CSV header is missing the {} column", name));
+    let (key, kind, ttl, value) = (column("key")?, column("type")?, column("ttl")?, column("value")?);
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(|e| e.to_string())?;
+        let line = row.position().map_or(0, |position| position.line());
+        let invalid = |e: &str| format!("Line {}: {}", line, e);
+        let get = |index: usize| row.get(index).unwrap_or_default();
+        let kind = get(kind).to_string();
+        records.push(Record {
+            key: get(key).to_string(),
+            ttl: match get(ttl) {
+                "" => None,
+                ttl => Some(ttl.parse().map_err(|_| invalid("ttl must be a non-negative integer"))?),
+            },
+            // 字符串的值原样保存，其他类型为 JSON 文本
+            value: if kind == "string" {
+                Value::String(get(value).to_string())
+            } else {
+                serde_json::from_str(get(value)).map_err(|e| invalid(&e.to_string()))?
+            },
+            kind,
+        });
+    }
+    Ok(records)
+}
+
+/// 重建一个键的命令，值的格式与类型不符时返回错误
+fn write_commands(record: &Record) -> Result<Vec<Command>, String> {
+    let key = Bytes::from(record.key.clone());
+    let bytes = |value: &Value| -> Result<Bytes, String> {
+        value.as_str().map(|s| Bytes::from(s.to_string())).ok_or_else(|| format!("expected a string, found {}", value))
+    };
+    let array = || record.value.as_array().ok_or_else(|| format!("{} value must be an array", record.kind));
+    let mut commands = match record.kind.as_str() {
+        "string" => vec![Command::Set { key: key.clone(), value: bytes(&record.value)? }],
+        "list" => array()?.iter().map(|item| Ok(Command::RPush { key: key.clone(), value: bytes(item)? })).collect::<Result<_, String>>()?,
+        "set" => array()?.iter().map(|item| Ok(Command::SAdd { key: key.clone(), member: bytes(item)? })).collect::<Result<_, String>>()?,
+        "hash" => {
+            let fields = record.value
+                .as_object()
+                .ok_or("hash value must be an object")?
+                .iter()
+                .map(|(field, value)| Ok((Bytes::from(field.clone()), bytes(value)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            if fields.is_empty() { vec![] } else { vec![Command::HSet { key: key.clone(), fields }] }
+        }
+        "zset" => array()?
+            .iter()
+            .map(|entry| {
+                let score = entry.get("score").and_then(Value::as_f64).ok_or("zset entries need a numeric score")?;
+                Ok(Command::ZAdd { key: key.clone(), score, member: bytes(entry.get("member").unwrap_or(&Value::Null))? })
+            })
+            .collect::<Result<_, String>>()?,
+        "ReJSON-RL" => vec![Command::JsonSet { key: key.clone(), path: "$".to_string(), value: record.value.clone() }],
+        "TSDB-TYPE" => {
+            let mut commands = vec![Command::TsCreate { key: key.clone(), retention_ms: None }];
+            for sample in array()? {
+                let timestamp = sample.get("timestamp").and_then(Value::as_u64).ok_or("time series samples need an integer timestamp")?;
+                let value = sample.get("value").and_then(Value::as_f64).ok_or("time series samples need a numeric value")?;
+                commands.push(Command::TsAdd { key: key.clone(), timestamp: Some(timestamp), value, retention_ms: None });
+            }
+            commands
+        }
+        other => return Err(format!("unsupported type {}", other)),
+    };
+    if commands.is_empty() {
+        return Err(format!("empty {} value", record.kind));
+    }
+    if let Some(ttl) = record.ttl {
+        commands.push(Command::PExpire { key, milliseconds: ttl.max(1), condition: None });
+    }
+    Ok(commands)
+}
@@ -0,0 +1,157 @@
+//! 客户端缓存
+//! `Client::enable_cache` 在连接上开启 CLIENT TRACKING，之后 GET 的回复保存在进程内的 LRU 缓存中，
+//! 重复读取同一个键时直接返回缓存的值，不再访问服务器；键被任何连接修改、删除、过期或淘汰时服务器推送失效通知，
+//! 客户端收到后从缓存中移除这个键。通知只在这个连接上推送，连接断开后缓存随之丢弃，重新连接时重新开启追踪。
+//! 从缓存返回之前先处理连接上已经到达的通知，但仍在网络上传输的通知无法等待，与 Redis 的客户端缓存相同，读到的值可能短暂地落后。
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// 客户端缓存的统计，由 `Client::cache_stats` 返回
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// 从缓存返回的 GET 次数
+    pub hits: u64,
+    /// 缓存中没有、发送到服务器的 GET 次数
+    pub misses: u64,
+    /// 服务器的失效通知移除的键数
+    pub invalidations: u64,
+    /// 超过最大条目数后按最近最少使用淘汰的键数
+    pub evictions: u64,
+    /// 当前缓存的键数
+    pub entries: usize,
+}
+
+impl CacheStats {
+    /// 命中率，hits / (hits + misses)，还没有 GET 时为 0
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// 跨越重新连接累计的计数
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// 客户端缓存的配置，为每个新连接创建空的缓存
+#[derive(Clone)]
+pub(crate) struct CacheConfig {
+    /// 最多缓存的键数
+    max_entries: usize,
+    /// 所有连接的缓存共享的计数
+    counters: Arc<Counters>,
+}
+
+impl CacheConfig {
+    /// 创建配置
+    ///
+    /// # Arguments
+    /// * `max_entries` - 最多缓存的键数，至少为 1
+    pub(crate) fn new(max_entries: usize) -> Self {
+        Self { max_entries: max_entries.max(1), counters: Arc::default() }
+    }
+
+    /// 为新连接创建空的缓存
+    pub(crate) fn cache(&self) -> Cache {
+        Cache { config: self.clone(), entries: HashMap::new(), recency: BTreeMap::new(), tick: 0 }
+    }
+
+    /// 当前的统计
+    ///
+    /// # Arguments
+    /// * `entries` - 当前连接缓存的键数
+    pub(crate) fn stats(&self, entries: usize) -> CacheStats {
+        let counters = &self.counters;
+        CacheStats {
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            invalidations: counters.invalidations.load(Ordering::Relaxed),
+            evictions: counters.evictions.load(Ordering::Relaxed),
+            entries,
+        }
+    }
+}
+
+/// 一个连接的 LRU 缓存，值为 GET 的回复，None 表示键不存在
+pub(crate) struct Cache {
+    config: CacheConfig,
+    /// 键到缓存的值和最近一次使用的序号
+    entries: HashMap<Bytes, (Option<Bytes>, u64)>,
+    /// 使用序号到键，第一个是最近最少使用的键
+    recency: BTreeMap<u64, Bytes>,
+    /// 下一个使用序号
+    tick: u64,
+}
+
+impl Cache {
+    /// 查找缓存的值并记录命中或未命中
+    ///
+    /// # Returns
+    /// * `Some(Some(value))` - 缓存的值
+    /// * `Some(None)` - 缓存中记录键不存在
+    /// * `None` - 没有缓存这个键
+    pub(crate) fn get(&mut self, key: &[u8]) -> Option<Option<Bytes>> {
+        let counters = &self.config.counters;
+        let Some((value, used)) = self.entries.get_mut(key) else {
+            counters.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        counters.hits.fetch_add(1, Ordering::Relaxed);
+        let key = self.recency.remove(used).expect("recency entry of a cached key");
+        *used = self.tick;
+        self.recency.insert(self.tick, key);
+        self.tick += 1;
+        Some(value.clone())
+    }
+
+    /// 缓存 GET 的回复，超过最大条目数时淘汰最近最少使用的键
+    pub(crate) fn insert(&mut self, key: Bytes, value: Option<Bytes>) {
+        if let Some((_, used)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, key);
+        self.tick += 1;
+        while self.entries.len() > self.config.max_entries {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.config.counters.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 处理失效通知，键列表为空表示所有键失效
+    pub(crate) fn invalidate(&mut self, keys: Vec<Bytes>) {
+        let removed = if keys.is_empty() {
+            let removed = self.entries.len();
+            self.entries.clear();
+            self.recency.clear();
+            removed
+        } else {
+            let mut removed = 0;
+            for key in keys {
+                if let Some((_, used)) = self.entries.remove(&key) {
+                    self.recency.remove(&used);
+                    removed += 1;
+                }
+            }
+            removed
+        };
+        self.config.counters.invalidations.fetch_add(removed as u64, Ordering::Relaxed);
+    }
+
+    /// 当前缓存的键数
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
@@ -0,0 +1,748 @@
+//! 到 Redox 服务器的连接
+//! 使用 bincode 二进制格式传输命令和回复，集合类型的回复保留原有的结构，不需要解析文本。
+//! 每个方法发送一个命令并等待回复，读取值的方法可以指定转换的类型（见 `FromRedoxValue`），如 `client.get::<u64>("counter")`；
+//! 服务器的错误回复返回 `ClientError::Server`，之后连接可以继续使用；
+//! 连接出错（`ClientError::is_connection_error`）后丢弃这个连接，下一个命令重新连接并认证。
+//! 建立连接和执行命令失败时按 `RetryPolicy` 重试，默认只重试幂等的命令。
+//! `Client::transaction` 用 MULTI / EXEC 执行事务，WATCH 的键被修改时自动重试。
+//! `Client::enable_cache` 启用客户端缓存，GET 的回复缓存在进程内，由服务器的 CLIENT TRACKING 通知失效。
+//! `Client::connect_via_sentinel` 通过哨兵找到主节点，故障转移后自动连接新的主节点。
+
+use crate::cache::{Cache, CacheConfig, CacheStats};
+use crate::error::{ClientError, Result};
+use crate::hash::RedoxHash;
+use crate::multiplex::MultiplexedClient;
+use crate::pubsub::Subscription;
+use crate::retry::RetryPolicy;
+use crate::sentinel::Primary;
+use crate::transaction::{self, Transaction};
+use crate::value::{FromRedoxValue, ToRedoxValue};
+use bytes::Bytes;
+use futures::{FutureExt, SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::compact::BinaryFormat;
+use redox_protocol::{Command, ExpireCondition, RedoxValue, Response};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{self, TcpStream, ToSocketAddrs};
+use tokio_util::codec::Framed;
+
+/// 使用 bincode 编码的连接
+pub(crate) type Transport = Framed<TcpStream, ClientCodec>;
+
+/// 到 Redox 服务器的连接
+pub struct Client {
+    /// 当前的连接，连接出错后为 None，下一个命令重新连接
+    connection: Option<Connection>,
+    /// 建立新连接的方式，订阅使用单独的连接
+    endpoint: Endpoint,
+    /// 启用客户端缓存时的配置，每个新连接开启 CLIENT TRACKING 并使用新的缓存
+    cache: Option<CacheConfig>,
+}
+
+/// 执行命令的连接和它的客户端缓存
+pub(crate) struct Connection {
+    framed: Transport,
+    /// 连接的服务器地址，通过哨兵连接时用于发现主节点已经切换
+    peer: Option<SocketAddr>,
+    /// 启用客户端缓存时缓存的 GET 回复，只有这个连接收到失效通知，连接断开后随之丢弃
+    cache: Option<Cache>,
+}
+
+impl Connection {
+    /// 包装新建立的连接，还没有启用缓存
+    fn new(framed: Transport) -> Self {
+        let peer = framed.get_ref().peer_addr().ok();
+        Self { framed, peer, cache: None }
+    }
+
+    /// 处理连接上已经到达的失效通知，然后查找缓存的值
+    ///
+    /// # Returns
+    /// * `Ok(Some(value))` - 缓存的 GET 回复，None 表示键不存在
+    /// * `Ok(None)` - 没有启用缓存或没有缓存这个键
+    /// * `Err` - 连接出错或收到了不是推送的消息，连接不能再使用
+    fn cached(&mut self, key: &[u8]) -> Result<Option<Option<Bytes>>> {
+        if self.cache.is_none() {
+            return Ok(None);
+        }
+        while let Some(frame) = self.framed.next().now_or_never() {
+            match frame {
+                Some(Ok(Response::Push { kind, items })) => self.push(kind, items),
+                Some(Ok(response)) => return Err(ClientError::Protocol(format!("Unexpected message from server: {:?}", response))),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server").into()),
+            }
+        }
+        Ok(self.cache.as_mut().and_then(|cache| cache.get(key)))
+    }
+
+    /// 处理服务器推送的消息，目前只有客户端缓存的失效通知
+    fn push(&mut self, kind: String, items: Vec<Response>) {
+        if let (Some(cache), "invalidate") = (&mut self.cache, kind.as_str()) {
+            cache.invalidate(items.into_iter().filter_map(|item| Bytes::from_redox_value(item).ok()).collect());
+        }
+    }
+}
+
+/// 服务器的地址和认证信息，用于建立订阅连接和断开后重新连接
+#[derive(Clone)]
+pub(crate) struct Endpoint {
+    /// 服务器的地址
+    target: Target,
+    /// AUTH 成功的用户名和密码
+    credentials: Option<(Option<String>, String)>,
+    /// 建立连接和执行命令失败时的重试策略
+    pub(crate) retry: RetryPolicy,
+}
+
+/// 连接的服务器
+#[derive(Clone)]
+enum Target {
+    /// 解析后的固定地址
+    Fixed(Vec<SocketAddr>),
+    /// 哨兵报告的当前主节点，故障转移后改变
+    Sentinel(Primary),
+}
+
+impl Endpoint {
+    /// 解析服务器的地址
+    pub(crate) async fn lookup<A: ToSocketAddrs>(addr: A, retry: RetryPolicy) -> Result<Self> {
+        Ok(Self { target: Target::Fixed(net::lookup_host(addr).await?.collect()), credentials: None, retry })
+    }
+
+    /// 建立新的连接，失败时按重试策略等待后重试
+    pub(crate) async fn connect(&self) -> Result<Transport> {
+        let mut attempt = 0;
+        loop {
+            match self.open().await {
+                Err(e) if self.retry.should_reconnect(attempt, &e) => {
+                    attempt += 1;
+                    tokio::time::sleep(self.retry.delay(attempt)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 建立新的连接，之前 AUTH 成功过时同样认证
+    pub(crate) async fn open(&self) -> Result<Transport> {
+        let stream = match &self.target {
+            Target::Fixed(addrs) => TcpStream::connect(&addrs[..]).await?,
+            Target::Sentinel(primary) => TcpStream::connect(&primary.addrs()[..]).await?,
+        };
+        stream.set_nodelay(true)?;
+        let mut framed = Framed::new(stream, ClientCodec::binary(BinaryFormat::Bincode));
+        if let Some((username, password)) = &self.credentials {
+            framed.send(&Command::Auth { username: username.clone(), password: password.clone() }).await?;
+            match framed.next().await {
+                Some(Ok(Response::Error(e))) => return Err(ClientError::Server(e)),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server").into()),
+            }
+        }
+        Ok(framed)
+    }
+
+    /// 连接到 `peer` 的连接是否仍然可以使用：通过哨兵连接时 `peer` 需要是当前的主节点
+    pub(crate) fn is_current(&self, peer: SocketAddr) -> bool {
+        match &self.target {
+            Target::Fixed(_) => true,
+            Target::Sentinel(primary) => primary.is_current(peer),
+        }
+    }
+}
+
+impl Client {
+    /// 连接服务器
+    ///
+    /// # Arguments
+    /// * `addr` - 服务器的地址，如 `"127.0.0.1:2001"` 或 `("localhost", 2001)`
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::connect_with_retry(addr, RetryPolicy::new()).await
+    }
+
+    /// 使用指定的重试策略连接服务器
+    ///
+    /// # Arguments
+    /// * `addr` - 服务器的地址
+    /// * `retry` - 建立连接和执行命令失败时的重试策略，也用于订阅连接
+    pub async fn connect_with_retry<A: ToSocketAddrs>(addr: A, retry: RetryPolicy) -> Result<Self> {
+        Self::with_endpoint(Endpoint::lookup(addr, retry).await?).await
+    }
+
+    /// 通过哨兵连接主节点：询问哨兵当前的主节点并连接，之后在后台订阅哨兵的 `+switch-master` 事件，
+    /// 故障转移后下一个命令自动连接新的主节点；订阅（`subscribe`）断开后同样重新连接新的主节点
+    ///
+    /// ```no_run
+    /// use redox_client::Client;
+    ///
+    /// # async fn example() -> redox_client::Result<()> {
+    /// let mut client = Client::connect_via_sentinel(["10.0.0.1:22001", "10.0.0.2:22001"], "mymaster").await?;
+    /// client.auth(None, "secret").await?;
+    /// client.set("greeting", "hello").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `sentinels` - 哨兵的地址，依次询问直到一个哨兵回复
+    /// * `master_name` - 哨兵监控的主节点名称
+    ///
+    /// # Returns
+    /// * `Ok(Client)` - 已连接主节点的客户端
+    /// * `Err` - 所有哨兵都无法连接或不知道这个主节点，或无法连接主节点
+    pub async fn connect_via_sentinel<S: Into<String>>(sentinels: impl IntoIterator<Item = S>, master_name: &str) -> Result<Self> {
+        let retry = RetryPolicy::new();
+        let primary = Primary::discover(sentinels.into_iter().map(Into::into).collect(), master_name.to_string(), retry.clone()).await?;
+        Self::with_endpoint(Endpoint { target: Target::Sentinel(primary), credentials: None, retry }).await
+    }
+
+    /// 连接服务器，失败时按重试策略重试
+    async fn with_endpoint(endpoint: Endpoint) -> Result<Self> {
+        let connection = Connection::new(endpoint.connect().await?);
+        Ok(Self { connection: Some(connection), endpoint, cache: None })
+    }
+
+    /// 建立新的连接，不重试
+    async fn open(&self) -> Result<Connection> {
+        let framed = self.endpoint.open().await?;
+        self.attach(framed).await
+    }
+
+    /// 在新建立的连接上开启 CLIENT TRACKING，启用了客户端缓存时使用
+    async fn attach(&self, framed: Transport) -> Result<Connection> {
+        let mut connection = Connection::new(framed);
+        if let Some(config) = &self.cache {
+            request(&mut connection, &Command::ClientTracking { on: true }).await?;
+            connection.cache = Some(config.cache());
+        }
+        Ok(connection)
+    }
+
+    /// 建立一个多路复用的连接，使用相同的地址、认证信息和重试策略，通过哨兵连接时同样跟随故障转移
+    /// 返回的 `MultiplexedClient` 可以克隆后交给多个任务，它们的命令在同一个连接上流水线发送；当前的连接不受影响
+    ///
+    /// ```no_run
+    /// use redox_client::Client;
+    ///
+    /// # async fn example(client: &Client) -> redox_client::Result<()> {
+    /// let shared = client.multiplexed().await?;
+    /// let tasks: Vec<_> = (0..100)
+    ///     .map(|i| {
+    ///         let shared = shared.clone();
+    ///         tokio::spawn(async move { shared.set(format!("key:{}", i), i).await })
+    ///     })
+    ///     .collect();
+    /// for task in tasks {
+    ///     task.await.unwrap()?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn multiplexed(&self) -> Result<MultiplexedClient> {
+        MultiplexedClient::open(self.endpoint.clone()).await
+    }
+
+    /// 通过哨兵连接时，主节点已经切换后丢弃连接到旧主节点的连接，下一个命令连接新的主节点
+    fn drop_stale(&mut self) {
+        let stale = self.connection.as_ref().and_then(|connection| connection.peer).is_some_and(|peer| !self.endpoint.is_current(peer));
+        if stale {
+            self.connection = None;
+        }
+    }
+
+    /// 修改重试策略，只影响之后执行的命令和建立的订阅
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.endpoint.retry = retry;
+    }
+
+    /// 发送一个命令并等待回复，用于没有对应方法的命令
+    /// 失败时按重试策略重试，连接出错时先重新连接
+    ///
+    /// # Returns
+    /// * `Ok(Response)` - 服务器的回复，不是错误回复
+    /// * `Err(ClientError::Server)` - 服务器的错误回复
+    /// * `Err` - 连接出错或被关闭，且不再重试
+    pub async fn execute(&mut self, cmd: &Command) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            self.drop_stale();
+            // 命令还没有发出，连接失败时总是可以重试
+            let connection = match &mut self.connection {
+                Some(connection) => connection,
+                None => match self.open().await {
+                    Ok(connection) => self.connection.insert(connection),
+                    Err(e) if self.endpoint.retry.should_reconnect(attempt, &e) => {
+                        attempt += 1;
+                        tokio::time::sleep(self.endpoint.retry.delay(attempt)).await;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                },
+            };
+            let result = request(connection, cmd).await;
+            if result.as_ref().is_err_and(ClientError::is_connection_error) {
+                self.connection = None;
+            }
+            match result {
+                Err(e) if self.endpoint.retry.should_retry(attempt, cmd, &e) => {
+                    attempt += 1;
+                    tokio::time::sleep(self.endpoint.retry.delay(attempt)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 用 MULTI / EXEC 执行事务，把 EXEC 的回复（每个命令的回复，按排队的顺序）转换为 T，如 `Vec<Response>` 或 `(bool, usize)`
+    /// body 中用 `Transaction::watch` WATCH 键、用 `Transaction::get` 等方法立即读取，用 `Transaction::set` 等方法排队写命令；
+    /// body 返回后排队的命令在一次往返中用 MULTI / EXEC 发送。WATCH 的键在 EXEC 之前被其他连接修改时服务器不执行事务，
+    /// 这时重新执行 body，直到事务成功。事务不会按重试策略重试，连接出错时返回错误，这时无法知道 EXEC 是否已经执行。
+    ///
+    /// ```no_run
+    /// use redox_client::Client;
+    ///
+    /// # async fn example(client: &mut Client) -> redox_client::Result<()> {
+    /// let (): () = client.transaction(async |tx| {
+    ///     tx.watch(&["balance"]).await?;
+    ///     let balance: i64 = tx.get::<Option<i64>>("balance").await?.unwrap_or(0);
+    ///     tx.set("balance", balance - 10).lpush("history", "-10");
+    ///     Ok(())
+    /// }).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Returns
+    /// * `Ok(T)` - 事务执行成功，转换后的回复
+    /// * `Err(ClientError::Server)` - body 返回的错误、排队时出错的命令（这时事务没有执行）或执行时第一个出错的命令（其他命令已经执行）
+    /// * `Err` - 连接出错或回复无法转换为 T
+    pub async fn transaction<T: FromRedoxValue>(&mut self, mut body: impl AsyncFnMut(&mut Transaction<'_>) -> Result<()>) -> Result<T> {
+        loop {
+            self.drop_stale();
+            if self.connection.is_none() {
+                let framed = self.endpoint.connect().await?;
+                self.connection = Some(self.attach(framed).await?);
+            }
+            let connection = self.connection.as_mut().unwrap();
+            let result = transaction::run(connection, &mut body).await;
+            if result.as_ref().is_err_and(ClientError::is_connection_error) {
+                self.connection = None;
+            }
+            match result? {
+                // WATCH 的键被修改，重新读取后再试
+                None => continue,
+                Some(Response::Replies(replies)) => {
+                    if let Some(Response::Error(e)) = replies.iter().find(|reply| matches!(reply, Response::Error(_))) {
+                        return Err(ClientError::Server(e.clone()));
+                    }
+                    return T::from_redox_value(Response::Replies(replies));
+                }
+                Some(response) => return T::from_redox_value(response),
+            }
+        }
+    }
+
+    /// 启用客户端缓存：在连接上开启 CLIENT TRACKING，之后 `get` 的回复缓存在进程内，键被修改时由服务器的通知移除
+    /// 只有 `get` 使用缓存，其他读取命令总是发送到服务器；服务器需要支持 CLIENT TRACKING
+    ///
+    /// # Arguments
+    /// * `max_entries` - 最多缓存的键数，超过时淘汰最近最少使用的键
+    pub async fn enable_cache(&mut self, max_entries: usize) -> Result<()> {
+        let config = CacheConfig::new(max_entries);
+        self.execute(&Command::ClientTracking { on: true }).await?;
+        if let Some(connection) = &mut self.connection {
+            connection.cache = Some(config.cache());
+        }
+        self.cache = Some(config);
+        Ok(())
+    }
+
+    /// 关闭客户端缓存并丢弃缓存的值
+    pub async fn disable_cache(&mut self) -> Result<()> {
+        self.cache = None;
+        if let Some(connection) = &mut self.connection {
+            connection.cache = None;
+        }
+        self.query(&Command::ClientTracking { on: false }).await
+    }
+
+    /// 客户端缓存的命中、未命中、失效和淘汰次数，没有启用缓存时为 None
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        let entries = self.connection.as_ref().and_then(|connection| connection.cache.as_ref()).map_or(0, Cache::len);
+        self.cache.as_ref().map(|config| config.stats(entries))
+    }
+
+    /// 发送一个命令，把回复转换为 T
+    ///
+    /// # Returns
+    /// * `Ok(T)` - 转换后的回复
+    /// * `Err(ClientError::UnexpectedReply)` - 回复无法转换为 T
+    /// * `Err` - 服务器的错误回复、连接出错或被关闭
+    pub async fn query<T: FromRedoxValue>(&mut self, cmd: &Command) -> Result<T> {
+        T::from_redox_value(self.execute(cmd).await?)
+    }
+
+    /// AUTH，认证连接
+    ///
+    /// # Arguments
+    /// * `username` - 用户名，None 表示 default 用户
+    /// * `password` - 密码
+    pub async fn auth(&mut self, username: Option<&str>, password: &str) -> Result<()> {
+        let cmd = Command::Auth { username: username.map(str::to_string), password: password.to_string() };
+        self.query::<()>(&cmd).await?;
+        self.endpoint.credentials = Some((username.map(str::to_string), password.to_string()));
+        Ok(())
+    }
+
+    /// PUBLISH，返回收到消息的订阅数
+    pub async fn publish(&mut self, channel: impl ToRedoxValue, message: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::Publish { channel: channel.to_redox_bytes(), message: message.to_redox_bytes() }).await
+    }
+
+    /// SUBSCRIBE，在单独的连接上订阅频道，返回收到的消息组成的 Stream
+    /// 这个连接不影响当前连接执行其他命令，断开后自动重新连接并重新订阅，断开期间发送的消息会丢失
+    ///
+    /// # Returns
+    /// * `Ok(Subscription)` - 服务器已确认订阅
+    /// * `Err` - 无法连接、认证失败或没有订阅的权限
+    pub async fn subscribe<C: ToRedoxValue>(&self, channels: impl IntoIterator<Item = C>) -> Result<Subscription> {
+        Subscription::open(self.endpoint.clone(), Command::Subscribe(channels.into_iter().map(|channel| channel.to_redox_bytes()).collect())).await
+    }
+
+    /// PSUBSCRIBE，与 `subscribe` 相同，但订阅名称匹配通配符的频道
+    pub async fn psubscribe<P: ToRedoxValue>(&self, patterns: impl IntoIterator<Item = P>) -> Result<Subscription> {
+        Subscription::open(self.endpoint.clone(), Command::PSubscribe(patterns.into_iter().map(|pattern| pattern.to_redox_bytes()).collect())).await
+    }
+
+    /// PING，检查连接是否可用
+    pub async fn ping(&mut self) -> Result<()> {
+        self.query(&Command::Ping { message: None }).await
+    }
+
+    /// ECHO，返回相同的消息
+    pub async fn echo<T: FromRedoxValue>(&mut self, message: impl ToRedoxValue) -> Result<T> {
+        self.query(&Command::Echo { message: message.to_redox_bytes() }).await
+    }
+
+    /// GET，转换为 `Option<T>` 时键不存在为 None，转换为其他类型时键不存在返回错误
+    /// 启用客户端缓存时先查找缓存，没有缓存时把服务器的回复加入缓存
+    pub async fn get<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue) -> Result<T> {
+        let key = key.to_redox_bytes();
+        self.drop_stale();
+        if let Some(connection) = &mut self.connection {
+            match connection.cached(&key) {
+                Ok(Some(Some(value))) => return T::from_redox_value(Response::Value(RedoxValue::String(value))),
+                Ok(Some(None)) => return T::from_redox_value(Response::Nil),
+                Ok(None) => {}
+                // 连接不能再使用，下面重新连接后发送 GET
+                Err(_) => self.connection = None,
+            }
+        }
+        let response = self.execute(&Command::Get { key: key.clone() }).await?;
+        if let Some(cache) = self.connection.as_mut().and_then(|connection| connection.cache.as_mut()) {
+            match &response {
+                Response::Value(RedoxValue::String(value)) => cache.insert(key, Some(value.clone())),
+                Response::Nil => cache.insert(key, None),
+                _ => {}
+            }
+        }
+        T::from_redox_value(response)
+    }
+
+    /// SET
+    pub async fn set(&mut self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> Result<()> {
+        self.query(&Command::Set { key: key.to_redox_bytes(), value: value.to_redox_bytes() }).await
+    }
+
+    /// CAS，当前值等于 expected 时设置为 value
+    ///
+    /// # Returns
+    /// 是否设置了新的值
+    pub async fn cas(&mut self, key: impl ToRedoxValue, expected: impl ToRedoxValue, value: impl ToRedoxValue) -> Result<bool> {
+        let cmd = Command::Cas { key: key.to_redox_bytes(), expected: expected.to_redox_bytes(), value: value.to_redox_bytes() };
+        self.query(&cmd).await
+    }
+
+    /// STRLEN，键不存在时为 0
+    pub async fn strlen(&mut self, key: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::StrLen { key: key.to_redox_bytes() }).await
+    }
+
+    /// MGET，按顺序返回每个键的值，如 `Vec<Option<String>>`，不存在的键为 None
+    pub async fn mget<T: FromRedoxValue, K: ToRedoxValue>(&mut self, keys: &[K]) -> Result<T> {
+        self.query(&Command::MGet(keys.iter().map(ToRedoxValue::to_redox_bytes).collect())).await
+    }
+
+    /// MSET
+    pub async fn mset<K: ToRedoxValue, V: ToRedoxValue>(&mut self, pairs: &[(K, V)]) -> Result<()> {
+        let cmd = Command::MSet(pairs.iter().map(|(key, value)| (key.to_redox_bytes(), value.to_redox_bytes())).collect());
+        self.query(&cmd).await
+    }
+
+    /// DEL，返回删除的键数
+    pub async fn del<K: ToRedoxValue>(&mut self, keys: &[K]) -> Result<usize> {
+        self.query(&Command::Del(keys.iter().map(ToRedoxValue::to_redox_bytes).collect())).await
+    }
+
+    /// UNLINK，与 DEL 相同，但较大的值在后台释放
+    pub async fn unlink<K: ToRedoxValue>(&mut self, keys: &[K]) -> Result<usize> {
+        self.query(&Command::Unlink(keys.iter().map(ToRedoxValue::to_redox_bytes).collect())).await
+    }
+
+    /// TYPE，键不存在时为 None
+    pub async fn key_type(&mut self, key: impl ToRedoxValue) -> Result<Option<String>> {
+        let name: Bytes = self.query(&Command::Type { key: key.to_redox_bytes() }).await?;
+        Ok((name.as_ref() != b"none").then(|| String::from_utf8_lossy(&name).into_owned()))
+    }
+
+    /// SCAN，返回下一次遍历使用的游标（0 表示遍历结束）和这一批键
+    ///
+    /// # Arguments
+    /// * `cursor` - 游标，第一次为 0
+    /// * `pattern` - 只返回匹配通配符的键
+    /// * `count` - 服务器每次检查的键数
+    pub async fn scan(&mut self, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> Result<(u64, Vec<Bytes>)> {
+        let pattern = pattern.map(|pattern| pattern.to_redox_bytes());
+        self.query(&Command::Scan { cursor, pattern, count }).await
+    }
+
+    /// EXPIRE，设置键在 seconds 秒后过期
+    ///
+    /// # Returns
+    /// 是否设置了过期时间，键不存在时为 false
+    pub async fn expire(&mut self, key: impl ToRedoxValue, seconds: u64) -> Result<bool> {
+        let cmd = Command::Expire { key: key.to_redox_bytes(), seconds, condition: None };
+        self.query(&cmd).await
+    }
+
+    /// PEXPIRE，按条件设置键在一段时间后过期，精确到毫秒
+    ///
+    /// # Arguments
+    /// * `key` - 键
+    /// * `ttl` - 剩余生存时间
+    /// * `condition` - NX、XX、GT 或 LT，None 表示总是设置
+    pub async fn pexpire(&mut self, key: impl ToRedoxValue, ttl: Duration, condition: Option<ExpireCondition>) -> Result<bool> {
+        let cmd = Command::PExpire { key: key.to_redox_bytes(), milliseconds: ttl.as_millis() as u64, condition };
+        self.query(&cmd).await
+    }
+
+    /// PERSIST，移除键的过期时间
+    pub async fn persist(&mut self, key: impl ToRedoxValue) -> Result<bool> {
+        self.query(&Command::Persist { key: key.to_redox_bytes() }).await
+    }
+
+    /// TTL，键的剩余生存时间，精确到秒
+    /// 键不存在、已过期或没有过期时间时为 None
+    pub async fn ttl(&mut self, key: impl ToRedoxValue) -> Result<Option<Duration>> {
+        let ttl: i64 = self.query(&Command::TTL { key: key.to_redox_bytes() }).await?;
+        Ok(remaining(ttl, Duration::from_secs))
+    }
+
+    /// PTTL，键的剩余生存时间，精确到毫秒
+    /// 键不存在、已过期或没有过期时间时为 None
+    pub async fn pttl(&mut self, key: impl ToRedoxValue) -> Result<Option<Duration>> {
+        let ttl: i64 = self.query(&Command::PTTL { key: key.to_redox_bytes() }).await?;
+        Ok(remaining(ttl, Duration::from_millis))
+    }
+
+    /// LPUSH，返回列表的新长度
+    pub async fn lpush(&mut self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::LPush { key: key.to_redox_bytes(), value: value.to_redox_bytes() }).await
+    }
+
+    /// RPUSH，返回列表的新长度
+    pub async fn rpush(&mut self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::RPush { key: key.to_redox_bytes(), value: value.to_redox_bytes() }).await
+    }
+
+    /// LPOP，列表为空或不存在时为 None
+    pub async fn lpop<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue) -> Result<Option<T>> {
+        self.query(&Command::LPop { key: key.to_redox_bytes() }).await
+    }
+
+    /// RPOP，列表为空或不存在时为 None
+    pub async fn rpop<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue) -> Result<Option<T>> {
+        self.query(&Command::RPop { key: key.to_redox_bytes() }).await
+    }
+
+    /// LRANGE，下标可以为负数，-1 表示最后一个元素
+    pub async fn lrange<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue, start: i64, stop: i64) -> Result<Vec<T>> {
+        self.query(&Command::LRange { key: key.to_redox_bytes(), start, stop }).await
+    }
+
+    /// LLEN
+    pub async fn llen(&mut self, key: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::LLen { key: key.to_redox_bytes() }).await
+    }
+
+    /// SADD，返回成员是否是新加入的
+    pub async fn sadd(&mut self, key: impl ToRedoxValue, member: impl ToRedoxValue) -> Result<bool> {
+        self.query(&Command::SAdd { key: key.to_redox_bytes(), member: member.to_redox_bytes() }).await
+    }
+
+    /// SREM，返回成员是否存在并被删除
+    pub async fn srem(&mut self, key: impl ToRedoxValue, member: impl ToRedoxValue) -> Result<bool> {
+        self.query(&Command::SRem { key: key.to_redox_bytes(), member: member.to_redox_bytes() }).await
+    }
+
+    /// SMEMBERS，如 `HashSet<String>` 或 `Vec<Bytes>`
+    pub async fn smembers<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue) -> Result<T> {
+        self.query(&Command::SMembers { key: key.to_redox_bytes() }).await
+    }
+
+    /// SISMEMBER
+    pub async fn sismember(&mut self, key: impl ToRedoxValue, member: impl ToRedoxValue) -> Result<bool> {
+        let cmd = Command::SIsMember { key: key.to_redox_bytes(), member: member.to_redox_bytes() };
+        self.query(&cmd).await
+    }
+
+    /// SCARD
+    pub async fn scard(&mut self, key: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::SCard { key: key.to_redox_bytes() }).await
+    }
+
+    /// HSET，返回字段是否是新加入的
+    pub async fn hset(&mut self, key: impl ToRedoxValue, field: impl ToRedoxValue, value: impl ToRedoxValue) -> Result<bool> {
+        let cmd = Command::HSet { key: key.to_redox_bytes(), fields: vec![(field.to_redox_bytes(), value.to_redox_bytes())] };
+        self.query(&cmd).await
+    }
+
+    /// HSET，一次设置多个字段，返回新加入的字段数
+    pub async fn hset_multiple<F: ToRedoxValue, V: ToRedoxValue>(&mut self, key: impl ToRedoxValue, fields: &[(F, V)]) -> Result<usize> {
+        if fields.is_empty() {
+            return Ok(0);
+        }
+        let fields = fields.iter().map(|(field, value)| (field.to_redox_bytes(), value.to_redox_bytes())).collect();
+        self.query(&Command::HSet { key: key.to_redox_bytes(), fields }).await
+    }
+
+    /// 把结构体的字段用 HSET 写入哈希表，返回新加入的字段数
+    /// 值为 None 的 `Option` 字段用 HDEL 从哈希表中删除，与 HSET 在同一个 MULTI / EXEC 中执行，读取时得到的就是写入的结构体
+    pub async fn hset_struct<T: RedoxHash>(&mut self, key: impl ToRedoxValue, value: &T) -> Result<usize> {
+        let absent = value.absent_fields();
+        if absent.is_empty() {
+            return self.hset_multiple(key, &value.to_hash()).await;
+        }
+        let key = key.to_redox_bytes();
+        let fields = value.to_hash();
+        let set = !fields.is_empty();
+        let replies: Vec<Response> = self.transaction(async |tx| {
+            if set {
+                tx.queue(Command::HSet { key: key.clone(), fields: fields.clone() });
+            }
+            for field in &absent {
+                tx.queue(Command::HDel { key: key.clone(), field: field.clone() });
+            }
+            Ok(())
+        }).await?;
+        match replies.into_iter().next() {
+            Some(reply) if set => usize::from_redox_value(reply),
+            _ => Ok(0),
+        }
+    }
+
+    /// 用 HGETALL 读取哈希表并转换为结构体，键不存在时为 None
+    pub async fn hget_struct<T: RedoxHash>(&mut self, key: impl ToRedoxValue) -> Result<Option<T>> {
+        let hash: HashMap<Bytes, Bytes> = self.hgetall(key).await?;
+        if hash.is_empty() {
+            return Ok(None);
+        }
+        T::from_hash(hash).map(Some)
+    }
+
+    /// HGET，转换为 `Option<T>` 时字段不存在为 None
+    pub async fn hget<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue, field: impl ToRedoxValue) -> Result<T> {
+        let cmd = Command::HGet { key: key.to_redox_bytes(), field: field.to_redox_bytes() };
+        self.query(&cmd).await
+    }
+
+    /// HGETALL，如 `HashMap<String, String>`
+    pub async fn hgetall<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue) -> Result<T> {
+        self.query(&Command::HGetAll { key: key.to_redox_bytes() }).await
+    }
+
+    /// HDEL，返回字段是否存在并被删除
+    pub async fn hdel(&mut self, key: impl ToRedoxValue, field: impl ToRedoxValue) -> Result<bool> {
+        self.query(&Command::HDel { key: key.to_redox_bytes(), field: field.to_redox_bytes() }).await
+    }
+
+    /// HLEN
+    pub async fn hlen(&mut self, key: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::HLen { key: key.to_redox_bytes() }).await
+    }
+
+    /// ZADD，返回成员是否是新加入的，已存在的成员更新分数
+    pub async fn zadd(&mut self, key: impl ToRedoxValue, score: f64, member: impl ToRedoxValue) -> Result<bool> {
+        let cmd = Command::ZAdd { key: key.to_redox_bytes(), score, member: member.to_redox_bytes() };
+        self.query(&cmd).await
+    }
+
+    /// ZREM，返回成员是否存在并被删除
+    pub async fn zrem(&mut self, key: impl ToRedoxValue, member: impl ToRedoxValue) -> Result<bool> {
+        self.query(&Command::ZRem { key: key.to_redox_bytes(), member: member.to_redox_bytes() }).await
+    }
+
+    /// ZRANGE，按排名返回成员和分数，如 `Vec<(String, f64)>`，下标可以为负数
+    pub async fn zrange<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue, start: i64, stop: i64) -> Result<T> {
+        self.query(&Command::ZRange { key: key.to_redox_bytes(), start, stop }).await
+    }
+
+    /// ZRANGEBYSCORE，返回分数在 [min, max] 中的成员和分数，如 `Vec<(String, f64)>`
+    pub async fn zrangebyscore<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue, min: f64, max: f64) -> Result<T> {
+        let cmd = Command::ZRangeByScore { key: key.to_redox_bytes(), min, max };
+        self.query(&cmd).await
+    }
+
+    /// ZCARD
+    pub async fn zcard(&mut self, key: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::ZCard { key: key.to_redox_bytes() }).await
+    }
+
+    /// INFO，服务器的信息和统计
+    pub async fn info(&mut self) -> Result<HashMap<String, String>> {
+        self.query(&Command::Info).await
+    }
+}
+
+/// 把 TTL 和 PTTL 的回复转换为剩余生存时间：-2 表示键不存在或已过期，-1 表示没有过期时间
+fn remaining(ttl: i64, unit: fn(u64) -> Duration) -> Option<Duration> {
+    match ttl {
+        ttl if ttl < 0 => None,
+        ttl => Some(unit(ttl as u64)),
+    }
+}
+
+/// 在连接上发送一个命令并读取回复，错误回复转换为 `ClientError::Server`
+pub(crate) async fn request(connection: &mut Connection, cmd: &Command) -> Result<Response> {
+    connection.framed.send(cmd).await?;
+    match receive(connection).await? {
+        Response::Error(e) => Err(ClientError::Server(e)),
+        response => Ok(response),
+    }
+}
+
+/// 读取一个回复，错误回复原样返回，之前到达的推送消息交给连接处理
+pub(crate) async fn receive(connection: &mut Connection) -> Result<Response> {
+    loop {
+        match connection.framed.next().await {
+            Some(Ok(Response::Push { kind, items })) => connection.push(kind, items),
+            Some(Ok(response)) => return Ok(response),
+            Some(Err(e)) => return Err(e.into()),
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server").into()),
+        }
+    }
+}
+
+/// 在连接上发送多个命令，用于事务一次发出 MULTI、排队的命令和 EXEC
+pub(crate) async fn send_all(connection: &mut Connection, cmds: impl IntoIterator<Item = &Command>) -> Result<()> {
+    for cmd in cmds {
+        connection.framed.feed(cmd).await?;
+    }
+    SinkExt::<&Command>::flush(&mut connection.framed).await?;
+    Ok(())
+}
@@ -0,0 +1,309 @@
+//! 集群客户端
+//! `ClusterClient` 从任意一个节点读取 CLUSTER SLOTS，建立槽到主节点的映射，按第一个键的槽把命令发送给负责的节点，
+//! 每个节点使用一个 `Client` 连接。回复为 `MOVED` 时重新读取集群拓扑并发送到新的节点，
+//! 为 `ASK` 时先向目标节点发送 ASKING 再重试这一个命令（不更新映射）；连接出错和 `CLUSTERDOWN`、`TRYAGAIN` 时
+//! 同样重新读取拓扑，并按重试策略重试。没有键的命令发送给任意一个主节点，`scan_all` 和 `broadcast` 访问所有主节点。
+
+use crate::client::Client;
+use crate::error::{ClientError, Result};
+use crate::retry::RetryPolicy;
+use crate::value::{FromRedoxValue, ToRedoxValue};
+use bytes::Bytes;
+use redox_protocol::slot::key_slot;
+use redox_protocol::{text, Command, RedoxError, Response};
+use std::collections::{BTreeMap, HashMap};
+
+/// 一个命令最多跟随的重定向次数，避免槽迁移期间节点之间来回重定向
+const MAX_REDIRECTS: usize = 16;
+
+/// 集群客户端
+///
+/// ```no_run
+/// use redox_client::ClusterClient;
+///
+/// # async fn example() -> redox_client::Result<()> {
+/// let mut cluster = ClusterClient::connect(["127.0.0.1:7001", "127.0.0.1:7002"]).await?;
+/// cluster.set("user:1", "alice").await?;
+/// let name: Option<String> = cluster.get("user:1").await?;
+/// let keys = cluster.scan_all(Some("user:*")).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClusterClient {
+    /// 初始的节点地址，所有已知节点都无法访问时从这里重新读取拓扑
+    seeds: Vec<String>,
+    /// 槽范围的起点到终点和负责的主节点地址
+    slots: BTreeMap<u16, (u16, String)>,
+    /// 节点地址到连接
+    nodes: HashMap<String, Client>,
+    /// AUTH 的用户名和密码，连接新的节点时使用
+    credentials: Option<(Option<String>, String)>,
+    /// 每个节点连接的重试策略，也用于连接出错和集群暂时不可用时重试整个命令
+    retry: RetryPolicy,
+}
+
+impl ClusterClient {
+    /// 连接集群，从第一个可以访问的节点读取拓扑
+    ///
+    /// # Arguments
+    /// * `seeds` - 集群中一个或多个节点的地址，如 `"127.0.0.1:7001"`
+    pub async fn connect<S: Into<String>>(seeds: impl IntoIterator<Item = S>) -> Result<Self> {
+        Self::connect_with(seeds, None, RetryPolicy::new()).await
+    }
+
+    /// 连接需要认证的集群
+    ///
+    /// # Arguments
+    /// * `seeds` - 集群中一个或多个节点的地址
+    /// * `username` - 用户名，None 表示 default 用户
+    /// * `password` - 密码，所有节点使用相同的用户和密码
+    pub async fn connect_with_auth<S: Into<String>>(
+        seeds: impl IntoIterator<Item = S>,
+        username: Option<&str>,
+        password: &str,
+    ) -> Result<Self> {
+        Self::connect_with(seeds, Some((username.map(str::to_string), password.to_string())), RetryPolicy::new()).await
+    }
+
+    /// 使用指定的认证信息和重试策略连接集群
+    ///
+    /// # Arguments
+    /// * `seeds` - 集群中一个或多个节点的地址
+    /// * `credentials` - 用户名和密码，None 表示不需要认证
+    /// * `retry` - 每个节点连接的重试策略
+    pub async fn connect_with<S: Into<String>>(
+        seeds: impl IntoIterator<Item = S>,
+        credentials: Option<(Option<String>, String)>,
+        retry: RetryPolicy,
+    ) -> Result<Self> {
+        let mut cluster = Self {
+            seeds: seeds.into_iter().map(Into::into).collect(),
+            slots: BTreeMap::new(),
+            nodes: HashMap::new(),
+            credentials,
+            retry,
+        };
+        cluster.refresh_topology().await?;
+        Ok(cluster)
+    }
+
+    /// 重新读取 CLUSTER SLOTS，依次尝试已知的节点和初始的节点，关闭不再是主节点的连接
+    ///
+    /// # Returns
+    /// * `Ok(())` - 已更新槽的映射
+    /// * `Err` - 所有节点都无法访问，或集群模式没有启用，为最后一个节点的错误
+    pub async fn refresh_topology(&mut self) -> Result<()> {
+        let mut candidates: Vec<String> = self.nodes.keys().cloned().collect();
+        candidates.extend(self.seeds.iter().filter(|seed| !self.nodes.contains_key(*seed)).cloned());
+        let mut last_error = ClientError::Protocol("No seed nodes given".to_string());
+        for addr in candidates {
+            let response = match self.node(&addr).await {
+                Ok(client) => client.execute(&Command::ClusterSlots).await,
+                Err(e) => Err(e),
+            };
+            match response.and_then(parse_slots) {
+                Ok(slots) => {
+                    self.nodes.retain(|addr, _| slots.values().any(|(_, primary)| primary == addr));
+                    self.slots = slots;
+                    return Ok(());
+                }
+                Err(e) => {
+                    self.nodes.remove(&addr);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// 修改重试策略，只影响之后建立的节点连接和执行的命令
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        for client in self.nodes.values_mut() {
+            client.set_retry_policy(retry.clone());
+        }
+        self.retry = retry;
+    }
+
+    /// 负责槽的主节点的地址，没有节点负责或映射中没有这个槽时为 None
+    pub fn node_for_slot(&self, slot: u16) -> Option<&str> {
+        let (_, (end, addr)) = self.slots.range(..=slot).next_back()?;
+        (slot <= *end).then_some(addr.as_str())
+    }
+
+    /// 所有主节点的地址，按地址排序
+    pub fn primaries(&self) -> Vec<String> {
+        let mut primaries: Vec<String> = self.slots.values().map(|(_, addr)| addr.clone()).collect();
+        primaries.sort_unstable();
+        primaries.dedup();
+        primaries
+    }
+
+    /// 到一个节点的连接，还没有连接时建立并认证，用于只需要访问某个节点的命令
+    ///
+    /// # Arguments
+    /// * `addr` - 节点的地址，如 `"127.0.0.1:7001"`
+    pub async fn node(&mut self, addr: &str) -> Result<&mut Client> {
+        if !self.nodes.contains_key(addr) {
+            let mut client = Client::connect_with_retry(addr, self.retry.clone()).await?;
+            if let Some((username, password)) = &self.credentials {
+                client.auth(username.as_deref(), password).await?;
+            }
+            self.nodes.insert(addr.to_string(), client);
+        }
+        Ok(self.nodes.get_mut(addr).unwrap())
+    }
+
+    /// 把命令发送给负责第一个键的槽的主节点，跟随 MOVED 和 ASK 重定向
+    /// 多个键必须在同一个槽，否则服务器回复 `CROSSSLOT`；没有键的命令发送给任意一个主节点
+    ///
+    /// # Returns
+    /// * `Ok(Response)` - 服务器的回复，不是错误回复
+    /// * `Err(ClientError::Server)` - 服务器的错误回复，重定向超过 16 次时为最后一次重定向
+    /// * `Err` - 连接出错且不再重试
+    pub async fn execute(&mut self, cmd: &Command) -> Result<Response> {
+        let slot = cmd.keys().first().map(|key| key_slot(key));
+        let mut target = None;
+        let mut asking = false;
+        let mut attempt = 0;
+        let mut redirects = 0;
+        loop {
+            let addr = match target.take() {
+                Some(addr) => addr,
+                None => self.route(slot)?,
+            };
+            let client = self.node(&addr).await?;
+            // ASK 只对紧接着的一个命令有效
+            if asking {
+                client.execute(&Command::Asking).await?;
+            }
+            let error = match client.execute(cmd).await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            if error.is_connection_error() {
+                self.nodes.remove(&addr);
+            }
+            asking = false;
+            match &error {
+                ClientError::Server(RedoxError::Moved { addr, .. }) if redirects < MAX_REDIRECTS => {
+                    // 槽已经属于其他节点，其他槽可能也已经变化；无法读取拓扑时仍然跟随这一次重定向
+                    redirects += 1;
+                    if self.refresh_topology().await.is_err() || self.route(slot).ok().as_ref() != Some(addr) {
+                        target = Some(addr.clone());
+                    }
+                }
+                ClientError::Server(RedoxError::Ask { addr, .. }) if redirects < MAX_REDIRECTS => {
+                    redirects += 1;
+                    target = Some(addr.clone());
+                    asking = true;
+                }
+                _ if self.retry.should_retry(attempt, cmd, &error) => {
+                    // 节点下线或集群暂时不可用，等待之后按新的拓扑重试
+                    attempt += 1;
+                    tokio::time::sleep(self.retry.delay(attempt)).await;
+                    let _ = self.refresh_topology().await;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+
+    /// 发送一个命令，把回复转换为 T
+    pub async fn query<T: FromRedoxValue>(&mut self, cmd: &Command) -> Result<T> {
+        T::from_redox_value(self.execute(cmd).await?)
+    }
+
+    /// GET，转换为 `Option<T>` 时键不存在为 None
+    pub async fn get<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue) -> Result<T> {
+        self.query(&Command::Get { key: key.to_redox_bytes() }).await
+    }
+
+    /// SET
+    pub async fn set(&mut self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> Result<()> {
+        self.query(&Command::Set { key: key.to_redox_bytes(), value: value.to_redox_bytes() }).await
+    }
+
+    /// DEL，返回删除的键数，所有键必须在同一个槽
+    pub async fn del<K: ToRedoxValue>(&mut self, keys: &[K]) -> Result<usize> {
+        self.query(&Command::Del(keys.iter().map(ToRedoxValue::to_redox_bytes).collect())).await
+    }
+
+    /// 在每个主节点上执行同一个命令，如 INFO、FLUSHALL、SCRIPT LOAD
+    ///
+    /// # Returns
+    /// 每个主节点的地址和回复，按地址排序；任何一个节点出错时返回这个错误
+    pub async fn broadcast(&mut self, cmd: &Command) -> Result<Vec<(String, Response)>> {
+        let mut replies = Vec::new();
+        for addr in self.primaries() {
+            let response = self.node(&addr).await?.execute(cmd).await?;
+            replies.push((addr, response));
+        }
+        Ok(replies)
+    }
+
+    /// 在每个主节点上用 SCAN 遍历所有键，返回匹配通配符的键
+    /// 遍历期间迁移的槽中的键可能出现两次或被遗漏，与单个节点上 SCAN 的保证相同
+    ///
+    /// # Arguments
+    /// * `pattern` - 只返回匹配通配符的键，None 表示所有键
+    pub async fn scan_all(&mut self, pattern: Option<&str>) -> Result<Vec<Bytes>> {
+        let mut keys = Vec::new();
+        for addr in self.primaries() {
+            let client = self.node(&addr).await?;
+            let mut cursor = 0;
+            loop {
+                let (next, batch) = client.scan(cursor, pattern, None).await?;
+                keys.extend(batch);
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+        Ok(keys)
+    }
+
+    /// 命令应当发送到的节点：负责这个槽的主节点，没有键或映射中没有这个槽时为任意一个主节点
+    fn route(&self, slot: Option<u16>) -> Result<String> {
+        if let Some(addr) = slot.and_then(|slot| self.node_for_slot(slot)) {
+            return Ok(addr.to_string());
+        }
+        self.slots.values().next()
+            .map(|(_, addr)| addr.clone())
+            .ok_or_else(|| ClientError::Server(RedoxError::ClusterDown("Hash slot not served".to_string())))
+    }
+}
+
+/// 解析 CLUSTER SLOTS 的回复：每个槽范围为 `start`、`end`、`master`（地址、端口和 ID）和 `replicas`
+fn parse_slots(response: Response) -> Result<BTreeMap<u16, (u16, String)>> {
+    let invalid = || ClientError::UnexpectedReply("invalid CLUSTER SLOTS reply".to_string());
+    let Response::Map(ranges) = response else {
+        return Err(invalid());
+    };
+    let mut slots = BTreeMap::new();
+    for (_, range) in ranges {
+        let Response::Map(fields) = range else {
+            return Err(invalid());
+        };
+        let (mut start, mut end, mut primary) = (None, None, None);
+        for (name, value) in fields {
+            match (name.as_str(), value) {
+                ("start", Response::Integer(n)) => start = u16::try_from(n).ok(),
+                ("end", Response::Integer(n)) => end = u16::try_from(n).ok(),
+                ("master", Response::Array(items)) => {
+                    if let [Some(host), Some(port), ..] = items.as_slice() {
+                        let host = text(host);
+                        let port = text(port);
+                        primary = Some(if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) });
+                    }
+                }
+                _ => {}
+            }
+        }
+        let (Some(start), Some(end), Some(primary)) = (start, end, primary) else {
+            return Err(invalid());
+        };
+        slots.insert(start, (end, primary));
+    }
+    Ok(slots)
+}
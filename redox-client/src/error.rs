@@ -0,0 +1,81 @@
+//! 客户端的错误类型
+
+use redox_protocol::codec::CodecError;
+use redox_protocol::RedoxError;
+use std::fmt;
+use std::io;
+
+/// 执行命令失败的原因
+#[derive(Debug)]
+pub enum ClientError {
+    /// 连接失败、读写出错或连接被服务器关闭
+    Io(io::Error),
+    /// 无法编码命令或解析回复
+    Protocol(String),
+    /// 服务器回复的错误，如 `WRONGTYPE`，连接仍然可以继续使用
+    Server(RedoxError),
+    /// 回复无法转换为请求的类型，如把 "abc" 转换为整数、把 nil 转换为 `String`，或回复的类型与命令不符
+    UnexpectedReply(String),
+}
+
+impl ClientError {
+    /// 是否是连接层面的错误，这时连接不能再使用
+    pub fn is_connection_error(&self) -> bool {
+        matches!(self, ClientError::Io(_) | ClientError::Protocol(_))
+    }
+
+    /// 复制错误，一次连接错误需要交给多个等待回复的调用者时使用；`io::Error` 只保留类型和信息
+    pub(crate) fn duplicate(&self) -> ClientError {
+        match self {
+            ClientError::Io(e) => ClientError::Io(io::Error::new(e.kind(), e.to_string())),
+            ClientError::Protocol(message) => ClientError::Protocol(message.clone()),
+            ClientError::Server(e) => ClientError::Server(e.clone()),
+            ClientError::UnexpectedReply(message) => ClientError::UnexpectedReply(message.clone()),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Protocol(message) => write!(f, "{}", message),
+            ClientError::Server(e) => write!(f, "{}", e),
+            ClientError::UnexpectedReply(message) => write!(f, "Unexpected reply: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(e) => Some(e),
+            ClientError::Server(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<CodecError> for ClientError {
+    fn from(e: CodecError) -> Self {
+        match e {
+            CodecError::Io(e) => ClientError::Io(e),
+            CodecError::Protocol(message) => ClientError::Protocol(message),
+        }
+    }
+}
+
+impl From<RedoxError> for ClientError {
+    fn from(e: RedoxError) -> Self {
+        ClientError::Server(e)
+    }
+}
+
+/// 客户端操作的结果
+pub type Result<T> = std::result::Result<T, ClientError>;
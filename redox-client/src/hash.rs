@@ -0,0 +1,46 @@
+//! 结构体与哈希表之间的映射
+//! `#[derive(RedoxHash)]` 为结构体生成 `RedoxHash` 的实现，`Client::hset_struct` 在一个事务中用 HSET 写入字段、用 HDEL 删除值为 None 的字段，
+//! `Client::hget_struct` 用 HGETALL 读取。每个字段通过 `ToRedoxValue` 编码、`FromRedoxValue` 解析，
+//! 因此字段的类型可以是字符串、字节串、数字和布尔值，以及它们的 `Option`。
+
+use crate::error::{ClientError, Result};
+use crate::value::FromRedoxValue;
+use bytes::Bytes;
+use redox_protocol::{RedoxValue, Response};
+use std::collections::HashMap;
+
+/// 可以存储为哈希表的类型，通常由 `#[derive(RedoxHash)]` 实现
+pub trait RedoxHash: Sized {
+    /// 转换为哈希表的字段和值，值为 None 的 `Option` 字段不包含在内
+    fn to_hash(&self) -> Vec<(Bytes, Bytes)>;
+
+    /// 值为 None 的 `Option` 字段的名字，`Client::hset_struct` 在写入时删除哈希表中的这些字段
+    fn absent_fields(&self) -> Vec<Bytes> {
+        Vec::new()
+    }
+
+    /// 从 HGETALL 的结果构造
+    ///
+    /// # Returns
+    /// * `Err(ClientError::UnexpectedReply)` - 缺少必需的字段，或字段的值无法转换为对应的类型
+    fn from_hash(hash: HashMap<Bytes, Bytes>) -> Result<Self>;
+}
+
+/// 取出必需的字段并转换，供派生的代码使用
+pub fn field<T: FromRedoxValue>(hash: &mut HashMap<Bytes, Bytes>, name: &str) -> Result<T> {
+    match optional_field(hash, name)? {
+        Some(value) => Ok(value),
+        None => Err(ClientError::UnexpectedReply(format!("missing field '{}'", name))),
+    }
+}
+
+/// 取出可选的字段并转换，字段不存在时为 None，供派生的代码使用
+pub fn optional_field<T: FromRedoxValue>(hash: &mut HashMap<Bytes, Bytes>, name: &str) -> Result<Option<T>> {
+    let Some(value) = hash.remove(name.as_bytes()) else {
+        return Ok(None);
+    };
+    T::from_redox_value(Response::Value(RedoxValue::String(value))).map(Some).map_err(|e| match e {
+        ClientError::UnexpectedReply(message) => ClientError::UnexpectedReply(format!("field '{}': {}", name, message)),
+        e => e,
+    })
+}
@@ -0,0 +1,62 @@
+//! Redox 的异步客户端库
+//! `Client::connect` 连接服务器，`get`、`set`、`lpush`、`zrange` 等方法发送对应的命令并把回复转换为 Rust 类型，
+//! 命令的编码和回复的解析使用 redox-protocol，没有对应方法的命令可以用 `Client::query` 或 `Client::execute` 发送。
+//! 回复通过 `FromRedoxValue` 转换为调用者指定的类型，参数通过 `ToRedoxValue` 编码，字符串、字节串和数字都可以直接作为键和值。
+//! 结构体可以用 `#[derive(RedoxHash)]` 映射为哈希表，通过 `Client::hset_struct` 和 `Client::hget_struct` 读写。
+//! `Client::subscribe` 在单独的连接上订阅频道，返回的 `Subscription` 是消息的 Stream，断开后自动重新订阅。
+//! 建立连接和执行命令失败时按 `RetryPolicy` 以指数退避重试，默认只重试幂等的命令。
+//! `Client::transaction` 用 MULTI / EXEC 执行事务，WATCH 的键被其他连接修改时自动重新执行。
+//! `Client::enable_cache` 启用客户端缓存，重复 GET 同一个键时直接返回缓存的值，键被修改时由服务器推送的通知移除。
+//! `Client::connect_via_sentinel` 通过哨兵找到主节点，订阅哨兵的故障转移通知，主节点切换后自动连接新的主节点。
+//! `MultiplexedClient` 让多个任务共享一个连接，命令流水线发送，回复按顺序交给各自的任务。
+//! `ClusterClient` 连接集群，按键的槽把命令发送给负责的节点并跟随 MOVED 和 ASK 重定向。
+//!
+//! ```no_run
+//! use redox_client::Client;
+//!
+//! # async fn example() -> redox_client::Result<()> {
+//! let mut client = Client::connect("127.0.0.1:2001").await?;
+//! client.set("greeting", "hello").await?;
+//! assert_eq!(client.get::<Option<String>>("greeting").await?.as_deref(), Some("hello"));
+//! client.set("counter", 41).await?;
+//! let counter: u64 = client.get("counter").await?;
+//! client.zadd("scores", 1.5, "alice").await?;
+//! let scores: Vec<(String, f64)> = client.zrange("scores", 0, -1).await?;
+//! for (member, score) in scores {
+//!     println!("{} {} {}", member, score, counter);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod cache;
+mod client;
+mod cluster;
+mod error;
+mod hash;
+mod multiplex;
+mod pubsub;
+mod retry;
+mod sentinel;
+mod transaction;
+mod value;
+
+pub use cache::CacheStats;
+pub use client::Client;
+pub use cluster::ClusterClient;
+pub use error::{ClientError, Result};
+pub use hash::RedoxHash;
+pub use multiplex::MultiplexedClient;
+pub use pubsub::{Message, Subscription};
+pub use retry::{is_idempotent, RetryPolicy};
+pub use transaction::Transaction;
+pub use value::{FromRedoxValue, ToRedoxValue};
+pub use bytes::Bytes;
+pub use redox_derive::RedoxHash;
+pub use redox_protocol::{Command, ExpireCondition, RedoxError, Response};
+
+/// 派生宏生成的代码使用的函数，不属于公开的 API
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::hash::{field, optional_field};
+}
@@ -0,0 +1,297 @@
+//! 多路复用的连接
+//! `MultiplexedClient` 让多个任务共享一个连接：命令交给后台任务排队，连续到达的命令在一次写入中流水线发送，
+//! 服务器按顺序回复，读取任务按发送的顺序把回复交给等待的任务。与连接池相比不需要为每个并发请求占用一个连接，
+//! 请求 / 回复形式的负载通常有更高的吞吐量。`MultiplexedClient` 可以克隆，克隆共享同一个连接。
+//! 改变连接状态的命令（订阅、事务、MONITOR、CLIENT TRACKING、AUTH、HELLO、RESET）和阻塞的 WAIT 会影响共享连接的其他任务，
+//! 直接返回错误：订阅用 `subscribe` 在单独的连接上进行，事务和其他命令使用 `Client`。
+//! 连接出错时已经发出、还在等待回复的命令都返回连接错误（无法知道服务器是否已经执行），下一个命令重新连接；命令按重试策略重试。
+
+use crate::client::{Endpoint, Transport};
+use crate::error::{ClientError, Result};
+use crate::pubsub::Subscription;
+use crate::retry::RetryPolicy;
+use crate::value::{FromRedoxValue, ToRedoxValue};
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::compact::BinaryFormat;
+use redox_protocol::meta::Category;
+use redox_protocol::{Command, RedoxError, Response};
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+/// 等待后台任务发送的命令数，超过时调用者等待
+const QUEUED_REQUESTS: usize = 1024;
+
+/// 一次写入最多流水线发送的命令数
+const PIPELINE_BATCH: usize = 128;
+
+/// 交给等待的任务的回复
+type Reply = oneshot::Sender<Result<Response>>;
+
+/// 一个调用者的命令
+struct Request {
+    cmd: Command,
+    reply: Reply,
+}
+
+/// 多个任务共享一个连接的客户端，可以克隆，克隆共享同一个连接
+#[derive(Clone)]
+pub struct MultiplexedClient {
+    /// 发送给后台任务的命令
+    requests: mpsc::Sender<Request>,
+    /// 地址、认证信息和重试策略，用于订阅连接
+    endpoint: Endpoint,
+}
+
+impl MultiplexedClient {
+    /// 连接服务器
+    ///
+    /// # Arguments
+    /// * `addr` - 服务器的地址，如 `"127.0.0.1:2001"`
+    pub async fn connect<A: tokio::net::ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::open(Endpoint::lookup(addr, RetryPolicy::new()).await?).await
+    }
+
+    /// 使用 `endpoint` 建立共享的连接并启动后台任务
+    pub(crate) async fn open(endpoint: Endpoint) -> Result<Self> {
+        let framed = endpoint.connect().await?;
+        let (requests, receiver) = mpsc::channel(QUEUED_REQUESTS);
+        let writer = Writer { endpoint: endpoint.clone(), requests: receiver };
+        tokio::spawn(writer.run(Link::new(framed)));
+        Ok(Self { requests, endpoint })
+    }
+
+    /// 发送一个命令并等待回复，失败时按重试策略重试
+    ///
+    /// # Returns
+    /// * `Ok(Response)` - 服务器的回复，不是错误回复
+    /// * `Err(ClientError::Server)` - 服务器的错误回复，或命令不能在共享的连接上执行
+    /// * `Err` - 连接出错或被关闭，且不再重试
+    pub async fn execute(&self, cmd: &Command) -> Result<Response> {
+        if !is_shareable(cmd) {
+            return Err(ClientError::Server(RedoxError::Err(format!(
+                "'{}' can not be used on a multiplexed connection, use subscribe or a dedicated Client",
+                cmd.name()
+            ))));
+        }
+        let retry = &self.endpoint.retry;
+        let mut attempt = 0;
+        loop {
+            match self.send(cmd.clone()).await {
+                Err(e) if retry.should_retry(attempt, cmd, &e) => {
+                    attempt += 1;
+                    tokio::time::sleep(retry.delay(attempt)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 把命令交给后台任务并等待回复
+    async fn send(&self, cmd: Command) -> Result<Response> {
+        let (reply, receiver) = oneshot::channel();
+        self.requests.send(Request { cmd, reply }).await.map_err(|_| closed())?;
+        match receiver.await.map_err(|_| closed())?? {
+            Response::Error(e) => Err(ClientError::Server(e)),
+            response => Ok(response),
+        }
+    }
+
+    /// 发送一个命令，把回复转换为 T
+    pub async fn query<T: FromRedoxValue>(&self, cmd: &Command) -> Result<T> {
+        T::from_redox_value(self.execute(cmd).await?)
+    }
+
+    /// GET，转换为 `Option<T>` 时键不存在为 None
+    pub async fn get<T: FromRedoxValue>(&self, key: impl ToRedoxValue) -> Result<T> {
+        self.query(&Command::Get { key: key.to_redox_bytes() }).await
+    }
+
+    /// SET
+    pub async fn set(&self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> Result<()> {
+        self.query(&Command::Set { key: key.to_redox_bytes(), value: value.to_redox_bytes() }).await
+    }
+
+    /// DEL，返回删除的键数
+    pub async fn del<K: ToRedoxValue>(&self, keys: &[K]) -> Result<usize> {
+        self.query(&Command::Del(keys.iter().map(ToRedoxValue::to_redox_bytes).collect())).await
+    }
+
+    /// MGET，转换为 `Vec<Option<T>>` 时不存在的键为 None
+    pub async fn mget<T: FromRedoxValue, K: ToRedoxValue>(&self, keys: &[K]) -> Result<T> {
+        self.query(&Command::MGet(keys.iter().map(ToRedoxValue::to_redox_bytes).collect())).await
+    }
+
+    /// PUBLISH，返回收到消息的订阅数
+    pub async fn publish(&self, channel: impl ToRedoxValue, message: impl ToRedoxValue) -> Result<usize> {
+        self.query(&Command::Publish { channel: channel.to_redox_bytes(), message: message.to_redox_bytes() }).await
+    }
+
+    /// SUBSCRIBE，与 `Client::subscribe` 相同，在单独的连接上订阅
+    pub async fn subscribe<C: ToRedoxValue>(&self, channels: impl IntoIterator<Item = C>) -> Result<Subscription> {
+        Subscription::open(self.endpoint.clone(), Command::Subscribe(channels.into_iter().map(|channel| channel.to_redox_bytes()).collect())).await
+    }
+
+    /// PSUBSCRIBE，与 `Client::psubscribe` 相同，在单独的连接上订阅
+    pub async fn psubscribe<P: ToRedoxValue>(&self, patterns: impl IntoIterator<Item = P>) -> Result<Subscription> {
+        Subscription::open(self.endpoint.clone(), Command::PSubscribe(patterns.into_iter().map(|pattern| pattern.to_redox_bytes()).collect())).await
+    }
+}
+
+/// 命令是否可以在共享的连接上执行：改变连接状态的命令和阻塞的命令不可以
+fn is_shareable(cmd: &Command) -> bool {
+    let spec = cmd.spec();
+    !spec.has_flag("blocking")
+        && spec.category != Category::Transaction
+        && !matches!(
+            cmd,
+            Command::Subscribe(_)
+                | Command::PSubscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::PUnsubscribe(_)
+                | Command::Monitor
+                | Command::ClientTracking { .. }
+                | Command::Auth { .. }
+                | Command::Hello { .. }
+                | Command::Reset
+        )
+}
+
+/// 回复到达之前连接已经关闭
+fn closed() -> ClientError {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "multiplexed connection closed before the reply arrived").into()
+}
+
+/// 写入共享连接的后台任务，所有 `MultiplexedClient` 被丢弃后结束
+struct Writer {
+    /// 重新连接使用的地址和认证信息
+    endpoint: Endpoint,
+    /// 调用者的命令
+    requests: mpsc::Receiver<Request>,
+}
+
+impl Writer {
+    /// 把命令写入连接，连接出错后在下一个命令到达时重新连接
+    async fn run(mut self, link: Link) {
+        let mut link = Some(link);
+        let mut batch = Vec::with_capacity(PIPELINE_BATCH);
+        while let Some(request) = self.requests.recv().await {
+            batch.push(request);
+            while batch.len() < PIPELINE_BATCH {
+                let Ok(request) = self.requests.try_recv() else {
+                    break;
+                };
+                batch.push(request);
+            }
+            // 读取任务已经结束（连接出错），或通过哨兵连接时主节点已经切换
+            if link.as_ref().is_some_and(|link| !link.is_usable(&self.endpoint)) {
+                if let Some(stale) = link.take() {
+                    stale.retire();
+                }
+            }
+            let current = match &mut link {
+                Some(current) => current,
+                None => match self.endpoint.connect().await {
+                    Ok(framed) => link.insert(Link::new(framed)),
+                    Err(e) => {
+                        for request in batch.drain(..) {
+                            let _ = request.reply.send(Err(e.duplicate()));
+                        }
+                        continue;
+                    }
+                },
+            };
+            if current.write(&mut batch).await.is_err() {
+                if let Some(broken) = link.take() {
+                    broken.retire();
+                }
+            }
+        }
+        // 已经发出的命令的回复仍然由读取任务交给调用者
+        if let Some(link) = link {
+            link.retire();
+        }
+    }
+}
+
+/// 共享连接的写入端，回复由读取任务读取
+struct Link {
+    sink: FramedWrite<OwnedWriteHalf, ClientCodec>,
+    /// 已经发出、等待回复的命令的回复通道，按发送的顺序交给读取任务
+    inflight: mpsc::UnboundedSender<Reply>,
+    /// 连接的服务器地址，通过哨兵连接时用于发现主节点已经切换
+    peer: Option<SocketAddr>,
+}
+
+impl Link {
+    /// 拆分已认证的连接，启动读取任务
+    fn new(framed: Transport) -> Self {
+        let peer = framed.get_ref().peer_addr().ok();
+        let parts = framed.into_parts();
+        let (read, write) = parts.io.into_split();
+        // 二进制格式的解码没有状态；编码器记录了是否已经发送握手，留给写入端
+        let mut stream = FramedRead::new(read, ClientCodec::binary(BinaryFormat::Bincode));
+        *stream.read_buffer_mut() = parts.read_buf;
+        let (inflight, pending) = mpsc::unbounded_channel();
+        tokio::spawn(read_replies(stream, pending));
+        Self { sink: FramedWrite::new(write, parts.codec), inflight, peer }
+    }
+
+    /// 读取任务仍在运行，且通过哨兵连接时仍然连接着当前的主节点
+    fn is_usable(&self, endpoint: &Endpoint) -> bool {
+        !self.inflight.is_closed() && self.peer.is_none_or(|peer| endpoint.is_current(peer))
+    }
+
+    /// 流水线写入一批命令，出错时还没有写入的命令返回同样的错误
+    async fn write(&mut self, batch: &mut Vec<Request>) -> Result<()> {
+        let mut requests = batch.drain(..);
+        let result: Result<()> = async {
+            for Request { cmd, reply } in &mut requests {
+                // 先交给读取任务，回复不会早于命令写出
+                if let Err(mpsc::error::SendError(reply)) = self.inflight.send(reply) {
+                    let _ = reply.send(Err(closed()));
+                    return Err(closed());
+                }
+                self.sink.feed(&cmd).await?;
+            }
+            SinkExt::<&Command>::flush(&mut self.sink).await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = &result {
+            for request in requests {
+                let _ = request.reply.send(Err(e.duplicate()));
+            }
+        }
+        result
+    }
+
+    /// 不再写入这个连接，读取任务交付已经发出的命令的回复后关闭连接
+    fn retire(self) {
+        // 不关闭写方向，服务器仍然会回复已经收到的命令
+        self.sink.into_inner().forget();
+    }
+}
+
+/// 按发送的顺序读取回复并交给等待的任务，连接出错后结束，之后的调用者得到连接错误
+async fn read_replies(mut stream: FramedRead<OwnedReadHalf, ClientCodec>, mut pending: mpsc::UnboundedReceiver<Reply>) {
+    while let Some(reply) = pending.recv().await {
+        let response = loop {
+            match stream.next().await {
+                // 共享的连接不开启 CLIENT TRACKING，忽略推送
+                Some(Ok(Response::Push { .. })) => continue,
+                Some(Ok(response)) => break Ok(response),
+                Some(Err(e)) => break Err(ClientError::from(e)),
+                None => break Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server").into()),
+            }
+        };
+        let failed = response.is_err();
+        let _ = reply.send(response);
+        if failed {
+            return;
+        }
+    }
+}
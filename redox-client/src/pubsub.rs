@@ -0,0 +1,224 @@
+//! 发布订阅的消费端
+//! `Client::subscribe` 和 `Client::psubscribe` 在单独的连接上订阅，后台任务读取推送的消息，通过 `Subscription` 以 Stream 的形式交给调用者。
+//! 连接断开后后台任务按客户端的重试策略（`RetryPolicy`）的退避时间不限次数地重新连接、认证并重新订阅所有频道和模式，断开期间发送的消息会丢失。
+//! `Subscription` 被丢弃时后台任务结束，连接随之关闭。
+
+use crate::client::{Endpoint, Transport};
+use crate::error::{ClientError, Result};
+use crate::value::{FromRedoxValue, ToRedoxValue};
+use bytes::Bytes;
+use futures::{SinkExt, Stream, StreamExt};
+use redox_protocol::{Command, RedoxValue, Response};
+use std::collections::HashSet;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// 后台任务最多缓存的消息数，调用者读取太慢时后台任务暂停读取连接
+const MESSAGE_BUFFER: usize = 1024;
+
+/// 收到的一条消息
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// 消息发送到的频道
+    pub channel: Bytes,
+    /// 通过 PSUBSCRIBE 收到时为匹配的模式
+    pub pattern: Option<Bytes>,
+    /// 消息内容
+    pub payload: Bytes,
+}
+
+impl Message {
+    /// 把消息内容转换为 T，如 `String` 或 `u64`
+    pub fn payload<T: FromRedoxValue>(&self) -> Result<T> {
+        T::from_redox_value(Response::Value(RedoxValue::String(self.payload.clone())))
+    }
+}
+
+/// 订阅的修改，由后台任务发送给服务器
+enum Change {
+    Subscribe(Vec<Bytes>),
+    PSubscribe(Vec<Bytes>),
+    Unsubscribe(Vec<Bytes>),
+    PUnsubscribe(Vec<Bytes>),
+}
+
+/// 订阅收到的消息，实现了 `Stream<Item = Message>`
+pub struct Subscription {
+    /// 后台任务读取到的消息
+    messages: mpsc::Receiver<Message>,
+    /// 发送给后台任务的订阅修改
+    changes: mpsc::UnboundedSender<Change>,
+}
+
+impl Subscription {
+    /// 建立订阅连接并等待服务器确认，之后由后台任务读取消息
+    pub(crate) async fn open(endpoint: Endpoint, cmd: Command) -> Result<Self> {
+        let mut framed = endpoint.connect().await?;
+        framed.send(&cmd).await?;
+        match framed.next().await {
+            Some(Ok(Response::Error(e))) => return Err(ClientError::Server(e)),
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e.into()),
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by server").into()),
+        }
+        let (channels, patterns) = match cmd {
+            Command::Subscribe(channels) => (channels.into_iter().collect(), HashSet::new()),
+            Command::PSubscribe(patterns) => (HashSet::new(), patterns.into_iter().collect()),
+            _ => (HashSet::new(), HashSet::new()),
+        };
+        let (messages, receiver) = mpsc::channel(MESSAGE_BUFFER);
+        let worker = Worker { endpoint, channels, patterns, messages };
+        let (changes, pending) = mpsc::unbounded_channel();
+        tokio::spawn(worker.run(framed, pending));
+        Ok(Self { messages: receiver, changes })
+    }
+
+    /// 再订阅一些频道，服务器确认之前发送到这些频道的消息不会收到
+    pub fn subscribe<C: ToRedoxValue>(&self, channels: impl IntoIterator<Item = C>) {
+        let _ = self.changes.send(Change::Subscribe(channels.into_iter().map(|channel| channel.to_redox_bytes()).collect()));
+    }
+
+    /// 再订阅一些模式
+    pub fn psubscribe<P: ToRedoxValue>(&self, patterns: impl IntoIterator<Item = P>) {
+        let _ = self.changes.send(Change::PSubscribe(patterns.into_iter().map(|pattern| pattern.to_redox_bytes()).collect()));
+    }
+
+    /// 取消订阅一些频道，为空时取消所有频道；取消之前已经收到的消息仍会返回
+    pub fn unsubscribe<C: ToRedoxValue>(&self, channels: impl IntoIterator<Item = C>) {
+        let _ = self.changes.send(Change::Unsubscribe(channels.into_iter().map(|channel| channel.to_redox_bytes()).collect()));
+    }
+
+    /// 取消订阅一些模式，为空时取消所有模式
+    pub fn punsubscribe<P: ToRedoxValue>(&self, patterns: impl IntoIterator<Item = P>) {
+        let _ = self.changes.send(Change::PUnsubscribe(patterns.into_iter().map(|pattern| pattern.to_redox_bytes()).collect()));
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.messages.poll_recv(cx)
+    }
+}
+
+/// 读取订阅连接的后台任务
+struct Worker {
+    /// 重新连接使用的地址和认证信息
+    endpoint: Endpoint,
+    /// 当前订阅的频道，重新连接后重新订阅
+    channels: HashSet<Bytes>,
+    /// 当前订阅的模式
+    patterns: HashSet<Bytes>,
+    /// 交给 `Subscription` 的消息
+    messages: mpsc::Sender<Message>,
+}
+
+impl Worker {
+    /// 转发消息并处理订阅的修改，直到 `Subscription` 被丢弃
+    async fn run(mut self, framed: Transport, mut changes: mpsc::UnboundedReceiver<Change>) {
+        let mut connection = Some(framed);
+        loop {
+            let framed = match &mut connection {
+                Some(framed) => framed,
+                None => match self.reconnect().await {
+                    Some(framed) => connection.insert(framed),
+                    None => return,
+                },
+            };
+            tokio::select! {
+                frame = framed.next() => match frame {
+                    Some(Ok(Response::Push { kind, items })) => {
+                        if let Some(message) = parse(&kind, items) {
+                            if self.messages.send(message).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    // 订阅确认以外的回复（如没有权限的错误）不影响已有的订阅
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => connection = None,
+                },
+                change = changes.recv() => {
+                    let Some(change) = change else { return };
+                    let cmd = self.apply(change);
+                    if framed.send(&cmd).await.is_err() {
+                        connection = None;
+                    }
+                }
+                _ = self.messages.closed() => return,
+            }
+        }
+    }
+
+    /// 记录订阅的修改，返回发送给服务器的命令
+    fn apply(&mut self, change: Change) -> Command {
+        match change {
+            Change::Subscribe(channels) => {
+                self.channels.extend(channels.iter().cloned());
+                Command::Subscribe(channels)
+            }
+            Change::PSubscribe(patterns) => {
+                self.patterns.extend(patterns.iter().cloned());
+                Command::PSubscribe(patterns)
+            }
+            Change::Unsubscribe(channels) => {
+                forget(&mut self.channels, &channels);
+                Command::Unsubscribe(channels)
+            }
+            Change::PUnsubscribe(patterns) => {
+                forget(&mut self.patterns, &patterns);
+                Command::PUnsubscribe(patterns)
+            }
+        }
+    }
+
+    /// 重新连接并重新订阅，失败时按退避时间等待后重试，不受最多重试次数的限制，直到成功或 `Subscription` 被丢弃
+    async fn reconnect(&self) -> Option<Transport> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            tokio::select! {
+                _ = tokio::time::sleep(self.endpoint.retry.delay(attempt)) => {}
+                _ = self.messages.closed() => return None,
+            }
+            if let Ok(framed) = self.resubscribe().await {
+                return Some(framed);
+            }
+        }
+    }
+
+    /// 建立新的连接并订阅当前的频道和模式
+    async fn resubscribe(&self) -> Result<Transport> {
+        let mut framed = self.endpoint.open().await?;
+        if !self.channels.is_empty() {
+            framed.send(&Command::Subscribe(self.channels.iter().cloned().collect())).await?;
+        }
+        if !self.patterns.is_empty() {
+            framed.send(&Command::PSubscribe(self.patterns.iter().cloned().collect())).await?;
+        }
+        Ok(framed)
+    }
+}
+
+/// 取消订阅后不再重新订阅，names 为空时取消所有
+fn forget(subscribed: &mut HashSet<Bytes>, names: &[Bytes]) {
+    if names.is_empty() {
+        subscribed.clear();
+    }
+    for name in names {
+        subscribed.remove(name);
+    }
+}
+
+/// 解析推送的消息，订阅确认和 PONG 返回 None
+fn parse(kind: &str, items: Vec<Response>) -> Option<Message> {
+    let mut items = items.into_iter().map(|item| Bytes::from_redox_value(item).ok());
+    match kind {
+        "message" => Some(Message { channel: items.next()??, pattern: None, payload: items.next()?? }),
+        "pmessage" => Some(Message { pattern: Some(items.next()??), channel: items.next()??, payload: items.next()?? }),
+        _ => None,
+    }
+}
@@ -0,0 +1,161 @@
+//! 重试策略
+//! `RetryPolicy` 决定建立连接和执行命令失败后是否重试、最多重试几次以及每次等待多久。
+//! 等待时间按指数退避增长，默认加入随机抖动，避免大量客户端在服务器恢复后同时重连；
+//! 默认只重试连接错误和 `TRYAGAIN`、`CLUSTERDOWN` 等稍后可能成功的错误，
+//! 并且只重试幂等的命令——连接在命令发出后断开时无法知道服务器是否已经执行，重复执行 LPUSH 之类的命令会写入两次。
+
+use crate::error::ClientError;
+use redox_protocol::{Command, RedoxError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 不读写数据、重复执行没有影响的连接命令
+const SAFE_CONNECTION_COMMANDS: &[&str] = &["auth", "ping", "echo"];
+
+/// 重复执行效果相同的写命令，只读命令（`readonly` 标志）总是幂等的
+const IDEMPOTENT_WRITES: &[&str] = &[
+    "set", "mset", "del", "unlink", "sadd", "srem", "hset", "hdel", "hpersist", "zadd", "zrem",
+    "persist", "expireat", "pexpireat",
+];
+
+/// 判断错误是否可以重试
+type Retryable = Arc<dyn Fn(&ClientError) -> bool + Send + Sync>;
+
+/// 重试策略，`RetryPolicy::new()` 为默认策略，用链式的方法修改
+///
+/// ```no_run
+/// use redox_client::{Client, RetryPolicy};
+/// use std::time::Duration;
+///
+/// # async fn example() -> redox_client::Result<()> {
+/// let retry = RetryPolicy::new().max_retries(5).backoff(Duration::from_millis(50), Duration::from_secs(2));
+/// let mut client = Client::connect_with_retry("127.0.0.1:2001", retry).await?;
+/// client.set_retry_policy(RetryPolicy::never());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// 第一次失败之后最多重试的次数
+    max_retries: u32,
+    /// 第一次重试前的等待时间，之后每次加倍
+    initial_backoff: Duration,
+    /// 等待时间的上限
+    max_backoff: Duration,
+    /// 是否在等待时间中加入随机抖动
+    jitter: bool,
+    /// 是否重试非幂等的命令
+    non_idempotent: bool,
+    /// 哪些错误可以重试
+    retryable: Retryable,
+}
+
+impl RetryPolicy {
+    /// 默认策略：最多重试 3 次，等待时间从 100 毫秒开始加倍、不超过 5 秒并加入抖动，只重试幂等的命令
+    pub fn new() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+            non_idempotent: false,
+            retryable: Arc::new(is_transient),
+        }
+    }
+
+    /// 从不重试，失败时直接返回错误
+    pub fn never() -> Self {
+        Self::new().max_retries(0)
+    }
+
+    /// 设置最多重试的次数，0 表示不重试
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// 设置指数退避的初始等待时间和上限
+    ///
+    /// # Arguments
+    /// * `initial` - 第一次重试前的等待时间
+    /// * `max` - 等待时间的上限
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// 设置是否加入随机抖动，加入时每次等待退避时间的 50% 到 100%
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// 设置是否重试非幂等的命令（如 LPUSH、RPOP），重试时命令可能被执行两次
+    pub fn retry_non_idempotent(mut self, non_idempotent: bool) -> Self {
+        self.non_idempotent = non_idempotent;
+        self
+    }
+
+    /// 设置哪些错误可以重试，代替默认的判断（连接错误、`TRYAGAIN` 和 `CLUSTERDOWN`）
+    pub fn retryable(mut self, retryable: impl Fn(&ClientError) -> bool + Send + Sync + 'static) -> Self {
+        self.retryable = Arc::new(retryable);
+        self
+    }
+
+    /// 最多重试的次数
+    pub fn retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// 第几次重试前的等待时间
+    ///
+    /// # Arguments
+    /// * `attempt` - 重试的序号，从 1 开始
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let backoff = self.initial_backoff.saturating_mul(1 << exponent).min(self.max_backoff);
+        if self.jitter {
+            backoff.mul_f64(0.5 + fastrand::f64() / 2.0)
+        } else {
+            backoff
+        }
+    }
+
+    /// 建立连接失败后是否重试，连接还没有发送命令，只检查错误和次数
+    ///
+    /// # Arguments
+    /// * `attempt` - 已经重试的次数
+    /// * `error` - 这一次失败的原因
+    pub fn should_reconnect(&self, attempt: u32, error: &ClientError) -> bool {
+        attempt < self.max_retries && (self.retryable)(error)
+    }
+
+    /// 命令执行失败后是否重试
+    ///
+    /// # Arguments
+    /// * `attempt` - 已经重试的次数
+    /// * `cmd` - 失败的命令
+    /// * `error` - 这一次失败的原因
+    pub fn should_retry(&self, attempt: u32, cmd: &Command, error: &ClientError) -> bool {
+        self.should_reconnect(attempt, error) && (self.non_idempotent || is_idempotent(cmd))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 命令是否可以安全地重复执行：只读命令、没有影响的连接命令，或重复执行效果相同的写命令
+pub fn is_idempotent(cmd: &Command) -> bool {
+    let spec = cmd.spec();
+    spec.has_flag("readonly") || SAFE_CONNECTION_COMMANDS.contains(&spec.name) || IDEMPOTENT_WRITES.contains(&spec.name)
+}
+
+/// 默认可以重试的错误：连接错误，以及集群迁移或故障转移期间稍后可能成功的错误
+fn is_transient(error: &ClientError) -> bool {
+    error.is_connection_error()
+        || matches!(error, ClientError::Server(RedoxError::TryAgain(_) | RedoxError::ClusterDown(_)))
+}
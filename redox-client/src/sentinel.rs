@@ -0,0 +1,195 @@
+//! 通过哨兵连接主节点
+//! `Client::connect_via_sentinel` 依次询问哨兵 SENTINEL GET-MASTER-ADDR-BY-NAME，得到当前的主节点后连接它。
+//! 后台任务在其中一个哨兵上订阅 `+switch-master`，故障转移后更新主节点的地址；客户端执行下一个命令前发现连接的不是当前的主节点时，
+//! 丢弃这个连接并连接新的主节点。与哨兵的连接断开后后台任务按退避时间连接下一个哨兵，订阅后重新查询主节点，断开期间的切换不会遗漏。
+//! 使用这个地址的客户端和订阅都被丢弃后后台任务结束。
+
+use crate::error::{ClientError, Result};
+use crate::retry::RetryPolicy;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::compact::BinaryFormat;
+use redox_protocol::{Command, RedoxError, Response};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{self, TcpStream};
+use tokio::sync::watch;
+use tokio::time;
+use tokio_util::codec::Framed;
+
+/// 连接哨兵和一次查询的超时时间，不可达的哨兵不会阻塞对下一个哨兵的查询
+const SENTINEL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 哨兵发布主节点切换的频道
+const SWITCH_MASTER: &str = "+switch-master";
+
+/// 到哨兵的连接
+type SentinelLink = Framed<TcpStream, ClientCodec>;
+
+/// 哨兵报告的当前主节点，由后台任务在故障转移后更新
+#[derive(Clone)]
+pub(crate) struct Primary {
+    addrs: watch::Receiver<Vec<SocketAddr>>,
+}
+
+impl Primary {
+    /// 查询当前的主节点，并启动订阅主节点切换的后台任务
+    ///
+    /// # Arguments
+    /// * `sentinels` - 哨兵的地址，依次询问直到一个哨兵回复
+    /// * `name` - 哨兵监控的主节点名称
+    /// * `retry` - 与哨兵的连接断开后重新连接的退避时间
+    ///
+    /// # Returns
+    /// * `Ok(Primary)` - 主节点的地址
+    /// * `Err` - 所有哨兵都无法连接或不知道这个主节点时，最后一个哨兵的错误
+    pub(crate) async fn discover(sentinels: Vec<String>, name: String, retry: RetryPolicy) -> Result<Self> {
+        let mut last_error = None;
+        for sentinel in &sentinels {
+            match query(sentinel, &name).await {
+                Ok(addrs) => {
+                    let (addrs, receiver) = watch::channel(addrs);
+                    tokio::spawn(Watcher { sentinels, name, addrs, retry }.run());
+                    return Ok(Self { addrs: receiver });
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no sentinel address given").into()))
+    }
+
+    /// 当前主节点解析后的地址
+    pub(crate) fn addrs(&self) -> Vec<SocketAddr> {
+        self.addrs.borrow().clone()
+    }
+
+    /// `addr` 是否是当前的主节点
+    pub(crate) fn is_current(&self, addr: SocketAddr) -> bool {
+        self.addrs.borrow().contains(&addr)
+    }
+}
+
+/// 订阅主节点切换的后台任务
+struct Watcher {
+    /// 所有哨兵的地址，连接断开后轮流使用
+    sentinels: Vec<String>,
+    /// 主节点名称
+    name: String,
+    /// 交给客户端的主节点地址
+    addrs: watch::Sender<Vec<SocketAddr>>,
+    /// 重新连接的退避时间
+    retry: RetryPolicy,
+}
+
+impl Watcher {
+    /// 轮流订阅每个哨兵，直到所有客户端被丢弃
+    async fn run(self) {
+        let mut attempt = 0;
+        for sentinel in self.sentinels.iter().cycle() {
+            tokio::select! {
+                _ = self.follow(sentinel, &mut attempt) => {}
+                _ = self.addrs.closed() => return,
+            }
+            attempt += 1;
+            tokio::select! {
+                _ = time::sleep(self.retry.delay(attempt)) => {}
+                _ = self.addrs.closed() => return,
+            }
+        }
+    }
+
+    /// 在一个哨兵上订阅主节点切换并更新地址，只在出错或连接断开时返回
+    async fn follow(&self, sentinel: &str, attempt: &mut u32) -> Result<()> {
+        let mut link = connect(sentinel).await?;
+        link.send(&Command::Subscribe(vec![Bytes::from_static(SWITCH_MASTER.as_bytes())])).await?;
+        if let Response::Error(e) = next(&mut link).await? {
+            return Err(ClientError::Server(e));
+        }
+        // 订阅之后再查询，订阅之前发生的切换不会遗漏
+        self.update(query(sentinel, &self.name).await?);
+        *attempt = 0;
+        loop {
+            let Response::Array(items) = next(&mut link).await? else {
+                continue;
+            };
+            if let Some((host, port)) = parse_switch(&items, &self.name) {
+                self.update(net::lookup_host((host.as_str(), port)).await?.collect());
+            }
+        }
+    }
+
+    /// 主节点的地址改变时通知客户端
+    fn update(&self, addrs: Vec<SocketAddr>) {
+        self.addrs.send_if_modified(|current| {
+            if *current == addrs {
+                return false;
+            }
+            *current = addrs;
+            true
+        });
+    }
+}
+
+/// 连接哨兵，超时视为连接失败
+async fn connect(sentinel: &str) -> Result<SentinelLink> {
+    let stream = time::timeout(SENTINEL_TIMEOUT, TcpStream::connect(sentinel))
+        .await
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, format!("connecting to sentinel {} timed out", sentinel))))?;
+    stream.set_nodelay(true)?;
+    Ok(Framed::new(stream, ClientCodec::binary(BinaryFormat::Bincode)))
+}
+
+/// 读取哨兵的下一个回复或推送的消息
+async fn next(link: &mut SentinelLink) -> Result<Response> {
+    match link.next().await {
+        Some(Ok(response)) => Ok(response),
+        Some(Err(e)) => Err(e.into()),
+        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by sentinel").into()),
+    }
+}
+
+/// 用 SENTINEL GET-MASTER-ADDR-BY-NAME 询问一个哨兵，返回主节点解析后的地址
+async fn query(sentinel: &str, name: &str) -> Result<Vec<SocketAddr>> {
+    let reply = time::timeout(SENTINEL_TIMEOUT, async {
+        let mut link = connect(sentinel).await?;
+        link.send(&Command::SentinelGetMasterAddr { name: name.to_string() }).await?;
+        next(&mut link).await
+    })
+    .await
+    .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, format!("sentinel {} did not reply in time", sentinel)).into()))?;
+    let (host, port) = match reply {
+        Response::Array(items) => match &items[..] {
+            [Some(host), Some(port)] => (
+                String::from_utf8_lossy(host).into_owned(),
+                String::from_utf8_lossy(port).parse::<u16>().map_err(|_| ClientError::UnexpectedReply(format!("invalid port from sentinel {}", sentinel)))?,
+            ),
+            _ => return Err(ClientError::UnexpectedReply(format!("invalid master address from sentinel {}", sentinel))),
+        },
+        Response::Nil => return Err(ClientError::Server(RedoxError::Err(format!("sentinel {} does not monitor a master named '{}'", sentinel, name)))),
+        Response::Error(e) => return Err(ClientError::Server(e)),
+        reply => return Err(ClientError::UnexpectedReply(format!("{:?}", reply))),
+    };
+    let addrs = net::lookup_host((host.as_str(), port)).await?.collect();
+    Ok(addrs)
+}
+
+/// 解析 `+switch-master` 消息，内容为 `<名称> <旧主机> <旧端口> <新主机> <新端口>`
+///
+/// # Returns
+/// 切换的是 `name` 时为新主节点的主机和端口，否则为 None
+fn parse_switch(items: &[Option<Bytes>], name: &str) -> Option<(String, u16)> {
+    let [Some(kind), Some(channel), Some(payload)] = items else {
+        return None;
+    };
+    if &kind[..] != b"message" || &channel[..] != SWITCH_MASTER.as_bytes() {
+        return None;
+    }
+    let payload = String::from_utf8_lossy(payload);
+    let fields: Vec<&str> = payload.split_whitespace().collect();
+    match fields[..] {
+        [master, _, _, host, port] if master == name => Some((host.to_string(), port.parse().ok()?)),
+        _ => None,
+    }
+}
@@ -0,0 +1,107 @@
+//! 事务
+//! `Client::transaction` 把连接借给 `Transaction`：WATCH 和读取命令立即发送，写命令排队，
+//! body 返回后在一次往返中发送 MULTI、排队的命令和 EXEC。事务期间不会重新连接，否则 WATCH 会随旧连接一起丢失。
+
+use crate::client::{receive, request, send_all, Connection};
+use crate::error::{ClientError, Result};
+use crate::value::{FromRedoxValue, ToRedoxValue};
+use redox_protocol::{Command, Response};
+
+/// 正在构造的事务，由 `Client::transaction` 传给 body
+pub struct Transaction<'a> {
+    /// 执行事务的连接
+    connection: &'a mut Connection,
+    /// 排队的命令，body 返回后用 MULTI / EXEC 发送
+    queued: Vec<Command>,
+}
+
+impl Transaction<'_> {
+    /// WATCH，立即发送，这些键在 EXEC 之前被其他连接修改时事务不执行，body 会被重新执行
+    pub async fn watch<K: ToRedoxValue>(&mut self, keys: &[K]) -> Result<()> {
+        request(self.connection, &Command::Watch(keys.iter().map(ToRedoxValue::to_redox_bytes).collect())).await?;
+        Ok(())
+    }
+
+    /// 立即发送一个命令，把回复转换为 T，用于在 WATCH 之后读取当前的值
+    pub async fn query<T: FromRedoxValue>(&mut self, cmd: &Command) -> Result<T> {
+        T::from_redox_value(request(self.connection, cmd).await?)
+    }
+
+    /// GET，立即发送，转换为 `Option<T>` 时键不存在为 None
+    pub async fn get<T: FromRedoxValue>(&mut self, key: impl ToRedoxValue) -> Result<T> {
+        self.query(&Command::Get { key: key.to_redox_bytes() }).await
+    }
+
+    /// 把一个命令加入事务，EXEC 时执行
+    pub fn queue(&mut self, cmd: Command) -> &mut Self {
+        self.queued.push(cmd);
+        self
+    }
+
+    /// 排队 SET
+    pub fn set(&mut self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> &mut Self {
+        self.queue(Command::Set { key: key.to_redox_bytes(), value: value.to_redox_bytes() })
+    }
+
+    /// 排队 DEL
+    pub fn del<K: ToRedoxValue>(&mut self, keys: &[K]) -> &mut Self {
+        self.queue(Command::Del(keys.iter().map(ToRedoxValue::to_redox_bytes).collect()))
+    }
+
+    /// 排队 LPUSH
+    pub fn lpush(&mut self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> &mut Self {
+        self.queue(Command::LPush { key: key.to_redox_bytes(), value: value.to_redox_bytes() })
+    }
+
+    /// 排队 RPUSH
+    pub fn rpush(&mut self, key: impl ToRedoxValue, value: impl ToRedoxValue) -> &mut Self {
+        self.queue(Command::RPush { key: key.to_redox_bytes(), value: value.to_redox_bytes() })
+    }
+
+    /// 排队 SADD
+    pub fn sadd(&mut self, key: impl ToRedoxValue, member: impl ToRedoxValue) -> &mut Self {
+        self.queue(Command::SAdd { key: key.to_redox_bytes(), member: member.to_redox_bytes() })
+    }
+
+    /// 排队 HSET
+    pub fn hset(&mut self, key: impl ToRedoxValue, field: impl ToRedoxValue, value: impl ToRedoxValue) -> &mut Self {
+        self.queue(Command::HSet { key: key.to_redox_bytes(), fields: vec![(field.to_redox_bytes(), value.to_redox_bytes())] })
+    }
+
+    /// 排队 ZADD
+    pub fn zadd(&mut self, key: impl ToRedoxValue, score: f64, member: impl ToRedoxValue) -> &mut Self {
+        self.queue(Command::ZAdd { key: key.to_redox_bytes(), score, member: member.to_redox_bytes() })
+    }
+}
+
+/// 执行一次 body 并发送事务
+///
+/// # Returns
+/// * `Ok(Some(Response))` - EXEC 的回复
+/// * `Ok(None)` - WATCH 的键被修改，事务没有执行
+/// * `Err` - body 返回的错误、排队时出错的命令或连接出错
+pub(crate) async fn run(connection: &mut Connection, body: &mut impl AsyncFnMut(&mut Transaction<'_>) -> Result<()>) -> Result<Option<Response>> {
+    let mut tx = Transaction { connection, queued: Vec::new() };
+    if let Err(e) = body(&mut tx).await {
+        // 放弃事务时取消 WATCH，连接之后还要继续使用
+        if !e.is_connection_error() {
+            request(tx.connection, &Command::Unwatch).await?;
+        }
+        return Err(e);
+    }
+    let Transaction { connection, queued } = tx;
+    send_all(connection, [&Command::Multi].into_iter().chain(&queued).chain([&Command::Exec])).await?;
+
+    // MULTI 和每个命令各有一个回复，排队时出错的命令使 EXEC 回复 EXECABORT，返回第一个出错的原因
+    let mut rejected = None;
+    for _ in 0..=queued.len() {
+        if let Response::Error(e) = receive(connection).await? {
+            rejected.get_or_insert(e);
+        }
+    }
+    match receive(connection).await? {
+        Response::Nil => Ok(None),
+        Response::Error(e) => Err(ClientError::Server(rejected.unwrap_or(e))),
+        response => Ok(Some(response)),
+    }
+}
@@ -0,0 +1,294 @@
+//! 回复和参数与 Rust 类型之间的转换
+//! `FromRedoxValue` 把回复转换为 Rust 类型：字符串值可以转换为 `Bytes`、`String`、整数、浮点数和布尔值；
+//! 列表、集合和数组可以转换为 `Vec` 和 `HashSet`；哈希表、有序集合、时间序列和 INFO 可以转换为 `HashMap` 和 `BTreeMap`，
+//! 也可以转换为二元组的 `Vec`，如 `Vec<(String, f64)>`；不存在的值（nil）只能转换为 `Option` 的 None 或空的集合。
+//! `ToRedoxValue` 把字符串、字节串、数字和布尔值编码为命令的参数。
+
+use crate::error::{ClientError, Result};
+use bytes::Bytes;
+use redox_protocol::{quote, text, RedoxValue, Response};
+use std::any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+
+/// 可以从回复转换得到的类型
+pub trait FromRedoxValue: Sized {
+    /// 从回复转换，回复的类型或内容不符时返回 `ClientError::UnexpectedReply`
+    fn from_redox_value(response: Response) -> Result<Self>;
+}
+
+/// 可以作为命令参数的类型
+pub trait ToRedoxValue {
+    /// 编码为命令的参数
+    fn to_redox_bytes(&self) -> Bytes;
+}
+
+/// 无法转换为 T 时的错误
+fn mismatch<T>(response: &Response) -> ClientError {
+    ClientError::UnexpectedReply(format!("cannot convert {} to {}", describe(response), any::type_name::<T>()))
+}
+
+/// 错误信息中回复的描述，字符串只显示开头的一部分
+fn describe(response: &Response) -> String {
+    const MAX_SHOWN: usize = 64;
+    match response {
+        Response::Ok => "OK".to_string(),
+        Response::Nil => "nil".to_string(),
+        Response::Integer(n) => format!("integer {}", n),
+        Response::Value(RedoxValue::String(s)) if s.len() > MAX_SHOWN => format!("string {}...", quote(&s[..MAX_SHOWN])),
+        Response::Value(RedoxValue::String(s)) => format!("string {}", quote(s)),
+        Response::Value(value) => value.type_name().to_string(),
+        Response::Error(e) => format!("error {}", e),
+        Response::Array(_) => "array".to_string(),
+        Response::Info(_) => "info".to_string(),
+        Response::Map(_) => "map".to_string(),
+        Response::Cursor(..) => "cursor".to_string(),
+        Response::Replies(_) => "replies".to_string(),
+        Response::Push { kind, .. } => format!("push {}", kind),
+    }
+}
+
+/// 字符串值的回复
+fn string(value: Bytes) -> Response {
+    Response::Value(RedoxValue::String(value))
+}
+
+/// 二元组的回复，哈希表、有序集合等的每一项以这种形式转换
+fn pair(first: Bytes, second: Bytes) -> Response {
+    Response::Array(vec![Some(first), Some(second)])
+}
+
+/// 集合类型的回复拆分为每个元素，哈希表、有序集合、时间序列和 INFO 的每一项为二元组
+fn elements<T>(response: Response) -> Result<Vec<Response>> {
+    match response {
+        Response::Nil => Ok(Vec::new()),
+        Response::Replies(replies) => Ok(replies),
+        Response::Array(values) => Ok(values.into_iter().map(|value| value.map_or(Response::Nil, string)).collect()),
+        Response::Value(RedoxValue::List(list)) => Ok(list.into_iter().map(string).collect()),
+        Response::Value(RedoxValue::Set(set)) => Ok(set.into_iter().map(string).collect()),
+        Response::Value(RedoxValue::Hash(hash)) => Ok(hash.into_iter().map(|(field, value)| pair(field, value)).collect()),
+        Response::Value(RedoxValue::SortedSet(zset)) => Ok(zset
+            .iter()
+            .map(|(member, score)| pair(member.clone(), Bytes::from(score.to_string())))
+            .collect()),
+        Response::Value(RedoxValue::TimeSeries(series)) => Ok(series
+            .samples
+            .into_iter()
+            .map(|(timestamp, value)| pair(Bytes::from(timestamp.to_string()), Bytes::from(value.to_string())))
+            .collect()),
+        Response::Info(info) => Ok(info.into_iter().map(|(name, value)| pair(Bytes::from(name), Bytes::from(value))).collect()),
+        response => Err(mismatch::<T>(&response)),
+    }
+}
+
+/// 映射的每一项，`Response::Map` 的值保留原有的结构
+fn entries<K: FromRedoxValue, V: FromRedoxValue, T>(response: Response) -> Result<Vec<(K, V)>> {
+    match response {
+        Response::Map(entries) => entries
+            .into_iter()
+            .map(|(name, value)| Ok((K::from_redox_value(string(Bytes::from(name)))?, V::from_redox_value(value)?)))
+            .collect(),
+        response => elements::<T>(response)?.into_iter().map(<(K, V)>::from_redox_value).collect(),
+    }
+}
+
+impl FromRedoxValue for Response {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        Ok(response)
+    }
+}
+
+/// 忽略回复的内容
+impl FromRedoxValue for () {
+    fn from_redox_value(_: Response) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl FromRedoxValue for Bytes {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        match response {
+            Response::Value(RedoxValue::String(value)) => Ok(value),
+            Response::Integer(n) => Ok(Bytes::from(n.to_string())),
+            Response::Ok => Ok(Bytes::from_static(b"OK")),
+            response => Err(mismatch::<Self>(&response)),
+        }
+    }
+}
+
+/// 必须是合法的 UTF-8
+impl FromRedoxValue for String {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        let bytes = Bytes::from_redox_value(response)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| mismatch::<Self>(&string(bytes)))
+    }
+}
+
+/// 整数回复或数字字符串
+macro_rules! from_number {
+    ($($ty:ty),*) => {
+        $(
+            impl FromRedoxValue for $ty {
+                fn from_redox_value(response: Response) -> Result<Self> {
+                    let parsed = match &response {
+                        Response::Integer(n) => <$ty>::try_from(*n).ok(),
+                        Response::Value(RedoxValue::String(s)) => text(s).parse().ok(),
+                        _ => None,
+                    };
+                    parsed.ok_or_else(|| mismatch::<Self>(&response))
+                }
+            }
+        )*
+    };
+}
+
+from_number!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+/// 浮点数，可以是整数回复或数字字符串，`inf` 和 `-inf` 表示无穷大
+macro_rules! from_float {
+    ($($ty:ty),*) => {
+        $(
+            impl FromRedoxValue for $ty {
+                fn from_redox_value(response: Response) -> Result<Self> {
+                    let parsed = match &response {
+                        Response::Integer(n) => Some(*n as $ty),
+                        Response::Value(RedoxValue::String(s)) => text(s).parse().ok(),
+                        _ => None,
+                    };
+                    parsed.ok_or_else(|| mismatch::<Self>(&response))
+                }
+            }
+        )*
+    };
+}
+
+from_float!(f32, f64);
+
+/// 1 为 true，0 为 false，可以是整数回复或字符串
+impl FromRedoxValue for bool {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        match i64::from_redox_value(response)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            n => Err(mismatch::<Self>(&Response::Integer(n))),
+        }
+    }
+}
+
+/// nil 为 None
+impl<T: FromRedoxValue> FromRedoxValue for Option<T> {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        match response {
+            Response::Nil => Ok(None),
+            response => T::from_redox_value(response).map(Some),
+        }
+    }
+}
+
+impl<T: FromRedoxValue> FromRedoxValue for Vec<T> {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        elements::<Self>(response)?.into_iter().map(T::from_redox_value).collect()
+    }
+}
+
+impl<T: FromRedoxValue + Eq + Hash> FromRedoxValue for HashSet<T> {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        elements::<Self>(response)?.into_iter().map(T::from_redox_value).collect()
+    }
+}
+
+impl<K: FromRedoxValue + Eq + Hash, V: FromRedoxValue> FromRedoxValue for HashMap<K, V> {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        Ok(entries::<K, V, Self>(response)?.into_iter().collect())
+    }
+}
+
+impl<K: FromRedoxValue + Ord, V: FromRedoxValue> FromRedoxValue for BTreeMap<K, V> {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        Ok(entries::<K, V, Self>(response)?.into_iter().collect())
+    }
+}
+
+/// 两个元素的数组，或 SCAN 的游标和这一批键
+impl<A: FromRedoxValue, B: FromRedoxValue> FromRedoxValue for (A, B) {
+    fn from_redox_value(response: Response) -> Result<Self> {
+        match response {
+            Response::Cursor(cursor, keys) => Ok((
+                A::from_redox_value(Response::Integer(cursor as i64))?,
+                B::from_redox_value(Response::Value(RedoxValue::List(keys.into())))?,
+            )),
+            response => match <[Response; 2]>::try_from(elements::<Self>(response)?) {
+                Ok([first, second]) => Ok((A::from_redox_value(first)?, B::from_redox_value(second)?)),
+                Err(elements) => Err(ClientError::UnexpectedReply(format!(
+                    "cannot convert {} elements to {}",
+                    elements.len(),
+                    any::type_name::<Self>()
+                ))),
+            },
+        }
+    }
+}
+
+impl<T: ToRedoxValue + ?Sized> ToRedoxValue for &T {
+    fn to_redox_bytes(&self) -> Bytes {
+        (**self).to_redox_bytes()
+    }
+}
+
+impl ToRedoxValue for Bytes {
+    fn to_redox_bytes(&self) -> Bytes {
+        self.clone()
+    }
+}
+
+impl ToRedoxValue for [u8] {
+    fn to_redox_bytes(&self) -> Bytes {
+        Bytes::copy_from_slice(self)
+    }
+}
+
+impl<const N: usize> ToRedoxValue for [u8; N] {
+    fn to_redox_bytes(&self) -> Bytes {
+        Bytes::copy_from_slice(self)
+    }
+}
+
+impl ToRedoxValue for Vec<u8> {
+    fn to_redox_bytes(&self) -> Bytes {
+        Bytes::copy_from_slice(self)
+    }
+}
+
+impl ToRedoxValue for str {
+    fn to_redox_bytes(&self) -> Bytes {
+        Bytes::copy_from_slice(self.as_bytes())
+    }
+}
+
+impl ToRedoxValue for String {
+    fn to_redox_bytes(&self) -> Bytes {
+        Bytes::copy_from_slice(self.as_bytes())
+    }
+}
+
+/// 数字以十进制字符串编码
+macro_rules! to_number {
+    ($($ty:ty),*) => {
+        $(
+            impl ToRedoxValue for $ty {
+                fn to_redox_bytes(&self) -> Bytes {
+                    Bytes::from(self.to_string())
+                }
+            }
+        )*
+    };
+}
+
+to_number!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+/// true 编码为 1，false 编码为 0
+impl ToRedoxValue for bool {
+    fn to_redox_bytes(&self) -> Bytes {
+        Bytes::from_static(if *self { b"1" } else { b"0" })
+    }
+}
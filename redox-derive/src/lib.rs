@@ -0,0 +1,114 @@
+//! redox-client 的派生宏
+//! `#[derive(RedoxHash)]` 为带命名字段的结构体实现 `redox_client::RedoxHash`，
+//! 每个字段对应哈希表的一个字段，字段名默认与结构体的字段相同，可以用 `#[redox(rename = "...")]` 修改；
+//! `Option` 类型的字段为 None 时不写入哈希表，而是列在 `absent_fields` 中由 `hset_struct` 删除，读取时哈希表中没有该字段则为 None。
+//! 生成的代码通过 `::redox_client` 引用 trait，应通过 redox-client 使用这个宏。
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Field, Fields, LitByteStr, LitStr, Type};
+
+/// 派生 `RedoxHash`
+///
+/// ```ignore
+/// #[derive(RedoxHash)]
+/// struct User {
+///     name: String,
+///     age: u32,
+///     #[redox(rename = "mail")]
+///     email: Option<String>,
+/// }
+/// ```
+#[proc_macro_derive(RedoxHash, attributes(redox))]
+pub fn derive_redox_hash(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// 生成 `RedoxHash` 的实现
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(&input.ident, "RedoxHash can only be derived for structs with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "RedoxHash can only be derived for structs")),
+    };
+
+    let mut writes = Vec::new();
+    let mut absent = Vec::new();
+    let mut reads = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let name = hash_field_name(field)?;
+        let literal = LitByteStr::new(name.value().as_bytes(), name.span());
+        if is_option(&field.ty) {
+            writes.push(quote! {
+                if let ::std::option::Option::Some(value) = &self.#ident {
+                    fields.push((::redox_client::Bytes::from_static(#literal), ::redox_client::ToRedoxValue::to_redox_bytes(value)));
+                }
+            });
+            absent.push(quote! {
+                if self.#ident.is_none() {
+                    fields.push(::redox_client::Bytes::from_static(#literal));
+                }
+            });
+            reads.push(quote! { #ident: ::redox_client::__private::optional_field(&mut hash, #name)? });
+        } else {
+            writes.push(quote! {
+                fields.push((::redox_client::Bytes::from_static(#literal), ::redox_client::ToRedoxValue::to_redox_bytes(&self.#ident)));
+            });
+            reads.push(quote! { #ident: ::redox_client::__private::field(&mut hash, #name)? });
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::redox_client::RedoxHash for #ident #ty_generics #where_clause {
+            fn to_hash(&self) -> ::std::vec::Vec<(::redox_client::Bytes, ::redox_client::Bytes)> {
+                let mut fields = ::std::vec::Vec::new();
+                #(#writes)*
+                fields
+            }
+
+            fn absent_fields(&self) -> ::std::vec::Vec<::redox_client::Bytes> {
+                let mut fields = ::std::vec::Vec::new();
+                #(#absent)*
+                fields
+            }
+
+            fn from_hash(
+                mut hash: ::std::collections::HashMap<::redox_client::Bytes, ::redox_client::Bytes>,
+            ) -> ::redox_client::Result<Self> {
+                ::std::result::Result::Ok(Self { #(#reads),* })
+            }
+        }
+    })
+}
+
+/// 字段在哈希表中的名字，`#[redox(rename = "...")]` 优先
+fn hash_field_name(field: &Field) -> syn::Result<LitStr> {
+    let ident = field.ident.as_ref().expect("named field");
+    let mut name = LitStr::new(&ident.to_string(), ident.span());
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("redox")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                name = meta.value()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported redox attribute, expected `rename = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
+/// 字段的类型是否是 `Option<T>`，按类型名判断，也接受 `std::option::Option<T>`
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last().is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
@@ -0,0 +1,44 @@
+//! 派生的 `RedoxHash` 把结构体转换为哈希表的字段后可以原样转换回来，`rename` 改变字段在哈希表中的名字
+
+use redox_client::{Bytes, RedoxHash};
+use std::collections::HashMap;
+
+#[derive(RedoxHash, Debug, PartialEq)]
+struct User {
+    name: String,
+    age: u32,
+    #[redox(rename = "mail")]
+    email: Option<String>,
+    nickname: std::option::Option<String>,
+}
+
+fn hash(user: &User) -> HashMap<Bytes, Bytes> {
+    user.to_hash().into_iter().collect()
+}
+
+#[test]
+fn fields_round_trip_under_their_hash_names() {
+    let user = User { name: "alice".to_string(), age: 30, email: Some("alice@example.com".to_string()), nickname: None };
+    let fields = hash(&user);
+    assert_eq!(fields.len(), 3);
+    assert_eq!(fields[&b"name"[..]], "alice");
+    assert_eq!(fields[&b"age"[..]], "30");
+    assert_eq!(fields[&b"mail"[..]], "alice@example.com");
+    assert!(!fields.contains_key(&b"email"[..]));
+    assert_eq!(user.absent_fields(), vec![Bytes::from_static(b"nickname")]);
+    assert_eq!(User::from_hash(fields).unwrap(), user);
+}
+
+#[test]
+fn none_fields_are_listed_as_absent_under_their_hash_names() {
+    let user = User { name: "bob".to_string(), age: 7, email: None, nickname: None };
+    assert_eq!(user.absent_fields(), vec![Bytes::from_static(b"mail"), Bytes::from_static(b"nickname")]);
+    assert_eq!(User::from_hash(hash(&user)).unwrap(), user);
+}
+
+#[test]
+fn missing_required_field_is_an_error() {
+    let mut fields = hash(&User { name: "carol".to_string(), age: 1, email: None, nickname: None });
+    fields.remove(&b"age"[..]);
+    assert!(User::from_hash(fields).is_err());
+}
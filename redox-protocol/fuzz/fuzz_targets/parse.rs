@@ -0,0 +1,19 @@
+//! 把任意字节交给 `Protocol::parse`，反复解析直到数据不完整或出现协议错误
+//! 解析不应 panic，每个完整的请求都要从缓冲区中取出数据，否则会无限循环
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use redox_protocol::{ParseError, Protocol};
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = BytesMut::from(data);
+    loop {
+        let len = buf.len();
+        match Protocol::parse(&mut buf) {
+            Ok(Some(_)) | Err(ParseError::Invalid(_)) => assert!(buf.len() < len, "a parsed request must consume input"),
+            Ok(None) | Err(ParseError::Protocol(_)) => break,
+        }
+    }
+});
@@ -0,0 +1,168 @@
+//! 二进制安全数据的 serde 序列化
+//! 合法的 UTF-8 数据序列化为字符串，与旧版本的数据文件保持兼容；其他数据序列化为字节数组。
+//! bincode 等不可读的紧凑格式不是自描述的，这些格式中统一使用字节串和 `[键, 值]` 对数组。
+
+use bytes::Bytes;
+use serde::de::{self, DeserializeOwned, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// 序列化时优先使用字符串表示的二进制数据
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Text(pub Bytes);
+
+impl Serialize for Text {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(&self.0);
+        }
+        match std::str::from_utf8(&self.0) {
+            Ok(text) => serializer.serialize_str(text),
+            Err(_) => serializer.collect_seq(self.0.iter()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Text {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextVisitor;
+
+        impl<'de> Visitor<'de> for TextVisitor {
+            type Value = Text;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or an array of bytes")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Text, E> {
+                Ok(Text(Bytes::copy_from_slice(v.as_bytes())))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<Text, E> {
+                Ok(Text(Bytes::from(v)))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Text, E> {
+                Ok(Text(Bytes::copy_from_slice(v)))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Text, E> {
+                Ok(Text(Bytes::from(v)))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Text, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(b) = seq.next_element::<u8>()? {
+                    bytes.push(b);
+                }
+                Ok(Text(Bytes::from(bytes)))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TextVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(TextVisitor)
+        }
+    }
+}
+
+/// 以二进制数据为键的映射
+/// 所有键都是合法的 UTF-8 时序列化为对象，否则序列化为 `[键, 值]` 对组成的数组；反序列化时两种格式都接受
+#[derive(Debug, Clone)]
+pub struct TextMap<V>(pub Vec<(Bytes, V)>);
+
+impl<V: Serialize> Serialize for TextMap<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let readable = serializer.is_human_readable();
+        if readable && self.0.iter().all(|(key, _)| std::str::from_utf8(key).is_ok()) {
+            let mut map = serializer.serialize_map(Some(self.0.len()))?;
+            for (key, value) in &self.0 {
+                map.serialize_entry(&Text(key.clone()), value)?;
+            }
+            map.end()
+        } else {
+            serializer.collect_seq(self.0.iter().map(|(key, value)| (Text(key.clone()), value)))
+        }
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for TextMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextMapVisitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for TextMapVisitor<V> {
+            type Value = TextMap<V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map or an array of [key, value] pairs")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<TextMap<V>, A::Error> {
+                let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some((Text(key), value)) = map.next_entry::<Text, V>()? {
+                    entries.push((key, value));
+                }
+                Ok(TextMap(entries))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<TextMap<V>, A::Error> {
+                let mut entries = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some((Text(key), value)) = seq.next_element::<(Text, V)>()? {
+                    entries.push((key, value));
+                }
+                Ok(TextMap(entries))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TextMapVisitor(PhantomData))
+        } else {
+            deserializer.deserialize_seq(TextMapVisitor(PhantomData))
+        }
+    }
+}
+
+impl<V> TextMap<V> {
+    /// 是否没有任何键
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<V> Default for TextMap<V> {
+    fn default() -> Self {
+        TextMap(Vec::new())
+    }
+}
+
+impl<V> FromIterator<(Bytes, V)> for TextMap<V> {
+    fn from_iter<I: IntoIterator<Item = (Bytes, V)>>(iter: I) -> Self {
+        TextMap(iter.into_iter().collect())
+    }
+}
+
+/// JSON 值的序列化，用于 `#[serde(with = "crate::binary::json")]`
+/// 可读的格式中保持原样；紧凑格式不支持 serde_json 反序列化所需的自描述数据，保存为 JSON 文本
+pub mod json {
+    use super::*;
+
+    pub fn serialize<T: Serialize, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            serializer.serialize_str(&serde_json::to_string(value).map_err(ser::Error::custom)?)
+        }
+    }
+
+    pub fn deserialize<'de, T: DeserializeOwned, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        if deserializer.is_human_readable() {
+            T::deserialize(deserializer)
+        } else {
+            let text = String::deserialize(deserializer)?;
+            serde_json::from_str(&text).map_err(de::Error::custom)
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! 基于 tokio_util 的帧编解码器
+//! `RedoxCodec` 用于服务器：从连接中解析出命令，并按连接的协议编码响应；
+//! `ClientCodec` 用于客户端：以行协议或协商的二进制格式发送命令并解析响应。
+//! 不完整的帧保留在缓冲区中等待更多数据，请求的大小受 `RequestLimits` 限制。
+
+use crate::compact::{self, BinaryFormat};
+use crate::resp::{self, RequestParser, RespVersion};
+use crate::{split_line, Chunks, Command, ParseError, Protocol, RedoxError, Response};
+use bytes::{Buf, BytesMut};
+use std::fmt;
+use std::io;
+use std::iter;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// 行协议中单行的最大长度（512MB），与 RESP 批量字符串的上限相同
+/// 用于客户端读取响应和解析可信的本地输入，服务器接受的请求由 `RequestLimits` 限制
+const MAX_LINE_LEN: usize = 512 * 1024 * 1024;
+
+/// 单行请求的默认最大长度（64KB），与 Redis 的内联命令相同，更大的值需要用 RESP 或二进制格式发送
+const DEFAULT_MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// 一个请求默认最多包含的参数个数
+const DEFAULT_MAX_ARGS: usize = 64 * 1024;
+
+/// 服务器接受的请求大小的上限
+/// 超过上限的请求回复协议错误并关闭连接，避免客户端发送不完整的超大请求时服务器无限制地缓存数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// 行协议和 RESP 内联命令中单行的最大长度
+    pub max_inline_len: usize,
+    /// 一个请求最多包含的参数个数（包括命令名）
+    pub max_args: usize,
+    /// RESP 中单个批量字符串的最大长度，二进制格式中单帧的最大长度
+    pub max_bulk_len: usize,
+}
+
+impl RequestLimits {
+    /// 只受协议本身限制的上限，用于解析可信的本地输入，如 redox-cli --pipe 读取的命令文件
+    pub fn trusted() -> Self {
+        Self {
+            max_inline_len: MAX_LINE_LEN,
+            max_args: 1024 * 1024,
+            max_bulk_len: compact::MAX_FRAME_LEN,
+        }
+    }
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_inline_len: DEFAULT_MAX_INLINE_LEN,
+            max_args: DEFAULT_MAX_ARGS,
+            max_bulk_len: 512 * 1024 * 1024,
+        }
+    }
+}
+
+/// 连接使用的协议，服务器根据客户端发送的第一个字节确定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireProtocol {
+    /// 以换行分隔的文本协议
+    Line,
+    /// RESP，兼容 redis-cli 和 Redis 客户端库，版本可以通过 HELLO 切换
+    Resp(RespVersion),
+    /// 握手协商的二进制格式，直接传输序列化的命令和响应
+    Binary(BinaryFormat),
+}
+
+impl WireProtocol {
+    /// 按连接的协议编码响应
+    pub fn encode_response(self, response: &Response) -> Vec<u8> {
+        match self {
+            WireProtocol::Line => Protocol::encode_response(response).into_bytes(),
+            WireProtocol::Resp(version) => resp::encode_response(response, version),
+            WireProtocol::Binary(format) => encode_binary(format, response),
+        }
+    }
+
+    /// 按连接的协议分块编码响应，集合类型的回复按元素依次编码
+    /// 
+    /// # Arguments
+    /// * `response` - 要编码的响应
+    /// * `chunk_size` - 每块的目标大小
+    pub fn encode_chunks(self, response: &Response, chunk_size: usize) -> Chunks<'_> {
+        match self {
+            WireProtocol::Line => Protocol::encode_chunks(response, chunk_size),
+            WireProtocol::Resp(version) => resp::encode_chunks(response, version, chunk_size),
+            WireProtocol::Binary(format) => Box::new(iter::once(encode_binary(format, response))),
+        }
+    }
+}
+
+/// 编解码过程中的错误
+#[derive(Debug)]
+pub enum CodecError {
+    /// 读写连接失败
+    Io(io::Error),
+    /// 协议错误，回复错误后应当关闭连接
+    Protocol(String),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::Io(e) => write!(f, "{}", e),
+            CodecError::Protocol(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<io::Error> for CodecError {
+    fn from(e: io::Error) -> Self {
+        CodecError::Io(e)
+    }
+}
+
+/// 服务器端的编解码器
+/// 解码出的每一帧是一个命令，命令格式错误时为 `Err(RedoxError)`，连接可以继续使用
+#[derive(Debug, Default)]
+pub struct RedoxCodec {
+    /// 连接的协议，收到第一个字节前为 None
+    protocol: Option<WireProtocol>,
+    /// 行协议中已经查找过换行符的位置，避免每次读取后从头查找
+    next_index: usize,
+    /// RESP 请求的解析状态，请求分多次到达时接着上次的位置解析
+    requests: RequestParser,
+    /// 请求大小的上限
+    limits: RequestLimits,
+}
+
+impl RedoxCodec {
+    /// 创建编解码器，协议根据收到的第一个字节检测：
+    /// 以 `*` 开头为 RESP，以 0 开头为二进制格式的握手，否则为行协议
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建使用指定请求大小上限的编解码器
+    pub fn with_limits(limits: RequestLimits) -> Self {
+        Self { limits, ..Self::default() }
+    }
+
+    /// 连接的协议，收到第一个字节前为 None
+    pub fn protocol(&self) -> Option<WireProtocol> {
+        self.protocol
+    }
+
+    /// 切换连接的协议，用于 HELLO 切换 RESP 版本和 RESET 恢复默认版本
+    pub fn set_protocol(&mut self, protocol: WireProtocol) {
+        self.protocol = Some(protocol);
+    }
+}
+
+impl Decoder for RedoxCodec {
+    type Item = Result<Command, RedoxError>;
+    type Error = CodecError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, CodecError> {
+        let protocol = match self.protocol {
+            Some(protocol) => protocol,
+            None => match buf.first() {
+                None => return Ok(None),
+                Some(b'*') => *self.protocol.insert(WireProtocol::Resp(RespVersion::Resp2)),
+                Some(b'\0') => match compact::parse_handshake(buf).map_err(CodecError::Protocol)? {
+                    Some(format) => *self.protocol.insert(WireProtocol::Binary(format)),
+                    None => return Ok(None),
+                },
+                Some(_) => *self.protocol.insert(WireProtocol::Line),
+            },
+        };
+        match decode_request(protocol, buf, &mut self.next_index, &mut self.requests, &self.limits) {
+            Ok(cmd) => Ok(cmd.map(Ok)),
+            Err(ParseError::Invalid(e)) => Ok(Some(Err(e))),
+            Err(ParseError::Protocol(e)) => Err(CodecError::Protocol(e)),
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, CodecError> {
+        if let Some(frame) = self.decode(buf)? {
+            return Ok(Some(frame));
+        }
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        // 行协议中最后一行可以没有换行符，RESP 和二进制格式中不完整的请求直接丢弃
+        let rest = buf.split();
+        self.next_index = 0;
+        self.requests = RequestParser::new();
+        match self.protocol {
+            Some(WireProtocol::Line) => Ok(Some(decode_line(&rest, self.limits.max_args))),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Encoder<&Response> for RedoxCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, response: &Response, dst: &mut BytesMut) -> Result<(), CodecError> {
+        let protocol = self.protocol.unwrap_or(WireProtocol::Line);
+        dst.extend_from_slice(&protocol.encode_response(response));
+        Ok(())
+    }
+}
+
+/// 已经编码好的回复片段，原样写出，用于分块写出大的回复
+impl Encoder<Vec<u8>> for RedoxCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, chunk: Vec<u8>, dst: &mut BytesMut) -> Result<(), CodecError> {
+        dst.extend_from_slice(&chunk);
+        Ok(())
+    }
+}
+
+/// 客户端的编解码器，默认使用行协议
+/// 行协议的响应不区分字符串和整数，解码规则见 `Protocol::decode_response`
+#[derive(Debug, Default)]
+pub struct ClientCodec {
+    /// 已经查找过换行符的位置
+    next_index: usize,
+    /// 使用的二进制格式，None 表示行协议
+    format: Option<BinaryFormat>,
+    /// 是否已经发送了二进制格式的握手
+    handshake_sent: bool,
+}
+
+impl ClientCodec {
+    /// 创建使用行协议的客户端编解码器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建使用二进制格式的客户端编解码器，握手随第一个命令一起发送
+    /// 
+    /// # Arguments
+    /// * `format` - 序列化格式
+    pub fn binary(format: BinaryFormat) -> Self {
+        Self { format: Some(format), ..Self::default() }
+    }
+}
+
+impl Decoder for ClientCodec {
+    type Item = Response;
+    type Error = CodecError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Response>, CodecError> {
+        if let Some(format) = self.format {
+            let Some(frame) = compact::take_frame(buf, compact::MAX_FRAME_LEN).map_err(CodecError::Protocol)? else {
+                return Ok(None);
+            };
+            return format.decode(&frame).map(Some).map_err(CodecError::Protocol);
+        }
+        Ok(take_line(buf, &mut self.next_index, MAX_LINE_LEN).map_err(CodecError::Protocol)?
+            .map(|line| Protocol::decode_response(&String::from_utf8_lossy(&line))))
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Response>, CodecError> {
+        if let Some(response) = self.decode(buf)? {
+            return Ok(Some(response));
+        }
+        if buf.is_empty() || self.format.is_some() {
+            return Ok(None);
+        }
+        let rest = buf.split();
+        self.next_index = 0;
+        Ok(Some(Protocol::decode_response(&String::from_utf8_lossy(&rest))))
+    }
+}
+
+impl Encoder<&Command> for ClientCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, cmd: &Command, dst: &mut BytesMut) -> Result<(), CodecError> {
+        let Some(format) = self.format else {
+            dst.extend_from_slice(Protocol::encode_command(cmd).as_bytes());
+            return Ok(());
+        };
+        let mut frame = Vec::new();
+        if !self.handshake_sent {
+            frame.extend_from_slice(&format.handshake());
+        }
+        format.encode_frame(cmd, &mut frame).map_err(CodecError::Protocol)?;
+        self.handshake_sent = true;
+        dst.extend_from_slice(&frame);
+        Ok(())
+    }
+}
+
+/// 按连接的协议从缓冲区开头解析一个请求，`RedoxCodec` 和 `Protocol::parse` 共用
+///
+/// # Arguments
+/// * `protocol` - 连接的协议
+/// * `buf` - 读取缓冲区
+/// * `next_index` - 行协议中上次查找换行符结束的位置
+/// * `requests` - RESP 请求的解析状态
+/// * `limits` - 请求大小的上限
+///
+/// # Returns
+/// * `Ok(Some(Command))` - 解析出的命令
+/// * `Ok(None)` - 数据不完整
+/// * `Err(ParseError)` - 命令无效或协议错误
+pub(crate) fn decode_request(
+    protocol: WireProtocol,
+    buf: &mut BytesMut,
+    next_index: &mut usize,
+    requests: &mut RequestParser,
+    limits: &RequestLimits,
+) -> Result<Option<Command>, ParseError> {
+    match protocol {
+        WireProtocol::Line => match take_line(buf, next_index, limits.max_inline_len).map_err(ParseError::Protocol)? {
+            Some(line) => decode_line(&line, limits.max_args).map(Some).map_err(ParseError::Invalid),
+            None => Ok(None),
+        },
+        WireProtocol::Resp(_) => loop {
+            let Some((args, consumed)) = requests.parse(buf, limits).map_err(ParseError::Protocol)? else {
+                return Ok(None);
+            };
+            buf.advance(consumed);
+            // 忽略空的内联命令
+            if args.is_empty() {
+                continue;
+            }
+            return Protocol::decode_args(&args).map(Some).map_err(|e| ParseError::Invalid(RedoxError::Syntax(e)));
+        },
+        WireProtocol::Binary(format) => match compact::take_frame(buf, limits.max_bulk_len).map_err(ParseError::Protocol)? {
+            Some(frame) => format.decode(&frame).map(Some).map_err(|e| ParseError::Invalid(RedoxError::Syntax(e))),
+            None => Ok(None),
+        },
+    }
+}
+
+/// 从缓冲区中取出以换行符结尾的一行
+///
+/// # Arguments
+/// * `buf` - 读取缓冲区
+/// * `next_index` - 上次查找换行符结束的位置，取出一行后归零
+/// * `max_len` - 一行的最大长度
+///
+/// # Returns
+/// * `Ok(Some(BytesMut))` - 包含换行符的一行
+/// * `Ok(None)` - 还没有收到完整的一行
+/// * `Err(String)` - 一行超过了长度上限
+fn take_line(buf: &mut BytesMut, next_index: &mut usize, max_len: usize) -> Result<Option<BytesMut>, String> {
+    match buf[*next_index..].iter().position(|b| *b == b'\n') {
+        Some(offset) if *next_index + offset > max_len => {
+            Err("Protocol error: too big inline request".to_string())
+        }
+        Some(offset) => {
+            let end = *next_index + offset + 1;
+            *next_index = 0;
+            Ok(Some(buf.split_to(end)))
+        }
+        None if buf.len() > max_len => {
+            Err("Protocol error: too big inline request".to_string())
+        }
+        None => {
+            *next_index = buf.len();
+            Ok(None)
+        }
+    }
+}
+
+/// 把响应编码为二进制格式的一帧，无法序列化时回复错误
+fn encode_binary(format: BinaryFormat, response: &Response) -> Vec<u8> {
+    let mut frame = Vec::new();
+    if let Err(e) = format.encode_frame(response, &mut frame) {
+        let error = Response::Error(RedoxError::Err(format!("Error encoding reply: {}", e)));
+        format.encode_frame(&error, &mut frame).expect("error replies are always serializable");
+    }
+    frame
+}
+
+/// 解析行协议的一行，无法解码为 UTF-8 的字节替换为 U+FFFD
+///
+/// # Arguments
+/// * `line` - 一行请求
+/// * `max_args` - 最多包含的参数个数，超过时不解析命令
+fn decode_line(line: &[u8], max_args: usize) -> Result<Command, RedoxError> {
+    let args = split_line(&String::from_utf8_lossy(line)).map_err(RedoxError::Syntax)?;
+    if args.len() > max_args {
+        return Err(RedoxError::Err("Protocol error: too many arguments".to_string()));
+    }
+    Protocol::decode_args(&args).map_err(RedoxError::Syntax)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESP: WireProtocol = WireProtocol::Resp(RespVersion::Resp2);
+
+    /// 用同一份解析状态解码缓冲区开头的请求
+    struct Requests {
+        next_index: usize,
+        requests: RequestParser,
+        limits: RequestLimits,
+    }
+
+    impl Requests {
+        fn new(limits: RequestLimits) -> Self {
+            Self { next_index: 0, requests: RequestParser::new(), limits }
+        }
+
+        fn decode(&mut self, protocol: WireProtocol, buf: &mut BytesMut) -> Result<Option<Command>, ParseError> {
+            decode_request(protocol, buf, &mut self.next_index, &mut self.requests, &self.limits)
+        }
+    }
+
+    #[test]
+    fn decodes_a_resp_request_fed_byte_by_byte() {
+        let request = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+        let mut requests = Requests::new(RequestLimits::default());
+        let mut buf = BytesMut::new();
+        for (i, byte) in request.iter().enumerate() {
+            buf.extend_from_slice(&[*byte]);
+            let decoded = requests.decode(RESP, &mut buf).unwrap();
+            if i + 1 < request.len() {
+                assert!(decoded.is_none(), "prefix of {} bytes", i + 1);
+            } else {
+                assert!(matches!(decoded, Some(Command::Get { key }) if key == "key"));
+            }
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decodes_a_line_request_fed_byte_by_byte() {
+        let request = b"SET key value\n";
+        let mut requests = Requests::new(RequestLimits::default());
+        let mut buf = BytesMut::new();
+        for byte in &request[..request.len() - 1] {
+            buf.extend_from_slice(&[*byte]);
+            assert!(requests.decode(WireProtocol::Line, &mut buf).unwrap().is_none());
+        }
+        buf.extend_from_slice(b"\n");
+        let decoded = requests.decode(WireProtocol::Line, &mut buf).unwrap();
+        assert!(matches!(decoded, Some(Command::Set { key, value }) if key == "key" && value == "value"));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn truncated_request_stays_in_the_buffer() {
+        let mut requests = Requests::new(RequestLimits::default());
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$3\r\nke"[..]);
+        assert_eq!(requests.decode(RESP, &mut buf).map(|cmd| cmd.is_none()), Ok(true));
+        assert_eq!(buf.len(), 19);
+    }
+
+    #[test]
+    fn decodes_frames_split_across_reads() {
+        let mut requests = Requests::new(RequestLimits::default());
+        let mut buf = BytesMut::from(&b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET"[..]);
+        assert!(matches!(requests.decode(RESP, &mut buf), Ok(Some(Command::Ping { message: None }))));
+        assert!(requests.decode(RESP, &mut buf).unwrap().is_none());
+        buf.extend_from_slice(b"\r\n$1\r\nk\r\n");
+        assert!(matches!(requests.decode(RESP, &mut buf), Ok(Some(Command::Get { key })) if key == "k"));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn skips_empty_inline_requests() {
+        let mut requests = Requests::new(RequestLimits::default());
+        let mut buf = BytesMut::from(&b"\r\n\r\n"[..]);
+        assert!(requests.decode(RESP, &mut buf).unwrap().is_none());
+        assert!(buf.is_empty());
+        buf.extend_from_slice(b"\r\nPING\r\n");
+        assert!(matches!(requests.decode(RESP, &mut buf), Ok(Some(Command::Ping { message: None }))));
+    }
+
+    #[test]
+    fn invalid_command_is_consumed_and_decoding_continues() {
+        let mut requests = Requests::new(RequestLimits::default());
+        let mut buf = BytesMut::from(&b"*1\r\n$7\r\nNOTACMD\r\n*1\r\n$4\r\nPING\r\n"[..]);
+        assert!(matches!(requests.decode(RESP, &mut buf), Err(ParseError::Invalid(_))));
+        assert!(matches!(requests.decode(RESP, &mut buf), Ok(Some(Command::Ping { message: None }))));
+    }
+
+    #[test]
+    fn rejects_requests_over_the_limits() {
+        let limits = RequestLimits { max_inline_len: 8, max_args: 2, max_bulk_len: 4 };
+        let protocol_error = |protocol: WireProtocol, input: &[u8]| {
+            match Requests::new(limits).decode(protocol, &mut BytesMut::from(input)) {
+                Err(ParseError::Protocol(e)) => e,
+                other => panic!("expected a protocol error, got {:?}", other),
+            }
+        };
+        assert!(protocol_error(WireProtocol::Line, b"GET a-long-key\n").contains("too big inline request"));
+        assert!(protocol_error(WireProtocol::Line, b"GET a-long-key").contains("too big inline request"));
+        assert!(protocol_error(RESP, b"GET a-long-key\r\n").contains("too big inline request"));
+        assert!(protocol_error(RESP, b"*3\r\n").contains("invalid multibulk length"));
+        assert!(protocol_error(RESP, b"*1\r\n$5\r\n").contains("invalid bulk length"));
+        // 行协议中参数过多只使这个命令无效，连接可以继续使用
+        let decoded = Requests::new(limits).decode(WireProtocol::Line, &mut BytesMut::from(&b"A B C\n"[..]));
+        assert!(matches!(decoded, Err(ParseError::Invalid(e)) if e.to_string().contains("too many arguments")));
+    }
+}
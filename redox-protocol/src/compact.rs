@@ -0,0 +1,144 @@
+//! 紧凑的二进制传输格式
+//! 不需要兼容 Redis 的服务之间可以协商使用 bincode 或 MessagePack 直接传输序列化的 `Command` 和 `Response`，
+//! 省去文本的格式化和解析。客户端连接后先发送握手：`\0RDX` 加一个格式字节（`b` 为 bincode，`m` 为 MessagePack），
+//! 之后的每个请求和响应都是一帧：4 字节大端序的长度，加上序列化后的数据。
+
+use bytes::{Buf, BytesMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// 握手的前缀，第一个字节为 0，不会与行协议和 RESP 的请求混淆
+pub const MAGIC: &[u8] = b"\0RDX";
+
+/// 单帧的最大长度（512MB），与 RESP 批量字符串的上限相同
+pub const MAX_FRAME_LEN: usize = 512 * 1024 * 1024;
+
+/// 帧长度前缀的字节数
+pub const LEN_PREFIX: usize = 4;
+
+/// 等待一帧的剩余部分时一次最多预留的缓冲区大小
+const MAX_RESERVE: usize = 64 * 1024;
+
+/// 二进制序列化格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFormat {
+    /// bincode，编码最紧凑，解析最快
+    Bincode,
+    /// MessagePack，其他语言也有成熟的实现
+    MessagePack,
+}
+
+impl BinaryFormat {
+    /// 解析格式名称（不区分大小写），支持 bincode、msgpack 和 messagepack
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bincode" => Some(BinaryFormat::Bincode),
+            "msgpack" | "messagepack" => Some(BinaryFormat::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// 格式名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryFormat::Bincode => "bincode",
+            BinaryFormat::MessagePack => "msgpack",
+        }
+    }
+
+    /// 客户端连接后发送的握手
+    pub fn handshake(self) -> Vec<u8> {
+        let byte = match self {
+            BinaryFormat::Bincode => b'b',
+            BinaryFormat::MessagePack => b'm',
+        };
+        [MAGIC, &[byte]].concat()
+    }
+
+    /// 把值编码为一帧，追加到 `dst` 末尾
+    ///
+    /// # Arguments
+    /// * `value` - 要编码的值
+    /// * `dst` - 输出缓冲区，编码失败时内容不变
+    ///
+    /// # Returns
+    /// * `Ok(())` - 编码成功
+    /// * `Err(String)` - 序列化失败的原因
+    pub fn encode_frame<T: Serialize>(self, value: &T, dst: &mut Vec<u8>) -> Result<(), String> {
+        let start = dst.len();
+        dst.extend_from_slice(&[0; LEN_PREFIX]);
+        let result = match self {
+            BinaryFormat::Bincode => bincode::serialize_into(&mut *dst, value).map_err(|e| e.to_string()),
+            BinaryFormat::MessagePack => rmp_serde::encode::write(&mut *dst, value).map_err(|e| e.to_string()),
+        };
+        let len = dst.len() - start - LEN_PREFIX;
+        if let Err(e) = result {
+            dst.truncate(start);
+            return Err(e);
+        }
+        if len > MAX_FRAME_LEN {
+            dst.truncate(start);
+            return Err("frame too large".to_string());
+        }
+        dst[start..start + LEN_PREFIX].copy_from_slice(&(len as u32).to_be_bytes());
+        Ok(())
+    }
+
+    /// 解析一帧的数据（不含长度前缀）
+    pub fn decode<T: DeserializeOwned>(self, data: &[u8]) -> Result<T, String> {
+        match self {
+            BinaryFormat::Bincode => bincode::deserialize(data).map_err(|e| e.to_string()),
+            BinaryFormat::MessagePack => rmp_serde::from_slice(data).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// 从缓冲区中解析握手
+///
+/// # Returns
+/// * `Ok(Some(BinaryFormat))` - 握手完整，已从缓冲区中取出
+/// * `Ok(None)` - 还没有收到完整的握手
+/// * `Err(String)` - 握手的前缀或格式无效
+pub fn parse_handshake(buf: &mut BytesMut) -> Result<Option<BinaryFormat>, String> {
+    let prefix = buf.len().min(MAGIC.len());
+    if buf[..prefix] != MAGIC[..prefix] {
+        return Err("Protocol error: invalid binary handshake".to_string());
+    }
+    let Some(&byte) = buf.get(MAGIC.len()) else {
+        return Ok(None);
+    };
+    let format = match byte {
+        b'b' => BinaryFormat::Bincode,
+        b'm' => BinaryFormat::MessagePack,
+        _ => return Err("Protocol error: unsupported binary format".to_string()),
+    };
+    buf.advance(MAGIC.len() + 1);
+    Ok(Some(format))
+}
+
+/// 从缓冲区中取出一帧
+///
+/// # Arguments
+/// * `buf` - 读取缓冲区
+/// * `max_len` - 一帧的最大长度，不超过 `MAX_FRAME_LEN`
+///
+/// # Returns
+/// * `Ok(Some(BytesMut))` - 一帧的数据，不含长度前缀
+/// * `Ok(None)` - 还没有收到完整的一帧
+/// * `Err(String)` - 帧的长度超过了上限
+pub fn take_frame(buf: &mut BytesMut, max_len: usize) -> Result<Option<BytesMut>, String> {
+    let Some(prefix) = buf.get(..LEN_PREFIX) else {
+        return Ok(None);
+    };
+    let len = u32::from_be_bytes(prefix.try_into().expect("prefix is 4 bytes")) as usize;
+    if len > max_len.min(MAX_FRAME_LEN) {
+        return Err("Protocol error: invalid frame length".to_string());
+    }
+    if buf.len() < LEN_PREFIX + len {
+        // 长度前缀由对端声明，每次最多多预留 MAX_RESERVE，内存随数据实际到达逐步增长
+        buf.reserve((LEN_PREFIX + len - buf.len()).min(MAX_RESERVE));
+        return Ok(None);
+    }
+    buf.advance(LEN_PREFIX);
+    Ok(Some(buf.split_to(len)))
+}
@@ -0,0 +1,186 @@
+//! 带标准前缀的错误类型
+//! 错误回复以错误码开头（如 `ERR`、`WRONGTYPE`、`NOAUTH`），客户端可以据此区分失败原因
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+
+/// 命令执行失败的原因
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RedoxError {
+    /// 通用错误，错误码为 ERR
+    Err(String),
+    /// 命令名称、参数个数或参数格式错误，错误码为 ERR
+    Syntax(String),
+    /// 操作的键保存的值类型与命令不匹配
+    WrongType,
+    /// 连接尚未认证，附带说明
+    NoAuth(String),
+    /// 用户名或密码错误，或用户已被禁用
+    WrongPass,
+    /// 用户没有执行命令或访问键的权限，附带说明
+    NoPerm(String),
+    /// EVALSHA 指定的脚本不存在
+    NoScript,
+    /// HELLO 请求了不支持的协议版本
+    NoProto,
+    /// RESTORE 的目标键已存在
+    BusyKey,
+    /// 内存占用超过 maxmemory 且无法淘汰键，拒绝可能增加内存的命令
+    Oom,
+    /// 键所属前缀的键数或字节数已达到配额，附带说明
+    Quota(String),
+    /// 只读副本拒绝写命令
+    ReadOnly,
+    /// 集群模式下键所在的槽由其他节点负责，附带槽和负责的节点的地址（`host:port`）
+    Moved { slot: u16, addr: String },
+    /// 集群模式下一个命令的多个键不在同一个槽
+    CrossSlot,
+    /// 集群模式下槽没有节点负责或集群不可用，附带说明
+    ClusterDown(String),
+    /// 集群模式下槽正在迁移，键不在本节点，客户端应当先发送 ASKING 再把这一个命令发给目标节点
+    Ask { slot: u16, addr: String },
+    /// 集群模式下槽正在迁移，多个键中只有一部分在本节点，客户端稍后重试
+    TryAgain(String),
+    /// MIGRATE 连接目标节点或等待回复时出错，附带说明
+    IoErr(String),
+    /// 事务中有命令排队时出错，EXEC 放弃执行整个事务
+    ExecAbort,
+}
+
+impl RedoxError {
+    /// 错误码，即错误回复的第一个单词
+    pub fn code(&self) -> &'static str {
+        match self {
+            RedoxError::Err(_) | RedoxError::Syntax(_) => "ERR",
+            RedoxError::WrongType => "WRONGTYPE",
+            RedoxError::NoAuth(_) => "NOAUTH",
+            RedoxError::WrongPass => "WRONGPASS",
+            RedoxError::NoPerm(_) => "NOPERM",
+            RedoxError::NoScript => "NOSCRIPT",
+            RedoxError::NoProto => "NOPROTO",
+            RedoxError::BusyKey => "BUSYKEY",
+            RedoxError::Oom => "OOM",
+            RedoxError::Quota(_) => "QUOTA",
+            RedoxError::ReadOnly => "READONLY",
+            RedoxError::Moved { .. } => "MOVED",
+            RedoxError::CrossSlot => "CROSSSLOT",
+            RedoxError::ClusterDown(_) => "CLUSTERDOWN",
+            RedoxError::Ask { .. } => "ASK",
+            RedoxError::TryAgain(_) => "TRYAGAIN",
+            RedoxError::IoErr(_) => "IOERR",
+            RedoxError::ExecAbort => "EXECABORT",
+        }
+    }
+
+    /// 错误码之后的说明
+    pub fn message(&self) -> Cow<'_, str> {
+        match self {
+            RedoxError::Err(message)
+            | RedoxError::Syntax(message)
+            | RedoxError::NoAuth(message)
+            | RedoxError::NoPerm(message)
+            | RedoxError::Quota(message)
+            | RedoxError::ClusterDown(message)
+            | RedoxError::TryAgain(message)
+            | RedoxError::IoErr(message) => {
+                Cow::Borrowed(message)
+            }
+            RedoxError::WrongType => "Operation against a key holding the wrong kind of value".into(),
+            RedoxError::WrongPass => "invalid username-password pair or user is disabled.".into(),
+            RedoxError::NoScript => "No matching script. Please use EVAL.".into(),
+            RedoxError::NoProto => "unsupported protocol version".into(),
+            RedoxError::BusyKey => "Target key name already exists.".into(),
+            RedoxError::Oom => "command not allowed when used memory > 'maxmemory'.".into(),
+            RedoxError::ReadOnly => "You can't write against a read only replica.".into(),
+            RedoxError::Moved { slot, addr } | RedoxError::Ask { slot, addr } => format!("{} {}", slot, addr).into(),
+            RedoxError::CrossSlot => "Keys in request don't hash to the same slot".into(),
+            RedoxError::ExecAbort => "Transaction discarded because of previous errors.".into(),
+        }
+    }
+
+    /// 从完整的错误文本还原错误，用于脚本和函数返回的错误
+    /// 以已知错误码开头的文本转换为对应的错误，其余文本作为通用错误
+    ///
+    /// # Arguments
+    /// * `text` - 错误文本，如 `WRONGTYPE Operation against ...`
+    pub fn parse(text: &str) -> Self {
+        Self::try_parse(text).unwrap_or_else(|| RedoxError::Err(text.to_string()))
+    }
+
+    /// 从完整的错误文本还原错误
+    ///
+    /// # Returns
+    /// * `Some(RedoxError)` - 文本以已知错误码开头
+    /// * `None` - 文本不是错误回复
+    pub fn try_parse(text: &str) -> Option<Self> {
+        let (code, rest) = text.split_once(' ').unwrap_or((text, ""));
+        Some(match code {
+            "ERR" => RedoxError::Err(rest.to_string()),
+            "WRONGTYPE" => RedoxError::WrongType,
+            "NOAUTH" => RedoxError::NoAuth(rest.to_string()),
+            "WRONGPASS" => RedoxError::WrongPass,
+            "NOPERM" => RedoxError::NoPerm(rest.to_string()),
+            "NOSCRIPT" => RedoxError::NoScript,
+            "NOPROTO" => RedoxError::NoProto,
+            "BUSYKEY" => RedoxError::BusyKey,
+            "OOM" => RedoxError::Oom,
+            "QUOTA" => RedoxError::Quota(rest.to_string()),
+            "READONLY" => RedoxError::ReadOnly,
+            "MOVED" => {
+                let (slot, addr) = rest.split_once(' ')?;
+                RedoxError::Moved { slot: slot.parse().ok()?, addr: addr.to_string() }
+            }
+            "CROSSSLOT" => RedoxError::CrossSlot,
+            "CLUSTERDOWN" => RedoxError::ClusterDown(rest.to_string()),
+            "ASK" => {
+                let (slot, addr) = rest.split_once(' ')?;
+                RedoxError::Ask { slot: slot.parse().ok()?, addr: addr.to_string() }
+            }
+            "TRYAGAIN" => RedoxError::TryAgain(rest.to_string()),
+            "IOERR" => RedoxError::IoErr(rest.to_string()),
+            "EXECABORT" => RedoxError::ExecAbort,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for RedoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for RedoxError {}
+
+impl From<String> for RedoxError {
+    fn from(message: String) -> Self {
+        RedoxError::Err(message)
+    }
+}
+
+impl From<&str> for RedoxError {
+    fn from(message: &str) -> Self {
+        RedoxError::Err(message.to_string())
+    }
+}
+
+/// 从字节流中增量解析请求时的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// 帧的格式正确但命令无效，帧已从缓冲区中取出，可以继续解析后面的请求
+    Invalid(RedoxError),
+    /// 帧的格式错误，无法确定下一个请求从哪里开始，连接应当关闭
+    Protocol(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Invalid(e) => write!(f, "{}", e),
+            ParseError::Protocol(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,106 +1,657 @@
+pub mod binary;
+pub mod codec;
+pub mod compact;
+pub mod error;
+pub mod meta;
+pub mod resp;
+pub mod slot;
+pub mod zset;
+
+use binary::{Text, TextMap};
+use bytes::{Bytes, BytesMut};
+use codec::WireProtocol;
+pub use error::{ParseError, RedoxError};
+pub use zset::SortedSet;
 use serde::{Serialize, Deserialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 /// 支持的数据类型
+/// 键、字符串、成员和字段都是二进制安全的 `Bytes`，可以保存任意字节；
 /// 使用 serde 进行序列化和反序列化，支持 JSON 格式
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ValueRepr", into = "ValueRepr")]
 pub enum RedoxValue {
     /// 字符串类型
-    String(String),
-    /// 列表类型，使用 Vec 实现
-    List(Vec<String>),
+    String(Bytes),
+    /// 列表类型，使用 VecDeque 实现，两端的插入和删除都是 O(1)
+    /// 序列化为数组，与使用 Vec 时的格式相同
+    List(std::collections::VecDeque<Bytes>),
     /// 集合类型，使用 HashSet 实现，保证元素唯一性
-    Set(std::collections::HashSet<String>),
+    Set(std::collections::HashSet<Bytes>),
     /// 哈希表类型，键值对存储
-    Hash(std::collections::HashMap<String, String>),
-    /// 有序集合类型，使用 BTreeMap 实现
-    /// 键为成员，值为分数，通过分数自动排序
-    SortedSet(std::collections::BTreeMap<String, f64>),
+    Hash(std::collections::HashMap<Bytes, Bytes>),
+    /// 有序集合类型，同时按成员和按 (分数, 成员) 建立索引
+    /// 遍历时按分数升序排列，分数相同时按成员的字典序排列
+    SortedSet(SortedSet),
+    /// JSON 文档类型，支持按路径读取和修改
+    Json(serde_json::Value),
+    /// 时间序列类型，按时间戳排序的 (毫秒时间戳, 数值) 样本
+    TimeSeries(TimeSeries),
+}
+
+impl RedoxValue {
+    /// 创建字符串值
+    pub fn string(s: impl Into<Bytes>) -> Self {
+        RedoxValue::String(s.into())
+    }
+
+    /// TYPE 返回的类型名称，JSON 文档和时间序列与 RedisJSON、RedisTimeSeries 的名称相同
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedoxValue::String(_) => "string",
+            RedoxValue::List(_) => "list",
+            RedoxValue::Set(_) => "set",
+            RedoxValue::Hash(_) => "hash",
+            RedoxValue::SortedSet(_) => "zset",
+            RedoxValue::Json(_) => "ReJSON-RL",
+            RedoxValue::TimeSeries(_) => "TSDB-TYPE",
+        }
+    }
+}
+
+/// `RedoxValue` 的序列化形式
+/// 合法的 UTF-8 数据保存为字符串，与旧版本的数据文件格式相同
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "RedoxValue")]
+enum ValueRepr {
+    String(Text),
+    List(Vec<Text>),
+    Set(Vec<Text>),
+    Hash(TextMap<Text>),
+    SortedSet(TextMap<f64>),
+    Json(#[serde(with = "binary::json")] serde_json::Value),
+    TimeSeries(TimeSeries),
+}
+
+impl From<ValueRepr> for RedoxValue {
+    fn from(repr: ValueRepr) -> Self {
+        match repr {
+            ValueRepr::String(s) => RedoxValue::String(s.0),
+            ValueRepr::List(list) => RedoxValue::List(list.into_iter().map(|s| s.0).collect()),
+            ValueRepr::Set(set) => RedoxValue::Set(set.into_iter().map(|s| s.0).collect()),
+            ValueRepr::Hash(hash) => RedoxValue::Hash(hash.0.into_iter().map(|(k, v)| (k, v.0)).collect()),
+            ValueRepr::SortedSet(zset) => RedoxValue::SortedSet(zset.0.into_iter().collect()),
+            ValueRepr::Json(json) => RedoxValue::Json(json),
+            ValueRepr::TimeSeries(ts) => RedoxValue::TimeSeries(ts),
+        }
+    }
+}
+
+impl From<RedoxValue> for ValueRepr {
+    fn from(value: RedoxValue) -> Self {
+        match value {
+            RedoxValue::String(s) => ValueRepr::String(Text(s)),
+            RedoxValue::List(list) => ValueRepr::List(list.into_iter().map(Text).collect()),
+            RedoxValue::Set(set) => ValueRepr::Set(set.into_iter().map(Text).collect()),
+            RedoxValue::Hash(hash) => ValueRepr::Hash(hash.into_iter().map(|(k, v)| (k, Text(v))).collect()),
+            RedoxValue::SortedSet(zset) => ValueRepr::SortedSet(TextMap(zset.into_iter().collect())),
+            RedoxValue::Json(json) => ValueRepr::Json(json),
+            RedoxValue::TimeSeries(ts) => ValueRepr::TimeSeries(ts),
+        }
+    }
+}
+
+/// 把二进制数据转换为文本，非法的 UTF-8 序列替换为 U+FFFD，用于行协议和错误信息
+pub fn text(bytes: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(bytes)
+}
+
+/// 时间序列
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimeSeries {
+    /// 样本保留时长（毫秒），0 表示永久保留
+    pub retention_ms: u64,
+    /// 样本数据，键为毫秒时间戳
+    pub samples: std::collections::BTreeMap<u64, f64>,
+}
+
+/// 时间序列的降采样聚合方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TsAggregation {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl TsAggregation {
+    /// 解析聚合方式名称（不区分大小写）
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "avg" => Some(TsAggregation::Avg),
+            "min" => Some(TsAggregation::Min),
+            "max" => Some(TsAggregation::Max),
+            "sum" => Some(TsAggregation::Sum),
+            "count" => Some(TsAggregation::Count),
+            _ => None,
+        }
+    }
+
+    /// 聚合方式名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TsAggregation::Avg => "avg",
+            TsAggregation::Min => "min",
+            TsAggregation::Max => "max",
+            TsAggregation::Sum => "sum",
+            TsAggregation::Count => "count",
+        }
+    }
+}
+
+/// 地理距离单位
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GeoUnit {
+    /// 米
+    Meters,
+    /// 千米
+    Kilometers,
+    /// 英里
+    Miles,
+    /// 英尺
+    Feet,
+}
+
+impl GeoUnit {
+    /// 解析单位名称（m、km、mi、ft，不区分大小写）
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "m" => Some(GeoUnit::Meters),
+            "km" => Some(GeoUnit::Kilometers),
+            "mi" => Some(GeoUnit::Miles),
+            "ft" => Some(GeoUnit::Feet),
+            _ => None,
+        }
+    }
+
+    /// 单位名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeoUnit::Meters => "m",
+            GeoUnit::Kilometers => "km",
+            GeoUnit::Miles => "mi",
+            GeoUnit::Feet => "ft",
+        }
+    }
+
+    /// 一个单位对应的米数
+    pub fn to_meters(&self) -> f64 {
+        match self {
+            GeoUnit::Meters => 1.0,
+            GeoUnit::Kilometers => 1000.0,
+            GeoUnit::Miles => 1609.34,
+            GeoUnit::Feet => 0.3048,
+        }
+    }
+}
+
+/// GEOSEARCH 的搜索中心
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GeoOrigin {
+    /// FROMMEMBER member
+    Member(Bytes),
+    /// FROMLONLAT longitude latitude
+    LonLat(f64, f64),
+}
+
+/// GEOSEARCH 的搜索范围
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GeoShape {
+    /// BYRADIUS radius unit
+    Radius { radius: f64, unit: GeoUnit },
+    /// BYBOX width height unit
+    Box { width: f64, height: f64, unit: GeoUnit },
+}
+
+impl GeoShape {
+    /// 范围使用的单位
+    pub fn unit(&self) -> GeoUnit {
+        match self {
+            GeoShape::Radius { unit, .. } | GeoShape::Box { unit, .. } => *unit,
+        }
+    }
+}
+
+/// CLUSTER SETSLOT 设置的槽的迁移状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SlotState {
+    /// IMPORTING node-id: 槽正在从指定的节点迁入本节点
+    Importing(String),
+    /// MIGRATING node-id: 槽正在从本节点迁出到指定的节点
+    Migrating(String),
+    /// NODE node-id: 槽改由指定的节点负责，结束迁移
+    Node(String),
+    /// STABLE: 取消槽的迁入或迁出状态
+    Stable,
+}
+
+/// EXPIRE 系列命令的设置条件
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ExpireCondition {
+    /// NX: 仅当键没有过期时间时设置
+    Nx,
+    /// XX: 仅当键已有过期时间时设置
+    Xx,
+    /// GT: 仅当新的过期时间晚于当前过期时间时设置
+    Gt,
+    /// LT: 仅当新的过期时间早于当前过期时间时设置
+    Lt,
+}
+
+impl ExpireCondition {
+    /// 解析条件名称（不区分大小写）
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_uppercase().as_str() {
+            "NX" => Some(ExpireCondition::Nx),
+            "XX" => Some(ExpireCondition::Xx),
+            "GT" => Some(ExpireCondition::Gt),
+            "LT" => Some(ExpireCondition::Lt),
+            _ => None,
+        }
+    }
+
+    /// 条件名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpireCondition::Nx => "NX",
+            ExpireCondition::Xx => "XX",
+            ExpireCondition::Gt => "GT",
+            ExpireCondition::Lt => "LT",
+        }
+    }
+}
+
+/// GETEX 命令对过期时间的调整方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GetExOption {
+    /// EX seconds
+    Ex(u64),
+    /// PX milliseconds
+    Px(u64),
+    /// EXAT unix-time-seconds
+    ExAt(u64),
+    /// PXAT unix-time-milliseconds
+    PxAt(u64),
+    /// PERSIST
+    Persist,
 }
 
 /// 命令类型
 /// 定义所有支持的命令及其参数
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Command {
-    /// 认证命令
-    Auth { password: String },
+    /// AUTH [username] password，不指定用户名时认证为 default 用户
+    Auth { username: Option<String>, password: String },
+
+    // 连接命令
+    /// PING [message]
+    Ping { message: Option<Bytes> },
+    /// ECHO message
+    Echo { message: Bytes },
+    /// RESET，将连接恢复到初始状态
+    Reset,
+    /// HELLO [protover [AUTH username password]]，协商 RESP 协议版本
+    Hello { protover: Option<u8>, auth: Option<(String, String)> },
     
     // 字符串操作
     /// SET key value
-    Set { key: String, value: String },
+    Set { key: Bytes, value: Bytes },
     /// GET key
-    Get { key: String },
+    Get { key: Bytes },
+    /// GETEX key [EX seconds|PX milliseconds|EXAT timestamp|PXAT timestamp|PERSIST]
+    GetEx { key: Bytes, option: Option<GetExOption> },
+    /// CAS key expected value
+    Cas { key: Bytes, expected: Bytes, value: Bytes },
+    /// STRLEN key
+    StrLen { key: Bytes },
     
     // 列表操作
     /// LPUSH key value
-    LPush { key: String, value: String },
+    LPush { key: Bytes, value: Bytes },
     /// RPUSH key value
-    RPush { key: String, value: String },
+    RPush { key: Bytes, value: Bytes },
     /// LPOP key
-    LPop { key: String },
+    LPop { key: Bytes },
     /// RPOP key
-    RPop { key: String },
+    RPop { key: Bytes },
     /// LRANGE key start stop
-    LRange { key: String, start: i64, stop: i64 },
+    LRange { key: Bytes, start: i64, stop: i64 },
+    /// LLEN key
+    LLen { key: Bytes },
     
     // 集合操作
     /// SADD key member
-    SAdd { key: String, member: String },
+    SAdd { key: Bytes, member: Bytes },
     /// SREM key member
-    SRem { key: String, member: String },
+    SRem { key: Bytes, member: Bytes },
     /// SMEMBERS key
-    SMembers { key: String },
+    SMembers { key: Bytes },
     /// SISMEMBER key member
-    SIsMember { key: String, member: String },
+    SIsMember { key: Bytes, member: Bytes },
+    /// SCARD key
+    SCard { key: Bytes },
     
     // 哈希操作
-    /// HSET key field value
-    HSet { key: String, field: String, value: String },
+    /// HSET key field value [field value ...]
+    HSet { key: Bytes, fields: Vec<(Bytes, Bytes)> },
     /// HGET key field
-    HGet { key: String, field: String },
+    HGet { key: Bytes, field: Bytes },
     /// HGETALL key
-    HGetAll { key: String },
+    HGetAll { key: Bytes },
     /// HDEL key field
-    HDel { key: String, field: String },
+    HDel { key: Bytes, field: Bytes },
+    /// HLEN key，不包括已过期的字段
+    HLen { key: Bytes },
+    /// HEXPIRE key seconds field [field ...]，设置字段的过期时间
+    HExpire { key: Bytes, seconds: u64, fields: Vec<Bytes> },
+    /// HTTL key field [field ...]，字段的剩余生存时间（秒）
+    HTTL { key: Bytes, fields: Vec<Bytes> },
+    /// HPERSIST key field [field ...]，移除字段的过期时间
+    HPersist { key: Bytes, fields: Vec<Bytes> },
     
     // 有序集合操作
     /// ZADD key score member
-    ZAdd { key: String, score: f64, member: String },
+    ZAdd { key: Bytes, score: f64, member: Bytes },
     /// ZREM key member
-    ZRem { key: String, member: String },
+    ZRem { key: Bytes, member: Bytes },
     /// ZRANGE key start stop
-    ZRange { key: String, start: i64, stop: i64 },
+    ZRange { key: Bytes, start: i64, stop: i64 },
     /// ZRANGEBYSCORE key min max
-    ZRangeByScore { key: String, min: f64, max: f64 },
-    MSet(Vec<(String, String)>),  // 批量设置
-    MGet(Vec<String>),           // 批量获取
+    ZRangeByScore { key: Bytes, min: f64, max: f64 },
+    /// ZCARD key
+    ZCard { key: Bytes },
+    MSet(Vec<(Bytes, Bytes)>),  // 批量设置
+    MGet(Vec<Bytes>),           // 批量获取
     Info,                        // 获取信息
-    Del(Vec<String>),  // DEL 命令支持删除多个键
+    /// CONFIG GET pattern，读取名称匹配的配置项
+    ConfigGet { pattern: String },
+    /// CONFIG SET parameter value [parameter value ...]，修改运行时配置
+    ConfigSet(Vec<(String, String)>),
+    /// ACL SETUSER username [rule ...]，创建或修改用户
+    AclSetUser { username: String, rules: Vec<String> },
+    /// ACL GETUSER username
+    AclGetUser { username: String },
+    /// ACL DELUSER username [username ...]
+    AclDelUser(Vec<String>),
+    /// ACL LIST，以规则的形式列出所有用户
+    AclList,
+    /// ACL USERS，列出所有用户名
+    AclUsers,
+    /// ACL WHOAMI，当前连接认证的用户名
+    AclWhoAmI,
+    /// ACL CAT [category]，列出命令类别或类别中的命令
+    AclCat { category: Option<String> },
+    /// ACL LOAD，重新加载 ACL 文件
+    AclLoad,
+    /// ACL SAVE，把当前的用户写入 ACL 文件
+    AclSave,
+    /// CLIENT ID，当前连接的编号
+    ClientId,
+    /// CLIENT LIST，列出所有连接
+    ClientList,
+    /// CLIENT GETNAME
+    ClientGetName,
+    /// CLIENT SETNAME name，名称为空字符串时清除名称
+    ClientSetName { name: String },
+    /// CLIENT KILL [ID id] [ADDR ip:port]，关闭所有匹配的连接；只指定地址的旧格式为 CLIENT KILL ip:port
+    ClientKill { id: Option<u64>, addr: Option<String>, legacy: bool },
+    /// CLIENT TRACKING ON|OFF，开启后连接读取过的键被修改时服务器推送失效通知，用于客户端缓存
+    ClientTracking { on: bool },
+    /// COMMAND，所有命令的元信息
+    CommandList,
+    /// COMMAND COUNT，命令的数量
+    CommandCount,
+    /// COMMAND INFO [name ...]，指定命令的元信息，不指定名称时返回所有命令
+    CommandInfo(Vec<String>),
+    /// DEBUG SLEEP seconds，阻塞整个服务器指定的秒数（可以是小数）
+    DebugSleep { seconds: f64 },
+    /// DEBUG OBJECT key，键的内部表示
+    DebugObject { key: Bytes },
+    /// DEBUG SET-ACTIVE-EXPIRE 0|1，关闭或开启后台的过期键清理
+    DebugSetActiveExpire { enabled: bool },
+    /// DEBUG QUICKSAVE，立即保存数据文件，不管数据是否有修改
+    DebugQuickSave,
+    /// DEBUG SET-TIME milliseconds，把服务器的手动时钟设置为指定的毫秒级 Unix 时间戳
+    DebugSetTime { unix_ms: u64 },
+    /// REPLICAOF host port，成为指定主节点的副本；REPLICAOF NO ONE 停止复制，成为主节点
+    ReplicaOf { primary: Option<(String, u16)> },
+    /// SYNC [listening-port]，副本请求全量同步和之后的修改，之后这个连接只用于复制
+    Sync { listening_port: Option<u16> },
+    /// PEERSYNC name，另一个数据中心的实例以 name 的名义推送匹配的键，之后这个连接只用于接收修改
+    PeerSync { name: String },
+    /// MONITOR，之后这个连接只用于接收服务器执行的每个命令
+    Monitor,
+    /// WAIT numreplicas timeout，等待之前的写入被指定数量的副本确认，timeout 为毫秒，0 表示一直等待
+    Wait { numreplicas: usize, timeout: u64 },
+    /// SUBSCRIBE channel [channel ...]，订阅频道，之后这个连接只能执行订阅相关的命令和 PING
+    Subscribe(Vec<Bytes>),
+    /// PSUBSCRIBE pattern [pattern ...]，订阅名称匹配通配符的频道
+    PSubscribe(Vec<Bytes>),
+    /// UNSUBSCRIBE [channel ...]，取消订阅频道，省略频道时取消所有频道
+    Unsubscribe(Vec<Bytes>),
+    /// PUNSUBSCRIBE [pattern ...]，取消订阅模式，省略模式时取消所有模式
+    PUnsubscribe(Vec<Bytes>),
+    /// PUBLISH channel message，向频道发送消息
+    Publish { channel: Bytes, message: Bytes },
+    /// MULTI，开始事务，之后的命令排队到 EXEC 时一起执行
+    Multi,
+    /// EXEC，原子地执行排队的命令；WATCH 的键被修改过时放弃执行，回复 nil
+    Exec,
+    /// DISCARD，放弃排队的命令
+    Discard,
+    /// WATCH key [key ...]，在下一个 EXEC 之前这些键被修改时放弃事务
+    Watch(Vec<Bytes>),
+    /// UNWATCH，取消所有 WATCH
+    Unwatch,
+    /// SENTINEL GET-MASTER-ADDR-BY-NAME name，哨兵监控的主节点当前的地址
+    SentinelGetMasterAddr { name: String },
+    /// SENTINEL MASTER name，哨兵监控的主节点的状态
+    SentinelMaster { name: String },
+    /// SENTINEL REPLICAS name，哨兵发现的主节点的副本及其状态，SLAVES 是旧名称
+    SentinelReplicas { name: String },
+    /// SENTINEL IS-MASTER-DOWN-BY-ADDR ip port current-epoch runid，哨兵之间询问主节点是否下线，
+    /// runid 不是 `*` 时同时请求对方在这一纪元投票给自己
+    SentinelIsMasterDownByAddr { host: String, port: u16, epoch: u64, runid: String },
+    /// SENTINEL FAILOVER name，不经其他哨兵同意，立即把一个副本提升为主节点
+    SentinelFailover { name: String },
+    /// CLUSTER MEET ip port，与指定的节点握手，把它加入本节点所在的集群
+    ClusterMeet { host: String, port: u16 },
+    /// CLUSTER ADDSLOTS slot [slot ...] 或 CLUSTER ADDSLOTSRANGE start end [start end ...]，由本节点负责指定的槽
+    ClusterAddSlots(Vec<u16>),
+    /// CLUSTER DELSLOTS slot [slot ...] 或 CLUSTER DELSLOTSRANGE start end [start end ...]，本节点不再负责指定的槽
+    ClusterDelSlots(Vec<u16>),
+    /// CLUSTER FORGET node-id，从本节点已知的节点中删除指定的节点
+    ClusterForget { id: String },
+    /// CLUSTER KEYSLOT key，键所在的槽
+    ClusterKeySlot { key: Bytes },
+    /// CLUSTER MYID，本节点的 ID
+    ClusterMyId,
+    /// CLUSTER INFO，集群的状态
+    ClusterInfo,
+    /// CLUSTER NODES，本节点已知的所有节点，每个节点一个元素
+    ClusterNodes,
+    /// CLUSTER SLOTS，每个槽范围由哪个主节点和哪些副本负责
+    ClusterSlots,
+    /// CLUSTER SHARDS，每个分片（一个主节点和它的副本）负责的槽和其中的节点
+    ClusterShards,
+    /// CLUSTER REPLICAS node-id，指定的主节点的副本，格式与 CLUSTER NODES 相同，SLAVES 是旧名称
+    ClusterReplicas { id: String },
+    /// CLUSTER REPLICATE node-id，本节点成为指定的主节点的副本
+    ClusterReplicate { id: String },
+    /// CLUSTER SETSLOT slot IMPORTING|MIGRATING|NODE node-id 或 CLUSTER SETSLOT slot STABLE，设置槽的迁移状态
+    ClusterSetSlot { slot: u16, state: SlotState },
+    /// CLUSTER GETKEYSINSLOT slot count，本节点上槽中的最多 count 个键
+    ClusterGetKeysInSlot { slot: u16, count: usize },
+    /// CLUSTER COUNTKEYSINSLOT slot，本节点上槽中的键数
+    ClusterCountKeysInSlot { slot: u16 },
+    /// CLUSTER BUS，节点之间建立集群总线，之后这个连接只用于交换集群的状态
+    ClusterBus,
+    /// ASKING，下一个命令访问正在迁入本节点的槽时不返回 MOVED
+    Asking,
+    Del(Vec<Bytes>),  // DEL 命令支持删除多个键
+    Unlink(Vec<Bytes>),  // 异步删除，值在后台释放
+    Touch(Vec<Bytes>),   // 更新键的最后访问时间
+    /// SCAN cursor [MATCH pattern] [COUNT count]，从游标处继续遍历键，count 为 None 时使用服务器的默认值
+    Scan { cursor: u64, pattern: Option<Bytes>, count: Option<usize> },
+    /// TYPE key，值的类型名称，键不存在时为 none
+    Type { key: Bytes },
+    /// FLUSHALL [ASYNC|SYNC]，删除所有键，ASYNC 在后台释放值
+    FlushAll { lazy: bool },
+    /// DUMP key
+    Dump { key: Bytes },
+    /// RESTORE key ttl payload [REPLACE]，payload 为 DUMP 返回的十六进制字符串
+    Restore { key: Bytes, ttl: u64, payload: String, replace: bool },
+    /// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [AUTH password | AUTH2 username password] [KEYS key ...]，
+    /// 把键发送到另一个实例后删除本地的键；username 为 None 时是 AUTH password
+    Migrate {
+        host: String,
+        port: u16,
+        keys: Vec<Bytes>,
+        timeout: u64,
+        copy: bool,
+        replace: bool,
+        auth: Option<(Option<String>, String)>,
+    },
     // 过期时间���令
-    Expire { key: String, seconds: u64 },  // 设置过期时间
-    TTL { key: String },                   // 获取剩余时间
-    Persist { key: String },               // 移除过期时间
+    Expire { key: Bytes, seconds: u64, condition: Option<ExpireCondition> },  // 设置过期时间
+    TTL { key: Bytes },                   // 获取剩余时间
+    Persist { key: Bytes },               // 移除过期时间
+    PExpire { key: Bytes, milliseconds: u64, condition: Option<ExpireCondition> },  // 设置过期时间（毫秒）
+    PTTL { key: Bytes },                        // 获取剩余时间（毫秒）
+    ExpireAt { key: Bytes, timestamp: u64, condition: Option<ExpireCondition> },   // 在指定 Unix 时间（秒）过期
+    PExpireAt { key: Bytes, timestamp: u64, condition: Option<ExpireCondition> },  // 在指定 Unix 时间（毫秒）过期
+
+    // 内省命令
+    /// OBJECT ENCODING key
+    ObjectEncoding { key: Bytes },
+    /// OBJECT IDLETIME key
+    ObjectIdleTime { key: Bytes },
+    /// MEMORY USAGE key [SAMPLES count]
+    MemoryUsage { key: Bytes, samples: Option<usize> },
+    /// MEMORY STATS
+    MemoryStats,
+
+    // 地理位置操作
+    /// GEOADD key longitude latitude member [longitude latitude member ...]
+    GeoAdd { key: Bytes, members: Vec<(f64, f64, Bytes)> },
+    /// GEODIST key member1 member2 [unit]
+    GeoDist { key: Bytes, member1: Bytes, member2: Bytes, unit: GeoUnit },
+    /// GEOPOS key member [member ...]
+    GeoPos { key: Bytes, members: Vec<Bytes> },
+    /// GEOSEARCH key FROMMEMBER member|FROMLONLAT lon lat BYRADIUS r unit|BYBOX w h unit
+    /// [ASC|DESC] [COUNT n] [WITHCOORD] [WITHDIST]
+    GeoSearch {
+        key: Bytes,
+        origin: GeoOrigin,
+        shape: GeoShape,
+        /// Some(true) 升序，Some(false) 降序，None 不排序
+        ascending: Option<bool>,
+        count: Option<usize>,
+        with_coord: bool,
+        with_dist: bool,
+    },
+
+    // JSON 文档操作
+    /// JSON.SET key path value
+    JsonSet {
+        key: Bytes,
+        path: String,
+        #[serde(with = "binary::json")]
+        value: serde_json::Value,
+    },
+    /// JSON.GET key [path]
+    JsonGet { key: Bytes, path: String },
+    /// JSON.DEL key [path]
+    JsonDel { key: Bytes, path: String },
+    /// JSON.NUMINCRBY key path number
+    JsonNumIncrBy {
+        key: Bytes,
+        path: String,
+        #[serde(with = "binary::json")]
+        increment: serde_json::Number,
+    },
+
+    // 时间序列操作
+    /// TS.CREATE key [RETENTION ms]
+    TsCreate { key: Bytes, retention_ms: Option<u64> },
+    /// TS.ADD key timestamp|* value [RETENTION ms]
+    TsAdd { key: Bytes, timestamp: Option<u64>, value: f64, retention_ms: Option<u64> },
+    /// TS.INCRBY key value [TIMESTAMP ts] [RETENTION ms]
+    TsIncrBy { key: Bytes, value: f64, timestamp: Option<u64>, retention_ms: Option<u64> },
+    /// TS.RANGE key from|- to|+ [AGGREGATION avg|min|max|sum|count bucket_ms]
+    TsRange { key: Bytes, from: u64, to: u64, aggregation: Option<(TsAggregation, u64)> },
+
+    // Lua 脚本
+    /// EVAL script numkeys [key ...] [arg ...]
+    Eval { script: String, keys: Vec<Bytes>, args: Vec<Bytes> },
+    /// EVALSHA sha1 numkeys [key ...] [arg ...]
+    EvalSha { sha1: String, keys: Vec<Bytes>, args: Vec<Bytes> },
+    /// SCRIPT LOAD script
+    ScriptLoad { script: String },
+    /// SCRIPT EXISTS sha1 [sha1 ...]
+    ScriptExists(Vec<String>),
+    /// SCRIPT FLUSH
+    ScriptFlush,
+
+    // 服务器端函数
+    /// FUNCTION LOAD [REPLACE] library code
+    FunctionLoad { library: String, code: String, replace: bool },
+    /// FUNCTION DELETE library
+    FunctionDelete { library: String },
+    /// FUNCTION LIST
+    FunctionList,
+    /// FUNCTION FLUSH
+    FunctionFlush,
+    /// FCALL function numkeys [key ...] [arg ...]
+    FCall { function: String, keys: Vec<Bytes>, args: Vec<Bytes> },
 }
 
 /// 响应类型
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     /// 操作成功，无返回值
     Ok,
     /// 操作成功，返回值
     Value(RedoxValue),
-    /// 操作失败，错误信息
-    Error(String),
-    Array(Vec<Option<String>>),  // 用于 MGET 的响应
+    /// 值不存在，与内容为 "NIL" 的字符串不同，行协议编码为 `(nil)`，RESP 编码为空值
+    Nil,
+    /// 操作失败，编码时以错误码开头，如 `WRONGTYPE ...`
+    Error(RedoxError),
+    Array(Vec<Option<Bytes>>),  // 用于 MGET 的响应
     Integer(i64),              // 用于 MSET 的响应
     Info(HashMap<String, String>), // 用于 INFO 的响应
+    /// 字段名到值的有序映射，用于 HELLO 等返回结构化信息的命令
+    Map(Vec<(String, Response)>),
+    /// 增量遍历的一批结果：下一次遍历使用的游标（0 表示遍历结束）和这一批元素，用于 SCAN
+    Cursor(u64, Vec<Bytes>),
+    /// 由多个回复组成的数组，元素可以是整数或嵌套的数组，如 EXEC 中按顺序排列的各命令的回复
+    Replies(Vec<Response>),
+    /// 服务器主动推送的消息，不是某个命令的回复，如 CLIENT TRACKING 的失效通知（kind 为 `invalidate`，items 为失效的键，为空表示所有键）
+    /// 和发布订阅的消息；元素通常是字符串，订阅确认中的订阅数为整数
+    Push { kind: String, items: Vec<Response> },
 }
 
+/// 行协议中表示不存在的值
+const NIL: &str = "(nil)";
+
 /// 协议解析和编码的实现
 pub struct Protocol;
 
 impl Protocol {
     /// 将命令编码为字符串格式
+    /// 包含空白、引号或无法显示的字节的参数会用双引号包围并转义，解析时可以还原为原始字节
     /// 
     /// # Arguments
     /// * `cmd` - 要编码的命令
@@ -109,49 +660,397 @@ impl Protocol {
     /// 编码后的字符串，以换行符结尾
     pub fn encode_command(cmd: &Command) -> String {
         match cmd {
-            Command::Auth { password } => format!("AUTH {}\n", password),
-            Command::Set { key, value } => format!("SET {} {}\n", key, value),
-            Command::Get { key } => format!("GET {}\n", key),
-            Command::LPush { key, value } => format!("LPUSH {} {}\n", key, value),
-            Command::RPush { key, value } => format!("RPUSH {} {}\n", key, value),
-            Command::LPop { key } => format!("LPOP {}\n", key),
-            Command::RPop { key } => format!("RPOP {}\n", key),
-            Command::LRange { key, start, stop } => format!("LRANGE {} {} {}\n", key, start, stop),
-            Command::SAdd { key, member } => format!("SADD {} {}\n", key, member),
-            Command::SRem { key, member } => format!("SREM {} {}\n", key, member),
-            Command::SMembers { key } => format!("SMEMBERS {}\n", key),
-            Command::SIsMember { key, member } => format!("SISMEMBER {} {}\n", key, member),
-            Command::HSet { key, field, value } => format!("HSET {} {} {}\n", key, field, value),
-            Command::HGet { key, field } => format!("HGET {} {}\n", key, field),
-            Command::HGetAll { key } => format!("HGETALL {}\n", key),
-            Command::HDel { key, field } => format!("HDEL {} {}\n", key, field),
-            Command::ZAdd { key, score, member } => format!("ZADD {} {} {}\n", key, score, member),
-            Command::ZRem { key, member } => format!("ZREM {} {}\n", key, member),
-            Command::ZRange { key, start, stop } => format!("ZRANGE {} {} {}\n", key, start, stop),
-            Command::ZRangeByScore { key, min, max } => format!("ZRANGEBYSCORE {} {} {}\n", key, min, max),
+            Command::Auth { username, password } => match username {
+                Some(username) => format!("AUTH {} {}\n", quote(username.as_bytes()), quote(password.as_bytes())),
+                None => format!("AUTH {}\n", quote(password.as_bytes())),
+            },
+            Command::Ping { message } => match message {
+                Some(message) => format!("PING {}\n", quote(message)),
+                None => "PING\n".to_string(),
+            },
+            Command::Echo { message } => format!("ECHO {}\n", quote(message)),
+            Command::Reset => "RESET\n".to_string(),
+            Command::Hello { protover, auth } => {
+                let mut cmd = "HELLO".to_string();
+                if let Some(protover) = protover {
+                    cmd.push_str(&format!(" {}", protover));
+                }
+                if let Some((username, password)) = auth {
+                    cmd.push_str(&format!(" AUTH {} {}", username, password));
+                }
+                cmd.push('\n');
+                cmd
+            },
+            Command::Set { key, value } => format!("SET {} {}\n", quote(key), quote(value)),
+            Command::Get { key } => format!("GET {}\n", quote(key)),
+            Command::Cas { key, expected, value } => format!("CAS {} {} {}\n", quote(key), quote(expected), quote(value)),
+            Command::StrLen { key } => format!("STRLEN {}\n", quote(key)),
+            Command::GetEx { key, option } => match option {
+                Some(GetExOption::Ex(v)) => format!("GETEX {} EX {}\n", quote(key), v),
+                Some(GetExOption::Px(v)) => format!("GETEX {} PX {}\n", quote(key), v),
+                Some(GetExOption::ExAt(v)) => format!("GETEX {} EXAT {}\n", quote(key), v),
+                Some(GetExOption::PxAt(v)) => format!("GETEX {} PXAT {}\n", quote(key), v),
+                Some(GetExOption::Persist) => format!("GETEX {} PERSIST\n", quote(key)),
+                None => format!("GETEX {}\n", quote(key)),
+            },
+            Command::LPush { key, value } => format!("LPUSH {} {}\n", quote(key), quote(value)),
+            Command::RPush { key, value } => format!("RPUSH {} {}\n", quote(key), quote(value)),
+            Command::LPop { key } => format!("LPOP {}\n", quote(key)),
+            Command::RPop { key } => format!("RPOP {}\n", quote(key)),
+            Command::LRange { key, start, stop } => format!("LRANGE {} {} {}\n", quote(key), start, stop),
+            Command::LLen { key } => format!("LLEN {}\n", quote(key)),
+            Command::SAdd { key, member } => format!("SADD {} {}\n", quote(key), quote(member)),
+            Command::SRem { key, member } => format!("SREM {} {}\n", quote(key), quote(member)),
+            Command::SMembers { key } => format!("SMEMBERS {}\n", quote(key)),
+            Command::SIsMember { key, member } => format!("SISMEMBER {} {}\n", quote(key), quote(member)),
+            Command::SCard { key } => format!("SCARD {}\n", quote(key)),
+            Command::HSet { key, fields } => {
+                let pairs: Vec<String> = fields.iter().map(|(field, value)| format!("{} {}", quote(field), quote(value))).collect();
+                format!("HSET {} {}\n", quote(key), pairs.join(" "))
+            },
+            Command::HGet { key, field } => format!("HGET {} {}\n", quote(key), quote(field)),
+            Command::HGetAll { key } => format!("HGETALL {}\n", quote(key)),
+            Command::HDel { key, field } => format!("HDEL {} {}\n", quote(key), quote(field)),
+            Command::HLen { key } => format!("HLEN {}\n", quote(key)),
+            Command::HExpire { key, seconds, fields } => {
+                format!("HEXPIRE {} {} {}\n", quote(key), seconds, join_quoted(fields))
+            },
+            Command::HTTL { key, fields } => format!("HTTL {} {}\n", quote(key), join_quoted(fields)),
+            Command::HPersist { key, fields } => format!("HPERSIST {} {}\n", quote(key), join_quoted(fields)),
+            Command::ZAdd { key, score, member } => format!("ZADD {} {} {}\n", quote(key), score, quote(member)),
+            Command::ZRem { key, member } => format!("ZREM {} {}\n", quote(key), quote(member)),
+            Command::ZRange { key, start, stop } => format!("ZRANGE {} {} {}\n", quote(key), start, stop),
+            Command::ZRangeByScore { key, min, max } => format!("ZRANGEBYSCORE {} {} {}\n", quote(key), min, max),
+            Command::ZCard { key } => format!("ZCARD {}\n", quote(key)),
             Command::MSet(pairs) => {
                 let mut cmd = String::new();
                 for (key, value) in pairs {
-                    cmd.push_str(&format!("SET {} {}\n", key, value));
+                    cmd.push_str(&format!("SET {} {}\n", quote(key), quote(value)));
                 }
                 cmd
             },
             Command::MGet(keys) => {
                 let mut cmd = String::new();
                 for key in keys {
-                    cmd.push_str(&format!("GET {}\n", key));
+                    cmd.push_str(&format!("GET {}\n", quote(key)));
                 }
                 cmd
             },
             Command::Info => "INFO\n".to_string(),
-            Command::Del(keys) => format!("DEL {}\n", keys.join(" ")),
-            Command::Expire { key, seconds } => format!("EXPIRE {} {}\n", key, seconds),
-            Command::TTL { key } => format!("TTL {}\n", key),
-            Command::Persist { key } => format!("PERSIST {}\n", key),
+            Command::ConfigGet { pattern } => format!("CONFIG GET {}\n", quote(pattern.as_bytes())),
+            Command::ConfigSet(params) => {
+                let params: Vec<String> = params.iter()
+                    .map(|(name, value)| format!("{} {}", name, quote(value.as_bytes())))
+                    .collect();
+                format!("CONFIG SET {}\n", params.join(" "))
+            },
+            Command::AclSetUser { username, rules } => {
+                let mut cmd = format!("ACL SETUSER {}", quote(username.as_bytes()));
+                for rule in rules {
+                    cmd.push_str(&format!(" {}", quote(rule.as_bytes())));
+                }
+                cmd.push('\n');
+                cmd
+            },
+            Command::AclGetUser { username } => format!("ACL GETUSER {}\n", quote(username.as_bytes())),
+            Command::AclDelUser(usernames) => {
+                let usernames: Vec<_> = usernames.iter().map(|name| quote(name.as_bytes())).collect();
+                format!("ACL DELUSER {}\n", usernames.join(" "))
+            },
+            Command::AclList => "ACL LIST\n".to_string(),
+            Command::AclUsers => "ACL USERS\n".to_string(),
+            Command::AclWhoAmI => "ACL WHOAMI\n".to_string(),
+            Command::AclCat { category } => match category {
+                Some(category) => format!("ACL CAT {}\n", quote(category.as_bytes())),
+                None => "ACL CAT\n".to_string(),
+            },
+            Command::AclLoad => "ACL LOAD\n".to_string(),
+            Command::AclSave => "ACL SAVE\n".to_string(),
+            Command::ClientId => "CLIENT ID\n".to_string(),
+            Command::ClientList => "CLIENT LIST\n".to_string(),
+            Command::ClientGetName => "CLIENT GETNAME\n".to_string(),
+            Command::ClientSetName { name } => format!("CLIENT SETNAME {}\n", quote(name.as_bytes())),
+            Command::ClientTracking { on } => format!("CLIENT TRACKING {}\n", if *on { "ON" } else { "OFF" }),
+            Command::ClientKill { id, addr, legacy } => match (id, addr) {
+                (None, Some(addr)) if *legacy => format!("CLIENT KILL {}\n", addr),
+                _ => {
+                    let mut cmd = "CLIENT KILL".to_string();
+                    if let Some(id) = id {
+                        cmd.push_str(&format!(" ID {}", id));
+                    }
+                    if let Some(addr) = addr {
+                        cmd.push_str(&format!(" ADDR {}", addr));
+                    }
+                    cmd.push('\n');
+                    cmd
+                }
+            },
+            Command::CommandList => "COMMAND\n".to_string(),
+            Command::CommandCount => "COMMAND COUNT\n".to_string(),
+            Command::CommandInfo(names) => {
+                let mut cmd = "COMMAND INFO".to_string();
+                for name in names {
+                    cmd.push_str(&format!(" {}", quote(name.as_bytes())));
+                }
+                cmd.push('\n');
+                cmd
+            },
+            Command::DebugSleep { seconds } => format!("DEBUG SLEEP {}\n", seconds),
+            Command::DebugObject { key } => format!("DEBUG OBJECT {}\n", quote(key)),
+            Command::DebugSetActiveExpire { enabled } => {
+                format!("DEBUG SET-ACTIVE-EXPIRE {}\n", if *enabled { 1 } else { 0 })
+            },
+            Command::DebugQuickSave => "DEBUG QUICKSAVE\n".to_string(),
+            Command::DebugSetTime { unix_ms } => format!("DEBUG SET-TIME {}\n", unix_ms),
+            Command::ReplicaOf { primary } => match primary {
+                Some((host, port)) => format!("REPLICAOF {} {}\n", quote(host.as_bytes()), port),
+                None => "REPLICAOF NO ONE\n".to_string(),
+            },
+            Command::Sync { listening_port } => match listening_port {
+                Some(port) => format!("SYNC {}\n", port),
+                None => "SYNC\n".to_string(),
+            },
+            Command::PeerSync { name } => format!("PEERSYNC {}\n", quote(name.as_bytes())),
+            Command::Monitor => "MONITOR\n".to_string(),
+            Command::Wait { numreplicas, timeout } => format!("WAIT {} {}\n", numreplicas, timeout),
+            Command::Subscribe(channels) => format!("SUBSCRIBE {}\n", join_quoted(channels)),
+            Command::PSubscribe(patterns) => format!("PSUBSCRIBE {}\n", join_quoted(patterns)),
+            Command::Unsubscribe(channels) if channels.is_empty() => "UNSUBSCRIBE\n".to_string(),
+            Command::Unsubscribe(channels) => format!("UNSUBSCRIBE {}\n", join_quoted(channels)),
+            Command::PUnsubscribe(patterns) if patterns.is_empty() => "PUNSUBSCRIBE\n".to_string(),
+            Command::PUnsubscribe(patterns) => format!("PUNSUBSCRIBE {}\n", join_quoted(patterns)),
+            Command::Publish { channel, message } => format!("PUBLISH {} {}\n", quote(channel), quote(message)),
+            Command::Multi => "MULTI\n".to_string(),
+            Command::Exec => "EXEC\n".to_string(),
+            Command::Discard => "DISCARD\n".to_string(),
+            Command::Watch(keys) => format!("WATCH {}\n", join_quoted(keys)),
+            Command::Unwatch => "UNWATCH\n".to_string(),
+            Command::SentinelGetMasterAddr { name } => {
+                format!("SENTINEL GET-MASTER-ADDR-BY-NAME {}\n", quote(name.as_bytes()))
+            }
+            Command::SentinelMaster { name } => format!("SENTINEL MASTER {}\n", quote(name.as_bytes())),
+            Command::SentinelReplicas { name } => format!("SENTINEL REPLICAS {}\n", quote(name.as_bytes())),
+            Command::SentinelIsMasterDownByAddr { host, port, epoch, runid } => format!(
+                "SENTINEL IS-MASTER-DOWN-BY-ADDR {} {} {} {}\n",
+                quote(host.as_bytes()), port, epoch, quote(runid.as_bytes())
+            ),
+            Command::SentinelFailover { name } => format!("SENTINEL FAILOVER {}\n", quote(name.as_bytes())),
+            Command::ClusterMeet { host, port } => format!("CLUSTER MEET {} {}\n", quote(host.as_bytes()), port),
+            Command::ClusterAddSlots(slots) => format!("CLUSTER ADDSLOTS {}\n", join_slots(slots)),
+            Command::ClusterDelSlots(slots) => format!("CLUSTER DELSLOTS {}\n", join_slots(slots)),
+            Command::ClusterForget { id } => format!("CLUSTER FORGET {}\n", quote(id.as_bytes())),
+            Command::ClusterKeySlot { key } => format!("CLUSTER KEYSLOT {}\n", quote(key)),
+            Command::ClusterMyId => "CLUSTER MYID\n".to_string(),
+            Command::ClusterInfo => "CLUSTER INFO\n".to_string(),
+            Command::ClusterNodes => "CLUSTER NODES\n".to_string(),
+            Command::ClusterSlots => "CLUSTER SLOTS\n".to_string(),
+            Command::ClusterShards => "CLUSTER SHARDS\n".to_string(),
+            Command::ClusterReplicas { id } => format!("CLUSTER REPLICAS {}\n", quote(id.as_bytes())),
+            Command::ClusterReplicate { id } => format!("CLUSTER REPLICATE {}\n", quote(id.as_bytes())),
+            Command::ClusterSetSlot { slot, state } => match state {
+                SlotState::Importing(id) => format!("CLUSTER SETSLOT {} IMPORTING {}\n", slot, quote(id.as_bytes())),
+                SlotState::Migrating(id) => format!("CLUSTER SETSLOT {} MIGRATING {}\n", slot, quote(id.as_bytes())),
+                SlotState::Node(id) => format!("CLUSTER SETSLOT {} NODE {}\n", slot, quote(id.as_bytes())),
+                SlotState::Stable => format!("CLUSTER SETSLOT {} STABLE\n", slot),
+            },
+            Command::ClusterGetKeysInSlot { slot, count } => format!("CLUSTER GETKEYSINSLOT {} {}\n", slot, count),
+            Command::ClusterCountKeysInSlot { slot } => format!("CLUSTER COUNTKEYSINSLOT {}\n", slot),
+            Command::ClusterBus => "CLUSTER BUS\n".to_string(),
+            Command::Asking => "ASKING\n".to_string(),
+            Command::Del(keys) => format!("DEL {}\n", join_quoted(keys)),
+            Command::Unlink(keys) => format!("UNLINK {}\n", join_quoted(keys)),
+            Command::Touch(keys) => format!("TOUCH {}\n", join_quoted(keys)),
+            Command::Scan { cursor, pattern, count } => {
+                let mut line = format!("SCAN {}", cursor);
+                if let Some(pattern) = pattern {
+                    line.push_str(&format!(" MATCH {}", quote(pattern)));
+                }
+                if let Some(count) = count {
+                    line.push_str(&format!(" COUNT {}", count));
+                }
+                line.push('\n');
+                line
+            }
+            Command::Type { key } => format!("TYPE {}\n", quote(key)),
+            Command::FlushAll { lazy } => {
+                if *lazy { "FLUSHALL ASYNC\n".to_string() } else { "FLUSHALL\n".to_string() }
+            },
+            Command::Dump { key } => format!("DUMP {}\n", quote(key)),
+            Command::Migrate { host, port, keys, timeout, copy, replace, auth } => {
+                let mut line = format!("MIGRATE {} {}", quote(host.as_bytes()), port);
+                match keys.as_slice() {
+                    [key] => line.push_str(&format!(" {} 0 {}", quote(key), timeout)),
+                    _ => line.push_str(&format!(" \"\" 0 {}", timeout)),
+                }
+                if *copy {
+                    line.push_str(" COPY");
+                }
+                if *replace {
+                    line.push_str(" REPLACE");
+                }
+                match auth {
+                    Some((Some(username), password)) => {
+                        line.push_str(&format!(" AUTH2 {} {}", quote(username.as_bytes()), quote(password.as_bytes())));
+                    }
+                    Some((None, password)) => line.push_str(&format!(" AUTH {}", quote(password.as_bytes()))),
+                    None => {}
+                }
+                if keys.len() != 1 {
+                    line.push_str(" KEYS");
+                    for key in keys {
+                        line.push(' ');
+                        line.push_str(&quote(key));
+                    }
+                }
+                line.push('\n');
+                line
+            }
+            Command::Restore { key, ttl, payload, replace } => {
+                let replace = if *replace { " REPLACE" } else { "" };
+                format!("RESTORE {} {} {}{}\n", quote(key), ttl, payload, replace)
+            },
+            Command::Expire { key, seconds, condition } => {
+                format!("EXPIRE {} {}{}\n", quote(key), seconds, encode_condition(condition))
+            },
+            Command::TTL { key } => format!("TTL {}\n", quote(key)),
+            Command::Persist { key } => format!("PERSIST {}\n", quote(key)),
+            Command::PExpire { key, milliseconds, condition } => {
+                format!("PEXPIRE {} {}{}\n", quote(key), milliseconds, encode_condition(condition))
+            },
+            Command::PTTL { key } => format!("PTTL {}\n", quote(key)),
+            Command::ExpireAt { key, timestamp, condition } => {
+                format!("EXPIREAT {} {}{}\n", quote(key), timestamp, encode_condition(condition))
+            },
+            Command::PExpireAt { key, timestamp, condition } => {
+                format!("PEXPIREAT {} {}{}\n", quote(key), timestamp, encode_condition(condition))
+            },
+            Command::ObjectEncoding { key } => format!("OBJECT ENCODING {}\n", quote(key)),
+            Command::ObjectIdleTime { key } => format!("OBJECT IDLETIME {}\n", quote(key)),
+            Command::MemoryUsage { key, samples } => match samples {
+                Some(n) => format!("MEMORY USAGE {} SAMPLES {}\n", quote(key), n),
+                None => format!("MEMORY USAGE {}\n", quote(key)),
+            },
+            Command::MemoryStats => "MEMORY STATS\n".to_string(),
+            Command::GeoAdd { key, members } => {
+                let members: Vec<String> = members.iter()
+                    .map(|(lon, lat, member)| format!("{} {} {}", lon, lat, quote(member)))
+                    .collect();
+                format!("GEOADD {} {}\n", quote(key), members.join(" "))
+            },
+            Command::GeoDist { key, member1, member2, unit } => {
+                format!("GEODIST {} {} {} {}\n", quote(key), quote(member1), quote(member2), unit.as_str())
+            },
+            Command::GeoPos { key, members } => format!("GEOPOS {} {}\n", quote(key), join_quoted(members)),
+            Command::GeoSearch { key, origin, shape, ascending, count, with_coord, with_dist } => {
+                let mut cmd = format!("GEOSEARCH {}", quote(key));
+                match origin {
+                    GeoOrigin::Member(member) => cmd.push_str(&format!(" FROMMEMBER {}", quote(member))),
+                    GeoOrigin::LonLat(lon, lat) => cmd.push_str(&format!(" FROMLONLAT {} {}", lon, lat)),
+                }
+                match shape {
+                    GeoShape::Radius { radius, unit } => {
+                        cmd.push_str(&format!(" BYRADIUS {} {}", radius, unit.as_str()))
+                    }
+                    GeoShape::Box { width, height, unit } => {
+                        cmd.push_str(&format!(" BYBOX {} {} {}", width, height, unit.as_str()))
+                    }
+                }
+                match ascending {
+                    Some(true) => cmd.push_str(" ASC"),
+                    Some(false) => cmd.push_str(" DESC"),
+                    None => {}
+                }
+                if let Some(count) = count {
+                    cmd.push_str(&format!(" COUNT {}", count));
+                }
+                if *with_coord {
+                    cmd.push_str(" WITHCOORD");
+                }
+                if *with_dist {
+                    cmd.push_str(" WITHDIST");
+                }
+                cmd.push('\n');
+                cmd
+            },
+            Command::JsonSet { key, path, value } => format!("JSON.SET {} {} {}\n", quote(key), path, value),
+            Command::JsonGet { key, path } => format!("JSON.GET {} {}\n", quote(key), path),
+            Command::JsonDel { key, path } => format!("JSON.DEL {} {}\n", quote(key), path),
+            Command::JsonNumIncrBy { key, path, increment } => {
+                format!("JSON.NUMINCRBY {} {} {}\n", quote(key), path, increment)
+            },
+            Command::TsCreate { key, retention_ms } => match retention_ms {
+                Some(ms) => format!("TS.CREATE {} RETENTION {}\n", quote(key), ms),
+                None => format!("TS.CREATE {}\n", quote(key)),
+            },
+            Command::TsAdd { key, timestamp, value, retention_ms } => {
+                let timestamp = timestamp.map(|t| t.to_string()).unwrap_or("*".to_string());
+                match retention_ms {
+                    Some(ms) => format!("TS.ADD {} {} {} RETENTION {}\n", quote(key), timestamp, value, ms),
+                    None => format!("TS.ADD {} {} {}\n", quote(key), timestamp, value),
+                }
+            },
+            Command::TsIncrBy { key, value, timestamp, retention_ms } => {
+                let mut cmd = format!("TS.INCRBY {} {}", quote(key), value);
+                if let Some(ts) = timestamp {
+                    cmd.push_str(&format!(" TIMESTAMP {}", ts));
+                }
+                if let Some(ms) = retention_ms {
+                    cmd.push_str(&format!(" RETENTION {}", ms));
+                }
+                cmd.push('\n');
+                cmd
+            },
+            Command::TsRange { key, from, to, aggregation } => match aggregation {
+                Some((agg, bucket)) => {
+                    format!("TS.RANGE {} {} {} AGGREGATION {} {}\n", quote(key), from, to, agg.as_str(), bucket)
+                }
+                None => format!("TS.RANGE {} {} {}\n", quote(key), from, to),
+            },
+            Command::Eval { script, keys, args } => {
+                format!("EVAL {} {}\n", quote(script.as_bytes()), encode_script_args(keys, args))
+            },
+            Command::EvalSha { sha1, keys, args } => {
+                format!("EVALSHA {} {}\n", sha1, encode_script_args(keys, args))
+            },
+            Command::ScriptLoad { script } => format!("SCRIPT LOAD {}\n", quote(script.as_bytes())),
+            Command::ScriptExists(shas) => format!("SCRIPT EXISTS {}\n", shas.join(" ")),
+            Command::ScriptFlush => "SCRIPT FLUSH\n".to_string(),
+            Command::FunctionLoad { library, code, replace } => {
+                let replace = if *replace { " REPLACE" } else { "" };
+                format!("FUNCTION LOAD{} {} {}\n", replace, library, quote(code.as_bytes()))
+            },
+            Command::FunctionDelete { library } => format!("FUNCTION DELETE {}\n", library),
+            Command::FunctionList => "FUNCTION LIST\n".to_string(),
+            Command::FunctionFlush => "FUNCTION FLUSH\n".to_string(),
+            Command::FCall { function, keys, args } => {
+                format!("FCALL {} {}\n", function, encode_script_args(keys, args))
+            },
         }
     }
 
+    /// 从字节缓冲区开头解析一个完整的请求，取出已解析的字节
+    /// 每个请求的协议根据第一个字节判断：以 `*` 开头为 RESP，否则为以换行结尾的行协议。
+    /// 可以直接传入从连接读到的原始数据，数据不完整时等待更多数据后再次调用。
+    /// 
+    /// # Arguments
+    /// * `buf` - 已读取但尚未处理的数据
+    /// 
+    /// # Returns
+    /// * `Ok(Some(Command))` - 解析出的命令
+    /// * `Ok(None)` - 数据不完整，缓冲区不变
+    /// * `Err(ParseError::Invalid)` - 请求已取出但不是有效的命令，可以继续解析
+    /// * `Err(ParseError::Protocol)` - 请求的格式错误，后续数据无法解析
+    pub fn parse(buf: &mut BytesMut) -> Result<Option<Command>, ParseError> {
+        let protocol = match buf.first() {
+            None => return Ok(None),
+            Some(b'*') => WireProtocol::Resp(resp::RespVersion::Resp2),
+            Some(_) => WireProtocol::Line,
+        };
+        codec::decode_request(protocol, buf, &mut 0, &mut resp::RequestParser::new(), &codec::RequestLimits::trusted())
+    }
+
     /// 将输入字符串解析为命令
+    /// 参数按空白拆分，可以用双引号或单引号包围以包含空白，双引号内支持 `\n`、`\"`、`\xHH` 等转义
     /// 
     /// # Arguments
     /// * `input` - 输入的命令字符串
@@ -160,252 +1059,844 @@ impl Protocol {
     /// * `Ok(Command)` - 解析成功的命令
     /// * `Err(String)` - 解析错误信息
     pub fn decode_command(input: &str) -> Result<Command, String> {
-        let input = input.trim();
-        let parts: Vec<&str> = input.split_whitespace().collect();
+        Self::decode_args(&split_line(input)?)
+    }
+
+    /// 将参数列表解析为命令
+    /// 每个参数对应 RESP 请求中的一个批量字符串，脚本、ECHO 消息和 JSON 值都是单个参数
+    /// 
+    /// # Arguments
+    /// * `args` - 命令名和参数
+    /// 
+    /// # Returns
+    /// * `Ok(Command)` - 解析成功的命令
+    /// * `Err(String)` - 解析错误信息
+    pub fn decode_args(args: &[Bytes]) -> Result<Command, String> {
+        // 命令名、选项和数字按文本解析，键和值保留原始字节
+        let words: Vec<Cow<str>> = args.iter().map(|arg| text(arg)).collect();
+        let parts: Vec<&str> = words.iter().map(|s| s.as_ref()).collect();
+
+        // 参数个数按命令表统一检查，下面只检查可选参数的组合
+        if let Some(spec) = meta::lookup_request(&parts) {
+            if !spec.accepts(parts.len()) {
+                return Err(format!("wrong number of arguments for '{}' command", spec.name));
+            }
+        }
         
-        match parts.get(0).map(|s| *s) {
+        match parts.first().copied() {
             Some(cmd) => match cmd.to_uppercase().as_str() {
-                "AUTH" => {
-                    if parts.len() != 2 {
-                        return Err("AUTH command requires password".to_string());
-                    }
-                    Ok(Command::Auth {
+                "AUTH" => match parts.len() {
+                    2 => Ok(Command::Auth {
+                        username: None,
                         password: parts[1].to_string(),
-                    })
-                }
-                "SET" => {
-                    if parts.len() != 3 {
-                        return Err("SET command requires KEY and VALUE".to_string());
-                    }
-                    Ok(Command::Set {
-                        key: parts[1].to_string(),
-                        value: parts[2].to_string(),
-                    })
-                }
-                "GET" => {
-                    if parts.len() != 2 {
-                        return Err("GET command requires KEY".to_string());
-                    }
-                    Ok(Command::Get {
-                        key: parts[1].to_string(),
-                    })
-                }
-                "LPUSH" => {
-                    if parts.len() != 3 {
-                        return Err("LPUSH command requires KEY and VALUE".to_string());
-                    }
-                    Ok(Command::LPush {
-                        key: parts[1].to_string(),
-                        value: parts[2].to_string(),
-                    })
-                }
-                "RPUSH" => {
-                    if parts.len() != 3 {
-                        return Err("RPUSH command requires KEY and VALUE".to_string());
+                    }),
+                    3 => Ok(Command::Auth {
+                        username: Some(parts[1].to_string()),
+                        password: parts[2].to_string(),
+                    }),
+                    _ => Err("AUTH command requires [USERNAME] PASSWORD".to_string()),
+                },
+                "PING" => {
+                    if parts.len() > 2 {
+                        return Err("PING command takes at most one MESSAGE".to_string());
                     }
-                    Ok(Command::RPush {
-                        key: parts[1].to_string(),
-                        value: parts[2].to_string(),
+                    Ok(Command::Ping {
+                        message: args.get(1).cloned(),
                     })
                 }
-                "LPOP" => {
-                    if parts.len() != 2 {
-                        return Err("LPOP command requires KEY".to_string());
-                    }
-                    Ok(Command::LPop {
-                        key: parts[1].to_string(),
-                    })
+                "ECHO" => Ok(Command::Echo {
+                    message: args[1].clone(),
+                }),
+                "RESET" => Ok(Command::Reset),
+                "HELLO" => {
+                    let protover = match parts.get(1) {
+                        Some(v) => match v.parse::<u8>() {
+                            Ok(v) => Some(v),
+                            Err(_) => return Err("Protocol version is not an integer or out of range".to_string()),
+                        },
+                        None => None,
+                    };
+                    let auth = match parts.get(2..).unwrap_or_default() {
+                        [] => None,
+                        [opt, username, password] if opt.eq_ignore_ascii_case("AUTH") => {
+                            Some((username.to_string(), password.to_string()))
+                        }
+                        _ => return Err("HELLO syntax is HELLO [protover [AUTH username password]]".to_string()),
+                    };
+                    Ok(Command::Hello { protover, auth })
                 }
-                "RPOP" => {
-                    if parts.len() != 2 {
-                        return Err("RPOP command requires KEY".to_string());
-                    }
-                    Ok(Command::RPop {
-                        key: parts[1].to_string(),
+                "SET" => Ok(Command::Set {
+                    key: args[1].clone(),
+                    value: args[2].clone(),
+                }),
+                "CAS" => Ok(Command::Cas {
+                    key: args[1].clone(),
+                    expected: args[2].clone(),
+                    value: args[3].clone(),
+                }),
+                "GET" => Ok(Command::Get {
+                    key: args[1].clone(),
+                }),
+                "STRLEN" => Ok(Command::StrLen {
+                    key: args[1].clone(),
+                }),
+                "GETEX" => {
+                    let option = match parts.len() {
+                        2 => None,
+                        3 if parts[2].eq_ignore_ascii_case("PERSIST") => Some(GetExOption::Persist),
+                        4 => {
+                            let value = parts[3].parse::<u64>()
+                                .map_err(|_| "Invalid expire time".to_string())?;
+                            match parts[2].to_uppercase().as_str() {
+                                "EX" => Some(GetExOption::Ex(value)),
+                                "PX" => Some(GetExOption::Px(value)),
+                                "EXAT" => Some(GetExOption::ExAt(value)),
+                                "PXAT" => Some(GetExOption::PxAt(value)),
+                                other => return Err(format!("Unknown GETEX option: {}", other)),
+                            }
+                        }
+                        _ => return Err("GETEX command requires KEY [EX seconds|PX milliseconds|EXAT timestamp|PXAT timestamp|PERSIST]".to_string()),
+                    };
+                    Ok(Command::GetEx {
+                        key: args[1].clone(),
+                        option,
                     })
                 }
+                "LPUSH" => Ok(Command::LPush {
+                    key: args[1].clone(),
+                    value: args[2].clone(),
+                }),
+                "RPUSH" => Ok(Command::RPush {
+                    key: args[1].clone(),
+                    value: args[2].clone(),
+                }),
+                "LPOP" => Ok(Command::LPop {
+                    key: args[1].clone(),
+                }),
+                "RPOP" => Ok(Command::RPop {
+                    key: args[1].clone(),
+                }),
                 "LRANGE" => {
-                    if parts.len() != 4 {
-                        return Err("LRANGE command requires KEY, START and STOP".to_string());
-                    }
                     let start = parts[2].parse::<i64>()
                         .map_err(|_| "Invalid START index".to_string())?;
                     let stop = parts[3].parse::<i64>()
                         .map_err(|_| "Invalid STOP index".to_string())?;
                     Ok(Command::LRange {
-                        key: parts[1].to_string(),
+                        key: args[1].clone(),
                         start,
                         stop,
                     })
                 }
-                "SADD" => {
-                    if parts.len() != 3 {
-                        return Err("SADD command requires KEY and MEMBER".to_string());
-                    }
-                    Ok(Command::SAdd {
-                        key: parts[1].to_string(),
-                        member: parts[2].to_string(),
-                    })
-                }
-                "SREM" => {
-                    if parts.len() != 3 {
-                        return Err("SREM command requires KEY and MEMBER".to_string());
-                    }
-                    Ok(Command::SRem {
-                        key: parts[1].to_string(),
-                        member: parts[2].to_string(),
-                    })
-                }
-                "SMEMBERS" => {
-                    if parts.len() != 2 {
-                        return Err("SMEMBERS command requires KEY".to_string());
-                    }
-                    Ok(Command::SMembers {
-                        key: parts[1].to_string(),
-                    })
-                }
-                "SISMEMBER" => {
-                    if parts.len() != 3 {
-                        return Err("SISMEMBER command requires KEY and MEMBER".to_string());
-                    }
-                    Ok(Command::SIsMember {
-                        key: parts[1].to_string(),
-                        member: parts[2].to_string(),
-                    })
-                }
+                "LLEN" => Ok(Command::LLen {
+                    key: args[1].clone(),
+                }),
+                "SADD" => Ok(Command::SAdd {
+                    key: args[1].clone(),
+                    member: args[2].clone(),
+                }),
+                "SREM" => Ok(Command::SRem {
+                    key: args[1].clone(),
+                    member: args[2].clone(),
+                }),
+                "SMEMBERS" => Ok(Command::SMembers {
+                    key: args[1].clone(),
+                }),
+                "SISMEMBER" => Ok(Command::SIsMember {
+                    key: args[1].clone(),
+                    member: args[2].clone(),
+                }),
+                "SCARD" => Ok(Command::SCard {
+                    key: args[1].clone(),
+                }),
                 "HSET" => {
-                    if parts.len() != 4 {
-                        return Err("HSET command requires KEY, FIELD and VALUE".to_string());
+                    if !parts.len().is_multiple_of(2) {
+                        return Err("HSET requires field value pairs".to_string());
                     }
                     Ok(Command::HSet {
-                        key: parts[1].to_string(),
-                        field: parts[2].to_string(),
-                        value: parts[3].to_string(),
-                    })
-                }
-                "HGET" => {
-                    if parts.len() != 3 {
-                        return Err("HGET command requires KEY and FIELD".to_string());
-                    }
-                    Ok(Command::HGet {
-                        key: parts[1].to_string(),
-                        field: parts[2].to_string(),
-                    })
-                }
-                "HDEL" => {
-                    if parts.len() != 3 {
-                        return Err("HDEL command requires KEY and FIELD".to_string());
-                    }
-                    Ok(Command::HDel {
-                        key: parts[1].to_string(),
-                        field: parts[2].to_string(),
+                        key: args[1].clone(),
+                        fields: args[2..].chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect(),
                     })
                 }
-                "HGETALL" => {
-                    if parts.len() != 2 {
-                        return Err("HGETALL command requires KEY".to_string());
-                    }
-                    Ok(Command::HGetAll {
-                        key: parts[1].to_string(),
+                "HGET" => Ok(Command::HGet {
+                    key: args[1].clone(),
+                    field: args[2].clone(),
+                }),
+                "HDEL" => Ok(Command::HDel {
+                    key: args[1].clone(),
+                    field: args[2].clone(),
+                }),
+                "HGETALL" => Ok(Command::HGetAll {
+                    key: args[1].clone(),
+                }),
+                "HLEN" => Ok(Command::HLen {
+                    key: args[1].clone(),
+                }),
+                "HEXPIRE" => {
+                    let seconds = parts[2].parse::<u64>()
+                        .map_err(|_| "Invalid seconds".to_string())?;
+                    Ok(Command::HExpire {
+                        key: args[1].clone(),
+                        seconds,
+                        fields: decode_fields(&args[3..])?,
                     })
                 }
+                "HTTL" => Ok(Command::HTTL {
+                    key: args[1].clone(),
+                    fields: decode_fields(&args[2..])?,
+                }),
+                "HPERSIST" => Ok(Command::HPersist {
+                    key: args[1].clone(),
+                    fields: decode_fields(&args[2..])?,
+                }),
                 "ZADD" => {
-                    if parts.len() != 4 {
-                        return Err("ZADD command requires KEY, SCORE and MEMBER".to_string());
-                    }
                     let score = parts[2].parse::<f64>()
                         .map_err(|_| "Invalid SCORE".to_string())?;
                     Ok(Command::ZAdd {
-                        key: parts[1].to_string(),
+                        key: args[1].clone(),
                         score,
-                        member: parts[3].to_string(),
-                    })
-                }
-                "ZREM" => {
-                    if parts.len() != 3 {
-                        return Err("ZREM command requires KEY and MEMBER".to_string());
-                    }
-                    Ok(Command::ZRem {
-                        key: parts[1].to_string(),
-                        member: parts[2].to_string(),
+                        member: args[3].clone(),
                     })
                 }
+                "ZREM" => Ok(Command::ZRem {
+                    key: args[1].clone(),
+                    member: args[2].clone(),
+                }),
                 "ZRANGE" => {
-                    if parts.len() != 4 {
-                        return Err("ZRANGE command requires KEY, START and STOP".to_string());
-                    }
                     let start = parts[2].parse::<i64>()
                         .map_err(|_| "Invalid START index".to_string())?;
                     let stop = parts[3].parse::<i64>()
                         .map_err(|_| "Invalid STOP index".to_string())?;
                     Ok(Command::ZRange {
-                        key: parts[1].to_string(),
+                        key: args[1].clone(),
                         start,
                         stop,
                     })
                 }
                 "ZRANGEBYSCORE" => {
-                    if parts.len() != 4 {
-                        return Err("ZRANGEBYSCORE command requires KEY, MIN and MAX".to_string());
-                    }
                     let min = parts[2].parse::<f64>()
                         .map_err(|_| "Invalid MIN score".to_string())?;
                     let max = parts[3].parse::<f64>()
                         .map_err(|_| "Invalid MAX score".to_string())?;
                     Ok(Command::ZRangeByScore {
-                        key: parts[1].to_string(),
+                        key: args[1].clone(),
                         min,
                         max,
                     })
                 }
+                "ZCARD" => Ok(Command::ZCard {
+                    key: args[1].clone(),
+                }),
                 "MSET" => {
                     if parts.len() < 3 || parts.len() % 2 != 1 {
                         return Err("MSET requires key value pairs".to_string());
                     }
                     let mut pairs = Vec::new();
-                    for chunk in parts[1..].chunks(2) {
-                        pairs.push((chunk[0].to_string(), chunk[1].to_string()));
+                    for chunk in args[1..].chunks(2) {
+                        pairs.push((chunk[0].clone(), chunk[1].clone()));
                     }
                     Ok(Command::MSet(pairs))
                 }
                 "MGET" => {
-                    if parts.len() < 2 {
-                        return Err("MGET requires at least one key".to_string());
-                    }
-                    Ok(Command::MGet(parts[1..].iter().map(|s| s.to_string()).collect()))
+                    Ok(Command::MGet(args[1..].to_vec()))
                 }
                 "INFO" => Ok(Command::Info),
+                "CONFIG" => {
+                    match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                        Some("GET") if parts.len() == 3 => Ok(Command::ConfigGet {
+                            pattern: parts[2].to_string(),
+                        }),
+                        Some("SET") if parts.len() >= 4 && parts.len().is_multiple_of(2) => Ok(Command::ConfigSet(
+                            parts[2..].chunks(2)
+                                .map(|pair| (pair[0].to_lowercase(), pair[1].to_string()))
+                                .collect(),
+                        )),
+                        Some("GET") => Err("CONFIG GET requires exactly one PATTERN".to_string()),
+                        Some("SET") => Err("CONFIG SET requires PARAMETER and VALUE pairs".to_string()),
+                        _ => Err("CONFIG subcommand must be GET or SET".to_string()),
+                    }
+                },
+                "ACL" => {
+                    let subcommand = parts.get(1).map(|s| s.to_uppercase());
+                    match (subcommand.as_deref(), &parts[1..]) {
+                        (Some("SETUSER"), [_, username, rules @ ..]) => Ok(Command::AclSetUser {
+                            username: username.to_string(),
+                            rules: rules.iter().map(|rule| rule.to_string()).collect(),
+                        }),
+                        (Some("GETUSER"), [_, username]) => Ok(Command::AclGetUser {
+                            username: username.to_string(),
+                        }),
+                        (Some("DELUSER"), [_, usernames @ ..]) if !usernames.is_empty() => Ok(Command::AclDelUser(
+                            usernames.iter().map(|name| name.to_string()).collect(),
+                        )),
+                        (Some("LIST"), [_]) => Ok(Command::AclList),
+                        (Some("USERS"), [_]) => Ok(Command::AclUsers),
+                        (Some("WHOAMI"), [_]) => Ok(Command::AclWhoAmI),
+                        (Some("CAT"), [_]) => Ok(Command::AclCat { category: None }),
+                        (Some("CAT"), [_, category]) => Ok(Command::AclCat {
+                            category: Some(category.to_string()),
+                        }),
+                        (Some("LOAD"), [_]) => Ok(Command::AclLoad),
+                        (Some("SAVE"), [_]) => Ok(Command::AclSave),
+                        (Some("SETUSER" | "GETUSER" | "DELUSER" | "LIST" | "USERS" | "WHOAMI" | "CAT" | "LOAD" | "SAVE"), _) => {
+                            Err(format!("Wrong number of arguments for ACL {}", parts[1].to_uppercase()))
+                        }
+                        _ => Err("ACL subcommand must be SETUSER, GETUSER, DELUSER, LIST, USERS, WHOAMI, CAT, LOAD or SAVE".to_string()),
+                    }
+                },
+                "CLIENT" => {
+                    let subcommand = parts.get(1).map(|s| s.to_uppercase());
+                    match (subcommand.as_deref(), &parts[1..]) {
+                        (Some("ID"), [_]) => Ok(Command::ClientId),
+                        (Some("LIST"), [_]) => Ok(Command::ClientList),
+                        (Some("GETNAME"), [_]) => Ok(Command::ClientGetName),
+                        (Some("SETNAME"), [_, name]) => {
+                            if name.chars().any(|c| !c.is_ascii_graphic()) {
+                                return Err("Client names cannot contain spaces, newlines or special characters.".to_string());
+                            }
+                            Ok(Command::ClientSetName { name: name.to_string() })
+                        }
+                        (Some("TRACKING"), [_, mode]) => match mode.to_uppercase().as_str() {
+                            "ON" => Ok(Command::ClientTracking { on: true }),
+                            "OFF" => Ok(Command::ClientTracking { on: false }),
+                            _ => Err("syntax error".to_string()),
+                        },
+                        (Some("KILL"), [_, addr]) => Ok(Command::ClientKill {
+                            id: None,
+                            addr: Some(addr.to_string()),
+                            legacy: true,
+                        }),
+                        (Some("KILL"), [_, filters @ ..]) if !filters.is_empty() && filters.len().is_multiple_of(2) => {
+                            let (mut id, mut addr) = (None, None);
+                            for pair in filters.chunks(2) {
+                                match pair[0].to_uppercase().as_str() {
+                                    "ID" => id = Some(pair[1].parse::<u64>()
+                                        .map_err(|_| "client-id should be greater than 0".to_string())?),
+                                    "ADDR" => addr = Some(pair[1].to_string()),
+                                    _ => return Err("syntax error".to_string()),
+                                }
+                            }
+                            Ok(Command::ClientKill { id, addr, legacy: false })
+                        }
+                        (Some("ID" | "LIST" | "GETNAME" | "SETNAME" | "KILL" | "TRACKING"), _) => {
+                            Err(format!("Wrong number of arguments for CLIENT {}", parts[1].to_uppercase()))
+                        }
+                        _ => Err("CLIENT subcommand must be ID, LIST, GETNAME, SETNAME, KILL or TRACKING".to_string()),
+                    }
+                },
+                "COMMAND" => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                    None => Ok(Command::CommandList),
+                    Some("COUNT") => Ok(Command::CommandCount),
+                    Some("INFO") => Ok(Command::CommandInfo(parts[2..].iter().map(|s| s.to_lowercase()).collect())),
+                    _ => Err("COMMAND subcommand must be COUNT or INFO".to_string()),
+                },
+                "DEBUG" => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                    Some("SLEEP") => {
+                        let seconds = parts[2].parse::<f64>()
+                            .ok()
+                            .filter(|s| s.is_finite() && *s >= 0.0)
+                            .ok_or_else(|| "Invalid SLEEP seconds".to_string())?;
+                        Ok(Command::DebugSleep { seconds })
+                    }
+                    Some("OBJECT") => Ok(Command::DebugObject {
+                        key: args[2].clone(),
+                    }),
+                    Some("SET-ACTIVE-EXPIRE") => match parts[2] {
+                        "0" => Ok(Command::DebugSetActiveExpire { enabled: false }),
+                        "1" => Ok(Command::DebugSetActiveExpire { enabled: true }),
+                        _ => Err("SET-ACTIVE-EXPIRE requires 0 or 1".to_string()),
+                    },
+                    Some("QUICKSAVE") => Ok(Command::DebugQuickSave),
+                    Some("SET-TIME") => {
+                        let unix_ms = parts[2].parse::<u64>()
+                            .map_err(|_| "Invalid SET-TIME milliseconds".to_string())?;
+                        Ok(Command::DebugSetTime { unix_ms })
+                    }
+                    Some(sub) => Err(format!("Unknown DEBUG subcommand: {}", sub)),
+                    None => Err("DEBUG command requires a subcommand".to_string()),
+                },
+                // SLAVEOF 是 REPLICAOF 的旧名称
+                "REPLICAOF" | "SLAVEOF" => match parts[1..] {
+                    [no, one] if no.eq_ignore_ascii_case("NO") && one.eq_ignore_ascii_case("ONE") => {
+                        Ok(Command::ReplicaOf { primary: None })
+                    }
+                    [host, port] => {
+                        let port = port.parse::<u16>()
+                            .map_err(|_| "Invalid master port".to_string())?;
+                        Ok(Command::ReplicaOf { primary: Some((host.to_string(), port)) })
+                    }
+                    _ => Err("REPLICAOF command requires HOST PORT or NO ONE".to_string()),
+                },
+                "SYNC" => match parts[1..] {
+                    [] => Ok(Command::Sync { listening_port: None }),
+                    [port] => {
+                        let port = port.parse::<u16>()
+                            .map_err(|_| "Invalid listening port".to_string())?;
+                        Ok(Command::Sync { listening_port: Some(port) })
+                    }
+                    _ => Err("SYNC command takes at most one LISTENING-PORT".to_string()),
+                },
+                "PEERSYNC" => Ok(Command::PeerSync { name: parts[1].to_string() }),
+                "MONITOR" => Ok(Command::Monitor),
+                "SUBSCRIBE" => Ok(Command::Subscribe(args[1..].to_vec())),
+                "PSUBSCRIBE" => Ok(Command::PSubscribe(args[1..].to_vec())),
+                "UNSUBSCRIBE" => Ok(Command::Unsubscribe(args[1..].to_vec())),
+                "PUNSUBSCRIBE" => Ok(Command::PUnsubscribe(args[1..].to_vec())),
+                "PUBLISH" => Ok(Command::Publish {
+                    channel: args[1].clone(),
+                    message: args[2].clone(),
+                }),
+                "MULTI" => Ok(Command::Multi),
+                "EXEC" => Ok(Command::Exec),
+                "DISCARD" => Ok(Command::Discard),
+                "WATCH" => Ok(Command::Watch(args[1..].to_vec())),
+                "UNWATCH" => Ok(Command::Unwatch),
+                "WAIT" => match parts[1..] {
+                    [numreplicas, timeout] => {
+                        let numreplicas = numreplicas.parse::<usize>()
+                            .map_err(|_| "Invalid number of replicas".to_string())?;
+                        let timeout = timeout.parse::<u64>()
+                            .map_err(|_| "timeout is not an integer or out of range".to_string())?;
+                        Ok(Command::Wait { numreplicas, timeout })
+                    }
+                    _ => Err("WAIT command requires NUMREPLICAS and TIMEOUT".to_string()),
+                },
+                "SENTINEL" => {
+                    let subcommand = parts.get(1).map(|s| s.to_uppercase());
+                    match (subcommand.as_deref(), &parts[1..]) {
+                        (Some("GET-MASTER-ADDR-BY-NAME"), [_, name]) => {
+                            Ok(Command::SentinelGetMasterAddr { name: name.to_string() })
+                        }
+                        (Some("MASTER"), [_, name]) => Ok(Command::SentinelMaster { name: name.to_string() }),
+                        (Some("REPLICAS" | "SLAVES"), [_, name]) => {
+                            Ok(Command::SentinelReplicas { name: name.to_string() })
+                        }
+                        (Some("IS-MASTER-DOWN-BY-ADDR"), [_, host, port, epoch, runid]) => {
+                            Ok(Command::SentinelIsMasterDownByAddr {
+                                host: host.to_string(),
+                                port: port.parse().map_err(|_| "Invalid port".to_string())?,
+                                epoch: epoch.parse().map_err(|_| "Invalid epoch".to_string())?,
+                                runid: runid.to_string(),
+                            })
+                        }
+                        (Some("FAILOVER"), [_, name]) => Ok(Command::SentinelFailover { name: name.to_string() }),
+                        (Some("GET-MASTER-ADDR-BY-NAME" | "MASTER" | "REPLICAS" | "SLAVES" | "IS-MASTER-DOWN-BY-ADDR" | "FAILOVER"), _) => {
+                            Err(format!("Wrong number of arguments for SENTINEL {}", parts[1].to_uppercase()))
+                        }
+                        _ => Err("SENTINEL subcommand must be GET-MASTER-ADDR-BY-NAME, MASTER, REPLICAS, IS-MASTER-DOWN-BY-ADDR or FAILOVER".to_string()),
+                    }
+                },
+                "CLUSTER" => {
+                    let subcommand = parts.get(1).map(|s| s.to_uppercase());
+                    match (subcommand.as_deref(), &parts[1..]) {
+                        // 第三个参数是 Redis 的集群总线端口，Redox 的集群总线使用同一个端口，忽略
+                        (Some("MEET"), [_, host, port] | [_, host, port, _]) => Ok(Command::ClusterMeet {
+                            host: host.to_string(),
+                            port: port.parse().map_err(|_| format!("Invalid node port: {}", port))?,
+                        }),
+                        (Some("ADDSLOTS"), [_, slots @ ..]) => Ok(Command::ClusterAddSlots(parse_slots(slots)?)),
+                        (Some("ADDSLOTSRANGE"), [_, ranges @ ..]) => Ok(Command::ClusterAddSlots(parse_slot_ranges(ranges)?)),
+                        (Some("DELSLOTS"), [_, slots @ ..]) => Ok(Command::ClusterDelSlots(parse_slots(slots)?)),
+                        (Some("DELSLOTSRANGE"), [_, ranges @ ..]) => Ok(Command::ClusterDelSlots(parse_slot_ranges(ranges)?)),
+                        (Some("FORGET"), [_, id]) => Ok(Command::ClusterForget { id: id.to_string() }),
+                        (Some("KEYSLOT"), [_, _]) => Ok(Command::ClusterKeySlot { key: args[2].clone() }),
+                        (Some("MYID"), [_]) => Ok(Command::ClusterMyId),
+                        (Some("INFO"), [_]) => Ok(Command::ClusterInfo),
+                        (Some("NODES"), [_]) => Ok(Command::ClusterNodes),
+                        (Some("SLOTS"), [_]) => Ok(Command::ClusterSlots),
+                        (Some("SHARDS"), [_]) => Ok(Command::ClusterShards),
+                        (Some("REPLICAS" | "SLAVES"), [_, id]) => Ok(Command::ClusterReplicas { id: id.to_string() }),
+                        (Some("REPLICATE"), [_, id]) => Ok(Command::ClusterReplicate { id: id.to_string() }),
+                        (Some("SETSLOT"), [_, slot, action, rest @ ..]) => {
+                            let slot = parse_slot(slot)?;
+                            let state = match (action.to_uppercase().as_str(), rest) {
+                                ("IMPORTING", [id]) => SlotState::Importing(id.to_string()),
+                                ("MIGRATING", [id]) => SlotState::Migrating(id.to_string()),
+                                ("NODE", [id]) => SlotState::Node(id.to_string()),
+                                ("STABLE", []) => SlotState::Stable,
+                                _ => return Err("Invalid CLUSTER SETSLOT action or number of arguments".to_string()),
+                            };
+                            Ok(Command::ClusterSetSlot { slot, state })
+                        }
+                        (Some("GETKEYSINSLOT"), [_, slot, count]) => Ok(Command::ClusterGetKeysInSlot {
+                            slot: parse_slot(slot)?,
+                            count: count.parse().map_err(|_| "Invalid number of keys".to_string())?,
+                        }),
+                        (Some("COUNTKEYSINSLOT"), [_, slot]) => Ok(Command::ClusterCountKeysInSlot { slot: parse_slot(slot)? }),
+                        (Some("BUS"), [_]) => Ok(Command::ClusterBus),
+                        (Some("MEET" | "FORGET" | "KEYSLOT" | "MYID" | "INFO" | "NODES" | "SLOTS" | "SHARDS" | "REPLICAS" | "SLAVES" | "REPLICATE" | "SETSLOT" | "GETKEYSINSLOT" | "COUNTKEYSINSLOT" | "BUS"), _) => {
+                            Err(format!("Wrong number of arguments for CLUSTER {}", parts[1].to_uppercase()))
+                        }
+                        _ => Err("CLUSTER subcommand must be MEET, ADDSLOTS, ADDSLOTSRANGE, DELSLOTS, DELSLOTSRANGE, FORGET, KEYSLOT, MYID, INFO, NODES, SLOTS, SHARDS, REPLICAS, REPLICATE, SETSLOT, GETKEYSINSLOT or COUNTKEYSINSLOT".to_string()),
+                    }
+                },
                 "DEL" => {
-                    if parts.len() < 2 {
-                        return Err("DEL command requires at least one KEY".to_string());
+                    Ok(Command::Del(args[1..].to_vec()))
+                },
+                "UNLINK" => {
+                    Ok(Command::Unlink(args[1..].to_vec()))
+                },
+                "FLUSHALL" => match parts[1..] {
+                    [] => Ok(Command::FlushAll { lazy: false }),
+                    [mode] if mode.eq_ignore_ascii_case("SYNC") => Ok(Command::FlushAll { lazy: false }),
+                    [mode] if mode.eq_ignore_ascii_case("ASYNC") => Ok(Command::FlushAll { lazy: true }),
+                    _ => Err("FLUSHALL only accepts ASYNC or SYNC".to_string()),
+                },
+                "MIGRATE" => decode_migrate(&parts, args),
+                "ASKING" => Ok(Command::Asking),
+                "DUMP" => Ok(Command::Dump {
+                    key: args[1].clone(),
+                }),
+                "RESTORE" => {
+                    if parts.len() != 4 && parts.len() != 5 {
+                        return Err("RESTORE command requires KEY, TTL and PAYLOAD".to_string());
                     }
-                    Ok(Command::Del(parts[1..].iter().map(|s| s.to_string()).collect()))
+                    let ttl = parts[2].parse::<u64>()
+                        .map_err(|_| "Invalid TTL".to_string())?;
+                    let replace = match parts.get(4) {
+                        Some(opt) if opt.eq_ignore_ascii_case("REPLACE") => true,
+                        Some(opt) => return Err(format!("Unknown RESTORE option: {}", opt)),
+                        None => false,
+                    };
+                    Ok(Command::Restore {
+                        key: args[1].clone(),
+                        ttl,
+                        payload: parts[3].to_string(),
+                        replace,
+                    })
                 },
+                "EVAL" => {
+                    let (keys, args) = decode_script_args(&args[2..])?;
+                    Ok(Command::Eval {
+                        script: parts[1].to_string(),
+                        keys,
+                        args,
+                    })
+                },
+                "EVALSHA" => {
+                    let (keys, args) = decode_script_args(&args[2..])?;
+                    Ok(Command::EvalSha {
+                        sha1: parts[1].to_lowercase(),
+                        keys,
+                        args,
+                    })
+                },
+                "SCRIPT" => {
+                    match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                        Some("LOAD") => Ok(Command::ScriptLoad {
+                            script: parts[2].to_string(),
+                        }),
+                        Some("EXISTS") if parts.len() > 2 => {
+                            Ok(Command::ScriptExists(parts[2..].iter().map(|s| s.to_lowercase()).collect()))
+                        }
+                        Some("FLUSH") if parts.len() == 2 => Ok(Command::ScriptFlush),
+                        _ => Err("SCRIPT subcommand must be LOAD, EXISTS or FLUSH".to_string()),
+                    }
+                },
+                "FUNCTION" => {
+                    match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                        Some("LOAD") => {
+                            let replace = parts.get(2).is_some_and(|s| s.eq_ignore_ascii_case("REPLACE"));
+                            let skip = if replace { 3 } else { 2 };
+                            if parts.len() != skip + 2 {
+                                return Err("FUNCTION LOAD requires LIBRARY and CODE".to_string());
+                            }
+                            Ok(Command::FunctionLoad {
+                                library: parts[skip].to_string(),
+                                code: parts[skip + 1].to_string(),
+                                replace,
+                            })
+                        }
+                        Some("DELETE") if parts.len() == 3 => Ok(Command::FunctionDelete {
+                            library: parts[2].to_string(),
+                        }),
+                        Some("LIST") if parts.len() == 2 => Ok(Command::FunctionList),
+                        Some("FLUSH") if parts.len() == 2 => Ok(Command::FunctionFlush),
+                        _ => Err("FUNCTION subcommand must be LOAD, DELETE, LIST or FLUSH".to_string()),
+                    }
+                },
+                "FCALL" => {
+                    let (keys, args) = decode_script_args(&args[2..])?;
+                    Ok(Command::FCall {
+                        function: parts[1].to_string(),
+                        keys,
+                        args,
+                    })
+                },
+                "TOUCH" => {
+                    Ok(Command::Touch(args[1..].to_vec()))
+                },
+                "SCAN" => {
+                    let cursor = parts[1].parse::<u64>()
+                        .map_err(|_| "Invalid cursor".to_string())?;
+                    let mut pattern = None;
+                    let mut count = None;
+                    let mut i = 2;
+                    while i < parts.len() {
+                        let value = args.get(i + 1).ok_or_else(|| format!("SCAN option {} requires a value", parts[i]))?;
+                        match parts[i].to_uppercase().as_str() {
+                            "MATCH" => pattern = Some(value.clone()),
+                            "COUNT" => {
+                                let n = parts[i + 1].parse::<usize>()
+                                    .ok()
+                                    .filter(|n| *n > 0)
+                                    .ok_or("COUNT must be a positive integer")?;
+                                count = Some(n);
+                            }
+                            other => return Err(format!("Unknown SCAN option: {}", other)),
+                        }
+                        i += 2;
+                    }
+                    Ok(Command::Scan { cursor, pattern, count })
+                },
+                "TYPE" => Ok(Command::Type {
+                    key: args[1].clone(),
+                }),
                 "EXPIRE" => {
-                    if parts.len() != 3 {
+                    if parts.len() != 3 && parts.len() != 4 {
                         return Err("EXPIRE command requires KEY and SECONDS".to_string());
                     }
                     let seconds = parts[2].parse::<u64>()
                         .map_err(|_| "Invalid seconds".to_string())?;
                     Ok(Command::Expire {
-                        key: parts[1].to_string(),
+                        key: args[1].clone(),
                         seconds,
+                        condition: decode_condition(&parts)?,
+                    })
+                },
+                "TTL" => Ok(Command::TTL {
+                    key: args[1].clone(),
+                }),
+                "PERSIST" => Ok(Command::Persist {
+                    key: args[1].clone(),
+                }),
+                "PEXPIRE" => {
+                    if parts.len() != 3 && parts.len() != 4 {
+                        return Err("PEXPIRE command requires KEY and MILLISECONDS".to_string());
+                    }
+                    let milliseconds = parts[2].parse::<u64>()
+                        .map_err(|_| "Invalid milliseconds".to_string())?;
+                    Ok(Command::PExpire {
+                        key: args[1].clone(),
+                        milliseconds,
+                        condition: decode_condition(&parts)?,
+                    })
+                },
+                "PTTL" => Ok(Command::PTTL {
+                    key: args[1].clone(),
+                }),
+                "EXPIREAT" | "PEXPIREAT" => {
+                    let name = cmd.to_uppercase();
+                    if parts.len() != 3 && parts.len() != 4 {
+                        return Err(format!("{} command requires KEY and TIMESTAMP", name));
+                    }
+                    let timestamp = parts[2].parse::<u64>()
+                        .map_err(|_| "Invalid timestamp".to_string())?;
+                    let key = args[1].clone();
+                    let condition = decode_condition(&parts)?;
+                    if name == "EXPIREAT" {
+                        Ok(Command::ExpireAt { key, timestamp, condition })
+                    } else {
+                        Ok(Command::PExpireAt { key, timestamp, condition })
+                    }
+                },
+                "OBJECT" => {
+                    match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                        Some("ENCODING") => Ok(Command::ObjectEncoding {
+                            key: args[2].clone(),
+                        }),
+                        Some("IDLETIME") => Ok(Command::ObjectIdleTime {
+                            key: args[2].clone(),
+                        }),
+                        Some(sub) => Err(format!("Unknown OBJECT subcommand: {}", sub)),
+                        None => Err("OBJECT command requires a subcommand".to_string()),
+                    }
+                },
+                "MEMORY" => {
+                    match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                        Some("USAGE") => {
+                            if parts.len() != 3 && parts.len() != 5 {
+                                return Err("MEMORY USAGE command requires KEY [SAMPLES count]".to_string());
+                            }
+                            let samples = if parts.len() == 5 {
+                                if !parts[3].eq_ignore_ascii_case("SAMPLES") {
+                                    return Err(format!("Unknown MEMORY USAGE option: {}", parts[3]));
+                                }
+                                Some(parts[4].parse::<usize>()
+                                    .map_err(|_| "Invalid SAMPLES count".to_string())?)
+                            } else {
+                                None
+                            };
+                            Ok(Command::MemoryUsage {
+                                key: args[2].clone(),
+                                samples,
+                            })
+                        }
+                        Some("STATS") => Ok(Command::MemoryStats),
+                        Some(sub) => Err(format!("Unknown MEMORY subcommand: {}", sub)),
+                        None => Err("MEMORY command requires a subcommand".to_string()),
+                    }
+                },
+                "GEOADD" => {
+                    if parts.len() < 5 || !(parts.len() - 2).is_multiple_of(3) {
+                        return Err("GEOADD command requires KEY and LONGITUDE LATITUDE MEMBER triples".to_string());
+                    }
+                    let mut members = Vec::new();
+                    for (chunk, raw) in parts[2..].chunks(3).zip(args[2..].chunks(3)) {
+                        let lon = chunk[0].parse::<f64>()
+                            .map_err(|_| "Invalid LONGITUDE".to_string())?;
+                        let lat = chunk[1].parse::<f64>()
+                            .map_err(|_| "Invalid LATITUDE".to_string())?;
+                        members.push((lon, lat, raw[2].clone()));
+                    }
+                    Ok(Command::GeoAdd {
+                        key: args[1].clone(),
+                        members,
+                    })
+                },
+                "GEODIST" => {
+                    if parts.len() != 4 && parts.len() != 5 {
+                        return Err("GEODIST command requires KEY, MEMBER1 and MEMBER2".to_string());
+                    }
+                    let unit = match parts.get(4) {
+                        Some(unit) => GeoUnit::parse(unit)
+                            .ok_or_else(|| "Unsupported unit, use m, km, ft or mi".to_string())?,
+                        None => GeoUnit::Meters,
+                    };
+                    Ok(Command::GeoDist {
+                        key: args[1].clone(),
+                        member1: args[2].clone(),
+                        member2: args[3].clone(),
+                        unit,
+                    })
+                },
+                "GEOPOS" => Ok(Command::GeoPos {
+                    key: args[1].clone(),
+                    members: args[2..].to_vec(),
+                }),
+                "GEOSEARCH" => Self::decode_geosearch(args, &parts),
+                "JSON.SET" => {
+                    let value = serde_json::from_str(parts[3])
+                        .map_err(|e| format!("Invalid JSON value: {}", e))?;
+                    Ok(Command::JsonSet {
+                        key: args[1].clone(),
+                        path: parts[2].to_string(),
+                        value,
                     })
                 },
-                "TTL" => {
-                    if parts.len() != 2 {
-                        return Err("TTL command requires KEY".to_string());
+                "JSON.GET" | "JSON.DEL" => {
+                    if parts.len() != 2 && parts.len() != 3 {
+                        return Err(format!("{} command requires KEY", parts[0].to_uppercase()));
+                    }
+                    let key = args[1].clone();
+                    let path = parts.get(2).unwrap_or(&"$").to_string();
+                    if cmd.eq_ignore_ascii_case("JSON.GET") {
+                        Ok(Command::JsonGet { key, path })
+                    } else {
+                        Ok(Command::JsonDel { key, path })
+                    }
+                },
+                "JSON.NUMINCRBY" => {
+                    let increment = parts[3].parse::<serde_json::Number>()
+                        .map_err(|_| "Invalid NUMBER".to_string())?;
+                    Ok(Command::JsonNumIncrBy {
+                        key: args[1].clone(),
+                        path: parts[2].to_string(),
+                        increment,
+                    })
+                },
+                "TS.CREATE" => {
+                    if parts.len() != 2 && parts.len() != 4 {
+                        return Err("TS.CREATE command requires KEY [RETENTION ms]".to_string());
+                    }
+                    let retention_ms = match parts.get(2) {
+                        Some(opt) if opt.eq_ignore_ascii_case("RETENTION") => Some(parts[3].parse::<u64>()
+                            .map_err(|_| "Invalid RETENTION".to_string())?),
+                        Some(opt) => return Err(format!("Unknown TS.CREATE option: {}", opt)),
+                        None => None,
+                    };
+                    Ok(Command::TsCreate {
+                        key: args[1].clone(),
+                        retention_ms,
+                    })
+                },
+                "TS.ADD" => {
+                    if parts.len() != 4 && parts.len() != 6 {
+                        return Err("TS.ADD command requires KEY, TIMESTAMP and VALUE".to_string());
+                    }
+                    let timestamp = match parts[2] {
+                        "*" => None,
+                        ts => Some(ts.parse::<u64>().map_err(|_| "Invalid TIMESTAMP".to_string())?),
+                    };
+                    let value = parts[3].parse::<f64>()
+                        .map_err(|_| "Invalid VALUE".to_string())?;
+                    let retention_ms = match parts.get(4) {
+                        Some(opt) if opt.eq_ignore_ascii_case("RETENTION") => Some(parts[5].parse::<u64>()
+                            .map_err(|_| "Invalid RETENTION".to_string())?),
+                        Some(opt) => return Err(format!("Unknown TS.ADD option: {}", opt)),
+                        None => None,
+                    };
+                    Ok(Command::TsAdd {
+                        key: args[1].clone(),
+                        timestamp,
+                        value,
+                        retention_ms,
+                    })
+                },
+                "TS.INCRBY" => {
+                    if parts.len() < 3 || !(parts.len() - 3).is_multiple_of(2) {
+                        return Err("TS.INCRBY command requires KEY and VALUE".to_string());
+                    }
+                    let value = parts[2].parse::<f64>()
+                        .map_err(|_| "Invalid VALUE".to_string())?;
+                    let mut timestamp = None;
+                    let mut retention_ms = None;
+                    for opt in parts[3..].chunks(2) {
+                        let arg = opt[1].parse::<u64>()
+                            .map_err(|_| format!("Invalid {}", opt[0].to_uppercase()))?;
+                        match opt[0].to_uppercase().as_str() {
+                            "TIMESTAMP" => timestamp = Some(arg),
+                            "RETENTION" => retention_ms = Some(arg),
+                            other => return Err(format!("Unknown TS.INCRBY option: {}", other)),
+                        }
                     }
-                    Ok(Command::TTL {
-                        key: parts[1].to_string(),
+                    Ok(Command::TsIncrBy {
+                        key: args[1].clone(),
+                        value,
+                        timestamp,
+                        retention_ms,
                     })
                 },
-                "PERSIST" => {
-                    if parts.len() != 2 {
-                        return Err("PERSIST command requires KEY".to_string());
+                "TS.RANGE" => {
+                    if parts.len() != 4 && parts.len() != 7 {
+                        return Err("TS.RANGE command requires KEY, FROM and TO".to_string());
                     }
-                    Ok(Command::Persist {
-                        key: parts[1].to_string(),
+                    let from = match parts[2] {
+                        "-" => 0,
+                        ts => ts.parse::<u64>().map_err(|_| "Invalid FROM timestamp".to_string())?,
+                    };
+                    let to = match parts[3] {
+                        "+" => u64::MAX,
+                        ts => ts.parse::<u64>().map_err(|_| "Invalid TO timestamp".to_string())?,
+                    };
+                    let aggregation = if parts.len() == 7 {
+                        if !parts[4].eq_ignore_ascii_case("AGGREGATION") {
+                            return Err(format!("Unknown TS.RANGE option: {}", parts[4]));
+                        }
+                        let agg = TsAggregation::parse(parts[5])
+                            .ok_or_else(|| "Unsupported aggregation, use avg, min, max, sum or count".to_string())?;
+                        let bucket = parts[6].parse::<u64>()
+                            .ok()
+                            .filter(|b| *b > 0)
+                            .ok_or_else(|| "Bucket duration must be a positive integer".to_string())?;
+                        Some((agg, bucket))
+                    } else {
+                        None
+                    };
+                    Ok(Command::TsRange {
+                        key: args[1].clone(),
+                        from,
+                        to,
+                        aggregation,
                     })
                 },
                 _ => Err(format!("Unknown command: {}", parts[0])),
@@ -414,6 +1905,96 @@ impl Protocol {
         }
     }
 
+    /// 解析 GEOSEARCH 命令的可选参数
+    fn decode_geosearch(args: &[Bytes], parts: &[&str]) -> Result<Command, String> {
+        let parse_f64 = |i: usize, name: &str| -> Result<f64, String> {
+            parts.get(i)
+                .ok_or_else(|| format!("Missing {}", name))?
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid {}", name))
+        };
+        let parse_unit = |i: usize| -> Result<GeoUnit, String> {
+            parts.get(i)
+                .and_then(|s| GeoUnit::parse(s))
+                .ok_or_else(|| "Unsupported unit, use m, km, ft or mi".to_string())
+        };
+
+        let mut origin = None;
+        let mut shape = None;
+        let mut ascending = None;
+        let mut count = None;
+        let mut with_coord = false;
+        let mut with_dist = false;
+
+        let mut i = 2;
+        while i < parts.len() {
+            match parts[i].to_uppercase().as_str() {
+                "FROMMEMBER" => {
+                    let member = args.get(i + 1).ok_or("Missing MEMBER")?;
+                    origin = Some(GeoOrigin::Member(member.clone()));
+                    i += 2;
+                }
+                "FROMLONLAT" => {
+                    origin = Some(GeoOrigin::LonLat(
+                        parse_f64(i + 1, "LONGITUDE")?,
+                        parse_f64(i + 2, "LATITUDE")?,
+                    ));
+                    i += 3;
+                }
+                "BYRADIUS" => {
+                    shape = Some(GeoShape::Radius {
+                        radius: parse_f64(i + 1, "RADIUS")?,
+                        unit: parse_unit(i + 2)?,
+                    });
+                    i += 3;
+                }
+                "BYBOX" => {
+                    shape = Some(GeoShape::Box {
+                        width: parse_f64(i + 1, "WIDTH")?,
+                        height: parse_f64(i + 2, "HEIGHT")?,
+                        unit: parse_unit(i + 3)?,
+                    });
+                    i += 4;
+                }
+                "ASC" => {
+                    ascending = Some(true);
+                    i += 1;
+                }
+                "DESC" => {
+                    ascending = Some(false);
+                    i += 1;
+                }
+                "COUNT" => {
+                    let n = parts.get(i + 1)
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .filter(|n| *n > 0)
+                        .ok_or("COUNT must be a positive integer")?;
+                    count = Some(n);
+                    i += 2;
+                }
+                "WITHCOORD" => {
+                    with_coord = true;
+                    i += 1;
+                }
+                "WITHDIST" => {
+                    with_dist = true;
+                    i += 1;
+                }
+                other => return Err(format!("Unknown GEOSEARCH option: {}", other)),
+            }
+        }
+
+        Ok(Command::GeoSearch {
+            key: args[1].clone(),
+            origin: origin.ok_or("GEOSEARCH requires FROMMEMBER or FROMLONLAT")?,
+            shape: shape.ok_or("GEOSEARCH requires BYRADIUS or BYBOX")?,
+            ascending,
+            count,
+            with_coord,
+            with_dist,
+        })
+    }
+
     /// 将响应编码为字符串格式
     /// 
     /// # Arguments
@@ -424,42 +2005,10 @@ impl Protocol {
     pub fn encode_response(resp: &Response) -> String {
         match resp {
             Response::Ok => "OK\n".to_string(),
-            Response::Value(value) => match value {
-                RedoxValue::String(s) => format!("{}\n", s),
-                RedoxValue::List(list) => format!("{}\n", list.join(" ")),
-                RedoxValue::Set(set) => format!("{}\n", set.iter()
-                    .map(|s| s.as_str())
-                    .collect::<Vec<_>>()
-                    .join(" ")),
-                RedoxValue::Hash(hash) => {
-                    let pairs: Vec<String> = hash
-                        .iter()
-                        .map(|(k, v)| format!("{} {}", k, v))
-                        .collect();
-                    format!("{}\n", pairs.join(" "))
-                },
-                RedoxValue::SortedSet(zset) => {
-                    let mut members: Vec<(&String, &f64)> = zset.iter().collect();
-                    // 按分数升序排序，分数相同时按成员字典序排序
-                    members.sort_by(|a, b| {
-                        a.1.partial_cmp(b.1)
-                            .unwrap_or(std::cmp::Ordering::Equal)
-                            .then(a.0.cmp(b.0))
-                    });
-                    let result: Vec<String> = members
-                        .iter()
-                        .map(|(member, score)| format!("{} {}", member, score))
-                        .collect();
-                    format!("{}\n", result.join(" "))
-                },
-            },
-            Response::Error(err) => format!("ERR {}\n", err),
-            Response::Array(items) => {
-                let items: Vec<String> = items.iter()
-                    .map(|item| item.as_ref().map(|s| s.to_string()).unwrap_or("NIL".to_string()))
-                    .collect();
-                format!("{}\n", items.join(" "))
-            },
+            Response::Nil => format!("{}\n", NIL),
+            Response::Value(RedoxValue::String(s)) => format!("{}\n", text(s)),
+            Response::Value(RedoxValue::Json(json)) => format!("{}\n", json),
+            Response::Error(err) => format!("{}\n", err),
             Response::Integer(value) => format!("{}\n", value),
             Response::Info(info) => {
                 let mut result = Vec::new();
@@ -469,6 +2018,466 @@ impl Protocol {
                 result.sort();  // 保证顺序一致
                 format!("{}\n", result.join(" "))  // 用空格分隔，一行输出
             },
+            Response::Map(fields) => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|(name, value)| format!("{} {}", name, Self::encode_response(value).trim_end()))
+                    .collect();
+                format!("{}\n", fields.join(" "))
+            },
+            // 元素按命令参数的规则加引号，可以用 `split_args` 拆分
+            Response::Cursor(cursor, items) if items.is_empty() => format!("{}\n", cursor),
+            Response::Cursor(cursor, items) => format!("{} {}\n", cursor, join_quoted(items)),
+            // 每个回复按命令参数的规则加引号后放在同一行，nil 为 `(nil)`
+            Response::Replies(replies) => {
+                let replies: Vec<Bytes> = replies.iter()
+                    .map(|reply| Bytes::from(Self::encode_response(reply).trim_end().to_string()))
+                    .collect();
+                format!("{}\n", join_quoted(&replies))
+            }
+            Response::Push { kind, items } if items.is_empty() => format!("{}\n", kind),
+            // 元素按命令参数的规则加引号后放在同一行
+            Response::Push { kind, items } => {
+                let items: Vec<Bytes> = items.iter().map(Self::encode_item).collect();
+                format!("{} {}\n", kind, join_quoted(&items))
+            }
+            // 集合类型的各个元素已经转换为文本，拼接后一定是合法的 UTF-8
+            _ => String::from_utf8_lossy(&Self::encode_chunks(resp, usize::MAX).collect::<Vec<_>>().concat()).into_owned(),
         }
     }
-} 
\ No newline at end of file
+
+    /// 编码推送消息的一个元素，去掉结尾的换行符；字符串保留原始字节，由调用者加引号
+    fn encode_item(item: &Response) -> Bytes {
+        match item {
+            Response::Value(RedoxValue::String(s)) => s.clone(),
+            item => Bytes::from(Self::encode_response(item).trim_end().to_string()),
+        }
+    }
+
+    /// 将响应分块编码为字符串格式，集合类型的回复按元素依次编码，不需要一次生成完整的回复
+    /// 
+    /// # Arguments
+    /// * `resp` - 要编码的响应
+    /// * `chunk_size` - 每块的目标大小，超过这个大小后开始新的一块
+    /// 
+    /// # Returns
+    /// 依次生成各块的迭代器，所有块拼接后与 `encode_response` 的结果相同
+    pub fn encode_chunks(resp: &Response, chunk_size: usize) -> Chunks<'_> {
+        // 元素之间以空格分隔，最后以换行符结尾
+        let mut started = false;
+        let mut separate = move |out: &mut Vec<u8>| {
+            if std::mem::replace(&mut started, true) {
+                out.push(b' ');
+            }
+        };
+        match resp {
+            Response::Array(items) => chunked(Vec::new(), items.iter(), chunk_size, b"\n", move |out, item| {
+                separate(out);
+                match item {
+                    Some(s) => out.extend_from_slice(text(s).as_bytes()),
+                    None => out.extend_from_slice(NIL.as_bytes()),
+                }
+            }),
+            Response::Value(RedoxValue::List(list)) => {
+                chunked(Vec::new(), list.iter(), chunk_size, b"\n", move |out, item| {
+                    separate(out);
+                    out.extend_from_slice(text(item).as_bytes());
+                })
+            }
+            Response::Value(RedoxValue::Set(set)) => {
+                chunked(Vec::new(), set.iter(), chunk_size, b"\n", move |out, member| {
+                    separate(out);
+                    out.extend_from_slice(text(member).as_bytes());
+                })
+            }
+            Response::Value(RedoxValue::Hash(hash)) => {
+                chunked(Vec::new(), hash.iter(), chunk_size, b"\n", move |out, (field, value)| {
+                    separate(out);
+                    out.extend_from_slice(format!("{} {}", text(field), text(value)).as_bytes());
+                })
+            }
+            Response::Value(RedoxValue::SortedSet(zset)) => {
+                // 按分数升序排列，分数相同时按成员字典序排列
+                chunked(Vec::new(), zset.iter(), chunk_size, b"\n", move |out, (member, score)| {
+                    separate(out);
+                    out.extend_from_slice(format!("{} {}", text(member), score).as_bytes());
+                })
+            }
+            Response::Value(RedoxValue::TimeSeries(ts)) => {
+                chunked(Vec::new(), ts.samples.iter(), chunk_size, b"\n", move |out, (timestamp, value)| {
+                    separate(out);
+                    out.extend_from_slice(format!("{} {}", timestamp, value).as_bytes());
+                })
+            }
+            _ => Box::new(std::iter::once(Self::encode_response(resp).into_bytes())),
+        }
+    }
+
+    /// 将行协议的一行响应解析为响应
+    /// 行协议不区分字符串和整数，也不保留集合的结构：`OK` 解析为 Ok，`(nil)` 解析为 Nil，
+    /// 以已知错误码开头的行解析为错误，整数解析为 Integer，其余内容解析为字符串
+    /// 
+    /// # Arguments
+    /// * `line` - 响应行，可以带有结尾的换行符
+    /// 
+    /// # Returns
+    /// 解析出的响应
+    pub fn decode_response(line: &str) -> Response {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        if line == "OK" {
+            return Response::Ok;
+        }
+        if line == NIL {
+            return Response::Nil;
+        }
+        if let Some(err) = RedoxError::try_parse(line) {
+            return Response::Error(err);
+        }
+        match line.parse::<i64>() {
+            Ok(n) if n.to_string() == line => Response::Integer(n),
+            _ => Response::Value(RedoxValue::string(line.to_string())),
+        }
+    }
+} 
+
+/// 解析 EXPIRE 系列命令第四个参数位置上的可选条件
+fn decode_condition(parts: &[&str]) -> Result<Option<ExpireCondition>, String> {
+    match parts.get(3) {
+        Some(flag) => ExpireCondition::parse(flag)
+            .map(Some)
+            .ok_or_else(|| format!("Unsupported option {}, use NX, XX, GT or LT", flag)),
+        None => Ok(None),
+    }
+}
+
+/// 解析 HEXPIRE、HTTL 和 HPERSIST 的字段列表
+/// 除了 `field [field ...]`，也接受 Redis 的 `FIELDS numfields field [field ...]` 写法
+fn decode_fields(args: &[Bytes]) -> Result<Vec<Bytes>, String> {
+    if let [keyword, numfields, fields @ ..] = args {
+        if keyword.eq_ignore_ascii_case(b"FIELDS") {
+            let numfields = std::str::from_utf8(numfields).ok().and_then(|n| n.parse::<usize>().ok());
+            if numfields == Some(fields.len()) {
+                return Ok(fields.to_vec());
+            }
+        }
+    }
+    if args.is_empty() {
+        return Err("At least one field is required".to_string());
+    }
+    Ok(args.to_vec())
+}
+
+/// 编码 EXPIRE 系列命令的可选条件，带前导空格
+fn encode_condition(condition: &Option<ExpireCondition>) -> String {
+    condition.map(|c| format!(" {}", c.as_str())).unwrap_or_default()
+}
+
+/// 跳过输入开头的若干个以空白分隔的词，返回剩余部分
+fn skip_tokens(input: &str, count: usize) -> &str {
+    let mut rest = input.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest.trim_end()
+}
+
+/// 把一行文本拆分为命令参数
+/// 大部分命令按空白拆分，参数可以用引号包围，见 `split_args`；
+/// PING、ECHO、JSON.SET 的最后一个参数取该行剩余的全部内容，PING 和 ECHO 的消息以引号开头时按引号解析
+fn split_line(input: &str) -> Result<Vec<Bytes>, String> {
+    let input = input.trim();
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    let word = |i: usize| parts.get(i).map(|s| s.to_uppercase()).unwrap_or_default();
+
+    // (在最后一个参数之前的词数, 最后一个参数是否可以用引号包围)
+    let (count, quoted) = match word(0).as_str() {
+        "PING" | "ECHO" => (1, true),
+        "JSON.SET" => (3, false),
+        _ => return split_args(input),
+    };
+    if parts.len() <= count {
+        return split_args(input);
+    }
+
+    let mut args: Vec<Bytes> = parts[..count].iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect();
+    let rest = skip_tokens(input, count);
+    if quoted && rest.starts_with(['"', '\'']) {
+        args.extend(split_args(rest)?);
+    } else {
+        args.push(Bytes::copy_from_slice(rest.as_bytes()));
+    }
+    Ok(args)
+}
+
+/// 按空白拆分参数，规则与 redis-cli 相同
+/// 双引号内支持 `\n`、`\r`、`\t`、`\b`、`\a`、`\\`、`\"` 和 `\xHH` 转义，单引号内只支持 `\'`；
+/// 右引号之后必须是空白或行尾
+pub fn split_args(input: &str) -> Result<Vec<Bytes>, String> {
+    let bytes = input.as_bytes();
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                loop {
+                    let Some(&b) = bytes.get(i) else {
+                        return Err("Unbalanced quotes in command".to_string());
+                    };
+                    i += 1;
+                    match b {
+                        b if b == quote => {
+                            if bytes.get(i).is_some_and(|b| !b.is_ascii_whitespace()) {
+                                return Err("Closing quote must be followed by a space".to_string());
+                            }
+                            break;
+                        }
+                        b'\\' if quote == b'"' && i < bytes.len() => {
+                            let (byte, len) = unescape(&bytes[i..]);
+                            arg.push(byte);
+                            i += len;
+                        }
+                        b'\\' if quote == b'\'' && bytes.get(i) == Some(&b'\'') => {
+                            arg.push(b'\'');
+                            i += 1;
+                        }
+                        b => arg.push(b),
+                    }
+                }
+            }
+            _ => {
+                while let Some(&b) = bytes.get(i).filter(|b| !b.is_ascii_whitespace()) {
+                    arg.push(b);
+                    i += 1;
+                }
+            }
+        }
+        args.push(Bytes::from(arg));
+    }
+}
+
+/// 解析双引号内反斜杠之后的转义序列
+///
+/// # Returns
+/// (转义得到的字节, 消耗的字节数)，未知的转义保留反斜杠之后的字符
+fn unescape(input: &[u8]) -> (u8, usize) {
+    match input[0] {
+        b'n' => (b'\n', 1),
+        b'r' => (b'\r', 1),
+        b't' => (b'\t', 1),
+        b'b' => (0x08, 1),
+        b'a' => (0x07, 1),
+        b'x' => input.get(1..3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .map(|byte| (byte, 3))
+            .unwrap_or((b'x', 1)),
+        other => (other, 1),
+    }
+}
+
+/// 编码单个参数，必要时用双引号包围并转义，`split_args` 可以还原出原始字节
+pub fn quote(arg: &[u8]) -> Cow<'_, str> {
+    let plain = !arg.is_empty()
+        && std::str::from_utf8(arg).is_ok_and(|s| {
+            !s.starts_with('\'') && s.chars().all(|c| !c.is_whitespace() && !c.is_control() && c != '"')
+        });
+    if plain {
+        return text(arg);
+    }
+
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for chunk in arg.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => quoted.push_str("\\\\"),
+                '"' => quoted.push_str("\\\""),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c if c.is_control() => {
+                    for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                        quoted.push_str(&format!("\\x{:02x}", byte));
+                    }
+                }
+                c => quoted.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            quoted.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// 解析 `numkeys [key ...] [arg ...]`
+fn decode_script_args(parts: &[Bytes]) -> Result<(Vec<Bytes>, Vec<Bytes>), String> {
+    let numkeys = parts.first()
+        .ok_or("NUMKEYS is required")?;
+    let numkeys = text(numkeys)
+        .parse::<usize>()
+        .map_err(|_| "Invalid NUMKEYS".to_string())?;
+    let mut keys = parts[1..].to_vec();
+    if numkeys > keys.len() {
+        return Err("Number of keys can't be greater than number of args".to_string());
+    }
+    let args = keys.split_off(numkeys);
+    Ok((keys, args))
+}
+
+/// 编码 `numkeys [key ...] [arg ...]`
+fn encode_script_args(keys: &[Bytes], args: &[Bytes]) -> String {
+    format!("{} {}", keys.len(), join_quoted(&[keys, args].concat()))
+}
+
+/// 分块编码的结果，依次生成回复的各个部分
+pub type Chunks<'a> = Box<dyn Iterator<Item = Vec<u8>> + Send + 'a>;
+
+/// 把集合的元素分批编码
+/// 
+/// # Arguments
+/// * `head` - 第一块开头的内容，如 RESP 的类型标记和长度
+/// * `items` - 集合的元素
+/// * `chunk_size` - 每块的目标大小，一块的内容超过这个大小后结束
+/// * `tail` - 最后一块结尾的内容
+/// * `write` - 把一个元素编码到当前块中
+pub(crate) fn chunked<'a, T: 'a>(
+    head: Vec<u8>,
+    mut items: impl Iterator<Item = T> + Send + 'a,
+    chunk_size: usize,
+    tail: &'static [u8],
+    mut write: impl FnMut(&mut Vec<u8>, T) + Send + 'a,
+) -> Chunks<'a> {
+    let mut head = Some(head);
+    let mut done = false;
+    Box::new(std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let mut out = head.take().unwrap_or_default();
+        while out.len() < chunk_size {
+            match items.next() {
+                Some(item) => write(&mut out, item),
+                None => {
+                    out.extend_from_slice(tail);
+                    done = true;
+                    break;
+                }
+            }
+        }
+        // 元素恰好在上一块结束时用完，且没有结尾内容
+        if out.is_empty() {
+            return None;
+        }
+        Some(out)
+    }))
+}
+
+/// 编码多个参数，以空格分隔
+fn join_quoted(items: &[Bytes]) -> String {
+    items.iter().map(|item| quote(item)).collect::<Vec<_>>().join(" ")
+}
+
+/// 解析 CLUSTER ADDSLOTS 和 DELSLOTS 的槽，同一个槽不能出现多次
+fn parse_slots(values: &[&str]) -> Result<Vec<u16>, String> {
+    let mut seen = vec![false; slot::SLOTS as usize];
+    let mut slots = Vec::with_capacity(values.len());
+    for value in values {
+        let slot = parse_slot(value)?;
+        if std::mem::replace(&mut seen[slot as usize], true) {
+            return Err(format!("Slot {} specified multiple times", slot));
+        }
+        slots.push(slot);
+    }
+    Ok(slots)
+}
+
+/// 解析 CLUSTER ADDSLOTSRANGE 和 DELSLOTSRANGE 的 `start end` 对，展开为槽的列表，范围不能重叠
+fn parse_slot_ranges(values: &[&str]) -> Result<Vec<u16>, String> {
+    if !values.len().is_multiple_of(2) {
+        return Err("Wrong number of arguments, slot ranges must be START END pairs".to_string());
+    }
+    let mut seen = vec![false; slot::SLOTS as usize];
+    let mut slots = Vec::new();
+    for pair in values.chunks(2) {
+        let (start, end) = (parse_slot(pair[0])?, parse_slot(pair[1])?);
+        if start > end {
+            return Err(format!("start slot number {} is greater than end slot number {}", start, end));
+        }
+        for slot in start..=end {
+            if std::mem::replace(&mut seen[slot as usize], true) {
+                return Err(format!("Slot {} specified multiple times", slot));
+            }
+            slots.push(slot);
+        }
+    }
+    Ok(slots)
+}
+
+/// 解析一个槽的编号
+fn parse_slot(value: &str) -> Result<u16, String> {
+    value.parse::<u16>()
+        .ok()
+        .filter(|&slot| slot < slot::SLOTS)
+        .ok_or_else(|| format!("Invalid or out of range slot: {}", value))
+}
+
+/// 解析 MIGRATE 的参数
+/// 只有一个数据库，destination-db 必须是 0；key 为空字符串时由 KEYS 选项指定多个键
+fn decode_migrate(parts: &[&str], args: &[Bytes]) -> Result<Command, String> {
+    if parts.len() < 6 {
+        return Err("MIGRATE command requires HOST, PORT, KEY, DESTINATION-DB and TIMEOUT".to_string());
+    }
+    let port = parts[2].parse::<u16>().map_err(|_| format!("Invalid port: {}", parts[2]))?;
+    if parts[4] != "0" {
+        return Err("DB index is out of range".to_string());
+    }
+    let timeout = parts[5].parse::<u64>().map_err(|_| "timeout is not an integer or out of range".to_string())?;
+    let (mut copy, mut replace, mut auth, mut keys) = (false, false, None, None);
+    let mut i = 6;
+    while i < parts.len() {
+        match parts[i].to_uppercase().as_str() {
+            "COPY" => copy = true,
+            "REPLACE" => replace = true,
+            "AUTH" if i + 1 < parts.len() => {
+                auth = Some((None, parts[i + 1].to_string()));
+                i += 1;
+            }
+            "AUTH2" if i + 2 < parts.len() => {
+                auth = Some((Some(parts[i + 1].to_string()), parts[i + 2].to_string()));
+                i += 2;
+            }
+            "KEYS" => {
+                if !args[3].is_empty() {
+                    return Err("When using MIGRATE KEYS option, the key argument must be set to the empty string".to_string());
+                }
+                keys = Some(args[i + 1..].to_vec());
+                break;
+            }
+            _ => return Err("syntax error".to_string()),
+        }
+        i += 1;
+    }
+    let keys = match keys {
+        Some(keys) if keys.is_empty() => return Err("MIGRATE KEYS option requires at least one key".to_string()),
+        Some(keys) => keys,
+        None if args[3].is_empty() => return Err("MIGRATE requires a key or the KEYS option".to_string()),
+        None => vec![args[3].clone()],
+    };
+    Ok(Command::Migrate { host: parts[1].to_string(), port, keys, timeout, copy, replace, auth })
+}
+
+/// 编码槽的列表，以空格分隔
+fn join_slots(slots: &[u16]) -> String {
+    slots.iter().map(|slot| slot.to_string()).collect::<Vec<_>>().join(" ")
+}
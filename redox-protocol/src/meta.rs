@@ -0,0 +1,647 @@
+//! 命令的元信息
+//! 每个命令的名称、参数个数、标志、所属类别和键的位置，解析请求时据此检查参数个数，
+//! 服务器据此做权限检查并回复 COMMAND；带子命令的命令以 `容器|子命令` 命名，如 `config|get`。
+//! 参数的写法、简介和加入的版本供客户端显示帮助，如 redox-cli 交互模式中的 HELP。
+
+use crate::Command;
+use bytes::Bytes;
+
+/// 命令类别，ACL 规则可以按类别允许或禁止命令，如 `+@read`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// 只读取数据的命令
+    Read,
+    /// 修改数据的命令
+    Write,
+    /// 读取或修改服务器状态的管理命令
+    Admin,
+    /// 连接相关的命令
+    Connection,
+    /// 脚本和函数命令
+    Scripting,
+    /// 发布订阅命令
+    PubSub,
+    /// 事务命令
+    Transaction,
+}
+
+impl Category {
+    /// 所有类别
+    pub const ALL: &'static [Category] = &[
+        Category::Read,
+        Category::Write,
+        Category::Admin,
+        Category::Connection,
+        Category::Scripting,
+        Category::PubSub,
+        Category::Transaction,
+    ];
+
+    /// 解析类别名称（不区分大小写）
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|category| category.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// 类别名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Read => "read",
+            Category::Write => "write",
+            Category::Admin => "admin",
+            Category::Connection => "connection",
+            Category::Scripting => "scripting",
+            Category::PubSub => "pubsub",
+            Category::Transaction => "transaction",
+        }
+    }
+}
+
+/// 一个命令的元信息
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// 命令名称（小写），子命令为 `容器|子命令`
+    pub name: &'static str,
+    /// 参数个数（包括命令名和子命令名），正数表示必须等于，负数表示至少为其绝对值
+    pub arity: i32,
+    /// 命令的标志，如 `write`、`readonly`、`admin`
+    pub flags: &'static [&'static str],
+    /// ACL 规则使用的类别
+    pub category: Category,
+    /// 第一个键的位置，0 表示没有键或键的位置不固定
+    pub first_key: i32,
+    /// 最后一个键的位置，-1 表示最后一个参数
+    pub last_key: i32,
+    /// 相邻两个键之间的距离
+    pub step: i32,
+    /// 命令名之后的参数的写法，如 `key value`
+    pub syntax: &'static str,
+    /// 一句话的简介
+    pub summary: &'static str,
+    /// 加入这个命令的版本
+    pub since: &'static str,
+}
+
+impl CommandSpec {
+    /// 创建命令的元信息
+    const fn new(
+        name: &'static str,
+        arity: i32,
+        flags: &'static [&'static str],
+        category: Category,
+        first_key: i32,
+        last_key: i32,
+        step: i32,
+    ) -> Self {
+        Self { name, arity, flags, category, first_key, last_key, step, syntax: "", summary: "", since: "" }
+    }
+
+    /// 设置帮助信息
+    ///
+    /// # Arguments
+    /// * `syntax` - 命令名之后的参数的写法
+    /// * `summary` - 一句话的简介
+    /// * `since` - 加入这个命令的版本
+    const fn doc(self, syntax: &'static str, summary: &'static str, since: &'static str) -> Self {
+        Self { syntax, summary, since, ..self }
+    }
+
+    /// 命令的完整写法，如 `CONFIG GET pattern`
+    pub fn usage(&self) -> String {
+        let name = self.name.replace('|', " ").to_uppercase();
+        if self.syntax.is_empty() {
+            name
+        } else {
+            format!("{} {}", name, self.syntax)
+        }
+    }
+
+    /// 参数个数（包括命令名）是否符合要求
+    pub fn accepts(&self, len: usize) -> bool {
+        let arity = self.arity.unsigned_abs() as usize;
+        if self.arity >= 0 {
+            len == arity
+        } else {
+            len >= arity
+        }
+    }
+
+    /// 命令是否带有指定的标志
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+}
+
+/// 读取数据的命令
+const READONLY: &[&str] = &["readonly"];
+/// 修改数据的命令
+const WRITE: &[&str] = &["write"];
+/// 可能增加内存占用的命令，内存超过上限时先淘汰键或被拒绝
+const DENYOOM: &[&str] = &["write", "denyoom"];
+/// 管理命令，不能在脚本中使用
+const ADMIN: &[&str] = &["admin", "noscript"];
+/// 只读取服务器状态的管理命令
+const LOADING: &[&str] = &["admin", "loading"];
+/// 连接命令，不能在脚本中使用
+const CONNECTION: &[&str] = &["fast", "noscript"];
+/// 管理脚本和函数的命令
+const SCRIPTING: &[&str] = &["noscript"];
+/// 发布订阅命令，不能在脚本中使用
+const PUBSUB: &[&str] = &["pubsub", "noscript"];
+
+/// 事务命令的标志
+const TRANSACTION: &[&str] = &["noscript", "fast"];
+
+/// EXEC 的标志，执行时间取决于排队的命令
+const EXEC: &[&str] = &["noscript"];
+/// 阻塞连接直到条件满足的命令，不能在脚本中使用
+const BLOCKING: &[&str] = &["noscript", "blocking"];
+/// 只读取集群状态的命令，不能在脚本中使用
+const CLUSTER_STATE: &[&str] = &["noscript", "loading", "stale"];
+/// MIGRATE，使用 KEYS 选项时键在命令的最后
+const MIGRATE: &[&str] = &["write", "movablekeys"];
+/// 执行脚本和函数的命令，键由 numkeys 参数指定
+const MOVABLE_KEYS: &[&str] = &["noscript", "movablekeys"];
+
+/// 所有命令的元信息
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("auth", -2, CONNECTION, Category::Connection, 0, 0, 0).doc("[username] password", "Authenticate the connection", "0.1.0"),
+    CommandSpec::new("ping", -1, CONNECTION, Category::Connection, 0, 0, 0).doc("[message]", "Ping the server", "0.1.0"),
+    CommandSpec::new("echo", 2, CONNECTION, Category::Connection, 0, 0, 0).doc("message", "Return the given message", "0.1.0"),
+    CommandSpec::new("reset", 1, CONNECTION, Category::Connection, 0, 0, 0).doc("", "Reset the connection to its initial state", "0.1.0"),
+    CommandSpec::new("hello", -1, CONNECTION, Category::Connection, 0, 0, 0).doc("[protover [AUTH username password]]", "Handshake with the server and select the protocol version", "0.1.0"),
+    CommandSpec::new("set", 3, DENYOOM, Category::Write, 1, 1, 1).doc("key value", "Set the string value of a key", "0.1.0"),
+    CommandSpec::new("get", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the value of a key", "0.1.0"),
+    CommandSpec::new("getex", -2, WRITE, Category::Write, 1, 1, 1).doc("key [EX seconds|PX milliseconds|EXAT timestamp|PXAT timestamp|PERSIST]", "Get the value of a key and optionally set its expiration", "0.1.0"),
+    CommandSpec::new("cas", 4, DENYOOM, Category::Write, 1, 1, 1).doc("key expected value", "Set the value of a key only if it currently equals the expected value", "0.1.0"),
+    CommandSpec::new("strlen", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the length of the value stored in a key", "0.1.0"),
+    CommandSpec::new("lpush", 3, DENYOOM, Category::Write, 1, 1, 1).doc("key value", "Prepend a value to a list", "0.1.0"),
+    CommandSpec::new("rpush", 3, DENYOOM, Category::Write, 1, 1, 1).doc("key value", "Append a value to a list", "0.1.0"),
+    CommandSpec::new("lpop", 2, WRITE, Category::Write, 1, 1, 1).doc("key", "Remove and get the first element of a list", "0.1.0"),
+    CommandSpec::new("rpop", 2, WRITE, Category::Write, 1, 1, 1).doc("key", "Remove and get the last element of a list", "0.1.0"),
+    CommandSpec::new("lrange", 4, READONLY, Category::Read, 1, 1, 1).doc("key start stop", "Get a range of elements from a list", "0.1.0"),
+    CommandSpec::new("llen", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the length of a list", "0.1.0"),
+    CommandSpec::new("sadd", 3, DENYOOM, Category::Write, 1, 1, 1).doc("key member", "Add a member to a set", "0.1.0"),
+    CommandSpec::new("srem", 3, WRITE, Category::Write, 1, 1, 1).doc("key member", "Remove a member from a set", "0.1.0"),
+    CommandSpec::new("smembers", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get all the members of a set", "0.1.0"),
+    CommandSpec::new("sismember", 3, READONLY, Category::Read, 1, 1, 1).doc("key member", "Determine if a value is a member of a set", "0.1.0"),
+    CommandSpec::new("scard", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the number of members in a set", "0.1.0"),
+    CommandSpec::new("hset", -4, DENYOOM, Category::Write, 1, 1, 1).doc("key field value [field value ...]", "Set the values of one or more hash fields", "0.1.0"),
+    CommandSpec::new("hget", 3, READONLY, Category::Read, 1, 1, 1).doc("key field", "Get the value of a hash field", "0.1.0"),
+    CommandSpec::new("hgetall", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get all the fields and values of a hash", "0.1.0"),
+    CommandSpec::new("hdel", 3, WRITE, Category::Write, 1, 1, 1).doc("key field", "Delete a hash field", "0.1.0"),
+    CommandSpec::new("hlen", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the number of fields in a hash", "0.1.0"),
+    CommandSpec::new("hexpire", -4, WRITE, Category::Write, 1, 1, 1).doc("key seconds field [field ...]", "Set the expiration of hash fields", "0.1.0"),
+    CommandSpec::new("httl", -3, READONLY, Category::Read, 1, 1, 1).doc("key field [field ...]", "Get the remaining time to live of hash fields", "0.1.0"),
+    CommandSpec::new("hpersist", -3, WRITE, Category::Write, 1, 1, 1).doc("key field [field ...]", "Remove the expiration of hash fields", "0.1.0"),
+    CommandSpec::new("zadd", 4, DENYOOM, Category::Write, 1, 1, 1).doc("key score member", "Add a member to a sorted set, or update its score", "0.1.0"),
+    CommandSpec::new("zrem", 3, WRITE, Category::Write, 1, 1, 1).doc("key member", "Remove a member from a sorted set", "0.1.0"),
+    CommandSpec::new("zrange", 4, READONLY, Category::Read, 1, 1, 1).doc("key start stop", "Get a range of members of a sorted set by index", "0.1.0"),
+    CommandSpec::new("zrangebyscore", 4, READONLY, Category::Read, 1, 1, 1).doc("key min max", "Get the members of a sorted set with scores in a range", "0.1.0"),
+    CommandSpec::new("zcard", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the number of members in a sorted set", "0.1.0"),
+    CommandSpec::new("mset", -3, DENYOOM, Category::Write, 1, -1, 2).doc("key value [key value ...]", "Set multiple keys to multiple values", "0.1.0"),
+    CommandSpec::new("mget", -2, READONLY, Category::Read, 1, -1, 1).doc("key [key ...]", "Get the values of multiple keys", "0.1.0"),
+    CommandSpec::new("info", -1, LOADING, Category::Admin, 0, 0, 0).doc("", "Get information and statistics about the server", "0.1.0"),
+    CommandSpec::new("config|get", 3, ADMIN, Category::Admin, 0, 0, 0).doc("pattern", "Get the values of configuration parameters", "0.1.0"),
+    CommandSpec::new("config|set", -4, ADMIN, Category::Admin, 0, 0, 0).doc("parameter value [parameter value ...]", "Set configuration parameters at runtime", "0.1.0"),
+    CommandSpec::new("acl|setuser", -3, ADMIN, Category::Admin, 0, 0, 0).doc("username [rule ...]", "Create or modify an ACL user", "0.1.0"),
+    CommandSpec::new("acl|getuser", 3, ADMIN, Category::Admin, 0, 0, 0).doc("username", "Get the rules of an ACL user", "0.1.0"),
+    CommandSpec::new("acl|deluser", -3, ADMIN, Category::Admin, 0, 0, 0).doc("username [username ...]", "Delete ACL users", "0.1.0"),
+    CommandSpec::new("acl|list", 2, ADMIN, Category::Admin, 0, 0, 0).doc("", "List the rules of all ACL users", "0.1.0"),
+    CommandSpec::new("acl|users", 2, ADMIN, Category::Admin, 0, 0, 0).doc("", "List the names of all ACL users", "0.1.0"),
+    CommandSpec::new("acl|whoami", 2, CONNECTION, Category::Connection, 0, 0, 0).doc("", "Get the name of the user the connection is authenticated as", "0.1.0"),
+    CommandSpec::new("acl|cat", -2, CONNECTION, Category::Connection, 0, 0, 0).doc("[category]", "List the ACL categories, or the commands in a category", "0.1.0"),
+    CommandSpec::new("acl|load", 2, ADMIN, Category::Admin, 0, 0, 0).doc("", "Reload the ACL users from the ACL file", "0.1.0"),
+    CommandSpec::new("acl|save", 2, ADMIN, Category::Admin, 0, 0, 0).doc("", "Save the ACL users to the ACL file", "0.1.0"),
+    CommandSpec::new("client|id", 2, CONNECTION, Category::Connection, 0, 0, 0).doc("", "Get the id of the connection", "0.1.0"),
+    CommandSpec::new("client|list", 2, ADMIN, Category::Admin, 0, 0, 0).doc("", "List the client connections", "0.1.0"),
+    CommandSpec::new("client|getname", 2, CONNECTION, Category::Connection, 0, 0, 0).doc("", "Get the name of the connection", "0.1.0"),
+    CommandSpec::new("client|setname", 3, CONNECTION, Category::Connection, 0, 0, 0).doc("name", "Set the name of the connection", "0.1.0"),
+    CommandSpec::new("client|kill", -3, ADMIN, Category::Admin, 0, 0, 0).doc("[ID id] [ADDR ip:port]", "Close client connections", "0.1.0"),
+    CommandSpec::new("client|tracking", 3, CONNECTION, Category::Connection, 0, 0, 0).doc("ON|OFF", "Enable or disable server assisted client side caching", "0.1.0"),
+    CommandSpec::new("command", -1, CONNECTION, Category::Connection, 0, 0, 0).doc("", "Get details about all commands", "0.1.0"),
+    CommandSpec::new("command|count", 2, CONNECTION, Category::Connection, 0, 0, 0).doc("", "Get the number of commands", "0.1.0"),
+    CommandSpec::new("command|info", -2, CONNECTION, Category::Connection, 0, 0, 0).doc("[name ...]", "Get details about specific commands", "0.1.0"),
+    CommandSpec::new("debug|sleep", 3, ADMIN, Category::Admin, 0, 0, 0).doc("seconds", "Block the server for a number of seconds", "0.1.0"),
+    CommandSpec::new("debug|object", 3, ADMIN, Category::Admin, 2, 2, 1).doc("key", "Get debugging information about a key", "0.1.0"),
+    CommandSpec::new("debug|set-active-expire", 3, ADMIN, Category::Admin, 0, 0, 0).doc("0|1", "Enable or disable the active expiration of keys", "0.1.0"),
+    CommandSpec::new("debug|quicksave", 2, ADMIN, Category::Admin, 0, 0, 0).doc("", "Save the dataset to disk synchronously", "0.1.0"),
+    CommandSpec::new("debug|set-time", 3, ADMIN, Category::Admin, 0, 0, 0).doc("milliseconds", "Set the manual clock of the server to a Unix time in milliseconds", "0.1.0"),
+    CommandSpec::new("replicaof", 3, ADMIN, Category::Admin, 0, 0, 0).doc("host port | NO ONE", "Make the server a replica of another server, or promote it to a master", "0.1.0"),
+    CommandSpec::new("sync", -1, ADMIN, Category::Admin, 0, 0, 0).doc("[listening-port]", "Internal command used by replicas to synchronize with the master", "0.1.0"),
+    CommandSpec::new("peersync", 2, ADMIN, Category::Admin, 0, 0, 0).doc("name", "Internal command used by cross data center replication links", "0.1.0"),
+    CommandSpec::new("monitor", 1, ADMIN, Category::Admin, 0, 0, 0).doc("", "Stream every command processed by the server", "0.1.0"),
+    CommandSpec::new("wait", 3, BLOCKING, Category::Connection, 0, 0, 0).doc("numreplicas timeout", "Wait until previous writes are acknowledged by replicas", "0.1.0"),
+    CommandSpec::new("subscribe", -2, PUBSUB, Category::PubSub, 0, 0, 0).doc("channel [channel ...]", "Listen for messages published to channels", "0.1.0"),
+    CommandSpec::new("psubscribe", -2, PUBSUB, Category::PubSub, 0, 0, 0).doc("pattern [pattern ...]", "Listen for messages published to channels matching patterns", "0.1.0"),
+    CommandSpec::new("unsubscribe", -1, PUBSUB, Category::PubSub, 0, 0, 0).doc("[channel ...]", "Stop listening for messages posted to channels", "0.1.0"),
+    CommandSpec::new("punsubscribe", -1, PUBSUB, Category::PubSub, 0, 0, 0).doc("[pattern ...]", "Stop listening for messages posted to channels matching patterns", "0.1.0"),
+    CommandSpec::new("publish", 3, PUBSUB, Category::PubSub, 0, 0, 0).doc("channel message", "Post a message to a channel", "0.1.0"),
+    CommandSpec::new("multi", 1, TRANSACTION, Category::Transaction, 0, 0, 0).doc("", "Start a transaction", "0.1.0"),
+    CommandSpec::new("exec", 1, EXEC, Category::Transaction, 0, 0, 0).doc("", "Execute all commands queued in a transaction", "0.1.0"),
+    CommandSpec::new("discard", 1, TRANSACTION, Category::Transaction, 0, 0, 0).doc("", "Discard all commands queued in a transaction", "0.1.0"),
+    CommandSpec::new("watch", -2, TRANSACTION, Category::Transaction, 1, -1, 1).doc("key [key ...]", "Abort the next transaction if any of the keys is modified", "0.1.0"),
+    CommandSpec::new("unwatch", 1, TRANSACTION, Category::Transaction, 0, 0, 0).doc("", "Forget all watched keys", "0.1.0"),
+    CommandSpec::new("sentinel|get-master-addr-by-name", 3, ADMIN, Category::Admin, 0, 0, 0).doc("name", "Get the address of a monitored master", "0.1.0"),
+    CommandSpec::new("sentinel|master", 3, ADMIN, Category::Admin, 0, 0, 0).doc("name", "Get the state of a monitored master", "0.1.0"),
+    CommandSpec::new("sentinel|replicas", 3, ADMIN, Category::Admin, 0, 0, 0).doc("name", "List the replicas of a monitored master", "0.1.0"),
+    CommandSpec::new("sentinel|is-master-down-by-addr", 6, ADMIN, Category::Admin, 0, 0, 0).doc("ip port current-epoch runid", "Ask a sentinel whether it considers a master down", "0.1.0"),
+    CommandSpec::new("sentinel|failover", 3, ADMIN, Category::Admin, 0, 0, 0).doc("name", "Force a failover of a monitored master", "0.1.0"),
+    CommandSpec::new("cluster|meet", -4, ADMIN, Category::Admin, 0, 0, 0).doc("ip port", "Add a node to the cluster", "0.1.0"),
+    CommandSpec::new("cluster|addslots", -3, ADMIN, Category::Admin, 0, 0, 0).doc("slot [slot ...]", "Assign hash slots to the node", "0.1.0"),
+    CommandSpec::new("cluster|addslotsrange", -4, ADMIN, Category::Admin, 0, 0, 0).doc("start end [start end ...]", "Assign ranges of hash slots to the node", "0.1.0"),
+    CommandSpec::new("cluster|delslots", -3, ADMIN, Category::Admin, 0, 0, 0).doc("slot [slot ...]", "Remove hash slots from the node", "0.1.0"),
+    CommandSpec::new("cluster|delslotsrange", -4, ADMIN, Category::Admin, 0, 0, 0).doc("start end [start end ...]", "Remove ranges of hash slots from the node", "0.1.0"),
+    CommandSpec::new("cluster|forget", 3, ADMIN, Category::Admin, 0, 0, 0).doc("node-id", "Remove a node from the node table", "0.1.0"),
+    CommandSpec::new("cluster|keyslot", 3, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("key", "Get the hash slot of a key", "0.1.0"),
+    CommandSpec::new("cluster|myid", 2, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("", "Get the id of the node", "0.1.0"),
+    CommandSpec::new("cluster|info", 2, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("", "Get information about the state of the cluster", "0.1.0"),
+    CommandSpec::new("cluster|nodes", 2, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("", "Get the cluster configuration as seen by the node", "0.1.0"),
+    CommandSpec::new("cluster|slots", 2, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("", "Get the mapping of hash slots to nodes", "0.1.0"),
+    CommandSpec::new("cluster|shards", 2, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("", "Get the shards of the cluster", "0.1.0"),
+    CommandSpec::new("cluster|replicas", 3, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("node-id", "List the replicas of a master node", "0.1.0"),
+    CommandSpec::new("cluster|replicate", 3, ADMIN, Category::Admin, 0, 0, 0).doc("node-id", "Make the node a replica of a master node", "0.1.0"),
+    CommandSpec::new("cluster|setslot", -4, ADMIN, Category::Admin, 0, 0, 0).doc("slot IMPORTING node-id | MIGRATING node-id | NODE node-id | STABLE", "Change the state of a hash slot during resharding", "0.1.0"),
+    CommandSpec::new("cluster|getkeysinslot", 4, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("slot count", "Get key names in a hash slot", "0.1.0"),
+    CommandSpec::new("cluster|countkeysinslot", 3, CLUSTER_STATE, Category::Connection, 0, 0, 0).doc("slot", "Get the number of keys in a hash slot", "0.1.0"),
+    CommandSpec::new("cluster|bus", 2, ADMIN, Category::Admin, 0, 0, 0).doc("", "Internal command that turns the connection into a cluster bus link", "0.1.0"),
+    CommandSpec::new("asking", 1, CONNECTION, Category::Connection, 0, 0, 0).doc("", "Allow the next command to access a slot being imported", "0.1.0"),
+    CommandSpec::new("del", -2, WRITE, Category::Write, 1, -1, 1).doc("key [key ...]", "Delete keys", "0.1.0"),
+    CommandSpec::new("unlink", -2, WRITE, Category::Write, 1, -1, 1).doc("key [key ...]", "Delete keys, freeing large values in the background", "0.1.0"),
+    CommandSpec::new("touch", -2, READONLY, Category::Read, 1, -1, 1).doc("key [key ...]", "Update the last access time of keys", "0.1.0"),
+    CommandSpec::new("scan", -2, READONLY, Category::Read, 0, 0, 0).doc("cursor [MATCH pattern] [COUNT count]", "Incrementally iterate the keyspace", "0.1.0"),
+    CommandSpec::new("type", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the type of the value stored in a key", "0.1.0"),
+    CommandSpec::new("flushall", -1, WRITE, Category::Write, 0, 0, 0).doc("[ASYNC|SYNC]", "Delete all keys", "0.1.0"),
+    CommandSpec::new("dump", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get a serialized version of the value stored in a key", "0.1.0"),
+    CommandSpec::new("restore", -4, DENYOOM, Category::Write, 1, 1, 1).doc("key ttl payload [REPLACE]", "Create a key from a serialized value", "0.1.0"),
+    CommandSpec::new("migrate", -6, MIGRATE, Category::Write, 3, 3, 1).doc("host port key|\"\" db timeout [COPY] [REPLACE] [AUTH password | AUTH2 username password] [KEYS key [key ...]]", "Atomically move keys to another server", "0.1.0"),
+    CommandSpec::new("expire", -3, WRITE, Category::Write, 1, 1, 1).doc("key seconds [NX|XX|GT|LT]", "Set the expiration of a key in seconds", "0.1.0"),
+    CommandSpec::new("ttl", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the remaining time to live of a key in seconds", "0.1.0"),
+    CommandSpec::new("persist", 2, WRITE, Category::Write, 1, 1, 1).doc("key", "Remove the expiration of a key", "0.1.0"),
+    CommandSpec::new("pexpire", -3, WRITE, Category::Write, 1, 1, 1).doc("key milliseconds [NX|XX|GT|LT]", "Set the expiration of a key in milliseconds", "0.1.0"),
+    CommandSpec::new("pttl", 2, READONLY, Category::Read, 1, 1, 1).doc("key", "Get the remaining time to live of a key in milliseconds", "0.1.0"),
+    CommandSpec::new("expireat", -3, WRITE, Category::Write, 1, 1, 1).doc("key timestamp [NX|XX|GT|LT]", "Set the expiration of a key as a Unix timestamp in seconds", "0.1.0"),
+    CommandSpec::new("pexpireat", -3, WRITE, Category::Write, 1, 1, 1).doc("key timestamp [NX|XX|GT|LT]", "Set the expiration of a key as a Unix timestamp in milliseconds", "0.1.0"),
+    CommandSpec::new("object|encoding", 3, READONLY, Category::Read, 2, 2, 1).doc("key", "Get the internal encoding of the value stored in a key", "0.1.0"),
+    CommandSpec::new("object|idletime", 3, READONLY, Category::Read, 2, 2, 1).doc("key", "Get the number of seconds since a key was last accessed", "0.1.0"),
+    CommandSpec::new("memory|usage", -3, READONLY, Category::Read, 2, 2, 1).doc("key [SAMPLES count]", "Estimate the memory used by a key and its value", "0.1.0"),
+    CommandSpec::new("memory|stats", 2, LOADING, Category::Admin, 0, 0, 0).doc("", "Get memory usage statistics of the server", "0.1.0"),
+    CommandSpec::new("geoadd", -5, DENYOOM, Category::Write, 1, 1, 1).doc("key longitude latitude member [longitude latitude member ...]", "Add geospatial members to a sorted set", "0.1.0"),
+    CommandSpec::new("geodist", -4, READONLY, Category::Read, 1, 1, 1).doc("key member1 member2 [m|km|mi|ft]", "Get the distance between two geospatial members", "0.1.0"),
+    CommandSpec::new("geopos", -3, READONLY, Category::Read, 1, 1, 1).doc("key member [member ...]", "Get the longitude and latitude of geospatial members", "0.1.0"),
+    CommandSpec::new("geosearch", -7, READONLY, Category::Read, 1, 1, 1).doc("key FROMMEMBER member|FROMLONLAT longitude latitude BYRADIUS radius unit|BYBOX width height unit [ASC|DESC] [COUNT n] [WITHCOORD] [WITHDIST]", "Find geospatial members within a radius or box", "0.1.0"),
+    CommandSpec::new("json.set", 4, DENYOOM, Category::Write, 1, 1, 1).doc("key path value", "Set a JSON value at a path", "0.1.0"),
+    CommandSpec::new("json.get", -2, READONLY, Category::Read, 1, 1, 1).doc("key [path]", "Get the JSON value at a path", "0.1.0"),
+    CommandSpec::new("json.del", -2, WRITE, Category::Write, 1, 1, 1).doc("key [path]", "Delete the JSON value at a path", "0.1.0"),
+    CommandSpec::new("json.numincrby", 4, DENYOOM, Category::Write, 1, 1, 1).doc("key path number", "Increment the number at a path", "0.1.0"),
+    CommandSpec::new("ts.create", -2, DENYOOM, Category::Write, 1, 1, 1).doc("key [RETENTION ms]", "Create a time series", "0.1.0"),
+    CommandSpec::new("ts.add", -4, DENYOOM, Category::Write, 1, 1, 1).doc("key timestamp|* value [RETENTION ms]", "Add a sample to a time series", "0.1.0"),
+    CommandSpec::new("ts.incrby", -3, DENYOOM, Category::Write, 1, 1, 1).doc("key value [TIMESTAMP ts] [RETENTION ms]", "Increment the latest sample of a time series", "0.1.0"),
+    CommandSpec::new("ts.range", -4, READONLY, Category::Read, 1, 1, 1).doc("key from|- to|+ [AGGREGATION avg|min|max|sum|count bucket_ms]", "Query a range of samples, optionally aggregated", "0.1.0"),
+    CommandSpec::new("eval", -3, MOVABLE_KEYS, Category::Scripting, 0, 0, 0).doc("script numkeys [key ...] [arg ...]", "Execute a Lua script", "0.1.0"),
+    CommandSpec::new("evalsha", -3, MOVABLE_KEYS, Category::Scripting, 0, 0, 0).doc("sha1 numkeys [key ...] [arg ...]", "Execute a cached Lua script by its SHA1 digest", "0.1.0"),
+    CommandSpec::new("script|load", 3, SCRIPTING, Category::Scripting, 0, 0, 0).doc("script", "Load a Lua script into the script cache", "0.1.0"),
+    CommandSpec::new("script|exists", -3, SCRIPTING, Category::Scripting, 0, 0, 0).doc("sha1 [sha1 ...]", "Determine whether scripts exist in the script cache", "0.1.0"),
+    CommandSpec::new("script|flush", 2, SCRIPTING, Category::Scripting, 0, 0, 0).doc("", "Remove all scripts from the script cache", "0.1.0"),
+    CommandSpec::new("function|load", -4, SCRIPTING, Category::Scripting, 0, 0, 0).doc("[REPLACE] library code", "Load a function library", "0.1.0"),
+    CommandSpec::new("function|delete", 3, SCRIPTING, Category::Scripting, 0, 0, 0).doc("library", "Delete a function library", "0.1.0"),
+    CommandSpec::new("function|list", 2, SCRIPTING, Category::Scripting, 0, 0, 0).doc("", "List the function libraries", "0.1.0"),
+    CommandSpec::new("function|flush", 2, SCRIPTING, Category::Scripting, 0, 0, 0).doc("", "Delete all function libraries", "0.1.0"),
+    CommandSpec::new("fcall", -3, MOVABLE_KEYS, Category::Scripting, 0, 0, 0).doc("function numkeys [key ...] [arg ...]", "Call a function", "0.1.0"),
+];
+
+/// 按名称查找命令，名称不区分大小写，子命令写作 `容器|子命令`
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+/// 查找请求对应的命令：带子命令的命令按前两个参数查找
+///
+/// # Arguments
+/// * `parts` - 命令名和参数
+///
+/// # Returns
+/// 命令的元信息，命令或子命令未知时为 None
+pub fn lookup_request(parts: &[&str]) -> Option<&'static CommandSpec> {
+    let name = parts.first()?;
+    let subcommand = COMMANDS.iter().find(|spec| {
+        spec.name.split_once('|').is_some_and(|(container, sub)| {
+            container.eq_ignore_ascii_case(name) && parts.get(1).is_some_and(|s| sub.eq_ignore_ascii_case(s))
+        })
+    });
+    subcommand.or_else(|| lookup(name))
+}
+
+/// 名称是否是已知的命令，或带子命令的命令的容器名称（如 `config`）
+pub fn is_command(name: &str) -> bool {
+    COMMANDS.iter().any(|spec| {
+        spec.name == name || spec.name.strip_prefix(name).is_some_and(|rest| rest.starts_with('|'))
+    })
+}
+
+impl Command {
+    /// 命令名称（小写）
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Auth { .. } => "auth",
+            Command::Ping { .. } => "ping",
+            Command::Echo { .. } => "echo",
+            Command::Reset => "reset",
+            Command::Hello { .. } => "hello",
+            Command::Set { .. } => "set",
+            Command::Get { .. } => "get",
+            Command::GetEx { .. } => "getex",
+            Command::Cas { .. } => "cas",
+            Command::StrLen { .. } => "strlen",
+            Command::LPush { .. } => "lpush",
+            Command::RPush { .. } => "rpush",
+            Command::LPop { .. } => "lpop",
+            Command::RPop { .. } => "rpop",
+            Command::LRange { .. } => "lrange",
+            Command::LLen { .. } => "llen",
+            Command::SAdd { .. } => "sadd",
+            Command::SRem { .. } => "srem",
+            Command::SMembers { .. } => "smembers",
+            Command::SIsMember { .. } => "sismember",
+            Command::SCard { .. } => "scard",
+            Command::HSet { .. } => "hset",
+            Command::HGet { .. } => "hget",
+            Command::HGetAll { .. } => "hgetall",
+            Command::HDel { .. } => "hdel",
+            Command::HLen { .. } => "hlen",
+            Command::HExpire { .. } => "hexpire",
+            Command::HTTL { .. } => "httl",
+            Command::HPersist { .. } => "hpersist",
+            Command::ZAdd { .. } => "zadd",
+            Command::ZRem { .. } => "zrem",
+            Command::ZRange { .. } => "zrange",
+            Command::ZRangeByScore { .. } => "zrangebyscore",
+            Command::ZCard { .. } => "zcard",
+            Command::MSet(_) => "mset",
+            Command::MGet(_) => "mget",
+            Command::Info => "info",
+            Command::ConfigGet { .. } => "config|get",
+            Command::ConfigSet(_) => "config|set",
+            Command::AclSetUser { .. } => "acl|setuser",
+            Command::AclGetUser { .. } => "acl|getuser",
+            Command::AclDelUser(_) => "acl|deluser",
+            Command::AclList => "acl|list",
+            Command::AclUsers => "acl|users",
+            Command::AclWhoAmI => "acl|whoami",
+            Command::AclCat { .. } => "acl|cat",
+            Command::AclLoad => "acl|load",
+            Command::AclSave => "acl|save",
+            Command::ClientId => "client|id",
+            Command::ClientList => "client|list",
+            Command::ClientGetName => "client|getname",
+            Command::ClientSetName { .. } => "client|setname",
+            Command::ClientKill { .. } => "client|kill",
+            Command::ClientTracking { .. } => "client|tracking",
+            Command::CommandList => "command",
+            Command::CommandCount => "command|count",
+            Command::CommandInfo(_) => "command|info",
+            Command::DebugSleep { .. } => "debug|sleep",
+            Command::DebugObject { .. } => "debug|object",
+            Command::DebugSetActiveExpire { .. } => "debug|set-active-expire",
+            Command::DebugQuickSave => "debug|quicksave",
+            Command::DebugSetTime { .. } => "debug|set-time",
+            Command::ReplicaOf { .. } => "replicaof",
+            Command::Sync { .. } => "sync",
+            Command::PeerSync { .. } => "peersync",
+            Command::Monitor => "monitor",
+            Command::Wait { .. } => "wait",
+            Command::Subscribe(_) => "subscribe",
+            Command::PSubscribe(_) => "psubscribe",
+            Command::Unsubscribe(_) => "unsubscribe",
+            Command::PUnsubscribe(_) => "punsubscribe",
+            Command::Publish { .. } => "publish",
+            Command::Multi => "multi",
+            Command::Exec => "exec",
+            Command::Discard => "discard",
+            Command::Watch(_) => "watch",
+            Command::Unwatch => "unwatch",
+            Command::SentinelGetMasterAddr { .. } => "sentinel|get-master-addr-by-name",
+            Command::SentinelMaster { .. } => "sentinel|master",
+            Command::SentinelReplicas { .. } => "sentinel|replicas",
+            Command::SentinelIsMasterDownByAddr { .. } => "sentinel|is-master-down-by-addr",
+            Command::SentinelFailover { .. } => "sentinel|failover",
+            Command::ClusterMeet { .. } => "cluster|meet",
+            Command::ClusterAddSlots(_) => "cluster|addslots",
+            Command::ClusterDelSlots(_) => "cluster|delslots",
+            Command::ClusterForget { .. } => "cluster|forget",
+            Command::ClusterKeySlot { .. } => "cluster|keyslot",
+            Command::ClusterMyId => "cluster|myid",
+            Command::ClusterInfo => "cluster|info",
+            Command::ClusterNodes => "cluster|nodes",
+            Command::ClusterSlots => "cluster|slots",
+            Command::ClusterShards => "cluster|shards",
+            Command::ClusterReplicas { .. } => "cluster|replicas",
+            Command::ClusterReplicate { .. } => "cluster|replicate",
+            Command::ClusterSetSlot { .. } => "cluster|setslot",
+            Command::ClusterGetKeysInSlot { .. } => "cluster|getkeysinslot",
+            Command::ClusterCountKeysInSlot { .. } => "cluster|countkeysinslot",
+            Command::ClusterBus => "cluster|bus",
+            Command::Asking => "asking",
+            Command::Del(_) => "del",
+            Command::Unlink(_) => "unlink",
+            Command::Touch(_) => "touch",
+            Command::Scan { .. } => "scan",
+            Command::Type { .. } => "type",
+            Command::FlushAll { .. } => "flushall",
+            Command::Dump { .. } => "dump",
+            Command::Restore { .. } => "restore",
+            Command::Migrate { .. } => "migrate",
+            Command::Expire { .. } => "expire",
+            Command::TTL { .. } => "ttl",
+            Command::Persist { .. } => "persist",
+            Command::PExpire { .. } => "pexpire",
+            Command::PTTL { .. } => "pttl",
+            Command::ExpireAt { .. } => "expireat",
+            Command::PExpireAt { .. } => "pexpireat",
+            Command::ObjectEncoding { .. } => "object|encoding",
+            Command::ObjectIdleTime { .. } => "object|idletime",
+            Command::MemoryUsage { .. } => "memory|usage",
+            Command::MemoryStats => "memory|stats",
+            Command::GeoAdd { .. } => "geoadd",
+            Command::GeoDist { .. } => "geodist",
+            Command::GeoPos { .. } => "geopos",
+            Command::GeoSearch { .. } => "geosearch",
+            Command::JsonSet { .. } => "json.set",
+            Command::JsonGet { .. } => "json.get",
+            Command::JsonDel { .. } => "json.del",
+            Command::JsonNumIncrBy { .. } => "json.numincrby",
+            Command::TsCreate { .. } => "ts.create",
+            Command::TsAdd { .. } => "ts.add",
+            Command::TsIncrBy { .. } => "ts.incrby",
+            Command::TsRange { .. } => "ts.range",
+            Command::Eval { .. } => "eval",
+            Command::EvalSha { .. } => "evalsha",
+            Command::ScriptLoad { .. } => "script|load",
+            Command::ScriptExists(_) => "script|exists",
+            Command::ScriptFlush => "script|flush",
+            Command::FunctionLoad { .. } => "function|load",
+            Command::FunctionDelete { .. } => "function|delete",
+            Command::FunctionList => "function|list",
+            Command::FunctionFlush => "function|flush",
+            Command::FCall { .. } => "fcall",
+        }
+    }
+
+    /// 命令的元信息
+    pub fn spec(&self) -> &'static CommandSpec {
+        lookup(self.name()).expect("every command is listed in COMMANDS")
+    }
+
+    /// 命令所属的类别
+    pub fn category(&self) -> Category {
+        self.spec().category
+    }
+
+    /// 命令读取或修改的键，脚本和函数为调用时声明的键
+    pub fn keys(&self) -> Vec<&Bytes> {
+        match self {
+            Command::Set { key, .. }
+            | Command::Get { key }
+            | Command::GetEx { key, .. }
+            | Command::Cas { key, .. }
+            | Command::StrLen { key }
+            | Command::LPush { key, .. }
+            | Command::RPush { key, .. }
+            | Command::LPop { key }
+            | Command::RPop { key }
+            | Command::LRange { key, .. }
+            | Command::LLen { key }
+            | Command::SAdd { key, .. }
+            | Command::SRem { key, .. }
+            | Command::SMembers { key }
+            | Command::SIsMember { key, .. }
+            | Command::SCard { key }
+            | Command::HSet { key, .. }
+            | Command::HGet { key, .. }
+            | Command::HGetAll { key }
+            | Command::HDel { key, .. }
+            | Command::HLen { key }
+            | Command::HExpire { key, .. }
+            | Command::HTTL { key, .. }
+            | Command::HPersist { key, .. }
+            | Command::ZAdd { key, .. }
+            | Command::ZRem { key, .. }
+            | Command::ZRange { key, .. }
+            | Command::ZRangeByScore { key, .. }
+            | Command::ZCard { key }
+            | Command::Type { key }
+            | Command::Dump { key }
+            | Command::Restore { key, .. }
+            | Command::Expire { key, .. }
+            | Command::TTL { key }
+            | Command::Persist { key }
+            | Command::PExpire { key, .. }
+            | Command::PTTL { key }
+            | Command::ExpireAt { key, .. }
+            | Command::PExpireAt { key, .. }
+            | Command::ObjectEncoding { key }
+            | Command::ObjectIdleTime { key }
+            | Command::DebugObject { key }
+            | Command::MemoryUsage { key, .. }
+            | Command::GeoAdd { key, .. }
+            | Command::GeoDist { key, .. }
+            | Command::GeoPos { key, .. }
+            | Command::GeoSearch { key, .. }
+            | Command::JsonSet { key, .. }
+            | Command::JsonGet { key, .. }
+            | Command::JsonDel { key, .. }
+            | Command::JsonNumIncrBy { key, .. }
+            | Command::TsCreate { key, .. }
+            | Command::TsAdd { key, .. }
+            | Command::TsIncrBy { key, .. }
+            | Command::TsRange { key, .. } => vec![key],
+            Command::MSet(pairs) => pairs.iter().map(|(key, _)| key).collect(),
+            Command::MGet(keys)
+            | Command::Del(keys)
+            | Command::Unlink(keys)
+            | Command::Touch(keys)
+            | Command::Migrate { keys, .. }
+            | Command::Watch(keys) => {
+                keys.iter().collect()
+            }
+            Command::Eval { keys, .. } | Command::EvalSha { keys, .. } | Command::FCall { keys, .. } => {
+                keys.iter().collect()
+            }
+            Command::Auth { .. }
+            | Command::Ping { .. }
+            | Command::Echo { .. }
+            | Command::Reset
+            | Command::Hello { .. }
+            | Command::Info
+            | Command::MemoryStats
+            | Command::Scan { .. }
+            | Command::ConfigGet { .. }
+            | Command::ConfigSet(_)
+            | Command::AclSetUser { .. }
+            | Command::AclGetUser { .. }
+            | Command::AclDelUser(_)
+            | Command::AclList
+            | Command::AclUsers
+            | Command::AclWhoAmI
+            | Command::AclCat { .. }
+            | Command::AclLoad
+            | Command::AclSave
+            | Command::ClientId
+            | Command::ClientList
+            | Command::ClientGetName
+            | Command::ClientSetName { .. }
+            | Command::ClientTracking { .. }
+            | Command::ClientKill { .. }
+            | Command::CommandList
+            | Command::CommandCount
+            | Command::CommandInfo(_)
+            | Command::DebugSleep { .. }
+            | Command::DebugSetActiveExpire { .. }
+            | Command::DebugQuickSave
+            | Command::DebugSetTime { .. }
+            | Command::ReplicaOf { .. }
+            | Command::Sync { .. }
+            | Command::PeerSync { .. }
+            | Command::Monitor
+            | Command::Wait { .. }
+            | Command::Subscribe(_)
+            | Command::PSubscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PUnsubscribe(_)
+            | Command::Publish { .. }
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Unwatch
+            | Command::SentinelGetMasterAddr { .. }
+            | Command::SentinelMaster { .. }
+            | Command::SentinelReplicas { .. }
+            | Command::SentinelIsMasterDownByAddr { .. }
+            | Command::SentinelFailover { .. }
+            | Command::ClusterMeet { .. }
+            | Command::ClusterAddSlots(_)
+            | Command::ClusterDelSlots(_)
+            | Command::ClusterForget { .. }
+            | Command::ClusterKeySlot { .. }
+            | Command::ClusterMyId
+            | Command::ClusterInfo
+            | Command::ClusterNodes
+            | Command::ClusterSlots
+            | Command::ClusterShards
+            | Command::ClusterReplicas { .. }
+            | Command::ClusterReplicate { .. }
+            | Command::ClusterSetSlot { .. }
+            | Command::ClusterGetKeysInSlot { .. }
+            | Command::ClusterCountKeysInSlot { .. }
+            | Command::ClusterBus
+            | Command::Asking
+            | Command::FlushAll { .. }
+            | Command::ScriptLoad { .. }
+            | Command::ScriptExists(_)
+            | Command::ScriptFlush
+            | Command::FunctionLoad { .. }
+            | Command::FunctionDelete { .. }
+            | Command::FunctionList
+            | Command::FunctionFlush => vec![],
+        }
+    }
+}
@@ -0,0 +1,459 @@
+//! RESP（Redis Serialization Protocol）的请求解析和响应编码
+//! 请求是批量字符串组成的数组，也兼容以换行结尾的内联命令；
+//! 响应默认使用 RESP2，客户端通过 HELLO 3 切换到 RESP3 后使用映射、集合、浮点数等类型
+
+use crate::codec::RequestLimits;
+use crate::{chunked, split_line, Chunks, RedoxError, RedoxValue, Response};
+use bytes::Bytes;
+
+/// 数组长度和批量字符串长度的最大位数，超过时不再等待 CRLF
+const MAX_INTEGER_LEN: usize = 20;
+
+/// 按客户端声明的参数个数预先分配的最大容量，更多的参数随解析增长
+const MAX_PREALLOCATED_ARGS: usize = 1024;
+
+/// 增量的请求解析器
+/// 与 Redis 的 multibulklen / bulklen 相同，保存数组请求中已经解析的参数和位置，数据分多次到达时每次只解析新到达的部分，
+/// 不从请求开头重新解析和复制；内联命令同样记住已经查找过换行符的位置。
+/// 请求完整之前不取出缓冲区中的数据，调用者在返回请求后取出消耗的字节，两次调用之间只能在缓冲区末尾追加数据。
+#[derive(Debug, Default)]
+pub struct RequestParser {
+    /// 正在解析的数组请求，None 表示缓冲区开头是新请求
+    pending: Option<Multibulk>,
+    /// 内联命令已经查找过换行符的位置
+    scanned: usize,
+}
+
+/// 解析了一部分的数组请求
+#[derive(Debug)]
+struct Multibulk {
+    /// 还没有解析的批量字符串个数
+    remaining: usize,
+    /// 下一个批量字符串在缓冲区中的位置
+    pos: usize,
+    /// 已经解析的参数
+    args: Vec<Bytes>,
+}
+
+impl RequestParser {
+    /// 创建解析器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从缓冲区开头解析一个请求，接着上次调用解析到的位置继续
+    ///
+    /// # Arguments
+    /// * `buf` - 已读取但尚未处理的数据
+    /// * `limits` - 请求大小的上限
+    ///
+    /// # Returns
+    /// * `Ok(Some((args, consumed)))` - 解析出的参数和消耗的字节数，空行返回空参数
+    /// * `Ok(None)` - 数据不完整，需要继续读取
+    /// * `Err(String)` - 协议错误，连接应当关闭
+    pub fn parse(&mut self, buf: &[u8], limits: &RequestLimits) -> Result<Option<(Vec<Bytes>, usize)>, String> {
+        let mut request = match self.pending.take() {
+            Some(request) => request,
+            None => {
+                let Some(&first) = buf.first() else {
+                    return Ok(None);
+                };
+                // 内联命令，按行协议拆分参数
+                if first != b'*' {
+                    return self.parse_inline(buf, limits);
+                }
+                let Some((count, pos)) = read_integer(buf, 1)? else {
+                    return Ok(None);
+                };
+                if count > limits.max_args as i64 {
+                    return Err("Protocol error: invalid multibulk length".to_string());
+                }
+                let remaining = count.max(0) as usize;
+                Multibulk { remaining, pos, args: Vec::with_capacity(remaining.min(MAX_PREALLOCATED_ARGS)) }
+            }
+        };
+
+        while request.remaining > 0 {
+            let Some((arg, next)) = read_bulk(buf, request.pos, limits)? else {
+                self.pending = Some(request);
+                return Ok(None);
+            };
+            request.args.push(arg);
+            request.pos = next;
+            request.remaining -= 1;
+        }
+        Ok(Some((request.args, request.pos)))
+    }
+
+    /// 解析以换行结尾的内联命令
+    fn parse_inline(&mut self, buf: &[u8], limits: &RequestLimits) -> Result<Option<(Vec<Bytes>, usize)>, String> {
+        let end = match buf[self.scanned..].iter().position(|b| *b == b'\n') {
+            Some(offset) if self.scanned + offset <= limits.max_inline_len => self.scanned + offset,
+            None if buf.len() <= limits.max_inline_len => {
+                self.scanned = buf.len();
+                return Ok(None);
+            }
+            _ => return Err("Protocol error: too big inline request".to_string()),
+        };
+        self.scanned = 0;
+        let line = std::str::from_utf8(&buf[..end])
+            .map_err(|_| "Protocol error: invalid UTF-8 in inline command".to_string())?;
+        let args = split_line(line)?;
+        if args.len() > limits.max_args {
+            return Err("Protocol error: too many arguments".to_string());
+        }
+        Ok(Some((args, end + 1)))
+    }
+}
+
+/// 读取从 `pos` 开始的一个批量字符串
+///
+/// # Returns
+/// (内容, 结尾 CRLF 之后的位置)，数据不完整时返回 None
+fn read_bulk(buf: &[u8], pos: usize, limits: &RequestLimits) -> Result<Option<(Bytes, usize)>, String> {
+    let Some(&marker) = buf.get(pos) else {
+        return Ok(None);
+    };
+    if marker != b'$' {
+        return Err(format!("Protocol error: expected '$', got '{}'", marker as char));
+    }
+    let Some((len, start)) = read_integer(buf, pos + 1)? else {
+        return Ok(None);
+    };
+    if !(0..=limits.max_bulk_len as i64).contains(&len) {
+        return Err("Protocol error: invalid bulk length".to_string());
+    }
+    let end = start + len as usize;
+    if buf.len() < end + 2 {
+        return Ok(None);
+    }
+    if &buf[end..end + 2] != b"\r\n" {
+        return Err("Protocol error: bulk string is not terminated by CRLF".to_string());
+    }
+    Ok(Some((Bytes::copy_from_slice(&buf[start..end]), end + 2)))
+}
+
+/// 读取从 `start` 开始、以 CRLF 结尾的整数
+///
+/// # Returns
+/// (整数, CRLF 之后的位置)，数据不完整时返回 None
+fn read_integer(buf: &[u8], start: usize) -> Result<Option<(i64, usize)>, String> {
+    let Some(offset) = buf[start..].windows(2).position(|w| w == b"\r\n") else {
+        if buf.len() - start > MAX_INTEGER_LEN + 1 {
+            return Err("Protocol error: invalid length".to_string());
+        }
+        return Ok(None);
+    };
+    let end = start + offset;
+    let value = std::str::from_utf8(&buf[start..end])
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or("Protocol error: invalid length")?;
+    Ok(Some((value, end + 2)))
+}
+
+/// 连接协商的 RESP 版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RespVersion {
+    /// RESP2，所有集合都编码为数组，空值为空批量字符串
+    Resp2,
+    /// RESP3，哈希表编码为映射，集合编码为集合类型，分数编码为浮点数，空值为 `_`
+    Resp3,
+}
+
+impl RespVersion {
+    /// HELLO 中使用的版本号
+    pub fn number(self) -> u8 {
+        match self {
+            RespVersion::Resp2 => 2,
+            RespVersion::Resp3 => 3,
+        }
+    }
+}
+
+/// 将响应编码为 RESP 格式
+///
+/// # Arguments
+/// * `resp` - 要编码的响应
+/// * `version` - 连接协商的 RESP 版本
+///
+/// # Returns
+/// 编码后的字节，批量字符串原样包含二进制数据
+pub fn encode_response(resp: &Response, version: RespVersion) -> Vec<u8> {
+    encode_chunks(resp, version, usize::MAX).collect::<Vec<_>>().concat()
+}
+
+/// 将响应分块编码为 RESP 格式，集合类型的回复按元素依次编码，不需要一次生成完整的回复
+///
+/// # Arguments
+/// * `resp` - 要编码的响应
+/// * `version` - 连接协商的 RESP 版本
+/// * `chunk_size` - 每块的目标大小，超过这个大小后开始新的一块
+///
+/// # Returns
+/// 依次生成各块的迭代器，第一块以类型标记和长度开头
+pub fn encode_chunks(resp: &Response, version: RespVersion, chunk_size: usize) -> Chunks<'_> {
+    let resp3 = version == RespVersion::Resp3;
+    match resp {
+        Response::Array(items) => {
+            chunked(head('*', items.len()), items.iter(), chunk_size, b"", move |out, item| match item {
+                Some(s) => bulk(out, s),
+                None => null(out, version),
+            })
+        }
+        Response::Value(RedoxValue::List(list)) => {
+            chunked(head('*', list.len()), list.iter(), chunk_size, b"", |out, item| bulk(out, item))
+        }
+        Response::Value(RedoxValue::Set(set)) => {
+            let marker = if resp3 { '~' } else { '*' };
+            chunked(head(marker, set.len()), set.iter(), chunk_size, b"", |out, member| bulk(out, member))
+        }
+        Response::Value(RedoxValue::Hash(hash)) => {
+            let first = if resp3 { head('%', hash.len()) } else { head('*', hash.len() * 2) };
+            chunked(first, hash.iter(), chunk_size, b"", |out, (field, value)| {
+                bulk(out, field);
+                bulk(out, value);
+            })
+        }
+        Response::Value(RedoxValue::SortedSet(zset)) => {
+            if resp3 {
+                // 每个成员编码为 [成员, 分数] 对，分数为浮点数
+                chunked(head('*', zset.len()), zset.iter(), chunk_size, b"", |out, (member, score)| {
+                    header(out, '*', 2);
+                    bulk(out, member);
+                    double(out, score);
+                })
+            } else {
+                chunked(head('*', zset.len() * 2), zset.iter(), chunk_size, b"", |out, (member, score)| {
+                    bulk(out, member);
+                    bulk(out, score.to_string().as_bytes());
+                })
+            }
+        }
+        Response::Value(RedoxValue::TimeSeries(ts)) => {
+            if resp3 {
+                chunked(head('*', ts.samples.len()), ts.samples.iter(), chunk_size, b"", |out, (timestamp, value)| {
+                    header(out, '*', 2);
+                    header(out, ':', *timestamp as i64);
+                    double(out, *value);
+                })
+            } else {
+                chunked(head('*', ts.samples.len() * 2), ts.samples.iter(), chunk_size, b"", |out, (timestamp, value)| {
+                    bulk(out, timestamp.to_string().as_bytes());
+                    bulk(out, value.to_string().as_bytes());
+                })
+            }
+        }
+        _ => {
+            let mut out = Vec::new();
+            write_response(&mut out, resp, version);
+            Box::new(std::iter::once(out))
+        }
+    }
+}
+
+/// 把不是集合的响应追加到输出缓冲区，映射中的值可以是任意响应
+fn write_response(out: &mut Vec<u8>, resp: &Response, version: RespVersion) {
+    let resp3 = version == RespVersion::Resp3;
+    match resp {
+        Response::Ok => out.extend_from_slice(b"+OK\r\n"),
+        Response::Nil => null(out, version),
+        Response::Error(err) => out.extend_from_slice(encode_error(err).as_bytes()),
+        Response::Integer(value) => header(out, ':', *value),
+        Response::Info(info) => {
+            let mut lines: Vec<String> = info.iter()
+                .map(|(key, value)| format!("{}:{}\r\n", key, value))
+                .collect();
+            lines.sort();
+            let text = lines.concat();
+            if resp3 {
+                // RESP3 的原样字符串，带 3 字节的格式前缀
+                header(out, '=', text.len() as i64 + 4);
+                out.extend_from_slice(b"txt:");
+                out.extend_from_slice(text.as_bytes());
+                out.extend_from_slice(b"\r\n");
+            } else {
+                bulk(out, text.as_bytes());
+            }
+        }
+        Response::Map(fields) => {
+            if resp3 {
+                header(out, '%', fields.len() as i64);
+            } else {
+                header(out, '*', fields.len() as i64 * 2);
+            }
+            for (name, value) in fields {
+                bulk(out, name.as_bytes());
+                out.extend_from_slice(&encode_response(value, version));
+            }
+        }
+        Response::Cursor(cursor, items) => {
+            // [游标, [元素 ...]]，游标是批量字符串
+            header(out, '*', 2);
+            bulk(out, cursor.to_string().as_bytes());
+            header(out, '*', items.len() as i64);
+            for item in items {
+                bulk(out, item);
+            }
+        }
+        Response::Replies(replies) => {
+            header(out, '*', replies.len() as i64);
+            for reply in replies {
+                out.extend_from_slice(&encode_response(reply, version));
+            }
+        }
+        Response::Push { kind, items } => out.extend_from_slice(&encode_push(kind, items, version)),
+        Response::Value(RedoxValue::String(s)) => bulk(out, s),
+        Response::Value(RedoxValue::Json(json)) => bulk(out, json.to_string().as_bytes()),
+        // 集合类型由 encode_chunks 编码
+        Response::Array(_) | Response::Value(_) => out.extend_from_slice(&encode_response(resp, version)),
+    }
+}
+
+/// 编码推送消息（RESP3 的 `>` 类型），用于发布订阅和客户端缓存失效通知等带外消息，
+/// 不会与命令的回复混淆；RESP2 连接中编码为普通数组
+///
+/// # Arguments
+/// * `kind` - 消息类型，如 `message`、`invalidate`
+/// * `items` - 消息内容，可以是字符串、整数或空值
+/// * `version` - 连接协商的 RESP 版本
+pub fn encode_push(kind: &str, items: &[Response], version: RespVersion) -> Vec<u8> {
+    let marker = match version {
+        RespVersion::Resp2 => '*',
+        RespVersion::Resp3 => '>',
+    };
+    let mut out = Vec::new();
+    header(&mut out, marker, items.len() as i64 + 1);
+    bulk(&mut out, kind.as_bytes());
+    for item in items {
+        write_response(&mut out, item, version);
+    }
+    out
+}
+
+/// 编码类型标记和长度（或整数值）
+fn header(out: &mut Vec<u8>, marker: char, len: i64) {
+    out.extend_from_slice(format!("{}{}\r\n", marker, len).as_bytes());
+}
+
+/// 编码批量字符串
+fn bulk(out: &mut Vec<u8>, s: &[u8]) {
+    header(out, '$', s.len() as i64);
+    out.extend_from_slice(s);
+    out.extend_from_slice(b"\r\n");
+}
+
+/// 编码空值
+fn null(out: &mut Vec<u8>, version: RespVersion) {
+    match version {
+        RespVersion::Resp2 => out.extend_from_slice(b"$-1\r\n"),
+        RespVersion::Resp3 => out.extend_from_slice(b"_\r\n"),
+    }
+}
+
+/// 编码 RESP3 浮点数，无穷大和 NaN 按规范写作 `inf`、`-inf` 和 `nan`
+fn double(out: &mut Vec<u8>, value: f64) {
+    let encoded = if value.is_nan() {
+        ",nan\r\n".to_string()
+    } else if value.is_infinite() {
+        format!(",{}inf\r\n", if value < 0.0 { "-" } else { "" })
+    } else {
+        format!(",{}\r\n", value)
+    };
+    out.extend_from_slice(encoded.as_bytes());
+}
+
+/// 编码集合的类型标记和长度，作为分块编码的第一块的开头
+fn head(marker: char, len: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    header(&mut out, marker, len as i64);
+    out
+}
+
+/// 编码错误，错误码（如 `WRONGTYPE`、`NOSCRIPT`）之后是去掉换行的错误说明
+fn encode_error(err: &RedoxError) -> String {
+    format!("-{} {}\r\n", err.code(), err.message().replace(['\r', '\n'], " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_inline_len: usize, max_args: usize, max_bulk_len: usize) -> RequestLimits {
+        RequestLimits { max_inline_len, max_args, max_bulk_len }
+    }
+
+    /// 完整请求的参数，按 UTF-8 解码
+    fn args(request: Option<(Vec<Bytes>, usize)>) -> Vec<String> {
+        let (args, _) = request.expect("complete request");
+        args.iter().map(|arg| String::from_utf8(arg.to_vec()).unwrap()).collect()
+    }
+
+    #[test]
+    fn parses_a_request_arriving_byte_by_byte() {
+        let request = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        let mut parser = RequestParser::new();
+        for end in 1..request.len() {
+            assert_eq!(parser.parse(&request[..end], &RequestLimits::default()), Ok(None), "prefix of {} bytes", end);
+        }
+        let parsed = parser.parse(request, &RequestLimits::default()).unwrap();
+        assert_eq!(parsed.as_ref().map(|(_, consumed)| *consumed), Some(request.len()));
+        assert_eq!(args(parsed), ["SET", "key", "value"]);
+    }
+
+    #[test]
+    fn parses_an_inline_request_arriving_byte_by_byte() {
+        let request = b"GET key\r\n";
+        let mut parser = RequestParser::new();
+        for end in 1..request.len() {
+            assert_eq!(parser.parse(&request[..end], &RequestLimits::default()), Ok(None), "prefix of {} bytes", end);
+        }
+        assert_eq!(args(parser.parse(request, &RequestLimits::default()).unwrap()), ["GET", "key"]);
+    }
+
+    #[test]
+    fn truncated_requests_wait_for_more_data() {
+        let mut parser = RequestParser::new();
+        assert_eq!(parser.parse(b"", &RequestLimits::default()), Ok(None));
+        assert_eq!(parser.parse(b"*2\r\n$3\r\nGET\r\n$3\r\nke", &RequestLimits::default()), Ok(None));
+        assert_eq!(parser.parse(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r", &RequestLimits::default()), Ok(None));
+    }
+
+    #[test]
+    fn consumes_only_the_first_of_two_frames() {
+        let buf = b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n";
+        let mut parser = RequestParser::new();
+        let (first, consumed) = parser.parse(buf, &RequestLimits::default()).unwrap().unwrap();
+        assert_eq!(first, [Bytes::from_static(b"PING")]);
+        assert_eq!(consumed, 14);
+        assert_eq!(args(parser.parse(&buf[consumed..], &RequestLimits::default()).unwrap()), ["GET", "k"]);
+    }
+
+    #[test]
+    fn empty_inline_request_has_no_arguments() {
+        let mut parser = RequestParser::new();
+        assert_eq!(parser.parse(b"\r\n", &RequestLimits::default()), Ok(Some((Vec::new(), 2))));
+        assert_eq!(parser.parse(b"*0\r\n", &RequestLimits::default()), Ok(Some((Vec::new(), 4))));
+    }
+
+    #[test]
+    fn rejects_requests_over_the_limits() {
+        let limits = limits(8, 2, 4);
+        let error = |buf: &[u8]| RequestParser::new().parse(buf, &limits).unwrap_err();
+        assert!(error(b"GET a-long-key\r\n").contains("too big inline request"));
+        assert!(error(b"GET a-long-key").contains("too big inline request"));
+        assert!(error(b"A B C\r\n").contains("too many arguments"));
+        assert!(error(b"*3\r\n").contains("invalid multibulk length"));
+        assert!(error(b"*1\r\n$5\r\n").contains("invalid bulk length"));
+        assert!(error(b"*1\r\n$-1\r\n").contains("invalid bulk length"));
+        assert!(error(b"*1\r\n$123456789012345678901234").contains("invalid length"));
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        let error = |buf: &[u8]| RequestParser::new().parse(buf, &RequestLimits::default()).unwrap_err();
+        assert!(error(b"*1\r\n:1\r\n").contains("expected '$'"));
+        assert!(error(b"*1\r\n$3\r\nGETxx").contains("not terminated by CRLF"));
+        assert!(error(b"*x\r\n").contains("invalid length"));
+    }
+}
@@ -0,0 +1,41 @@
+//! 集群的哈希槽
+//! 键空间分为 16384 个槽，键所在的槽是键的 CRC16（XMODEM）对 16384 取模，与 Redis Cluster 相同；
+//! 键中包含非空的 `{...}` 时只计算第一个 `{` 和之后第一个 `}` 之间的部分（哈希标签），
+//! 带相同哈希标签的键总是在同一个槽，可以在一个命令中一起操作。
+
+/// 槽的数量
+pub const SLOTS: u16 = 16384;
+
+/// 计算键所在的槽
+///
+/// # Arguments
+/// * `key` - 键，可以包含哈希标签
+///
+/// # Returns
+/// 槽的编号，小于 `SLOTS`
+pub fn key_slot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) % SLOTS
+}
+
+/// 键中参与计算槽的部分：非空的哈希标签，没有时为整个键
+fn hash_tag(key: &[u8]) -> &[u8] {
+    let Some(open) = key.iter().position(|&b| b == b'{') else {
+        return key;
+    };
+    match key[open + 1..].iter().position(|&b| b == b'}') {
+        Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+        _ => key,
+    }
+}
+
+/// CRC16/XMODEM：多项式 0x1021，初始值 0，不反转
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
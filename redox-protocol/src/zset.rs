@@ -0,0 +1,157 @@
+//! 有序集合
+//! 同时维护成员到分数的哈希表和按 (分数, 成员) 排序的索引，两者在每次修改时同步更新：
+//! 按成员查询分数为 O(1)，按分数范围查询为 O(log n + k)，按排名查询从较近的一端开始遍历，
+//! 读取时都不需要复制和排序整个集合。
+
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+/// 可以排序的分数，按 `f64::total_cmp` 比较
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// 有序集合，成员唯一，按分数升序排列，分数相同时按成员的字典序排列
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    /// 成员到分数的映射
+    scores: HashMap<Bytes, f64>,
+    /// 按 (分数, 成员) 排序的索引
+    order: BTreeSet<(Score, Bytes)>,
+}
+
+impl SortedSet {
+    /// 创建空的有序集合
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 成员数量
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// 是否没有成员
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// 成员的分数，成员不存在时为 None
+    pub fn get(&self, member: &[u8]) -> Option<&f64> {
+        self.scores.get(member)
+    }
+
+    /// 成员是否存在
+    pub fn contains_key(&self, member: &[u8]) -> bool {
+        self.scores.contains_key(member)
+    }
+
+    /// 添加成员或更新已有成员的分数
+    ///
+    /// # Returns
+    /// 成员原来的分数，新成员为 None
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let old = self.scores.insert(member.clone(), score);
+        if let Some(old) = old {
+            self.order.remove(&(Score(old), member.clone()));
+        }
+        self.order.insert((Score(score), member));
+        old
+    }
+
+    /// 删除成员
+    ///
+    /// # Returns
+    /// 成员的分数，成员不存在时为 None
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let (member, score) = self.scores.remove_entry(member)?;
+        self.order.remove(&(Score(score), member));
+        Some(score)
+    }
+
+    /// 按分数升序遍历所有成员
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> + Send + '_ {
+        self.order.iter().map(|(score, member)| (member, score.0))
+    }
+
+    /// 按排名获取成员，排名从 0 开始
+    /// 从离范围较近的一端开始遍历，复杂度为 O(min(start, len - stop) + k)
+    ///
+    /// # Arguments
+    /// * `start` - 起始排名（包含）
+    /// * `stop` - 结束排名（包含），需要小于成员数量
+    pub fn range_by_rank(&self, start: usize, stop: usize) -> Vec<(&Bytes, f64)> {
+        if start > stop || stop >= self.len() {
+            return Vec::new();
+        }
+        let count = stop - start + 1;
+        let from_end = self.len() - 1 - stop;
+        if start <= from_end {
+            self.iter().skip(start).take(count).collect()
+        } else {
+            let mut members: Vec<_> = self.iter().rev().skip(from_end).take(count).collect();
+            members.reverse();
+            members
+        }
+    }
+
+    /// 获取分数在 [min, max] 范围内的成员，按分数升序排列
+    pub fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        self.order
+            .range((Bound::Included((Score(min), Bytes::new())), Bound::Unbounded))
+            .take_while(move |(score, _)| score.0 <= max)
+            .map(|(score, member)| (member, score.0))
+    }
+}
+
+impl FromIterator<(Bytes, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Bytes, f64)>>(iter: I) -> Self {
+        let mut zset = SortedSet::new();
+        for (member, score) in iter {
+            zset.insert(member, score);
+        }
+        zset
+    }
+}
+
+impl IntoIterator for SortedSet {
+    type Item = (Bytes, f64);
+    type IntoIter = IntoIter;
+
+    /// 按分数升序取出所有成员
+    fn into_iter(self) -> IntoIter {
+        IntoIter(self.order.into_iter())
+    }
+}
+
+/// 按分数升序取出有序集合成员的迭代器
+pub struct IntoIter(std::collections::btree_set::IntoIter<(Score, Bytes)>);
+
+impl Iterator for IntoIter {
+    type Item = (Bytes, f64);
+
+    fn next(&mut self) -> Option<(Bytes, f64)> {
+        self.0.next().map(|(score, member)| (member, score.0))
+    }
+}
@@ -0,0 +1,193 @@
+//! 哨兵配置
+//! 启动时由配置文件和命令行参数生成，命令行参数优先；被监控的主节点和其他哨兵的地址在两处指定的都会使用。
+
+use clap::Parser;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// 命令行参数，指定的参数覆盖配置文件中的同名配置
+#[derive(Parser)]
+#[command(author, version, about = "Monitors Redox primaries and fails over to a replica when a primary is down")]
+pub struct SentinelArgs {
+    /// TOML configuration file
+    #[arg(short = 'c', long)]
+    pub config: Option<String>,
+
+    /// Address to listen on (default: 127.0.0.1)
+    #[arg(long)]
+    pub bind: Option<String>,
+
+    /// Port to listen on (default: 22001)
+    #[arg(short = 'P', long)]
+    pub port: Option<u16>,
+
+    /// Primary to monitor as "<name> <host> <port> <quorum>", can be given more than once
+    #[arg(long)]
+    pub monitor: Vec<String>,
+
+    /// Another sentinel monitoring the same primaries as "<host>:<port>", can be given more than once
+    #[arg(long)]
+    pub sentinel: Vec<String>,
+
+    /// Milliseconds without a valid reply before a node is considered down (default: 30000)
+    #[arg(long)]
+    pub down_after_milliseconds: Option<u64>,
+
+    /// Milliseconds to wait before retrying a failed failover of the same primary is twice this (default: 180000)
+    #[arg(long)]
+    pub failover_timeout: Option<u64>,
+}
+
+/// 配置文件的内容，所有字段都是可选的
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    bind: Option<String>,
+    port: Option<u16>,
+    sentinels: Vec<String>,
+    down_after_milliseconds: Option<u64>,
+    failover_timeout: Option<u64>,
+    monitor: Vec<MonitorSection>,
+}
+
+/// 配置文件的 `[[monitor]]` 部分，每个被监控的主节点一项
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct MonitorSection {
+    name: String,
+    host: String,
+    port: u16,
+    quorum: usize,
+    down_after_milliseconds: Option<u64>,
+    failover_timeout: Option<u64>,
+    auth_user: Option<String>,
+    auth_pass: Option<String>,
+}
+
+/// 一个被监控的主节点的配置
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    /// 主节点的名称，客户端用它查询主节点的地址
+    pub name: String,
+    /// 启动时主节点的主机名或地址
+    pub host: String,
+    /// 启动时主节点的端口
+    pub port: u16,
+    /// 认为主节点客观下线需要的哨兵数（包括自己）
+    pub quorum: usize,
+    /// 节点超过这个时间没有正常回复时认为它主观下线
+    pub down_after: Duration,
+    /// 同一个主节点两次故障转移尝试之间至少间隔这个时间的两倍
+    pub failover_timeout: Duration,
+    /// 连接主节点和副本时认证的用户，None 表示 default 用户
+    pub auth_user: Option<String>,
+    /// 连接主节点和副本时认证的密码，None 表示不需要认证
+    pub auth_pass: Option<String>,
+}
+
+/// 哨兵配置
+#[derive(Debug)]
+pub struct Config {
+    /// 监听地址
+    pub bind: String,
+    /// 监听端口
+    pub port: u16,
+    /// 其他哨兵的地址
+    pub sentinels: Vec<(String, u16)>,
+    /// 被监控的主节点
+    pub monitors: Vec<MonitorConfig>,
+}
+
+/// 默认的监听端口
+const DEFAULT_PORT: u16 = 22001;
+
+/// 默认的主观下线时间（毫秒）
+const DEFAULT_DOWN_AFTER_MS: u64 = 30_000;
+
+/// 默认的故障转移超时（毫秒）
+const DEFAULT_FAILOVER_TIMEOUT_MS: u64 = 180_000;
+
+impl Config {
+    /// 读取配置文件并用命令行参数覆盖
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - 生成的配置
+    /// * `Err(String)` - 配置文件无法读取或配置无效
+    pub fn load(args: &SentinelArgs) -> Result<Self, String> {
+        let file = match &args.config {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Error reading config file {}: {}", path, e))?;
+                toml::from_str::<ConfigFile>(&content)
+                    .map_err(|e| format!("Error parsing config file {}: {}", path, e))?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let down_after = args.down_after_milliseconds
+            .or(file.down_after_milliseconds)
+            .unwrap_or(DEFAULT_DOWN_AFTER_MS);
+        let failover_timeout = args.failover_timeout.or(file.failover_timeout).unwrap_or(DEFAULT_FAILOVER_TIMEOUT_MS);
+        let mut monitors = Vec::new();
+        for section in file.monitor {
+            monitors.push(MonitorConfig {
+                name: section.name,
+                host: section.host,
+                port: section.port,
+                quorum: section.quorum,
+                down_after: Duration::from_millis(section.down_after_milliseconds.unwrap_or(down_after)),
+                failover_timeout: Duration::from_millis(section.failover_timeout.unwrap_or(failover_timeout)),
+                auth_user: section.auth_user,
+                auth_pass: section.auth_pass,
+            });
+        }
+        for value in &args.monitor {
+            let invalid = || format!("Invalid monitor, expected \"<name> <host> <port> <quorum>\": {}", value);
+            let [name, host, port, quorum] = value.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(invalid());
+            };
+            monitors.push(MonitorConfig {
+                name: name.to_string(),
+                host: host.to_string(),
+                port: port.parse().map_err(|_| invalid())?,
+                quorum: quorum.parse().map_err(|_| invalid())?,
+                down_after: Duration::from_millis(down_after),
+                failover_timeout: Duration::from_millis(failover_timeout),
+                auth_user: None,
+                auth_pass: None,
+            });
+        }
+
+        let mut sentinels = Vec::new();
+        for value in file.sentinels.iter().chain(&args.sentinel) {
+            let address = parse_address(value)
+                .ok_or_else(|| format!("Invalid sentinel address, expected \"<host>:<port>\": {}", value))?;
+            sentinels.push(address);
+        }
+
+        let config = Config {
+            bind: args.bind.clone().or(file.bind).unwrap_or_else(|| "127.0.0.1".to_string()),
+            port: args.port.or(file.port).unwrap_or(DEFAULT_PORT),
+            sentinels,
+            monitors,
+        };
+        if config.monitors.is_empty() {
+            return Err("No primary to monitor, use --monitor or [[monitor]] in the config file".to_string());
+        }
+        for (i, monitor) in config.monitors.iter().enumerate() {
+            if config.monitors[..i].iter().any(|other| other.name == monitor.name) {
+                return Err(format!("Duplicate monitor name: {}", monitor.name));
+            }
+            if monitor.quorum == 0 {
+                return Err(format!("quorum of {} must be at least 1", monitor.name));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// 解析 `<host>:<port>` 格式的地址
+fn parse_address(value: &str) -> Option<(String, u16)> {
+    let (host, port) = value.rsplit_once(':')?;
+    Some((host.to_string(), port.parse().ok()?))
+}
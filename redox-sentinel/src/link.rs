@@ -0,0 +1,88 @@
+//! 哨兵到数据节点和其他哨兵的连接
+//! 使用 bincode 二进制格式，INFO 和 SENTINEL MASTER 等回复保留原有的结构；
+//! 连接在第一次请求时建立，请求出错或超时后丢弃，下次请求时重新连接。
+
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::compact::BinaryFormat;
+use redox_protocol::{Command, Response};
+use std::io;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_util::codec::Framed;
+
+/// 一次请求（包括连接和认证）的超时时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 到一个节点的连接
+pub struct Link {
+    /// 节点的主机名或地址
+    host: String,
+    /// 节点的端口
+    port: u16,
+    /// 连接后认证的用户和密码
+    auth: Option<(Option<String>, String)>,
+    /// 已建立的连接
+    framed: Option<Framed<TcpStream, ClientCodec>>,
+}
+
+impl Link {
+    /// 创建连接，第一次请求时才连接节点
+    ///
+    /// # Arguments
+    /// * `host` - 节点的主机名或地址
+    /// * `port` - 节点的端口
+    /// * `auth` - 认证的用户和密码，None 表示不需要认证
+    pub fn new(host: String, port: u16, auth: Option<(Option<String>, String)>) -> Self {
+        Self { host, port, auth, framed: None }
+    }
+
+    /// 发送命令并等待回复
+    ///
+    /// # Returns
+    /// * `Ok(Response)` - 节点的回复，包括错误回复
+    /// * `Err` - 连接失败、认证失败、连接断开或超时
+    pub async fn request(&mut self, cmd: &Command) -> io::Result<Response> {
+        let result = time::timeout(REQUEST_TIMEOUT, self.exchange(cmd))
+            .await
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "request timed out")));
+        // 出错后连接上可能还有未读取的回复，不再使用
+        if result.is_err() {
+            self.framed = None;
+        }
+        result
+    }
+
+    /// 必要时连接并认证，然后发送命令
+    async fn exchange(&mut self, cmd: &Command) -> io::Result<Response> {
+        let framed = match &mut self.framed {
+            Some(framed) => framed,
+            None => {
+                let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+                stream.set_nodelay(true)?;
+                let mut framed = Framed::new(stream, ClientCodec::binary(BinaryFormat::Bincode));
+                if let Some((username, password)) = &self.auth {
+                    let auth = Command::Auth { username: username.clone(), password: password.clone() };
+                    match roundtrip(&mut framed, &auth).await? {
+                        Response::Ok => {}
+                        Response::Error(e) => return Err(io::Error::other(format!("AUTH failed: {}", e))),
+                        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected reply to AUTH")),
+                    }
+                }
+                self.framed.insert(framed)
+            }
+        };
+        roundtrip(framed, cmd).await
+    }
+}
+
+/// 在连接上发送一个命令并读取回复
+async fn roundtrip(framed: &mut Framed<TcpStream, ClientCodec>, cmd: &Command) -> io::Result<Response> {
+    framed.send(cmd).await.map_err(|e| io::Error::other(e.to_string()))?;
+    match framed.next().await {
+        Some(Ok(response)) => Ok(response),
+        Some(Err(e)) => Err(io::Error::other(e.to_string())),
+        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")),
+    }
+}
@@ -0,0 +1,48 @@
+mod config;
+mod link;
+mod monitor;
+mod server;
+
+use clap::Parser;
+use config::{Config, SentinelArgs};
+use monitor::Sentinel;
+use tokio::net::TcpListener;
+
+/// 哨兵入口函数
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = SentinelArgs::parse();
+    let config = Config::load(&args)?;
+    if let Some(path) = &args.config {
+        println!("Using config file: {}", path);
+    }
+
+    let sentinel = Sentinel::new(config.monitors, config.sentinels, config.port);
+    let listener = TcpListener::bind((config.bind.as_str(), config.port)).await?;
+    println!("Sentinel {} listening on {}:{}", sentinel.runid(), config.bind, config.port);
+    sentinel.start();
+
+    tokio::select! {
+        result = server::run(listener, sentinel) => result?,
+        _ = shutdown_signal() => println!("Sentinel stopped"),
+    }
+    Ok(())
+}
+
+/// 等待 SIGINT（Ctrl+C）或 SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
@@ -0,0 +1,722 @@
+//! 监控主节点和副本，主节点下线时与其他哨兵协商后故障转移
+//! 每个被监控的主节点由一个任务每秒检查一次：
+//! 1. 向主节点和已发现的副本发送 INFO，从主节点的 slave<n> 字段发现副本；超过 down-after-milliseconds 没有正常回复的节点主观下线
+//! 2. 向其他哨兵发送 SENTINEL MASTER，其他哨兵的配置纪元更大时说明它已经完成了故障转移，采用它记录的主节点地址
+//! 3. 主节点主观下线时用 SENTINEL IS-MASTER-DOWN-BY-ADDR 询问其他哨兵，包括自己在内不少于 quorum 个哨兵认为下线时客观下线
+//! 4. 客观下线后增加纪元并请求其他哨兵投票，得到不少于 quorum 且超过所有哨兵半数的票后由自己故障转移：
+//!    把复制偏移量最大的可达副本提升为主节点，其余副本改为复制它；旧的主节点恢复后同样被改为副本
+//!
+//! 每个哨兵在一个纪元只投一票，所以同一纪元最多一个哨兵执行故障转移；完成故障转移的哨兵把纪元记为配置纪元，
+//! 其他哨兵据此得知新的主节点。哨兵的状态只在内存中，重启后从配置的主节点开始，再从其他哨兵得知之后的故障转移。
+//! 主节点切换时除了输出 `+switch-master` 事件，还发布到同名的频道，客户端订阅后可以立即连接新的主节点。
+
+use crate::config::MonitorConfig;
+use crate::link::Link;
+use futures::future::join_all;
+use redox_protocol::{Command, RedoxError, RedoxValue, Response};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::lookup_host;
+use tokio::sync::broadcast;
+use tokio::time;
+
+/// 检查节点和其他哨兵的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 开始选举前随机等待的最长毫秒数，避免多个哨兵同时发起选举而都得不到多数票
+const MAX_DESYNC_MS: u64 = 1000;
+
+/// 等待发送给订阅连接的事件数，连接读取太慢时丢弃较早的事件
+const EVENT_BACKLOG: usize = 64;
+
+/// 主节点切换事件的频道
+const SWITCH_MASTER: &str = "+switch-master";
+
+/// 节点的主机名或地址和端口
+type Address = (String, u16);
+
+/// 认证的用户和密码
+type Auth = Option<(Option<String>, String)>;
+
+/// 哨兵记录的一个副本
+struct Replica {
+    /// 最后一次正常回复 INFO 的时间
+    last_ok: Instant,
+    /// 是否主观下线
+    sdown: bool,
+    /// 副本报告的角色，master 表示它已经不再复制任何节点，还没有回复过时为空
+    role: String,
+    /// 副本报告的正在复制的主节点
+    master: Option<Address>,
+    /// 与主节点的连接是否正常
+    link_up: bool,
+    /// 副本已应用的复制偏移量
+    offset: u64,
+}
+
+impl Replica {
+    /// 刚发现的副本，在一个下线时间内视为可达
+    fn discovered() -> Self {
+        Self { last_ok: Instant::now(), sdown: false, role: String::new(), master: None, link_up: false, offset: 0 }
+    }
+}
+
+/// 另一个哨兵的状态
+#[derive(Default)]
+struct Peer {
+    /// 最后一次正常回复的时间
+    last_ok: Option<Instant>,
+    /// 它是否认为主节点下线
+    reports_down: bool,
+}
+
+/// 一个被监控的主节点的状态
+struct MasterState {
+    /// 主节点当前的地址，故障转移后为被提升的副本
+    addr: Address,
+    /// 配置纪元，即最近一次故障转移的纪元，0 表示没有发生过故障转移
+    config_epoch: u64,
+    /// 主节点最后一次正常回复 INFO 的时间
+    last_ok: Instant,
+    /// 是否主观下线
+    sdown: bool,
+    /// 是否客观下线
+    odown: bool,
+    /// 已发现的副本
+    replicas: BTreeMap<Address, Replica>,
+    /// 其他哨兵的状态，与 `Sentinel::peers` 的顺序相同
+    peers: Vec<Peer>,
+    /// 最近一次投票给的哨兵
+    leader: Option<String>,
+    /// 最近一次投票的纪元
+    leader_epoch: u64,
+    /// 最近一次发起故障转移或投票给其他哨兵的时间
+    last_failover: Option<Instant>,
+}
+
+/// 一个被监控的主节点
+struct Master {
+    /// 监控的配置
+    config: MonitorConfig,
+    /// 监控的状态，不跨 await 持有
+    state: Mutex<MasterState>,
+    /// SENTINEL FAILOVER 请求的故障转移，由监控任务在下一次检查时执行
+    forced: AtomicBool,
+}
+
+/// 发布给订阅连接的事件
+#[derive(Clone)]
+pub struct Event {
+    /// 事件的频道，如 `+switch-master`
+    pub channel: String,
+    /// 事件的内容，与输出的事件相同，如 `mymaster 10.0.0.1 2001 10.0.0.2 2001`
+    pub payload: String,
+}
+
+/// 哨兵，所有连接和监控任务共享
+pub struct Sentinel {
+    /// 启动时随机生成的标识，选举时用于投票
+    runid: String,
+    /// 监听的端口，用于 INFO
+    port: u16,
+    /// 当前纪元，每次选举加一，收到更大的纪元时更新
+    current_epoch: AtomicU64,
+    /// 被监控的主节点，按名称索引
+    masters: BTreeMap<String, Arc<Master>>,
+    /// 其他哨兵的地址
+    peers: Vec<Address>,
+    /// 发布给订阅连接的事件
+    events: broadcast::Sender<Event>,
+}
+
+impl Sentinel {
+    /// 创建哨兵
+    ///
+    /// # Arguments
+    /// * `monitors` - 被监控的主节点
+    /// * `peers` - 其他哨兵的地址
+    /// * `port` - 监听的端口
+    pub fn new(monitors: Vec<MonitorConfig>, peers: Vec<Address>, port: u16) -> Arc<Self> {
+        let masters = monitors
+            .into_iter()
+            .map(|config| {
+                let state = MasterState {
+                    addr: (config.host.clone(), config.port),
+                    config_epoch: 0,
+                    last_ok: Instant::now(),
+                    sdown: false,
+                    odown: false,
+                    replicas: BTreeMap::new(),
+                    peers: peers.iter().map(|_| Peer::default()).collect(),
+                    leader: None,
+                    leader_epoch: 0,
+                    last_failover: None,
+                };
+                let master = Master { config, state: Mutex::new(state), forced: AtomicBool::new(false) };
+                (master.config.name.clone(), Arc::new(master))
+            })
+            .collect();
+        let runid = format!("{:016x}{:016x}{:08x}", fastrand::u64(..), fastrand::u64(..), fastrand::u32(..));
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        Arc::new(Self { runid, port, current_epoch: AtomicU64::new(0), masters, peers, events })
+    }
+
+    /// 启动时随机生成的标识
+    pub fn runid(&self) -> &str {
+        &self.runid
+    }
+
+    /// 接收之后发布的事件，由订阅了频道的连接使用
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// 为每个被监控的主节点启动监控任务
+    pub fn start(self: &Arc<Self>) {
+        for master in self.masters.values() {
+            let addr = master.state.lock().unwrap().addr.clone();
+            println!("+monitor master {} {} {} quorum {}", master.config.name, addr.0, addr.1, master.config.quorum);
+            tokio::spawn(self.clone().monitor(master.clone()));
+        }
+    }
+
+    /// 执行客户端或其他哨兵发送的命令
+    pub fn execute(&self, cmd: Command) -> Response {
+        match cmd {
+            Command::Ping { message } => Response::Value(match message {
+                Some(message) => RedoxValue::String(message),
+                None => RedoxValue::string("PONG"),
+            }),
+            Command::Info => Response::Info(self.info()),
+            Command::SentinelGetMasterAddr { name } => match self.masters.get(&name) {
+                Some(master) => {
+                    let (host, port) = master.state.lock().unwrap().addr.clone();
+                    Response::Array(vec![Some(host.into()), Some(port.to_string().into())])
+                }
+                None => Response::Nil,
+            },
+            Command::SentinelMaster { name } => match self.masters.get(&name) {
+                Some(master) => Response::Map(self.describe_master(master)),
+                None => no_such_master(),
+            },
+            Command::SentinelReplicas { name } => match self.masters.get(&name) {
+                Some(master) => {
+                    let state = master.state.lock().unwrap();
+                    Response::Map(
+                        state.replicas.iter()
+                            .map(|(addr, replica)| (format!("{}:{}", addr.0, addr.1), Response::Map(describe_replica(addr, replica))))
+                            .collect(),
+                    )
+                }
+                None => no_such_master(),
+            },
+            Command::SentinelIsMasterDownByAddr { host, port, epoch, runid } => {
+                let addr = (host, port);
+                let Some(master) = self.masters.values().find(|master| master.state.lock().unwrap().addr == addr) else {
+                    return Response::Array(vec![Some("0".into()), Some("*".into()), Some("0".into())]);
+                };
+                let down = master.state.lock().unwrap().sdown;
+                let (leader, leader_epoch) = if runid == "*" {
+                    ("*".to_string(), 0)
+                } else {
+                    self.vote(master, epoch, &runid)
+                };
+                Response::Array(vec![
+                    Some(if down { "1" } else { "0" }.into()),
+                    Some(leader.into()),
+                    Some(leader_epoch.to_string().into()),
+                ])
+            }
+            Command::SentinelFailover { name } => match self.masters.get(&name) {
+                Some(master) => {
+                    let state = master.state.lock().unwrap();
+                    let down_after = master.config.down_after;
+                    if !state.replicas.values().any(|replica| replica.role == "slave" && replica.last_ok.elapsed() <= down_after) {
+                        return Response::Error("No suitable replica to promote".into());
+                    }
+                    master.forced.store(true, Ordering::Relaxed);
+                    Response::Ok
+                }
+                None => no_such_master(),
+            },
+            cmd => Response::Error(RedoxError::Err(format!("'{}' is not available in sentinel mode", cmd.name()))),
+        }
+    }
+
+    /// INFO 的内容：每个主节点一个 master<n> 字段
+    fn info(&self) -> HashMap<String, String> {
+        let mut info = HashMap::new();
+        info.insert("run_id".to_string(), self.runid.clone());
+        info.insert("tcp_port".to_string(), self.port.to_string());
+        info.insert("sentinel_masters".to_string(), self.masters.len().to_string());
+        info.insert("sentinel_current_epoch".to_string(), self.current_epoch.load(Ordering::SeqCst).to_string());
+        for (i, master) in self.masters.values().enumerate() {
+            let state = master.state.lock().unwrap();
+            let status = if state.odown {
+                "odown"
+            } else if state.sdown {
+                "sdown"
+            } else {
+                "ok"
+            };
+            info.insert(
+                format!("master{}", i),
+                format!(
+                    "name={},status={},address={}:{},slaves={},sentinels={}",
+                    master.config.name,
+                    status,
+                    state.addr.0,
+                    state.addr.1,
+                    state.replicas.len(),
+                    1 + reachable_peers(&state, master.config.down_after),
+                ),
+            );
+        }
+        info
+    }
+
+    /// SENTINEL MASTER 的回复，其他哨兵从中读取 ip、port 和 config-epoch
+    fn describe_master(&self, master: &Master) -> Vec<(String, Response)> {
+        let state = master.state.lock().unwrap();
+        let mut flags = "master".to_string();
+        if state.sdown {
+            flags.push_str(",s_down");
+        }
+        if state.odown {
+            flags.push_str(",o_down");
+        }
+        vec![
+            field("name", master.config.name.clone()),
+            field("ip", state.addr.0.clone()),
+            field("port", state.addr.1.to_string()),
+            field("flags", flags),
+            field("num-slaves", state.replicas.len().to_string()),
+            field("num-other-sentinels", reachable_peers(&state, master.config.down_after).to_string()),
+            field("quorum", master.config.quorum.to_string()),
+            field("config-epoch", state.config_epoch.to_string()),
+            field("down-after-milliseconds", master.config.down_after.as_millis().to_string()),
+            field("failover-timeout", master.config.failover_timeout.as_millis().to_string()),
+        ]
+    }
+
+    /// 处理其他哨兵（或自己）在 `epoch` 纪元的投票请求，每个纪元只投给第一个请求的哨兵
+    ///
+    /// # Returns
+    /// 这个主节点最近一次投票给的哨兵和纪元
+    fn vote(&self, master: &Master, epoch: u64, runid: &str) -> (String, u64) {
+        let current = self.current_epoch.fetch_max(epoch, Ordering::SeqCst).max(epoch);
+        let mut state = master.state.lock().unwrap();
+        if state.leader_epoch < epoch && current <= epoch {
+            state.leader = Some(runid.to_string());
+            state.leader_epoch = current;
+            if runid != self.runid {
+                println!("+vote-for-leader {} {}", runid, epoch);
+                // 投票给其他哨兵后暂不发起自己的选举，给它时间完成故障转移
+                state.last_failover = Some(Instant::now());
+            }
+        }
+        (state.leader.clone().unwrap_or_else(|| "*".to_string()), state.leader_epoch)
+    }
+
+    /// 监控一个主节点，直到进程退出
+    async fn monitor(self: Arc<Self>, master: Arc<Master>) {
+        let auth: Auth = master.config.auth_pass.clone().map(|password| (master.config.auth_user.clone(), password));
+        let mut nodes: HashMap<Address, Link> = HashMap::new();
+        let mut peers: Vec<Link> = self.peers.iter().map(|(host, port)| Link::new(host.clone(), *port, None)).collect();
+        let mut interval = time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.check_nodes(&master, &mut nodes, &auth).await;
+            self.check_peers(&master, &mut peers).await;
+
+            if master.forced.swap(false, Ordering::Relaxed) {
+                // 手动故障转移不需要其他哨兵同意，但同样使用新的纪元，其他哨兵据此采用新的主节点
+                let epoch = self.current_epoch.fetch_add(1, Ordering::SeqCst) + 1;
+                println!("+new-epoch {}", epoch);
+                self.failover(&master, epoch, &mut nodes, &auth).await;
+                continue;
+            }
+            if !self.check_odown(&master, &mut peers).await || !can_start_failover(&master) {
+                continue;
+            }
+            time::sleep(Duration::from_millis(fastrand::u64(0..MAX_DESYNC_MS))).await;
+            // 等待期间可能已经投票给了先发起选举的哨兵
+            if !can_start_failover(&master) {
+                continue;
+            }
+            if let Some(epoch) = self.elect(&master, &mut peers).await {
+                self.failover(&master, epoch, &mut nodes, &auth).await;
+            }
+        }
+    }
+
+    /// 向主节点和已发现的副本发送 INFO，更新它们的状态；主节点正常时把复制了其他节点或不再复制的副本改为复制主节点
+    async fn check_nodes(&self, master: &Master, nodes: &mut HashMap<Address, Link>, auth: &Auth) {
+        let (primary, targets) = {
+            let state = master.state.lock().unwrap();
+            let mut targets = vec![state.addr.clone()];
+            targets.extend(state.replicas.keys().cloned());
+            (state.addr.clone(), targets)
+        };
+        nodes.retain(|addr, _| targets.contains(addr));
+        for addr in &targets {
+            nodes.entry(addr.clone()).or_insert_with(|| Link::new(addr.0.clone(), addr.1, auth.clone()));
+        }
+        let replies = join_all(nodes.iter_mut().map(|(addr, link)| async move {
+            match link.request(&Command::Info).await {
+                Ok(Response::Info(info)) => (addr.clone(), Some(info)),
+                _ => (addr.clone(), None),
+            }
+        }))
+        .await;
+
+        let name = &master.config.name;
+        let down_after = master.config.down_after;
+        let mut reconfigure = Vec::new();
+        {
+            let mut state = master.state.lock().unwrap();
+            let now = Instant::now();
+            for (addr, info) in replies {
+                let Some(info) = info else {
+                    continue;
+                };
+                if addr == state.addr {
+                    state.last_ok = now;
+                    let found: Vec<Address> = info.iter()
+                        .filter(|(key, _)| key.strip_prefix("slave").is_some_and(|n| n.parse::<usize>().is_ok()))
+                        .filter_map(|(_, value)| parse_replica(value))
+                        .collect();
+                    for replica in found {
+                        if replica != state.addr && !state.replicas.contains_key(&replica) {
+                            println!("+slave slave {}:{} @ {} {} {}", replica.0, replica.1, name, state.addr.0, state.addr.1);
+                            state.replicas.insert(replica, Replica::discovered());
+                        }
+                    }
+                } else if let Some(replica) = state.replicas.get_mut(&addr) {
+                    replica.last_ok = now;
+                    replica.role = info.get("role").cloned().unwrap_or_default();
+                    replica.master = match (info.get("master_host"), info.get("master_port").and_then(|port| port.parse().ok())) {
+                        (Some(host), Some(port)) => Some((host.clone(), port)),
+                        _ => None,
+                    };
+                    replica.link_up = info.get("master_link_status").is_some_and(|status| status == "up");
+                    replica.offset = info.get("slave_repl_offset").and_then(|offset| offset.parse().ok()).unwrap_or(0);
+                }
+            }
+
+            let sdown = state.last_ok.elapsed() > down_after;
+            if sdown != state.sdown {
+                state.sdown = sdown;
+                let sign = if sdown { '+' } else { '-' };
+                println!("{}sdown master {} {} {}", sign, name, state.addr.0, state.addr.1);
+            }
+            let primary_ok = !state.sdown;
+            let primary_addr = state.addr.clone();
+            for (addr, replica) in state.replicas.iter_mut() {
+                let sdown = replica.last_ok.elapsed() > down_after;
+                if sdown != replica.sdown {
+                    replica.sdown = sdown;
+                    let sign = if sdown { '+' } else { '-' };
+                    println!("{}sdown slave {}:{} @ {} {} {}", sign, addr.0, addr.1, name, primary_addr.0, primary_addr.1);
+                }
+                // 主节点下线时不改动副本，其中可能有其他哨兵刚提升的新主节点
+                if primary_ok && !sdown {
+                    match replica.role.as_str() {
+                        "master" => reconfigure.push((addr.clone(), None)),
+                        "slave" if replica.master.as_ref() != Some(&primary_addr) => {
+                            reconfigure.push((addr.clone(), replica.master.clone()));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        for (addr, following) in reconfigure {
+            if let Some(following) = &following {
+                // 副本可能用主机名记录主节点，解析后是同一个节点时不需要修改
+                if same_node(following, &primary).await {
+                    continue;
+                }
+            }
+            let Some(link) = nodes.get_mut(&addr) else {
+                continue;
+            };
+            match link.request(&Command::ReplicaOf { primary: Some(primary.clone()) }).await {
+                Ok(Response::Ok) => {
+                    let event = if following.is_some() { "+fix-slave-config" } else { "+convert-to-slave" };
+                    println!("{} slave {}:{} @ {} {} {}", event, addr.0, addr.1, name, primary.0, primary.1);
+                }
+                Ok(reply) => eprintln!("Failed to reconfigure replica {}:{}: {:?}", addr.0, addr.1, reply),
+                Err(e) => eprintln!("Failed to reconfigure replica {}:{}: {}", addr.0, addr.1, e),
+            }
+        }
+    }
+
+    /// 向其他哨兵查询这个主节点，采用配置纪元最大的哨兵记录的地址
+    async fn check_peers(&self, master: &Master, peers: &mut [Link]) {
+        let cmd = Command::SentinelMaster { name: master.config.name.clone() };
+        let replies = join_all(peers.iter_mut().map(|link| link.request(&cmd))).await;
+        let mut state = master.state.lock().unwrap();
+        let now = Instant::now();
+        let mut newest: Option<(u64, Address, usize)> = None;
+        for (i, reply) in replies.into_iter().enumerate() {
+            let Ok(Response::Map(fields)) = reply else {
+                continue;
+            };
+            state.peers[i].last_ok = Some(now);
+            let host = lookup_field(&fields, "ip");
+            let port = lookup_field(&fields, "port").and_then(|port| port.parse().ok());
+            let epoch = lookup_field(&fields, "config-epoch").and_then(|epoch| epoch.parse().ok());
+            let (Some(host), Some(port), Some(epoch)) = (host, port, epoch) else {
+                continue;
+            };
+            if epoch > state.config_epoch && newest.as_ref().is_none_or(|(newest, ..)| epoch > *newest) {
+                newest = Some((epoch, (host, port), i));
+            }
+        }
+        if let Some((epoch, addr, i)) = newest {
+            self.current_epoch.fetch_max(epoch, Ordering::SeqCst);
+            let (host, port) = &self.peers[i];
+            println!("+config-update-from sentinel {}:{} {}", host, port, master.config.name);
+            self.switch_master(master, &mut state, addr, epoch);
+        }
+    }
+
+    /// 主节点主观下线时询问其他哨兵，判断是否客观下线
+    async fn check_odown(&self, master: &Master, peers: &mut [Link]) -> bool {
+        let (addr, sdown) = {
+            let state = master.state.lock().unwrap();
+            (state.addr.clone(), state.sdown)
+        };
+        let replies = if sdown {
+            let cmd = Command::SentinelIsMasterDownByAddr {
+                host: addr.0.clone(),
+                port: addr.1,
+                epoch: self.current_epoch.load(Ordering::SeqCst),
+                runid: "*".to_string(),
+            };
+            join_all(peers.iter_mut().map(|link| link.request(&cmd))).await
+        } else {
+            Vec::new()
+        };
+
+        let mut state = master.state.lock().unwrap();
+        // 等待回复期间主节点可能已经被替换
+        if state.addr != addr {
+            return false;
+        }
+        for peer in state.peers.iter_mut() {
+            peer.reports_down = false;
+        }
+        for (peer, reply) in state.peers.iter_mut().zip(replies) {
+            peer.reports_down = parse_down_reply(reply).is_some_and(|(down, ..)| down);
+        }
+        let agreed = if sdown { 1 + state.peers.iter().filter(|peer| peer.reports_down).count() } else { 0 };
+        let odown = agreed >= master.config.quorum;
+        if odown != state.odown {
+            state.odown = odown;
+            if odown {
+                println!("+odown master {} {} {} #quorum {}/{}", master.config.name, addr.0, addr.1, agreed, master.config.quorum);
+            } else {
+                println!("-odown master {} {} {}", master.config.name, addr.0, addr.1);
+            }
+        }
+        odown
+    }
+
+    /// 发起选举，请求其他哨兵在新的纪元投票给自己
+    ///
+    /// # Returns
+    /// 当选时为选举的纪元，否则为 None
+    async fn elect(&self, master: &Master, peers: &mut [Link]) -> Option<u64> {
+        let epoch = self.current_epoch.fetch_add(1, Ordering::SeqCst) + 1;
+        let addr = {
+            let mut state = master.state.lock().unwrap();
+            state.last_failover = Some(Instant::now());
+            state.addr.clone()
+        };
+        println!("+new-epoch {}", epoch);
+        println!("+try-failover master {} {} {}", master.config.name, addr.0, addr.1);
+
+        let (leader, _) = self.vote(master, epoch, &self.runid);
+        let mut votes = usize::from(leader == self.runid);
+        let cmd = Command::SentinelIsMasterDownByAddr { host: addr.0.clone(), port: addr.1, epoch, runid: self.runid.clone() };
+        for reply in join_all(peers.iter_mut().map(|link| link.request(&cmd))).await {
+            if parse_down_reply(reply).is_some_and(|(_, leader, leader_epoch)| leader == self.runid && leader_epoch == epoch) {
+                votes += 1;
+            }
+        }
+        // 包括自己在内所有哨兵的多数
+        let sentinels = self.peers.len() + 1;
+        let needed = master.config.quorum.max(sentinels / 2 + 1);
+        if votes >= needed {
+            println!("+elected-leader master {} {} {} ({} votes)", master.config.name, addr.0, addr.1, votes);
+            Some(epoch)
+        } else {
+            println!("-failover-abort-not-elected master {} {} {} ({}/{} votes)", master.config.name, addr.0, addr.1, votes, needed);
+            None
+        }
+    }
+
+    /// 把复制偏移量最大的可达副本提升为主节点，其余副本改为复制它
+    async fn failover(&self, master: &Master, epoch: u64, nodes: &mut HashMap<Address, Link>, auth: &Auth) {
+        let name = &master.config.name;
+        let (old, candidates) = {
+            let mut state = master.state.lock().unwrap();
+            state.last_failover = Some(Instant::now());
+            let down_after = master.config.down_after;
+            let mut candidates: Vec<(&Address, &Replica)> = state.replicas.iter()
+                .filter(|(_, replica)| replica.role == "slave" && replica.last_ok.elapsed() <= down_after)
+                .collect();
+            candidates.sort_by(|a, b| b.1.offset.cmp(&a.1.offset).then_with(|| a.0.cmp(b.0)));
+            let candidates: Vec<Address> = candidates.into_iter().map(|(addr, _)| addr.clone()).collect();
+            (state.addr.clone(), candidates)
+        };
+
+        for candidate in candidates {
+            let link = nodes.entry(candidate.clone())
+                .or_insert_with(|| Link::new(candidate.0.clone(), candidate.1, auth.clone()));
+            match link.request(&Command::ReplicaOf { primary: None }).await {
+                Ok(Response::Ok) => {}
+                Ok(reply) => {
+                    eprintln!("Failed to promote replica {}:{}: {:?}", candidate.0, candidate.1, reply);
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Failed to promote replica {}:{}: {}", candidate.0, candidate.1, e);
+                    continue;
+                }
+            }
+            println!("+promoted-slave slave {}:{} @ {} {} {}", candidate.0, candidate.1, name, old.0, old.1);
+            let others: Vec<Address> = {
+                let mut state = master.state.lock().unwrap();
+                self.switch_master(master, &mut state, candidate.clone(), epoch);
+                state.replicas.keys().filter(|addr| **addr != old).cloned().collect()
+            };
+            // 失败的副本和恢复后的旧主节点在之后的检查中再改为副本
+            let reconfigure = Command::ReplicaOf { primary: Some(candidate.clone()) };
+            for addr in others {
+                let link = nodes.entry(addr.clone()).or_insert_with(|| Link::new(addr.0.clone(), addr.1, auth.clone()));
+                if let Ok(Response::Ok) = link.request(&reconfigure).await {
+                    println!("+slave-reconf-sent slave {}:{} @ {} {} {}", addr.0, addr.1, name, candidate.0, candidate.1);
+                }
+            }
+            println!("+failover-end master {} {} {}", name, candidate.0, candidate.1);
+            return;
+        }
+        println!("-failover-abort-no-good-slave master {} {} {}", name, old.0, old.1);
+    }
+
+    /// 把主节点的地址改为 `addr`，旧的主节点作为副本继续监控，恢复后被改为复制新的主节点
+    fn switch_master(&self, master: &Master, state: &mut MasterState, addr: Address, epoch: u64) {
+        let old = std::mem::replace(&mut state.addr, addr.clone());
+        let payload = format!("{} {} {} {} {}", master.config.name, old.0, old.1, addr.0, addr.1);
+        println!("{} {}", SWITCH_MASTER, payload);
+        // 没有订阅的连接时发送失败，忽略
+        let _ = self.events.send(Event { channel: SWITCH_MASTER.to_string(), payload });
+        state.config_epoch = epoch;
+        state.replicas.remove(&addr);
+        if old != addr {
+            state.replicas.entry(old).or_insert_with(Replica::discovered);
+        }
+        state.last_ok = Instant::now();
+        state.sdown = false;
+        state.odown = false;
+    }
+}
+
+/// 主节点客观下线，且距上一次故障转移尝试或投票给其他哨兵已超过两倍的故障转移超时
+fn can_start_failover(master: &Master) -> bool {
+    let state = master.state.lock().unwrap();
+    state.odown && state.last_failover.is_none_or(|last| last.elapsed() >= master.config.failover_timeout * 2)
+}
+
+/// 最近一个下线时间内正常回复过的其他哨兵数
+fn reachable_peers(state: &MasterState, down_after: Duration) -> usize {
+    state.peers.iter().filter(|peer| peer.last_ok.is_some_and(|last| last.elapsed() <= down_after)).count()
+}
+
+/// SENTINEL REPLICAS 中一个副本的状态
+fn describe_replica(addr: &Address, replica: &Replica) -> Vec<(String, Response)> {
+    let flags = if replica.sdown { "slave,s_down" } else { "slave" };
+    let (master_host, master_port) = match &replica.master {
+        Some((host, port)) => (host.clone(), port.to_string()),
+        None => (String::new(), String::new()),
+    };
+    vec![
+        field("ip", addr.0.clone()),
+        field("port", addr.1.to_string()),
+        field("flags", flags.to_string()),
+        field("role-reported", replica.role.clone()),
+        field("master-host", master_host),
+        field("master-port", master_port),
+        field("master-link-status", if replica.link_up { "up" } else { "down" }.to_string()),
+        field("slave-repl-offset", replica.offset.to_string()),
+    ]
+}
+
+/// 回复中的一个字段
+fn field(name: &str, value: String) -> (String, Response) {
+    (name.to_string(), Response::Value(RedoxValue::string(value)))
+}
+
+/// 读取回复中的一个字段
+fn lookup_field(fields: &[(String, Response)], name: &str) -> Option<String> {
+    match &fields.iter().find(|(field, _)| field == name)?.1 {
+        Response::Value(RedoxValue::String(value)) => Some(String::from_utf8_lossy(value).into_owned()),
+        Response::Integer(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// 指定的主节点不存在的错误
+fn no_such_master() -> Response {
+    Response::Error("No such master with that name".into())
+}
+
+/// 解析主节点 INFO 中的 `slave<n>` 字段，如 `ip=127.0.0.1,port=2002,state=online,offset=0,lag=0`
+fn parse_replica(value: &str) -> Option<Address> {
+    let mut ip = None;
+    let mut port = None;
+    for pair in value.split(',') {
+        match pair.split_once('=') {
+            Some(("ip", value)) => ip = Some(value.to_string()),
+            Some(("port", value)) => port = value.parse().ok(),
+            _ => {}
+        }
+    }
+    Some((ip?, port?))
+}
+
+/// 解析 SENTINEL IS-MASTER-DOWN-BY-ADDR 的回复
+///
+/// # Returns
+/// 对方是否认为主节点下线、投票给的哨兵和投票的纪元，回复无效时为 None
+fn parse_down_reply(reply: std::io::Result<Response>) -> Option<(bool, String, u64)> {
+    let Ok(Response::Array(items)) = reply else {
+        return None;
+    };
+    let text = |i: usize| items.get(i).cloned().flatten().map(|item| String::from_utf8_lossy(&item).into_owned());
+    Some((text(0)? == "1", text(1)?, text(2)?.parse().ok()?))
+}
+
+/// 两个地址是否指向同一个节点，主机名解析后比较；无法解析时视为同一个节点，不改动副本
+async fn same_node(a: &Address, b: &Address) -> bool {
+    if a == b {
+        return true;
+    }
+    if a.1 != b.1 {
+        return false;
+    }
+    match (lookup_host((a.0.as_str(), a.1)).await, lookup_host((b.0.as_str(), b.1)).await) {
+        (Ok(a), Ok(b)) => {
+            let a: Vec<_> = a.collect();
+            b.into_iter().any(|addr| a.contains(&addr))
+        }
+        _ => true,
+    }
+}
@@ -0,0 +1,111 @@
+//! 哨兵的客户端连接
+//! 与数据节点使用同样的编解码器，客户端可以用行协议、RESP 或二进制格式连接；只接受 PING、INFO、SENTINEL 命令，
+//! 以及订阅事件的 SUBSCRIBE 和 UNSUBSCRIBE。订阅的连接仍然可以执行其他命令，事件以 `message <频道> <内容>` 的消息推送，
+//! 目前只发布 `+switch-master`。
+
+use crate::monitor::{Event, Sentinel};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::{CodecError, RedoxCodec};
+use redox_protocol::{Command, Response};
+use std::collections::BTreeSet;
+use std::io;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::codec::Framed;
+
+/// 接受连接，每个连接由一个任务处理
+///
+/// # Returns
+/// 只在监听器出错时返回
+pub async fn run(listener: TcpListener, sentinel: Arc<Sentinel>) -> io::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let sentinel = sentinel.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(socket, &sentinel).await {
+                eprintln!("Connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// 处理一个连接上的命令，直到客户端断开
+async fn handle_connection(socket: TcpStream, sentinel: &Sentinel) -> Result<(), CodecError> {
+    socket.set_nodelay(true)?;
+    let mut framed = Framed::new(socket, RedoxCodec::new());
+    let mut events = sentinel.events();
+    // 订阅的频道
+    let mut channels = BTreeSet::new();
+    loop {
+        let frame = tokio::select! {
+            frame = framed.next() => frame,
+            event = events.recv() => {
+                match event {
+                    Ok(Event { channel, payload }) if channels.contains(&channel) => {
+                        framed.send(&reply(&["message", &channel, &payload])).await?;
+                    }
+                    // 没有订阅这个频道，或读取太慢丢失了部分事件
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+                continue;
+            }
+        };
+        let Some(frame) = frame else {
+            break;
+        };
+        let (responses, close) = match frame {
+            Ok(Ok(Command::Subscribe(names))) => (subscribe(&mut channels, names), false),
+            Ok(Ok(Command::Unsubscribe(names))) => (unsubscribe(&mut channels, names), false),
+            Ok(Ok(cmd)) => (vec![sentinel.execute(cmd)], false),
+            Ok(Err(e)) => (vec![Response::Error(e)], false),
+            // 协议错误后无法确定下一个请求从哪里开始，回复错误后关闭连接
+            Err(CodecError::Protocol(e)) => (vec![Response::Error(e.into())], true),
+            Err(CodecError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        for response in &responses {
+            framed.feed(response).await?;
+        }
+        SinkExt::<&Response>::flush(&mut framed).await?;
+        if close {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// SUBSCRIBE，每个频道回复一次 `subscribe <频道> <订阅数>`
+fn subscribe(channels: &mut BTreeSet<String>, names: Vec<Bytes>) -> Vec<Response> {
+    names.iter()
+        .map(|name| {
+            let name = String::from_utf8_lossy(name).into_owned();
+            channels.insert(name.clone());
+            reply(&["subscribe", &name, &channels.len().to_string()])
+        })
+        .collect()
+}
+
+/// UNSUBSCRIBE，names 为空时取消所有订阅；没有任何订阅时仍回复一次，频道为 nil
+fn unsubscribe(channels: &mut BTreeSet<String>, names: Vec<Bytes>) -> Vec<Response> {
+    let names: Vec<String> = match names.is_empty() {
+        true => std::mem::take(channels).into_iter().collect(),
+        false => names.iter().map(|name| String::from_utf8_lossy(name).into_owned()).collect(),
+    };
+    if names.is_empty() {
+        return vec![Response::Array(vec![Some("unsubscribe".into()), None, Some("0".into())])];
+    }
+    names.into_iter()
+        .map(|name| {
+            channels.remove(&name);
+            reply(&["unsubscribe", &name, &channels.len().to_string()])
+        })
+        .collect()
+}
+
+/// 订阅的确认和推送的消息
+fn reply(items: &[&str]) -> Response {
+    Response::Array(items.iter().map(|item| Some(item.to_string().into())).collect())
+}
@@ -0,0 +1,419 @@
+//! 访问控制列表（ACL）
+//! 每个用户有独立的密码、允许执行的命令类别和命令，以及允许访问的键模式。
+//! 连接认证为某个用户后，每个命令在执行前按该用户的权限检查。
+//! 用户可以写在 ACL 文件中启动时加载，也可以通过 ACL SETUSER 在运行时修改，规则的写法与 Redis 相同，
+//! 如 `user alice on >secret ~cache:* +@read -del`。
+//! 没有配置 ACL 时只有 default 用户，它可以执行所有命令，密码即 requirepass。
+
+use crate::glob::glob_match;
+use redox_protocol::meta::{self, Category};
+use redox_protocol::{Command, RedoxError, RedoxValue, Response};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+/// 默认用户，不指定用户名的 AUTH 认证为这个用户
+pub const DEFAULT_USER: &str = "default";
+
+/// 命令规则匹配的范围
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    /// 所有命令
+    All,
+    /// 一个类别中的命令
+    Category(Category),
+    /// 一个命令，或带子命令的命令的所有子命令
+    Command(String),
+}
+
+impl Selector {
+    /// 是否匹配名称和类别为给定值的命令
+    fn matches(&self, name: &str, category: Category) -> bool {
+        match self {
+            Selector::All => true,
+            Selector::Category(c) => *c == category,
+            Selector::Command(command) => {
+                name == command || name.strip_prefix(command.as_str()).is_some_and(|rest| rest.starts_with('|'))
+            }
+        }
+    }
+}
+
+/// 一个用户的认证信息和权限
+#[derive(Debug, Clone, Default)]
+struct User {
+    /// 是否可以认证为这个用户
+    enabled: bool,
+    /// 是否接受任意密码
+    nopass: bool,
+    /// 密码的 SHA-256 摘要（十六进制）
+    passwords: BTreeSet<String>,
+    /// 按顺序应用的命令规则，后面的规则优先；没有匹配的规则时禁止执行
+    commands: Vec<(bool, Selector)>,
+    /// 允许访问的键模式
+    keys: Vec<String>,
+}
+
+impl User {
+    /// 默认用户：可以执行所有命令、访问所有键，没有密码时不需要认证
+    fn default_user(password: Option<&str>) -> Self {
+        let mut user = User {
+            enabled: true,
+            commands: vec![(true, Selector::All)],
+            keys: vec!["*".to_string()],
+            ..User::default()
+        };
+        user.set_password(password);
+        user
+    }
+
+    /// 把密码替换为给定的密码，None 表示不需要密码
+    fn set_password(&mut self, password: Option<&str>) {
+        self.passwords.clear();
+        self.nopass = password.is_none();
+        self.passwords.extend(password.map(hash_password));
+    }
+
+    /// 应用一条规则
+    ///
+    /// # Arguments
+    /// * `rule` - 规则，如 `on`、`>password`、`~pattern`、`+@read`、`-del`
+    ///
+    /// # Returns
+    /// * `Ok(())` - 规则有效，已应用
+    /// * `Err(String)` - 规则无效的原因
+    fn apply(&mut self, rule: &str) -> Result<(), String> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.passwords.clear();
+                self.nopass = true;
+            }
+            "resetpass" => {
+                self.passwords.clear();
+                self.nopass = false;
+            }
+            "allkeys" => self.keys = vec!["*".to_string()],
+            "resetkeys" => self.keys.clear(),
+            "allcommands" | "+@all" => self.commands = vec![(true, Selector::All)],
+            "nocommands" | "-@all" => self.commands.clear(),
+            "reset" => *self = User::default(),
+            _ => return self.apply_with_argument(rule),
+        }
+        Ok(())
+    }
+
+    /// 应用带参数的规则：密码、键模式、命令类别和命令
+    fn apply_with_argument(&mut self, rule: &str) -> Result<(), String> {
+        let Some(first) = rule.chars().next() else {
+            return Err("Syntax error".to_string());
+        };
+        let argument = &rule[first.len_utf8()..];
+        match first {
+            '>' => {
+                self.passwords.insert(hash_password(argument));
+                self.nopass = false;
+            }
+            '<' => {
+                if !self.passwords.remove(&hash_password(argument)) {
+                    return Err("no such password".to_string());
+                }
+            }
+            '#' => {
+                if argument.len() != 64 || !argument.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string());
+                }
+                self.passwords.insert(argument.to_lowercase());
+                self.nopass = false;
+            }
+            '!' => {
+                if !self.passwords.remove(&argument.to_lowercase()) {
+                    return Err("no such password".to_string());
+                }
+            }
+            '~' => {
+                if argument.is_empty() {
+                    return Err("Syntax error".to_string());
+                }
+                self.keys.push(argument.to_string());
+            }
+            '+' | '-' => {
+                let allow = first == '+';
+                let selector = match argument.strip_prefix('@') {
+                    Some(name) => Category::parse(name)
+                        .map(Selector::Category)
+                        .ok_or_else(|| "Unknown command category".to_string())?,
+                    None => {
+                        let name = argument.to_lowercase();
+                        if !meta::is_command(&name) {
+                            return Err("Unknown command".to_string());
+                        }
+                        Selector::Command(name)
+                    }
+                };
+                self.commands.push((allow, selector));
+            }
+            _ => return Err("Syntax error".to_string()),
+        }
+        Ok(())
+    }
+
+    /// 密码是否正确
+    fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    /// 是否可以执行命令
+    fn can_run(&self, name: &str, category: Category) -> bool {
+        self.commands.iter().rev()
+            .find(|(_, selector)| selector.matches(name, category))
+            .is_some_and(|(allow, _)| *allow)
+    }
+
+    /// 是否可以访问键
+    fn can_access(&self, key: &[u8]) -> bool {
+        self.keys.iter().any(|pattern| glob_match(pattern.as_bytes(), key))
+    }
+
+    /// 以规则的形式描述用户，重新应用这些规则可以得到同样的权限
+    fn rules(&self) -> Vec<String> {
+        let mut rules = vec![if self.enabled { "on" } else { "off" }.to_string()];
+        if self.nopass {
+            rules.push("nopass".to_string());
+        }
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        if self.keys.is_empty() {
+            rules.push("resetkeys".to_string());
+        }
+        rules.extend(self.keys.iter().map(|pattern| format!("~{}", pattern)));
+        if self.commands.first().is_none_or(|(_, selector)| *selector != Selector::All) {
+            rules.push("-@all".to_string());
+        }
+        for (allow, selector) in &self.commands {
+            let sign = if *allow { '+' } else { '-' };
+            rules.push(match selector {
+                Selector::All => format!("{}@all", sign),
+                Selector::Category(category) => format!("{}@{}", sign, category.as_str()),
+                Selector::Command(name) => format!("{}{}", sign, name),
+            });
+        }
+        rules
+    }
+}
+
+/// 密码的 SHA-256 摘要，用户信息中只保存摘要
+fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 所有用户，所有连接共享
+pub struct Acl {
+    /// 用户名到用户的映射
+    users: RwLock<BTreeMap<String, User>>,
+    /// ACL 文件的路径，ACL LOAD 和 ACL SAVE 读写这个文件
+    file: Option<String>,
+}
+
+impl Acl {
+    /// 创建只有 default 用户的 ACL
+    ///
+    /// # Arguments
+    /// * `requirepass` - default 用户的密码，None 表示不需要认证
+    /// * `file` - ACL 文件的路径
+    pub fn new(requirepass: Option<&str>, file: Option<String>) -> Self {
+        let users = BTreeMap::from([(DEFAULT_USER.to_string(), User::default_user(requirepass))]);
+        Self { users: RwLock::new(users), file }
+    }
+
+    /// 从 ACL 文件加载用户，替换当前所有的用户；文件中没有 default 用户时保留当前的 default 用户
+    /// 文件每行一个用户，格式为 `user <用户名> [规则 ...]`，空行和以 `#` 开头的行被忽略
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - 加载的用户数
+    /// * `Err(String)` - 没有配置 ACL 文件、文件无法读取或规则无效，此时当前的用户保持不变
+    pub fn load(&self) -> Result<usize, String> {
+        let path = self.file.as_deref().ok_or("This Redox instance is not configured to use an ACL file")?;
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error reading ACL file {}: {}", path, e))?;
+
+        let mut loaded = BTreeMap::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some("user"), Some(name)) = (words.next(), words.next()) else {
+                return Err(format!("{}:{}: should start with user keyword followed by the username", path, number + 1));
+            };
+            let mut user = User::default();
+            for rule in words {
+                user.apply(rule).map_err(|e| format!("{}:{}: Error in user declaration '{}': {}", path, number + 1, rule, e))?;
+            }
+            loaded.insert(name.to_string(), user);
+        }
+
+        let count = loaded.len();
+        let mut users = self.users.write().unwrap();
+        if !loaded.contains_key(DEFAULT_USER) {
+            if let Some(default) = users.remove(DEFAULT_USER) {
+                loaded.insert(DEFAULT_USER.to_string(), default);
+            }
+        }
+        *users = loaded;
+        Ok(count)
+    }
+
+    /// 把当前所有的用户写入 ACL 文件，密码只保存摘要
+    fn save(&self) -> Result<(), String> {
+        let path = self.file.as_deref().ok_or("This Redox instance is not configured to use an ACL file")?;
+        let content: String = self.users.read().unwrap().iter()
+            .map(|(name, user)| format!("user {} {}\n", name, user.rules().join(" ")))
+            .collect();
+        std::fs::write(path, content).map_err(|e| format!("Error writing ACL file {}: {}", path, e))
+    }
+
+    /// 把 default 用户的密码设置为 requirepass，用于 CONFIG SET 和重新加载配置文件
+    pub fn set_default_password(&self, password: Option<&str>) {
+        if let Some(user) = self.users.write().unwrap().get_mut(DEFAULT_USER) {
+            user.set_password(password);
+        }
+    }
+
+    /// 新连接自动认证的用户：default 用户已启用且不需要密码时为 default，否则需要认证
+    pub fn default_login(&self) -> Option<String> {
+        let users = self.users.read().unwrap();
+        let user = users.get(DEFAULT_USER)?;
+        (user.enabled && user.nopass).then(|| DEFAULT_USER.to_string())
+    }
+
+    /// 不指定用户名的 AUTH 是否需要检查密码
+    pub fn default_requires_password(&self) -> bool {
+        self.users.read().unwrap().get(DEFAULT_USER).is_none_or(|user| !user.nopass)
+    }
+
+    /// 验证用户名和密码
+    ///
+    /// # Returns
+    /// * `Ok(())` - 用户存在、已启用且密码正确
+    /// * `Err(RedoxError::WrongPass)` - 用户不存在、已禁用或密码错误
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<(), RedoxError> {
+        let users = self.users.read().unwrap();
+        match users.get(username) {
+            Some(user) if user.enabled && user.check_password(password) => Ok(()),
+            _ => Err(RedoxError::WrongPass),
+        }
+    }
+
+    /// 检查用户是否可以执行命令，AUTH、HELLO 和 RESET 总是允许
+    ///
+    /// # Arguments
+    /// * `username` - 连接认证的用户
+    /// * `cmd` - 要执行的命令
+    ///
+    /// # Returns
+    /// * `Ok(())` - 允许执行
+    /// * `Err(RedoxError)` - 用户已被删除，或没有执行命令、访问键的权限
+    pub fn check(&self, username: &str, cmd: &Command) -> Result<(), RedoxError> {
+        if matches!(cmd, Command::Auth { .. } | Command::Hello { .. } | Command::Reset) {
+            return Ok(());
+        }
+        let users = self.users.read().unwrap();
+        let Some(user) = users.get(username) else {
+            return Err(RedoxError::NoAuth(format!("User {} no longer exists, please authenticate again", username)));
+        };
+        let name = cmd.name();
+        if !user.can_run(name, cmd.category()) {
+            return Err(RedoxError::NoPerm(format!(
+                "User {} has no permissions to run the '{}' command", username, name,
+            )));
+        }
+        if cmd.keys().iter().any(|key| !user.can_access(key)) {
+            return Err(RedoxError::NoPerm("No permissions to access a key".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 处理 ACL 命令
+    ///
+    /// # Arguments
+    /// * `current_user` - 当前连接认证的用户，用于 ACL WHOAMI
+    /// * `cmd` - ACL 命令
+    pub fn execute(&self, current_user: &str, cmd: Command) -> Response {
+        let text = |s: String| Some(s.into());
+        match cmd {
+            Command::AclSetUser { username, rules } => {
+                let mut users = self.users.write().unwrap();
+                let mut user = users.get(&username).cloned().unwrap_or_default();
+                for rule in &rules {
+                    if let Err(e) = user.apply(rule) {
+                        return Response::Error(format!("Error in ACL SETUSER modifier '{}': {}", rule, e).into());
+                    }
+                }
+                users.insert(username, user);
+                Response::Ok
+            }
+            Command::AclGetUser { username } => {
+                let users = self.users.read().unwrap();
+                let Some(user) = users.get(&username) else {
+                    return Response::Nil;
+                };
+                let list = |items: Vec<String>| Response::Array(items.into_iter().map(text).collect());
+                let mut flags = vec![if user.enabled { "on" } else { "off" }.to_string()];
+                if user.nopass {
+                    flags.push("nopass".to_string());
+                }
+                let commands = user.rules().into_iter()
+                    .filter(|rule| rule.starts_with('+') || rule.starts_with('-'))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                Response::Map(vec![
+                    ("flags".to_string(), list(flags)),
+                    ("passwords".to_string(), list(user.passwords.iter().cloned().collect())),
+                    ("commands".to_string(), Response::Value(RedoxValue::string(commands))),
+                    ("keys".to_string(), list(user.keys.iter().map(|pattern| format!("~{}", pattern)).collect())),
+                ])
+            }
+            Command::AclDelUser(usernames) => {
+                if usernames.iter().any(|name| name == DEFAULT_USER) {
+                    return Response::Error("The 'default' user cannot be removed".into());
+                }
+                let mut users = self.users.write().unwrap();
+                let deleted = usernames.iter().filter(|name| users.remove(name.as_str()).is_some()).count();
+                Response::Integer(deleted as i64)
+            }
+            Command::AclList => Response::Array(
+                self.users.read().unwrap().iter()
+                    .map(|(name, user)| text(format!("user {} {}", name, user.rules().join(" "))))
+                    .collect(),
+            ),
+            Command::AclUsers => Response::Array(self.users.read().unwrap().keys().cloned().map(text).collect()),
+            Command::AclWhoAmI => Response::Value(RedoxValue::string(current_user.to_string())),
+            Command::AclCat { category: None } => Response::Array(
+                Category::ALL.iter().map(|category| text(category.as_str().to_string())).collect(),
+            ),
+            Command::AclCat { category: Some(name) } => match Category::parse(&name) {
+                Some(category) => Response::Array(
+                    meta::COMMANDS.iter()
+                        .filter(|spec| spec.category == category)
+                        .map(|spec| text(spec.name.to_string()))
+                        .collect(),
+                ),
+                None => Response::Error(format!("Unknown category '{}'", name).into()),
+            },
+            Command::AclLoad => match self.load() {
+                Ok(_) => Response::Ok,
+                Err(e) => Response::Error(e.into()),
+            },
+            Command::AclSave => match self.save() {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e.into()),
+            },
+            _ => Response::Error("Not an ACL command".into()),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! 在进程内运行服务器
+//! `Server::builder()` 按配置创建存储、ACL 和 TLS 接受器，`spawn` 绑定地址后在后台任务中运行服务器，
+//! 返回的 `ServerHandle` 提供实际监听的地址和关闭方法。`redox-server` 可执行文件同样用它创建服务器。
+
+use crate::acl::Acl;
+use crate::config::Config;
+use crate::logging::{notice, warning};
+use crate::network::Server;
+use crate::storage::Storage;
+use crate::task::spawn_named;
+use crate::tls;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// 服务器的构造器，由 `Server::builder` 创建
+#[derive(Default)]
+pub struct ServerBuilder {
+    /// 服务器配置
+    config: Config,
+    /// 使用的存储实例，None 时创建不持久化的空存储
+    storage: Option<Storage>,
+    /// 覆盖配置中的 requirepass
+    password: Option<String>,
+    /// 覆盖配置中的 bind 和 port
+    addr: Option<String>,
+}
+
+impl Server {
+    /// 创建服务器的构造器，默认使用 `Config::default()` 和不持久化的空存储
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+}
+
+impl ServerBuilder {
+    /// 使用的服务器配置，`password` 和 `bind` 设置的值优先于配置中的值
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 使用的存储实例，可以先写入数据，或者用 `Persistence` 创建持久化的存储
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// 默认用户的密码，相当于配置 requirepass
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// `spawn` 监听的地址（如 "127.0.0.1:0"），端口为 0 时由系统分配，`ServerHandle::addr` 返回实际的端口
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.addr = Some(addr.into());
+        self
+    }
+
+    /// 创建服务器但不绑定地址：把配置中的内存上限、惰性释放阈值和配额应用到存储，
+    /// 启动过期键的清理任务，加载 TLS 证书和 ACL 文件
+    ///
+    /// # Returns
+    /// * `Ok((Server, JoinHandle))` - 服务器和清理任务，调用者之后用 `Server::run` 或 `bind` 和 `serve` 运行它
+    /// * `Err` - TLS 证书、ACL 文件或集群配置文件无法读取
+    pub async fn build(self) -> io::Result<(Server, JoinHandle<()>)> {
+        let mut config = self.config;
+        if let Some(password) = self.password {
+            config.requirepass = Some(password);
+        }
+        let storage = self.storage.unwrap_or_else(|| Storage::new(None));
+        storage.set_maxmemory(config.maxmemory, config.maxmemory_policy);
+        storage.set_lazyfree_threshold(config.lazyfree_threshold);
+        storage.set_quotas(config.quotas.clone()).await;
+
+        let tls = match (&config.tls_cert, &config.tls_key) {
+            (Some(cert), Some(key)) => Some(tls::load_acceptor(cert, key).map_err(invalid_input)?),
+            _ => None,
+        };
+
+        let acl = Acl::new(config.requirepass.as_deref(), config.aclfile.clone());
+        if let Some(path) = &config.aclfile {
+            let count = acl.load().map_err(invalid_input)?;
+            notice!("Loaded {} user(s) from ACL file {}", count, path);
+        }
+
+        let cleanup = spawn_named("expire-cleanup", storage.clone().start_cleanup_task());
+        let server = Server::new(storage, config, acl, tls)?;
+        Ok((server, cleanup))
+    }
+
+    /// 创建服务器，绑定地址后在后台任务中运行
+    ///
+    /// # Returns
+    /// * `Ok(ServerHandle)` - 已经开始接受连接的服务器
+    /// * `Err` - 创建服务器失败，或地址无法绑定
+    pub async fn spawn(self) -> io::Result<ServerHandle> {
+        let addr = self.addr.clone().unwrap_or_else(|| format!("{}:{}", self.config.bind, self.config.port));
+        let storage = self.storage.clone().unwrap_or_else(|| Storage::new(None));
+        let (server, cleanup) = self.storage(storage.clone()).build().await?;
+        let listeners = match server.bind(&addr).await {
+            Ok(listeners) => listeners,
+            Err(e) => {
+                cleanup.abort();
+                return Err(e);
+            }
+        };
+        let local = listeners[0].local_addr()?;
+        let shutdown = CancellationToken::new();
+        let server = Arc::new(server);
+        let task = spawn_named(&format!("server {}", local), {
+            let shutdown = shutdown.clone();
+            async move { server.serve(listeners, shutdown.cancelled_owned()).await }
+        });
+        Ok(ServerHandle { addr: local, storage, shutdown, task, cleanup })
+    }
+}
+
+/// 在后台运行的服务器，由 `ServerBuilder::spawn` 返回
+/// 被丢弃时服务器同样开始关闭，但不等待连接完成，也不保存数据
+pub struct ServerHandle {
+    /// 实际监听的地址
+    addr: SocketAddr,
+    /// 服务器使用的存储
+    storage: Storage,
+    /// 取消时服务器开始关闭
+    shutdown: CancellationToken,
+    /// 运行服务器的任务
+    task: JoinHandle<io::Result<()>>,
+    /// 过期键的清理任务
+    cleanup: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// 实际监听的地址，绑定到端口 0 时为系统分配的端口
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// 服务器使用的存储，可以绕过网络直接读写数据
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// 关闭服务器：停止接受新连接，等待各连接处理完已收到的命令，配置了持久化时保存一次快照
+    ///
+    /// # Returns
+    /// * `Ok(())` - 服务器已经停止
+    /// * `Err` - 服务器运行时出错，或保存数据失败
+    pub async fn shutdown(mut self) -> io::Result<()> {
+        self.shutdown.cancel();
+        let result = (&mut self.task).await.map_err(io::Error::other).and_then(|result| result);
+        self.cleanup.abort();
+        if let Err(e) = self.storage.save_now().await {
+            warning!("Error saving data: {}", e);
+            return result.and(Err(e));
+        }
+        result
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+        self.cleanup.abort();
+    }
+}
+
+/// 把读取 TLS 证书或 ACL 文件的错误转换为 io::Error
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
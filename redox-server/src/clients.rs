@@ -0,0 +1,185 @@
+//! 连接登记表
+//! 记录每个连接的编号、地址、名称、建立时间、最后一次执行的命令和认证的用户，
+//! 用于 INFO 的连接统计、CLIENT LIST 排查卡住的客户端，以及 CLIENT KILL 关闭指定的连接。
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// 所有连接的登记表，所有连接共享
+pub struct Clients {
+    /// 当前的连接数
+    connected: AtomicUsize,
+    /// 启动以来同时存在的最大连接数
+    peak: AtomicUsize,
+    /// 下一个连接的编号
+    next_id: AtomicU64,
+    /// 编号到连接信息的映射，按编号排序
+    registry: Mutex<BTreeMap<u64, Arc<ClientInfo>>>,
+}
+
+/// 一个连接的信息
+pub struct ClientInfo {
+    /// 连接编号，从 1 开始递增，不会重复使用
+    pub id: u64,
+    /// 客户端地址
+    pub addr: SocketAddr,
+    /// 连接建立的时间
+    created: Instant,
+    /// 取消时连接在处理完当前的命令后关闭
+    kill: CancellationToken,
+    /// 会随命令变化的信息
+    activity: Mutex<Activity>,
+}
+
+/// 连接信息中会随命令变化的部分
+struct Activity {
+    /// CLIENT SETNAME 设置的名称
+    name: Option<String>,
+    /// 认证的用户
+    user: Option<String>,
+    /// 最后一次执行的命令
+    last_command: &'static str,
+    /// 最后一次执行命令的时间
+    last_active: Instant,
+}
+
+impl Clients {
+    /// 创建空的登记表
+    pub fn new() -> Self {
+        Self {
+            connected: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            next_id: AtomicU64::new(1),
+            registry: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 登记一个新连接
+    ///
+    /// # Arguments
+    /// * `max` - 最大连接数，0 表示不限制
+    /// * `addr` - 客户端地址
+    /// * `kill` - CLIENT KILL 时取消，连接在等待请求时检查
+    /// * `user` - 连接建立时自动认证的用户
+    ///
+    /// # Returns
+    /// * `Some(ClientGuard)` - 登记成功，连接关闭时释放
+    /// * `None` - 已达到最大连接数
+    pub fn register(
+        self: &Arc<Self>,
+        max: usize,
+        addr: SocketAddr,
+        kill: CancellationToken,
+        user: Option<String>,
+    ) -> Option<ClientGuard> {
+        let result = self.connected.fetch_update(Ordering::AcqRel, Ordering::Acquire, |connected| {
+            (max == 0 || connected < max).then_some(connected + 1)
+        });
+        let connected = result.ok()? + 1;
+        self.peak.fetch_max(connected, Ordering::Relaxed);
+
+        let now = Instant::now();
+        let info = Arc::new(ClientInfo {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            addr,
+            created: now,
+            kill,
+            activity: Mutex::new(Activity { name: None, user, last_command: "NULL", last_active: now }),
+        });
+        self.registry.lock().unwrap().insert(info.id, info.clone());
+        Some(ClientGuard { clients: self.clone(), info })
+    }
+
+    /// 把连接统计加入 INFO 的输出
+    pub fn add_info(&self, info: &mut HashMap<String, String>, max: usize) {
+        info.insert("connected_clients".to_string(), self.connected.load(Ordering::Relaxed).to_string());
+        info.insert("peak_connected_clients".to_string(), self.peak.load(Ordering::Relaxed).to_string());
+        info.insert("maxclients".to_string(), max.to_string());
+    }
+
+    /// 所有连接的信息，每个连接一行，格式与 Redis 的 CLIENT LIST 相同
+    pub fn list(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        registry.values().map(|client| client.describe() + "\n").collect()
+    }
+
+    /// 关闭所有满足条件的连接，连接在处理完当前的命令后关闭
+    ///
+    /// # Arguments
+    /// * `id` - 连接编号，None 表示不限制
+    /// * `addr` - 客户端地址，格式为 `ip:port`，None 表示不限制
+    ///
+    /// # Returns
+    /// 关闭的连接数
+    pub fn kill(&self, id: Option<u64>, addr: Option<&str>) -> usize {
+        let registry = self.registry.lock().unwrap();
+        let mut killed = 0;
+        for client in registry.values() {
+            let id_matches = id.is_none_or(|id| id == client.id);
+            let addr_matches = addr.is_none_or(|addr| addr == client.addr.to_string());
+            if id_matches && addr_matches {
+                client.kill.cancel();
+                killed += 1;
+            }
+        }
+        killed
+    }
+}
+
+impl ClientInfo {
+    /// 记录连接开始执行一个命令
+    pub fn touch(&self, command: &'static str) {
+        let mut activity = self.activity.lock().unwrap();
+        activity.last_command = command;
+        activity.last_active = Instant::now();
+    }
+
+    /// 连接的名称
+    pub fn name(&self) -> Option<String> {
+        self.activity.lock().unwrap().name.clone()
+    }
+
+    /// 设置连接的名称，None 表示清除名称
+    pub fn set_name(&self, name: Option<String>) {
+        self.activity.lock().unwrap().name = name;
+    }
+
+    /// 记录连接认证的用户
+    pub fn set_user(&self, user: Option<String>) {
+        self.activity.lock().unwrap().user = user;
+    }
+
+    /// CLIENT LIST 中的一行
+    fn describe(&self) -> String {
+        let activity = self.activity.lock().unwrap();
+        format!(
+            "id={} addr={} name={} age={} idle={} cmd={} user={}",
+            self.id,
+            self.addr,
+            activity.name.as_deref().unwrap_or(""),
+            self.created.elapsed().as_secs(),
+            activity.last_active.elapsed().as_secs(),
+            activity.last_command,
+            activity.user.as_deref().unwrap_or(""),
+        )
+    }
+}
+
+/// 已登记的连接，释放时从登记表中移除
+pub struct ClientGuard {
+    /// 所属的登记表
+    clients: Arc<Clients>,
+    /// 连接的信息
+    pub info: Arc<ClientInfo>,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.clients.registry.lock().unwrap().remove(&self.info.id);
+        self.clients.connected.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,80 @@
+//! 时钟
+//! 存储判断键是否过期、计算 TTL 和空闲时间，持久化判断自动保存的条件和导出 RDB 时都从 `Clock` 读取当前时间。
+//! 服务器默认使用系统时钟；测试可以换成 `ManualClock`，修改时间后立即看到键过期，不需要等待。
+
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 当前时间的来源
+pub trait Clock: Any + Send + Sync {
+    /// 当前的毫秒级 Unix 时间戳
+    fn now_ms(&self) -> u64;
+}
+
+/// 存储和持久化共享的时钟
+pub type SharedClock = Arc<dyn Clock>;
+
+/// 系统时钟，服务器默认使用
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        now_ms()
+    }
+}
+
+/// 手动时钟，时间只在调用 `set` 或 `advance`（或执行 DEBUG SET-TIME）时改变
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    /// 当前的毫秒级 Unix 时间戳
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// 创建停在指定时间的时钟
+    ///
+    /// # Arguments
+    /// * `now_ms` - 初始的毫秒级 Unix 时间戳
+    pub fn new(now_ms: u64) -> Self {
+        Self { now: AtomicU64::new(now_ms) }
+    }
+
+    /// 创建停在当前系统时间的时钟
+    pub fn from_system() -> Self {
+        Self::new(now_ms())
+    }
+
+    /// 把时间设置为指定的毫秒级 Unix 时间戳，可以向前或向后调整
+    pub fn set(&self, now_ms: u64) {
+        self.now.store(now_ms, Ordering::Relaxed);
+    }
+
+    /// 把时间向后推进
+    pub fn advance(&self, duration: Duration) {
+        self.now.fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now.load(Ordering::Relaxed)
+    }
+}
+
+/// 时钟是手动时钟时返回它，DEBUG SET-TIME 通过它修改时间
+pub fn as_manual(clock: &SharedClock) -> Option<&ManualClock> {
+    let clock: &dyn Any = clock.as_ref();
+    clock.downcast_ref()
+}
+
+/// 系统时间的毫秒级 Unix 时间戳
+/// 复制和集群链路的心跳、超时等与数据无关的计时直接使用系统时间，不受 DEBUG SET-TIME 影响
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
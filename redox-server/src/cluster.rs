@@ -0,0 +1,1308 @@
+//! 集群模式
+//! 键空间的 16384 个哈希槽分布在集群的各个节点上，每个槽由一个节点负责（槽的计算见 `redox_protocol::slot`）。
+//! 访问键的命令先计算键所在的槽：槽由本节点负责时正常执行，由其他节点负责时回复 `MOVED <slot> <host>:<port>`，
+//! 客户端据此把命令发给正确的节点；一个命令的多个键必须在同一个槽。
+//!
+//! 节点之间通过集群总线交换状态：节点连接其他节点的客户端端口并发送 CLUSTER BUS，之后连接上的每一帧都是
+//! 4 字节大端序的长度加上 bincode 序列化的 `Message`。每个节点每秒向每个已知的节点发送 PING，对方回复 PONG，
+//! 两者都带有发送者的 ID、纪元和负责的槽，以及发送者已知的所有其他节点（gossip），节点由此发现集群中的其他节点。
+//! 两个节点声明同一个槽时，配置纪元较大的节点负责；配置纪元相同的两个节点中 ID 较小的节点取一个新的纪元。
+//!
+//! 超过 cluster-node-timeout 没有回复 PONG 的节点标记为疑似下线（PFAIL），其他节点在 gossip 中报告疑似下线，
+//! 负责槽的主节点中的多数都认为它疑似下线时标记为下线（FAIL）。有槽没有节点负责或负责的节点下线时集群不可用，
+//! 访问键的命令返回 CLUSTERDOWN 错误。
+//! 节点的 ID、纪元、已知的节点和槽的归属保存在集群配置文件中（格式与 Redis 的 nodes.conf 相同），重启后恢复。
+//!
+//! 没有槽的节点可以用 CLUSTER REPLICATE 成为一个主节点的副本，通过普通的复制（见 `crate::replication`）接收主节点的数据；
+//! 副本的角色和主节点随心跳传播。CLUSTER NODES、SLOTS、SHARDS 和 REPLICAS 返回本节点看到的拓扑，
+//! 集群客户端据此建立槽到节点的映射。
+//!
+//! 迁移一个槽时，目标节点先用 CLUSTER SETSLOT IMPORTING 标记槽正在迁入，源节点用 CLUSTER SETSLOT MIGRATING 标记槽正在迁出，
+//! 之后用 CLUSTER GETKEYSINSLOT 和 MIGRATE 把槽中的键逐批移到目标节点，最后在两个节点上执行 CLUSTER SETSLOT NODE。
+//! 迁移期间源节点执行键还在本节点的命令，键不在时回复 `ASK <slot> <host>:<port>`；客户端先向目标节点发送 ASKING，
+//! 目标节点才会执行下一个访问迁入中的槽的命令。目标节点在 SETSLOT NODE 时取一个新的配置纪元，
+//! 其他节点收到它的心跳后改由它负责这个槽。
+
+use crate::config::SharedConfig;
+use crate::logging::{notice, warning};
+use crate::replication::Replication;
+use crate::clock::now_ms;
+use crate::storage::Storage;
+use crate::task::spawn_named;
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::compact::{self, BinaryFormat, MAX_FRAME_LEN};
+use redox_protocol::slot::{self, SLOTS};
+use redox_protocol::{Command, RedoxError, RedoxValue, Response, SlotState};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_util::codec::{Framed, FramedParts};
+use tokio_util::sync::CancellationToken;
+
+/// 向每个已知的节点发送 PING 的间隔
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 检查节点是否下线、集群是否可用和保存集群配置文件的间隔
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 集群总线断开后重连前等待的时间
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// 连接其他节点和等待握手回复的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// CLUSTER FORGET 之后这段时间内不再从 gossip 中重新加入被删除的节点
+const FORGET_TTL: Duration = Duration::from_secs(60);
+
+/// 集群总线上的消息
+#[derive(Serialize, Deserialize)]
+enum Message {
+    /// CLUSTER MEET 之后发送的 PING，接收者把发送者加入已知的节点
+    Meet(Header),
+    /// 定期发送的心跳
+    Ping(Header),
+    /// MEET 和 PING 的回复
+    Pong(Header),
+}
+
+/// 每条消息都带有的发送者的状态
+#[derive(Serialize, Deserialize)]
+struct Header {
+    /// 发送者的 ID
+    id: String,
+    /// 发送者的地址，为空时接收者使用连接的对端地址
+    host: String,
+    /// 发送者的客户端端口
+    port: u16,
+    /// 发送者的当前纪元
+    current_epoch: u64,
+    /// 发送者的配置纪元
+    config_epoch: u64,
+    /// 发送者负责的槽，每个槽一位
+    slots: Vec<u8>,
+    /// 发送者复制的主节点的 ID，None 表示发送者是主节点
+    primary: Option<String>,
+    /// 发送者的复制偏移量
+    repl_offset: u64,
+    /// 发送者是否是还没有完成全量同步的副本
+    loading: bool,
+    /// 回复 PONG 的节点看到的连接的对端地址，即接收者的地址，本身监听所有地址的节点据此得知自己的地址；
+    /// MEET 和 PING 中为空
+    peer_host: String,
+    /// 发送者已知的其他节点
+    gossip: Vec<Gossip>,
+}
+
+/// gossip 中的一个节点
+#[derive(Serialize, Deserialize)]
+struct Gossip {
+    /// 节点的 ID
+    id: String,
+    /// 节点的地址
+    host: String,
+    /// 节点的客户端端口
+    port: u16,
+    /// 发送者是否认为它疑似下线或已下线
+    failing: bool,
+}
+
+/// 一个已知的节点，包括本节点
+struct Node {
+    /// 节点的 ID，握手完成前是临时的随机 ID
+    id: String,
+    /// 节点的地址
+    host: String,
+    /// 节点的客户端端口
+    port: u16,
+    /// 节点的配置纪元，声明同一个槽时较大的节点负责
+    config_epoch: u64,
+    /// 节点复制的主节点的 ID，None 表示节点是主节点
+    primary: Option<String>,
+    /// 节点最后报告的复制偏移量
+    repl_offset: u64,
+    /// 节点是否是还没有完成全量同步的副本
+    loading: bool,
+    /// 是否在等待 CLUSTER MEET 之后的第一个 PONG
+    handshake: bool,
+    /// 是否疑似下线
+    pfail: bool,
+    /// 是否已下线
+    fail: bool,
+    /// 发送了还没有收到回复的 PING 的时间（毫秒级 Unix 时间戳），0 表示没有
+    ping_sent: u64,
+    /// 最后一次收到 PONG 的时间（毫秒级 Unix 时间戳）
+    pong_received: u64,
+    /// 加入已知节点的时间，握手超时后删除
+    created: Instant,
+    /// 其他节点报告它疑似下线的时间，按报告者的 ID
+    fail_reports: HashMap<String, Instant>,
+    /// 到这个节点的集群总线是否已连接
+    connected: bool,
+    /// 取消时停止连接这个节点
+    link: CancellationToken,
+}
+
+impl Node {
+    /// 创建一个新发现的节点
+    fn new(id: String, host: String, port: u16) -> Self {
+        Self {
+            id,
+            host,
+            port,
+            config_epoch: 0,
+            primary: None,
+            repl_offset: 0,
+            loading: false,
+            handshake: false,
+            pfail: false,
+            fail: false,
+            ping_sent: 0,
+            pong_received: now_ms(),
+            created: Instant::now(),
+            fail_reports: HashMap::new(),
+            connected: false,
+            link: CancellationToken::new(),
+        }
+    }
+
+    /// 节点的客户端地址，用于 MOVED
+    fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// 节点的客户端地址，用于 CLUSTER SLOTS 和 SHARDS；本节点还不知道自己的地址时使用客户端连接的本地地址
+    fn endpoint(&self, local: IpAddr) -> (String, u16) {
+        let host = if self.host.is_empty() { local.to_string() } else { self.host.clone() };
+        (host, self.port)
+    }
+
+    /// 节点的角色，用于 CLUSTER SHARDS
+    fn role(&self) -> &'static str {
+        if self.primary.is_some() {
+            "replica"
+        } else {
+            "master"
+        }
+    }
+}
+
+/// 集群的状态
+struct State {
+    /// 本节点的 ID
+    myself: String,
+    /// 当前纪元，即集群中见过的最大的纪元
+    current_epoch: u64,
+    /// 已知的节点，包括本节点
+    nodes: HashMap<String, Node>,
+    /// 每个槽由哪个节点负责
+    owners: Vec<Option<String>>,
+    /// CLUSTER FORGET 删除的节点，在期限之前不从 gossip 中重新加入
+    forgotten: HashMap<String, Instant>,
+    /// 正在迁出的槽和迁入的节点
+    migrating: BTreeMap<u16, String>,
+    /// 正在迁入的槽和迁出的节点
+    importing: BTreeMap<u16, String>,
+    /// 集群是否可用，即所有槽都有节点负责且负责的节点都没有下线
+    ok: bool,
+    /// 集群配置是否有修改还没有保存
+    dirty: bool,
+}
+
+impl State {
+    /// 本节点
+    fn myself(&self) -> &Node {
+        &self.nodes[&self.myself]
+    }
+
+    /// 负责至少一个槽的节点数
+    fn size(&self) -> usize {
+        let mut owners: Vec<&String> = self.owners.iter().flatten().collect();
+        owners.sort_unstable();
+        owners.dedup();
+        owners.len()
+    }
+
+    /// 节点负责的槽，合并为连续的范围
+    fn slot_ranges(&self, id: &str) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for slot in 0..SLOTS {
+            if self.owners[slot as usize].as_deref() != Some(id) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == slot => *end = slot,
+                _ => ranges.push((slot, slot)),
+            }
+        }
+        ranges
+    }
+
+    /// 主节点的副本，按 ID 排序，不包括握手中的节点
+    fn replicas(&self, id: &str) -> Vec<&Node> {
+        let mut replicas: Vec<&Node> = self.nodes.values()
+            .filter(|node| !node.handshake && node.primary.as_deref() == Some(id))
+            .collect();
+        replicas.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        replicas
+    }
+
+    /// 所有主节点，按 ID 排序，不包括握手中的节点
+    fn primaries(&self) -> Vec<&Node> {
+        let mut primaries: Vec<&Node> = self.nodes.values()
+            .filter(|node| !node.handshake && node.primary.is_none())
+            .collect();
+        primaries.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        primaries
+    }
+
+    /// 按 CLUSTER NODES 的格式描述一个节点
+    fn describe(&self, node: &Node) -> String {
+        let mut flags = Vec::new();
+        if node.id == self.myself {
+            flags.push("myself");
+        }
+        flags.push(if node.primary.is_some() { "slave" } else { "master" });
+        if node.fail {
+            flags.push("fail");
+        } else if node.pfail {
+            flags.push("fail?");
+        }
+        if node.handshake {
+            flags.push("handshake");
+        }
+        let mut line = format!(
+            "{} {}:{}@{} {} {} {} {} {} {}",
+            node.id,
+            node.host,
+            node.port,
+            node.port,
+            flags.join(","),
+            node.primary.as_deref().unwrap_or("-"),
+            node.ping_sent,
+            node.pong_received,
+            node.config_epoch,
+            if node.connected || node.id == self.myself { "connected" } else { "disconnected" },
+        );
+        for (start, end) in self.slot_ranges(&node.id) {
+            if start == end {
+                let _ = write!(line, " {}", start);
+            } else {
+                let _ = write!(line, " {}-{}", start, end);
+            }
+        }
+        // 与 Redis 相同，本节点正在迁出和迁入的槽写在最后
+        if node.id == self.myself {
+            for (slot, target) in &self.migrating {
+                let _ = write!(line, " [{}->-{}]", slot, target);
+            }
+            for (slot, source) in &self.importing {
+                let _ = write!(line, " [{}-<-{}]", slot, source);
+            }
+        }
+        line
+    }
+
+    /// 本节点的配置纪元不大于其他节点时取一个新的纪元，之后本节点声明的槽在与其他节点冲突时由本节点负责
+    fn bump_epoch(&mut self) {
+        let myself = self.myself.clone();
+        let others = self.nodes.values().filter(|node| node.id != myself).map(|node| node.config_epoch).max().unwrap_or(0);
+        if self.myself().config_epoch > others {
+            return;
+        }
+        self.current_epoch += 1;
+        let epoch = self.current_epoch;
+        self.nodes.get_mut(&myself).unwrap().config_epoch = epoch;
+        self.dirty = true;
+        notice!("Config epoch set to {} to take over migrated slots", epoch);
+    }
+
+    /// 集群配置文件的内容：每个节点一行，最后一行是纪元
+    fn to_config_file(&self) -> String {
+        let mut content = String::new();
+        for node in self.nodes.values().filter(|node| !node.handshake) {
+            content.push_str(&self.describe(node));
+            content.push('\n');
+        }
+        let _ = writeln!(content, "vars currentEpoch {} lastVoteEpoch 0", self.current_epoch);
+        content
+    }
+
+    /// 更新集群是否可用，状态改变时输出日志
+    fn update_ok(&mut self) {
+        let ok = self.owners.iter().all(|owner| {
+            owner.as_ref().is_some_and(|id| self.nodes.get(id).is_some_and(|node| !node.fail))
+        });
+        if ok != self.ok {
+            notice!("Cluster state changed: {}", if ok { "ok" } else { "fail" });
+            self.ok = ok;
+        }
+    }
+}
+
+/// 集群，所有连接共享
+pub struct Cluster {
+    /// 集群的状态
+    state: RwLock<State>,
+    /// 服务器配置，读取节点超时、本身监听的地址和连接其他节点时的认证信息
+    config: SharedConfig,
+    /// 集群配置文件
+    path: String,
+    /// 复制的状态，CLUSTER REPLICATE 之后通过它复制主节点
+    replication: Arc<Replication>,
+    /// 存储实例，用于检查迁移中的槽的键是否还在本节点
+    storage: Arc<Storage>,
+}
+
+impl Cluster {
+    /// 读取集群配置文件，文件不存在时以新的 ID 创建一个只有本节点的集群
+    ///
+    /// # Arguments
+    /// * `config` - 服务器配置，`cluster_config_file` 指定集群配置文件
+    /// * `storage` - 存储实例
+    /// * `replication` - 复制的状态，本节点是副本时用于复制主节点
+    ///
+    /// # Returns
+    /// * `Ok(Cluster)` - 集群的状态，`start` 之后开始与其他节点通信
+    /// * `Err` - 文件无法读取或格式无效
+    pub fn load(config: SharedConfig, storage: Arc<Storage>, replication: Arc<Replication>) -> io::Result<Arc<Self>> {
+        let path = config.read().unwrap().cluster_config_file.clone();
+        let state = match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                let state = parse_config_file(&content)
+                    .map_err(|e| invalid_data(format!("Invalid cluster config file {}: {}", path, e)))?;
+                notice!("Cluster node {} loaded from {} with {} known node(s)", state.myself, path, state.nodes.len());
+                state
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let myself = random_id();
+                notice!("No cluster config file found, creating node {}", myself);
+                let mut nodes = HashMap::new();
+                nodes.insert(myself.clone(), Node::new(myself.clone(), String::new(), 0));
+                State {
+                    myself,
+                    current_epoch: 0,
+                    nodes,
+                    owners: vec![None; SLOTS as usize],
+                    forgotten: HashMap::new(),
+                    migrating: BTreeMap::new(),
+                    importing: BTreeMap::new(),
+                    ok: false,
+                    dirty: true,
+                }
+            }
+            Err(e) => return Err(io::Error::new(e.kind(), format!("Error reading cluster config file {}: {}", path, e))),
+        };
+        Ok(Arc::new(Self { state: RwLock::new(state), config, path, replication, storage }))
+    }
+
+    /// 开始与其他节点通信：连接所有已知的节点，本节点是副本时开始复制主节点，并启动定期检查节点和集群状态的任务
+    /// 在服务器开始监听之后调用，本节点的端口是实际监听的端口
+    pub fn start(self: &Arc<Self>) {
+        let (bind, port) = {
+            let config = self.config.read().unwrap();
+            (config.bind.clone(), config.port)
+        };
+        // 监听所有地址时不知道其他节点应该用哪个地址连接本节点，由它们使用连接的对端地址
+        let host = match bind.parse::<IpAddr>() {
+            Ok(ip) if ip.is_unspecified() => String::new(),
+            _ => bind,
+        };
+        let mut state = self.state.write().unwrap();
+        let myself = state.myself.clone();
+        let node = state.nodes.get_mut(&myself).unwrap();
+        if node.host != host || node.port != port {
+            node.host = host;
+            node.port = port;
+            state.dirty = true;
+        }
+        for node in state.nodes.values().filter(|node| node.id != myself) {
+            self.connect(node);
+        }
+        state.update_ok();
+        let primary = state.myself().primary.as_ref().and_then(|id| state.nodes.get(id)).map(|node| (node.host.clone(), node.port));
+        drop(state);
+        if primary.is_some() {
+            self.replication.replicate(primary);
+        }
+        spawn_named("cluster-cron", self.clone().cron());
+    }
+
+    /// 检查命令访问的键是否由本节点负责
+    ///
+    /// # Arguments
+    /// * `cmd` - 要执行的命令
+    /// * `asking` - 连接在这个命令之前发送了 ASKING
+    ///
+    /// # Returns
+    /// * `Ok(())` - 命令不访问键，或所有键所在的槽都由本节点负责；槽正在迁出时键都还在本节点，
+    ///   槽正在迁入时客户端发送了 ASKING
+    /// * `Err(RedoxError)` - 键不在同一个槽（CROSSSLOT）、集群不可用（CLUSTERDOWN）、槽由其他节点负责（MOVED）、
+    ///   键已迁出（ASK）或多个键中只有一部分在本节点（TRYAGAIN）
+    pub async fn route(&self, cmd: &Command, asking: bool) -> Result<(), RedoxError> {
+        let keys = cmd.keys();
+        let Some((first, rest)) = keys.split_first() else {
+            return Ok(());
+        };
+        let slot = slot::key_slot(first);
+        if rest.iter().any(|key| slot::key_slot(key) != slot) {
+            return Err(RedoxError::CrossSlot);
+        }
+        // 槽正在迁出时为迁入的节点的地址，正在迁入时为 None
+        let target = {
+            let state = self.state.read().unwrap();
+            if !state.ok {
+                return Err(RedoxError::ClusterDown("The cluster is down".to_string()));
+            }
+            match &state.owners[slot as usize] {
+                Some(owner) if *owner == state.myself => match state.migrating.get(&slot) {
+                    Some(target) => Some(state.nodes.get(target).map(Node::addr).unwrap_or_default()),
+                    None => return Ok(()),
+                },
+                _ if asking && state.importing.contains_key(&slot) => None,
+                Some(owner) => return Err(RedoxError::Moved { slot, addr: state.nodes[owner].addr() }),
+                None => return Err(RedoxError::ClusterDown("Hash slot not served".to_string())),
+            }
+        };
+        // 迁移中的槽由 MIGRATE 在本节点上处理
+        if matches!(cmd, Command::Migrate { .. }) {
+            return Ok(());
+        }
+        let existing = self.storage.count_existing(&keys).await;
+        if existing == keys.len() {
+            return Ok(());
+        }
+        // 源节点上部分键已迁出、目标节点上部分键还没有迁入时，多键命令只能稍后重试
+        match target {
+            Some(addr) if existing == 0 => Err(RedoxError::Ask { slot, addr }),
+            None if keys.len() == 1 => Ok(()),
+            _ => Err(RedoxError::TryAgain("Multiple keys request during rehashing of slot".to_string())),
+        }
+    }
+
+    /// 执行 CLUSTER 命令（CLUSTER BUS 除外）
+    ///
+    /// # Arguments
+    /// * `cmd` - 要执行的命令
+    /// * `local` - 客户端连接的本地地址，本节点还不知道自己的地址时用于 CLUSTER SLOTS 和 SHARDS
+    pub async fn execute(self: &Arc<Self>, cmd: Command, local: IpAddr) -> Response {
+        let response = match cmd {
+            Command::ClusterMeet { host, port } => self.meet(host, port),
+            Command::ClusterAddSlots(slots) => self.add_slots(&slots),
+            Command::ClusterDelSlots(slots) => self.del_slots(&slots),
+            Command::ClusterForget { id } => self.forget(&id),
+            Command::ClusterKeySlot { key } => Response::Integer(slot::key_slot(&key) as i64),
+            Command::ClusterMyId => Response::Value(RedoxValue::string(self.state.read().unwrap().myself.clone())),
+            Command::ClusterInfo => Response::Info(self.info()),
+            Command::ClusterNodes => self.nodes(),
+            Command::ClusterSlots => self.slots(local),
+            Command::ClusterShards => self.shards(local),
+            Command::ClusterReplicas { id } => self.replicas(&id),
+            Command::ClusterReplicate { id } => self.replicate(&id),
+            Command::ClusterSetSlot { slot, state } => self.set_slot(slot, state).await,
+            Command::ClusterGetKeysInSlot { slot, count } => Response::Array(
+                self.keys_in_slot(slot, count).await.into_iter().map(Some).collect(),
+            ),
+            Command::ClusterCountKeysInSlot { slot } => Response::Integer(self.keys_in_slot(slot, usize::MAX).await.len() as i64),
+            cmd => Response::Error(format!("'{}' is not a CLUSTER command", cmd.name()).into()),
+        };
+        self.save();
+        response
+    }
+
+    /// 处理 CLUSTER MEET：以临时 ID 加入节点并开始握手，收到第一个 PONG 后换成节点的真实 ID
+    fn meet(self: &Arc<Self>, host: String, port: u16) -> Response {
+        let mut state = self.state.write().unwrap();
+        let mut node = Node::new(random_id(), host, port);
+        node.handshake = true;
+        self.connect(&node);
+        state.nodes.insert(node.id.clone(), node);
+        Response::Ok
+    }
+
+    /// 处理 CLUSTER ADDSLOTS，任何一个槽已经有节点负责时都不修改
+    fn add_slots(&self, slots: &[u16]) -> Response {
+        let mut state = self.state.write().unwrap();
+        if let Some(slot) = slots.iter().find(|&&slot| state.owners[slot as usize].is_some()) {
+            return Response::Error(format!("Slot {} is already busy", slot).into());
+        }
+        let myself = state.myself.clone();
+        for &slot in slots {
+            state.owners[slot as usize] = Some(myself.clone());
+        }
+        state.dirty = true;
+        state.update_ok();
+        Response::Ok
+    }
+
+    /// 处理 CLUSTER DELSLOTS，任何一个槽没有节点负责时都不修改
+    fn del_slots(&self, slots: &[u16]) -> Response {
+        let mut state = self.state.write().unwrap();
+        if let Some(slot) = slots.iter().find(|&&slot| state.owners[slot as usize].is_none()) {
+            return Response::Error(format!("Slot {} is already unassigned", slot).into());
+        }
+        for &slot in slots {
+            state.owners[slot as usize] = None;
+        }
+        state.dirty = true;
+        state.update_ok();
+        Response::Ok
+    }
+
+    /// 处理 CLUSTER FORGET：删除节点和它负责的槽的归属，一段时间内不从 gossip 中重新加入
+    fn forget(&self, id: &str) -> Response {
+        let mut state = self.state.write().unwrap();
+        if id == state.myself {
+            return Response::Error("I tried hard but I can't forget myself...".into());
+        }
+        let Some(node) = state.nodes.remove(id) else {
+            return Response::Error(format!("Unknown node {}", id).into());
+        };
+        node.link.cancel();
+        for owner in state.owners.iter_mut() {
+            if owner.as_deref() == Some(id) {
+                *owner = None;
+            }
+        }
+        state.forgotten.insert(id.to_string(), Instant::now() + FORGET_TTL);
+        state.dirty = true;
+        state.update_ok();
+        Response::Ok
+    }
+
+    /// 本节点上槽中的键，最多 `count` 个
+    async fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<Bytes> {
+        self.storage.keys_where(count, |key| slot::key_slot(key) == slot).await
+    }
+
+    /// 处理 CLUSTER SETSLOT：设置槽的迁入或迁出状态，或在迁移结束时改变槽的归属
+    async fn set_slot(&self, slot: u16, slot_state: SlotState) -> Response {
+        // 本节点仍有这个槽的键时不能把槽交给其他节点
+        let holds_keys = matches!(slot_state, SlotState::Node(_)) && !self.keys_in_slot(slot, 1).await.is_empty();
+        let mut state = self.state.write().unwrap();
+        if state.myself().primary.is_some() {
+            return Response::Error("Please use SETSLOT only with masters.".into());
+        }
+        let mine = state.owners[slot as usize].as_deref() == Some(state.myself.as_str());
+        if let SlotState::Importing(id) | SlotState::Migrating(id) | SlotState::Node(id) = &slot_state {
+            match state.nodes.get(id) {
+                Some(node) if !node.handshake && node.primary.is_none() => {}
+                Some(node) if !node.handshake => return Response::Error("Target node is not a master".into()),
+                _ => return Response::Error(format!("I don't know about node {}", id).into()),
+            }
+        }
+        match slot_state {
+            SlotState::Migrating(id) => {
+                if !mine {
+                    return Response::Error(format!("I'm not the owner of hash slot {}", slot).into());
+                }
+                if id == state.myself {
+                    return Response::Error("Can't MIGRATE to myself".into());
+                }
+                state.migrating.insert(slot, id);
+            }
+            SlotState::Importing(id) => {
+                if mine {
+                    return Response::Error(format!("I'm already the owner of hash slot {}", slot).into());
+                }
+                if id == state.myself {
+                    return Response::Error("Can't IMPORT from myself".into());
+                }
+                state.importing.insert(slot, id);
+            }
+            SlotState::Stable => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+            SlotState::Node(id) => {
+                if id != state.myself {
+                    if mine && holds_keys {
+                        return Response::Error(format!(
+                            "Can't assign hashslot {} to a different node while I still hold keys for this hash slot.",
+                            slot,
+                        ).into());
+                    }
+                    state.migrating.remove(&slot);
+                } else if state.importing.remove(&slot).is_some() {
+                    // 迁入结束：取一个新的配置纪元，原来的节点收到心跳后放弃这个槽
+                    state.bump_epoch();
+                }
+                if state.owners[slot as usize].as_ref() != Some(&id) {
+                    notice!("Slot {} is now served by node {}", slot, id);
+                }
+                state.owners[slot as usize] = Some(id);
+                state.update_ok();
+            }
+        }
+        state.dirty = true;
+        Response::Ok
+    }
+
+    /// 处理 CLUSTER REPLICATE：本节点成为指定的主节点的副本，之后本节点的数据由主节点的数据替换
+    fn replicate(&self, id: &str) -> Response {
+        let mut state = self.state.write().unwrap();
+        let Some(node) = state.nodes.get(id).filter(|node| !node.handshake) else {
+            return Response::Error(format!("Unknown node {}", id).into());
+        };
+        if id == state.myself {
+            return Response::Error("Can't replicate myself".into());
+        }
+        if node.primary.is_some() {
+            return Response::Error("I can only replicate a master, not a replica.".into());
+        }
+        let primary = (node.host.clone(), node.port);
+        if state.owners.iter().any(|owner| owner.as_deref() == Some(&state.myself)) {
+            return Response::Error("To set a master the node must be empty and without assigned slots.".into());
+        }
+        let myself = state.myself.clone();
+        let node = state.nodes.get_mut(&myself).unwrap();
+        if node.primary.as_deref() != Some(id) {
+            notice!("Node is now a replica of node {}", id);
+            node.primary = Some(id.to_string());
+            state.dirty = true;
+        }
+        drop(state);
+        self.replication.replicate(Some(primary));
+        Response::Ok
+    }
+
+    /// CLUSTER NODES 的回复：每个节点一个元素，按 ID 排序
+    /// 与 Redis 的多行文本不同，行协议中回复不能包含换行符
+    fn nodes(&self) -> Response {
+        let state = self.state.read().unwrap();
+        let mut nodes: Vec<&Node> = state.nodes.values().collect();
+        nodes.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        Response::Array(nodes.into_iter().map(|node| Some(state.describe(node).into())).collect())
+    }
+
+    /// CLUSTER REPLICAS 的回复：主节点的每个副本一行，格式与 CLUSTER NODES 相同
+    fn replicas(&self, id: &str) -> Response {
+        let state = self.state.read().unwrap();
+        match state.nodes.get(id) {
+            None => Response::Error(format!("Unknown node {}", id).into()),
+            Some(node) if node.primary.is_some() => Response::Error("The specified node is not a master".into()),
+            Some(_) => Response::Array(state.replicas(id).into_iter().map(|node| Some(state.describe(node).into())).collect()),
+        }
+    }
+
+    /// CLUSTER SLOTS 的回复：以 `start-end` 为键，每个槽范围包括起止的槽、负责的主节点的 `[地址, 端口, ID]`
+    /// 和没有下线的副本（以 ID 为键的 `[地址, 端口]`）
+    fn slots(&self, local: IpAddr) -> Response {
+        let state = self.state.read().unwrap();
+        let mut ranges = Vec::new();
+        for primary in state.primaries() {
+            let (host, port) = primary.endpoint(local);
+            let replicas: Vec<(String, String, u16)> = state.replicas(&primary.id).into_iter()
+                .filter(|replica| !replica.fail)
+                .map(|replica| {
+                    let (host, port) = replica.endpoint(local);
+                    (replica.id.clone(), host, port)
+                })
+                .collect();
+            let addr = |host: &str, port: u16| vec![Some(host.to_string().into()), Some(port.to_string().into())];
+            for (start, end) in state.slot_ranges(&primary.id) {
+                let mut master = addr(&host, port);
+                master.push(Some(primary.id.clone().into()));
+                let replicas = replicas.iter().map(|(id, host, port)| (id.clone(), Response::Array(addr(host, *port))));
+                let fields = vec![
+                    ("start".to_string(), Response::Integer(start as i64)),
+                    ("end".to_string(), Response::Integer(end as i64)),
+                    ("master".to_string(), Response::Array(master)),
+                    ("replicas".to_string(), Response::Map(replicas.collect())),
+                ];
+                ranges.push((start, format!("{}-{}", start, end), Response::Map(fields)));
+            }
+        }
+        ranges.sort_unstable_by_key(|(start, _, _)| *start);
+        Response::Map(ranges.into_iter().map(|(_, range, fields)| (range, fields)).collect())
+    }
+
+    /// CLUSTER SHARDS 的回复：以主节点的 ID 为键，每个分片包括主节点负责的槽范围（依次为起止的槽）
+    /// 和分片中的节点（以 ID 为键，主节点在前）
+    fn shards(&self, local: IpAddr) -> Response {
+        let state = self.state.read().unwrap();
+        let shards = state.primaries().into_iter().map(|primary| {
+            let slots = state.slot_ranges(&primary.id).into_iter()
+                .flat_map(|(start, end)| [Some(start.to_string().into()), Some(end.to_string().into())])
+                .collect();
+            let nodes = std::iter::once(primary).chain(state.replicas(&primary.id)).map(|node| {
+                let (host, port) = node.endpoint(local);
+                let (repl_offset, loading) = if node.id == state.myself {
+                    (self.replication.offset(), self.replication.is_loading())
+                } else {
+                    (node.repl_offset, node.loading)
+                };
+                // 已下线为 failed，还没有完成全量同步的副本为 loading
+                let health = if node.fail { "failed" } else if loading { "loading" } else { "online" };
+                let fields = vec![
+                    ("id".to_string(), Response::Value(RedoxValue::string(node.id.clone()))),
+                    ("port".to_string(), Response::Integer(port as i64)),
+                    ("ip".to_string(), Response::Value(RedoxValue::string(host.clone()))),
+                    ("endpoint".to_string(), Response::Value(RedoxValue::string(host))),
+                    ("role".to_string(), Response::Value(RedoxValue::string(node.role()))),
+                    ("replication-offset".to_string(), Response::Integer(repl_offset as i64)),
+                    ("health".to_string(), Response::Value(RedoxValue::string(health))),
+                ];
+                (node.id.clone(), Response::Map(fields))
+            });
+            let fields = vec![
+                ("slots".to_string(), Response::Array(slots)),
+                ("nodes".to_string(), Response::Map(nodes.collect())),
+            ];
+            (primary.id.clone(), Response::Map(fields))
+        });
+        Response::Map(shards.collect())
+    }
+
+    /// CLUSTER INFO 的字段，与 Redis 相同
+    fn info(&self) -> HashMap<String, String> {
+        let state = self.state.read().unwrap();
+        let (mut assigned, mut pfail, mut fail) = (0, 0, 0);
+        for owner in state.owners.iter().flatten() {
+            assigned += 1;
+            match state.nodes.get(owner) {
+                Some(node) if node.fail => fail += 1,
+                Some(node) if node.pfail => pfail += 1,
+                _ => {}
+            }
+        }
+        let mut info = HashMap::new();
+        info.insert("cluster_state".to_string(), if state.ok { "ok" } else { "fail" }.to_string());
+        info.insert("cluster_slots_assigned".to_string(), assigned.to_string());
+        info.insert("cluster_slots_ok".to_string(), (assigned - pfail - fail).to_string());
+        info.insert("cluster_slots_pfail".to_string(), pfail.to_string());
+        info.insert("cluster_slots_fail".to_string(), fail.to_string());
+        info.insert("cluster_known_nodes".to_string(), state.nodes.len().to_string());
+        info.insert("cluster_size".to_string(), state.size().to_string());
+        info.insert("cluster_current_epoch".to_string(), state.current_epoch.to_string());
+        info.insert("cluster_my_epoch".to_string(), state.myself().config_epoch.to_string());
+        info
+    }
+
+    /// 处理其他节点发来的集群总线连接：每收到一个 MEET 或 PING 回复一个 PONG，直到连接断开或 `kill` 被取消
+    ///
+    /// # Arguments
+    /// * `socket` - 已回复 CLUSTER BUS 的连接
+    /// * `peer` - 对端的地址，发送者没有报告自己的地址时使用
+    /// * `kill` - 服务器关闭或 CLIENT KILL 时取消
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+        self: &Arc<Self>,
+        mut socket: S,
+        peer: SocketAddr,
+        kill: CancellationToken,
+    ) -> io::Result<()> {
+        let mut buf = BytesMut::new();
+        loop {
+            let message = tokio::select! {
+                message = read_message(&mut socket, &mut buf) => message,
+                _ = kill.cancelled() => return Ok(()),
+            };
+            let message = match message {
+                Ok(message) => message,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            match message {
+                Message::Meet(header) => self.receive(header, peer.ip(), true),
+                Message::Ping(header) => self.receive(header, peer.ip(), false),
+                Message::Pong(_) => return Err(invalid_data("unexpected PONG on an incoming cluster bus connection")),
+            }
+            let mut header = self.header();
+            header.peer_host = peer.ip().to_string();
+            let pong = Message::Pong(header);
+            write_message(&mut socket, &pong).await?;
+            self.save();
+        }
+    }
+
+    /// 本节点的状态，作为消息的头部
+    fn header(&self) -> Header {
+        let state = self.state.read().unwrap();
+        let myself = state.myself();
+        let mut slots = vec![0u8; SLOTS as usize / 8];
+        for (slot, owner) in state.owners.iter().enumerate() {
+            if owner.as_deref() == Some(&state.myself) {
+                slots[slot / 8] |= 1 << (slot % 8);
+            }
+        }
+        let gossip = state.nodes.values()
+            .filter(|node| node.id != state.myself && !node.handshake)
+            .map(|node| Gossip {
+                id: node.id.clone(),
+                host: node.host.clone(),
+                port: node.port,
+                failing: node.pfail || node.fail,
+            })
+            .collect();
+        Header {
+            id: myself.id.clone(),
+            host: myself.host.clone(),
+            port: myself.port,
+            current_epoch: state.current_epoch,
+            config_epoch: myself.config_epoch,
+            slots,
+            primary: myself.primary.clone(),
+            repl_offset: self.replication.offset(),
+            loading: self.replication.is_loading(),
+            peer_host: String::new(),
+            gossip,
+        }
+    }
+
+    /// 处理其他节点的 MEET 或 PING
+    ///
+    /// # Arguments
+    /// * `header` - 发送者的状态
+    /// * `peer` - 连接的对端地址
+    /// * `meet` - 是否是 MEET，只有 MEET 会加入未知的发送者
+    fn receive(self: &Arc<Self>, header: Header, peer: IpAddr, meet: bool) {
+        let mut state = self.state.write().unwrap();
+        if header.id == state.myself {
+            return;
+        }
+        if !state.nodes.contains_key(&header.id) {
+            if !meet || state.forgotten.contains_key(&header.id) {
+                return;
+            }
+            let host = if header.host.is_empty() { peer.to_string() } else { header.host.clone() };
+            notice!("Node {} at {}:{} joined the cluster", header.id, host, header.port);
+            let node = Node::new(header.id.clone(), host, header.port);
+            self.connect(&node);
+            state.nodes.insert(node.id.clone(), node);
+            state.dirty = true;
+        }
+        self.apply_header(&mut state, header, peer);
+    }
+
+    /// 处理连接的节点回复的 PONG
+    ///
+    /// # Arguments
+    /// * `id` - 连接的节点在本节点的 ID，握手时是临时 ID
+    /// * `header` - 回复者的状态
+    /// * `peer` - 连接的对端地址
+    ///
+    /// # Returns
+    /// 之后连接使用的节点 ID，握手完成时是节点的真实 ID；None 表示节点已被删除或与已知的节点重复，应当断开
+    fn on_pong(self: &Arc<Self>, id: &str, header: Header, peer: IpAddr) -> Option<String> {
+        let mut state = self.state.write().unwrap();
+        let node = state.nodes.get(id)?;
+        if node.handshake {
+            // 握手完成：对方是本节点或已知的节点时删除临时的节点，否则换成对方的真实 ID
+            let mut node = state.nodes.remove(id).unwrap();
+            if header.id == state.myself || state.nodes.contains_key(&header.id) {
+                return None;
+            }
+            notice!("Handshake with node {} at {}:{} completed", header.id, node.host, node.port);
+            node.id = header.id.clone();
+            node.handshake = false;
+            state.nodes.insert(node.id.clone(), node);
+            state.dirty = true;
+        } else if header.id != id {
+            // 同一个地址上的节点换了 ID（例如删除了集群配置文件后重启），不更新原来的节点
+            return Some(id.to_string());
+        }
+        // 本节点监听所有地址时从回复中得知其他节点用哪个地址连接本节点
+        let myself = state.myself.clone();
+        let me = state.nodes.get_mut(&myself).unwrap();
+        if me.host.is_empty() && !header.peer_host.is_empty() {
+            notice!("Cluster node address set to {}", header.peer_host);
+            me.host = header.peer_host.clone();
+            state.dirty = true;
+        }
+        let node = state.nodes.get_mut(&header.id).unwrap();
+        node.ping_sent = 0;
+        node.pong_received = now_ms();
+        node.connected = true;
+        node.fail_reports.clear();
+        if node.pfail || node.fail {
+            notice!("Node {} is reachable again", node.id);
+            node.pfail = false;
+            node.fail = false;
+            state.update_ok();
+        }
+        let id = header.id.clone();
+        self.apply_header(&mut state, header, peer);
+        Some(id)
+    }
+
+    /// 根据已知的发送者的状态更新纪元、槽的归属和已知的节点
+    fn apply_header(self: &Arc<Self>, state: &mut State, header: Header, peer: IpAddr) {
+        if header.current_epoch > state.current_epoch {
+            state.current_epoch = header.current_epoch;
+            state.dirty = true;
+        }
+        let host = if header.host.is_empty() { peer.to_string() } else { header.host };
+        let myself = state.myself.clone();
+        let following = state.myself().primary.as_deref() == Some(header.id.as_str());
+        let sender = state.nodes.get_mut(&header.id).unwrap();
+        sender.repl_offset = header.repl_offset;
+        sender.loading = header.loading;
+        let moved = sender.host != host || sender.port != header.port;
+        if moved || sender.config_epoch != header.config_epoch || sender.primary != header.primary {
+            if sender.primary != header.primary {
+                match &header.primary {
+                    Some(primary) => notice!("Node {} is now a replica of node {}", header.id, primary),
+                    None => notice!("Node {} is now a master", header.id),
+                }
+            }
+            sender.host = host;
+            sender.port = header.port;
+            sender.config_epoch = header.config_epoch;
+            sender.primary = header.primary.clone();
+            state.dirty = true;
+        }
+        // 本节点复制的主节点换了地址时重新连接
+        if moved && following {
+            let sender = &state.nodes[&header.id];
+            self.replication.replicate(Some((sender.host.clone(), sender.port)));
+        }
+
+        // 发送者声明的槽没有节点负责，或负责的节点的配置纪元较小时，改由发送者负责
+        let mut claimed = 0;
+        for slot in 0..SLOTS as usize {
+            if header.slots.get(slot / 8).is_none_or(|byte| byte & (1 << (slot % 8)) == 0) {
+                continue;
+            }
+            // 迁入中的槽的归属只由 CLUSTER SETSLOT 改变
+            if state.importing.contains_key(&(slot as u16)) {
+                continue;
+            }
+            let newer = match &state.owners[slot] {
+                Some(owner) if *owner == header.id => continue,
+                Some(owner) => state.nodes.get(owner).is_none_or(|node| node.config_epoch < header.config_epoch),
+                None => true,
+            };
+            if newer {
+                if state.owners[slot].as_deref() == Some(myself.as_str()) {
+                    state.migrating.remove(&(slot as u16));
+                }
+                state.owners[slot] = Some(header.id.clone());
+                claimed += 1;
+            }
+        }
+        if claimed > 0 {
+            notice!("{} slot(s) are now served by node {} (config epoch {})", claimed, header.id, header.config_epoch);
+            state.dirty = true;
+            state.update_ok();
+        }
+
+        // 主节点之间的配置纪元冲突：ID 较小的节点取一个新的纪元，之后两者声明同一个槽时可以区分先后
+        let both_primaries = header.primary.is_none() && state.myself().primary.is_none();
+        if both_primaries && header.config_epoch == state.myself().config_epoch && myself < header.id {
+            state.current_epoch += 1;
+            let epoch = state.current_epoch;
+            state.nodes.get_mut(&myself).unwrap().config_epoch = epoch;
+            state.dirty = true;
+            notice!("Config epoch collision with node {}, config epoch set to {}", header.id, epoch);
+        }
+
+        // 从 gossip 中发现新的节点，发送者是主节点时记录它对其他节点的下线报告
+        let now = Instant::now();
+        state.forgotten.retain(|_, until| *until > now);
+        for gossip in header.gossip {
+            if gossip.id == myself {
+                continue;
+            }
+            match state.nodes.get_mut(&gossip.id) {
+                Some(node) => {
+                    if gossip.failing && header.primary.is_none() {
+                        node.fail_reports.insert(header.id.clone(), now);
+                    } else {
+                        node.fail_reports.remove(&header.id);
+                    }
+                }
+                None if state.forgotten.contains_key(&gossip.id) => {}
+                None => {
+                    notice!("Node {} at {}:{} discovered through node {}", gossip.id, gossip.host, gossip.port, header.id);
+                    let node = Node::new(gossip.id, gossip.host, gossip.port);
+                    self.connect(&node);
+                    state.nodes.insert(node.id.clone(), node);
+                    state.dirty = true;
+                }
+            }
+        }
+    }
+
+    /// 启动连接节点的任务，节点被删除时 `node.link` 被取消
+    fn connect(self: &Arc<Self>, node: &Node) {
+        let task = self.clone().link(node.id.clone(), node.host.clone(), node.port, node.link.clone());
+        spawn_named(&format!("cluster-link {}:{}", node.host, node.port), task);
+    }
+
+    /// 连接一个节点并每秒发送 PING，连接断开后等待一段时间重连，直到节点被删除
+    async fn link(self: Arc<Self>, mut id: String, host: String, port: u16, cancel: CancellationToken) {
+        loop {
+            let result = tokio::select! {
+                result = self.exchange(&mut id, &host, port) => result,
+                _ = cancel.cancelled() => return,
+            };
+            let was_connected = {
+                let mut state = self.state.write().unwrap();
+                match state.nodes.get_mut(&id) {
+                    Some(node) => std::mem::replace(&mut node.connected, false),
+                    None => return,
+                }
+            };
+            match result {
+                Ok(()) => return,
+                Err(e) if was_connected => warning!("Cluster bus to {}:{} lost: {}", host, port, e),
+                Err(_) => {}
+            }
+            tokio::select! {
+                _ = time::sleep(RECONNECT_DELAY) => {}
+                _ = cancel.cancelled() => return,
+            }
+        }
+    }
+
+    /// 建立到节点的集群总线，之后每秒发送 PING 并等待 PONG
+    ///
+    /// # Returns
+    /// * `Ok(())` - 节点已被删除或与已知的节点重复，不再连接
+    /// * `Err` - 连接失败、断开或超时，需要重连
+    async fn exchange(self: &Arc<Self>, id: &mut String, host: &str, port: u16) -> io::Result<()> {
+        let stream = time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))??;
+        stream.set_nodelay(true)?;
+        let peer = stream.peer_addr()?.ip();
+        let mut framed = Framed::new(stream, ClientCodec::new());
+        let (username, password) = {
+            let config = self.config.read().unwrap();
+            (config.masteruser.clone(), config.masterauth.clone())
+        };
+        if let Some(password) = password {
+            request(&mut framed, &Command::Auth { username, password }).await?;
+        }
+        request(&mut framed, &Command::ClusterBus).await?;
+        let FramedParts { io: mut stream, read_buf: mut buf, .. } = framed.into_parts();
+
+        let mut interval = time::interval(PING_INTERVAL);
+        loop {
+            interval.tick().await;
+            let message = {
+                let mut state = self.state.write().unwrap();
+                let Some(node) = state.nodes.get_mut(id.as_str()) else {
+                    return Ok(());
+                };
+                if node.ping_sent == 0 {
+                    node.ping_sent = now_ms();
+                }
+                let handshake = node.handshake;
+                drop(state);
+                if handshake { Message::Meet(self.header()) } else { Message::Ping(self.header()) }
+            };
+            write_message(&mut stream, &message).await?;
+            let timeout = Duration::from_millis(self.config.read().unwrap().cluster_node_timeout);
+            let reply = time::timeout(timeout, read_message(&mut stream, &mut buf))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no PONG from the node"))??;
+            let Message::Pong(header) = reply else {
+                return Err(invalid_data("expected PONG"));
+            };
+            match self.on_pong(id, header, peer) {
+                Some(new_id) => *id = new_id,
+                None => return Ok(()),
+            }
+            self.save();
+        }
+    }
+
+    /// 定期检查节点是否下线和集群是否可用，并保存修改过的集群配置
+    async fn cron(self: Arc<Self>) {
+        let mut interval = time::interval(CRON_INTERVAL);
+        loop {
+            interval.tick().await;
+            let timeout = self.config.read().unwrap().cluster_node_timeout;
+            {
+                let mut state = self.state.write().unwrap();
+                self.detect_failures(&mut state, timeout);
+                state.update_ok();
+            }
+            self.save();
+        }
+    }
+
+    /// 标记超时没有回复的节点为疑似下线，多数负责槽的主节点都报告疑似下线时标记为下线；
+    /// 删除握手超时的节点
+    fn detect_failures(&self, state: &mut State, timeout: u64) {
+        let now = now_ms();
+        let report_validity = Duration::from_millis(timeout * 2);
+        let needed = state.size() / 2 + 1;
+        let myself = state.myself.clone();
+        // 本节点是主节点时自己也认为它疑似下线，计入报告数
+        let own_report = usize::from(state.myself().primary.is_none());
+        state.nodes.retain(|id, node| {
+            let expired = node.handshake && node.created.elapsed() > Duration::from_millis(timeout).max(PING_INTERVAL * 5);
+            if expired {
+                notice!("Handshake with {}:{} timed out", node.host, node.port);
+                node.link.cancel();
+            }
+            !expired || *id == myself
+        });
+        for node in state.nodes.values_mut() {
+            if node.id == myself || node.handshake {
+                continue;
+            }
+            if !node.pfail && now.saturating_sub(node.pong_received) > timeout {
+                notice!("Node {} is not responding, marking it as possibly failing", node.id);
+                node.pfail = true;
+            }
+            node.fail_reports.retain(|_, reported| reported.elapsed() <= report_validity);
+            let reports = node.fail_reports.len() + own_report;
+            if node.pfail && !node.fail && reports >= needed {
+                warning!("Marking node {} as failing ({} of {} node(s) agree)", node.id, reports, needed);
+                node.fail = true;
+            }
+        }
+    }
+
+    /// 集群配置有修改时写入集群配置文件，先写入临时文件再重命名，写入中途失败时原来的文件不受影响
+    fn save(&self) {
+        let content = {
+            let mut state = self.state.write().unwrap();
+            if !state.dirty {
+                return;
+            }
+            state.dirty = false;
+            state.to_config_file()
+        };
+        let temp = format!("{}.tmp", self.path);
+        if let Err(e) = std::fs::write(&temp, content).and_then(|_| std::fs::rename(&temp, &self.path)) {
+            warning!("Error saving cluster config file {}: {}", self.path, e);
+            self.state.write().unwrap().dirty = true;
+        }
+    }
+}
+
+/// 解析集群配置文件
+///
+/// # Returns
+/// * `Ok(State)` - 文件中的节点、槽的归属和纪元
+/// * `Err(String)` - 格式无效或没有标记为 myself 的节点
+fn parse_config_file(content: &str) -> Result<State, String> {
+    let mut state = State {
+        myself: String::new(),
+        current_epoch: 0,
+        nodes: HashMap::new(),
+        owners: vec![None; SLOTS as usize],
+        forgotten: HashMap::new(),
+        migrating: BTreeMap::new(),
+        importing: BTreeMap::new(),
+        ok: false,
+        dirty: false,
+    };
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields[0] == "vars" {
+            for pair in fields[1..].chunks(2) {
+                if let [name, value] = pair {
+                    if *name == "currentEpoch" {
+                        state.current_epoch = value.parse().map_err(|_| format!("invalid currentEpoch: {}", value))?;
+                    }
+                }
+            }
+            continue;
+        }
+        if fields.len() < 8 {
+            return Err(format!("invalid line: {}", line));
+        }
+        let addr = fields[1].split('@').next().unwrap_or_default();
+        let (host, port) = addr.rsplit_once(':').ok_or_else(|| format!("invalid address: {}", fields[1]))?;
+        let mut node = Node::new(
+            fields[0].to_string(),
+            host.to_string(),
+            port.parse().map_err(|_| format!("invalid address: {}", fields[1]))?,
+        );
+        node.config_epoch = fields[6].parse().map_err(|_| format!("invalid config epoch: {}", fields[6]))?;
+        if fields[3] != "-" {
+            node.primary = Some(fields[3].to_string());
+        }
+        if fields[2].split(',').any(|flag| flag == "myself") {
+            state.myself = node.id.clone();
+        }
+        for range in fields.iter().skip(8) {
+            // 迁移中的槽：[slot->-target] 或 [slot-<-source]
+            if let Some(open) = range.strip_prefix('[').and_then(|range| range.strip_suffix(']')) {
+                let (slot, migrating, id) = match (open.split_once("->-"), open.split_once("-<-")) {
+                    (Some((slot, id)), _) => (slot, true, id),
+                    (_, Some((slot, id))) => (slot, false, id),
+                    _ => return Err(format!("invalid slot state: {}", range)),
+                };
+                let slot = slot.parse::<u16>().ok().filter(|&slot| slot < SLOTS).ok_or_else(|| format!("invalid slot state: {}", range))?;
+                if migrating {
+                    state.migrating.insert(slot, id.to_string());
+                } else {
+                    state.importing.insert(slot, id.to_string());
+                }
+                continue;
+            }
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let (Ok(start), Ok(end)) = (start.parse::<u16>(), end.parse::<u16>()) else {
+                return Err(format!("invalid slot range: {}", range));
+            };
+            if start > end || end >= SLOTS {
+                return Err(format!("invalid slot range: {}", range));
+            }
+            for slot in start..=end {
+                state.owners[slot as usize] = Some(node.id.clone());
+            }
+        }
+        state.nodes.insert(node.id.clone(), node);
+    }
+    if state.myself.is_empty() {
+        return Err("no node is flagged as myself".to_string());
+    }
+    Ok(state)
+}
+
+/// 生成 40 个十六进制字符的随机节点 ID
+fn random_id() -> String {
+    format!("{:016x}{:016x}{:08x}", fastrand::u64(..), fastrand::u64(..), fastrand::u32(..))
+}
+
+/// 从集群总线读取一条消息
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut BytesMut) -> io::Result<Message> {
+    loop {
+        if let Some(frame) = compact::take_frame(buf, MAX_FRAME_LEN).map_err(invalid_data)? {
+            return BinaryFormat::Bincode.decode(&frame).map_err(invalid_data);
+        }
+        if reader.read_buf(buf).await? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "cluster bus connection closed"));
+        }
+    }
+}
+
+/// 编码一条消息并写入集群总线
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> io::Result<()> {
+    let mut frame = Vec::new();
+    BinaryFormat::Bincode.encode_frame(message, &mut frame).map_err(io::Error::other)?;
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+/// 建立集群总线时发送一个命令，回复不是 OK 时返回错误
+async fn request(framed: &mut Framed<TcpStream, ClientCodec>, cmd: &Command) -> io::Result<()> {
+    framed.send(cmd).await.map_err(|e| io::Error::other(e.to_string()))?;
+    match time::timeout(CONNECT_TIMEOUT, framed.next()).await {
+        Ok(Some(Ok(Response::Ok))) => Ok(()),
+        Ok(Some(Ok(Response::Error(e)))) => Err(io::Error::other(format!("{} failed: {}", cmd.name(), e))),
+        Ok(Some(Ok(_))) => Err(invalid_data(format!("unexpected reply to {}", cmd.name()))),
+        Ok(Some(Err(e))) => Err(io::Error::other(e.to_string())),
+        Ok(None) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the node")),
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "no reply from the node")),
+    }
+}
+
+/// 数据无效的错误
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,570 @@
+//! 数据命令的执行
+
+use crate::dump;
+use crate::geo;
+use crate::glob::glob_match;
+use crate::memory;
+use crate::migrate;
+use crate::storage::Storage;
+use redox_protocol::{Command, RedoxError, RedoxValue, Response};
+
+/// SCAN 没有指定 COUNT 时每次检查的键数
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// 执行数据命令并生成响应
+/// 连接相关的命令（AUTH、PING、RESET 等）、脚本和函数命令由 `network` 模块处理，
+/// 这里只处理对存储的读写，供客户端连接和脚本共用
+/// 
+/// # Arguments
+/// * `storage` - 存储实例
+/// * `cmd` - 要执行的命令
+/// 
+/// # Returns
+/// 命令的响应
+pub async fn execute(storage: &Storage, cmd: Command) -> Response {
+    // 只读副本的数据只由复制修改，客户端和脚本的写命令都被拒绝
+    if cmd.spec().has_flag("write") {
+        if let Err(e) = storage.ensure_writable() {
+            return Response::Error(e);
+        }
+    }
+    // 可能增加内存占用的命令执行前检查内存上限并预留键前缀的配额，脚本中的命令同样经过这里
+    // 预留按命令序列化后的大小估算写入的字节数，命令执行完后释放
+    let _reservation = if cmd.spec().has_flag("denyoom") {
+        if let Err(e) = storage.ensure_memory().await {
+            return Response::Error(e);
+        }
+        let bytes = bincode::serialized_size(&cmd).unwrap_or(0) as usize;
+        match storage.reserve_quota(&cmd.keys(), bytes).await {
+            Ok(reservation) => reservation,
+            Err(e) => return Response::Error(e),
+        }
+    } else {
+        None
+    };
+    apply(storage, cmd).await
+}
+
+/// 执行数据命令，不检查只读、内存上限和配额
+/// 复制 Redis 时用来应用主节点发送的写命令，这些命令已经在主节点上执行过
+///
+/// # Arguments
+/// * `storage` - 存储实例
+/// * `cmd` - 要执行的命令
+///
+/// # Returns
+/// 命令的响应
+pub async fn apply(storage: &Storage, cmd: Command) -> Response {
+    match cmd {
+        // 字符串操作
+        Command::Set { key, value } => {
+            storage.set_string(key, value).await;
+            Response::Ok
+        }
+        Command::Get { key } => {
+            match storage.get_string(&key).await {
+                Ok(Some(value)) => Response::Value(RedoxValue::String(value)),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::Cas { key, expected, value } => {
+            match storage.cas(&key, &expected, value).await {
+                Ok(swapped) => Response::Integer(swapped as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::StrLen { key } => {
+            match storage.strlen(&key).await {
+                Ok(len) => Response::Integer(len as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::GetEx { key, option } => {
+            match storage.getex(&key, option).await {
+                Ok(Some(value)) => Response::Value(RedoxValue::String(value)),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        // 列表操作
+        Command::LPush { key, value } => {
+            match storage.lpush(key, value).await {
+                Ok(len) => Response::Integer(len as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::RPush { key, value } => {
+            match storage.rpush(key, value).await {
+                Ok(len) => Response::Integer(len as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::LPop { key } => {
+            match storage.lpop(&key).await {
+                Ok(Some(value)) => Response::Value(RedoxValue::String(value)),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::RPop { key } => {
+            match storage.rpop(&key).await {
+                Ok(Some(value)) => Response::Value(RedoxValue::String(value)),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::LRange { key, start, stop } => {
+            match storage.lrange(&key, start, stop).await {
+                Ok(list) => Response::Value(RedoxValue::List(list.into())),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::LLen { key } => {
+            match storage.llen(&key).await {
+                Ok(len) => Response::Integer(len as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        // 集合操作
+        Command::SAdd { key, member } => {
+            match storage.sadd(key, member).await {
+                Ok(added) => Response::Integer(added as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::SRem { key, member } => {
+            match storage.srem(&key, &member).await {
+                Ok(removed) => Response::Integer(removed as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::SMembers { key } => {
+            match storage.smembers(&key).await {
+                Ok(members) => Response::Value(RedoxValue::Set(members.into_iter().collect())),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::SIsMember { key, member } => {
+            match storage.sismember(&key, &member).await {
+                Ok(is_member) => Response::Integer(is_member as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::SCard { key } => {
+            match storage.scard(&key).await {
+                Ok(len) => Response::Integer(len as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        // 哈希表操作
+        Command::HSet { key, fields } => {
+            match storage.hset(key, fields).await {
+                Ok(added) => Response::Integer(added as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::HGet { key, field } => {
+            match storage.hget(&key, &field).await {
+                Ok(Some(value)) => Response::Value(RedoxValue::String(value)),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::HDel { key, field } => {
+            match storage.hdel(&key, &field).await {
+                Ok(deleted) => Response::Integer(deleted as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::HGetAll { key } => {
+            match storage.hgetall(&key).await {
+                Ok(hash) => Response::Value(RedoxValue::Hash(hash)),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::HLen { key } => {
+            match storage.hlen(&key).await {
+                Ok(len) => Response::Integer(len as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::HExpire { key, seconds, fields } => integers(storage.hexpire(&key, seconds, &fields).await),
+        Command::HTTL { key, fields } => integers(storage.httl(&key, &fields).await),
+        Command::HPersist { key, fields } => integers(storage.hpersist(&key, &fields).await),
+        // 有序集合操作
+        Command::ZAdd { key, score, member } => {
+            match storage.zadd(key, score, member).await {
+                Ok(added) => Response::Integer(added as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::ZRem { key, member } => {
+            match storage.zrem(&key, &member).await {
+                Ok(removed) => Response::Integer(removed as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::ZRange { key, start, stop } => {
+            match storage.zrange(&key, start, stop).await {
+                Ok(members) => {
+                    let zset = members.into_iter().collect();
+                    Response::Value(RedoxValue::SortedSet(zset))
+                }
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::ZRangeByScore { key, min, max } => {
+            match storage.zrangebyscore(&key, min, max).await {
+                Ok(members) => {
+                    let zset = members.into_iter().collect();
+                    Response::Value(RedoxValue::SortedSet(zset))
+                }
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::ZCard { key } => {
+            match storage.zcard(&key).await {
+                Ok(len) => Response::Integer(len as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::MSet(pairs) => {
+            let count = storage.mset(pairs).await;
+            Response::Integer(count as i64)
+        }
+        Command::MGet(keys) => {
+            let values = storage.mget(&keys).await;
+            Response::Array(values)
+        }
+        Command::Info => {
+            let info = storage.info().await;
+            Response::Info(info)
+        }
+        Command::Del(keys) => {
+            let count = storage.del(&keys).await;
+            Response::Integer(count as i64)
+        }
+        Command::Unlink(keys) => {
+            let count = storage.unlink(&keys).await;
+            Response::Integer(count as i64)
+        }
+        Command::FlushAll { lazy } => {
+            storage.flushall(lazy).await;
+            Response::Ok
+        }
+        Command::Dump { key } => {
+            match storage.dump(&key).await {
+                Some(value) => Response::Value(RedoxValue::string(dump::to_hex(&dump::serialize(&value)))),
+                None => Response::Nil,
+            }
+        }
+        Command::Restore { key, ttl, payload, replace } => {
+            let value = dump::from_hex(&payload)
+                .ok_or_else(|| "DUMP payload version or checksum are wrong".to_string())
+                .and_then(|blob| dump::deserialize(&blob))
+                .map_err(RedoxError::Err);
+            match value {
+                Ok(value) => match storage.restore(key, value, ttl, replace).await {
+                    Ok(()) => Response::Ok,
+                    Err(e) => Response::Error(e),
+                },
+                Err(e) => Response::Error(e),
+            }
+        }
+        cmd @ Command::Migrate { .. } => migrate::migrate(storage, cmd).await,
+        Command::Touch(keys) => {
+            let count = storage.touch(&keys).await;
+            Response::Integer(count as i64)
+        }
+        Command::Scan { cursor, pattern, count } => {
+            let count = count.unwrap_or(DEFAULT_SCAN_COUNT);
+            let (cursor, keys) = storage
+                .scan(cursor, count, |key| pattern.as_ref().is_none_or(|pattern| glob_match(pattern, key)))
+                .await;
+            Response::Cursor(cursor, keys)
+        }
+        Command::Type { key } => {
+            let name = storage.value_type(&key).await.unwrap_or("none");
+            Response::Value(RedoxValue::string(name))
+        }
+        Command::Expire { key, seconds, condition } => {
+            let success = storage.expire(&key, seconds, condition).await;
+            Response::Integer(if success { 1 } else { 0 })
+        }
+        Command::TTL { key } => {
+            match storage.ttl(&key).await {
+                Some(Some(ttl)) => Response::Integer(ttl as i64),
+                Some(None) => Response::Integer(-1),
+                None => Response::Integer(-2),
+            }
+        }
+        Command::Persist { key } => {
+            let success = storage.persist(&key).await;
+            Response::Integer(if success { 1 } else { 0 })
+        }
+        Command::PExpire { key, milliseconds, condition } => {
+            let success = storage.pexpire(&key, milliseconds, condition).await;
+            Response::Integer(if success { 1 } else { 0 })
+        }
+        Command::PTTL { key } => {
+            match storage.pttl(&key).await {
+                Some(Some(ttl)) => Response::Integer(ttl as i64),
+                Some(None) => Response::Integer(-1),
+                None => Response::Integer(-2),
+            }
+        }
+        Command::ExpireAt { key, timestamp, condition } => {
+            let success = storage.expire_at(&key, timestamp, condition).await;
+            Response::Integer(if success { 1 } else { 0 })
+        }
+        Command::PExpireAt { key, timestamp, condition } => {
+            let success = storage.pexpire_at(&key, timestamp, condition).await;
+            Response::Integer(if success { 1 } else { 0 })
+        }
+        // 内省命令
+        Command::ObjectEncoding { key } => {
+            match storage.object_encoding(&key).await {
+                Some(encoding) => Response::Value(RedoxValue::string(encoding.to_string())),
+                None => Response::Nil,
+            }
+        }
+        Command::ObjectIdleTime { key } => {
+            match storage.idle_time(&key).await {
+                Some(seconds) => Response::Integer(seconds as i64),
+                None => Response::Nil,
+            }
+        }
+        Command::MemoryUsage { key, samples } => {
+            let samples = samples.unwrap_or(memory::DEFAULT_SAMPLES);
+            match storage.memory_usage(&key, samples).await {
+                Some(bytes) => Response::Integer(bytes as i64),
+                None => Response::Nil,
+            }
+        }
+        Command::MemoryStats => {
+            let stats = storage.memory_stats().await;
+            let int = |n: usize| Response::Integer(n as i64);
+            let encodings = stats.by_encoding.iter()
+                .map(|(encoding, (bytes, keys))| {
+                    let fields = vec![("keys".to_string(), int(*keys)), ("bytes".to_string(), int(*bytes))];
+                    (encoding.to_string(), Response::Map(fields))
+                })
+                .collect();
+            Response::Map(vec![
+                ("keys.count".to_string(), int(stats.keys)),
+                ("dataset.bytes".to_string(), int(stats.bytes)),
+                ("keys.bytes-per-key".to_string(), int(stats.bytes.checked_div(stats.keys).unwrap_or(0))),
+                ("encodings".to_string(), Response::Map(encodings)),
+            ])
+        }
+        // 地理位置操作
+        Command::GeoAdd { key, members } => {
+            match members.iter().find(|(lon, lat, _)| !geo::is_valid_coord(*lon, *lat)) {
+                Some((lon, lat, _)) => Response::Error(format!("Invalid longitude,latitude pair {},{}", lon, lat).into()),
+                None => match storage.geoadd(key, members).await {
+                    Ok(added) => Response::Integer(added as i64),
+                    Err(e) => Response::Error(e),
+                },
+            }
+        }
+        Command::GeoDist { key, member1, member2, unit } => {
+            match storage.geodist(&key, &member1, &member2).await {
+                Ok(Some(dist)) => Response::Value(RedoxValue::string(format!("{:.4}", dist / unit.to_meters()))),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::GeoPos { key, members } => {
+            match storage.geopos(&key, &members).await {
+                // 每个成员一个 [经度, 纬度] 数组，不存在的成员为 nil
+                Ok(positions) => Response::Replies(positions.into_iter()
+                    .map(|pos| pos.map_or(Response::Nil, coordinates))
+                    .collect()),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::GeoSearch { key, origin, shape, ascending, count, with_coord, with_dist } => {
+            match storage.geosearch(&key, &origin, &shape, ascending, count).await {
+                // 没有 WITHDIST 和 WITHCOORD 时只返回成员名，否则每个成员是一个 [成员, 距离, [经度, 纬度]] 数组
+                Ok(Some(matches)) if !with_dist && !with_coord => {
+                    Response::Array(matches.into_iter().map(|(member, _, _)| Some(member)).collect())
+                }
+                Ok(Some(matches)) => {
+                    let factor = shape.unit().to_meters();
+                    Response::Replies(matches.into_iter()
+                        .map(|(member, dist, point)| {
+                            let mut item = vec![Response::Value(RedoxValue::String(member))];
+                            if with_dist {
+                                item.push(Response::Value(RedoxValue::string(format!("{:.4}", dist / factor))));
+                            }
+                            if with_coord {
+                                item.push(coordinates(point));
+                            }
+                            Response::Replies(item)
+                        })
+                        .collect())
+                }
+                Ok(None) => Response::Error("could not decode requested zset member".into()),
+                Err(e) => Response::Error(e),
+            }
+        }
+        // JSON 文档操作
+        Command::JsonSet { key, path, value } => {
+            match storage.json_set(key, &path, value).await {
+                Ok(true) => Response::Ok,
+                Ok(false) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::JsonGet { key, path } => {
+            match storage.json_get(&key, &path).await {
+                Ok(Some(value)) => Response::Value(RedoxValue::Json(value)),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::JsonDel { key, path } => {
+            match storage.json_del(&key, &path).await {
+                Ok(count) => Response::Integer(count as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::JsonNumIncrBy { key, path, increment } => {
+            match storage.json_numincrby(&key, &path, &increment).await {
+                Ok(Some(value)) => Response::Value(RedoxValue::Json(value)),
+                Ok(None) => Response::Nil,
+                Err(e) => Response::Error(e),
+            }
+        }
+        // 时间序列操作
+        Command::TsCreate { key, retention_ms } => {
+            match storage.ts_create(key, retention_ms.unwrap_or(0)).await {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::TsAdd { key, timestamp, value, retention_ms } => {
+            match storage.ts_add(key, timestamp, value, retention_ms).await {
+                Ok(ts) => Response::Integer(ts as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::TsIncrBy { key, value, timestamp, retention_ms } => {
+            match storage.ts_incrby(key, value, timestamp, retention_ms).await {
+                Ok(ts) => Response::Integer(ts as i64),
+                Err(e) => Response::Error(e),
+            }
+        }
+        Command::TsRange { key, from, to, aggregation } => {
+            match storage.ts_range(&key, from, to, aggregation).await {
+                // 每个样本（或聚合后的每个时间桶）是一个 [时间戳, 值] 对
+                Ok(samples) => Response::Replies(samples.into_iter()
+                    .map(|(ts, value)| Response::Replies(vec![
+                        Response::Integer(ts as i64),
+                        Response::Value(RedoxValue::string(value.to_string())),
+                    ]))
+                    .collect()),
+                Err(e) => Response::Error(e),
+            }
+        }
+        // 连接命令、脚本命令、配置命令、访问控制命令和调试命令只能在客户端连接中使用
+        Command::Auth { .. }
+        | Command::Ping { .. }
+        | Command::Echo { .. }
+        | Command::Reset
+        | Command::Hello { .. }
+        | Command::Eval { .. }
+        | Command::EvalSha { .. }
+        | Command::ScriptLoad { .. }
+        | Command::ScriptExists(_)
+        | Command::ScriptFlush
+        | Command::FunctionLoad { .. }
+        | Command::FunctionDelete { .. }
+        | Command::FunctionList
+        | Command::FunctionFlush
+        | Command::FCall { .. }
+        | Command::ConfigGet { .. }
+        | Command::ConfigSet(_)
+        | Command::AclSetUser { .. }
+        | Command::AclGetUser { .. }
+        | Command::AclDelUser(_)
+        | Command::AclList
+        | Command::AclUsers
+        | Command::AclWhoAmI
+        | Command::AclCat { .. }
+        | Command::AclLoad
+        | Command::AclSave
+        | Command::ClientId
+        | Command::ClientList
+        | Command::ClientGetName
+        | Command::ClientSetName { .. }
+        | Command::ClientTracking { .. }
+        | Command::ClientKill { .. }
+        | Command::CommandList
+        | Command::CommandCount
+        | Command::CommandInfo(_)
+        | Command::DebugSleep { .. }
+        | Command::DebugObject { .. }
+        | Command::DebugSetActiveExpire { .. }
+        | Command::DebugQuickSave
+        | Command::DebugSetTime { .. }
+        | Command::ReplicaOf { .. }
+        | Command::Sync { .. }
+        | Command::PeerSync { .. }
+        | Command::Monitor
+        | Command::Wait { .. }
+        | Command::Subscribe(_)
+        | Command::PSubscribe(_)
+        | Command::Unsubscribe(_)
+        | Command::PUnsubscribe(_)
+        | Command::Publish { .. }
+        | Command::Multi
+        | Command::Exec
+        | Command::Discard
+        | Command::Watch(_)
+        | Command::Unwatch
+        | Command::ClusterMeet { .. }
+        | Command::ClusterAddSlots(_)
+        | Command::ClusterDelSlots(_)
+        | Command::ClusterForget { .. }
+        | Command::ClusterKeySlot { .. }
+        | Command::ClusterMyId
+        | Command::ClusterInfo
+        | Command::ClusterNodes
+        | Command::ClusterSlots
+        | Command::ClusterShards
+        | Command::ClusterReplicas { .. }
+        | Command::ClusterReplicate { .. }
+        | Command::ClusterSetSlot { .. }
+        | Command::ClusterGetKeysInSlot { .. }
+        | Command::ClusterCountKeysInSlot { .. }
+        | Command::ClusterBus
+        | Command::Asking => {
+            Response::Error("This command is not allowed from scripts".into())
+        }
+        Command::SentinelGetMasterAddr { .. }
+        | Command::SentinelMaster { .. }
+        | Command::SentinelReplicas { .. }
+        | Command::SentinelIsMasterDownByAddr { .. }
+        | Command::SentinelFailover { .. } => {
+            Response::Error("SENTINEL commands are only available in redox-sentinel".into())
+        }
+    }
+}
+
+/// 把经纬度编码为 GEOPOS 和 GEOSEARCH WITHCOORD 使用的 [经度, 纬度] 数组
+fn coordinates((lon, lat): (f64, f64)) -> Response {
+    Response::Replies(vec![
+        Response::Value(RedoxValue::string(format!("{:.6}", lon))),
+        Response::Value(RedoxValue::string(format!("{:.6}", lat))),
+    ])
+}
+
+/// 把每个字段一个整数的结果编码为整数数组响应，用于 HEXPIRE、HTTL 和 HPERSIST
+fn integers(result: Result<Vec<i64>, RedoxError>) -> Response {
+    match result {
+        Ok(values) => Response::Replies(values.into_iter().map(Response::Integer).collect()),
+        Err(e) => Response::Error(e),
+    }
+}
@@ -0,0 +1,931 @@
+//! 服务器配置
+//! 启动时由配置文件和命令行参数生成，命令行参数优先，所有连接共享同一份配置；
+//! CONFIG GET 按名称读取配置项，CONFIG SET 修改可以在运行时生效的配置项，
+//! 收到 SIGHUP 时重新读取配置文件，并应用其中可以在运行时生效的配置项。
+
+use crate::acl::Acl;
+use crate::eviction::Policy;
+use crate::glob::glob_match;
+use crate::logging::{self, notice, warning, Level};
+use crate::persistence::{BackendKind, SaveRule, DEFAULT_SAVE_RULES, MAX_COMPRESSION_LEVEL};
+use crate::peers::PeerConfig;
+use crate::quota::Quota;
+use crate::replication::MasterType;
+use crate::s3::S3Config;
+use crate::storage::Storage;
+use crate::task::spawn_named;
+use clap::Parser;
+use redox_protocol::codec::RequestLimits;
+use redox_protocol::{RedoxValue, Response};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// 命令行参数，指定的参数覆盖配置文件中的同名配置
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct ServerConfig {
+    /// TOML configuration file
+    #[arg(short = 'c', long)]
+    pub config: Option<String>,
+
+    /// Address to listen on (default: 127.0.0.1)
+    #[arg(long)]
+    pub bind: Option<String>,
+
+    /// Port to listen on, 0 lets the system pick a free port (default: 2001)
+    #[arg(short = 'P', long)]
+    pub port: Option<u16>,
+
+    /// Password for authentication
+    #[arg(short = 'p', long)]
+    pub password: Option<String>,
+
+    /// Data file path for persistence
+    #[arg(short = 'f', long)]
+    pub data_file: Option<String>,
+
+    /// Persistence backend: file (a single snapshot file), s3 (the data file, uploaded to S3-compatible
+    /// object storage after every save) or sled (a sled database directory that stores each key separately,
+    /// saves only modified keys and holds values that do not fit in maxmemory) (default: file)
+    #[arg(long)]
+    pub persistence_backend: Option<String>,
+
+    /// S3-compatible endpoint for the s3 backend, e.g. https://s3.us-east-1.amazonaws.com
+    #[arg(long)]
+    pub s3_endpoint: Option<String>,
+
+    /// Bucket for the s3 backend
+    #[arg(long)]
+    pub s3_bucket: Option<String>,
+
+    /// Region for the s3 backend (default: us-east-1)
+    #[arg(long)]
+    pub s3_region: Option<String>,
+
+    /// Object key of the snapshot for the s3 backend (default: the file name of the data file)
+    #[arg(long)]
+    pub s3_key: Option<String>,
+
+    /// Auto-save rules as "<seconds> <changes>" pairs, e.g. "900 1 60 1000", empty to disable (default: "3600 1 300 100 60 10000")
+    #[arg(long)]
+    pub save: Option<String>,
+
+    /// zstd compression level for the data file, 1-22, 0 to disable (default: 0)
+    #[arg(long)]
+    pub compression_level: Option<u32>,
+
+    /// Maximum number of simultaneous client connections, 0 for unlimited (default: 10000)
+    #[arg(long)]
+    pub maxclients: Option<usize>,
+
+    /// Maximum length of a single inline request line, e.g. 1mb (default: 64kb)
+    #[arg(long)]
+    pub proto_max_inline_len: Option<String>,
+
+    /// Maximum number of arguments in a single request (default: 65536)
+    #[arg(long)]
+    pub proto_max_multibulk_len: Option<usize>,
+
+    /// Maximum size of a single RESP bulk string or binary frame, e.g. 16mb (default: 512mb)
+    #[arg(long)]
+    pub proto_max_bulk_len: Option<String>,
+
+    /// Memory limit for the dataset, e.g. 100mb, 0 for unlimited (default: 0)
+    #[arg(long)]
+    pub maxmemory: Option<String>,
+
+    /// What to do when maxmemory is reached: noeviction, allkeys-lru, allkeys-lfu, volatile-lru,
+    /// volatile-ttl or volatile-random (default: noeviction)
+    #[arg(long)]
+    pub maxmemory_policy: Option<String>,
+
+    /// Values with more elements than this are freed in a background thread by UNLINK, FLUSHALL ASYNC,
+    /// eviction and expiry (default: 64)
+    #[arg(long)]
+    pub lazyfree_threshold: Option<usize>,
+
+    /// TLS certificate chain file (PEM), enables TLS together with --tls-key
+    #[arg(long)]
+    pub tls_cert: Option<String>,
+
+    /// TLS private key file (PEM)
+    #[arg(long)]
+    pub tls_key: Option<String>,
+
+    /// Log level: debug, verbose, notice or warning (default: notice)
+    #[arg(long)]
+    pub loglevel: Option<String>,
+
+    /// ACL file with one `user <name> [rule ...]` line per user
+    #[arg(long)]
+    pub aclfile: Option<String>,
+
+    /// Number of accept loops, each on its own SO_REUSEPORT socket (default: 1)
+    #[arg(long)]
+    pub acceptors: Option<usize>,
+
+    /// Seconds of idle time before sending TCP keepalive probes, 0 to disable (default: 300)
+    #[arg(long)]
+    pub tcp_keepalive: Option<u64>,
+
+    /// Disable Nagle's algorithm on client sockets, true or false (default: true)
+    #[arg(long)]
+    pub tcp_nodelay: Option<bool>,
+
+    /// Expect a PROXY protocol v1/v2 header on every connection (when running behind a load balancer)
+    #[arg(long)]
+    pub proxy_protocol: bool,
+
+    /// Replicate from a primary at startup, as "<host> <port>"
+    #[arg(long)]
+    pub replicaof: Option<String>,
+
+    /// Kind of primary that replicaof points to, redox or redis (default: redox)
+    #[arg(long)]
+    pub master_type: Option<String>,
+
+    /// Run as a cluster node, serving only the hash slots assigned to this node
+    #[arg(long)]
+    pub cluster_enabled: bool,
+
+    /// File where the cluster node saves its id, known nodes and slot assignments (default: nodes.conf)
+    #[arg(long, value_name = "PATH")]
+    pub cluster_config_file: Option<String>,
+
+    /// Milliseconds without a PONG before a cluster node is considered failing (default: 15000)
+    #[arg(long)]
+    pub cluster_node_timeout: Option<u64>,
+
+    /// Allow the DEBUG command (SLEEP, OBJECT, SET-ACTIVE-EXPIRE, QUICKSAVE), intended for testing
+    #[arg(long)]
+    pub enable_debug_command: bool,
+
+    /// Import the keys of a Redis RDB file (database 0) at startup, overwriting keys with the same name
+    #[arg(long, value_name = "PATH")]
+    pub import_rdb: Option<String>,
+
+    /// Write the dataset to a Redis-compatible RDB file after loading, then exit without serving clients
+    #[arg(long, value_name = "PATH")]
+    pub export_rdb: Option<String>,
+}
+
+/// 配置文件的内容，所有配置项都是可选的
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    bind: Option<String>,
+    port: Option<u16>,
+    requirepass: Option<String>,
+    acceptors: Option<usize>,
+    tcp_keepalive: Option<u64>,
+    tcp_nodelay: Option<bool>,
+    proxy_protocol: Option<bool>,
+    enable_debug_command: Option<bool>,
+    persistence: PersistenceSection,
+    replication: ReplicationSection,
+    cluster: ClusterSection,
+    limits: LimitsSection,
+    tls: TlsSection,
+    logging: LoggingSection,
+    acl: AclSection,
+    quotas: BTreeMap<String, QuotaSection>,
+    peers: BTreeMap<String, PeerSection>,
+}
+
+/// 配置文件的 `[persistence]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct PersistenceSection {
+    data_file: Option<String>,
+    backend: Option<String>,
+    save: Option<String>,
+    compression_level: Option<u32>,
+    s3: S3Section,
+}
+
+/// 配置文件的 `[persistence.s3]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct S3Section {
+    endpoint: Option<String>,
+    bucket: Option<String>,
+    region: Option<String>,
+    key: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    ca_file: Option<String>,
+}
+
+/// 配置文件的 `[replication]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ReplicationSection {
+    replicaof: Option<String>,
+    master_type: Option<String>,
+    masteruser: Option<String>,
+    masterauth: Option<String>,
+    replica_read_only: Option<bool>,
+}
+
+/// 配置文件的 `[cluster]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ClusterSection {
+    enabled: Option<bool>,
+    config_file: Option<String>,
+    node_timeout: Option<u64>,
+}
+
+/// 配置文件的 `[limits]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct LimitsSection {
+    maxclients: Option<usize>,
+    proto_max_inline_len: Option<String>,
+    proto_max_multibulk_len: Option<usize>,
+    proto_max_bulk_len: Option<String>,
+    maxmemory: Option<String>,
+    maxmemory_policy: Option<String>,
+    lazyfree_threshold: Option<usize>,
+}
+
+/// 配置文件的 `[tls]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct TlsSection {
+    cert_file: Option<String>,
+    key_file: Option<String>,
+}
+
+/// 配置文件的 `[logging]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct LoggingSection {
+    level: Option<String>,
+}
+
+/// 配置文件的 `[acl]` 部分
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct AclSection {
+    file: Option<String>,
+}
+
+/// 配置文件的 `[quotas."前缀*"]` 部分，每个前缀一节
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct QuotaSection {
+    max_keys: Option<usize>,
+    max_bytes: Option<String>,
+}
+
+/// 配置文件的 `[peers.<名称>]` 部分，每条跨数据中心的链路一节
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct PeerSection {
+    address: Option<String>,
+    keys: Vec<String>,
+    compression_level: Option<u32>,
+    user: Option<String>,
+    password: Option<String>,
+}
+
+/// 所有连接共享的配置
+pub type SharedConfig = Arc<RwLock<Config>>;
+
+/// 服务器配置
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 监听地址
+    pub bind: String,
+    /// 监听端口
+    pub port: u16,
+    /// 认证密码，None 表示不需要认证
+    pub requirepass: Option<String>,
+    /// 数据文件路径，None 表示不持久化
+    pub data_file: Option<String>,
+    /// 持久化后端，sled 后端的数据路径为数据库目录
+    pub persistence_backend: BackendKind,
+    /// s3 后端的对象存储配置
+    pub s3: S3Config,
+    /// 自动保存的条件，满足任意一条时保存，为空时不自动保存
+    pub save: Vec<SaveRule>,
+    /// 作为副本复制的主节点地址，None 表示本身是主节点；REPLICAOF 命令会修改它
+    pub replicaof: Option<(String, u16)>,
+    /// 复制的主节点是 Redox 还是 Redis，下次连接主节点时生效
+    pub master_type: MasterType,
+    /// 连接主节点时认证的用户，None 表示 default 用户
+    pub masteruser: Option<String>,
+    /// 连接主节点时认证的密码，None 表示主节点不需要认证
+    pub masterauth: Option<String>,
+    /// 作为副本时是否拒绝客户端的写命令
+    pub replica_read_only: bool,
+    /// 是否以集群模式运行
+    pub cluster_enabled: bool,
+    /// 集群配置文件，保存节点的 ID、已知的节点和槽的归属
+    pub cluster_config_file: String,
+    /// 集群节点超过这个毫秒数没有回复 PONG 时认为它疑似下线
+    pub cluster_node_timeout: u64,
+    /// 保存数据文件时的 zstd 压缩级别，0 表示不压缩
+    pub compression_level: u32,
+    /// 最大连接数，0 表示不限制
+    pub maxclients: usize,
+    /// 行协议和内联命令中单行的最大字节数
+    pub proto_max_inline_len: usize,
+    /// 一个请求最多包含的参数个数
+    pub proto_max_multibulk_len: usize,
+    /// 单个批量字符串或二进制帧的最大字节数
+    pub proto_max_bulk_len: usize,
+    /// 内存上限（字节），0 表示不限制
+    pub maxmemory: usize,
+    /// 内存超过上限时的处理方式
+    pub maxmemory_policy: Policy,
+    /// 元素数超过这个值的值在后台释放
+    pub lazyfree_threshold: usize,
+    /// TLS 证书链文件
+    pub tls_cert: Option<String>,
+    /// TLS 私钥文件
+    pub tls_key: Option<String>,
+    /// 日志级别
+    pub loglevel: Level,
+    /// ACL 文件，None 表示只使用 default 用户
+    pub aclfile: Option<String>,
+    /// 接受循环的数量，大于 1 时每个循环以 SO_REUSEPORT 绑定自己的监听器
+    pub acceptors: usize,
+    /// 连接空闲多少秒后发送 TCP keepalive 探测，0 表示不启用
+    pub tcp_keepalive: u64,
+    /// 是否关闭 Nagle 算法，使小的回复立即发出
+    pub tcp_nodelay: bool,
+    /// 连接是否以 PROXY 协议头开头
+    pub proxy_protocol: bool,
+    /// 是否允许 DEBUG 命令
+    pub enable_debug_command: bool,
+    /// 按键前缀的配额，只能在配置文件中设置
+    pub quotas: Vec<Quota>,
+    /// 跨数据中心的链路，只能在配置文件中设置，修改后需要重启
+    pub peers: Vec<PeerConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 2001,
+            requirepass: None,
+            data_file: None,
+            persistence_backend: BackendKind::File,
+            s3: S3Config::default(),
+            save: SaveRule::parse_list(DEFAULT_SAVE_RULES).unwrap(),
+            replicaof: None,
+            master_type: MasterType::Redox,
+            masteruser: None,
+            masterauth: None,
+            replica_read_only: true,
+            cluster_enabled: false,
+            cluster_config_file: "nodes.conf".to_string(),
+            cluster_node_timeout: 15000,
+            compression_level: 0,
+            maxclients: 10000,
+            proto_max_inline_len: RequestLimits::default().max_inline_len,
+            proto_max_multibulk_len: RequestLimits::default().max_args,
+            proto_max_bulk_len: RequestLimits::default().max_bulk_len,
+            maxmemory: 0,
+            maxmemory_policy: Policy::NoEviction,
+            lazyfree_threshold: crate::lazyfree::DEFAULT_THRESHOLD,
+            tls_cert: None,
+            tls_key: None,
+            loglevel: Level::Notice,
+            aclfile: None,
+            acceptors: 1,
+            tcp_keepalive: 300,
+            tcp_nodelay: true,
+            proxy_protocol: false,
+            enable_debug_command: false,
+            quotas: Vec::new(),
+            peers: Vec::new(),
+        }
+    }
+}
+
+/// 跨数据中心的链路默认的 zstd 压缩级别
+const DEFAULT_PEER_COMPRESSION_LEVEL: u32 = 3;
+
+/// 所有配置项的名称，CONFIG GET 按这个顺序返回
+const PARAMETERS: &[&str] = &[
+    "bind",
+    "port",
+    "requirepass",
+    "data-file",
+    "persistence-backend",
+    "s3-endpoint",
+    "s3-bucket",
+    "s3-region",
+    "s3-key",
+    "save",
+    "compression-level",
+    "replicaof",
+    "master-type",
+    "masteruser",
+    "masterauth",
+    "replica-read-only",
+    "cluster-enabled",
+    "cluster-config-file",
+    "cluster-node-timeout",
+    "maxclients",
+    "proto-max-inline-len",
+    "proto-max-multibulk-len",
+    "proto-max-bulk-len",
+    "maxmemory",
+    "maxmemory-policy",
+    "lazyfree-threshold",
+    "tls-cert-file",
+    "tls-key-file",
+    "loglevel",
+    "aclfile",
+    "acceptors",
+    "tcp-keepalive",
+    "tcp-nodelay",
+    "proxy-protocol",
+    "enable-debug-command",
+];
+
+/// 可以在运行时修改的配置项，其余配置项需要重启服务器才能生效
+const MUTABLE: &[&str] = &[
+    "requirepass",
+    "save",
+    "compression-level",
+    "master-type",
+    "masteruser",
+    "masterauth",
+    "replica-read-only",
+    "cluster-node-timeout",
+    "maxclients",
+    "proto-max-inline-len",
+    "proto-max-multibulk-len",
+    "proto-max-bulk-len",
+    "maxmemory",
+    "maxmemory-policy",
+    "lazyfree-threshold",
+    "tcp-keepalive",
+    "tcp-nodelay",
+    "loglevel",
+];
+
+impl Config {
+    /// 根据配置文件和命令行参数生成配置，命令行参数优先，都没有指定的配置项使用默认值
+    ///
+    /// # Arguments
+    /// * `args` - 命令行参数，`args.config` 指定配置文件
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - 合并后的配置
+    /// * `Err(String)` - 配置文件无法读取或解析，或配置项的值无效
+    pub fn load(args: &ServerConfig) -> Result<Self, String> {
+        let file = match &args.config {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Error reading config file {}: {}", path, e))?;
+                toml::from_str::<ConfigFile>(&content)
+                    .map_err(|e| format!("Error parsing config file {}: {}", path, e))?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let defaults = Config::default();
+        let loglevel = match args.loglevel.as_ref().or(file.logging.level.as_ref()) {
+            Some(name) => Level::parse(name).ok_or_else(|| format!("Invalid log level: {}", name))?,
+            None => defaults.loglevel,
+        };
+        let maxmemory_policy = match args.maxmemory_policy.as_ref().or(file.limits.maxmemory_policy.as_ref()) {
+            Some(name) => Policy::parse(name).ok_or_else(|| format!("Invalid maxmemory policy: {}", name))?,
+            None => defaults.maxmemory_policy,
+        };
+        let size = |flag: &Option<String>, file: Option<String>, default: usize| {
+            match flag.clone().or(file) {
+                Some(value) => parse_size(&value).ok_or_else(|| format!("Invalid size: {}", value)),
+                None => Ok(default),
+            }
+        };
+        let mut quotas = Vec::with_capacity(file.quotas.len());
+        for (pattern, section) in &file.quotas {
+            let max_bytes = match &section.max_bytes {
+                Some(value) => parse_size(value).ok_or_else(|| format!("Invalid size: {}", value))?,
+                None => 0,
+            };
+            quotas.push(Quota::new(pattern, section.max_keys.unwrap_or(0), max_bytes));
+        }
+        let mut peers = Vec::with_capacity(file.peers.len());
+        for (name, section) in file.peers {
+            let address = section.address.ok_or_else(|| format!("peers.{}: address is required", name))?;
+            let (host, port) = parse_address(&address)
+                .ok_or_else(|| format!("peers.{}: invalid address, expected \"<host> <port>\": {}", name, address))?;
+            if section.keys.is_empty() {
+                return Err(format!("peers.{}: keys is required", name));
+            }
+            let compression_level = section.compression_level.unwrap_or(DEFAULT_PEER_COMPRESSION_LEVEL);
+            if compression_level > MAX_COMPRESSION_LEVEL {
+                return Err(format!("peers.{}: compression-level must be between 0 and {}", name, MAX_COMPRESSION_LEVEL));
+            }
+            peers.push(PeerConfig {
+                name,
+                host,
+                port,
+                keys: section.keys,
+                compression_level,
+                user: section.user,
+                password: section.password,
+            });
+        }
+        let persistence_backend = match args.persistence_backend.as_ref().or(file.persistence.backend.as_ref()) {
+            Some(name) => BackendKind::parse(name).ok_or_else(|| format!("Invalid persistence backend: {}", name))?,
+            None => defaults.persistence_backend,
+        };
+        // 密钥不在命令行中指定，避免出现在进程列表里；配置文件中没有时使用 AWS 的环境变量
+        let s3_file = file.persistence.s3;
+        let s3 = S3Config {
+            endpoint: args.s3_endpoint.clone().or(s3_file.endpoint),
+            bucket: args.s3_bucket.clone().or(s3_file.bucket),
+            region: args.s3_region.clone().or(s3_file.region),
+            key: args.s3_key.clone().or(s3_file.key),
+            access_key: s3_file.access_key.or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok()),
+            secret_key: s3_file.secret_key.or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok()),
+            ca_file: s3_file.ca_file,
+        };
+        let save = match args.save.as_ref().or(file.persistence.save.as_ref()) {
+            Some(rules) => SaveRule::parse_list(rules).ok_or_else(|| format!("Invalid save rules: {}", rules))?,
+            None => defaults.save,
+        };
+        let replicaof = match args.replicaof.as_ref().or(file.replication.replicaof.as_ref()) {
+            Some(value) => Some(parse_address(value).ok_or_else(|| format!("Invalid replicaof, expected \"<host> <port>\": {}", value))?),
+            None => None,
+        };
+        let master_type = match args.master_type.as_ref().or(file.replication.master_type.as_ref()) {
+            Some(name) => MasterType::parse(name).ok_or_else(|| format!("Invalid master-type: {}", name))?,
+            None => defaults.master_type,
+        };
+        let config = Config {
+            bind: args.bind.clone().or(file.bind).unwrap_or(defaults.bind),
+            port: args.port.or(file.port).unwrap_or(defaults.port),
+            requirepass: args.password.clone().or(file.requirepass),
+            data_file: args.data_file.clone().or(file.persistence.data_file),
+            persistence_backend,
+            s3,
+            save,
+            replicaof,
+            master_type,
+            masteruser: file.replication.masteruser,
+            masterauth: file.replication.masterauth,
+            replica_read_only: file.replication.replica_read_only.unwrap_or(defaults.replica_read_only),
+            cluster_enabled: args.cluster_enabled || file.cluster.enabled.unwrap_or(defaults.cluster_enabled),
+            cluster_config_file: args.cluster_config_file.clone()
+                .or(file.cluster.config_file)
+                .unwrap_or(defaults.cluster_config_file),
+            cluster_node_timeout: args.cluster_node_timeout
+                .or(file.cluster.node_timeout)
+                .unwrap_or(defaults.cluster_node_timeout),
+            compression_level: args.compression_level
+                .or(file.persistence.compression_level)
+                .unwrap_or(defaults.compression_level),
+            maxclients: args.maxclients.or(file.limits.maxclients).unwrap_or(defaults.maxclients),
+            proto_max_inline_len: size(&args.proto_max_inline_len, file.limits.proto_max_inline_len, defaults.proto_max_inline_len)?,
+            proto_max_multibulk_len: args.proto_max_multibulk_len
+                .or(file.limits.proto_max_multibulk_len)
+                .unwrap_or(defaults.proto_max_multibulk_len),
+            proto_max_bulk_len: size(&args.proto_max_bulk_len, file.limits.proto_max_bulk_len, defaults.proto_max_bulk_len)?,
+            maxmemory: size(&args.maxmemory, file.limits.maxmemory, defaults.maxmemory)?,
+            maxmemory_policy,
+            lazyfree_threshold: args.lazyfree_threshold
+                .or(file.limits.lazyfree_threshold)
+                .unwrap_or(defaults.lazyfree_threshold),
+            tls_cert: args.tls_cert.clone().or(file.tls.cert_file),
+            tls_key: args.tls_key.clone().or(file.tls.key_file),
+            loglevel,
+            aclfile: args.aclfile.clone().or(file.acl.file),
+            acceptors: args.acceptors.or(file.acceptors).unwrap_or(defaults.acceptors),
+            tcp_keepalive: args.tcp_keepalive.or(file.tcp_keepalive).unwrap_or(defaults.tcp_keepalive),
+            tcp_nodelay: args.tcp_nodelay.or(file.tcp_nodelay).unwrap_or(defaults.tcp_nodelay),
+            proxy_protocol: args.proxy_protocol || file.proxy_protocol.unwrap_or(defaults.proxy_protocol),
+            enable_debug_command: args.enable_debug_command || file.enable_debug_command.unwrap_or(defaults.enable_debug_command),
+            quotas,
+            peers,
+        };
+        if config.compression_level > MAX_COMPRESSION_LEVEL {
+            return Err(format!("compression-level must be between 0 and {}", MAX_COMPRESSION_LEVEL));
+        }
+        if config.acceptors == 0 {
+            return Err("acceptors must be at least 1".to_string());
+        }
+        if config.proto_max_inline_len == 0 || config.proto_max_multibulk_len == 0 || config.proto_max_bulk_len == 0 {
+            return Err("proto-max-* limits must be greater than 0".to_string());
+        }
+        if config.cluster_enabled && config.replicaof.is_some() {
+            return Err("replicaof can't be used in cluster mode".to_string());
+        }
+        if config.cluster_enabled && config.master_type == MasterType::Redis {
+            return Err("master-type redis can't be used in cluster mode".to_string());
+        }
+        if config.cluster_node_timeout == 0 {
+            return Err("cluster-node-timeout must be greater than 0".to_string());
+        }
+        if config.tls_cert.is_some() != config.tls_key.is_some() {
+            return Err("TLS requires both a certificate and a private key".to_string());
+        }
+        Ok(config)
+    }
+
+    /// 新连接使用的请求大小上限
+    pub fn request_limits(&self) -> RequestLimits {
+        RequestLimits {
+            max_inline_len: self.proto_max_inline_len,
+            max_args: self.proto_max_multibulk_len,
+            max_bulk_len: self.proto_max_bulk_len,
+        }
+    }
+
+    /// 是否拒绝客户端的写命令，即本身是副本且 replica-read-only 为 yes
+    pub fn read_only(&self) -> bool {
+        self.replicaof.is_some() && self.replica_read_only
+    }
+
+    /// 读取配置项的值，未设置的可选配置项为空字符串
+    fn get(&self, name: &str) -> Option<String> {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        Some(match name {
+            "bind" => self.bind.clone(),
+            "port" => self.port.to_string(),
+            "requirepass" => optional(&self.requirepass),
+            "data-file" => optional(&self.data_file),
+            "persistence-backend" => self.persistence_backend.as_str().to_string(),
+            "s3-endpoint" => optional(&self.s3.endpoint),
+            "s3-bucket" => optional(&self.s3.bucket),
+            "s3-region" => self.s3.region.clone().unwrap_or_else(|| crate::s3::DEFAULT_REGION.to_string()),
+            "s3-key" => optional(&self.s3.key),
+            "save" => SaveRule::format_list(&self.save),
+            "compression-level" => self.compression_level.to_string(),
+            "replicaof" => self.replicaof.as_ref().map(|(host, port)| format!("{} {}", host, port)).unwrap_or_default(),
+            "master-type" => self.master_type.as_str().to_string(),
+            "masteruser" => optional(&self.masteruser),
+            "masterauth" => optional(&self.masterauth),
+            "replica-read-only" => if self.replica_read_only { "yes" } else { "no" }.to_string(),
+            "cluster-enabled" => if self.cluster_enabled { "yes" } else { "no" }.to_string(),
+            "cluster-config-file" => self.cluster_config_file.clone(),
+            "cluster-node-timeout" => self.cluster_node_timeout.to_string(),
+            "maxclients" => self.maxclients.to_string(),
+            "proto-max-inline-len" => self.proto_max_inline_len.to_string(),
+            "proto-max-multibulk-len" => self.proto_max_multibulk_len.to_string(),
+            "proto-max-bulk-len" => self.proto_max_bulk_len.to_string(),
+            "maxmemory" => self.maxmemory.to_string(),
+            "maxmemory-policy" => self.maxmemory_policy.as_str().to_string(),
+            "lazyfree-threshold" => self.lazyfree_threshold.to_string(),
+            "tls-cert-file" => optional(&self.tls_cert),
+            "tls-key-file" => optional(&self.tls_key),
+            "loglevel" => self.loglevel.as_str().to_string(),
+            "aclfile" => optional(&self.aclfile),
+            "acceptors" => self.acceptors.to_string(),
+            "tcp-keepalive" => self.tcp_keepalive.to_string(),
+            "tcp-nodelay" => if self.tcp_nodelay { "yes" } else { "no" }.to_string(),
+            "proxy-protocol" => if self.proxy_protocol { "yes" } else { "no" }.to_string(),
+            "enable-debug-command" => if self.enable_debug_command { "yes" } else { "no" }.to_string(),
+            _ => return None,
+        })
+    }
+
+    /// 修改配置项
+    ///
+    /// # Arguments
+    /// * `name` - 配置项名称（小写）
+    /// * `value` - 新的值，requirepass、masteruser 和 masterauth 为空字符串时取消设置
+    ///
+    /// # Returns
+    /// * `Ok(())` - 修改成功
+    /// * `Err(String)` - 配置项不存在、不能在运行时修改或值无效
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed", name);
+        match name {
+            "requirepass" => {
+                self.requirepass = (!value.is_empty()).then(|| value.to_string());
+            }
+            "save" => {
+                self.save = SaveRule::parse_list(value).ok_or_else(invalid)?;
+            }
+            "master-type" => {
+                self.master_type = MasterType::parse(value).ok_or_else(invalid)?;
+            }
+            "masteruser" => {
+                self.masteruser = (!value.is_empty()).then(|| value.to_string());
+            }
+            "masterauth" => {
+                self.masterauth = (!value.is_empty()).then(|| value.to_string());
+            }
+            "replica-read-only" => {
+                self.replica_read_only = match value.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(invalid()),
+                };
+            }
+            "cluster-node-timeout" => {
+                self.cluster_node_timeout = value.parse().ok().filter(|&timeout| timeout > 0).ok_or_else(invalid)?;
+            }
+            "compression-level" => {
+                self.compression_level = value.parse().ok().filter(|&level| level <= MAX_COMPRESSION_LEVEL).ok_or_else(invalid)?;
+            }
+            "maxclients" => {
+                self.maxclients = value.parse().map_err(|_| invalid())?;
+            }
+            "proto-max-inline-len" => {
+                self.proto_max_inline_len = parse_size(value).filter(|&len| len > 0).ok_or_else(invalid)?;
+            }
+            "proto-max-multibulk-len" => {
+                self.proto_max_multibulk_len = value.parse().ok().filter(|&len| len > 0).ok_or_else(invalid)?;
+            }
+            "proto-max-bulk-len" => {
+                self.proto_max_bulk_len = parse_size(value).filter(|&len| len > 0).ok_or_else(invalid)?;
+            }
+            "maxmemory" => {
+                self.maxmemory = parse_size(value).ok_or_else(invalid)?;
+            }
+            "maxmemory-policy" => {
+                self.maxmemory_policy = Policy::parse(value).ok_or_else(invalid)?;
+            }
+            "lazyfree-threshold" => {
+                self.lazyfree_threshold = value.parse().map_err(|_| invalid())?;
+            }
+            "tcp-keepalive" => {
+                self.tcp_keepalive = value.parse().map_err(|_| invalid())?;
+            }
+            "tcp-nodelay" => {
+                self.tcp_nodelay = match value.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(invalid()),
+                };
+            }
+            "loglevel" => {
+                self.loglevel = Level::parse(value).ok_or_else(invalid)?;
+            }
+            // 新增可修改的配置项时需要同时加入 MUTABLE
+            _ if PARAMETERS.contains(&name) => {
+                return Err(format!("CONFIG SET failed (possibly related to argument '{}') - can't set immutable config", name));
+            }
+            _ => return Err(format!("Unknown option or number of arguments for CONFIG SET - '{}'", name)),
+        }
+        Ok(())
+    }
+}
+
+/// 解析字节数，支持 `kb`、`mb`、`gb` 单位（不区分大小写，按 1024 换算），没有单位时为字节
+///
+/// # Returns
+/// 字节数，格式无效或溢出时为 None
+fn parse_size(value: &str) -> Option<usize> {
+    let value = value.trim().to_lowercase();
+    let (digits, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value.as_str(), ""),
+    };
+    let multiplier: usize = match unit {
+        "" | "b" => 1,
+        "k" | "kb" => 1024,
+        "m" | "mb" => 1024 * 1024,
+        "g" | "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// 解析 `<host> <port>` 格式的地址，用于 replicaof
+fn parse_address(value: &str) -> Option<(String, u16)> {
+    match value.split_whitespace().collect::<Vec<_>>()[..] {
+        [host, port] => Some((host.to_string(), port.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// 处理 CONFIG GET，返回名称与模式匹配的配置项
+///
+/// # Arguments
+/// * `config` - 服务器配置
+/// * `pattern` - 名称模式，支持 `*` 和 `?`，不区分大小写
+pub fn config_get(config: &SharedConfig, pattern: &str) -> Response {
+    let config = config.read().unwrap();
+    let pattern = pattern.to_lowercase();
+    Response::Map(
+        PARAMETERS.iter()
+            .filter(|name| glob_match(pattern.as_bytes(), name.as_bytes()))
+            .filter_map(|name| {
+                let value = config.get(name)?;
+                Some((name.to_string(), Response::Value(RedoxValue::string(value))))
+            })
+            .collect(),
+    )
+}
+
+/// 处理 CONFIG SET，所有配置项都有效时才一起修改，并把修改应用到运行中的服务器
+///
+/// # Arguments
+/// * `config` - 服务器配置
+/// * `storage` - 存储实例，修改保存条件、压缩级别、内存上限、惰性释放阈值或副本是否只读时通知存储
+/// * `acl` - 用户列表，修改 requirepass 时同时修改 default 用户的密码
+/// * `params` - 配置项名称和值
+pub fn config_set(config: &SharedConfig, storage: &Storage, acl: &Acl, params: Vec<(String, String)>) -> Response {
+    let mut config = config.write().unwrap();
+    let mut updated = config.clone();
+    for (name, value) in &params {
+        if let Err(e) = updated.set(name, value) {
+            return Response::Error(e.into());
+        }
+    }
+    apply(&mut config, updated, storage, acl);
+    Response::Ok
+}
+
+/// 重新读取配置文件，应用可以在运行时修改的配置项，用于 SIGHUP
+/// 命令行参数仍然覆盖文件中的配置；文件无法读取或配置无效时保留当前的配置
+///
+/// # Arguments
+/// * `args` - 启动时的命令行参数
+/// * `config` - 服务器配置
+/// * `storage` - 存储实例
+/// * `acl` - 用户列表
+pub fn reload(args: &ServerConfig, config: &SharedConfig, storage: &Storage, acl: &Acl) {
+    let loaded = match Config::load(args) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            warning!("Config reload failed, keeping the current configuration: {}", e);
+            return;
+        }
+    };
+
+    let mut config = config.write().unwrap();
+    let mut updated = config.clone();
+    let mut changed = 0;
+    for name in PARAMETERS {
+        let (Some(old), Some(new)) = (config.get(name), loaded.get(name)) else {
+            continue;
+        };
+        if old == new {
+            continue;
+        }
+        if !MUTABLE.contains(name) {
+            warning!("Config reload: {} changed from '{}' to '{}', restart the server to apply", name, old, new);
+            continue;
+        }
+        if let Err(e) = updated.set(name, &new) {
+            warning!("Config reload: {}", e);
+            continue;
+        }
+        // 不在日志中输出密码
+        if matches!(*name, "requirepass" | "masterauth") {
+            notice!("Config reload: {} changed", name);
+        } else {
+            notice!("Config reload: {} changed from '{}' to '{}'", name, old, new);
+        }
+        changed += 1;
+    }
+    // 配额不是 CONFIG GET/SET 的配置项，单独比较
+    if loaded.quotas != config.quotas {
+        notice!("Config reload: quotas changed ({} prefix(es))", loaded.quotas.len());
+        updated.quotas = loaded.quotas;
+        changed += 1;
+    }
+    // 链路不是 CONFIG GET/SET 的配置项，修改后需要重启
+    if loaded.peers != config.peers {
+        warning!("Config reload: peers changed, restart the server to apply");
+    }
+    apply(&mut config, updated, storage, acl);
+    notice!("Config reloaded, {} setting(s) changed", changed);
+}
+
+/// 用新的配置替换当前的配置，并通知需要立即生效的子系统
+fn apply(config: &mut Config, updated: Config, storage: &Storage, acl: &Acl) {
+    if updated.requirepass != config.requirepass {
+        acl.set_default_password(updated.requirepass.as_deref());
+    }
+    if updated.save != config.save {
+        storage.set_save_rules(updated.save.clone());
+    }
+    if updated.compression_level != config.compression_level {
+        storage.set_compression_level(updated.compression_level);
+    }
+    if updated.maxmemory != config.maxmemory || updated.maxmemory_policy != config.maxmemory_policy {
+        storage.set_maxmemory(updated.maxmemory, updated.maxmemory_policy);
+    }
+    if updated.lazyfree_threshold != config.lazyfree_threshold {
+        storage.set_lazyfree_threshold(updated.lazyfree_threshold);
+    }
+    if updated.read_only() != config.read_only() {
+        storage.set_read_only(updated.read_only());
+    }
+    if updated.quotas != config.quotas {
+        // 重新统计用量需要锁定所有分片，在后台完成，不在持有配置锁时等待
+        let storage = storage.clone();
+        let quotas = updated.quotas.clone();
+        spawn_named("set-quotas", async move {
+            storage.set_quotas(quotas).await;
+        });
+    }
+    logging::set_level(updated.loglevel);
+    *config = updated;
+}
@@ -0,0 +1,69 @@
+//! DEBUG 命令
+//! 用于测试和排查问题：模拟服务器卡顿、查看键的内部表示、关闭后台的过期键清理、强制保存数据文件和修改手动时钟的时间，
+//! 默认禁用，需要通过 enable-debug-command 配置项启用。
+
+use crate::clock;
+use crate::dump;
+use crate::memory;
+use crate::scripting::Scripting;
+use crate::storage::Storage;
+use redox_protocol::{Command, RedoxValue, Response};
+use std::time::Duration;
+
+/// 未启用 DEBUG 命令时的错误信息
+pub const DISABLED: &str =
+    "DEBUG command not allowed. Start the server with --enable-debug-command or set enable-debug-command = true in the config file";
+
+/// 服务器没有使用手动时钟时 DEBUG SET-TIME 的错误信息
+pub const NO_MANUAL_CLOCK: &str =
+    "DEBUG SET-TIME requires a manual clock. Build the server with --features test-clock, or embed it with a ManualClock";
+
+/// 执行 DEBUG 命令
+///
+/// # Arguments
+/// * `storage` - 存储实例
+/// * `scripting` - 脚本引擎，DEBUG SLEEP 持有它的独占锁，使其他连接的命令都需要等待
+/// * `cmd` - DEBUG 的某个子命令
+pub async fn execute(storage: &Storage, scripting: &Scripting, cmd: Command) -> Response {
+    match cmd {
+        Command::DebugSleep { seconds } => {
+            let _exclusive = scripting.exclusive().await;
+            tokio::time::sleep(Duration::from_secs_f64(seconds)).await;
+            Response::Ok
+        }
+        Command::DebugObject { key } => {
+            // 先读取空闲时间，避免被读取值的操作更新
+            let Some(idle) = storage.idle_time(&key).await else {
+                return Response::Error("no such key".into());
+            };
+            let Some(value) = storage.peek(&key).await else {
+                return Response::Error("no such key".into());
+            };
+            let ttl = storage.ttl(&key).await.flatten().map_or(-1, |ttl| ttl as i64);
+            Response::Value(RedoxValue::string(format!(
+                "encoding:{} serializedlength:{} lru_seconds_idle:{} ttl:{}",
+                memory::encoding(&value),
+                dump::serialize(&value).len(),
+                idle,
+                ttl,
+            )))
+        }
+        Command::DebugSetActiveExpire { enabled } => {
+            storage.set_active_expire(enabled);
+            Response::Ok
+        }
+        Command::DebugQuickSave => match storage.save_now().await {
+            Ok(true) => Response::Ok,
+            Ok(false) => Response::Error("persistence is not enabled".into()),
+            Err(e) => Response::Error(format!("Error saving data: {}", e).into()),
+        },
+        Command::DebugSetTime { unix_ms } => match clock::as_manual(storage.clock()) {
+            Some(clock) => {
+                clock.set(unix_ms);
+                Response::Ok
+            }
+            None => Response::Error(NO_MANUAL_CLOCK.into()),
+        },
+        _ => Response::Error("Not a DEBUG command".into()),
+    }
+}
@@ -0,0 +1,69 @@
+//! DUMP/RESTORE 使用的单键序列化格式
+//! 格式为：值的 bincode 序列化 + 2 字节格式版本（小端）+ 4 字节 CRC32 校验和（小端），
+//! 在文本协议中以十六进制字符串传输。版本 1 使用 JSON 序列化，无法表示 inf 和 nan 分数，只在 RESTORE 时读取
+
+use bincode::Options;
+use redox_protocol::RedoxValue;
+
+/// 当前的序列化格式版本
+pub const DUMP_VERSION: u16 = 2;
+
+/// 值以 JSON 序列化的旧版本
+const JSON_VERSION: u16 = 1;
+
+/// 版本号和校验和占用的字节数
+const FOOTER_LEN: usize = 2 + 4;
+
+/// 将值序列化为带版本和校验和的二进制数据
+pub fn serialize(value: &RedoxValue) -> Vec<u8> {
+    let mut payload = bincode::serialize(value).expect("RedoxValue is always serializable");
+    payload.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    let checksum = crc32fast::hash(&payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+    payload
+}
+
+/// 校验并反序列化 DUMP 生成的二进制数据
+///
+/// # Returns
+/// * `Ok(RedoxValue)` - 还原的值
+/// * `Err(String)` - 版本不兼容、校验和错误或数据损坏
+pub fn deserialize(blob: &[u8]) -> Result<RedoxValue, String> {
+    const BAD_PAYLOAD: &str = "DUMP payload version or checksum are wrong";
+    if blob.len() < FOOTER_LEN {
+        return Err(BAD_PAYLOAD.to_string());
+    }
+    let (body, checksum) = blob.split_at(blob.len() - 4);
+    let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+    if crc32fast::hash(body) != checksum {
+        return Err(BAD_PAYLOAD.to_string());
+    }
+    let (payload, version) = body.split_at(body.len() - 2);
+    let version = u16::from_le_bytes(version.try_into().unwrap());
+    match version {
+        JSON_VERSION => serde_json::from_slice(payload).map_err(|_| BAD_PAYLOAD.to_string()),
+        // 与 bincode::serialize 相同的定长整数编码，读取的字节数不超过载荷的长度，伪造的长度不会导致分配大量内存
+        DUMP_VERSION => bincode::options()
+            .with_fixint_encoding()
+            .with_limit(payload.len() as u64)
+            .deserialize(payload)
+            .map_err(|_| BAD_PAYLOAD.to_string()),
+        _ => Err(BAD_PAYLOAD.to_string()),
+    }
+}
+
+/// 十六进制编码
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 十六进制解码
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
@@ -0,0 +1,104 @@
+//! 内存淘汰策略
+//! 设置了 maxmemory 后，可能增加内存占用的命令（带 denyoom 标志）执行前检查估算的内存占用，
+//! 超过上限时按策略淘汰键直到低于上限，或者拒绝命令并返回 OOM 错误。
+//! 淘汰不遍历所有键，而是随机抽取几个键后淘汰其中最久没有访问（LRU）或访问频率最低（LFU）的键。
+//! 访问频率与 Redis 一样使用 8 位的对数计数器：访问次数越多，计数器增加的概率越低，
+//! 没有访问时每分钟减 1，很久以前的热点键会逐渐变冷。
+
+/// 每次淘汰时随机抽取的键数，越大越接近精确的 LRU/LFU，但每次淘汰的开销也越大
+pub const SAMPLES: usize = 5;
+
+/// 新键的访问频率计数器初始值，避免新写入的键立即被 LFU 淘汰
+const LFU_INIT: u8 = 5;
+
+/// 计数器增长的对数因子，越大计数器增长越慢，约 100 万次访问后达到 255
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// 计数器每经过多少分钟没有访问减 1
+const LFU_DECAY_MINUTES: u64 = 1;
+
+/// 内存超过上限时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// 不淘汰键，拒绝可能增加内存的命令，删除和读取命令不受影响
+    NoEviction,
+    /// 在所有键中淘汰最久没有访问的键
+    AllKeysLru,
+    /// 在所有键中淘汰访问频率最低的键
+    AllKeysLfu,
+    /// 在设置了过期时间的键中淘汰最久没有访问的键
+    VolatileLru,
+    /// 在设置了过期时间的键中淘汰最早过期的键
+    VolatileTtl,
+    /// 在设置了过期时间的键中随机淘汰
+    VolatileRandom,
+}
+
+impl Policy {
+    /// 所有策略，下标与 `Policy as u8` 相同
+    const ALL: [Policy; 6] = [
+        Policy::NoEviction,
+        Policy::AllKeysLru,
+        Policy::AllKeysLfu,
+        Policy::VolatileLru,
+        Policy::VolatileTtl,
+        Policy::VolatileRandom,
+    ];
+
+    /// 解析策略名称（不区分大小写）
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|policy| policy.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// 策略名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Policy::NoEviction => "noeviction",
+            Policy::AllKeysLru => "allkeys-lru",
+            Policy::AllKeysLfu => "allkeys-lfu",
+            Policy::VolatileLru => "volatile-lru",
+            Policy::VolatileTtl => "volatile-ttl",
+            Policy::VolatileRandom => "volatile-random",
+        }
+    }
+
+    /// 从 `Policy as u8` 的值还原策略，用于保存在原子变量中的策略
+    pub fn from_u8(value: u8) -> Self {
+        Self::ALL[value as usize]
+    }
+}
+
+/// 键的访问记录，由读写操作和 TOUCH 更新
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    /// 最后访问时间（毫秒）
+    pub last_ms: u64,
+    /// 对数访问频率计数器，只在访问时衰减，读取时用 `frequency` 计算衰减后的值
+    counter: u8,
+}
+
+impl Access {
+    /// 新键的访问记录
+    pub fn new(now: u64) -> Self {
+        Self { last_ms: now, counter: LFU_INIT }
+    }
+
+    /// 记录一次访问，先按距上次访问的时间衰减计数器，再以对数概率增加
+    pub fn touch(&mut self, now: u64) {
+        let mut counter = self.frequency(now);
+        if counter < u8::MAX {
+            let base = counter.saturating_sub(LFU_INIT) as f64;
+            if fastrand::f64() < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+                counter += 1;
+            }
+        }
+        self.counter = counter;
+        self.last_ms = now;
+    }
+
+    /// 衰减后的访问频率计数器，越小越适合被 LFU 淘汰
+    pub fn frequency(&self, now: u64) -> u8 {
+        let periods = now.saturating_sub(self.last_ms) / 60_000 / LFU_DECAY_MINUTES;
+        self.counter.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+}
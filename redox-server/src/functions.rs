@@ -0,0 +1,316 @@
+//! 持久化的服务器端函数（FUNCTION / FCALL）
+//! 函数库使用 Rhai 编写，源码随数据一起保存在数据文件中，服务器启动时重新编译。
+//! 库中接受两个参数 `(keys, args)` 的公开函数可以通过 FCALL 调用，
+//! 函数内通过 `redis_call([...])` / `redis_pcall([...])` 执行数据命令，命令按调用 FCALL 的连接的用户检查 ACL 权限。
+
+use crate::logging::warning;
+use crate::scripting::Caller;
+use crate::storage::Storage;
+use bytes::Bytes;
+use redox_protocol::{Protocol, RedoxError, RedoxValue, Response};
+use rhai::{Array, Blob, CallFnOptions, Dynamic, Engine, EvalAltResult, FnAccess, Map, NativeCallContext, Scope, AST};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::Mutex;
+
+/// 单次函数调用最多执行的操作数，防止死循环占用服务器
+const MAX_OPERATIONS: u64 = 50_000_000;
+
+/// 编译后的函数库
+struct Library {
+    /// 编译结果
+    ast: AST,
+    /// 可以通过 FCALL 调用的函数名
+    functions: Vec<String>,
+}
+
+/// 函数库管理器
+/// 源码保存在 `Storage` 中以便持久化，这里保存编译后的结果
+pub struct Functions {
+    /// 存储实例
+    storage: Arc<Storage>,
+    /// Rhai 引擎，注册了 `redis_call` 和 `redis_pcall`
+    engine: Arc<Engine>,
+    /// 库名到编译结果的映射
+    libraries: Mutex<BTreeMap<String, Library>>,
+}
+
+impl Functions {
+    /// 创建函数库管理器
+    ///
+    /// # Arguments
+    /// * `storage` - 存储实例，函数通过它执行命令
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self {
+            engine: Arc::new(new_engine(storage.clone())),
+            storage,
+            libraries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 编译数据文件中保存的函数库，无法编译的库会被跳过
+    pub async fn restore(&self) {
+        let mut libraries = self.libraries.lock().await;
+        for (name, code) in self.storage.function_libraries().await {
+            match self.compile(&code) {
+                Ok(library) => {
+                    libraries.insert(name, library);
+                }
+                Err(e) => warning!("Error loading function library {}: {}", name, e),
+            }
+        }
+    }
+
+    /// 加载函数库
+    ///
+    /// # Arguments
+    /// * `name` - 库名
+    /// * `code` - Rhai 源码
+    /// * `replace` - 是否覆盖同名的库
+    ///
+    /// # Returns
+    /// * `Ok(())` - 加载成功
+    /// * `Err(String)` - 库已存在、编译失败或函数名与其他库冲突
+    pub async fn load(&self, name: String, code: String, replace: bool) -> Result<(), String> {
+        let mut libraries = self.libraries.lock().await;
+        if libraries.contains_key(&name) && !replace {
+            return Err(format!("Library '{}' already exists", name));
+        }
+        let library = self.compile(&code)?;
+        for (other, existing) in libraries.iter() {
+            if *other == name {
+                continue;
+            }
+            if let Some(function) = library.functions.iter().find(|f| existing.functions.contains(f)) {
+                return Err(format!("Function {} already exists", function));
+            }
+        }
+        libraries.insert(name.clone(), library);
+        self.storage.set_function_library(name, code).await;
+        Ok(())
+    }
+
+    /// 删除函数库
+    ///
+    /// # Returns
+    /// 库是否存在
+    pub async fn delete(&self, name: &str) -> bool {
+        let removed = self.libraries.lock().await.remove(name).is_some();
+        if removed {
+            self.storage.remove_function_library(name).await;
+        }
+        removed
+    }
+
+    /// 删除所有函数库
+    pub async fn flush(&self) {
+        self.libraries.lock().await.clear();
+        self.storage.clear_function_libraries().await;
+    }
+
+    /// 列出所有函数库及其中的函数
+    pub async fn list(&self) -> Vec<(String, Vec<String>)> {
+        self.libraries.lock().await
+            .iter()
+            .map(|(name, library)| (name.clone(), library.functions.clone()))
+            .collect()
+    }
+
+    /// 调用函数
+    ///
+    /// # Arguments
+    /// * `caller` - 调用函数的连接的用户，函数中的命令按这个用户的权限检查
+    /// * `function` - 函数名
+    /// * `keys` - 作为第一个参数传入的键
+    /// * `args` - 作为第二个参数传入的参数
+    pub async fn call(&self, caller: Caller, function: String, keys: Vec<Bytes>, args: Vec<Bytes>) -> Response {
+        let ast = self.libraries.lock().await
+            .values()
+            .find(|library| library.functions.contains(&function))
+            .map(|library| library.ast.clone());
+        let Some(ast) = ast else {
+            return Response::Error("Function not found".into());
+        };
+
+        // 函数中的命令通过阻塞等待执行，需要在阻塞线程池中运行；引擎在调用之间共享，调用者作为这次调用的标签传给 `redis_call`
+        let engine = self.engine.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let keys: Array = keys.into_iter().map(bytes_to_dynamic).collect();
+            let args: Array = args.into_iter().map(bytes_to_dynamic).collect();
+            let options = CallFnOptions::new().with_tag(caller);
+            engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &ast, &function, (keys, args))
+        })
+        .await;
+
+        match result {
+            Ok(Ok(value)) => dynamic_to_response(value),
+            Ok(Err(e)) => Response::Error(format!("Error running function: {}", error_message(&e)).into()),
+            Err(e) => Response::Error(format!("Error running function: {}", e).into()),
+        }
+    }
+
+    /// 编译函数库源码，并找出可以调用的函数
+    fn compile(&self, code: &str) -> Result<Library, String> {
+        let ast = self.engine.compile(code)
+            .map_err(|e| format!("Error compiling function library: {}", e))?;
+        let functions: Vec<String> = ast.iter_functions()
+            .filter(|f| f.access == FnAccess::Public && f.params.len() == 2)
+            .map(|f| f.name.to_string())
+            .collect();
+        if functions.is_empty() {
+            return Err("No functions registered, define at least one fn name(keys, args)".to_string());
+        }
+        Ok(Library { ast, functions })
+    }
+}
+
+/// 创建注册了命令调用函数的 Rhai 引擎
+fn new_engine(storage: Arc<Storage>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let call_storage = storage.clone();
+    engine.register_fn("redis_call", move |context: NativeCallContext, command: Array| -> Result<Dynamic, Box<EvalAltResult>> {
+        match call(&call_storage, &context, command) {
+            Response::Error(e) => Err(e.to_string().into()),
+            response => Ok(response_to_dynamic(response)),
+        }
+    });
+
+    engine.register_fn("redis_pcall", move |context: NativeCallContext, command: Array| {
+        response_to_dynamic(call(&storage, &context, command))
+    });
+
+    engine
+}
+
+/// 执行函数中发起的命令，命令以数组形式给出，如 `["SET", key, value]`
+/// 调用者是 `Functions::call` 设置的调用标签，没有标签时（不经过 FCALL 的调用）不执行命令
+fn call(storage: &Storage, context: &NativeCallContext, command: Array) -> Response {
+    let Some(caller) = context.tag().and_then(|tag| tag.clone().try_cast::<Caller>()) else {
+        return Response::Error("redis_call can only be used in a function called by FCALL".into());
+    };
+    let words: Vec<Bytes> = command.into_iter().map(dynamic_to_bytes).collect();
+    if words.is_empty() {
+        return Response::Error("Please specify at least one argument for redis_call".into());
+    }
+    match Protocol::decode_args(&words) {
+        Ok(cmd) => Handle::current().block_on(caller.execute(storage, cmd)),
+        Err(e) => Response::Error(RedoxError::Syntax(e)),
+    }
+}
+
+/// 把命令的响应转换为 Rhai 值
+/// 不存在的值转换为 `()`，集合转换为字符串数组，错误转换为 `#{err: ...}`
+fn response_to_dynamic(response: Response) -> Dynamic {
+    let strings = |items: Vec<Bytes>| -> Dynamic {
+        Dynamic::from_array(items.into_iter().map(bytes_to_dynamic).collect())
+    };
+    match response {
+        Response::Ok => Dynamic::from("OK".to_string()),
+        Response::Error(e) => {
+            let mut map = Map::new();
+            map.insert("err".into(), Dynamic::from(e.to_string()));
+            Dynamic::from_map(map)
+        }
+        Response::Nil => Dynamic::UNIT,
+        Response::Integer(n) => Dynamic::from_int(n),
+        Response::Array(items) => Dynamic::from_array(
+            items.into_iter().map(|item| item.map(bytes_to_dynamic).unwrap_or(Dynamic::UNIT)).collect(),
+        ),
+        Response::Info(info) => {
+            let mut pairs: Vec<(String, String)> = info.into_iter().collect();
+            pairs.sort();
+            strings(pairs.into_iter().flat_map(|(k, v)| [k.into(), v.into()]).collect())
+        }
+        Response::Map(fields) => Dynamic::from_map(
+            fields.into_iter().map(|(name, value)| (name.into(), response_to_dynamic(value))).collect(),
+        ),
+        Response::Cursor(cursor, items) => Dynamic::from_array(vec![Dynamic::from(cursor.to_string()), strings(items)]),
+        Response::Replies(replies) => Dynamic::from_array(replies.into_iter().map(response_to_dynamic).collect()),
+        Response::Push { kind, items } => Dynamic::from_array(
+            std::iter::once(Dynamic::from(kind)).chain(items.into_iter().map(response_to_dynamic)).collect(),
+        ),
+        Response::Value(value) => match value {
+            RedoxValue::String(s) => bytes_to_dynamic(s),
+            RedoxValue::List(list) => strings(list.into()),
+            RedoxValue::Set(set) => strings(set.into_iter().collect()),
+            RedoxValue::Hash(hash) => strings(hash.into_iter().flat_map(|(k, v)| [k, v]).collect()),
+            RedoxValue::SortedSet(zset) => {
+                strings(zset.into_iter().flat_map(|(m, s)| [m, s.to_string().into()]).collect())
+            }
+            RedoxValue::Json(json) => Dynamic::from(json.to_string()),
+            RedoxValue::TimeSeries(ts) => strings(
+                ts.samples.into_iter().flat_map(|(t, v)| [t.to_string().into(), v.to_string().into()]).collect(),
+            ),
+        },
+    }
+}
+
+/// 把函数的返回值转换为响应
+/// 整数原样返回，浮点数截断为整数，true 为 1，false 和 `()` 为 Nil，
+/// 数组转换为数组响应，带 `err` 或 `ok` 字段的对象分别转换为错误和状态回复
+fn dynamic_to_response(value: Dynamic) -> Response {
+    if value.is_unit() {
+        return Response::Nil;
+    }
+    if let Ok(b) = value.as_bool() {
+        return if b { Response::Integer(1) } else { Response::Nil };
+    }
+    if let Ok(n) = value.as_int() {
+        return Response::Integer(n);
+    }
+    if let Ok(f) = value.as_float() {
+        return Response::Integer(f as i64);
+    }
+    if value.is_array() {
+        let items = value.cast::<Array>()
+            .into_iter()
+            .map(|item| match dynamic_to_response(item) {
+                Response::Integer(n) => Some(n.to_string().into()),
+                Response::Value(RedoxValue::String(s)) => Some(s),
+                // 不支持嵌套数组，整体转换为空值
+                _ => None,
+            })
+            .collect();
+        return Response::Array(items);
+    }
+    if value.is_map() {
+        let map = value.clone().cast::<Map>();
+        if let Some(err) = map.get("err") {
+            return Response::Error(RedoxError::parse(&err.to_string()));
+        }
+        if let Some(ok) = map.get("ok") {
+            let ok = ok.to_string();
+            return if ok == "OK" { Response::Ok } else { Response::Value(RedoxValue::string(ok)) };
+        }
+    }
+    Response::Value(RedoxValue::String(dynamic_to_bytes(value)))
+}
+
+/// 把二进制数据转换为 Rhai 值，合法的 UTF-8 转换为字符串，其他数据转换为 Blob
+fn bytes_to_dynamic(bytes: Bytes) -> Dynamic {
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(s) => Dynamic::from(s),
+        Err(e) => Dynamic::from_blob(e.into_bytes()),
+    }
+}
+
+/// 把 Rhai 值转换为二进制数据，Blob 原样转换，其他值使用其字符串形式
+fn dynamic_to_bytes(value: Dynamic) -> Bytes {
+    if value.is_blob() {
+        Bytes::from(value.cast::<Blob>())
+    } else {
+        Bytes::from(value.to_string())
+    }
+}
+
+/// 提取函数执行错误的主要信息
+fn error_message(error: &EvalAltResult) -> String {
+    match error {
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => error_message(inner),
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        e => e.to_string(),
+    }
+}
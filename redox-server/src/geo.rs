@@ -0,0 +1,172 @@
+//! 地理位置编码
+//! 与 Redis 相同，把经纬度交错编码为 52 位 geohash 并作为有序集合的分数存储，
+//! 52 位整数可以无损地保存在 f64 中
+
+use std::collections::BTreeSet;
+
+/// geohash 的精度（每个维度的位数）
+const GEO_STEP: u32 = 26;
+
+/// 经度范围
+pub const GEO_LONG_MIN: f64 = -180.0;
+pub const GEO_LONG_MAX: f64 = 180.0;
+/// 纬度范围（Web Mercator 投影的有效范围）
+pub const GEO_LAT_MIN: f64 = -85.051_128_78;
+pub const GEO_LAT_MAX: f64 = 85.051_128_78;
+
+/// 地球半径（米），与 Redis 保持一致
+const EARTH_RADIUS_IN_METERS: f64 = 6_372_797.560_856;
+
+/// 检查经纬度是否在可编码的范围内
+pub fn is_valid_coord(longitude: f64, latitude: f64) -> bool {
+    (GEO_LONG_MIN..=GEO_LONG_MAX).contains(&longitude)
+        && (GEO_LAT_MIN..=GEO_LAT_MAX).contains(&latitude)
+}
+
+/// 将经纬度编码为 geohash 分数
+///
+/// # Arguments
+/// * `longitude` - 经度
+/// * `latitude` - 纬度
+///
+/// # Returns
+/// 52 位交错编码的 geohash，转换为 f64 以便存入有序集合
+pub fn encode(longitude: f64, latitude: f64) -> f64 {
+    let (long_bits, lat_bits) = cell(longitude, latitude);
+    // 偶数位为纬度，奇数位为经度
+    (interleave(lat_bits) | (interleave(long_bits) << 1)) as f64
+}
+
+/// 计算经纬度在最高精度下所在的单元格
+///
+/// # Returns
+/// (经度方向的编号, 纬度方向的编号)
+fn cell(longitude: f64, latitude: f64) -> (u64, u64) {
+    let lat_offset = (latitude - GEO_LAT_MIN) / (GEO_LAT_MAX - GEO_LAT_MIN);
+    let long_offset = (longitude - GEO_LONG_MIN) / (GEO_LONG_MAX - GEO_LONG_MIN);
+
+    let scale = (1u64 << GEO_STEP) as f64;
+    let max_cell = (1u64 << GEO_STEP) - 1;
+    let lat_bits = ((lat_offset * scale) as u64).min(max_cell);
+    let long_bits = ((long_offset * scale) as u64).min(max_cell);
+    (long_bits, lat_bits)
+}
+
+/// 计算覆盖搜索范围的 geohash 单元格对应的分数区间
+/// 选择单元格不小于搜索范围半径的最高精度，中心点所在的单元格和周围 8 个单元格一定覆盖整个搜索范围，
+/// 每个单元格对应有序集合中一段连续的分数
+///
+/// # Arguments
+/// * `center` - 搜索中心 (经度, 纬度)
+/// * `half_width` - 搜索范围东西方向的半宽（米）
+/// * `half_height` - 搜索范围南北方向的半高（米）
+///
+/// # Returns
+/// 按分数排列、互不重叠的 [min, max] 分数区间（包含两端）
+pub fn covering_ranges(center: (f64, f64), half_width: f64, half_height: f64) -> Vec<(f64, f64)> {
+    let lat_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+    // 经度方向的跨度在离赤道最远的纬度上最大
+    let max_lat = (center.1.abs() + lat_delta).min(90.0);
+    let long_delta = (half_width / EARTH_RADIUS_IN_METERS).to_degrees() / max_lat.to_radians().cos();
+
+    // 靠近极点时 long_delta 为无穷大或 NaN，精度降到 0，整个有序集合只有一个单元格
+    let covers = |step: u32| {
+        let cells = (1u64 << step) as f64;
+        (GEO_LAT_MAX - GEO_LAT_MIN) / cells >= lat_delta && (GEO_LONG_MAX - GEO_LONG_MIN) / cells >= long_delta
+    };
+    let step = (0..=GEO_STEP).rev().find(|&step| covers(step)).unwrap_or(0);
+
+    let (long_bits, lat_bits) = cell(center.0, center.1);
+    let cells = 1i64 << step;
+    let long_cell = (long_bits >> (GEO_STEP - step)) as i64;
+    let lat_cell = (lat_bits >> (GEO_STEP - step)) as i64;
+    let shift = 2 * (GEO_STEP - step);
+
+    let mut hashes = BTreeSet::new();
+    for lat in lat_cell - 1..=lat_cell + 1 {
+        // 纬度不回绕，超出范围的一侧没有可以编码的点
+        if !(0..cells).contains(&lat) {
+            continue;
+        }
+        for long in long_cell - 1..=long_cell + 1 {
+            // 经度在 ±180 处回绕
+            let long = long.rem_euclid(cells) as u64;
+            hashes.insert(interleave(lat as u64) | (interleave(long) << 1));
+        }
+    }
+    hashes.into_iter()
+        .map(|hash| ((hash << shift) as f64, (((hash + 1) << shift) - 1) as f64))
+        .collect()
+}
+
+/// 将 geohash 分数解码为经纬度（取所在单元格的中心点）
+///
+/// # Returns
+/// (longitude, latitude)
+pub fn decode(score: f64) -> (f64, f64) {
+    let hash = score as u64;
+    let lat_bits = deinterleave(hash);
+    let long_bits = deinterleave(hash >> 1);
+
+    let scale = (1u64 << GEO_STEP) as f64;
+    let lat_min = GEO_LAT_MIN + (lat_bits as f64 / scale) * (GEO_LAT_MAX - GEO_LAT_MIN);
+    let lat_max = GEO_LAT_MIN + ((lat_bits + 1) as f64 / scale) * (GEO_LAT_MAX - GEO_LAT_MIN);
+    let long_min = GEO_LONG_MIN + (long_bits as f64 / scale) * (GEO_LONG_MAX - GEO_LONG_MIN);
+    let long_max = GEO_LONG_MIN + ((long_bits + 1) as f64 / scale) * (GEO_LONG_MAX - GEO_LONG_MIN);
+
+    let longitude = ((long_min + long_max) / 2.0).clamp(GEO_LONG_MIN, GEO_LONG_MAX);
+    let latitude = ((lat_min + lat_max) / 2.0).clamp(GEO_LAT_MIN, GEO_LAT_MAX);
+    (longitude, latitude)
+}
+
+/// 使用 haversine 公式计算两点间的球面距离（米）
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let lat1r = lat1.to_radians();
+    let lat2r = lat2.to_radians();
+    let u = ((lat2r - lat1r) / 2.0).sin();
+    let v = ((lon2 - lon1).to_radians() / 2.0).sin();
+    let a = u * u + lat1r.cos() * lat2r.cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+/// 判断点是否位于以中心点为基准的矩形范围内
+///
+/// # Arguments
+/// * `width` - 矩形宽度（米，东西方向）
+/// * `height` - 矩形高度（米，南北方向）
+///
+/// # Returns
+/// 在矩形内时返回到中心点的距离（米）
+pub fn within_box(center: (f64, f64), width: f64, height: f64, point: (f64, f64)) -> Option<f64> {
+    // 南北方向的距离只取决于纬度差
+    let lat_distance = distance(point.0, point.1, point.0, center.1);
+    if lat_distance > height / 2.0 {
+        return None;
+    }
+    // 东西方向的距离在点所在纬度上测量
+    let long_distance = distance(point.0, point.1, center.0, point.1);
+    if long_distance > width / 2.0 {
+        return None;
+    }
+    Some(distance(center.0, center.1, point.0, point.1))
+}
+
+/// 把 32 位整数的各位分散到 64 位整数的偶数位上
+fn interleave(value: u64) -> u64 {
+    let mut x = value & 0xFFFF_FFFF;
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// interleave 的逆操作，取出偶数位
+fn deinterleave(value: u64) -> u64 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    (x | (x >> 16)) & 0x0000_0000_FFFF_FFFF
+}
@@ -0,0 +1,12 @@
+//! 通配符匹配
+//! CONFIG GET 的名称模式和 ACL 的键模式共用，`*` 匹配任意个字符，`?` 匹配一个字符。
+
+/// 判断文本是否与模式匹配
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
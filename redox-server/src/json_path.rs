@@ -0,0 +1,160 @@
+//! JSON 文档的路径操作
+//! 支持 `$`（或 `.`）表示根节点，`.field` / `["field"]` 访问对象字段，`[n]` 访问数组元素
+//! （负数从末尾开始计数），例如 `$.user.tags[0]`
+
+use serde_json::Value;
+
+/// 路径中的一段
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    /// 对象字段
+    Field(String),
+    /// 数组下标
+    Index(i64),
+}
+
+/// 解析路径字符串
+///
+/// # Arguments
+/// * `path` - 路径字符串，如 `$.a.b[0]`
+///
+/// # Returns
+/// * `Ok(Vec<Segment>)` - 解析后的路径，空表示根节点
+/// * `Err(String)` - 路径语法错误
+pub fn parse(path: &str) -> Result<Vec<Segment>, String> {
+    let rest = path.strip_prefix('$').unwrap_or(path);
+    let chars: Vec<char> = rest.chars().collect();
+    let mut segments = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                i += 1;
+                let start = i;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                if start == i {
+                    // 单独的 "." 表示根节点
+                    if chars.len() == 1 {
+                        break;
+                    }
+                    return Err(format!("Invalid path: {}", path));
+                }
+                segments.push(Segment::Field(chars[start..i].iter().collect()));
+            }
+            '[' => {
+                let end = chars[i..].iter().position(|c| *c == ']')
+                    .map(|p| p + i)
+                    .ok_or_else(|| format!("Invalid path: {}", path))?;
+                let inner: String = chars[i + 1..end].iter().collect();
+                let inner = inner.trim();
+                let quoted = (inner.starts_with('"') && inner.ends_with('"'))
+                    || (inner.starts_with('\'') && inner.ends_with('\''));
+                if quoted && inner.len() >= 2 {
+                    segments.push(Segment::Field(inner[1..inner.len() - 1].to_string()));
+                } else {
+                    let index = inner.parse::<i64>()
+                        .map_err(|_| format!("Invalid path: {}", path))?;
+                    segments.push(Segment::Index(index));
+                }
+                i = end + 1;
+            }
+            _ if i == 0 && path == rest => {
+                // 兼容不带前缀的写法，如 "a.b"
+                let start = i;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                segments.push(Segment::Field(chars[start..i].iter().collect()));
+            }
+            _ => return Err(format!("Invalid path: {}", path)),
+        }
+    }
+
+    Ok(segments)
+}
+
+/// 把可能为负数的下标转换为数组中的实际位置
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index >= 0 && (index as usize) < len {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+/// 获取路径指向的节点
+pub fn get<'a>(root: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(root, |node, segment| match (segment, node) {
+        (Segment::Field(field), Value::Object(map)) => map.get(field),
+        (Segment::Index(index), Value::Array(arr)) => arr.get(resolve_index(*index, arr.len())?),
+        _ => None,
+    })
+}
+
+/// 获取路径指向节点的可变引用
+pub fn get_mut<'a>(root: &'a mut Value, path: &[Segment]) -> Option<&'a mut Value> {
+    path.iter().try_fold(root, |node, segment| match (segment, node) {
+        (Segment::Field(field), Value::Object(map)) => map.get_mut(field),
+        (Segment::Index(index), Value::Array(arr)) => {
+            let index = resolve_index(*index, arr.len())?;
+            arr.get_mut(index)
+        }
+        _ => None,
+    })
+}
+
+/// 设置路径指向的节点
+/// 已存在的节点会被替换；父节点是对象时可以新建字段
+///
+/// # Returns
+/// * `true` - 设置成功
+/// * `false` - 父节点不存在或类型不匹配
+pub fn set(root: &mut Value, path: &[Segment], value: Value) -> bool {
+    let Some((last, parent_path)) = path.split_last() else {
+        *root = value;
+        return true;
+    };
+    match (last, get_mut(root, parent_path)) {
+        (Segment::Field(field), Some(Value::Object(map))) => {
+            map.insert(field.clone(), value);
+            true
+        }
+        (Segment::Index(index), Some(Value::Array(arr))) => {
+            match resolve_index(*index, arr.len()) {
+                Some(index) => {
+                    arr[index] = value;
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// 删除路径指向的节点（不能是根节点）
+///
+/// # Returns
+/// 是否删除了节点
+pub fn delete(root: &mut Value, path: &[Segment]) -> bool {
+    let Some((last, parent_path)) = path.split_last() else {
+        return false;
+    };
+    match (last, get_mut(root, parent_path)) {
+        (Segment::Field(field), Some(Value::Object(map))) => map.remove(field).is_some(),
+        (Segment::Index(index), Some(Value::Array(arr))) => {
+            match resolve_index(*index, arr.len()) {
+                Some(index) => {
+                    arr.remove(index);
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
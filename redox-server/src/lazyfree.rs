@@ -0,0 +1,118 @@
+//! 惰性释放
+//! 释放有几百万个元素的集合需要逐个释放元素，在分片的写锁内完成会阻塞访问同一分片的所有命令。
+//! UNLINK、FLUSHALL ASYNC、内存淘汰和过期键清理在锁内只摘除键，
+//! 元素数超过阈值的值交给专门的后台线程释放；小的值直接释放，比发送到后台线程更快。
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use redox_protocol::RedoxValue;
+
+/// 默认的惰性释放阈值，与 Redis 的 LAZYFREE_THRESHOLD 相同
+pub const DEFAULT_THRESHOLD: usize = 64;
+
+/// 交给后台线程释放的对象
+type Garbage = Box<dyn Send>;
+
+/// 惰性释放器，克隆后共享同一个后台线程和统计
+#[derive(Clone)]
+pub struct LazyFree {
+    /// 发送待释放对象的通道，后台线程在所有发送端释放后退出
+    sender: Sender<Garbage>,
+    /// 元素数超过这个值的值在后台释放，可以在运行时修改
+    threshold: Arc<AtomicUsize>,
+    /// 已发送但还没有释放的对象数
+    pending: Arc<AtomicUsize>,
+    /// 后台线程已释放的对象数
+    freed: Arc<AtomicU64>,
+}
+
+impl LazyFree {
+    /// 创建惰性释放器并启动后台线程
+    ///
+    /// # Arguments
+    /// * `threshold` - 元素数超过这个值的值在后台释放
+    pub fn new(threshold: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Garbage>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let freed = Arc::new(AtomicU64::new(0));
+        {
+            let pending = pending.clone();
+            let freed = freed.clone();
+            std::thread::Builder::new()
+                .name("lazyfree".to_string())
+                .spawn(move || {
+                    for garbage in receiver {
+                        drop(garbage);
+                        pending.fetch_sub(1, Ordering::Relaxed);
+                        freed.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+                .expect("failed to spawn lazyfree thread");
+        }
+        Self {
+            sender,
+            threshold: Arc::new(AtomicUsize::new(threshold)),
+            pending,
+            freed,
+        }
+    }
+
+    /// 修改惰性释放阈值，立即生效
+    pub fn set_threshold(&self, threshold: usize) {
+        self.threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    /// 释放从存储中摘除的值
+    /// 值仍被其他读取者或保存中的快照共享时只减少引用计数，直接释放；
+    /// 否则元素数超过阈值时交给后台线程，不超过时直接释放
+    pub fn free(&self, value: Arc<RedoxValue>) {
+        if Arc::strong_count(&value) > 1 || effort(&value) <= self.threshold.load(Ordering::Relaxed) {
+            return;
+        }
+        self.free_in_background(Box::new(value));
+    }
+
+    /// 不管大小，把对象交给后台线程释放，用于 FLUSHALL ASYNC 摘除的整个分片
+    pub fn free_in_background(&self, garbage: Garbage) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        if let Err(mpsc::SendError(garbage)) = self.sender.send(garbage) {
+            // 后台线程已经退出（只会在进程结束时发生），直接释放
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            drop(garbage);
+        }
+    }
+
+    /// 已发送但还没有释放的对象数，用于 INFO 的 lazyfree_pending_objects
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// 后台线程已释放的对象数，用于 INFO 的 lazyfreed_objects
+    pub fn freed(&self) -> u64 {
+        self.freed.load(Ordering::Relaxed)
+    }
+}
+
+/// 释放值需要的工作量，即需要逐个释放的元素数
+fn effort(value: &RedoxValue) -> usize {
+    match value {
+        RedoxValue::String(_) => 1,
+        RedoxValue::List(list) => list.len(),
+        RedoxValue::Set(set) => set.len(),
+        RedoxValue::Hash(hash) => hash.len(),
+        RedoxValue::SortedSet(zset) => zset.len(),
+        RedoxValue::Json(json) => json_effort(json),
+        RedoxValue::TimeSeries(series) => series.samples.len(),
+    }
+}
+
+/// JSON 值中数组和对象的元素总数
+fn json_effort(value: &serde_json::Value) -> usize {
+    use serde_json::Value;
+    match value {
+        Value::Array(arr) => arr.len() + arr.iter().map(json_effort).sum::<usize>(),
+        Value::Object(map) => map.len() + map.values().map(json_effort).sum::<usize>(),
+        _ => 0,
+    }
+}
@@ -0,0 +1,61 @@
+//! Redox 服务器
+//! 除了 `redox-server` 可执行文件，也可以作为库嵌入其他程序，在进程内运行一个 Redox 实例，
+//! 集成测试不需要启动单独的服务器进程：
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! let server = redox_server::Server::builder()
+//!     .password("secret")
+//!     .bind("127.0.0.1:0")
+//!     .spawn()
+//!     .await?;
+//! println!("listening on {}", server.addr());
+//! server.shutdown().await
+//! # }
+//! ```
+
+pub mod acl;
+pub mod builder;
+pub mod clock;
+pub(crate) mod clients;
+pub(crate) mod cluster;
+pub(crate) mod commands;
+pub mod config;
+pub(crate) mod debug;
+pub(crate) mod dump;
+pub(crate) mod eviction;
+pub(crate) mod functions;
+pub(crate) mod geo;
+pub(crate) mod glob;
+pub(crate) mod json_path;
+pub(crate) mod lazyfree;
+pub mod logging;
+pub(crate) mod memory;
+pub(crate) mod migrate;
+pub(crate) mod monitor;
+pub mod network;
+pub(crate) mod observer;
+pub(crate) mod peers;
+pub mod persistence;
+pub(crate) mod proxy;
+pub(crate) mod pubsub;
+pub(crate) mod quota;
+pub mod rdb;
+pub(crate) mod redis_upstream;
+pub(crate) mod replication;
+pub(crate) mod s3;
+pub(crate) mod scripting;
+pub(crate) mod sled_backend;
+pub mod storage;
+pub mod task;
+pub(crate) mod timeseries;
+pub(crate) mod tls;
+pub(crate) mod tracking;
+pub(crate) mod transaction;
+
+pub use builder::{ServerBuilder, ServerHandle};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use config::Config;
+pub use network::Server;
+pub use persistence::Persistence;
+pub use storage::Storage;
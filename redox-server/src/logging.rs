@@ -0,0 +1,76 @@
+//! 服务器日志
+//! 按级别过滤输出：`notice!` 输出到标准输出，`warning!` 输出到标准错误，
+//! 级别可以在配置文件中设置，也可以通过 CONFIG SET loglevel 修改。
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// 日志级别，从低到高
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// 调试信息
+    Debug,
+    /// 详细信息
+    Verbose,
+    /// 启动、关闭和保存等重要事件
+    Notice,
+    /// 只输出错误和警告
+    Warning,
+}
+
+impl Level {
+    /// 解析级别名称（不区分大小写）
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "verbose" => Some(Level::Verbose),
+            "notice" => Some(Level::Notice),
+            "warning" => Some(Level::Warning),
+            _ => None,
+        }
+    }
+
+    /// 级别名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Verbose => "verbose",
+            Level::Notice => "notice",
+            Level::Warning => "warning",
+        }
+    }
+}
+
+/// 当前的日志级别
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Notice as u8);
+
+/// 设置日志级别，低于这个级别的日志不再输出
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// 这个级别的日志是否需要输出
+pub fn enabled(level: Level) -> bool {
+    level as u8 >= LEVEL.load(Ordering::Relaxed)
+}
+
+/// 输出 notice 级别的日志
+#[macro_export]
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Notice) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// 输出 warning 级别的日志
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Warning) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub use {notice, warning};
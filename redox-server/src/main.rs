@@ -1,72 +1,115 @@
-mod network;
-mod storage;
-mod persistence;
-
-use network::Server;
-use storage::Storage;
-use persistence::Persistence;
+use redox_server::clock::SharedClock;
+use redox_server::config::{self, Config, ServerConfig};
+use redox_server::logging::{self, notice, warning};
+use redox_server::{rdb, Persistence, Server, Storage};
+use redox_server::task::spawn_named;
 use clap::Parser;
-use std::time::Duration;
-
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct ServerConfig {
-    /// Port to listen on (default: 2001)
-    #[arg(short = 'P', long, default_value_t = 2001)]
-    port: u16,
-
-    /// Password for authentication
-    #[arg(short = 'p', long)]
-    password: Option<String>,
-
-    /// Data file path for persistence
-    #[arg(short = 'f', long)]
-    data_file: Option<String>,
-
-    /// Auto-save interval in seconds
-    #[arg(short = 'i', long, default_value_t = 60)]
-    save_interval: u64,
-}
+use std::sync::Arc;
 
 /// 服务器入口函数
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = ServerConfig::parse();
+    // 启用 console 特性时，注册 tokio-console 的订阅者
+    #[cfg(feature = "console")]
+    console_subscriber::init();
 
-    let persistence = config.data_file.map(|path| {
-        println!("Using data file: {}", path);
-        Persistence::new(
-            path,
-            Duration::from_secs(config.save_interval),
-        )
-    });
+    let args = ServerConfig::parse();
+    let config = Config::load(&args)?;
+    logging::set_level(config.loglevel);
+    if let Some(path) = &args.config {
+        notice!("Using config file: {}", path);
+    }
 
-    let storage = Storage::new(persistence);
-    
-    // 启动清理任务
-    let storage_clone = storage.clone();
-    tokio::spawn(async move {
-        storage_clone.start_cleanup_task().await;
-    });
+    let persistence = match config.data_file.clone() {
+        Some(path) => {
+            notice!("Using data file: {} ({} backend)", path, config.persistence_backend.as_str());
+            let backend = config.persistence_backend.open(path, config.compression_level, &config.s3)?;
+            Some(Persistence::new(backend, config.save.clone()))
+        }
+        None => None,
+    };
 
-    let server = Server::new(storage, config.password);
-    
+    // 以 test-clock 特性编译的测试构建使用手动时钟，键的过期由 DEBUG SET-TIME 控制，测试不需要等待
+    #[cfg(feature = "test-clock")]
+    let clock: SharedClock = {
+        notice!("Using a manual clock, time only changes with DEBUG SET-TIME");
+        Arc::new(redox_server::ManualClock::from_system())
+    };
+    #[cfg(not(feature = "test-clock"))]
+    let clock: SharedClock = Arc::new(redox_server::SystemClock);
+    let storage = Storage::with_clock(persistence, clock);
+    if let Some(path) = &args.import_rdb {
+        let imported = rdb::load(path)?;
+        let count = storage.import(imported).await;
+        notice!("Imported {} key(s) from {}", count, path);
+    }
+    if let Some(path) = &args.export_rdb {
+        let count = storage.export_rdb(path.clone()).await?;
+        notice!("Exported {} key(s) to {}", count, path);
+        return Ok(());
+    }
+
+    let bind = config.bind.clone();
     let mut current_port = config.port;
-    loop {
-        let addr = format!("127.0.0.1:{}", current_port);
-        match server.try_bind(&addr).await {
-            Ok(_) => {
-                println!("Successfully bound to port {}", current_port);
-                server.run(&addr).await?;
-                break;
+    let (server, _cleanup) = Server::builder().config(config).storage(storage.clone()).build().await?;
+
+    // 收到 SIGHUP 时重新加载配置文件
+    #[cfg(unix)]
+    if args.config.is_some() {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = signal(SignalKind::hangup())?;
+        let config = server.config();
+        let acl = server.acl();
+        let storage = storage.clone();
+        spawn_named("config-reload", async move {
+            while hangup.recv().await.is_some() {
+                config::reload(&args, &config, &storage, &acl);
             }
-            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
-                println!("Port {} is in use, trying {}", current_port, current_port + 1);
+        });
+    }
+    
+    // 端口被占用时尝试下一个端口，绑定成功的监听器直接用于接受连接，端口不会在两次绑定之间被其他进程占用
+    let listeners = loop {
+        let addr = format!("{}:{}", bind, current_port);
+        match server.bind(&addr).await {
+            Ok(listeners) => break listeners,
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && current_port != 0 && current_port < u16::MAX => {
+                notice!("Port {} is in use, trying {}", current_port, current_port + 1);
                 current_port += 1;
             }
             Err(e) => return Err(e.into()),
         }
+    };
+
+    // 供测试脚本等待并读取实际端口的一行，--port 0 时端口由系统分配，不受日志级别影响
+    let local = listeners[0].local_addr()?;
+    println!("READY addr={} pid={}", local, std::process::id());
+    server.serve(listeners, shutdown_signal()).await?;
+
+    // 不管数据是否有修改都保存一次快照，即使数据文件在运行中被删除或损坏，退出后也是完整的
+    match storage.save_now().await {
+        Ok(true) => notice!("Data saved"),
+        Ok(false) => {}
+        Err(e) => warning!("Error saving data: {}", e),
     }
-    
+    notice!("Server stopped");
     Ok(())
+}
+
+/// 等待 SIGINT（Ctrl+C）或 SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,156 @@
+//! 内存占用估算
+//! 根据 Rust 数据结构的布局估算每个键占用的字节数，集合类型可以只采样部分元素后按比例推算。
+//! 存储在写入和删除键时按同样的估算维护内存统计，用于 MEMORY STATS 和 INFO。
+
+use bytes::Bytes;
+use redox_protocol::RedoxValue;
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+/// MEMORY USAGE 默认的采样数量
+pub const DEFAULT_SAMPLES: usize = 5;
+
+/// 哈希表每个槽位的额外开销（hashbrown 的控制字节）
+const HASH_CTRL_BYTES: usize = 1;
+
+/// 值的内部表示名称
+pub fn encoding(value: &RedoxValue) -> &'static str {
+    match value {
+        RedoxValue::String(_) => "string",
+        RedoxValue::List(_) => "vecdeque",
+        RedoxValue::Set(_) => "hashset",
+        RedoxValue::Hash(_) => "hashmap",
+        RedoxValue::SortedSet(_) => "hashmap+btreeset",
+        RedoxValue::Json(_) => "json",
+        RedoxValue::TimeSeries(_) => "timeseries",
+    }
+}
+
+/// 估算一个键值对占用的字节数
+///
+/// # Arguments
+/// * `key` - 键
+/// * `value` - 值
+/// * `samples` - 集合类型采样的元素数量，0 表示统计全部元素
+pub fn key_usage(key: &[u8], value: &RedoxValue, samples: usize) -> usize {
+    // 键和值本身在全局哈希表中的槽位
+    let slot = size_of::<Bytes>() + size_of::<RedoxValue>() + HASH_CTRL_BYTES;
+    slot + key.len() + value_heap_size(value, samples)
+}
+
+/// 内存统计中一个键的统计项：值的内部表示名称和估算的字节数
+/// 同一个值多次估算的结果相同，写入时加入统计的数量在删除时可以原样减去
+pub fn measure(key: &[u8], value: &RedoxValue) -> (&'static str, usize) {
+    (encoding(value), key_usage(key, value, DEFAULT_SAMPLES))
+}
+
+/// 一组键的内存统计
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStats {
+    /// 所有键估算的字节数
+    pub bytes: usize,
+    /// 键的数量
+    pub keys: usize,
+    /// 内部表示名称到 (字节数, 键数) 的映射
+    pub by_encoding: BTreeMap<&'static str, (usize, usize)>,
+}
+
+impl MemoryStats {
+    /// 加入一个键的统计项
+    pub fn add(&mut self, (encoding, bytes): (&'static str, usize)) {
+        self.bytes += bytes;
+        self.keys += 1;
+        let entry = self.by_encoding.entry(encoding).or_default();
+        entry.0 += bytes;
+        entry.1 += 1;
+    }
+
+    /// 减去一个键的统计项
+    pub fn sub(&mut self, (encoding, bytes): (&'static str, usize)) {
+        self.bytes -= bytes;
+        self.keys -= 1;
+        if let Some(entry) = self.by_encoding.get_mut(encoding) {
+            entry.0 -= bytes;
+            entry.1 -= 1;
+            if entry.1 == 0 {
+                self.by_encoding.remove(encoding);
+            }
+        }
+    }
+
+    /// 合并另一组键的统计
+    pub fn merge(&mut self, other: &MemoryStats) {
+        self.bytes += other.bytes;
+        self.keys += other.keys;
+        for (encoding, (bytes, keys)) in &other.by_encoding {
+            let entry = self.by_encoding.entry(encoding).or_default();
+            entry.0 += bytes;
+            entry.1 += keys;
+        }
+    }
+}
+
+/// 以 K、M、G 为单位显示字节数，如 `1.50M`，用于 INFO 的 used_memory_human
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [(&str, f64); 3] = [("G", 1073741824.0), ("M", 1048576.0), ("K", 1024.0)];
+    for (unit, size) in UNITS {
+        if bytes as f64 >= size {
+            return format!("{:.2}{}", bytes as f64 / size, unit);
+        }
+    }
+    format!("{}B", bytes)
+}
+
+/// 估算值在堆上占用的字节数
+pub fn value_heap_size(value: &RedoxValue, samples: usize) -> usize {
+    match value {
+        RedoxValue::String(s) => s.len(),
+        RedoxValue::List(list) => {
+            list.capacity() * size_of::<Bytes>()
+                + sampled(list.iter(), list.len(), samples, |s| s.len())
+        }
+        RedoxValue::Set(set) => {
+            set.capacity() * (size_of::<Bytes>() + HASH_CTRL_BYTES)
+                + sampled(set.iter(), set.len(), samples, |s| s.len())
+        }
+        RedoxValue::Hash(hash) => {
+            hash.capacity() * (size_of::<(Bytes, Bytes)>() + HASH_CTRL_BYTES)
+                + sampled(hash.iter(), hash.len(), samples, |(k, v)| k.len() + v.len())
+        }
+        RedoxValue::SortedSet(zset) => {
+            // 每个成员在哈希表和排序索引中各有一项，成员的字节由两处共享；
+            // BTreeSet 的节点开销按每个元素一个指针估算
+            zset.len() * (size_of::<(Bytes, f64)>() + HASH_CTRL_BYTES + size_of::<(f64, Bytes)>() + size_of::<usize>())
+                + sampled(zset.iter(), zset.len(), samples, |(member, _)| member.len())
+        }
+        RedoxValue::Json(json) => json_size(json),
+        RedoxValue::TimeSeries(series) => {
+            series.samples.len() * (size_of::<(u64, f64)>() + size_of::<usize>())
+        }
+    }
+}
+
+/// 统计样本元素的平均大小，并推算到全部元素
+fn sampled<I, T>(iter: I, len: usize, samples: usize, size: impl Fn(T) -> usize) -> usize
+where
+    I: Iterator<Item = T>,
+{
+    if samples == 0 || samples >= len {
+        return iter.map(size).sum();
+    }
+    let total: usize = iter.take(samples).map(size).sum();
+    total * len / samples
+}
+
+/// 递归估算 JSON 值占用的字节数
+fn json_size(value: &serde_json::Value) -> usize {
+    use serde_json::Value;
+    match value {
+        Value::String(s) => s.capacity(),
+        Value::Array(arr) => arr.capacity() * size_of::<Value>() + arr.iter().map(json_size).sum::<usize>(),
+        Value::Object(map) => map.iter()
+            .map(|(k, v)| size_of::<(String, Value)>() + k.capacity() + json_size(v))
+            .sum(),
+        _ => 0,
+    }
+}
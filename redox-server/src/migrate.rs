@@ -0,0 +1,122 @@
+//! MIGRATE：把键发送到另一个实例
+//! 每个键按 DUMP 的格式序列化，连接目标实例后依次发送 ASKING 和 RESTORE，目标节点正在迁入键所在的槽时也会接受；
+//! 目标实例确认后删除本地的键（指定 COPY 时保留）。每次 MIGRATE 建立一个新的连接，命令完成后关闭。
+//! 集群模式下迁移槽时，对迁出的槽中的键逐批执行 MIGRATE，客户端在迁移期间由 ASK 重定向到目标节点。
+
+use crate::dump;
+use crate::storage::Storage;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::{Command, RedoxError, RedoxValue, Response};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_util::codec::Framed;
+
+/// timeout 为 0 时连接和等待每个回复的超时时间
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 执行 MIGRATE
+///
+/// # Arguments
+/// * `storage` - 存储实例
+/// * `cmd` - MIGRATE 命令
+///
+/// # Returns
+/// * `Response::Ok` - 所有存在的键都已发送到目标实例
+/// * `NOKEY` - 所有键都不存在
+/// * `Response::Error` - 连接失败或超时（IOERR），或目标实例拒绝了某个键；目标实例已接受的键仍然从本地删除
+pub async fn migrate(storage: &Storage, cmd: Command) -> Response {
+    let Command::Migrate { host, port, keys, timeout, copy, replace, auth } = cmd else {
+        return Response::Error(format!("'{}' is not MIGRATE", cmd.name()).into());
+    };
+    let timeout = if timeout == 0 { DEFAULT_TIMEOUT } else { Duration::from_millis(timeout) };
+
+    // 序列化存在的键，剩余的生存时间作为 RESTORE 的 ttl
+    let now = storage.now_ms();
+    let mut restores = Vec::new();
+    for key in keys {
+        let Some(entry) = storage.entry(&key).await else {
+            continue;
+        };
+        let ttl = entry.expire_at.map_or(0, |when| when.saturating_sub(now).max(1));
+        let payload = dump::to_hex(&dump::serialize(&entry.value));
+        restores.push((key.clone(), Command::Restore { key, ttl, payload, replace }));
+    }
+    if restores.is_empty() {
+        return Response::Value(RedoxValue::string("NOKEY"));
+    }
+
+    let mut framed = match time::timeout(timeout, TcpStream::connect((host.as_str(), port))).await {
+        Ok(Ok(stream)) => Framed::new(stream, ClientCodec::new()),
+        _ => return Response::Error(RedoxError::IoErr("error or timeout connecting to the client".to_string())),
+    };
+    if let Some((username, password)) = auth {
+        match request(&mut framed, &Command::Auth { username, password }, timeout).await {
+            Ok(Response::Error(e)) => return Response::Error(target_error(&e)),
+            Ok(_) => {}
+            Err(e) => return Response::Error(e),
+        }
+    }
+
+    // 所有命令一次发出，再依次读取回复；ASKING 在没有启用集群模式的目标上返回错误，忽略它的回复
+    for (_, restore) in &restores {
+        for cmd in [&Command::Asking, restore] {
+            if let Err(e) = framed.feed(cmd).await {
+                return Response::Error(RedoxError::IoErr(e.to_string()));
+            }
+        }
+    }
+    if let Err(e) = framed.flush().await {
+        return Response::Error(RedoxError::IoErr(e.to_string()));
+    }
+    let mut migrated = Vec::new();
+    let mut error = None;
+    for (key, _) in restores {
+        let reply = match read_reply(&mut framed, timeout).await {
+            Ok(_) => read_reply(&mut framed, timeout).await,
+            Err(e) => Err(e),
+        };
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        };
+        match reply {
+            Response::Error(e) => {
+                error.get_or_insert(target_error(&e));
+            }
+            _ => migrated.push(key),
+        }
+    }
+    if !copy && !migrated.is_empty() {
+        storage.del(&migrated).await;
+    }
+    match error {
+        Some(e) => Response::Error(e),
+        None => Response::Ok,
+    }
+}
+
+/// 目标实例拒绝命令时返回给客户端的错误
+fn target_error(e: &RedoxError) -> RedoxError {
+    RedoxError::Err(format!("Target instance replied with error: {}", e))
+}
+
+/// 发送一个命令并等待回复
+async fn request(framed: &mut Framed<TcpStream, ClientCodec>, cmd: &Command, timeout: Duration) -> Result<Response, RedoxError> {
+    framed.send(cmd).await.map_err(|e| RedoxError::IoErr(e.to_string()))?;
+    read_reply(framed, timeout).await
+}
+
+/// 读取一个回复
+async fn read_reply(framed: &mut Framed<TcpStream, ClientCodec>, timeout: Duration) -> Result<Response, RedoxError> {
+    match time::timeout(timeout, framed.next()).await {
+        Ok(Some(Ok(reply))) => Ok(reply),
+        Ok(Some(Err(e))) => Err(RedoxError::IoErr(e.to_string())),
+        Ok(None) => Err(RedoxError::IoErr("connection closed by the target instance".to_string())),
+        Err(_) => Err(RedoxError::IoErr("error or timeout reading from the target instance".to_string())),
+    }
+}
@@ -0,0 +1,92 @@
+//! MONITOR
+//! 每个连接执行命令前把命令交给 `Monitor`，执行了 MONITOR 的连接依次收到这些命令，用于调试时观察服务器收到的请求。
+//! 没有 MONITOR 连接时不生成输出行；携带密码的命令（AUTH、HELLO、带 AUTH 的 MIGRATE）和管理命令不会输出，与 Redis 相同。
+
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::{CodecError, RedoxCodec, WireProtocol};
+use redox_protocol::{Command, Protocol, RedoxValue, Response};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+
+/// 每个 MONITOR 连接最多积压的命令数，连接读取太慢时跳过较早的命令
+const MONITOR_BACKLOG: usize = 4096;
+
+/// 把执行的命令分发给所有 MONITOR 连接
+pub struct Monitor {
+    /// 输出行的广播通道，每个 MONITOR 连接一个接收端
+    feed: broadcast::Sender<String>,
+}
+
+impl Monitor {
+    /// 创建没有 MONITOR 连接的分发器
+    pub fn new() -> Self {
+        let (feed, _) = broadcast::channel(MONITOR_BACKLOG);
+        Self { feed }
+    }
+
+    /// 记录一个即将执行的命令
+    /// 输出行的格式为 `<秒>.<微秒> [0 <客户端地址>] <命令>`，命令按行协议编码，参数必要时加引号
+    ///
+    /// # Arguments
+    /// * `addr` - 发送命令的客户端地址
+    /// * `cmd` - 命令
+    pub fn feed(&self, addr: SocketAddr, cmd: &Command) {
+        if self.feed.receiver_count() == 0 || hidden(cmd) {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let line = format!(
+            "{}.{:06} [0 {}] {}",
+            now.as_secs(),
+            now.subsec_micros(),
+            addr,
+            Protocol::encode_command(cmd).trim_end(),
+        );
+        let _ = self.feed.send(line);
+    }
+
+    /// 把连接转为 MONITOR 连接，直到连接断开或 `kill` 被取消
+    /// 之后收到的请求都被忽略
+    ///
+    /// # Arguments
+    /// * `framed` - 连接，已回复 MONITOR
+    /// * `protocol` - 连接的协议，每个命令编码为一个字符串回复
+    /// * `kill` - 服务器关闭或 CLIENT KILL 时取消
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        mut framed: Framed<S, RedoxCodec>,
+        protocol: WireProtocol,
+        kill: CancellationToken,
+    ) -> Result<(), CodecError> {
+        let mut feed = self.feed.subscribe();
+        loop {
+            let line = tokio::select! {
+                line = feed.recv() => match line {
+                    Ok(line) => line,
+                    Err(RecvError::Lagged(skipped)) => format!("({} commands skipped, the monitor is reading too slowly)", skipped),
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                frame = framed.next() => match frame {
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                },
+                _ = kill.cancelled() => return Ok(()),
+            };
+            let reply = protocol.encode_response(&Response::Value(RedoxValue::string(line)));
+            framed.send(reply).await?;
+        }
+    }
+}
+
+/// 不输出的命令
+fn hidden(cmd: &Command) -> bool {
+    match cmd {
+        Command::Auth { .. } | Command::Hello { .. } | Command::Migrate { auth: Some(_), .. } => true,
+        cmd => cmd.spec().has_flag("admin"),
+    }
+}
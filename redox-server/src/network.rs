@@ -1,17 +1,79 @@
+use crate::acl::{Acl, DEFAULT_USER};
+use crate::clients::Clients;
+use crate::cluster::Cluster;
+use crate::commands;
+use crate::debug;
+use crate::config::{self, Config, SharedConfig};
+use crate::logging::{notice, warning};
+use crate::monitor::Monitor;
+use crate::functions::Functions;
+use crate::proxy;
+use crate::pubsub::PubSub;
+use crate::peers::{self, Peers};
+use crate::replication::Replication;
+use crate::scripting::{Caller, Scripting};
 use crate::storage::Storage;
-use redox_protocol::{Command, Protocol, Response, RedoxValue};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use crate::task::spawn_named;
+use crate::tracking::{Tracker, Tracking, INVALIDATION_BACKLOG};
+use crate::transaction::{self, Transaction, Watch, Watches};
+use bytes::Bytes;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, SinkExt, StreamExt};
+use redox_protocol::codec::{CodecError, RedoxCodec, WireProtocol};
+use redox_protocol::meta::{self, CommandSpec};
+use redox_protocol::resp::RespVersion;
+use redox_protocol::{Command, RedoxError, Response, RedoxValue};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
 use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use std::future::Future;
 use std::io;
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 /// 服务器结构体
 /// 管理网络连接和存储实例
 pub struct Server {
+    /// 所有连接共享的状态
+    shared: Shared,
+    /// 启用 TLS 时的接受器，所有连接都需要先完成 TLS 握手
+    tls: Option<TlsAcceptor>,
+}
+
+/// 所有连接共享的服务器状态，每个连接持有一份克隆
+#[derive(Clone)]
+struct Shared {
     /// 存储实例，用于数据操作
     storage: Arc<Storage>,
-    /// 可选的认证密码
-    password: Option<Arc<String>>,
+    /// 服务器配置，可以通过 CONFIG SET 修改
+    config: SharedConfig,
+    /// Lua 脚本引擎，所有连接共享脚本缓存
+    scripting: Arc<Scripting>,
+    /// FUNCTION LOAD 加载的函数库
+    functions: Arc<Functions>,
+    /// 连接登记表
+    clients: Arc<Clients>,
+    /// 用户和权限
+    acl: Arc<Acl>,
+    /// 主从复制的状态
+    replication: Arc<Replication>,
+    /// 集群的状态，None 表示没有启用集群模式
+    cluster: Option<Arc<Cluster>>,
+    /// 跨数据中心的链路
+    peers: Arc<Peers>,
+    /// 把执行的命令分发给 MONITOR 连接
+    monitor: Arc<Monitor>,
+    /// 发布订阅的频道和订阅者
+    pubsub: Arc<PubSub>,
+    /// 所有连接 WATCH 的键
+    watches: Arc<Watches>,
+    /// 开启 CLIENT TRACKING 的连接读取过的键
+    tracking: Arc<Tracking>,
 }
 
 impl Server {
@@ -19,265 +81,916 @@ impl Server {
     /// 
     /// # Arguments
     /// * `storage` - 存储实例
-    /// * `password` - 可选的认证密码
-    pub fn new(storage: Storage, password: Option<String>) -> Self {
-        Server { 
-            storage: Arc::new(storage),
-            password: password.map(Arc::new),
-        }
+    /// * `config` - 服务器配置
+    /// * `acl` - 用户和权限
+    /// * `tls` - 可选的 TLS 接受器
+    ///
+    /// # Returns
+    /// * `Ok(Server)` - 服务器实例
+    /// * `Err` - 启用集群模式时集群配置文件无法读取或格式无效
+    pub fn new(storage: Storage, config: Config, acl: Acl, tls: Option<TlsAcceptor>) -> io::Result<Self> {
+        let storage = Arc::new(storage);
+        let watches = Arc::new(Watches::new());
+        storage.register_observer(watches.clone());
+        let tracking = Arc::new(Tracking::new());
+        storage.register_observer(tracking.clone());
+        let cluster_enabled = config.cluster_enabled;
+        let config = Arc::new(RwLock::new(config));
+        let replication = Replication::new(storage.clone(), config.clone());
+        let peers = Peers::new(storage.clone(), replication.clone(), config.read().unwrap().peers.clone());
+        let cluster = if cluster_enabled {
+            Some(Cluster::load(config.clone(), storage.clone(), replication.clone())?)
+        } else {
+            None
+        };
+        let shared = Shared {
+            functions: Arc::new(Functions::new(storage.clone())),
+            replication,
+            storage,
+            config,
+            scripting: Arc::new(Scripting::new()),
+            clients: Arc::new(Clients::new()),
+            acl: Arc::new(acl),
+            cluster,
+            peers,
+            monitor: Arc::new(Monitor::new()),
+            pubsub: Arc::new(PubSub::new()),
+            watches,
+            tracking,
+        };
+        Ok(Server { shared, tls })
+    }
+
+    /// 服务器配置，用于 SIGHUP 时重新加载配置文件
+    pub fn config(&self) -> SharedConfig {
+        self.shared.config.clone()
+    }
+
+    /// 用户和权限，用于 SIGHUP 时同步 requirepass
+    pub fn acl(&self) -> Arc<Acl> {
+        self.shared.acl.clone()
     }
 
-    /// 尝试绑定到指定地址
+    /// 运行服务器，监听连接，直到收到关闭信号
+    /// 收到信号后停止接受新连接，等待各连接处理完已收到的命令后返回
     /// 
     /// # Arguments
-    /// * `addr` - 要绑定的地址（如 "127.0.0.1:6379"）
+    /// * `addr` - 监听地址
+    /// * `shutdown` - 关闭信号，完成时开始关闭服务器
     /// 
     /// # Returns
-    /// * `Ok(())` - 绑定成功
-    /// * `Err` - 绑定失败的错误
-    pub async fn try_bind(&self, addr: &str) -> io::Result<()> {
-        TcpListener::bind(addr).await?;
-        Ok(())
+    /// * `Ok(SocketAddr)` - 服务器正常退出，返回实际监听的地址
+    /// * `Err` - 绑定失败或运行过程中的错误
+    pub async fn run(&self, addr: &str, shutdown: impl Future<Output = ()>) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+        let listeners = self.bind(addr).await?;
+        let local = listeners[0].local_addr()?;
+        self.serve(listeners, shutdown).await?;
+        Ok(local)
     }
 
-    /// 运行服务器，监听连接
-    /// 
+    /// 绑定 TCP 监听器，启用多个接受循环时每个循环一个监听器
+    /// 配置中的端口更新为实际监听的端口，地址中的端口为 0 时由系统分配
+    ///
     /// # Arguments
     /// * `addr` - 监听地址
-    /// 
+    ///
+    /// # Returns
+    /// * `Ok(Vec<TcpListener>)` - 绑定的监听器，交给 `serve`
+    /// * `Err` - 地址无法解析或绑定失败
+    pub async fn bind(&self, addr: &str) -> io::Result<Vec<TcpListener>> {
+        let acceptors = self.shared.config.read().unwrap().acceptors;
+        let listeners = bind_listeners(addr, acceptors).await?;
+        let local = listeners[0].local_addr()?;
+        self.shared.config.write().unwrap().port = local.port();
+        notice!(
+            "Server listening on {}{}{}",
+            local,
+            if self.tls.is_some() { " (TLS)" } else { "" },
+            if listeners.len() > 1 { format!(" with {} acceptors", listeners.len()) } else { String::new() },
+        );
+        Ok(listeners)
+    }
+
+    /// 在绑定的监听器上接受连接，直到收到关闭信号
+    /// 收到信号后停止接受新连接，等待各连接处理完已收到的命令后返回
+    ///
+    /// # Arguments
+    /// * `listeners` - `bind` 返回的监听器
+    /// * `shutdown` - 关闭信号，完成时开始关闭服务器
+    ///
     /// # Returns
     /// * `Ok(())` - 服务器正常退出
-    /// * `Err` - 运行过程中的错误
-    pub async fn run(&self, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
-        // 绑定 TCP 监听器
-        let listener = TcpListener::bind(addr).await?;
-        println!("Server listening on {}", addr);
+    /// * `Err` - 接受连接失败
+    pub async fn serve(&self, listeners: Vec<TcpListener>, shutdown: impl Future<Output = ()>) -> io::Result<()> {
+        let proxy_protocol = self.shared.config.read().unwrap().proxy_protocol;
+
+        // 编译数据文件中保存的函数库
+        self.shared.functions.restore().await;
+
+        // 配置了 replicaof 时开始复制主节点，副本向主节点报告的端口是上面实际监听的端口
+        let replicaof = self.shared.config.read().unwrap().replicaof.clone();
+        if replicaof.is_some() {
+            self.shared.replication.replicate(replicaof);
+        }
 
-        // 循环接受新的连接
+        // 连接配置的其他数据中心
+        self.shared.peers.start();
+
+        // 集群模式下连接已知的节点，本节点的端口同样是实际监听的端口
+        if let Some(cluster) = &self.shared.cluster {
+            cluster.start();
+        }
+
+        let token = CancellationToken::new();
+        let tracker = TaskTracker::new();
+        let accept_loop = AcceptLoop {
+            shared: self.shared.clone(),
+            tls: self.tls.clone(),
+            proxy_protocol,
+            stop: CancellationToken::new(),
+            shutdown: token.clone(),
+            tracker: tracker.clone(),
+        };
+        let mut loops: FuturesUnordered<_> = listeners.into_iter()
+            .enumerate()
+            .map(|(i, listener)| spawn_named(&format!("acceptor {}", i), accept_loop.clone().run(listener)))
+            .collect();
+
+        // 收到关闭信号，或某个接受循环出错时关闭服务器
+        let result = tokio::select! {
+            _ = shutdown => Ok(()),
+            Some(finished) = loops.next() => finished.map_err(io::Error::other).and_then(|result| result),
+        };
+
+        // 停止接受新连接，通知空闲的连接关闭，并等待正在执行的命令完成
+        accept_loop.stop.cancel();
+        while loops.next().await.is_some() {}
+        notice!("Shutting down, waiting for {} connection(s) to finish", tracker.len());
+        token.cancel();
+        tracker.close();
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, tracker.wait()).await.is_err() {
+            warning!("{} connection(s) did not finish in time", tracker.len());
+        }
+        result
+    }
+}
+
+/// 一个接受循环，接受新连接并为每个连接创建任务
+#[derive(Clone)]
+struct AcceptLoop {
+    /// 所有连接共享的状态
+    shared: Shared,
+    /// 启用 TLS 时的接受器
+    tls: Option<TlsAcceptor>,
+    /// 连接是否以 PROXY 协议头开头
+    proxy_protocol: bool,
+    /// 取消时停止接受新连接
+    stop: CancellationToken,
+    /// 服务器关闭时取消，传给每个连接
+    shutdown: CancellationToken,
+    /// 跟踪所有连接任务，关闭服务器时等待它们完成
+    tracker: TaskTracker,
+}
+
+impl AcceptLoop {
+    /// 循环接受新的连接，直到 `stop` 被取消
+    ///
+    /// # Returns
+    /// * `Ok(())` - 已停止接受新连接，监听器随之关闭
+    /// * `Err` - 接受连接失败
+    async fn run(self, listener: TcpListener) -> io::Result<()> {
         loop {
-            let (socket, _) = listener.accept().await?;
-            let storage = self.storage.clone();
-            let password = self.password.clone();
-            
-            // 为每个连接创建新的异步任务
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(socket, storage, password).await {
-                    eprintln!("Error handling connection: {}", e);
+            let (mut socket, peer) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = self.stop.cancelled() => return Ok(()),
+            };
+            let (keepalive, nodelay) = {
+                let config = self.shared.config.read().unwrap();
+                (config.tcp_keepalive, config.tcp_nodelay)
+            };
+            if let Err(e) = tune_socket(&socket, keepalive, nodelay) {
+                warning!("Error setting socket options for {}: {}", peer, e);
+            }
+            let local = match socket.local_addr() {
+                Ok(local) => local,
+                Err(e) => {
+                    warning!("Error getting local address for {}: {}", peer, e);
+                    continue;
                 }
-            });
+            };
+            let shared = self.shared.clone();
+            let tls = self.tls.clone();
+            let shutdown = self.shutdown.clone();
+            let proxy_protocol = self.proxy_protocol;
+
+            // 为每个连接创建新的异步任务，PROXY 协议头和 TLS 握手也在任务中处理，不阻塞接受新连接
+            spawn_named(&format!("connection {}", peer), self.tracker.track_future(async move {
+                // 负载均衡之后的连接先读取 PROXY 协议头，记录客户端的真实地址
+                let peer = if proxy_protocol {
+                    match proxy::read_header(&mut socket).await {
+                        Ok(addr) => addr.unwrap_or(peer),
+                        Err(e) => {
+                            warning!("Closing connection from {}: {}", peer, e);
+                            return;
+                        }
+                    }
+                } else {
+                    peer
+                };
+                let result = match tls {
+                    Some(acceptor) => match acceptor.accept(socket).await {
+                        Ok(stream) => handle_connection(stream, peer, local, shared, shutdown).await,
+                        Err(e) => Err(format!("TLS handshake with {} failed: {}", peer, e).into()),
+                    },
+                    None => handle_connection(socket, peer, local, shared, shutdown).await,
+                };
+                if let Err(e) = result {
+                    warning!("Error handling connection: {}", e);
+                }
+            }));
+        }
+    }
+}
+
+/// 绑定监听器
+/// 多个接受循环时每个循环一个监听器，都以 SO_REUSEPORT 绑定到同一个地址，由内核把新连接分配给各个监听器
+///
+/// # Arguments
+/// * `addr` - 监听地址
+/// * `count` - 监听器的数量，不支持 SO_REUSEPORT 的平台上只绑定一个
+async fn bind_listeners(addr: &str, count: usize) -> io::Result<Vec<TcpListener>> {
+    if count <= 1 || !cfg!(unix) {
+        if count > 1 {
+            warning!("SO_REUSEPORT is not supported on this platform, using a single acceptor");
         }
+        return Ok(vec![TcpListener::bind(addr).await?]);
+    }
+    let mut addr = tokio::net::lookup_host(addr).await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, format!("could not resolve {}", addr)))?;
+    let mut listeners = Vec::with_capacity(count);
+    for _ in 0..count {
+        let listener = bind_reuseport(addr)?;
+        // 端口为 0 时其余的监听器绑定到第一个监听器分配到的端口
+        addr = listener.local_addr()?;
+        listeners.push(listener);
+    }
+    Ok(listeners)
+}
+
+/// 以 SO_REUSEPORT 绑定一个监听器
+fn bind_reuseport(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(socket2::Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// 设置新连接的 TCP 选项
+///
+/// # Arguments
+/// * `socket` - 刚接受的连接
+/// * `keepalive` - 空闲多少秒后开始发送 keepalive 探测，之后每隔三分之一的时间探测一次，0 表示不启用
+/// * `nodelay` - 是否关闭 Nagle 算法
+fn tune_socket(socket: &TcpStream, keepalive: u64, nodelay: bool) -> io::Result<()> {
+    socket.set_nodelay(nodelay)?;
+    if keepalive > 0 {
+        let interval = Duration::from_secs((keepalive / 3).max(1));
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(keepalive)).with_interval(interval);
+        SockRef::from(socket).set_tcp_keepalive(&keepalive)?;
     }
+    Ok(())
 }
 
+/// 关闭服务器时等待连接处理完已收到的命令的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 以 SO_REUSEPORT 绑定的监听器等待接受的连接队列长度
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// 创建连接时预留的读取缓冲区大小
+const READ_CHUNK: usize = 16 * 1024;
+
+/// 管道中累积的响应超过这个大小时先写出，避免大批量请求占用过多内存
+const MAX_PENDING_OUTPUT: usize = 64 * 1024;
+
+/// 大的集合回复按这个大小分块编码，写出缓冲区满时等待连接可写后再编码下一块
+const REPLY_CHUNK: usize = 16 * 1024;
+
 /// 客户端连接的状态
 struct ConnectionState {
-    /// 是否已通过认证
-    authenticated: bool,
+    /// 认证的用户，None 表示尚未认证
+    user: Option<String>,
+    /// 上一个命令是 ASKING，集群模式下这个命令可以访问正在迁入本节点的槽
+    asking: bool,
+    /// MULTI 之后排队的命令，None 表示不在事务中
+    transaction: Option<Transaction>,
+    /// WATCH 的键，EXEC、DISCARD 和 UNWATCH 后清除
+    watch: Option<Watch>,
+    /// CLIENT TRACKING 开启时连接的追踪状态
+    tracker: Option<Tracker>,
+}
+
+/// 事务中有命令在排队前出错（无法解析、没有权限、槽不在本节点），EXEC 时放弃整个事务
+///
+/// # Arguments
+/// * `state` - 连接状态
+fn abort_transaction(state: &mut ConnectionState) {
+    if let Some(transaction) = &mut state.transaction {
+        transaction.failed = true;
+    }
+}
+
+/// 开启 CLIENT TRACKING 的连接在执行只读命令之前记录读取的键
+///
+/// # Arguments
+/// * `state` - 连接状态
+/// * `cmd` - 将要执行的命令
+fn track_reads(state: &ConnectionState, cmd: &Command) {
+    if let Some(tracker) = &state.tracker {
+        if cmd.spec().has_flag("readonly") {
+            tracker.track(cmd.keys());
+        }
+    }
+}
+
+/// 执行脚本或函数的连接的用户，脚本和函数中发起的命令按这个用户的权限检查
+///
+/// # Arguments
+/// * `acl` - 用户和权限
+/// * `state` - 连接状态
+fn caller(acl: &Arc<Acl>, state: &ConnectionState) -> Caller {
+    Caller { acl: acl.clone(), user: state.user.clone().unwrap_or_default() }
+}
+
+/// 下一条失效通知，没有开启 CLIENT TRACKING 时一直等待
+///
+/// # Returns
+/// * `Some(Response)` - 失效通知
+/// * `None` - 积压的通知超过上限，连接需要关闭
+async fn next_invalidation(tracker: &mut Option<Tracker>) -> Option<Response> {
+    match tracker {
+        Some(tracker) => tracker.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 处理 HELLO：可选地认证，并切换连接的 RESP 版本
+/// 
+/// # Arguments
+/// * `state` - 连接状态
+/// * `protocol` - 连接的协议，只有 RESP 连接可以切换版本
+/// * `acl` - 用户和权限
+/// * `protover` - 请求的协议版本
+/// * `auth` - 用户名和密码
+/// * `role` - 服务器的复制角色
+/// * `mode` - 服务器的运行模式，standalone 或 cluster
+/// 
+/// # Returns
+/// 服务器信息
+fn hello(
+    state: &mut ConnectionState,
+    protocol: &mut WireProtocol,
+    acl: &Acl,
+    protover: Option<u8>,
+    auth: Option<(String, String)>,
+    role: &str,
+    mode: &str,
+) -> Response {
+    let WireProtocol::Resp(version) = protocol else {
+        return Response::Error("HELLO is only supported on RESP connections".into());
+    };
+    if protover.is_some_and(|v| !(2..=3).contains(&v)) {
+        return Response::Error(RedoxError::NoProto);
+    }
+    if let Some((username, password)) = auth {
+        if let Err(e) = acl.authenticate(&username, &password) {
+            return Response::Error(e);
+        }
+        state.user = Some(username);
+    }
+    if state.user.is_none() {
+        return Response::Error(RedoxError::NoAuth("HELLO must be called with the client already authenticated, \
+            otherwise use HELLO <proto> AUTH <user> <pass>".to_string()));
+    }
+    if let Some(protover) = protover {
+        *version = if protover == 3 { RespVersion::Resp3 } else { RespVersion::Resp2 };
+    }
+
+    let text = |s: &str| Response::Value(RedoxValue::string(s.to_string()));
+    Response::Map(vec![
+        ("server".to_string(), text("redox")),
+        ("version".to_string(), text(env!("CARGO_PKG_VERSION"))),
+        ("proto".to_string(), Response::Integer(version.number() as i64)),
+        ("mode".to_string(), text(mode)),
+        ("role".to_string(), text(role)),
+        ("modules".to_string(), Response::Array(vec![])),
+    ])
+}
+
+/// COMMAND 回复中一个命令的元信息，与 Redis 相同为按位置排列的数组：
+/// `[名称, 参数个数, [标志 ...], 第一个键, 最后一个键, 步长, [ACL 类别 ...]]`
+fn describe_command(spec: &CommandSpec) -> Response {
+    let text = |s: &str| Some(Bytes::from(s.to_string()));
+    Response::Replies(vec![
+        Response::Value(RedoxValue::string(spec.name.to_string())),
+        Response::Integer(spec.arity as i64),
+        Response::Array(spec.flags.iter().map(|flag| text(flag)).collect()),
+        Response::Integer(spec.first_key as i64),
+        Response::Integer(spec.last_key as i64),
+        Response::Integer(spec.step as i64),
+        Response::Array(vec![text(&format!("@{}", spec.category.as_str()))]),
+    ])
 }
 
 /// 处理单个客户端连接
 /// 
 /// # Arguments
-/// * `socket` - TCP 连接
-/// * `storage` - 存储实例
-/// * `password` - 可选的认证密码
+/// * `socket` - 客户端连接，TCP 连接或完成握手的 TLS 连接
+/// * `peer` - 客户端地址
+/// * `local` - 连接的本地地址，集群模式下本节点还不知道自己的地址时报告给客户端
+/// * `shared` - 共享的服务器状态；超过最大连接数时回复错误并关闭连接，执行命令前检查认证的用户是否有权限
+/// * `shutdown` - 服务器关闭时取消，连接处理完已收到的命令后关闭；CLIENT KILL 也以同样的方式关闭连接
 /// 
 /// # Returns
 /// * `Ok(())` - 连接正常关闭
 /// * `Err` - 处理过程中的错误
-async fn handle_connection(
-    mut socket: TcpStream,
-    storage: Arc<Storage>,
-    password: Option<Arc<String>>,
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    mut socket: S,
+    peer: SocketAddr,
+    local: SocketAddr,
+    shared: Shared,
+    shutdown: CancellationToken,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let (reader, mut writer) = socket.split();
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
+    let Shared { storage, config, scripting, functions, clients, acl, replication, cluster, peers, monitor, pubsub, watches, tracking } = shared;
+
+    // 连接数已满时不读取请求，按 RESP 格式回复错误后关闭，行协议的客户端也能看到错误信息
+    let max_clients = config.read().unwrap().maxclients;
+    let kill = shutdown.child_token();
+    let Some(client) = clients.register(max_clients, peer, kill.clone(), acl.default_login()) else {
+        let error = Response::Error("max number of clients reached".into());
+        socket.write_all(&WireProtocol::Resp(RespVersion::Resp2).encode_response(&error)).await?;
+        socket.shutdown().await?;
+        return Ok(());
+    };
+
+    let limits = config.read().unwrap().request_limits();
+    let mut framed = Framed::with_capacity(socket, RedoxCodec::with_limits(limits), READ_CHUNK);
+    framed.set_backpressure_boundary(MAX_PENDING_OUTPUT);
 
     // 初始化连接状态
     let mut state = ConnectionState {  // 添加 mut
-        authenticated: password.is_none(),  // 如果没有设置密码，则默认已认证
+        user: acl.default_login(),  // default 用户不需要密码时，则默认已认证
+        asking: false,
+        transaction: None,
+        watch: None,
+        tracker: None,
     };
 
     // 主处理循环
     loop {
-        line.clear();
-        if reader.read_line(&mut line).await? == 0 {
-            break;  // 连接关闭
-        }
-
+        // 缓冲区中还有完整的请求时继续处理，把响应合并后写出；否则先写出已有的响应再等待
+        let frame = match framed.next().now_or_never() {
+            Some(frame) => frame,
+            None => {
+                SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+                tokio::select! {
+                    frame = framed.next() => frame,
+                    invalidation = next_invalidation(&mut state.tracker) => match invalidation {
+                        Some(push) => {
+                            let protocol = framed.codec().protocol().unwrap_or(WireProtocol::Line);
+                            send_response(&mut framed, protocol, &push).await?;
+                            continue;
+                        }
+                        None => {
+                            warning!("Closing client {}: more than {} invalidations pending", peer, INVALIDATION_BACKLOG);
+                            break;
+                        }
+                    },
+                    // 服务器关闭或连接被 CLIENT KILL 关闭时不再等待新的请求
+                    _ = kill.cancelled() => break,
+                }
+            }
+        };
         // 解析命令
-        let cmd = match Protocol::decode_command(&line) {
-            Ok(cmd) => cmd,
-            Err(e) => {
-                let response = Protocol::encode_response(&Response::Error(e));
-                writer.write_all(response.as_bytes()).await?;
+        let cmd = match frame {
+            None => break,
+            Some(Ok(Ok(cmd))) => cmd,
+            Some(Ok(Err(e))) => {
+                abort_transaction(&mut state);
+                let protocol = framed.codec().protocol().unwrap_or(WireProtocol::Line);
+                send_response(&mut framed, protocol, &Response::Error(e)).await?;
                 continue;
             }
+            Some(Err(CodecError::Protocol(e))) => {
+                let protocol = framed.codec().protocol().unwrap_or(WireProtocol::Line);
+                send_response(&mut framed, protocol, &Response::Error(e.into())).await?;
+                break;
+            }
+            // TLS 客户端未发送 close_notify 就断开连接，按正常关闭处理
+            Some(Err(CodecError::Io(e))) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Some(Err(e)) => return Err(e.into()),
         };
+        let mut protocol = framed.codec().protocol().unwrap_or(WireProtocol::Line);
+        let name = cmd.name();
+        client.info.touch(name);
+
+        // 已认证的连接按用户的权限检查命令，未认证的连接在下面只能执行 AUTH 等连接命令
+        if let Some(user) = &state.user {
+            if let Err(e) = acl.check(user, &cmd) {
+                abort_transaction(&mut state);
+                send_response(&mut framed, protocol, &Response::Error(e)).await?;
+                continue;
+            }
+        }
+
+        // 集群模式下访问其他节点负责的槽的命令返回重定向，客户端把命令发给负责的节点；ASKING 只对下一个命令有效
+        let asking = std::mem::take(&mut state.asking);
+        if let (Some(cluster), Some(_)) = (&cluster, &state.user) {
+            if let Err(e) = cluster.route(&cmd, asking).await {
+                abort_transaction(&mut state);
+                send_response(&mut framed, protocol, &Response::Error(e)).await?;
+                continue;
+            }
+        }
+
+        // 已认证的连接执行的命令交给 MONITOR 连接
+        if state.user.is_some() {
+            monitor.feed(peer, &cmd);
+        }
+
+        // MULTI 之后数据命令排队到 EXEC 时执行，除了事务命令和 RESET 之外的其他命令会使事务被放弃
+        if let (Some(transaction), Some(_)) = (&mut state.transaction, &state.user) {
+            if transaction::is_queueable(&cmd) {
+                transaction.queued.push(cmd);
+                send_response(&mut framed, protocol, &Response::Value(RedoxValue::string("QUEUED"))).await?;
+                continue;
+            }
+            if !matches!(cmd, Command::Multi | Command::Exec | Command::Discard | Command::Watch(_) | Command::Reset) {
+                transaction.failed = true;
+                let error = Response::Error(format!("{} is not allowed inside a transaction", name.to_uppercase()).into());
+                send_response(&mut framed, protocol, &error).await?;
+                continue;
+            }
+        }
 
         // 处理命令并生成响应
         let response = match cmd {
-            Command::Auth { password: input_password } => {
-                if let Some(server_password) = &password {
-                    if input_password == **server_password {
-                        state.authenticated = true;  // 更新认证状态
+            Command::Auth { username: None, .. } if !acl.default_requires_password() => {
+                Response::Error("Authentication not required".into())
+            }
+            Command::Auth { username, password } => {
+                let username = username.unwrap_or_else(|| DEFAULT_USER.to_string());
+                match acl.authenticate(&username, &password) {
+                    Ok(()) => {
+                        state.user = Some(username);  // 更新认证状态
                         Response::Ok
-                    } else {
-                        Response::Error("Invalid password".to_string())
                     }
-                } else {
-                    Response::Error("Authentication not required".to_string())
+                    Err(e) => Response::Error(e),
                 }
             }
-            _ if !state.authenticated => {
-                Response::Error("Authentication required".to_string())
+            // PING 在认证前也可以使用，便于健康检查
+            Command::Ping { message } => {
+                Response::Value(RedoxValue::String(message.unwrap_or_else(|| Bytes::from_static(b"PONG"))))
             }
-            // 字符串操作
-            Command::Set { key, value } => {
-                storage.set_string(key, value).await;
-                Response::Ok
+            Command::Reset => {
+                state = ConnectionState {
+                    user: acl.default_login(),
+                    asking: false,
+                    transaction: None,
+                    watch: None,
+                    tracker: None,
+                };
+                if let WireProtocol::Resp(_) = protocol {
+                    protocol = WireProtocol::Resp(RespVersion::Resp2);
+                }
+                Response::Value(RedoxValue::string("RESET"))
+            }
+            Command::Hello { protover, auth } => {
+                let mode = if cluster.is_some() { "cluster" } else { "standalone" };
+                hello(&mut state, &mut protocol, &acl, protover, auth, replication.role(), mode)
+            }
+            _ if state.user.is_none() => {
+                Response::Error(RedoxError::NoAuth("Authentication required.".to_string()))
             }
-            Command::Get { key } => {
-                match storage.get_string(&key).await {
-                    Some(value) => Response::Value(RedoxValue::String(value)),
-                    None => Response::Value(RedoxValue::String("NIL".to_string())),
+            Command::Echo { message } => Response::Value(RedoxValue::String(message)),
+            Command::Info => {
+                let mut info = storage.info().await;
+                clients.add_info(&mut info, config.read().unwrap().maxclients);
+                replication.add_info(&mut info);
+                peers.add_info(&mut info);
+                info.insert("cluster_enabled".to_string(), if cluster.is_some() { "1" } else { "0" }.to_string());
+                Response::Info(info)
+            }
+            // 复制命令
+            Command::ReplicaOf { .. } if cluster.is_some() => {
+                Response::Error("REPLICAOF not allowed in cluster mode.".into())
+            }
+            Command::ReplicaOf { primary } => {
+                let stop = primary.is_none();
+                if replication.replicate(primary) || stop {
+                    Response::Ok
+                } else {
+                    Response::Value(RedoxValue::string("OK Already connected to specified master"))
                 }
             }
-            // 列表操作
-            Command::LPush { key, value } => {
-                let len = storage.lpush(key, value).await;
-                Response::Value(RedoxValue::String(len.to_string()))
+            Command::Sync { listening_port } => {
+                // 回复之后这个连接只用于向副本发送快照和修改，不再读取请求
+                let session = replication.attach(peer, listening_port);
+                send_response(&mut framed, protocol, &session.reply()).await?;
+                SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+                session.serve(framed.into_inner(), kill).await?;
+                return Ok(());
             }
-            Command::RPush { key, value } => {
-                let len = storage.rpush(key, value).await;
-                Response::Value(RedoxValue::String(len.to_string()))
+            Command::PeerSync { .. } if config.read().unwrap().read_only() => Response::Error(RedoxError::ReadOnly),
+            Command::PeerSync { name } => {
+                // 回复之后这个连接只用于接收另一个数据中心推送的修改
+                send_response(&mut framed, protocol, &Response::Ok).await?;
+                SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+                peers::serve(&storage, &name, framed.into_inner(), kill).await?;
+                return Ok(());
             }
-            Command::LPop { key } => {
-                match storage.lpop(&key).await {
-                    Some(value) => Response::Value(RedoxValue::String(value)),
-                    None => Response::Value(RedoxValue::String("NIL".to_string())),
+            Command::Monitor => {
+                // 回复之后这个连接只用于输出其他连接执行的命令
+                send_response(&mut framed, protocol, &Response::Ok).await?;
+                SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+                monitor.serve(framed, protocol, kill).await?;
+                return Ok(());
+            }
+            // 发布订阅命令
+            Command::Publish { channel, message } => Response::Integer(pubsub.publish(&channel, &message) as i64),
+            cmd @ (Command::Subscribe(_) | Command::PSubscribe(_) | Command::Unsubscribe(_) | Command::PUnsubscribe(_)) => {
+                // 订阅期间这个连接只接收消息和订阅相关的命令，取消所有订阅后恢复正常
+                SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+                let user = state.user.clone().unwrap_or_default();
+                if pubsub.serve(&mut framed, protocol, cmd, |cmd| acl.check(&user, cmd), peer, &kill).await? {
+                    continue;
                 }
+                break;
+            }
+            // 事务命令
+            Command::Multi if state.transaction.is_some() => Response::Error("MULTI calls can not be nested".into()),
+            Command::Multi => {
+                state.transaction = Some(Transaction::default());
+                Response::Ok
             }
-            Command::RPop { key } => {
-                match storage.rpop(&key).await {
-                    Some(value) => Response::Value(RedoxValue::String(value)),
-                    None => Response::Value(RedoxValue::String("NIL".to_string())),
+            Command::Exec => {
+                let watch = state.watch.take();
+                match state.transaction.take() {
+                    None => Response::Error("EXEC without MULTI".into()),
+                    Some(transaction) if transaction.failed => Response::Error(RedoxError::ExecAbort),
+                    Some(transaction) => {
+                        // 独占执行闸门后其他连接的命令不能穿插执行，WATCH 的键不会在检查之后被修改
+                        let _exclusive = scripting.exclusive().await;
+                        if watch.is_some_and(|watch| watch.is_dirty()) {
+                            Response::Nil
+                        } else {
+                            let mut replies = Vec::with_capacity(transaction.queued.len());
+                            for cmd in transaction.queued {
+                                track_reads(&state, &cmd);
+                                replies.push(commands::execute(&storage, cmd).await);
+                            }
+                            Response::Replies(replies)
+                        }
+                    }
                 }
             }
-            Command::LRange { key, start, stop } => {
-                match storage.lrange(&key, start, stop).await {
-                    Some(list) => Response::Value(RedoxValue::List(list)),
-                    None => Response::Value(RedoxValue::List(vec![])),
+            Command::Discard => {
+                state.watch = None;
+                match state.transaction.take() {
+                    Some(_) => Response::Ok,
+                    None => Response::Error("DISCARD without MULTI".into()),
                 }
             }
-            // 集合操作
-            Command::SAdd { key, member } => {
-                let added = storage.sadd(key, member).await;
-                Response::Value(RedoxValue::String(if added { "1" } else { "0" }.to_string()))
+            Command::Watch(_) if state.transaction.is_some() => {
+                Response::Error("WATCH inside MULTI is not allowed".into())
+            }
+            Command::Watch(keys) => {
+                state.watch.get_or_insert_with(|| watches.watch()).add(keys);
+                Response::Ok
+            }
+            Command::Unwatch => {
+                state.watch = None;
+                Response::Ok
             }
-            Command::SRem { key, member } => {
-                let removed = storage.srem(&key, &member).await;
-                Response::Value(RedoxValue::String(if removed { "1" } else { "0" }.to_string()))
+            Command::Wait { .. } if replication.is_replica() => {
+                Response::Error("WAIT cannot be used with replica instances".into())
             }
-            Command::SMembers { key } => {
-                match storage.smembers(&key).await {
-                    Some(members) => Response::Value(RedoxValue::Set(members.into_iter().collect())),
-                    None => Response::Value(RedoxValue::Set(std::collections::HashSet::new())),
+            Command::Wait { numreplicas, timeout } => {
+                // 写出之前的响应后再等待，服务器关闭或连接被 CLIENT KILL 关闭时不再等待
+                SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+                tokio::select! {
+                    acked = replication.wait(numreplicas, timeout) => Response::Integer(acked as i64),
+                    _ = kill.cancelled() => break,
                 }
             }
-            Command::SIsMember { key, member } => {
-                let is_member = storage.sismember(&key, &member).await;
-                Response::Value(RedoxValue::String(if is_member { "1" } else { "0" }.to_string()))
+            // 集群命令
+            Command::ClusterMeet { .. }
+            | Command::ClusterAddSlots(_)
+            | Command::ClusterDelSlots(_)
+            | Command::ClusterForget { .. }
+            | Command::ClusterKeySlot { .. }
+            | Command::ClusterMyId
+            | Command::ClusterInfo
+            | Command::ClusterNodes
+            | Command::ClusterSlots
+            | Command::ClusterShards
+            | Command::ClusterReplicas { .. }
+            | Command::ClusterReplicate { .. }
+            | Command::ClusterSetSlot { .. }
+            | Command::ClusterGetKeysInSlot { .. }
+            | Command::ClusterCountKeysInSlot { .. }
+            | Command::ClusterBus
+            | Command::Asking
+                if cluster.is_none() =>
+            {
+                Response::Error("This instance has cluster support disabled".into())
             }
-            // 哈希表操作
-            Command::HSet { key, field, value } => {
-                let is_new = storage.hset(key, field, value).await;
-                Response::Value(RedoxValue::String(if is_new { "1" } else { "0" }.to_string()))
+            Command::ClusterBus => {
+                // 回复之后这个连接只用于交换集群的状态，不再读取请求
+                let cluster = cluster.unwrap();
+                send_response(&mut framed, protocol, &Response::Ok).await?;
+                SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+                cluster.serve(framed.into_inner(), peer, kill).await?;
+                return Ok(());
             }
-            Command::HGet { key, field } => {
-                match storage.hget(&key, &field).await {
-                    Some(value) => Response::Value(RedoxValue::String(value)),
-                    None => Response::Value(RedoxValue::String("NIL".to_string())),
-                }
+            cmd @ (Command::ClusterMeet { .. }
+            | Command::ClusterAddSlots(_)
+            | Command::ClusterDelSlots(_)
+            | Command::ClusterForget { .. }
+            | Command::ClusterKeySlot { .. }
+            | Command::ClusterMyId
+            | Command::ClusterInfo
+            | Command::ClusterNodes
+            | Command::ClusterSlots
+            | Command::ClusterShards
+            | Command::ClusterReplicas { .. }
+            | Command::ClusterReplicate { .. }
+            | Command::ClusterSetSlot { .. }
+            | Command::ClusterGetKeysInSlot { .. }
+            | Command::ClusterCountKeysInSlot { .. }) => cluster.as_ref().unwrap().execute(cmd, local.ip()).await,
+            Command::Asking => {
+                state.asking = true;
+                Response::Ok
             }
-            Command::HDel { key, field } => {
-                let deleted = storage.hdel(&key, &field).await;
-                Response::Value(RedoxValue::String(if deleted { "1" } else { "0" }.to_string()))
+            // 配置命令
+            Command::ConfigGet { pattern } => config::config_get(&config, &pattern),
+            Command::ConfigSet(params) => config::config_set(&config, &storage, &acl, params),
+            // 访问控制命令
+            cmd @ (Command::AclSetUser { .. }
+            | Command::AclGetUser { .. }
+            | Command::AclDelUser(_)
+            | Command::AclList
+            | Command::AclUsers
+            | Command::AclWhoAmI
+            | Command::AclCat { .. }
+            | Command::AclLoad
+            | Command::AclSave) => {
+                acl.execute(state.user.as_deref().unwrap_or_default(), cmd)
             }
-            Command::HGetAll { key } => {
-                match storage.hgetall(&key).await {
-                    Some(hash) => Response::Value(RedoxValue::Hash(hash)),
-                    None => Response::Value(RedoxValue::Hash(std::collections::HashMap::new())),
-                }
+            // 命令元信息
+            Command::CommandList => Response::Replies(meta::COMMANDS.iter().map(describe_command).collect()),
+            Command::CommandCount => Response::Integer(meta::COMMANDS.len() as i64),
+            Command::CommandInfo(names) if names.is_empty() => {
+                Response::Replies(meta::COMMANDS.iter().map(describe_command).collect())
             }
-            // 有序集合操作
-            Command::ZAdd { key, score, member } => {
-                let added = storage.zadd(key, score, member).await;
-                Response::Value(RedoxValue::String(if added { "1" } else { "0" }.to_string()))
-            }
-            Command::ZRem { key, member } => {
-                let removed = storage.zrem(&key, &member).await;
-                Response::Value(RedoxValue::String(if removed { "1" } else { "0" }.to_string()))
-            }
-            Command::ZRange { key, start, stop } => {
-                match storage.zrange(&key, start, stop).await {
-                    Some(members) => {
-                        let zset = members.into_iter()
-                            .map(|(member, score)| (member, score))
-                            .collect();
-                        Response::Value(RedoxValue::SortedSet(zset))
-                    }
-                    None => Response::Value(RedoxValue::SortedSet(std::collections::BTreeMap::new())),
+            // 按参数的顺序回复，未知的命令为 nil
+            Command::CommandInfo(names) => Response::Replies(
+                names.iter().map(|name| meta::lookup(name).map_or(Response::Nil, describe_command)).collect(),
+            ),
+            // 连接管理命令
+            Command::ClientId => Response::Integer(client.info.id as i64),
+            Command::ClientList => Response::Value(RedoxValue::string(clients.list())),
+            Command::ClientGetName => match client.info.name() {
+                Some(name) => Response::Value(RedoxValue::string(name)),
+                None => Response::Nil,
+            },
+            Command::ClientSetName { name } => {
+                client.info.set_name((!name.is_empty()).then_some(name));
+                Response::Ok
+            }
+            Command::ClientTracking { on: true } if matches!(protocol, WireProtocol::Line | WireProtocol::Resp(RespVersion::Resp2)) => {
+                Response::Error("CLIENT TRACKING requires RESP3 or a binary transport to receive invalidation pushes".into())
+            }
+            Command::ClientTracking { on } => {
+                if on {
+                    state.tracker.get_or_insert_with(|| tracking.tracker());
+                } else {
+                    state.tracker = None;
                 }
+                Response::Ok
             }
-            Command::ZRangeByScore { key, min, max } => {
-                match storage.zrangebyscore(&key, min, max).await {
-                    Some(members) => {
-                        let zset = members.into_iter()
-                            .map(|(member, score)| (member, score))
-                            .collect();
-                        Response::Value(RedoxValue::SortedSet(zset))
-                    }
-                    None => Response::Value(RedoxValue::SortedSet(std::collections::BTreeMap::new())),
+            Command::ClientKill { id, addr, legacy } => {
+                let killed = clients.kill(id, addr.as_deref());
+                match (legacy, killed) {
+                    (true, 0) => Response::Error("No such client".into()),
+                    (true, _) => Response::Ok,
+                    (false, killed) => Response::Integer(killed as i64),
                 }
             }
-            Command::MSet(pairs) => {
-                let count = storage.mset(pairs).await;
-                Response::Integer(count as i64)
+            // 调试命令，需要在配置中启用
+            Command::DebugSleep { .. }
+            | Command::DebugObject { .. }
+            | Command::DebugSetActiveExpire { .. }
+            | Command::DebugQuickSave
+            | Command::DebugSetTime { .. }
+                if !config.read().unwrap().enable_debug_command =>
+            {
+                Response::Error(debug::DISABLED.into())
             }
-            Command::MGet(keys) => {
-                let values = storage.mget(&keys).await;
-                Response::Array(values)
+            cmd @ (Command::DebugSleep { .. }
+            | Command::DebugObject { .. }
+            | Command::DebugSetActiveExpire { .. }
+            | Command::DebugQuickSave
+            | Command::DebugSetTime { .. }) => debug::execute(&storage, &scripting, cmd).await,
+            // 脚本命令
+            Command::Eval { script, keys, args } => {
+                scripting.eval(storage.clone(), caller(&acl, &state), script, keys, args).await
             }
-            Command::Info => {
-                let info = storage.info().await;
-                Response::Info(info)
+            Command::EvalSha { sha1, keys, args } => {
+                scripting.eval_sha(storage.clone(), caller(&acl, &state), &sha1, keys, args).await
             }
-            Command::Del(keys) => {
-                let count = storage.del(&keys).await;
-                Response::Integer(count as i64)
+            Command::ScriptLoad { script } => {
+                Response::Value(RedoxValue::string(scripting.load(script).await))
             }
-            Command::Expire { key, seconds } => {
-                let success = storage.expire(&key, seconds).await;
-                Response::Integer(if success { 1 } else { 0 })
+            Command::ScriptExists(shas) => Response::Array(
+                scripting.exists(&shas).await
+                    .into_iter()
+                    .map(|exists| Some(Bytes::from_static(if exists { b"1" } else { b"0" })))
+                    .collect(),
+            ),
+            Command::ScriptFlush => {
+                scripting.flush().await;
+                Response::Ok
             }
-            Command::TTL { key } => {
-                match storage.ttl(&key).await {
-                    Some(ttl) => Response::Integer(ttl),
-                    None => Response::Integer(-2_i64),
+            // 函数命令
+            Command::FunctionLoad { library, code, replace } => {
+                match functions.load(library.clone(), code, replace).await {
+                    Ok(()) => Response::Value(RedoxValue::string(library)),
+                    Err(e) => Response::Error(e.into()),
                 }
             }
-            Command::Persist { key } => {
-                let success = storage.persist(&key).await;
-                Response::Integer(if success { 1 } else { 0 })
+            Command::FunctionDelete { library } => {
+                if functions.delete(&library).await {
+                    Response::Ok
+                } else {
+                    Response::Error("Library not found".into())
+                }
+            }
+            Command::FunctionList => Response::Array(
+                functions.list().await
+                    .into_iter()
+                    .map(|(library, names)| Some(format!("{}:{}", library, names.join(",")).into()))
+                    .collect(),
+            ),
+            Command::FunctionFlush => {
+                functions.flush().await;
+                Response::Ok
+            }
+            Command::FCall { function, keys, args } => {
+                let _exclusive = scripting.exclusive().await;
+                functions.call(caller(&acl, &state), function, keys, args).await
+            }
+            // 数据命令，脚本执行期间需要等待
+            cmd => {
+                track_reads(&state, &cmd);
+                let _shared = scripting.shared().await;
+                commands::execute(&storage, cmd).await
             }
         };
 
-        // 发送响应
-        let response_str = Protocol::encode_response(&response);
-        writer.write_all(response_str.as_bytes()).await?;
+        // 认证状态可能改变的命令执行后，同步 CLIENT LIST 中显示的用户
+        if matches!(name, "auth" | "hello" | "reset") {
+            client.info.set_user(state.user.clone());
+        }
+
+        // 执行命令之前产生的失效通知先于回复发出，客户端收到回复时缓存中不会留有更早被修改的值
+        framed.codec_mut().set_protocol(protocol);
+        if let Some(tracker) = &mut state.tracker {
+            while let Some(push) = tracker.try_next() {
+                send_response(&mut framed, protocol, &push).await?;
+            }
+        }
+        send_response(&mut framed, protocol, &response).await?;
     }
 
+    SinkExt::<Vec<u8>>::flush(&mut framed).await?;
+    Ok(())
+}
+
+/// 把响应加入写出缓冲区
+/// 大的集合回复分块编码，缓冲区超过 `MAX_PENDING_OUTPUT` 时先等待写出，再编码下一块
+async fn send_response<S: AsyncRead + AsyncWrite + Unpin>(
+    framed: &mut Framed<S, RedoxCodec>,
+    protocol: WireProtocol,
+    response: &Response,
+) -> Result<(), CodecError> {
+    for chunk in protocol.encode_chunks(response, REPLY_CHUNK) {
+        framed.feed(chunk).await?;
+    }
     Ok(())
 } 
\ No newline at end of file
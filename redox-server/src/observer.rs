@@ -0,0 +1,47 @@
+//! 存储事件
+//! 键被写入、删除、过期或淘汰时，存储依次通知注册的观察者。持久化的脏标记、键空间通知、复制和审计日志
+//! 等功能都通过同一个入口获得修改事件，不需要各自修改每个写命令的实现。
+//! 观察者在持有分片的写锁时被调用，同一个键的事件按发生的顺序到达；
+//! 实现中不能阻塞，也不能再访问存储，需要较长时间的处理应当把事件发送到自己的任务中完成。
+
+use std::sync::{Arc, RwLock};
+
+/// 存储事件的观察者，所有方法默认什么也不做，只需要实现关心的事件
+pub trait StorageObserver: Send + Sync {
+    /// 键被写入或修改，删除最后一个元素使键被删除时改为调用 `on_delete`
+    ///
+    /// # Arguments
+    /// * `key` - 键
+    /// * `event` - 修改的方式，为小写的命令名，如 "set"、"hset"、"expire"
+    fn on_set(&self, _key: &[u8], _event: &str) {}
+
+    /// 键被 DEL、UNLINK 等命令删除，或者删除了集合的最后一个元素
+    fn on_delete(&self, _key: &[u8]) {}
+
+    /// 键因过期被删除，包括哈希表的最后一个字段过期
+    fn on_expire(&self, _key: &[u8]) {}
+
+    /// 键因内存超过上限被淘汰
+    fn on_evict(&self, _key: &[u8]) {}
+
+    /// 所有键被 FLUSHALL 删除，不再为每个键调用 `on_delete`
+    fn on_flush(&self) {}
+}
+
+/// 注册在存储上的观察者，存储和各分片共享同一个列表
+#[derive(Clone, Default)]
+pub struct Observers(Arc<RwLock<Vec<Arc<dyn StorageObserver>>>>);
+
+impl Observers {
+    /// 注册观察者，之后发生的事件都会通知它
+    pub fn register(&self, observer: Arc<dyn StorageObserver>) {
+        self.0.write().unwrap().push(observer);
+    }
+
+    /// 按注册的顺序通知所有观察者
+    pub fn notify(&self, event: impl Fn(&dyn StorageObserver)) {
+        for observer in self.0.read().unwrap().iter() {
+            event(observer.as_ref());
+        }
+    }
+}
@@ -0,0 +1,486 @@
+//! 跨数据中心的复制
+//! 配置文件中的每个 `[peers.<名称>]` 是一条到另一个数据中心的实例的单向链路，只推送键名匹配 keys 中任意模式的键。
+//! 发送方连接对方后发送 PEERSYNC，第一次连接时推送所有匹配的键，之后推送被修改的键的当前状态；
+//! 与主从复制一样，同一个键在发送前被修改多次时只发送最新的状态。
+//! 每一帧是 4 字节大端序的长度、1 字节的压缩标记和一批 bincode 序列化的 `PeerMessage`，压缩时用 zstd 压缩整批消息。
+//! 链路断开期间被修改的键记录在待发送的集合中，重连后只发送这些键，集合超过上限时改为重新推送所有匹配的键。
+//! 接收方不是副本，把收到的键直接写入存储；从链路写入的修改不会再推送出去，两个数据中心可以互相配置而不会循环。
+//! 过期和淘汰不推送，键的过期时间随键一起发送，两边各自过期。
+
+use crate::glob::glob_match;
+use crate::logging::{notice, warning};
+use crate::observer::StorageObserver;
+use crate::replication::{invalid_data, Replication, CONNECT_TIMEOUT, REPL_TIMEOUT, RECONNECT_DELAY_MAX, RECONNECT_DELAY_MIN};
+use crate::storage::{Entry, Storage};
+use crate::task::spawn_named;
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::compact::{self, BinaryFormat, LEN_PREFIX, MAX_FRAME_LEN};
+use redox_protocol::{Command, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio_util::codec::{Framed, FramedParts};
+use tokio_util::sync::CancellationToken;
+
+/// 链路断开期间最多记录的待发送的键，超过时改为重新推送所有匹配的键
+const MAX_PENDING: usize = 1024 * 1024;
+
+/// 一帧最多包含的消息数
+const BATCH_SIZE: usize = 512;
+
+/// 没有修改时发送空帧的间隔，接收方超过 `REPL_TIMEOUT` 没有收到数据时断开
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 帧的压缩标记：不压缩
+const RAW: u8 = 0;
+
+/// 帧的压缩标记：zstd 压缩
+const ZSTD: u8 = 1;
+
+tokio::task_local! {
+    /// 在应用链路收到的修改时设置，观察者据此不再推送这些修改
+    static FROM_PEER: ();
+}
+
+/// 一条链路的配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerConfig {
+    /// 链路的名称，即配置文件中 `[peers.<名称>]` 的名称，接收方在日志中显示
+    pub name: String,
+    /// 对方的主机名或地址
+    pub host: String,
+    /// 对方的端口
+    pub port: u16,
+    /// 键名的通配符模式，匹配任意一个的键被推送
+    pub keys: Vec<String>,
+    /// 帧的 zstd 压缩级别，0 表示不压缩
+    pub compression_level: u32,
+    /// 认证的用户，None 表示 default 用户
+    pub user: Option<String>,
+    /// 认证的密码，None 表示对方不需要认证
+    pub password: Option<String>,
+}
+
+impl PeerConfig {
+    /// 键是否需要推送
+    fn matches(&self, key: &[u8]) -> bool {
+        matches_any(&self.keys, key)
+    }
+}
+
+/// 链路上的消息
+#[derive(Serialize, Deserialize)]
+enum PeerMessage {
+    /// 键被写入或修改，为键的当前状态
+    Set { key: Bytes, entry: Entry },
+    /// 键被删除
+    Delete { key: Bytes },
+    /// 发送方执行了 FLUSHALL，接收方删除匹配这些模式的键
+    Flush { keys: Vec<String> },
+}
+
+/// 存储观察者记录的修改
+enum Change {
+    /// 键被修改或删除
+    Key(Bytes),
+    /// 所有键被删除
+    Flush,
+}
+
+/// 注册在存储上的观察者，把匹配的键交给各链路的任务
+struct PeerFeed {
+    /// 每条链路的配置和发送修改的通道
+    links: Vec<(PeerConfig, mpsc::UnboundedSender<Change>)>,
+}
+
+impl PeerFeed {
+    /// 记录修改，从链路写入的修改不记录
+    fn record(&self, key: Option<&[u8]>) {
+        if FROM_PEER.try_with(|_| ()).is_ok() {
+            return;
+        }
+        for (config, sender) in &self.links {
+            let change = match key {
+                Some(key) if config.matches(key) => Change::Key(Bytes::copy_from_slice(key)),
+                Some(_) => continue,
+                None => Change::Flush,
+            };
+            let _ = sender.send(change);
+        }
+    }
+}
+
+impl StorageObserver for PeerFeed {
+    fn on_set(&self, key: &[u8], _event: &str) {
+        self.record(Some(key));
+    }
+
+    fn on_delete(&self, key: &[u8]) {
+        self.record(Some(key));
+    }
+
+    fn on_flush(&self) {
+        self.record(None);
+    }
+}
+
+/// 一条链路的状态，用于 INFO
+struct PeerLink {
+    /// 链路的配置
+    config: PeerConfig,
+    /// 是否已连接并在推送修改
+    up: AtomicBool,
+    /// 待发送的键数
+    pending: AtomicUsize,
+    /// 已发送的字节数（压缩后）
+    sent_bytes: AtomicU64,
+}
+
+/// 链路断开期间积累的需要发送的修改
+#[derive(Default)]
+struct Pending {
+    /// 需要推送所有匹配的键，第一次连接和待发送的键过多时为 true
+    full: bool,
+    /// 需要让对方删除匹配的键
+    flush: bool,
+    /// 被修改过的键
+    keys: HashSet<Bytes>,
+}
+
+impl Pending {
+    /// 记录一个修改
+    fn record(&mut self, change: Change) {
+        match change {
+            Change::Key(key) => {
+                self.keys.insert(key);
+                if self.keys.len() > MAX_PENDING {
+                    self.keys.clear();
+                    self.full = true;
+                }
+            }
+            Change::Flush => {
+                self.keys.clear();
+                self.flush = true;
+            }
+        }
+    }
+}
+
+/// 所有链路
+pub struct Peers {
+    /// 每条链路的状态
+    links: Vec<Arc<PeerLink>>,
+    /// 每条链路接收修改的通道，`start` 时交给链路的任务
+    receivers: Mutex<Vec<mpsc::UnboundedReceiver<Change>>>,
+    /// 存储实例
+    storage: Arc<Storage>,
+    /// 复制的状态，本身是副本时不推送
+    replication: Arc<Replication>,
+}
+
+impl Peers {
+    /// 创建链路，有链路时在存储上注册观察者
+    ///
+    /// # Arguments
+    /// * `storage` - 存储实例
+    /// * `replication` - 复制的状态
+    /// * `configs` - 配置文件中的链路
+    pub fn new(storage: Arc<Storage>, replication: Arc<Replication>, configs: Vec<PeerConfig>) -> Arc<Self> {
+        let mut links = Vec::new();
+        let mut senders = Vec::new();
+        let mut receivers = Vec::new();
+        for config in configs {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            senders.push((config.clone(), sender));
+            receivers.push(receiver);
+            links.push(Arc::new(PeerLink {
+                config,
+                up: AtomicBool::new(false),
+                pending: AtomicUsize::new(0),
+                sent_bytes: AtomicU64::new(0),
+            }));
+        }
+        if !links.is_empty() {
+            storage.register_observer(Arc::new(PeerFeed { links: senders }));
+        }
+        Arc::new(Self { links, receivers: Mutex::new(receivers), storage, replication })
+    }
+
+    /// 启动每条链路的任务
+    pub fn start(&self) {
+        let receivers = mem::take(&mut *self.receivers.lock().unwrap());
+        for (link, changes) in self.links.iter().zip(receivers) {
+            let task = link.clone().run(self.storage.clone(), self.replication.clone(), changes);
+            spawn_named("peer-link", task);
+        }
+    }
+
+    /// 把链路的状态加入 INFO，每条链路一个 peer<n> 字段
+    pub fn add_info(&self, info: &mut HashMap<String, String>) {
+        for (i, link) in self.links.iter().enumerate() {
+            let config = &link.config;
+            info.insert(
+                format!("peer{}", i),
+                format!(
+                    "name={},addr={}:{},state={},pending={},sent_bytes={}",
+                    config.name,
+                    config.host,
+                    config.port,
+                    if link.up.load(Ordering::Relaxed) { "up" } else { "down" },
+                    link.pending.load(Ordering::Relaxed),
+                    link.sent_bytes.load(Ordering::Relaxed),
+                ),
+            );
+        }
+    }
+}
+
+impl PeerLink {
+    /// 连接对方并推送修改，断开后等待一段时间重连；本身是副本时不推送，成为主节点后重新推送所有匹配的键
+    async fn run(self: Arc<Self>, storage: Arc<Storage>, replication: Arc<Replication>, mut changes: mpsc::UnboundedReceiver<Change>) {
+        let config = &self.config;
+        let mut pending = Pending { full: true, ..Pending::default() };
+        let mut delay = RECONNECT_DELAY_MIN;
+        loop {
+            if replication.is_replica() {
+                while changes.try_recv().is_ok() {}
+                pending = Pending { full: true, ..Pending::default() };
+                time::sleep(RECONNECT_DELAY_MIN).await;
+                continue;
+            }
+            match self.connect().await {
+                Ok(socket) => {
+                    notice!("Peer link {} to {}:{} connected", config.name, config.host, config.port);
+                    self.up.store(true, Ordering::Relaxed);
+                    delay = RECONNECT_DELAY_MIN;
+                    let e = self.stream(socket, &storage, &replication, &mut pending, &mut changes).await;
+                    self.up.store(false, Ordering::Relaxed);
+                    warning!("Peer link {} to {}:{} lost: {}, reconnecting", config.name, config.host, config.port, e);
+                }
+                Err(e) => {
+                    warning!(
+                        "Peer link {} to {}:{} failed: {}, retrying in {} second(s)",
+                        config.name, config.host, config.port, e, delay.as_secs()
+                    );
+                }
+            }
+            // 等待重连期间继续记录修改
+            let sleep = time::sleep(delay);
+            tokio::pin!(sleep);
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    Some(change) = changes.recv() => {
+                        pending.record(change);
+                        self.pending.store(pending.keys.len(), Ordering::Relaxed);
+                    }
+                }
+            }
+            delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+        }
+    }
+
+    /// 连接对方，认证后发送 PEERSYNC
+    async fn connect(&self) -> io::Result<TcpStream> {
+        let config = &self.config;
+        let stream = time::timeout(CONNECT_TIMEOUT, TcpStream::connect((config.host.as_str(), config.port)))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))??;
+        stream.set_nodelay(true)?;
+        let mut framed = Framed::new(stream, ClientCodec::new());
+        if let Some(password) = &config.password {
+            let auth = Command::Auth { username: config.user.clone(), password: password.clone() };
+            request(&mut framed, &auth).await?;
+        }
+        request(&mut framed, &Command::PeerSync { name: config.name.clone() }).await?;
+        let FramedParts { io, read_buf, .. } = framed.into_parts();
+        if !read_buf.is_empty() {
+            return Err(invalid_data("unexpected data after PEERSYNC"));
+        }
+        Ok(io)
+    }
+
+    /// 先发送断开期间积累的修改，再持续发送新的修改
+    ///
+    /// # Returns
+    /// 连接断开、出错或本身成为副本的原因，没有发出的修改放回 `pending`
+    async fn stream(
+        &self,
+        mut socket: TcpStream,
+        storage: &Storage,
+        replication: &Replication,
+        pending: &mut Pending,
+        changes: &mut mpsc::UnboundedReceiver<Change>,
+    ) -> io::Error {
+        let config = &self.config;
+        // 发出之前先留在 pending 中，发送失败时下次重连再发送
+        let mut batch = Vec::new();
+        if pending.flush {
+            batch.push(PeerMessage::Flush { keys: config.keys.clone() });
+        }
+        let mut keys: Vec<Bytes> = pending.keys.iter().cloned().collect();
+        if pending.full {
+            keys = storage.keys_where(usize::MAX, |key| config.matches(key)).await;
+        }
+        for chunk in keys.chunks(BATCH_SIZE) {
+            for key in chunk {
+                batch.push(current_state(storage, key.clone()).await);
+            }
+            if let Err(e) = self.write_batch(&mut socket, &mut batch).await {
+                return e;
+            }
+        }
+        if let Err(e) = self.write_batch(&mut socket, &mut batch).await {
+            return e;
+        }
+        let synced = keys.len();
+        *pending = Pending::default();
+        self.pending.store(0, Ordering::Relaxed);
+        if synced > 0 {
+            notice!("Peer link {}: sent {} key(s) changed while disconnected", config.name, synced);
+        }
+
+        let mut heartbeat = time::interval(HEARTBEAT_INTERVAL);
+        heartbeat.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        loop {
+            let mut sent = Vec::new();
+            tokio::select! {
+                change = changes.recv() => {
+                    let Some(change) = change else {
+                        return io::Error::other("change feed closed");
+                    };
+                    // 一次取出已经积累的修改，合并为一帧
+                    let mut next = Some(change);
+                    while let Some(change) = next {
+                        match change {
+                            Change::Key(key) => {
+                                batch.push(current_state(storage, key.clone()).await);
+                                sent.push(Change::Key(key));
+                            }
+                            Change::Flush => {
+                                batch.push(PeerMessage::Flush { keys: config.keys.clone() });
+                                sent.push(Change::Flush);
+                            }
+                        }
+                        next = if batch.len() < BATCH_SIZE { changes.try_recv().ok() } else { None };
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if replication.is_replica() {
+                        return io::Error::other("this instance became a replica");
+                    }
+                }
+            }
+            if let Err(e) = self.write_batch(&mut socket, &mut batch).await {
+                for change in sent {
+                    pending.record(change);
+                }
+                self.pending.store(pending.keys.len(), Ordering::Relaxed);
+                return e;
+            }
+        }
+    }
+
+    /// 把一批消息编码为一帧写入连接，没有消息时发送空帧作为心跳
+    async fn write_batch(&self, socket: &mut TcpStream, batch: &mut Vec<PeerMessage>) -> io::Result<()> {
+        let body = bincode::serialize(&*batch).map_err(io::Error::other)?;
+        batch.clear();
+        let level = self.config.compression_level;
+        let (flag, body) = if level > 0 {
+            (ZSTD, zstd::encode_all(&body[..], level as i32)?)
+        } else {
+            (RAW, body)
+        };
+        let mut frame = Vec::with_capacity(LEN_PREFIX + 1 + body.len());
+        frame.extend_from_slice(&((body.len() + 1) as u32).to_be_bytes());
+        frame.push(flag);
+        frame.extend_from_slice(&body);
+        socket.write_all(&frame).await?;
+        self.sent_bytes.fetch_add(frame.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// 接收另一个数据中心推送的修改，直到连接断开或 `kill` 被取消
+///
+/// # Arguments
+/// * `storage` - 存储实例
+/// * `name` - 对方的链路名称
+/// * `socket` - 连接，已回复 PEERSYNC
+/// * `kill` - 服务器关闭或 CLIENT KILL 时取消
+pub async fn serve<S: AsyncRead + Unpin>(storage: &Storage, name: &str, mut socket: S, kill: CancellationToken) -> io::Result<()> {
+    notice!("Peer link {} connected", name);
+    let mut buf = BytesMut::new();
+    loop {
+        while let Some(frame) = compact::take_frame(&mut buf, MAX_FRAME_LEN).map_err(invalid_data)? {
+            let body = match frame.split_first() {
+                Some((&RAW, body)) => body.to_vec(),
+                Some((&ZSTD, body)) => zstd::decode_all(body)?,
+                _ => return Err(invalid_data("invalid peer frame")),
+            };
+            let batch: Vec<PeerMessage> = BinaryFormat::Bincode.decode(&body).map_err(invalid_data)?;
+            FROM_PEER.scope((), apply(storage, batch)).await;
+        }
+        let read = tokio::select! {
+            read = time::timeout(REPL_TIMEOUT, socket.read_buf(&mut buf)) => read,
+            _ = kill.cancelled() => return Ok(()),
+        };
+        let read = read.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timeout, no data from the peer"))??;
+        if read == 0 {
+            notice!("Peer link {} disconnected", name);
+            return Ok(());
+        }
+    }
+}
+
+/// 应用一批修改
+async fn apply(storage: &Storage, batch: Vec<PeerMessage>) {
+    for message in batch {
+        match message {
+            PeerMessage::Set { key, entry } => storage.set_entry(key, entry).await,
+            PeerMessage::Delete { key } => {
+                storage.del(&[key]).await;
+            }
+            PeerMessage::Flush { keys } => {
+                let matched = storage.keys_where(usize::MAX, |key| matches_any(&keys, key)).await;
+                storage.del(&matched).await;
+            }
+        }
+    }
+}
+
+/// 键的当前状态，键不存在时为删除
+async fn current_state(storage: &Storage, key: Bytes) -> PeerMessage {
+    match storage.entry(&key).await {
+        Some(entry) => PeerMessage::Set { key, entry },
+        None => PeerMessage::Delete { key },
+    }
+}
+
+/// 键是否匹配任意一个模式
+fn matches_any(patterns: &[String], key: &[u8]) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern.as_bytes(), key))
+}
+
+/// 握手时发送一个命令，回复不是 OK 时返回错误
+async fn request(framed: &mut Framed<TcpStream, ClientCodec>, cmd: &Command) -> io::Result<()> {
+    framed.send(cmd).await.map_err(|e| io::Error::other(e.to_string()))?;
+    match time::timeout(CONNECT_TIMEOUT, framed.next()).await {
+        Ok(Some(Ok(Response::Ok))) => Ok(()),
+        Ok(Some(Ok(Response::Error(e)))) => Err(io::Error::other(format!("{} failed: {}", cmd.name().to_uppercase(), e))),
+        Ok(Some(Ok(_))) => Err(invalid_data(format!("unexpected reply to {}", cmd.name().to_uppercase()))),
+        Ok(Some(Err(e))) => Err(io::Error::other(e.to_string())),
+        Ok(None) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the peer")),
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "no reply from the peer")),
+    }
+}
@@ -1,187 +1,718 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::io::Write;
 use std::path::Path;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use tokio::time;
-use tokio::sync::Mutex;
+use bytes::Bytes;
+use redox_protocol::binary::TextMap;
 use redox_protocol::RedoxValue;
+use crate::clock::{now_ms, SharedClock, SystemClock};
+use crate::logging::{notice, warning};
+use crate::observer::StorageObserver;
+use crate::s3::{S3Backend, S3Config};
+use crate::sled_backend::SledBackend;
+use crate::storage::Entry;
 use serde::{Serialize, Deserialize};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use tokio::fs::File as TokioFile;
-use tokio::io::{self as tokio_io, AsyncReadExt, AsyncWriteExt, BufReader as TokioBufReader, BufWriter as TokioBufWriter};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use tokio::io as tokio_io;
 
-/// 持久化数据的序列化结构
-/// 使用 serde 进行 JSON 序列化和反序列化
+/// 二进制快照文件开头的魔数，加载时据此区分二进制快照和旧版本的 JSON 文件
+const SNAPSHOT_MAGIC: &[u8; 8] = b"REDOXSNP";
+
+/// 当前的二进制快照格式版本，快照的结构不兼容地改变时增加
+const SNAPSHOT_VERSION: u16 = 1;
+
+/// zstd 帧开头的魔数，加载时据此识别压缩的数据文件
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// 最大的 zstd 压缩级别
+pub const MAX_COMPRESSION_LEVEL: u32 = 22;
+
+/// 二进制快照的内容
+/// 文件格式为：8 字节魔数 + 2 字节格式版本（小端）+ bincode 序列化的快照 + 4 字节 CRC32 校验和（小端），
+/// 校验和覆盖之前的所有字节。保存时值为与存储共享的 `Arc<RedoxValue>`，加载时为 `RedoxValue`，两者的格式相同
 #[derive(Serialize, Deserialize)]
+pub struct Snapshot<V = RedoxValue> {
+    /// 所有键值对
+    pub data: TextMap<V>,
+    /// 键的过期时间（毫秒级 Unix 时间戳）
+    pub expiry_ms: TextMap<u64>,
+    /// 哈希表字段的过期时间，键到 (字段, 毫秒级 Unix 时间戳) 的映射
+    pub field_expiry_ms: TextMap<TextMap<u64>>,
+    /// FUNCTION LOAD 加载的函数库源码，库名到源码的映射
+    pub functions: BTreeMap<String, String>,
+}
+
+impl Snapshot<Arc<RedoxValue>> {
+    /// 由存储的快照生成要保存的快照
+    /// 
+    /// # Arguments
+    /// * `data` - 所有键值对，值与存储共享
+    /// * `expiry` - 键的过期时间（毫秒）
+    /// * `field_expiry` - 哈希表字段的过期时间（毫秒），按键分组
+    /// * `functions` - 函数库源码
+    pub fn new(
+        data: Vec<(Bytes, Arc<RedoxValue>)>,
+        expiry: Vec<(Bytes, u64)>,
+        field_expiry: Vec<(Bytes, Vec<(Bytes, u64)>)>,
+        functions: BTreeMap<String, String>,
+    ) -> Self {
+        Snapshot {
+            data: TextMap(data),
+            expiry_ms: TextMap(expiry),
+            field_expiry_ms: TextMap(field_expiry.into_iter().map(|(key, fields)| (key, TextMap(fields))).collect()),
+            functions,
+        }
+    }
+}
+
+/// 旧版本的 JSON 数据格式，只在加载时读取，保存时改为二进制快照
+#[derive(Deserialize)]
 struct PersistentData {
     /// 存储所有键值对的哈希表
-    data: HashMap<String, RedoxValue>,
-    expiry: HashMap<String, u64>,
+    data: TextMap<RedoxValue>,
+    /// 更早的版本以秒为单位的过期时间
+    #[serde(default)]
+    expiry: TextMap<u64>,
+    /// 键的过期时间（毫秒级 Unix 时间戳）
+    #[serde(default)]
+    expiry_ms: TextMap<u64>,
+    /// 哈希表字段的过期时间，键到 (字段, 毫秒级 Unix 时间戳) 的映射
+    #[serde(default)]
+    field_expiry_ms: TextMap<TextMap<u64>>,
+    /// FUNCTION LOAD 加载的函数库源码，库名到源码的映射
+    #[serde(default)]
+    functions: BTreeMap<String, String>,
 }
 
-/// 旧版本的数据格式
-#[derive(Serialize, Deserialize)]
+/// 从数据文件加载的内容
+#[derive(Default)]
+pub struct LoadedData {
+    /// 键值数据
+    pub data: HashMap<Bytes, RedoxValue>,
+    /// 键的过期时间（毫秒）
+    pub expiry: HashMap<Bytes, u64>,
+    /// 哈希表字段的过期时间（毫秒），每项为 (键, 字段, 过期时间)
+    pub field_expiry: Vec<(Bytes, Bytes, u64)>,
+    /// 函数库源码
+    pub functions: BTreeMap<String, String>,
+}
+
+/// 最早版本的 JSON 数据格式
+#[derive(Deserialize)]
 struct LegacyData {
-    data: HashMap<String, RedoxValue>,
+    data: TextMap<RedoxValue>,
+}
+
+/// 默认的自动保存条件，与 Redis 的默认值相同
+pub const DEFAULT_SAVE_RULES: &str = "3600 1 300 100 60 10000";
+
+/// 自动保存任务检查保存条件的间隔
+const SAVE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 自动保存失败后等待多久再重试，避免磁盘已满时不停地重写文件
+const SAVE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// 自动保存的条件：距上次保存至少 `seconds` 秒并且至少有 `changes` 次修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    /// 距上次保存的秒数
+    pub seconds: u64,
+    /// 上次保存之后的修改次数
+    pub changes: u64,
+}
+
+impl SaveRule {
+    /// 解析 Redis 格式的保存条件，由若干对 `<秒数> <修改次数>` 组成，如 "3600 1 300 100 60 10000"
+    ///
+    /// # Returns
+    /// * `Some(Vec<SaveRule>)` - 保存条件，空字符串表示不自动保存
+    /// * `None` - 数字的个数不是偶数，或者有数字无效或为 0
+    pub fn parse_list(value: &str) -> Option<Vec<SaveRule>> {
+        let numbers = value.split_whitespace()
+            .map(|n| n.parse::<u64>().ok().filter(|&n| n > 0))
+            .collect::<Option<Vec<u64>>>()?;
+        if !numbers.len().is_multiple_of(2) {
+            return None;
+        }
+        Some(numbers.chunks(2).map(|pair| SaveRule { seconds: pair[0], changes: pair[1] }).collect())
+    }
+
+    /// 格式化为 Redis 格式，用于 CONFIG GET
+    pub fn format_list(rules: &[SaveRule]) -> String {
+        rules.iter()
+            .map(|rule| format!("{} {}", rule.seconds, rule.changes))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// 持久化后端的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// 单个数据文件，保存时写入临时文件再替换
+    File,
+    /// 本地数据文件，每次保存后上传到 S3 兼容的对象存储，本地没有数据文件时从对象存储下载
+    S3,
+    /// sled 数据库，每个键保存为一项，保存时只写入修改过的键，内存超过上限时把值移到数据库中
+    Sled,
+}
+
+impl BackendKind {
+    /// 所有后端
+    const ALL: [BackendKind; 3] = [BackendKind::File, BackendKind::S3, BackendKind::Sled];
+
+    /// 解析后端名称（不区分大小写）
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// 后端名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::File => "file",
+            BackendKind::S3 => "s3",
+            BackendKind::Sled => "sled",
+        }
+    }
+
+    /// 打开数据路径上的后端
+    ///
+    /// # Arguments
+    /// * `path` - 数据文件路径，sled 后端为数据库目录
+    /// * `compression_level` - 保存时的 zstd 压缩级别，sled 后端不使用
+    /// * `s3` - 对象存储的配置，只有 s3 后端使用
+    pub fn open(&self, path: String, compression_level: u32, s3: &S3Config) -> tokio_io::Result<Arc<dyn PersistenceBackend>> {
+        Ok(match self {
+            BackendKind::File => Arc::new(FileBackend::new(path, compression_level)),
+            BackendKind::S3 => Arc::new(S3Backend::new(path, compression_level, s3)?),
+            BackendKind::Sled => Arc::new(SledBackend::open(&path)?),
+        })
+    }
+}
+
+/// 持久化后端，负责把数据写入存储介质和读回
+/// 整体保存的后端（file、s3）每次保存和加载完整的快照；按键保存的后端（sled）还可以只写入修改过的键（`append`）、
+/// 逐个读取保存的键（`iterate`）和读取单个键（`get`），存储据此只保存修改过的键，并把内存放不下的值留在后端中
+/// 方法都是阻塞的，由持久化管理器在阻塞线程池中调用
+pub trait PersistenceBackend: Send + Sync {
+    /// 读取上次保存的全部数据，从未保存过时返回空的数据
+    fn load(&self) -> tokio_io::Result<LoadedData>;
+
+    /// 保存快照，成功后替换上次保存的全部内容；失败时上次保存的内容保持完整
+    fn save(&self, snapshot: &Snapshot<Arc<RedoxValue>>) -> tokio_io::Result<()>;
+
+    /// 是否按键保存数据，为 true 时存储改用 `iterate`、`append` 和 `get`，不再调用 `load` 和 `save`
+    fn keyed(&self) -> bool {
+        false
+    }
+
+    /// 写入修改过的键，一次写入的内容要么全部生效，要么都不生效
+    fn append(&self, _changes: &Changes) -> tokio_io::Result<()> {
+        Err(unsupported("append"))
+    }
+
+    /// 读取单个键保存的状态
+    ///
+    /// # Returns
+    /// * `Ok(Some(Entry))` - 键保存的值和过期时间
+    /// * `Ok(None)` - 没有保存这个键
+    fn get(&self, _key: &[u8]) -> tokio_io::Result<Option<Entry>> {
+        Err(unsupported("get"))
+    }
+
+    /// 逐个读取保存的所有键，按键保存的后端不会同时把全部数据放在内存中；
+    /// 默认实现先用 `load` 读取全部数据再逐个交给 `visit`
+    fn iterate(&self, visit: &mut dyn FnMut(Bytes, Entry)) -> tokio_io::Result<()> {
+        let mut loaded = self.load()?;
+        let mut field_expiry: HashMap<Bytes, Vec<(Bytes, u64)>> = HashMap::new();
+        for (key, field, when) in loaded.field_expiry {
+            field_expiry.entry(key).or_default().push((field, when));
+        }
+        for (key, value) in loaded.data {
+            let entry = Entry {
+                value: Arc::new(value),
+                expire_at: loaded.expiry.remove(&key),
+                field_expires: field_expiry.remove(&key).unwrap_or_default(),
+            };
+            visit(key, entry);
+        }
+        Ok(())
+    }
+
+    /// 读取保存的函数库源码，库名到源码的映射；默认实现用 `load` 读取全部数据后只取出函数库
+    fn load_functions(&self) -> tokio_io::Result<BTreeMap<String, String>> {
+        self.load().map(|loaded| loaded.functions)
+    }
+
+    /// 修改保存时的压缩级别，不支持压缩的后端忽略
+    fn set_compression_level(&self, _level: u32) {}
+}
+
+/// 后端不支持的操作返回的错误
+fn unsupported(operation: &str) -> tokio_io::Error {
+    tokio_io::Error::new(tokio_io::ErrorKind::Unsupported, format!("the persistence backend does not support {}", operation))
+}
+
+/// 一次写入按键保存的后端的内容
+#[derive(Default)]
+pub struct Changes {
+    /// 是否先删除之前保存的所有键，上次写入之后执行过 FLUSHALL 时为 true
+    pub clear: bool,
+    /// 修改过的键的当前状态，None 表示键已被删除
+    pub keys: Vec<(Bytes, Option<Entry>)>,
+    /// 函数库源码，None 表示不修改
+    pub functions: Option<BTreeMap<String, String>>,
+    /// 是否在返回前把写入刷到磁盘；保存时为 true，把值移出内存时为 false，随后台刷新或下一次保存写到磁盘
+    pub sync: bool,
+}
+
+/// 单个数据文件的后端，格式见 `Snapshot`
+pub struct FileBackend {
+    /// 数据文件的路径
+    path: String,
+    /// 保存时的 zstd 压缩级别，0 表示不压缩，可以在运行时修改
+    compression_level: AtomicU32,
+}
+
+impl FileBackend {
+    /// 创建数据文件后端
+    /// 
+    /// # Arguments
+    /// * `path` - 数据文件的路径
+    /// * `compression_level` - 保存时的 zstd 压缩级别，0 表示不压缩
+    pub fn new(path: String, compression_level: u32) -> Self {
+        Self {
+            path,
+            compression_level: AtomicU32::new(compression_level),
+        }
+    }
+}
+
+impl PersistenceBackend for FileBackend {
+    fn load(&self) -> tokio_io::Result<LoadedData> {
+        if !Path::new(&self.path).exists() {
+            warning!("Data file not found: {}", self.path);
+            return Ok(LoadedData::default());
+        }
+        let content = std::fs::read(&self.path).inspect_err(|e| warning!("Error opening data file: {}", e))?;
+        decode(&content)
+    }
+
+    fn save(&self, snapshot: &Snapshot<Arc<RedoxValue>>) -> tokio_io::Result<()> {
+        // 每个键序列化后直接经过缓冲区写入文件（压缩时先经过 zstd），不在内存中生成整个文件的内容，
+        // 保存时额外占用的内存与数据量无关
+        let level = self.compression_level.load(Ordering::Relaxed);
+        let temp_path = format!("{}.temp", self.path);
+        let file = std::fs::File::create(&temp_path)?;
+        if level == 0 {
+            write_snapshot(file, snapshot)?;
+        } else {
+            write_snapshot(zstd::Encoder::new(file, level as i32)?, snapshot)?.finish()?;
+        }
+        std::fs::rename(temp_path, &self.path)
+    }
+
+    fn set_compression_level(&self, level: u32) {
+        self.compression_level.store(level, Ordering::Relaxed);
+    }
 }
 
 /// 持久化管理器
-/// 负责数据的加载、保存和自动保存
+/// 负责数据的加载、保存和自动保存，数据的存储方式由后端决定
 #[derive(Clone)]
 pub struct Persistence {
-    /// 数据文件的路径
-    file_path: String,
-    /// 自动保存的时间间隔
-    save_interval: Duration,
-    /// 上次保存时间
+    /// 保存和加载数据的后端
+    backend: Arc<dyn PersistenceBackend>,
+    /// 自动保存的条件，满足任意一条时保存，可以在运行时修改
+    save_rules: Arc<RwLock<Vec<SaveRule>>>,
+    /// 上次成功保存的时间（秒级 Unix 时间戳），启动时为启动的时间
     last_save: Arc<AtomicU64>,
-    /// 脏标记
-    dirty: Arc<AtomicBool>,
-    expiry: Arc<Mutex<HashMap<String, u64>>>,
+    /// 上次保存之后的修改次数
+    changes: Arc<AtomicU64>,
+    /// 判断自动保存条件时使用的时钟，由存储设置为与自己相同的时钟
+    clock: SharedClock,
+    /// 上次保存之后修改过的键，只有按键保存的后端记录
+    dirty: Arc<Mutex<DirtyKeys>>,
+    /// 按键保存的后端的写入依次进行，读取键的状态和写入后端之间不会插入同一个键的另一次写入
+    appending: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// 上次保存之后修改过的键
+#[derive(Default)]
+struct DirtyKeys {
+    /// 被写入、删除、过期或淘汰的键
+    keys: HashSet<Bytes>,
+    /// 是否执行过 FLUSHALL，下次写入时先删除后端中的所有键
+    cleared: bool,
 }
 
 impl Persistence {
     /// 创建新的持久化管理器实例
     /// 
     /// # Arguments
-    /// * `file_path` - 数据文件的路径
-    /// * `save_interval` - 自动保存的时间间隔
-    pub fn new(file_path: String, save_interval: Duration) -> Self {
+    /// * `backend` - 保存和加载数据的后端
+    /// * `save_rules` - 自动保存的条件，为空时不自动保存
+    pub fn new(backend: Arc<dyn PersistenceBackend>, save_rules: Vec<SaveRule>) -> Self {
         Self {
-            file_path,
-            save_interval,
-            last_save: Arc::new(AtomicU64::new(0)),
-            dirty: Arc::new(AtomicBool::new(false)),
-            expiry: Arc::new(Mutex::new(HashMap::new())),
+            backend,
+            save_rules: Arc::new(RwLock::new(save_rules)),
+            last_save: Arc::new(AtomicU64::new(now_ms() / 1000)),
+            changes: Arc::new(AtomicU64::new(0)),
+            clock: Arc::new(SystemClock),
+            dirty: Arc::new(Mutex::new(DirtyKeys::default())),
+            appending: Arc::new(tokio::sync::Mutex::new(())),
         }
     }
 
-    /// 从文件载数据
+    /// 改为使用指定的时钟，上次保存的时间重新设置为这个时钟的当前时间
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.last_save.store(clock.now_ms() / 1000, Ordering::Relaxed);
+        self.clock = clock;
+        self
+    }
+
+    /// 从后端加载数据
     /// 
     /// # Returns
-    /// * `Ok(HashMap)` - 成功加载的数据
+    /// * `Ok(LoadedData)` - 成功加载的数据、键的过期时间（毫秒）和函数库
     /// * `Err` - 加载过程中的错误
-    pub async fn load(&self) -> tokio_io::Result<HashMap<String, RedoxValue>> {
-        if !Path::new(&self.file_path).exists() {
-            eprintln!("Data file not found: {}", self.file_path);
-            return Ok(HashMap::new());
-        }
-
-        let file = match TokioFile::open(&self.file_path).await {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("Error opening data file: {}", e);
-                return Err(e);
-            }
-        };
-        
-        let mut content = String::new();
-        let mut reader = TokioBufReader::new(file);
-        reader.read_to_string(&mut content).await?;
-
-        // 尝试以新格式读取
-        match serde_json::from_str::<PersistentData>(&content) {
-            Ok(persistent_data) => {
-                let mut expiry = self.expiry.lock().await;
-                *expiry = persistent_data.expiry;
-                Ok(persistent_data.data)
-            }
-            Err(e) => {
-                eprintln!("Failed to read as new format: {}", e);
-                // 如果失败，尝试以旧格式读取
-                match serde_json::from_str::<LegacyData>(&content) {
-                    Ok(legacy_data) => {
-                        println!("Successfully loaded data in legacy format");
-                        Ok(legacy_data.data)
-                    }
-                    Err(e) => {
-                        eprintln!("Error deserializing data: {}", e);
-                        Err(tokio_io::Error::new(tokio_io::ErrorKind::InvalidData, e))
-                    }
-                }
-            }
-        }
+    pub async fn load(&self) -> tokio_io::Result<LoadedData> {
+        // 读取和解析大量数据需要较长的时间，与保存一样放到阻塞线程池中执行
+        let backend = self.backend.clone();
+        tokio::task::spawn_blocking(move || backend.load())
+            .await
+            .map_err(tokio_io::Error::other)?
     }
 
-    /// 将数据保存到文件
+    /// 将数据保存到后端
     /// 
     /// # Arguments
-    /// * `data` - 要保存的数据
+    /// * `data` - 要保存的键值对快照，由存储在各分片加锁时复制值的引用得到，保存时不持有任何锁；
+    ///   快照之后被修改的键在存储中复制出新的值，快照中的值保持不变
+    /// * `expiry` - 键的过期时间（毫秒）
+    /// * `field_expiry` - 哈希表字段的过期时间（毫秒），按键分组
+    /// * `functions` - 函数库源码
     /// 
     /// # Returns
     /// * `Ok(())` - 保存成功
     /// * `Err` - 保存过程中的错误
-    pub async fn save(&self, data: &HashMap<String, RedoxValue>) -> tokio_io::Result<()> {
-        let expiry = self.expiry.lock().await;
-        let persistent_data = PersistentData {
-            data: data.clone(),
-            expiry: expiry.clone(),
-        };
-
-        let temp_path = format!("{}.temp", self.file_path);
-        let file = TokioFile::create(&temp_path).await?;
-        let mut writer = TokioBufWriter::new(file);
-
-        let json = serde_json::to_string(&persistent_data)?;
-        writer.write_all(json.as_bytes()).await?;
-        writer.flush().await?;
-
-        tokio::fs::rename(temp_path, &self.file_path).await?;
+    pub async fn save(
+        &self,
+        data: Vec<(Bytes, Arc<RedoxValue>)>,
+        expiry: Vec<(Bytes, u64)>,
+        field_expiry: Vec<(Bytes, Vec<(Bytes, u64)>)>,
+        functions: BTreeMap<String, String>,
+    ) -> tokio_io::Result<()> {
+        let snapshot = Snapshot::new(data, expiry, field_expiry, functions);
+
+        // 序列化和压缩大量数据需要较长的 CPU 时间，放到阻塞线程池中执行，不占用处理连接的工作线程
+        let backend = self.backend.clone();
+        tokio::task::spawn_blocking(move || backend.save(&snapshot))
+            .await
+            .map_err(tokio_io::Error::other)??;
+
+        self.last_save.store(self.now_secs(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 后端是否按键保存数据，见 `PersistenceBackend::keyed`
+    pub fn keyed(&self) -> bool {
+        self.backend.keyed()
+    }
+
+    /// 逐个读取后端保存的所有键，启动时由存储在 `block_in_place` 中调用
+    pub fn iterate(&self, visit: &mut dyn FnMut(Bytes, Entry)) -> tokio_io::Result<()> {
+        self.backend.iterate(visit)
+    }
+
+    /// 读取后端保存的函数库源码，启动时与 `iterate` 一起调用
+    pub fn load_functions(&self) -> tokio_io::Result<BTreeMap<String, String>> {
+        self.backend.load_functions()
+    }
+
+    /// 从按键保存的后端读取单个键，在阻塞线程池中执行
+    pub async fn get(&self, key: Bytes) -> tokio_io::Result<Option<Entry>> {
+        let backend = self.backend.clone();
+        tokio::task::spawn_blocking(move || backend.get(&key))
+            .await
+            .map_err(tokio_io::Error::other)?
+    }
+
+    /// 把修改过的键写入按键保存的后端，在阻塞线程池中执行；调用者先通过 `lock_appends` 获得写入的顺序
+    /// `changes.sync` 为 true 时这是一次保存，成功后更新上次保存的时间
+    pub async fn append(&self, changes: Changes) -> tokio_io::Result<()> {
+        let sync = changes.sync;
+        let backend = self.backend.clone();
+        tokio::task::spawn_blocking(move || backend.append(&changes))
+            .await
+            .map_err(tokio_io::Error::other)??;
+        if sync {
+            self.last_save.store(self.now_secs(), Ordering::Relaxed);
+        }
         Ok(())
     }
 
+    /// 等待之前的写入完成，持有返回的锁期间读取键的状态并写入后端
+    pub async fn lock_appends(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.appending.lock().await
+    }
+
+    /// 取出上次保存之后修改过的键
+    ///
+    /// # Returns
+    /// (是否执行过 FLUSHALL, 修改过的键)
+    pub fn take_dirty(&self) -> (bool, Vec<Bytes>) {
+        let mut dirty = self.dirty.lock().unwrap();
+        (std::mem::take(&mut dirty.cleared), dirty.keys.drain().collect())
+    }
+
+    /// 写入失败时把取出的键放回去，下次保存时重新写入
+    pub fn restore_dirty(&self, cleared: bool, keys: impl IntoIterator<Item = Bytes>) {
+        let mut dirty = self.dirty.lock().unwrap();
+        dirty.cleared |= cleared;
+        dirty.keys.extend(keys);
+    }
+
+    /// 取出是否执行过 FLUSHALL，把值移出内存时与这个值一起写入，修改过的键留给下次保存
+    pub fn take_cleared(&self) -> bool {
+        std::mem::take(&mut self.dirty.lock().unwrap().cleared)
+    }
+
+    /// 记录键的一次修改，按键保存的后端同时记下这个键
+    fn mark_key_dirty(&self, key: &[u8]) {
+        self.mark_dirty();
+        if self.keyed() {
+            self.dirty.lock().unwrap().keys.insert(Bytes::copy_from_slice(key));
+        }
+    }
+
     /// 启动自动保存任务
     /// 
     /// # Arguments
-    /// * `data` - 要保存的数据的共享引用
+    /// * `save` - 保存一次数据，数据没有修改时不写文件，返回值与 `Storage::flush` 相同
     /// 
-    /// 这个方法会创建一个新的异步任务，定期保存数据
-    pub async fn start_auto_save(
-        self,
-        data: Arc<tokio::sync::Mutex<HashMap<String, RedoxValue>>>,
-    ) {
-        let mut interval = time::interval(self.save_interval);
+    /// 这个方法会创建一个新的异步任务，每秒检查一次保存条件，满足任意一条时保存数据
+    pub async fn start_auto_save<F, Fut>(self, mut save: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = tokio_io::Result<bool>>,
+    {
+        let mut interval = time::interval(SAVE_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
         loop {
             interval.tick().await;
-            
-            if !self.dirty.load(Ordering::Relaxed) {
+            if !self.should_save() {
                 continue;
             }
-
-            let data = data.lock().await;
-            if let Err(e) = self.save(&data).await {
-                eprintln!("Error saving data: {}", e);
-            } else {
-                self.dirty.store(false, Ordering::Relaxed);
-                self.last_save.store(
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    Ordering::Relaxed
-                );
+            if let Err(e) = save().await {
+                warning!("Error saving data: {}, retrying in {} seconds", e, SAVE_RETRY_DELAY.as_secs());
+                time::sleep(SAVE_RETRY_DELAY).await;
             }
         }
     }
 
-    #[allow(dead_code)]
-    pub async fn set_expiry(&self, key: String, expires: u64) {
-        let mut expiry = self.expiry.lock().await;
-        expiry.insert(key, expires);
+    /// 是否满足任意一条自动保存的条件
+    fn should_save(&self) -> bool {
+        let changes = self.changes();
+        if changes == 0 {
+            return false;
+        }
+        let elapsed = self.now_secs().saturating_sub(self.last_save());
+        self.save_rules.read().unwrap()
+            .iter()
+            .any(|rule| elapsed >= rule.seconds && changes >= rule.changes)
+    }
+
+    /// 修改自动保存的条件，立即生效
+    pub fn set_save_rules(&self, save_rules: Vec<SaveRule>) {
+        *self.save_rules.write().unwrap() = save_rules;
+    }
+
+    /// 修改保存时的 zstd 压缩级别，从下一次保存开始生效
+    pub fn set_compression_level(&self, level: u32) {
+        self.backend.set_compression_level(level);
     }
 
-    #[allow(dead_code)]
-    pub async fn get_expiry(&self, key: &str) -> Option<u64> {
-        let expiry = self.expiry.lock().await;
-        expiry.get(key).copied()
+    /// 清零修改次数
+    /// 
+    /// # Returns
+    /// 清零前的修改次数；保存在清零之后复制数据，之后的修改重新计数
+    pub fn take_changes(&self) -> u64 {
+        self.changes.swap(0, Ordering::AcqRel)
     }
 
+    /// 保存失败时把取出的修改次数加回去，下次检查时重试
+    pub fn restore_changes(&self, changes: u64) {
+        self.changes.fetch_add(changes, Ordering::Relaxed);
+    }
+
+    /// 记录一次修改
     pub fn mark_dirty(&self) {
-        self.dirty.store(true, Ordering::Relaxed);
+        self.changes.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub async fn remove_expiry(&self, key: &str) -> bool {
-        let mut expiry = self.expiry.lock().await;
-        expiry.remove(key).is_some()
+    /// 上次保存之后的修改次数，用于 INFO 的 rdb_changes_since_last_save
+    pub fn changes(&self) -> u64 {
+        self.changes.load(Ordering::Relaxed)
     }
-} 
\ No newline at end of file
+
+    /// 上次成功保存的时间（秒级 Unix 时间戳），用于 INFO 的 rdb_last_save_time
+    pub fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::Relaxed)
+    }
+
+    /// 时钟的当前秒级 Unix 时间戳
+    fn now_secs(&self) -> u64 {
+        self.clock.now_ms() / 1000
+    }
+}
+
+/// 注册为存储的观察者，任何键的修改都记录一次修改，按键保存的后端还记下修改过的键
+impl StorageObserver for Persistence {
+    fn on_set(&self, key: &[u8], _event: &str) {
+        self.mark_key_dirty(key);
+    }
+
+    fn on_delete(&self, key: &[u8]) {
+        self.mark_key_dirty(key);
+    }
+
+    fn on_expire(&self, key: &[u8]) {
+        self.mark_key_dirty(key);
+    }
+
+    fn on_evict(&self, key: &[u8]) {
+        self.mark_key_dirty(key);
+    }
+
+    fn on_flush(&self) {
+        self.mark_dirty();
+        if self.keyed() {
+            let mut dirty = self.dirty.lock().unwrap();
+            dirty.keys.clear();
+            dirty.cleared = true;
+        }
+    }
+} 
+/// 把快照以带魔数、版本和校验和的二进制格式写入写入器，保存数据文件和复制的全量同步共用
+///
+/// # Returns
+/// 写完并刷新缓冲区后的写入器，压缩时由调用者结束 zstd 帧
+pub fn write_snapshot<W: Write>(writer: W, snapshot: &Snapshot<Arc<RedoxValue>>) -> tokio_io::Result<W> {
+    let mut buffered = std::io::BufWriter::new(ChecksumWriter { inner: writer, hasher: crc32fast::Hasher::new() });
+    buffered.write_all(SNAPSHOT_MAGIC)?;
+    buffered.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+    bincode::serialize_into(&mut buffered, snapshot).map_err(tokio_io::Error::other)?;
+    let ChecksumWriter { mut inner, hasher } = buffered.into_inner().map_err(|e| e.into_error())?;
+    inner.write_all(&hasher.finalize().to_le_bytes())?;
+    inner.flush()?;
+    Ok(inner)
+}
+
+/// 把数据原样写入内部的写入器，同时计算写入数据的 CRC32
+struct ChecksumWriter<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 校验并解码二进制快照
+///
+/// # Returns
+/// * `Ok(Snapshot)` - 快照的内容
+/// * `Err(String)` - 文件被截断、校验和错误、版本比当前版本新或内容损坏
+fn decode_snapshot(content: &[u8]) -> Result<Snapshot, String> {
+    let header = SNAPSHOT_MAGIC.len() + 2;
+    if content.len() < header + 4 {
+        return Err("snapshot is truncated".to_string());
+    }
+    let (body, checksum) = content.split_at(content.len() - 4);
+    let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+    if crc32fast::hash(body) != checksum {
+        return Err("snapshot checksum mismatch, the file is truncated or corrupted".to_string());
+    }
+    let version = u16::from_le_bytes([body[SNAPSHOT_MAGIC.len()], body[SNAPSHOT_MAGIC.len() + 1]]);
+    if version > SNAPSHOT_VERSION {
+        return Err(format!(
+            "snapshot format version {} is newer than the supported version {}",
+            version, SNAPSHOT_VERSION
+        ));
+    }
+    bincode::deserialize(&body[header..]).map_err(|e| format!("invalid snapshot: {}", e))
+}
+
+/// 解析数据文件的内容
+/// 以 zstd 魔数开头的先解压；以快照魔数开头的是二进制快照，否则依次尝试以 JSON 格式和最早的 JSON 格式读取，
+/// 下次保存时改写为二进制快照；副本也用它解析全量同步收到的快照
+pub fn decode(content: &[u8]) -> tokio_io::Result<LoadedData> {
+    let invalid = |e: String| tokio_io::Error::new(tokio_io::ErrorKind::InvalidData, e);
+    if content.starts_with(ZSTD_MAGIC) {
+        let decompressed = zstd::decode_all(content)
+            .map_err(|e| invalid(format!("failed to decompress data file: {}", e)))?;
+        return decode(&decompressed);
+    }
+    if content.starts_with(SNAPSHOT_MAGIC) {
+        let snapshot = decode_snapshot(content).map_err(invalid)?;
+        return Ok(LoadedData {
+            data: snapshot.data.0.into_iter().collect(),
+            expiry: snapshot.expiry_ms.0.into_iter().collect(),
+            field_expiry: flatten_field_expiry(snapshot.field_expiry_ms),
+            functions: snapshot.functions,
+        });
+    }
+
+    match serde_json::from_slice::<PersistentData>(content) {
+        Ok(persistent_data) => {
+            notice!("Loaded data file in JSON format, it will be converted to the binary snapshot format on the next save");
+            // 兼容以秒为单位保存的过期时间
+            let mut expiry: HashMap<Bytes, u64> = persistent_data.expiry_ms.0.into_iter().collect();
+            for (key, seconds) in persistent_data.expiry.0 {
+                expiry.entry(key).or_insert(seconds.saturating_mul(1000));
+            }
+            Ok(LoadedData {
+                data: persistent_data.data.0.into_iter().collect(),
+                expiry,
+                field_expiry: flatten_field_expiry(persistent_data.field_expiry_ms),
+                functions: persistent_data.functions,
+            })
+        }
+        Err(e) => {
+            warning!("Failed to read as new format: {}", e);
+            // 如果失败，尝试以旧格式读取
+            match serde_json::from_slice::<LegacyData>(content) {
+                Ok(legacy_data) => {
+                    notice!("Successfully loaded data in legacy format");
+                    Ok(LoadedData {
+                        data: legacy_data.data.0.into_iter().collect(),
+                        ..LoadedData::default()
+                    })
+                }
+                Err(e) => {
+                    warning!("Error deserializing data: {}", e);
+                    Err(invalid(e.to_string()))
+                }
+            }
+        }
+    }
+}
+
+/// 把按键分组的字段过期时间展开为 (键, 字段, 过期时间)
+fn flatten_field_expiry(field_expiry: TextMap<TextMap<u64>>) -> Vec<(Bytes, Bytes, u64)> {
+    field_expiry.0.into_iter()
+        .flat_map(|(key, fields)| fields.0.into_iter().map(move |(field, when)| (key.clone(), field, when)))
+        .collect()
+}
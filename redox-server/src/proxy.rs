@@ -0,0 +1,121 @@
+//! PROXY 协议
+//! 服务器部署在 HAProxy、NLB 等负载均衡之后时，负载均衡在每个连接的开头发送 PROXY 协议头，
+//! 说明客户端的真实地址。启用 proxy-protocol 后，服务器在接受连接时读取 v1（文本）或 v2（二进制）格式的协议头，
+//! 在 CLIENT LIST 等地方记录客户端的真实地址，而不是负载均衡的地址。
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// v1 协议头的前缀
+const V1_PREFIX: &[u8] = b"PROXY";
+
+/// v1 协议头的最大长度（包括结尾的 CRLF）
+const V1_MAX_LEN: usize = 107;
+
+/// v2 协议头的签名
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// v2 协议头中签名、版本、地址族和长度占用的字节数
+const V2_HEADER_LEN: usize = 16;
+
+/// 读取协议头的最长时间，避免不发送协议头的连接一直占用任务
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 从连接的开头读取 PROXY 协议头，只读取协议头本身，之后的数据留给连接处理
+///
+/// # Arguments
+/// * `socket` - 刚接受的连接，TLS 握手之前
+///
+/// # Returns
+/// * `Ok(Some(SocketAddr))` - 客户端的真实地址
+/// * `Ok(None)` - 协议头有效但没有携带地址（v1 的 UNKNOWN、v2 的 LOCAL 命令或 UNIX 地址族），使用连接的地址
+/// * `Err` - 读取失败、超时或协议头无效，连接应当关闭
+pub async fn read_header<S: AsyncRead + Unpin>(socket: &mut S) -> io::Result<Option<SocketAddr>> {
+    tokio::time::timeout(READ_TIMEOUT, read_header_inner(socket))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for PROXY protocol header"))?
+}
+
+/// 读取并解析协议头，不限制时间
+async fn read_header_inner<S: AsyncRead + Unpin>(socket: &mut S) -> io::Result<Option<SocketAddr>> {
+    // 两种格式的前 5 个字节不同，先读取这 5 个字节再按格式读取剩下的部分
+    let mut header = vec![0; V1_PREFIX.len()];
+    socket.read_exact(&mut header).await?;
+    if header == V1_PREFIX {
+        while !header.ends_with(b"\r\n") {
+            if header.len() >= V1_MAX_LEN {
+                return Err(invalid("PROXY v1 header is too long"));
+            }
+            header.push(socket.read_u8().await?);
+        }
+        return parse_v1(&header[..header.len() - 2]);
+    }
+    if header == V2_SIGNATURE[..header.len()] {
+        header.resize(V2_HEADER_LEN, 0);
+        socket.read_exact(&mut header[V1_PREFIX.len()..]).await?;
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        let mut addresses = vec![0; len];
+        socket.read_exact(&mut addresses).await?;
+        return parse_v2(&header, &addresses);
+    }
+    Err(invalid("missing PROXY protocol header"))
+}
+
+/// 解析 v1 协议头，如 `PROXY TCP4 192.0.2.1 198.51.100.1 56324 6379`（不含 CRLF）
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("invalid PROXY v1 header"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _destination, port, _destination_port] => {
+            let ip = source.parse::<IpAddr>().map_err(|_| invalid("invalid PROXY v1 source address"))?;
+            let port = port.parse::<u16>().map_err(|_| invalid("invalid PROXY v1 source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY v1 header")),
+    }
+}
+
+/// 解析 v2 协议头
+///
+/// # Arguments
+/// * `header` - 16 字节的固定部分
+/// * `addresses` - 地址部分，可能还包含 TLV 扩展
+fn parse_v2(header: &[u8], addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if header[..V2_SIGNATURE.len()] != *V2_SIGNATURE {
+        return Err(invalid("invalid PROXY v2 signature"));
+    }
+    let version_command = header[12];
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    match version_command & 0x0f {
+        // LOCAL：负载均衡自己建立的连接，如健康检查
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unsupported PROXY v2 command")),
+    }
+    // 高 4 位为地址族，低 4 位为传输协议（TCP 或 UDP）
+    match header[13] >> 4 {
+        1 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16].try_into().expect("slice is 16 bytes");
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port)))
+        }
+        1 | 2 => Err(invalid("truncated PROXY v2 address")),
+        // UNSPEC 和 UNIX 地址族没有可用的 IP 地址
+        _ => Ok(None),
+    }
+}
+
+/// 协议头无效的错误
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,284 @@
+//! 发布订阅
+//! PUBLISH 把消息发送给订阅了这个频道、或订阅了与频道名称匹配的模式的所有连接，消息不保存，没有订阅者时被丢弃。
+//! 连接执行 SUBSCRIBE 或 PSUBSCRIBE 后进入订阅模式，只能执行订阅相关的命令和 PING，取消所有订阅后恢复正常。
+//! 订阅者读取太慢、积压的消息超过上限时连接被关闭，与 Redis 的 client-output-buffer-limit pubsub 相同。
+//! 消息只发送给本节点的订阅者，不会复制到副本或集群的其他节点。
+
+use crate::glob::glob_match;
+use crate::logging::warning;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::{CodecError, RedoxCodec, WireProtocol};
+use redox_protocol::{Command, RedoxError, RedoxValue, Response};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
+
+/// 每个订阅连接最多积压的消息数，超过时关闭连接
+const SUBSCRIBER_BACKLOG: usize = 4096;
+
+/// 频道和模式的订阅者
+pub struct PubSub {
+    /// 频道或模式到订阅者的映射，键为订阅连接的编号
+    registry: Mutex<Registry>,
+    /// 下一个订阅连接的编号
+    next_id: AtomicU64,
+}
+
+/// 所有订阅
+#[derive(Default)]
+struct Registry {
+    /// 频道名称到订阅者
+    channels: HashMap<Bytes, HashMap<u64, Arc<Subscriber>>>,
+    /// 模式到订阅者
+    patterns: HashMap<Bytes, HashMap<u64, Arc<Subscriber>>>,
+}
+
+/// 一个订阅连接的消息队列
+struct Subscriber {
+    /// 发送给连接的消息，同一条消息的所有订阅者共享一个回复
+    messages: mpsc::Sender<Arc<Response>>,
+    /// 消息积压超过上限时取消，连接随之关闭
+    overflowed: CancellationToken,
+}
+
+impl Subscriber {
+    /// 把消息放入队列，返回是否成功，队列已满时标记连接需要关闭
+    fn deliver(&self, message: Arc<Response>) -> bool {
+        match self.messages.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.overflowed.cancel();
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+impl PubSub {
+    /// 创建没有订阅者的实例
+    pub fn new() -> Self {
+        Self { registry: Mutex::new(Registry::default()), next_id: AtomicU64::new(1) }
+    }
+
+    /// 发送消息
+    ///
+    /// # Arguments
+    /// * `channel` - 频道名称
+    /// * `message` - 消息内容
+    ///
+    /// # Returns
+    /// 收到消息的订阅数，同一个连接通过频道和模式各订阅一次时计为两次
+    pub fn publish(&self, channel: &Bytes, message: &Bytes) -> usize {
+        let registry = self.registry.lock().unwrap();
+        let mut receivers = 0;
+        if let Some(subscribers) = registry.channels.get(channel) {
+            let reply = Arc::new(push("message", vec![string(channel), string(message)]));
+            receivers += subscribers.values().filter(|subscriber| subscriber.deliver(reply.clone())).count();
+        }
+        for (pattern, subscribers) in &registry.patterns {
+            if glob_match(pattern, channel) {
+                let reply = Arc::new(push("pmessage", vec![string(pattern), string(channel), string(message)]));
+                receivers += subscribers.values().filter(|subscriber| subscriber.deliver(reply.clone())).count();
+            }
+        }
+        receivers
+    }
+
+    /// 把连接转为订阅模式，执行第一个订阅命令后转发消息，直到取消所有订阅、连接断开或 `kill` 被取消
+    ///
+    /// # Arguments
+    /// * `framed` - 连接，之前的回复已经写出
+    /// * `protocol` - 连接的协议
+    /// * `cmd` - 进入订阅模式的命令，SUBSCRIBE、PSUBSCRIBE、UNSUBSCRIBE 或 PUNSUBSCRIBE
+    /// * `check` - 检查用户是否有权限执行订阅模式中的命令
+    /// * `peer` - 客户端地址，用于日志
+    /// * `kill` - 服务器关闭或 CLIENT KILL 时取消
+    ///
+    /// # Returns
+    /// * `Ok(true)` - 已取消所有订阅，连接恢复正常
+    /// * `Ok(false)` - 连接需要关闭
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        framed: &mut Framed<S, RedoxCodec>,
+        protocol: WireProtocol,
+        cmd: Command,
+        check: impl Fn(&Command) -> Result<(), RedoxError>,
+        peer: SocketAddr,
+        kill: &CancellationToken,
+    ) -> Result<bool, CodecError> {
+        let (messages, mut queue) = mpsc::channel(SUBSCRIBER_BACKLOG);
+        let mut session = Session {
+            pubsub: self,
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            subscriber: Arc::new(Subscriber { messages, overflowed: CancellationToken::new() }),
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+        };
+        let mut pending = Some(cmd);
+        loop {
+            if let Some(cmd) = pending.take() {
+                for reply in session.execute(cmd) {
+                    framed.feed(protocol.encode_response(&reply)).await?;
+                }
+                SinkExt::<Vec<u8>>::flush(framed).await?;
+                if session.is_empty() {
+                    return Ok(true);
+                }
+            }
+            tokio::select! {
+                message = queue.recv() => match message {
+                    Some(message) => framed.send(protocol.encode_response(&message)).await?,
+                    None => return Ok(false),
+                },
+                frame = framed.next() => {
+                    let reply = match frame {
+                        Some(Ok(Ok(cmd))) => match check(&cmd) {
+                            Ok(()) => {
+                                pending = Some(cmd);
+                                continue;
+                            }
+                            Err(e) => Response::Error(e),
+                        },
+                        Some(Ok(Err(e))) => Response::Error(e),
+                        Some(Err(CodecError::Protocol(e))) => {
+                            framed.send(protocol.encode_response(&Response::Error(e.into()))).await?;
+                            return Ok(false);
+                        }
+                        Some(Err(e)) => return Err(e),
+                        None => return Ok(false),
+                    };
+                    framed.send(protocol.encode_response(&reply)).await?;
+                }
+                _ = session.subscriber.overflowed.cancelled() => {
+                    warning!("Closing subscriber {}: more than {} messages pending", peer, SUBSCRIBER_BACKLOG);
+                    return Ok(false);
+                }
+                _ = kill.cancelled() => return Ok(false),
+            }
+        }
+    }
+}
+
+/// 一个订阅模式的连接订阅的频道和模式，结束时从 `PubSub` 中移除
+struct Session<'a> {
+    pubsub: &'a PubSub,
+    /// 连接的编号
+    id: u64,
+    /// 连接的消息队列
+    subscriber: Arc<Subscriber>,
+    /// 订阅的频道
+    channels: HashSet<Bytes>,
+    /// 订阅的模式
+    patterns: HashSet<Bytes>,
+}
+
+impl Session<'_> {
+    /// 执行订阅模式中的一个命令
+    ///
+    /// # Returns
+    /// 回复，每个频道或模式一个
+    fn execute(&mut self, cmd: Command) -> Vec<Response> {
+        match cmd {
+            Command::Subscribe(channels) => channels.into_iter().map(|channel| self.subscribe(false, channel)).collect(),
+            Command::PSubscribe(patterns) => patterns.into_iter().map(|pattern| self.subscribe(true, pattern)).collect(),
+            Command::Unsubscribe(channels) => self.unsubscribe_all(false, channels),
+            Command::PUnsubscribe(patterns) => self.unsubscribe_all(true, patterns),
+            Command::Ping { message } => vec![push("pong", vec![string(&message.unwrap_or_default())])],
+            cmd => vec![Response::Error(
+                format!(
+                    "Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+                    cmd.name()
+                )
+                .into(),
+            )],
+        }
+    }
+
+    /// 订阅一个频道或模式，已经订阅的不重复订阅
+    fn subscribe(&mut self, pattern: bool, name: Bytes) -> Response {
+        let (subscribed, kind) = if pattern { (&mut self.patterns, "psubscribe") } else { (&mut self.channels, "subscribe") };
+        if subscribed.insert(name.clone()) {
+            let mut registry = self.pubsub.registry.lock().unwrap();
+            let registry = if pattern { &mut registry.patterns } else { &mut registry.channels };
+            registry.entry(name.clone()).or_default().insert(self.id, self.subscriber.clone());
+        }
+        push(kind, vec![string(&name), self.count()])
+    }
+
+    /// 取消订阅，names 为空时取消这一类的所有订阅；没有任何订阅时仍回复一次，频道为 nil
+    fn unsubscribe_all(&mut self, pattern: bool, names: Vec<Bytes>) -> Vec<Response> {
+        let kind = if pattern { "punsubscribe" } else { "unsubscribe" };
+        let names = match names.is_empty() {
+            true if pattern => self.patterns.iter().cloned().collect(),
+            true => self.channels.iter().cloned().collect(),
+            false => names,
+        };
+        if names.is_empty() {
+            return vec![push(kind, vec![Response::Nil, self.count()])];
+        }
+        names.into_iter()
+            .map(|name| {
+                self.unsubscribe(pattern, &name);
+                push(kind, vec![string(&name), self.count()])
+            })
+            .collect()
+    }
+
+    /// 取消一个订阅，没有订阅时忽略
+    fn unsubscribe(&mut self, pattern: bool, name: &Bytes) {
+        let subscribed = if pattern { &mut self.patterns } else { &mut self.channels };
+        if subscribed.remove(name) {
+            self.unregister(pattern, name);
+        }
+    }
+
+    /// 从 `PubSub` 中移除这个连接的一个订阅
+    fn unregister(&self, pattern: bool, name: &Bytes) {
+        let mut registry = self.pubsub.registry.lock().unwrap();
+        let registry = if pattern { &mut registry.patterns } else { &mut registry.channels };
+        if let Some(subscribers) = registry.get_mut(name) {
+            subscribers.remove(&self.id);
+            if subscribers.is_empty() {
+                registry.remove(name);
+            }
+        }
+    }
+
+    /// 订阅的频道和模式数，订阅确认中的整数
+    fn count(&self) -> Response {
+        Response::Integer((self.channels.len() + self.patterns.len()) as i64)
+    }
+
+    /// 是否已取消所有订阅
+    fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.patterns.is_empty()
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        for channel in &self.channels {
+            self.unregister(false, channel);
+        }
+        for pattern in &self.patterns {
+            self.unregister(true, pattern);
+        }
+    }
+}
+
+/// 订阅模式中推送给连接的回复，RESP3 连接中编码为推送类型，RESP2 连接中编码为数组
+fn push(kind: &str, items: Vec<Response>) -> Response {
+    Response::Push { kind: kind.to_string(), items }
+}
+
+/// 推送消息中的字符串元素
+fn string(s: &[u8]) -> Response {
+    Response::Value(RedoxValue::String(Bytes::copy_from_slice(s)))
+}
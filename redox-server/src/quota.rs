@@ -0,0 +1,274 @@
+//! 按键前缀的配额
+//! 共享实例中每个应用使用自己的键前缀（如 `app1:`），配置文件可以限制每个前缀的键数和字节数。
+//! 存储在更新内存统计时同时更新键所属前缀的用量，可能增加内存的命令写入前原子地预留配额，
+//! 用量已达到上限时拒绝写入并返回 QUOTA 错误；删除和读取命令不受影响。
+//! 一个键属于所有与它匹配的前缀，每个前缀的配额分别检查。
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// 一个前缀的配额
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quota {
+    /// 键的前缀，配置中结尾的 `*` 被去掉，`app1:*` 与 `app1:` 相同
+    pub prefix: Bytes,
+    /// 最多的键数，0 表示不限制
+    pub max_keys: usize,
+    /// 最多的字节数（按 MEMORY STATS 的估算），0 表示不限制
+    pub max_bytes: usize,
+}
+
+impl Quota {
+    /// 创建配额
+    ///
+    /// # Arguments
+    /// * `pattern` - 配置中的前缀，可以带结尾的 `*`
+    /// * `max_keys` - 最多的键数，0 表示不限制
+    /// * `max_bytes` - 最多的字节数，0 表示不限制
+    pub fn new(pattern: &str, max_keys: usize, max_bytes: usize) -> Self {
+        let prefix = pattern.strip_suffix('*').unwrap_or(pattern);
+        Self {
+            prefix: Bytes::copy_from_slice(prefix.as_bytes()),
+            max_keys,
+            max_bytes,
+        }
+    }
+
+    /// 配置中显示的前缀
+    pub fn pattern(&self) -> String {
+        format!("{}*", String::from_utf8_lossy(&self.prefix))
+    }
+}
+
+/// 一个前缀的配额和当前用量
+#[derive(Debug)]
+struct Usage {
+    /// 配额
+    quota: Quota,
+    /// 前缀下的键数，包括已预留但还没有创建的键
+    keys: AtomicUsize,
+    /// 前缀下的键估算的字节数，包括正在执行的写命令预留的字节数
+    bytes: AtomicUsize,
+}
+
+impl Usage {
+    /// 用量未达到上限时原子地增加用量，上限为 0 表示不限制
+    fn try_add(counter: &AtomicUsize, limit: usize, amount: usize) -> bool {
+        counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (limit == 0 || used < limit).then_some(used + amount)
+            })
+            .is_ok()
+    }
+}
+
+/// 配额和预留的状态
+#[derive(Default)]
+struct State {
+    /// 每个前缀的配额和用量
+    usages: Vec<Usage>,
+    /// 已预留但还没有创建的键及其预留次数，创建键时使用预留的键数，不再重复计数
+    pending: Mutex<HashMap<Bytes, usize>>,
+    /// 替换配额或清零用量时加一，之前的预留在释放时不再修改用量
+    generation: u64,
+}
+
+/// 所有前缀的配额和用量，存储和各分片共享
+/// 用量在分片的写锁内更新，替换配额时持有所有分片的锁重新统计
+#[derive(Clone, Default)]
+pub struct Quotas(Arc<RwLock<State>>);
+
+impl Quotas {
+    /// 替换所有配额
+    ///
+    /// # Arguments
+    /// * `quotas` - 新的配额及其前缀下已有的 (键数, 字节数)
+    pub fn replace(&self, quotas: Vec<(Quota, usize, usize)>) {
+        let mut state = self.0.write().unwrap();
+        state.usages = quotas
+            .into_iter()
+            .map(|(quota, keys, bytes)| Usage {
+                quota,
+                keys: AtomicUsize::new(keys),
+                bytes: AtomicUsize::new(bytes),
+            })
+            .collect();
+        state.pending.get_mut().unwrap().clear();
+        state.generation += 1;
+    }
+
+    /// 按键修改前后估算的字节数更新键所属前缀的用量
+    /// 创建的键已经预留过时使用预留的键数
+    ///
+    /// # Arguments
+    /// * `key` - 键
+    /// * `before` - 修改前的字节数，键原来不存在时为 None
+    /// * `after` - 修改后的字节数，键被删除时为 None
+    pub fn account(&self, key: &[u8], before: Option<usize>, after: Option<usize>) {
+        let state = self.0.read().unwrap();
+        if state.usages.is_empty() {
+            return;
+        }
+        let reserved = before.is_none() && after.is_some() && take_pending(&state.pending, key);
+        for usage in state.usages.iter().filter(|usage| key.starts_with(&usage.quota.prefix)) {
+            if let Some(bytes) = before {
+                usage.keys.fetch_sub(1, Ordering::Relaxed);
+                usage.bytes.fetch_sub(bytes, Ordering::Relaxed);
+            }
+            if let Some(bytes) = after {
+                if !reserved {
+                    usage.keys.fetch_add(1, Ordering::Relaxed);
+                }
+                usage.bytes.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 写入键之前预留键所属前缀的配额
+    /// 不存在的键在每个匹配的前缀上原子地预留一个键数，命令写入的字节数按 `bytes` 预留，
+    /// 同时执行的写命令看到彼此预留的用量，不会一起超过配额
+    ///
+    /// # Arguments
+    /// * `keys` - 要写入的键及其是否已存在，已存在的键不占用新的键数
+    /// * `bytes` - 命令可能写入的字节数的估算
+    ///
+    /// # Returns
+    /// * `Ok(QuotaReservation)` - 所有匹配的前缀都没有达到配额，释放时归还没有用到的预留
+    /// * `Err(String)` - 达到配额的说明，已预留的部分被归还
+    pub fn reserve(&self, keys: &[(&Bytes, bool)], bytes: usize) -> Result<QuotaReservation, String> {
+        let state = self.0.read().unwrap();
+        let mut reservation = QuotaReservation {
+            quotas: self.clone(),
+            generation: state.generation,
+            keys: Vec::new(),
+            usages: Vec::new(),
+            bytes,
+        };
+        for (index, usage) in state.usages.iter().enumerate() {
+            if !keys.iter().any(|(key, _)| key.starts_with(&usage.quota.prefix)) {
+                continue;
+            }
+            let quota = &usage.quota;
+            if !Usage::try_add(&usage.bytes, quota.max_bytes, bytes) {
+                return Err(format!("memory quota of {} bytes reached for prefix '{}'", quota.max_bytes, quota.pattern()));
+            }
+            reservation.usages.push(index);
+        }
+        for (key, _) in keys.iter().filter(|(_, exists)| !exists) {
+            let usages: Vec<&Usage> = state.usages.iter().filter(|usage| key.starts_with(&usage.quota.prefix)).collect();
+            if usages.is_empty() {
+                continue;
+            }
+            for (reserved, usage) in usages.iter().enumerate() {
+                let quota = &usage.quota;
+                if !Usage::try_add(&usage.keys, quota.max_keys, 1) {
+                    for usage in &usages[..reserved] {
+                        usage.keys.fetch_sub(1, Ordering::Relaxed);
+                    }
+                    return Err(format!("key count quota of {} keys reached for prefix '{}'", quota.max_keys, quota.pattern()));
+                }
+            }
+            *state.pending.lock().unwrap().entry((*key).clone()).or_default() += 1;
+            reservation.keys.push((*key).clone());
+        }
+        Ok(reservation)
+    }
+
+    /// 是否配置了配额，没有配额时写入前不需要检查
+    pub fn is_empty(&self) -> bool {
+        self.0.read().unwrap().usages.is_empty()
+    }
+
+    /// 清零所有前缀的用量，用于 FLUSHALL
+    /// 正在执行的写命令的预留作废，它们之后创建的键按新的用量重新计数
+    pub fn reset(&self) {
+        let mut state = self.0.write().unwrap();
+        for usage in &state.usages {
+            usage.keys.store(0, Ordering::Relaxed);
+            usage.bytes.store(0, Ordering::Relaxed);
+        }
+        state.pending.get_mut().unwrap().clear();
+        state.generation += 1;
+    }
+
+    /// 每个前缀的用量，用于 INFO，格式与 Redis 的 keyspace 部分相同
+    ///
+    /// # Returns
+    /// (`quota:前缀`, `keys=..,max_keys=..,bytes=..,max_bytes=..`) 的列表
+    pub fn info(&self) -> Vec<(String, String)> {
+        self.0
+            .read()
+            .unwrap()
+            .usages
+            .iter()
+            .map(|usage| {
+                (
+                    format!("quota:{}", usage.quota.pattern()),
+                    format!(
+                        "keys={},max_keys={},bytes={},max_bytes={}",
+                        usage.keys.load(Ordering::Relaxed),
+                        usage.quota.max_keys,
+                        usage.bytes.load(Ordering::Relaxed),
+                        usage.quota.max_bytes,
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+/// 从预留中取出一次键的预留
+///
+/// # Returns
+/// 键是否有预留
+fn take_pending(pending: &Mutex<HashMap<Bytes, usize>>, key: &[u8]) -> bool {
+    let mut pending = pending.lock().unwrap();
+    match pending.get_mut(key) {
+        Some(count) => {
+            *count -= 1;
+            if *count == 0 {
+                pending.remove(key);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// 一个写命令预留的配额，命令执行完后释放
+/// 命令创建的键已经在写入时使用了预留的键数，释放时只归还没有创建的键的键数和预留的字节数，
+/// 实际写入的字节数已经由存储按键的新值计入用量
+pub struct QuotaReservation {
+    /// 预留所在的配额
+    quotas: Quotas,
+    /// 预留时配额的版本，配额被替换或清零后不再归还
+    generation: u64,
+    /// 预留了键数的键
+    keys: Vec<Bytes>,
+    /// 预留了字节数的前缀的下标
+    usages: Vec<usize>,
+    /// 每个前缀预留的字节数
+    bytes: usize,
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        let state = self.quotas.0.read().unwrap();
+        if state.generation != self.generation {
+            return;
+        }
+        for &index in &self.usages {
+            state.usages[index].bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+        }
+        for key in &self.keys {
+            if !take_pending(&state.pending, key) {
+                continue;
+            }
+            for usage in state.usages.iter().filter(|usage| key.starts_with(&usage.quota.prefix)) {
+                usage.keys.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
@@ -0,0 +1,667 @@
+//! Redis RDB 文件
+//! 解析 Redis 保存的 RDB 文件，把其中的字符串、列表、集合、哈希表和有序集合转换为 `RedoxValue`，
+//! 用于把已有的 Redis 数据一次迁移到 Redox，或作为 Redis 的副本加载全量同步的快照。支持 Redis 2.x 到 7.x 使用的各种编码
+//! （ziplist、listpack、intset、quicklist 和 LZF 压缩的字符串）；
+//! Redox 只有一个数据库，只导入 0 号数据库，流、模块类型和带字段过期时间的哈希表无法导入。
+//! 反方向把 Redox 的数据写成 Redis 5.0 及以上版本都能加载的 RDB 文件，只使用不压缩的基本编码。
+
+use bytes::Bytes;
+use redox_protocol::{RedoxValue, SortedSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use crate::logging::warning;
+use crate::persistence::LoadedData;
+
+/// 文件开头的魔数，之后是 4 位十进制的格式版本
+const MAGIC: &[u8] = b"REDIS";
+
+/// 从这个版本开始文件末尾有 8 字节的 CRC64 校验和
+const CHECKSUM_VERSION: u32 = 5;
+
+/// 导出时使用的格式版本，Redis 5.0 及以上版本都能加载
+const EXPORT_VERSION: &[u8] = b"0009";
+
+// 操作码
+const OPCODE_SLOT_INFO: u8 = 244;
+const OPCODE_FUNCTION2: u8 = 245;
+const OPCODE_FUNCTION_PRE_GA: u8 = 246;
+const OPCODE_MODULE_AUX: u8 = 247;
+const OPCODE_IDLE: u8 = 248;
+const OPCODE_FREQ: u8 = 249;
+const OPCODE_AUX: u8 = 250;
+const OPCODE_RESIZEDB: u8 = 251;
+const OPCODE_EXPIRETIME_MS: u8 = 252;
+const OPCODE_EXPIRETIME: u8 = 253;
+const OPCODE_SELECTDB: u8 = 254;
+const OPCODE_EOF: u8 = 255;
+
+// 值的类型
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+
+/// quicklist 2 中只保存一个大元素的节点
+const QUICKLIST_NODE_PLAIN: usize = 1;
+
+/// 长度编码的特殊格式：8、16、32 位整数和 LZF 压缩的字符串
+const ENC_INT8: usize = 0;
+const ENC_INT16: usize = 1;
+const ENC_INT32: usize = 2;
+const ENC_LZF: usize = 3;
+
+/// Redis 使用的 CRC64 多项式（Jones），按反射的输入和输出计算
+const CRC64_POLY: u64 = 0x95ac9329ac4bc9b5;
+
+/// 按字节查表计算 CRC64 的表
+static CRC64_TABLE: [u64; 256] = crc64_table();
+
+/// 读取 RDB 文件并转换为 Redox 的数据
+///
+/// # Arguments
+/// * `path` - RDB 文件的路径
+///
+/// # Returns
+/// * `Ok(LoadedData)` - 0 号数据库中的键值和过期时间
+/// * `Err(String)` - 文件无法读取、格式错误、校验和错误或包含无法导入的类型
+pub fn load(path: &str) -> Result<LoadedData, String> {
+    let content = std::fs::read(path).map_err(|e| format!("Error reading RDB file {}: {}", path, e))?;
+    parse(&content).map_err(|e| format!("Invalid RDB file {}: {}", path, e))
+}
+
+/// 把数据写成 RDB 文件，先写临时文件再改名，写入过程中失败不会留下不完整的文件
+/// JSON 文档和时间序列在 Redis 中没有对应的类型，跳过并在日志中给出数量；已经过期的键不导出
+///
+/// # Arguments
+/// * `path` - RDB 文件的路径
+/// * `data` - 键值对
+/// * `expiry` - 键的过期时间（毫秒）
+/// * `now` - 存储的时钟的当前时间（毫秒），这个时间之前过期的键不导出
+///
+/// # Returns
+/// * `Ok(usize)` - 导出的键数
+/// * `Err(String)` - 写文件失败
+pub fn save(path: &str, data: &[(Bytes, Arc<RedoxValue>)], expiry: &HashMap<Bytes, u64>, now: u64) -> Result<usize, String> {
+    let live: Vec<_> = data.iter()
+        .filter(|(key, _)| expiry.get(key).is_none_or(|&when| when > now))
+        .collect();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(EXPORT_VERSION);
+    out.push(OPCODE_AUX);
+    write_string(&mut out, b"redox-ver");
+    write_string(&mut out, env!("CARGO_PKG_VERSION").as_bytes());
+    out.push(OPCODE_SELECTDB);
+    write_length(&mut out, 0);
+    out.push(OPCODE_RESIZEDB);
+    write_length(&mut out, live.len());
+    write_length(&mut out, live.iter().filter(|(key, _)| expiry.contains_key(key)).count());
+
+    let mut exported = 0;
+    let mut skipped = 0;
+    for (key, value) in live {
+        let mut entry = Vec::new();
+        if !write_value(&mut entry, key, value) {
+            skipped += 1;
+            continue;
+        }
+        if let Some(&when) = expiry.get(key) {
+            out.push(OPCODE_EXPIRETIME_MS);
+            out.extend_from_slice(&when.to_le_bytes());
+        }
+        out.extend_from_slice(&entry);
+        exported += 1;
+    }
+    out.push(OPCODE_EOF);
+    let checksum = crc64(0, &out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+
+    let temp_path = format!("{}.temp", path);
+    std::fs::write(&temp_path, &out)
+        .and_then(|_| std::fs::rename(&temp_path, path))
+        .map_err(|e| format!("Error writing RDB file {}: {}", path, e))?;
+    if skipped > 0 {
+        warning!("Skipped {} JSON document(s) and time series, Redis has no equivalent types", skipped);
+    }
+    Ok(exported)
+}
+
+/// 写入一个键值对，类型字节在最前面
+///
+/// # Returns
+/// 值在 Redis 中是否有对应的类型，没有时不写入任何内容
+fn write_value(out: &mut Vec<u8>, key: &[u8], value: &RedoxValue) -> bool {
+    match value {
+        RedoxValue::String(s) => {
+            out.push(TYPE_STRING);
+            write_string(out, key);
+            write_string(out, s);
+        }
+        RedoxValue::List(list) => {
+            out.push(TYPE_LIST);
+            write_string(out, key);
+            write_length(out, list.len());
+            for item in list {
+                write_string(out, item);
+            }
+        }
+        RedoxValue::Set(set) => {
+            out.push(TYPE_SET);
+            write_string(out, key);
+            write_length(out, set.len());
+            for member in set {
+                write_string(out, member);
+            }
+        }
+        RedoxValue::Hash(hash) => {
+            out.push(TYPE_HASH);
+            write_string(out, key);
+            write_length(out, hash.len());
+            for (field, value) in hash {
+                write_string(out, field);
+                write_string(out, value);
+            }
+        }
+        RedoxValue::SortedSet(zset) => {
+            out.push(TYPE_ZSET_2);
+            write_string(out, key);
+            write_length(out, zset.len());
+            for (member, score) in zset.iter() {
+                write_string(out, member);
+                out.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+        RedoxValue::Json(_) | RedoxValue::TimeSeries(_) => return false,
+    }
+    true
+}
+
+/// 写入长度编码
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend_from_slice(&[0x40 | (len >> 8) as u8, len as u8]);
+    } else if let Ok(len) = u32::try_from(len) {
+        out.push(0x80);
+        out.extend_from_slice(&len.to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+}
+
+/// 写入不压缩的字符串
+fn write_string(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_length(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+/// 解析 RDB 文件的内容，复制 Redis 时也用于解析全量同步收到的快照
+pub fn parse(content: &[u8]) -> Result<LoadedData, String> {
+    let mut reader = Reader::new(content);
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err("not an RDB file".to_string());
+    }
+    let version = std::str::from_utf8(reader.bytes(4)?)
+        .ok()
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or("invalid RDB version")?;
+
+    let mut loaded = LoadedData::default();
+    let mut db = 0;
+    let mut expire_ms = None;
+    let mut skipped = 0;
+    let mut functions = 0;
+    loop {
+        match reader.u8()? {
+            OPCODE_EOF => break,
+            OPCODE_SELECTDB => db = reader.length()?,
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OPCODE_EXPIRETIME => {
+                expire_ms = Some(u32::from_le_bytes(reader.array()?) as u64 * 1000);
+            }
+            OPCODE_EXPIRETIME_MS => {
+                expire_ms = Some(u64::from_le_bytes(reader.array()?));
+            }
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            OPCODE_SLOT_INFO => {
+                reader.length()?;
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_FUNCTION2 => {
+                // Redis 的函数使用 Lua 编写，不能在 Redox 中运行
+                reader.string()?;
+                functions += 1;
+            }
+            OPCODE_FUNCTION_PRE_GA | OPCODE_MODULE_AUX => {
+                return Err("module data and pre-release function libraries are not supported".to_string());
+            }
+            value_type => {
+                let key = reader.string()?;
+                let value = read_value(&mut reader, value_type)
+                    .map_err(|e| format!("key '{}': {}", String::from_utf8_lossy(&key), e))?;
+                if db == 0 {
+                    if let Some(when) = expire_ms {
+                        loaded.expiry.insert(key.clone(), when);
+                    }
+                    loaded.data.insert(key, value);
+                } else {
+                    skipped += 1;
+                }
+                expire_ms = None;
+            }
+        }
+    }
+
+    if version >= CHECKSUM_VERSION {
+        let end = reader.pos;
+        let expected = u64::from_le_bytes(reader.array()?);
+        // 校验和为 0 表示保存时关闭了校验（rdbchecksum no）
+        if expected != 0 && crc64(0, &content[..end]) != expected {
+            return Err("checksum mismatch, the file is truncated or corrupted".to_string());
+        }
+    }
+    if skipped > 0 {
+        warning!("Skipped {} key(s) in databases other than 0, Redox has a single keyspace", skipped);
+    }
+    if functions > 0 {
+        warning!("Skipped {} Lua function librar(ies), Redox functions are written in Rhai", functions);
+    }
+    Ok(loaded)
+}
+
+/// 读取一个值
+fn read_value(reader: &mut Reader, value_type: u8) -> Result<RedoxValue, String> {
+    Ok(match value_type {
+        TYPE_STRING => RedoxValue::String(reader.string()?),
+        TYPE_LIST => {
+            let len = reader.length()?;
+            RedoxValue::List((0..len).map(|_| reader.string()).collect::<Result<_, _>>()?)
+        }
+        TYPE_SET => {
+            let len = reader.length()?;
+            RedoxValue::Set((0..len).map(|_| reader.string()).collect::<Result<_, _>>()?)
+        }
+        TYPE_ZSET | TYPE_ZSET_2 => {
+            let len = reader.length()?;
+            let mut zset = SortedSet::new();
+            for _ in 0..len {
+                let member = reader.string()?;
+                let score = if value_type == TYPE_ZSET_2 {
+                    f64::from_le_bytes(reader.array()?)
+                } else {
+                    reader.string_double()?
+                };
+                zset.insert(member, score);
+            }
+            RedoxValue::SortedSet(zset)
+        }
+        TYPE_HASH => {
+            let len = reader.length()?;
+            // 长度来自文件（或上游的复制流），不能据此预先分配
+            let mut hash = HashMap::new();
+            for _ in 0..len {
+                let field = reader.string()?;
+                hash.insert(field, reader.string()?);
+            }
+            RedoxValue::Hash(hash)
+        }
+        TYPE_LIST_ZIPLIST => RedoxValue::List(ziplist(&reader.string()?)?.into()),
+        TYPE_LIST_QUICKLIST => {
+            let nodes = reader.length()?;
+            let mut list = VecDeque::new();
+            for _ in 0..nodes {
+                list.extend(ziplist(&reader.string()?)?);
+            }
+            RedoxValue::List(list)
+        }
+        TYPE_LIST_QUICKLIST_2 => {
+            let nodes = reader.length()?;
+            let mut list = VecDeque::new();
+            for _ in 0..nodes {
+                let container = reader.length()?;
+                let node = reader.string()?;
+                if container == QUICKLIST_NODE_PLAIN {
+                    list.push_back(node);
+                } else {
+                    list.extend(listpack(&node)?);
+                }
+            }
+            RedoxValue::List(list)
+        }
+        TYPE_SET_INTSET => RedoxValue::Set(intset(&reader.string()?)?.into_iter().collect()),
+        TYPE_SET_LISTPACK => RedoxValue::Set(listpack(&reader.string()?)?.into_iter().collect::<HashSet<_>>()),
+        TYPE_HASH_ZIPLIST => RedoxValue::Hash(pairs(ziplist(&reader.string()?)?)?.collect()),
+        TYPE_HASH_LISTPACK => RedoxValue::Hash(pairs(listpack(&reader.string()?)?)?.collect()),
+        TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+            let blob = reader.string()?;
+            let entries = if value_type == TYPE_ZSET_ZIPLIST { ziplist(&blob)? } else { listpack(&blob)? };
+            let mut zset = SortedSet::new();
+            for (member, score) in pairs(entries)? {
+                zset.insert(member, parse_double(&score)?);
+            }
+            RedoxValue::SortedSet(zset)
+        }
+        other => {
+            return Err(format!(
+                "unsupported value type {} (streams, module types and hashes with field expiration cannot be imported)",
+                other
+            ))
+        }
+    })
+}
+
+/// 把交替排列的字段和值组成对
+fn pairs(entries: Vec<Bytes>) -> Result<impl Iterator<Item = (Bytes, Bytes)>, String> {
+    if !entries.len().is_multiple_of(2) {
+        return Err("odd number of entries in a hash or sorted set".to_string());
+    }
+    let mut entries = entries.into_iter();
+    Ok(std::iter::from_fn(move || Some((entries.next()?, entries.next()?))))
+}
+
+/// 解析以字符串保存的分数
+fn parse_double(bytes: &[u8]) -> Result<f64, String> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or_else(|| format!("invalid score '{}'", String::from_utf8_lossy(bytes)))
+}
+
+/// 整数元素转换为十进制字符串，与 Redis 读取时的结果相同
+fn integer(value: i64) -> Bytes {
+    Bytes::from(value.to_string())
+}
+
+/// 解析 ziplist 中的所有元素
+fn ziplist(blob: &[u8]) -> Result<Vec<Bytes>, String> {
+    let mut reader = Reader::new(blob);
+    // zlbytes（4 字节）、zltail（4 字节）、zllen（2 字节）
+    reader.bytes(10)?;
+    let mut entries = Vec::new();
+    loop {
+        // 每个元素以前一个元素的长度开头，254 表示之后 4 字节为长度，255 为结束标记
+        match reader.u8()? {
+            0xff => return Ok(entries),
+            0xfe => {
+                reader.bytes(4)?;
+            }
+            _ => {}
+        }
+        let encoding = reader.u8()?;
+        let entry = match encoding >> 6 {
+            0 => Bytes::copy_from_slice(reader.bytes((encoding & 0x3f) as usize)?),
+            1 => {
+                let len = ((encoding & 0x3f) as usize) << 8 | reader.u8()? as usize;
+                Bytes::copy_from_slice(reader.bytes(len)?)
+            }
+            2 => {
+                let len = u32::from_be_bytes(reader.array()?) as usize;
+                Bytes::copy_from_slice(reader.bytes(len)?)
+            }
+            _ => integer(match encoding {
+                0xc0 => i16::from_le_bytes(reader.array()?) as i64,
+                0xd0 => i32::from_le_bytes(reader.array()?) as i64,
+                0xe0 => i64::from_le_bytes(reader.array()?),
+                0xf0 => int24(reader.array()?),
+                0xfe => reader.u8()? as i8 as i64,
+                // 0xf1 到 0xfd 直接表示 0 到 12
+                0xf1..=0xfd => (encoding & 0x0f) as i64 - 1,
+                _ => return Err(format!("invalid ziplist encoding {:#04x}", encoding)),
+            }),
+        };
+        entries.push(entry);
+    }
+}
+
+/// 解析 listpack 中的所有元素
+fn listpack(blob: &[u8]) -> Result<Vec<Bytes>, String> {
+    let mut reader = Reader::new(blob);
+    // 总字节数（4 字节）、元素个数（2 字节）
+    reader.bytes(6)?;
+    let mut entries = Vec::new();
+    loop {
+        let encoding = reader.u8()?;
+        let (entry, size) = if encoding == 0xff {
+            return Ok(entries);
+        } else if encoding & 0x80 == 0 {
+            (integer((encoding & 0x7f) as i64), 1)
+        } else if encoding & 0xc0 == 0x80 {
+            let len = (encoding & 0x3f) as usize;
+            (Bytes::copy_from_slice(reader.bytes(len)?), 1 + len)
+        } else if encoding & 0xe0 == 0xc0 {
+            // 13 位有符号整数
+            let value = ((encoding & 0x1f) as i64) << 8 | reader.u8()? as i64;
+            (integer(if value >= 1 << 12 { value - (1 << 13) } else { value }), 2)
+        } else if encoding & 0xf0 == 0xe0 {
+            let len = ((encoding & 0x0f) as usize) << 8 | reader.u8()? as usize;
+            (Bytes::copy_from_slice(reader.bytes(len)?), 2 + len)
+        } else {
+            match encoding {
+                0xf0 => {
+                    let len = u32::from_le_bytes(reader.array()?) as usize;
+                    (Bytes::copy_from_slice(reader.bytes(len)?), 5 + len)
+                }
+                0xf1 => (integer(i16::from_le_bytes(reader.array()?) as i64), 3),
+                0xf2 => (integer(int24(reader.array()?)), 4),
+                0xf3 => (integer(i32::from_le_bytes(reader.array()?) as i64), 5),
+                0xf4 => (integer(i64::from_le_bytes(reader.array()?)), 9),
+                _ => return Err(format!("invalid listpack encoding {:#04x}", encoding)),
+            }
+        };
+        // 每个元素以编码和内容的总长度结尾，用于反向遍历
+        reader.bytes(backlen_size(size))?;
+        entries.push(entry);
+    }
+}
+
+/// listpack 中元素末尾的反向长度占用的字节数
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// 解析 intset 中的所有整数
+fn intset(blob: &[u8]) -> Result<Vec<Bytes>, String> {
+    let mut reader = Reader::new(blob);
+    let width = u32::from_le_bytes(reader.array()?) as usize;
+    let len = u32::from_le_bytes(reader.array()?) as usize;
+    (0..len)
+        .map(|_| {
+            Ok(integer(match width {
+                2 => i16::from_le_bytes(reader.array()?) as i64,
+                4 => i32::from_le_bytes(reader.array()?) as i64,
+                8 => i64::from_le_bytes(reader.array()?),
+                _ => return Err(format!("invalid intset encoding {}", width)),
+            }))
+        })
+        .collect()
+}
+
+/// 小端的 24 位有符号整数
+fn int24(bytes: [u8; 3]) -> i64 {
+    (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as i64
+}
+
+/// 解压 LZF 压缩的数据
+///
+/// # Arguments
+/// * `input` - 压缩的数据
+/// * `expected` - 解压后的长度
+fn lzf_decompress(input: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    const CORRUPT: &str = "corrupted LZF data";
+    // 有效的数据解压后不会比压缩的数据短，按压缩的长度预先分配，不信任文件中的解压长度
+    let mut output = Vec::with_capacity(expected.min(input.len()));
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            // 之后的 ctrl + 1 个字节原样复制
+            let literal = input.get(i..i + ctrl + 1).ok_or(CORRUPT)?;
+            if output.len() + literal.len() > expected {
+                return Err(CORRUPT.to_string());
+            }
+            output.extend_from_slice(literal);
+            i += ctrl + 1;
+        } else {
+            // 从已解压的数据中向前 offset 处复制 len + 2 个字节，两段可以重叠
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += *input.get(i).ok_or(CORRUPT)? as usize;
+                i += 1;
+            }
+            let offset = ((ctrl & 0x1f) << 8) + *input.get(i).ok_or(CORRUPT)? as usize + 1;
+            i += 1;
+            let start = output.len().checked_sub(offset).ok_or(CORRUPT)?;
+            if output.len() + len + 2 > expected {
+                return Err(CORRUPT.to_string());
+            }
+            for k in start..start + len + 2 {
+                output.push(output[k]);
+            }
+        }
+    }
+    if output.len() != expected {
+        return Err(CORRUPT.to_string());
+    }
+    Ok(output)
+}
+
+/// 生成 CRC64 的查找表
+const fn crc64_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ CRC64_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// 在 `crc` 的基础上继续计算数据的 CRC64，与 Redis 的 crc64 函数相同
+fn crc64(crc: u64, data: &[u8]) -> u64 {
+    data.iter()
+        .fold(crc, |crc, &byte| CRC64_TABLE[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// 按 RDB 的编码顺序读取数据
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// 读取 n 个字节
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).ok_or("unexpected end of data")?;
+        let bytes = self.data.get(self.pos..end).ok_or("unexpected end of data")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// 读取定长的字节数组，用于整数
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// 读取长度编码
+    ///
+    /// # Returns
+    /// (长度, 是否为特殊格式)，特殊格式时长度为 `ENC_*` 中的一个
+    fn encoded_length(&mut self) -> Result<(usize, bool), String> {
+        let first = self.u8()?;
+        Ok(match first >> 6 {
+            0 => ((first & 0x3f) as usize, false),
+            1 => (((first & 0x3f) as usize) << 8 | self.u8()? as usize, false),
+            2 => match first {
+                0x80 => (u32::from_be_bytes(self.array()?) as usize, false),
+                0x81 => (u64::from_be_bytes(self.array()?) as usize, false),
+                _ => return Err(format!("invalid length encoding {:#04x}", first)),
+            },
+            _ => ((first & 0x3f) as usize, true),
+        })
+    }
+
+    /// 读取长度，不能是特殊格式
+    fn length(&mut self) -> Result<usize, String> {
+        match self.encoded_length()? {
+            (len, false) => Ok(len),
+            (_, true) => Err("unexpected encoded length".to_string()),
+        }
+    }
+
+    /// 读取字符串，整数格式转换为十进制字符串，LZF 格式解压
+    fn string(&mut self) -> Result<Bytes, String> {
+        let (len, encoded) = self.encoded_length()?;
+        if !encoded {
+            return Ok(Bytes::copy_from_slice(self.bytes(len)?));
+        }
+        Ok(match len {
+            ENC_INT8 => integer(self.u8()? as i8 as i64),
+            ENC_INT16 => integer(i16::from_le_bytes(self.array()?) as i64),
+            ENC_INT32 => integer(i32::from_le_bytes(self.array()?) as i64),
+            ENC_LZF => {
+                let compressed_len = self.length()?;
+                let len = self.length()?;
+                Bytes::from(lzf_decompress(self.bytes(compressed_len)?, len)?)
+            }
+            other => return Err(format!("invalid string encoding {}", other)),
+        })
+    }
+
+    /// 读取旧格式有序集合中以字符串保存的分数，253、254、255 分别表示 NaN、正无穷和负无穷
+    fn string_double(&mut self) -> Result<f64, String> {
+        Ok(match self.u8()? {
+            253 => f64::NAN,
+            254 => f64::INFINITY,
+            255 => f64::NEG_INFINITY,
+            len => parse_double(self.bytes(len as usize)?)?,
+        })
+    }
+}
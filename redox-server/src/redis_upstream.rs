@@ -0,0 +1,367 @@
+//! 复制 Redis 实例
+//! master-type 为 redis 时，REPLICAOF 按 Redis 副本的握手连接 Redis：依次发送 AUTH、REPLCONF 和 PSYNC，
+//! 主节点回复 FULLRESYNC 后发送 RDB 格式的快照（磁盘复制以 `$<长度>` 开头，无盘复制以 40 字节的随机标记结尾），
+//! 之后的复制流是 RESP 格式的写命令，逐个转换为 Redox 的命令执行，偏移量按复制流的字节数计算，与 Redis 相同。
+//! 连接断开后用 PSYNC 请求从已应用的偏移量继续，主节点的积压缓冲区中还有这些数据时不需要重新全量同步。
+//! 用于把正在运行的 Redis 迁移到 Redox：复制追上后把客户端切换到 Redox，再执行 REPLICAOF NO ONE。
+
+use crate::clock::now_ms;
+use crate::commands;
+use crate::logging::{notice, warning};
+use crate::rdb;
+use crate::replication::{invalid_data, UpstreamLink, ACK_INTERVAL, CONNECT_TIMEOUT, REPL_TIMEOUT};
+use crate::storage::{Entry, Storage};
+use bytes::{Buf, Bytes, BytesMut};
+use redox_protocol::codec::RequestLimits;
+use redox_protocol::{resp, Protocol, RedoxValue, Response};
+use std::collections::HashSet;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time;
+
+/// 无盘复制的快照前缀，之后是标记快照结束的 40 字节
+const EOF_PREFIX: &str = "EOF:";
+
+/// 无盘复制的结束标记的长度
+const EOF_MARK_LEN: usize = 40;
+
+/// 复制流中不需要执行的命令：心跳、事务的边界和发布订阅的消息（只发送给 Redis 主节点上的订阅者）
+const IGNORED: &[&str] = &["PING", "MULTI", "EXEC", "PUBLISH", "SPUBLISH"];
+
+/// 可以带多个元素的命令和每个元素的参数个数，Redox 的对应命令每次只处理一个元素，按元素拆开执行
+const VARIADIC: &[(&str, usize)] = &[
+    ("LPUSH", 1),
+    ("RPUSH", 1),
+    ("SADD", 1),
+    ("SREM", 1),
+    ("HDEL", 1),
+    ("ZREM", 1),
+    ("HMSET", 2),
+    ("ZADD", 2),
+];
+
+/// 断开后请求部分同步所需的状态，在同一个主节点的多次连接之间保留
+#[derive(Default)]
+pub struct Resume {
+    /// 主节点的复制 ID，还没有完成全量同步时为 None
+    replid: Option<String>,
+    /// 复制流当前选择的数据库
+    db: u64,
+}
+
+/// 连接 Redis 主节点，同步后持续应用复制流中的写命令
+///
+/// # Arguments
+/// * `storage` - 存储实例
+/// * `host` / `port` - 主节点的地址
+/// * `link` - 连接状态，偏移量和部分同步的状态保存在这里
+/// * `auth` - 认证的用户名和密码，None 表示主节点不需要认证
+/// * `listening_port` - 向主节点报告的本身监听的端口
+///
+/// # Returns
+/// 只在连接断开或出错时返回
+pub async fn sync_from(
+    storage: &Storage,
+    host: &str,
+    port: u16,
+    link: &UpstreamLink,
+    auth: Option<(Option<String>, String)>,
+    listening_port: u16,
+) -> io::Result<()> {
+    let stream = time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))??;
+    stream.set_nodelay(true)?;
+    let (reader, mut writer) = stream.into_split();
+    let mut conn = Connection { reader, buf: BytesMut::new(), link, deadline: time::Instant::now() + CONNECT_TIMEOUT };
+
+    if let Some((username, password)) = auth {
+        let mut args = vec!["AUTH".to_string()];
+        args.extend(username);
+        args.push(password);
+        expect_ok(&mut conn, &mut writer, "AUTH", &args).await?;
+    }
+    let listening_port = listening_port.to_string();
+    expect_ok(&mut conn, &mut writer, "REPLCONF", &["REPLCONF", "listening-port", &listening_port]).await?;
+    // 旧版本的 Redis 不认识的能力会被忽略，回复错误时同样继续
+    write_command(&mut writer, &["REPLCONF", "capa", "eof", "capa", "psync2"]).await?;
+    conn.line().await?;
+
+    let (replid, offset) = {
+        let resume = link.resume.lock().unwrap();
+        let offset = link.offset.load(Ordering::Relaxed) + 1;
+        match &resume.replid {
+            Some(replid) => (replid.clone(), offset.to_string()),
+            None => ("?".to_string(), "-1".to_string()),
+        }
+    };
+    write_command(&mut writer, &["PSYNC", &replid, &offset]).await?;
+    let reply = conn.line().await?;
+    let words: Vec<&str> = reply.split_whitespace().collect();
+    match words[..] {
+        ["+FULLRESYNC", replid, offset] => {
+            let offset = offset.parse::<u64>().map_err(|_| invalid_data(format!("unexpected reply to PSYNC: {}", reply)))?;
+            full_sync(storage, host, port, &mut conn).await?;
+            *link.resume.lock().unwrap() = Resume { replid: Some(replid.to_string()), db: 0 };
+            link.offset.store(offset, Ordering::Relaxed);
+        }
+        ["+CONTINUE", ..] => {
+            // 主节点故障转移后复制 ID 会改变，之后用新的 ID 请求部分同步
+            if let Some(replid) = words.get(1) {
+                link.resume.lock().unwrap().replid = Some(replid.to_string());
+            }
+            notice!("Partial resync with Redis at {}:{} accepted", host, port);
+        }
+        _ if reply.starts_with('-') => return Err(io::Error::other(format!("PSYNC failed: {}", &reply[1..]))),
+        _ => return Err(invalid_data(format!("unexpected reply to PSYNC: {}", reply))),
+    }
+    link.up.store(true, Ordering::Relaxed);
+
+    // 无盘复制时主节点收到第一次确认后才开始发送复制流，所以第一次确认立即发送
+    let mut applier = Applier { storage, db: link.resume.lock().unwrap().db, warned: HashSet::new() };
+    let limits = RequestLimits::default();
+    let mut requests = resp::RequestParser::new();
+    let mut ack = time::interval(ACK_INTERVAL);
+    ack.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    loop {
+        while let Some((args, len)) = requests.parse(&conn.buf, &limits).map_err(invalid_data)? {
+            conn.buf.advance(len);
+            if args.is_empty() {
+                continue;
+            }
+            // 确认的偏移量不包括 GETACK 本身，与 Redis 的副本相同
+            if is_getack(&args) {
+                write_ack(&mut writer, link).await?;
+            } else {
+                applier.apply(args).await;
+            }
+            link.offset.fetch_add(len as u64, Ordering::Relaxed);
+            link.resume.lock().unwrap().db = applier.db;
+        }
+        tokio::select! {
+            read = conn.fill() => read?,
+            _ = ack.tick() => write_ack(&mut writer, link).await?,
+        }
+    }
+}
+
+/// 接收 RDB 格式的快照并替换本身的所有数据
+async fn full_sync(storage: &Storage, host: &str, port: u16, conn: &mut Connection<'_>) -> io::Result<()> {
+    conn.link.syncing.store(true, Ordering::Relaxed);
+    notice!("Full sync with Redis at {}:{} started", host, port);
+    let started = Instant::now();
+    // 主节点生成快照期间只发送换行保持连接，快照可能很久之后才开始
+    conn.deadline = time::Instant::now() + REPL_TIMEOUT;
+    let header = conn.line().await?;
+    let payload = match header.strip_prefix('$') {
+        Some(mark) if mark.starts_with(EOF_PREFIX) && mark.len() == EOF_PREFIX.len() + EOF_MARK_LEN => {
+            let mark = mark.as_bytes()[EOF_PREFIX.len()..].to_vec();
+            while !conn.buf.ends_with(&mark) {
+                conn.fill().await?;
+            }
+            let mut payload = conn.buf.split();
+            payload.truncate(payload.len() - EOF_MARK_LEN);
+            payload
+        }
+        Some(len) => {
+            let len = len.parse::<usize>().map_err(|_| invalid_data(format!("invalid RDB payload header: {}", header)))?;
+            while conn.buf.len() < len {
+                conn.fill().await?;
+            }
+            conn.buf.split_to(len)
+        }
+        None => return Err(invalid_data(format!("invalid RDB payload header: {}", header))),
+    };
+    let size = payload.len();
+    let loaded = tokio::task::spawn_blocking(move || rdb::parse(&payload))
+        .await
+        .map_err(io::Error::other)?
+        .map_err(invalid_data)?;
+    // 全量同步替换副本原有的所有数据
+    storage.flushall(true).await;
+    let count = storage.import(loaded).await;
+    conn.link.syncing.store(false, Ordering::Relaxed);
+    notice!(
+        "Full sync with Redis at {}:{} finished: loaded {} key(s) from {} bytes in {} ms",
+        host, port, count, size, started.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+/// 是否是主节点请求确认的 REPLCONF GETACK
+fn is_getack(args: &[Bytes]) -> bool {
+    matches!(args, [name, sub, ..] if name.eq_ignore_ascii_case(b"REPLCONF") && sub.eq_ignore_ascii_case(b"GETACK"))
+}
+
+/// 把复制流中的命令转换为 Redox 的命令执行
+struct Applier<'a> {
+    /// 存储实例
+    storage: &'a Storage,
+    /// 复制流当前选择的数据库，只执行 0 号数据库的命令
+    db: u64,
+    /// 已经输出过警告的命令，每种命令只警告一次
+    warned: HashSet<String>,
+}
+
+impl Applier<'_> {
+    /// 执行一个命令，无法执行的命令输出警告后跳过
+    async fn apply(&mut self, args: Vec<Bytes>) {
+        let name = String::from_utf8_lossy(&args[0]).to_uppercase();
+        let result = match name.as_str() {
+            _ if IGNORED.contains(&name.as_str()) || name == "REPLCONF" => Ok(()),
+            "SELECT" => match std::str::from_utf8(args.get(1).map_or(&[][..], |db| db)).ok().and_then(|db| db.parse().ok()) {
+                Some(db) => {
+                    self.db = db;
+                    Ok(())
+                }
+                None => Err("invalid database".to_string()),
+            },
+            // FLUSHALL 也清空 0 号数据库，在检查数据库之前执行
+            "FLUSHALL" => self.execute(&args).await,
+            _ if self.db != 0 => Ok(()),
+            "FLUSHDB" => {
+                self.storage.flushall(false).await;
+                Ok(())
+            }
+            "SET" | "SETEX" | "PSETEX" => self.set(&name, &args).await,
+            _ => match VARIADIC.iter().find(|(command, _)| *command == name) {
+                Some(&(_, group)) => self.execute_each(&name, &args, group).await,
+                None => self.execute(&args).await,
+            },
+        };
+        if let Err(e) = result {
+            if self.warned.insert(name.clone()) {
+                warning!("Can't apply '{}' from the Redis primary, the keys it modifies may be out of date: {}", name, e);
+            }
+        }
+    }
+
+    /// 解析并执行一个命令
+    async fn execute(&self, args: &[Bytes]) -> Result<(), String> {
+        let cmd = Protocol::decode_args(args)?;
+        match commands::apply(self.storage, cmd).await {
+            Response::Error(e) => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// 把带多个元素的命令按元素拆开执行，HMSET 按 HSET 执行
+    async fn execute_each(&self, name: &str, args: &[Bytes], group: usize) -> Result<(), String> {
+        let items = args.get(2..).unwrap_or_default();
+        if items.is_empty() || items.len() % group != 0 {
+            return self.execute(args).await;
+        }
+        let name = Bytes::from(if name == "HMSET" { "HSET" } else { name }.to_string());
+        for item in items.chunks(group) {
+            let mut single = vec![name.clone(), args[1].clone()];
+            single.extend_from_slice(item);
+            self.execute(&single).await?;
+        }
+        Ok(())
+    }
+
+    /// 执行 SET、SETEX 和 PSETEX：替换值并设置或清除过期时间
+    /// Redis 7 把 SET 的 EX/PX/EXAT 转换为 PXAT 后发送给副本，旧版本发送原来的选项，都转换为绝对时间；
+    /// NX、XX 和 GET 不影响结果，主节点只发送实际执行了的写入
+    async fn set(&self, name: &str, args: &[Bytes]) -> Result<(), String> {
+        let number = |arg: &Bytes| std::str::from_utf8(arg).ok().and_then(|n| n.parse::<u64>().ok()).ok_or("value is not an integer or out of range");
+        let now = self.storage.now_ms();
+        let (key, value, expire_at) = match (name, args) {
+            ("SETEX", [_, key, seconds, value]) => (key, value, Some(now + number(seconds)? * 1000)),
+            ("PSETEX", [_, key, milliseconds, value]) => (key, value, Some(now + number(milliseconds)?)),
+            ("SET", [_, key, value, options @ ..]) => {
+                let mut expire_at = None;
+                let mut options = options.iter();
+                while let Some(option) = options.next() {
+                    let option = String::from_utf8_lossy(option).to_uppercase();
+                    let mut argument = || options.next().ok_or("syntax error").and_then(number);
+                    expire_at = match option.as_str() {
+                        "NX" | "XX" | "GET" => expire_at,
+                        "KEEPTTL" => self.storage.entry(key).await.and_then(|entry| entry.expire_at),
+                        "EX" => Some(now + argument()? * 1000),
+                        "PX" => Some(now + argument()?),
+                        "EXAT" => Some(argument()? * 1000),
+                        "PXAT" => Some(argument()?),
+                        _ => return Err("syntax error".to_string()),
+                    };
+                }
+                (key, value, expire_at)
+            }
+            _ => return Err(format!("wrong number of arguments for '{}' command", name.to_lowercase())),
+        };
+        let entry = Entry { value: Arc::new(RedoxValue::String(value.clone())), expire_at, field_expires: Vec::new() };
+        self.storage.set_entry(key.clone(), entry).await;
+        Ok(())
+    }
+}
+
+/// 与主节点的连接的读取端
+struct Connection<'a> {
+    /// 连接的读取端
+    reader: OwnedReadHalf,
+    /// 已读取但尚未处理的数据
+    buf: BytesMut,
+    /// 连接状态，收到数据时更新最后一次收到数据的时间
+    link: &'a UpstreamLink,
+    /// 超过这个时间没有收到数据时断开，每次收到数据后延后
+    deadline: time::Instant,
+}
+
+impl Connection<'_> {
+    /// 从连接读取更多数据
+    async fn fill(&mut self) -> io::Result<()> {
+        let read = time::timeout_at(self.deadline, self.reader.read_buf(&mut self.buf))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timeout, no data from the primary"))??;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the primary"));
+        }
+        self.deadline = time::Instant::now() + REPL_TIMEOUT;
+        self.link.last_io.store(now_ms(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 读取一行回复，跳过主节点保持连接发送的空行
+    async fn line(&mut self) -> io::Result<String> {
+        loop {
+            while self.buf.first() == Some(&b'\n') {
+                self.buf.advance(1);
+            }
+            if let Some(end) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line = self.buf.split_to(end + 2);
+                return Ok(String::from_utf8_lossy(&line[..end]).into_owned());
+            }
+            self.fill().await?;
+        }
+    }
+}
+
+/// 发送握手命令，回复不是 +OK 时返回错误
+async fn expect_ok(conn: &mut Connection<'_>, writer: &mut OwnedWriteHalf, command: &str, args: &[impl AsRef<str>]) -> io::Result<()> {
+    write_command(writer, args).await?;
+    match conn.line().await? {
+        reply if reply == "+OK" => Ok(()),
+        reply => Err(io::Error::other(format!("{} failed: {}", command, reply.trim_start_matches(['-', '+'])))),
+    }
+}
+
+/// 以 RESP 数组发送一个命令
+async fn write_command(writer: &mut OwnedWriteHalf, args: &[impl AsRef<str>]) -> io::Result<()> {
+    let mut out = format!("*{}\r\n", args.len());
+    for arg in args {
+        let arg = arg.as_ref();
+        out.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    writer.write_all(out.as_bytes()).await
+}
+
+/// 向主节点确认已应用的偏移量
+async fn write_ack(writer: &mut OwnedWriteHalf, link: &UpstreamLink) -> io::Result<()> {
+    let offset = link.offset.load(Ordering::Relaxed).to_string();
+    write_command(writer, &["REPLCONF", "ACK", &offset]).await
+}
@@ -0,0 +1,803 @@
+//! 主从复制
+//! 副本通过 REPLICAOF 连接主节点并发送 SYNC：主节点先发送所有数据的快照（全量同步），之后持续发送被修改的键。
+//! 复制是异步的，主节点不等待副本收到修改就回复客户端。
+//! 主节点作为存储的观察者记录被修改的键，由后台任务读取键的当前状态，编码为一帧后发给所有副本；
+//! 同一个键在发送前被修改多次时副本直接得到最新的状态，键被删除、过期或淘汰时发送删除。
+//! SYNC 的回复之后，连接上的每一帧都是 4 字节大端序的长度加上 bincode 序列化的 `Message`。
+//! 副本每秒以及收到 `GetAck` 时在同一个连接上回复已应用的复制偏移量，WAIT 据此等待副本确认之前的写入。
+//! 副本应用的修改同样通知观察者，所以副本也可以再带自己的副本。
+//! 连接断开后副本重新连接并重新全量同步；接收修改跟不上的副本会被断开，之后同样重新同步。
+//! master-type 为 redis 时复制的是 Redis 实例，连接主节点后的同步由 `redis_upstream` 模块完成。
+
+use crate::config::SharedConfig;
+use crate::logging::{notice, warning};
+use crate::observer::StorageObserver;
+use crate::persistence;
+use crate::redis_upstream::{self, Resume};
+use crate::clock::now_ms;
+use crate::storage::{Entry, Storage};
+use crate::task::spawn_named;
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use redox_protocol::codec::ClientCodec;
+use redox_protocol::compact::{self, BinaryFormat, LEN_PREFIX, MAX_FRAME_LEN};
+use redox_protocol::{Command, RedoxValue, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::time;
+use tokio_util::codec::{Framed, FramedParts};
+use tokio_util::sync::CancellationToken;
+
+/// 每个副本最多积压的修改帧数，超过时断开副本，让它重新全量同步
+const REPLICA_BACKLOG: usize = 64 * 1024;
+
+/// 全量同步时快照按这个大小分段发送
+const SNAPSHOT_CHUNK: usize = 64 * 1024;
+
+/// 全量同步时最多缓冲的快照分段数，连接写得慢时序列化快照的线程等待
+const SNAPSHOT_QUEUE: usize = 16;
+
+/// 没有修改时主节点发送心跳的间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 副本超过这个时间没有收到主节点的任何数据时断开重连
+pub const REPL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 副本定期回复复制偏移量的间隔
+pub const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 副本连接主节点和等待握手回复的超时时间
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 副本第一次重连前等待的时间，之后每次失败加倍
+pub const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+
+/// 副本重连前等待的最长时间
+pub const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+
+/// 复制的主节点的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MasterType {
+    /// Redox 实例，通过 SYNC 接收快照和按键发送的修改
+    Redox,
+    /// Redis 实例，通过 PSYNC 接收 RDB 快照和写命令组成的复制流，用于从 Redis 迁移
+    Redis,
+}
+
+impl MasterType {
+    /// 所有种类
+    const ALL: [MasterType; 2] = [MasterType::Redox, MasterType::Redis];
+
+    /// 解析种类名称（不区分大小写）
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// 种类名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MasterType::Redox => "redox",
+            MasterType::Redis => "redis",
+        }
+    }
+}
+
+/// 复制连接上的消息，除 `Ack` 外都由主节点发送给副本
+#[derive(Serialize, Deserialize)]
+enum Message {
+    /// 快照的一段，所有分段按顺序拼接后是数据文件格式的快照
+    Snapshot(Bytes),
+    /// 快照发送完毕，之后是修改
+    SnapshotEnd,
+    /// 键被写入或修改，为键的当前状态
+    Set { key: Bytes, entry: Entry },
+    /// 键被删除、过期或淘汰
+    Delete { key: Bytes },
+    /// 所有键被 FLUSHALL 删除
+    Flush,
+    /// 没有修改时定期发送的心跳，不计入复制偏移量
+    Ping,
+    /// 请求副本立即回复复制偏移量，不计入复制偏移量
+    GetAck,
+    /// 副本回复的已应用的复制偏移量
+    Ack(u64),
+}
+
+/// 存储观察者记录的修改，由后台任务读取键的当前状态后发送
+enum Change {
+    /// 键被修改或删除
+    Key(Bytes),
+    /// 所有键被删除
+    Flush,
+    /// WAIT 插入的标记，之前的修改都已发出后回复当前的复制偏移量，并请求副本确认
+    Barrier(oneshot::Sender<u64>),
+}
+
+/// 注册在存储上的观察者，有副本时把修改交给后台任务
+struct ChangeFeed {
+    /// 是否有副本，没有副本时不记录修改
+    enabled: Arc<AtomicBool>,
+    /// 发送给后台任务的修改
+    sender: mpsc::UnboundedSender<Change>,
+}
+
+impl ChangeFeed {
+    /// 有副本时记录修改
+    fn record(&self, change: Change) {
+        if self.enabled.load(Ordering::Acquire) {
+            let _ = self.sender.send(change);
+        }
+    }
+}
+
+impl StorageObserver for ChangeFeed {
+    fn on_set(&self, key: &[u8], _event: &str) {
+        self.record(Change::Key(Bytes::copy_from_slice(key)));
+    }
+
+    fn on_delete(&self, key: &[u8]) {
+        self.record(Change::Key(Bytes::copy_from_slice(key)));
+    }
+
+    fn on_expire(&self, key: &[u8]) {
+        self.record(Change::Key(Bytes::copy_from_slice(key)));
+    }
+
+    fn on_evict(&self, key: &[u8]) {
+        self.record(Change::Key(Bytes::copy_from_slice(key)));
+    }
+
+    fn on_flush(&self) {
+        self.record(Change::Flush);
+    }
+}
+
+/// 主节点一侧的一个副本
+struct ReplicaLink {
+    /// 副本的地址
+    addr: SocketAddr,
+    /// 副本监听的端口，副本没有报告时为连接的端口
+    port: u16,
+    /// 是否已完成全量同步
+    online: AtomicBool,
+    /// 副本确认已应用的复制偏移量
+    acked: AtomicU64,
+    /// 最后一次收到副本确认的时间（毫秒级 Unix 时间戳），0 表示还没有确认过
+    last_ack: AtomicU64,
+    /// 发送给副本的帧
+    frames: mpsc::Sender<Bytes>,
+}
+
+/// 副本一侧与主节点的连接状态
+#[derive(Default)]
+pub struct UpstreamLink {
+    /// 是否已完成全量同步并在接收修改
+    pub up: AtomicBool,
+    /// 是否正在接收全量同步
+    pub syncing: AtomicBool,
+    /// 已应用的复制偏移量
+    pub offset: AtomicU64,
+    /// 最后一次收到主节点数据的时间（毫秒级 Unix 时间戳），0 表示还没有收到
+    pub last_io: AtomicU64,
+    /// 复制 Redis 时断开后请求部分同步所需的状态
+    pub resume: Mutex<Resume>,
+}
+
+/// 副本一侧正在复制的主节点
+struct Upstream {
+    /// 主节点的主机名或地址
+    host: String,
+    /// 主节点的端口
+    port: u16,
+    /// 取消时停止复制
+    cancel: CancellationToken,
+    /// 连接状态
+    link: Arc<UpstreamLink>,
+}
+
+/// 复制的状态，所有连接共享
+/// 同一个实例既是主节点（有副本连接时），也可以是副本（REPLICAOF 之后）
+pub struct Replication {
+    /// 存储实例
+    storage: Arc<Storage>,
+    /// 服务器配置，连接主节点时读取认证信息和本身监听的端口
+    config: SharedConfig,
+    /// 已连接的副本
+    replicas: Mutex<Vec<Arc<ReplicaLink>>>,
+    /// 是否有副本，与观察者共享
+    enabled: Arc<AtomicBool>,
+    /// 复制偏移量，即启动以来发送给副本的修改的总字节数
+    offset: AtomicU64,
+    /// 发送给后台任务的修改，WAIT 通过它插入标记
+    changes: mpsc::UnboundedSender<Change>,
+    /// 收到副本确认时通知等待的 WAIT
+    acks: Notify,
+    /// 正在复制的主节点，None 表示本身是主节点
+    primary: Mutex<Option<Upstream>>,
+}
+
+impl Replication {
+    /// 创建复制状态，在存储上注册观察者并启动发送修改的后台任务
+    ///
+    /// # Arguments
+    /// * `storage` - 存储实例
+    /// * `config` - 服务器配置
+    pub fn new(storage: Arc<Storage>, config: SharedConfig) -> Arc<Self> {
+        let enabled = Arc::new(AtomicBool::new(false));
+        let (sender, changes) = mpsc::unbounded_channel();
+        storage.register_observer(Arc::new(ChangeFeed { enabled: enabled.clone(), sender: sender.clone() }));
+        let replication = Arc::new(Self {
+            storage,
+            config,
+            replicas: Mutex::new(Vec::new()),
+            enabled,
+            offset: AtomicU64::new(0),
+            changes: sender,
+            acks: Notify::new(),
+            primary: Mutex::new(None),
+        });
+        spawn_named("replication-feed", replication.clone().forward_changes(changes));
+        replication
+    }
+
+    /// 读取修改过的键的当前状态，编码后发给所有副本；积压过多的副本被断开
+    async fn forward_changes(self: Arc<Self>, mut changes: mpsc::UnboundedReceiver<Change>) {
+        while let Some(change) = changes.recv().await {
+            let message = match change {
+                Change::Barrier(reply) => {
+                    let _ = reply.send(self.offset.load(Ordering::Relaxed));
+                    Message::GetAck
+                }
+                _ if !self.enabled.load(Ordering::Acquire) => continue,
+                Change::Key(key) => match self.storage.entry(&key).await {
+                    Some(entry) => Message::Set { key, entry },
+                    None => Message::Delete { key },
+                },
+                Change::Flush => Message::Flush,
+            };
+            let mut frame = Vec::new();
+            if let Err(e) = BinaryFormat::Bincode.encode_frame(&message, &mut frame) {
+                warning!("Error encoding replication stream: {}", e);
+                continue;
+            }
+            if !matches!(message, Message::GetAck) {
+                self.offset.fetch_add(frame.len() as u64, Ordering::Relaxed);
+            }
+            let frame = Bytes::from(frame);
+            let mut replicas = self.replicas.lock().unwrap();
+            replicas.retain(|replica| match replica.frames.try_send(frame.clone()) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    warning!("Replica {}:{} is too slow, disconnecting it", replica.addr.ip(), replica.port);
+                    false
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            });
+            self.enabled.store(!replicas.is_empty(), Ordering::Release);
+        }
+    }
+
+    /// 登记请求 SYNC 的副本，之后的修改都会发给它
+    ///
+    /// # Arguments
+    /// * `addr` - 副本的地址
+    /// * `listening_port` - 副本报告的监听端口
+    ///
+    /// # Returns
+    /// 副本的会话，由 `ReplicaSession::serve` 发送快照和修改，结束时注销副本
+    pub fn attach(self: &Arc<Self>, addr: SocketAddr, listening_port: Option<u16>) -> ReplicaSession {
+        let (sender, frames) = mpsc::channel(REPLICA_BACKLOG);
+        let mut replicas = self.replicas.lock().unwrap();
+        // 先登记副本再生成快照，快照之后的修改都在积压中，快照之前的修改都在快照中
+        let offset = self.offset.load(Ordering::Relaxed);
+        let link = Arc::new(ReplicaLink {
+            addr,
+            port: listening_port.unwrap_or(addr.port()),
+            online: AtomicBool::new(false),
+            acked: AtomicU64::new(0),
+            last_ack: AtomicU64::new(0),
+            frames: sender,
+        });
+        replicas.push(link.clone());
+        self.enabled.store(true, Ordering::Release);
+        ReplicaSession { replication: self.clone(), link, frames, offset }
+    }
+
+    /// 注销副本
+    fn detach(&self, link: &Arc<ReplicaLink>) {
+        let mut replicas = self.replicas.lock().unwrap();
+        replicas.retain(|replica| !Arc::ptr_eq(replica, link));
+        self.enabled.store(!replicas.is_empty(), Ordering::Release);
+    }
+
+    /// 处理 WAIT：等待调用之前的所有修改被至少 `numreplicas` 个副本确认
+    /// 在修改的队列中插入标记，标记之前的修改都已发出时得到要等待的复制偏移量，同时请求副本立即确认
+    ///
+    /// # Arguments
+    /// * `numreplicas` - 需要确认的副本数
+    /// * `timeout` - 最多等待的毫秒数，0 表示一直等待
+    ///
+    /// # Returns
+    /// 已确认的副本数，超时时可能少于 `numreplicas`
+    pub async fn wait(&self, numreplicas: usize, timeout: u64) -> usize {
+        let (reply, offset) = oneshot::channel();
+        let _ = self.changes.send(Change::Barrier(reply));
+        let Ok(offset) = offset.await else {
+            return 0;
+        };
+        let deadline = (timeout > 0).then(|| time::Instant::now() + Duration::from_millis(timeout));
+        loop {
+            // 先登记通知再统计，统计之后收到的确认不会错过
+            let notified = self.acks.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let acked = self.acked(offset);
+            if acked >= numreplicas {
+                return acked;
+            }
+            match deadline {
+                Some(deadline) => {
+                    if time::timeout_at(deadline, notified).await.is_err() {
+                        return self.acked(offset);
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    /// 已确认复制偏移量 `offset` 之前的所有修改的副本数
+    fn acked(&self, offset: u64) -> usize {
+        self.replicas
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|replica| replica.last_ack.load(Ordering::Relaxed) > 0 && replica.acked.load(Ordering::Relaxed) >= offset)
+            .count()
+    }
+
+    /// 处理 REPLICAOF：开始复制指定的主节点，或停止复制成为主节点
+    /// 开始复制后副本的数据在全量同步时被主节点的数据替换，replica-read-only 为 yes 时拒绝客户端的写命令；
+    /// 停止复制时保留已有的数据
+    ///
+    /// # Arguments
+    /// * `primary` - 主节点的地址，None 表示 REPLICAOF NO ONE
+    ///
+    /// # Returns
+    /// 是否改变了复制的主节点，已经在复制同一个主节点时为 false
+    pub fn replicate(self: &Arc<Self>, primary: Option<(String, u16)>) -> bool {
+        let mut current = self.primary.lock().unwrap();
+        let same = match (&*current, &primary) {
+            (Some(upstream), Some((host, port))) => upstream.host == *host && upstream.port == *port,
+            (None, None) => true,
+            _ => false,
+        };
+        if same {
+            return false;
+        }
+        if let Some(upstream) = current.take() {
+            upstream.cancel.cancel();
+        }
+        match &primary {
+            Some((host, port)) => {
+                notice!("Replicating from {}:{}", host, port);
+                let upstream = Upstream {
+                    host: host.clone(),
+                    port: *port,
+                    cancel: CancellationToken::new(),
+                    link: Arc::new(UpstreamLink::default()),
+                };
+                let task = self.clone().follow(host.clone(), *port, upstream.link.clone(), upstream.cancel.clone());
+                spawn_named("replication", task);
+                *current = Some(upstream);
+            }
+            None => notice!("Replication stopped, now serving as a primary"),
+        }
+        let mut config = self.config.write().unwrap();
+        config.replicaof = primary;
+        self.storage.set_read_only(config.read_only());
+        true
+    }
+
+    /// 本身是否是副本
+    pub fn is_replica(&self) -> bool {
+        self.primary.lock().unwrap().is_some()
+    }
+
+    /// 本身的角色，用于 HELLO
+    pub fn role(&self) -> &'static str {
+        if self.is_replica() {
+            "replica"
+        } else {
+            "master"
+        }
+    }
+
+    /// 本身是否是还没有完成全量同步的副本
+    pub fn is_loading(&self) -> bool {
+        self.primary.lock().unwrap().as_ref().is_some_and(|upstream| !upstream.link.up.load(Ordering::Relaxed))
+    }
+
+    /// 当前的复制偏移量：副本是已应用的主节点的偏移量，主节点是发送给副本的偏移量
+    pub fn offset(&self) -> u64 {
+        match &*self.primary.lock().unwrap() {
+            Some(upstream) => upstream.link.offset.load(Ordering::Relaxed),
+            None => self.offset.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 把复制的状态加入 INFO，字段名与 Redis 的 replication 部分相同
+    pub fn add_info(&self, info: &mut HashMap<String, String>) {
+        match &*self.primary.lock().unwrap() {
+            None => {
+                info.insert("role".to_string(), "master".to_string());
+            }
+            Some(upstream) => {
+                let link = &upstream.link;
+                let flag = |value: &AtomicBool| if value.load(Ordering::Relaxed) { "1" } else { "0" }.to_string();
+                let last_io = match link.last_io.load(Ordering::Relaxed) {
+                    0 => -1,
+                    when => (now_ms().saturating_sub(when) / 1000) as i64,
+                };
+                info.insert("role".to_string(), "slave".to_string());
+                info.insert("master_host".to_string(), upstream.host.clone());
+                info.insert("master_port".to_string(), upstream.port.to_string());
+                let status = if link.up.load(Ordering::Relaxed) { "up" } else { "down" };
+                info.insert("master_link_status".to_string(), status.to_string());
+                info.insert("master_last_io_seconds_ago".to_string(), last_io.to_string());
+                info.insert("master_sync_in_progress".to_string(), flag(&link.syncing));
+                info.insert("slave_repl_offset".to_string(), link.offset.load(Ordering::Relaxed).to_string());
+            }
+        }
+        let replicas = self.replicas.lock().unwrap();
+        info.insert("connected_slaves".to_string(), replicas.len().to_string());
+        for (i, replica) in replicas.iter().enumerate() {
+            let state = if replica.online.load(Ordering::Relaxed) { "online" } else { "send_bulk" };
+            let lag = match replica.last_ack.load(Ordering::Relaxed) {
+                0 => -1,
+                when => (now_ms().saturating_sub(when) / 1000) as i64,
+            };
+            info.insert(
+                format!("slave{}", i),
+                format!(
+                    "ip={},port={},state={},offset={},lag={}",
+                    replica.addr.ip(),
+                    replica.port,
+                    state,
+                    replica.acked.load(Ordering::Relaxed),
+                    lag,
+                ),
+            );
+        }
+        info.insert("master_repl_offset".to_string(), self.offset.load(Ordering::Relaxed).to_string());
+    }
+
+    /// 复制主节点，连接断开或同步失败后等待一段时间重连，直到 `cancel` 被取消
+    async fn follow(self: Arc<Self>, host: String, port: u16, link: Arc<UpstreamLink>, cancel: CancellationToken) {
+        let mut delay = RECONNECT_DELAY_MIN;
+        loop {
+            let result = tokio::select! {
+                result = self.sync_from(&host, port, &link) => result,
+                _ = cancel.cancelled() => return,
+            };
+            link.syncing.store(false, Ordering::Relaxed);
+            // 完成过全量同步的连接断开后立即以最短的间隔重连
+            if link.up.swap(false, Ordering::Relaxed) {
+                delay = RECONNECT_DELAY_MIN;
+            }
+            if let Err(e) = result {
+                warning!("Replication from {}:{} failed: {}, retrying in {} second(s)", host, port, e, delay.as_secs());
+            }
+            tokio::select! {
+                _ = time::sleep(delay) => {}
+                _ = cancel.cancelled() => return,
+            }
+            delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+        }
+    }
+
+    /// 连接主节点，全量同步后持续应用收到的修改
+    ///
+    /// # Returns
+    /// 只在连接断开或出错时返回
+    async fn sync_from(&self, host: &str, port: u16, link: &UpstreamLink) -> io::Result<()> {
+        let (username, password, listening_port, master_type) = {
+            let config = self.config.read().unwrap();
+            (config.masteruser.clone(), config.masterauth.clone(), config.port, config.master_type)
+        };
+        if master_type == MasterType::Redis {
+            let auth = password.map(|password| (username, password));
+            return redis_upstream::sync_from(&self.storage, host, port, link, auth, listening_port).await;
+        }
+
+        let stream = time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))??;
+        stream.set_nodelay(true)?;
+        let mut framed = Framed::new(stream, ClientCodec::new());
+
+        if let Some(password) = password {
+            match request(&mut framed, &Command::Auth { username, password }).await? {
+                Response::Ok => {}
+                reply => return Err(unexpected_reply("AUTH", reply)),
+            }
+        }
+        let offset = match request(&mut framed, &Command::Sync { listening_port: Some(listening_port) }).await? {
+            Response::Value(RedoxValue::String(reply)) => std::str::from_utf8(&reply)
+                .ok()
+                .and_then(|reply| reply.strip_prefix("FULLRESYNC "))
+                .and_then(|offset| offset.parse::<u64>().ok())
+                .ok_or_else(|| invalid_data(format!("unexpected reply to SYNC: {}", String::from_utf8_lossy(&reply))))?,
+            reply => return Err(unexpected_reply("SYNC", reply)),
+        };
+
+        let FramedParts { io, read_buf, .. } = framed.into_parts();
+        let (io, mut writer) = io.into_split();
+        let mut reader = FrameReader { io, buf: read_buf, link, deadline: time::Instant::now() + REPL_TIMEOUT };
+        link.syncing.store(true, Ordering::Relaxed);
+        notice!("Full sync with {}:{} started", host, port);
+        let started = Instant::now();
+        let mut snapshot = Vec::new();
+        loop {
+            match reader.next().await? {
+                (Message::Snapshot(chunk), _) => snapshot.extend_from_slice(&chunk),
+                (Message::SnapshotEnd, _) => break,
+                (Message::Ping | Message::GetAck, _) => {}
+                _ => return Err(invalid_data("unexpected message during full sync")),
+            }
+        }
+        let size = snapshot.len();
+        let loaded = tokio::task::spawn_blocking(move || persistence::decode(&snapshot))
+            .await
+            .map_err(io::Error::other)??;
+        // 全量同步替换副本原有的所有数据
+        self.storage.flushall(true).await;
+        let count = self.storage.import(loaded).await;
+        link.offset.store(offset, Ordering::Relaxed);
+        link.syncing.store(false, Ordering::Relaxed);
+        link.up.store(true, Ordering::Relaxed);
+        notice!(
+            "Full sync with {}:{} finished: loaded {} key(s) from {} bytes in {} ms",
+            host, port, count, size, started.elapsed().as_millis()
+        );
+
+        // 第一次确认立即发送，主节点从此开始把这个副本计入 WAIT
+        let mut ack = time::interval(ACK_INTERVAL);
+        ack.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        loop {
+            let (message, len) = tokio::select! {
+                next = reader.next() => next?,
+                _ = ack.tick() => {
+                    write_message(&mut writer, &Message::Ack(link.offset.load(Ordering::Relaxed))).await?;
+                    continue;
+                }
+            };
+            match message {
+                Message::Set { key, entry } => self.storage.set_entry(key, entry).await,
+                Message::Delete { key } => {
+                    self.storage.del(&[key]).await;
+                }
+                Message::Flush => self.storage.flushall(false).await,
+                Message::Ping => continue,
+                // 之前的修改都已应用，偏移量包含 WAIT 之前的所有写入
+                Message::GetAck => {
+                    write_message(&mut writer, &Message::Ack(link.offset.load(Ordering::Relaxed))).await?;
+                    continue;
+                }
+                Message::Snapshot(_) | Message::SnapshotEnd | Message::Ack(_) => {
+                    return Err(invalid_data("unexpected message after full sync"));
+                }
+            }
+            link.offset.fetch_add(len as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 主节点一侧一个副本的会话，结束时注销副本
+pub struct ReplicaSession {
+    /// 所属的复制状态
+    replication: Arc<Replication>,
+    /// 登记的副本
+    link: Arc<ReplicaLink>,
+    /// 登记之后的帧
+    frames: mpsc::Receiver<Bytes>,
+    /// 登记时的复制偏移量，副本全量同步后从这里开始
+    offset: u64,
+}
+
+impl ReplicaSession {
+    /// SYNC 的回复，之后连接上开始发送快照
+    pub fn reply(&self) -> Response {
+        Response::Value(RedoxValue::string(format!("FULLRESYNC {}", self.offset)))
+    }
+
+    /// 向副本发送快照，然后持续发送修改并接收副本的确认，直到连接断开、副本被断开或 `kill` 被取消
+    ///
+    /// # Arguments
+    /// * `socket` - 副本的连接，已发送 SYNC 的回复
+    /// * `kill` - 服务器关闭或 CLIENT KILL 时取消
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(mut self, socket: S, kill: CancellationToken) -> io::Result<()> {
+        let name = format!("{}:{}", self.link.addr.ip(), self.link.port);
+        notice!("Replica {} asks for synchronization", name);
+        let (mut reader, socket) = tokio::io::split(socket);
+        let mut socket = BufWriter::new(socket);
+
+        // 快照在阻塞线程池中序列化，分段经过通道发送，不在内存中生成整个快照
+        let started = Instant::now();
+        let snapshot = self.replication.storage.sync_snapshot().await;
+        let (sender, mut chunks) = mpsc::channel(SNAPSHOT_QUEUE);
+        let writer = tokio::task::spawn_blocking(move || {
+            persistence::write_snapshot(ChunkWriter { sender, buffer: Vec::new() }, &snapshot).map(|_| ())
+        });
+        let mut size = 0;
+        loop {
+            let chunk = tokio::select! {
+                chunk = chunks.recv() => chunk,
+                _ = kill.cancelled() => return Ok(()),
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            size += chunk.len();
+            write_message(&mut socket, &Message::Snapshot(chunk)).await?;
+        }
+        writer.await.map_err(io::Error::other)??;
+        write_message(&mut socket, &Message::SnapshotEnd).await?;
+        socket.flush().await?;
+        self.link.online.store(true, Ordering::Relaxed);
+        notice!("Full sync of {} bytes to replica {} finished in {} ms", size, name, started.elapsed().as_millis());
+
+        let mut heartbeat = time::interval(HEARTBEAT_INTERVAL);
+        heartbeat.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let mut acks = BytesMut::new();
+        loop {
+            tokio::select! {
+                frame = self.frames.recv() => {
+                    // 副本积压过多被断开时通道关闭
+                    let Some(frame) = frame else {
+                        return Ok(());
+                    };
+                    socket.write_all(&frame).await?;
+                    if self.frames.is_empty() {
+                        socket.flush().await?;
+                    }
+                }
+                read = reader.read_buf(&mut acks) => {
+                    if read? == 0 {
+                        return Ok(());
+                    }
+                    while let Some(frame) = compact::take_frame(&mut acks, MAX_FRAME_LEN).map_err(invalid_data)? {
+                        let Message::Ack(offset) = BinaryFormat::Bincode.decode(&frame).map_err(invalid_data)? else {
+                            return Err(invalid_data("unexpected message from the replica"));
+                        };
+                        self.link.acked.store(offset, Ordering::Relaxed);
+                        self.link.last_ack.store(now_ms(), Ordering::Relaxed);
+                    }
+                    self.replication.acks.notify_waiters();
+                }
+                _ = heartbeat.tick() => {
+                    write_message(&mut socket, &Message::Ping).await?;
+                    socket.flush().await?;
+                }
+                _ = kill.cancelled() => return Ok(()),
+            }
+        }
+    }
+}
+
+impl Drop for ReplicaSession {
+    fn drop(&mut self) {
+        self.replication.detach(&self.link);
+    }
+}
+
+/// 把快照分段发送到通道的写入器，在阻塞线程池中使用
+struct ChunkWriter {
+    /// 发送分段的通道
+    sender: mpsc::Sender<Bytes>,
+    /// 还没有发送的数据
+    buffer: Vec<u8>,
+}
+
+impl ChunkWriter {
+    /// 发送缓冲的数据，副本断开时返回错误，停止序列化
+    fn send(&mut self) -> io::Result<()> {
+        let chunk = Bytes::from(std::mem::take(&mut self.buffer));
+        self.sender
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "replica disconnected"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= SNAPSHOT_CHUNK {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.send()
+    }
+}
+
+/// 副本从主节点的连接上读取消息
+struct FrameReader<'a> {
+    /// 与主节点的连接的读取端
+    io: OwnedReadHalf,
+    /// 已读取但尚未解析的数据
+    buf: BytesMut,
+    /// 连接状态，收到数据时更新最后一次收到数据的时间
+    link: &'a UpstreamLink,
+    /// 超过这个时间没有收到数据时断开，每次收到数据后延后；中途取消读取不会重新计时
+    deadline: time::Instant,
+}
+
+impl FrameReader<'_> {
+    /// 读取下一条消息
+    ///
+    /// # Returns
+    /// * `Ok((Message, usize))` - 消息和这一帧的字节数（含长度前缀）
+    /// * `Err` - 连接断开、超时或数据无效
+    async fn next(&mut self) -> io::Result<(Message, usize)> {
+        loop {
+            if let Some(frame) = compact::take_frame(&mut self.buf, MAX_FRAME_LEN).map_err(invalid_data)? {
+                let message = BinaryFormat::Bincode.decode(&frame).map_err(invalid_data)?;
+                return Ok((message, LEN_PREFIX + frame.len()));
+            }
+            let read = time::timeout_at(self.deadline, self.io.read_buf(&mut self.buf))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timeout, no data from the primary"))??;
+            if read == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the primary"));
+            }
+            self.deadline = time::Instant::now() + REPL_TIMEOUT;
+            self.link.last_io.store(now_ms(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// 编码一条消息并写入连接
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> io::Result<()> {
+    let mut frame = Vec::new();
+    BinaryFormat::Bincode.encode_frame(message, &mut frame).map_err(io::Error::other)?;
+    writer.write_all(&frame).await
+}
+
+/// 副本握手时向主节点发送一个命令并等待回复
+async fn request(framed: &mut Framed<TcpStream, ClientCodec>, cmd: &Command) -> io::Result<Response> {
+    framed.send(cmd).await.map_err(|e| io::Error::other(e.to_string()))?;
+    match time::timeout(CONNECT_TIMEOUT, framed.next()).await {
+        Ok(Some(Ok(response))) => Ok(response),
+        Ok(Some(Err(e))) => Err(io::Error::other(e.to_string())),
+        Ok(None) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed by the primary")),
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "no reply from the primary")),
+    }
+}
+
+/// 握手的回复不是预期的回复
+fn unexpected_reply(command: &str, reply: Response) -> io::Error {
+    match reply {
+        Response::Error(e) => io::Error::other(format!("{} failed: {}", command, e)),
+        _ => invalid_data(format!("unexpected reply to {}", command)),
+    }
+}
+
+/// 数据无效的错误
+pub fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,404 @@
+//! S3 快照后端
+//! 在本地数据文件的基础上，每次保存后把数据文件上传到 S3 兼容的对象存储（AWS S3、MinIO 等）；
+//! 启动时本地没有数据文件（如新创建的容器）则先从对象存储下载最近一次上传的快照，再按普通数据文件加载。
+//! 请求使用路径风格的 URL（`<endpoint>/<bucket>/<key>`）和 AWS 签名版本 4，请求体不参与签名（UNSIGNED-PAYLOAD），
+//! 上传时直接从文件流式发送，不把快照读入内存。对象存储的操作都在阻塞线程池中执行，使用阻塞的套接字。
+
+use crate::logging::notice;
+use crate::persistence::{FileBackend, LoadedData, PersistenceBackend, Snapshot};
+use redox_protocol::RedoxValue;
+use ring::hmac;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, ServerName};
+use sha2::{Digest, Sha256};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_rustls::rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+/// 没有配置区域时使用的区域，MinIO 等自建服务通常不检查区域
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+/// 没有配置 CA 证书文件时使用的系统证书包
+const DEFAULT_CA_FILE: &str = "/etc/ssl/certs/ca-certificates.crt";
+
+/// 连接、读和写对象存储的超时时间
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 不参与签名的请求体的哈希值
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// 对象存储的配置
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct S3Config {
+    /// 服务地址，如 `https://s3.us-east-1.amazonaws.com` 或 `http://127.0.0.1:9000`
+    pub endpoint: Option<String>,
+    /// 存储桶
+    pub bucket: Option<String>,
+    /// 区域，None 表示 `DEFAULT_REGION`
+    pub region: Option<String>,
+    /// 快照的对象键，None 表示使用数据文件的文件名
+    pub key: Option<String>,
+    /// 访问密钥 ID
+    pub access_key: Option<String>,
+    /// 访问密钥
+    pub secret_key: Option<String>,
+    /// 验证 HTTPS 服务端证书的 PEM 格式 CA 证书文件，None 表示系统证书包
+    pub ca_file: Option<String>,
+}
+
+/// 以本地数据文件为缓存、把快照上传到对象存储的后端
+pub struct S3Backend {
+    /// 本地数据文件
+    local: FileBackend,
+    /// 本地数据文件的路径
+    path: String,
+    /// 对象存储的客户端
+    client: S3Client,
+    /// 快照的对象键
+    key: String,
+    /// 启动时下载快照失败，此时存储中是空的数据，上传会覆盖对象存储中完整的快照
+    download_failed: AtomicBool,
+}
+
+impl S3Backend {
+    /// 创建 S3 快照后端
+    ///
+    /// # Arguments
+    /// * `path` - 本地数据文件的路径
+    /// * `compression_level` - 保存时的 zstd 压缩级别，上传的快照与本地数据文件相同
+    /// * `config` - 对象存储的配置
+    ///
+    /// # Returns
+    /// * `Ok(S3Backend)` - 创建成功，此时还没有连接对象存储
+    /// * `Err` - 缺少服务地址、存储桶或密钥，服务地址无效，或者 CA 证书无法读取
+    pub fn new(path: String, compression_level: u32, config: &S3Config) -> io::Result<Self> {
+        let key = match &config.key {
+            Some(key) => key.trim_start_matches('/').to_string(),
+            None => Path::new(&path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| invalid_input(format!("Invalid data file path: {}", path)))?,
+        };
+        Ok(Self {
+            local: FileBackend::new(path.clone(), compression_level),
+            path,
+            client: S3Client::new(config)?,
+            key,
+            download_failed: AtomicBool::new(false),
+        })
+    }
+
+    /// 快照在对象存储中的位置，用于日志
+    fn location(&self) -> String {
+        format!("s3://{}/{}", self.client.bucket, self.key)
+    }
+}
+
+impl PersistenceBackend for S3Backend {
+    fn load(&self) -> io::Result<LoadedData> {
+        // 本地的数据文件总是不比上传的快照旧，只在本地没有数据文件时下载
+        if !Path::new(&self.path).exists() {
+            notice!("Downloading the latest snapshot from {}", self.location());
+            let downloaded = self.client.download(&self.key, &self.path)
+                .inspect_err(|_| self.download_failed.store(true, Ordering::Relaxed))?;
+            if downloaded {
+                notice!("Downloaded the snapshot to {}", self.path);
+            } else {
+                notice!("No snapshot found at {}, starting with an empty dataset", self.location());
+            }
+        }
+        self.local.load()
+    }
+
+    fn save(&self, snapshot: &Snapshot<Arc<RedoxValue>>) -> io::Result<()> {
+        self.local.save(snapshot)?;
+        if self.download_failed.load(Ordering::Relaxed) {
+            return Err(io::Error::other(format!(
+                "not uploading to {} because the snapshot could not be downloaded at startup, restart the server to retry",
+                self.location()
+            )));
+        }
+        self.client.upload(&self.path, &self.key)?;
+        notice!("Uploaded the snapshot to {}", self.location());
+        Ok(())
+    }
+
+    fn set_compression_level(&self, level: u32) {
+        self.local.set_compression_level(level);
+    }
+}
+
+/// 对象存储的最小客户端，只支持上传和下载单个对象
+struct S3Client {
+    /// 服务的主机名
+    host: String,
+    /// 服务的端口
+    port: u16,
+    /// Host 头的值，与服务地址中写的相同
+    authority: String,
+    /// 使用 HTTPS 时的 TLS 配置
+    tls: Option<Arc<ClientConfig>>,
+    /// 存储桶
+    bucket: String,
+    /// 区域
+    region: String,
+    /// 访问密钥 ID
+    access_key: String,
+    /// 访问密钥
+    secret_key: String,
+}
+
+/// 可以读写的连接，普通 TCP 连接或 TLS 连接
+trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
+
+impl S3Client {
+    /// 根据配置创建客户端
+    fn new(config: &S3Config) -> io::Result<Self> {
+        let required = |value: &Option<String>, name: &str| {
+            value.clone().filter(|value| !value.is_empty())
+                .ok_or_else(|| invalid_input(format!("The s3 persistence backend requires {}", name)))
+        };
+        let endpoint = required(&config.endpoint, "s3-endpoint")?;
+        let (tls, authority) = match (endpoint.strip_prefix("https://"), endpoint.strip_prefix("http://")) {
+            (Some(authority), _) => (true, authority),
+            (None, Some(authority)) => (false, authority),
+            _ => return Err(invalid_input(format!("Invalid s3 endpoint, expected http:// or https://: {}", endpoint))),
+        };
+        let authority = authority.trim_end_matches('/').to_string();
+        if authority.is_empty() || authority.contains('/') {
+            return Err(invalid_input(format!("Invalid s3 endpoint: {}", endpoint)));
+        }
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host.to_string(),
+                port.parse().map_err(|_| invalid_input(format!("Invalid port in s3 endpoint: {}", endpoint)))?,
+            ),
+            None => (authority.clone(), if tls { 443 } else { 80 }),
+        };
+        let tls = match tls {
+            true => Some(Arc::new(client_config(config.ca_file.as_deref().unwrap_or(DEFAULT_CA_FILE))?)),
+            false => None,
+        };
+        Ok(Self {
+            host,
+            port,
+            authority,
+            tls,
+            bucket: required(&config.bucket, "s3-bucket")?,
+            region: config.region.clone().unwrap_or_else(|| DEFAULT_REGION.to_string()),
+            access_key: required(&config.access_key, "an access key (access-key or AWS_ACCESS_KEY_ID)")?,
+            secret_key: required(&config.secret_key, "a secret key (secret-key or AWS_SECRET_ACCESS_KEY)")?,
+        })
+    }
+
+    /// 把本地文件上传为对象，覆盖同名的对象
+    fn upload(&self, path: &str, key: &str) -> io::Result<()> {
+        let mut file = std::fs::File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut response = self.request("PUT", key, Some((&mut file, length)))?;
+        if response.status != 200 {
+            return Err(response.error("PUT", key));
+        }
+        Ok(())
+    }
+
+    /// 把对象下载为本地文件，先写入临时文件，完整下载后再替换
+    ///
+    /// # Returns
+    /// * `Ok(true)` - 下载成功
+    /// * `Ok(false)` - 对象不存在
+    /// * `Err` - 请求失败或下载不完整
+    fn download(&self, key: &str, path: &str) -> io::Result<bool> {
+        let mut response = self.request("GET", key, None)?;
+        match response.status {
+            200 => {}
+            404 => return Ok(false),
+            _ => return Err(response.error("GET", key)),
+        }
+        let temp_path = format!("{}.download", path);
+        let mut file = std::fs::File::create(&temp_path)?;
+        let copied = io::copy(&mut response.body, &mut file)?;
+        if response.length.is_some_and(|length| length != copied) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("Snapshot download of {} was truncated", key)));
+        }
+        file.sync_all()?;
+        std::fs::rename(temp_path, path)?;
+        Ok(true)
+    }
+
+    /// 发送一个签名的请求
+    ///
+    /// # Arguments
+    /// * `method` - HTTP 方法
+    /// * `key` - 对象键
+    /// * `body` - 请求体及其长度
+    fn request(&self, method: &str, key: &str, body: Option<(&mut dyn Read, u64)>) -> io::Result<Response> {
+        let path = format!("/{}/{}", uri_encode(&self.bucket), uri_encode(key));
+        let (date, time) = utc_timestamp(SystemTime::now());
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, self.authority, UNSIGNED_PAYLOAD, time, signed_headers, UNSIGNED_PAYLOAD
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            time, scope, hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [date.as_str(), self.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(format!("AWS4{}", self.secret_key).into_bytes(), |key, part| sign(&key, part.as_bytes()));
+        let signature = hex(&sign(&signing_key, string_to_sign.as_bytes()));
+
+        let mut stream = io::BufWriter::new(self.connect()?);
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nx-amz-date: {}\r\nx-amz-content-sha256: {}\r\n\
+             Authorization: AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            method, path, self.authority, time, UNSIGNED_PAYLOAD,
+            self.access_key, scope, signed_headers, signature,
+            body.as_ref().map_or(0, |(_, length)| *length),
+        )?;
+        if let Some((body, length)) = body {
+            let sent = io::copy(&mut body.take(length), &mut stream)?;
+            if sent != length {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Request body is shorter than its length"));
+            }
+        }
+        stream.flush()?;
+        let stream = stream.into_inner().map_err(|e| e.into_error())?;
+        Response::read(BufReader::new(stream))
+    }
+
+    /// 连接到服务，使用 HTTPS 时完成 TLS 握手之前不发送数据
+    fn connect(&self) -> io::Result<Box<dyn Connection + Send>> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))?;
+        tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+        tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+        let Some(tls) = &self.tls else {
+            return Ok(Box::new(tcp));
+        };
+        let name = ServerName::try_from(self.host.clone()).map_err(|e| invalid_input(e.to_string()))?;
+        let connection = ClientConnection::new(tls.clone(), name).map_err(io::Error::other)?;
+        Ok(Box::new(StreamOwned::new(connection, tcp)))
+    }
+}
+
+/// 对象存储的响应
+struct Response {
+    /// HTTP 状态码
+    status: u16,
+    /// Content-Length 头的值，没有时读到连接关闭为止
+    length: Option<u64>,
+    /// 响应体
+    body: Box<dyn Read + Send>,
+}
+
+impl Response {
+    /// 读取状态行和响应头，响应体留在连接中由调用者读取
+    fn read(mut reader: BufReader<Box<dyn Connection + Send>>) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| invalid("Invalid HTTP response from the s3 endpoint"))?;
+        let mut length = None;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid("Connection closed while reading HTTP response headers"));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = Some(value.trim().parse().map_err(|_| invalid("Invalid Content-Length"))?);
+                } else if name.eq_ignore_ascii_case("transfer-encoding") && !value.trim().eq_ignore_ascii_case("identity") {
+                    return Err(invalid("Chunked HTTP responses from the s3 endpoint are not supported"));
+                }
+            }
+        }
+        let body: Box<dyn Read + Send> = match length {
+            Some(length) => Box::new(reader.take(length)),
+            None => Box::new(reader),
+        };
+        Ok(Self { status, length, body })
+    }
+
+    /// 把失败的响应转换为错误，错误信息包含响应体中 S3 的错误码
+    fn error(&mut self, method: &str, key: &str) -> io::Error {
+        let mut text = String::new();
+        let _ = (&mut self.body).take(4096).read_to_string(&mut text);
+        let code = text
+            .split_once("<Code>")
+            .and_then(|(_, rest)| rest.split_once("</Code>"))
+            .map_or("", |(code, _)| code);
+        io::Error::other(format!("s3 {} {} failed with HTTP status {} {}", method, key, self.status, code))
+    }
+}
+
+/// 从 PEM 文件读取受信任的 CA 证书，创建 TLS 客户端配置
+fn client_config(ca_file: &str) -> io::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca_file).map_err(|e| invalid_input(format!("Error reading CA file {}: {}", ca_file, e)))? {
+        let cert = cert.map_err(|e| invalid_input(format!("Error reading CA file {}: {}", ca_file, e)))?;
+        roots.add(cert).map_err(|e| invalid_input(format!("Invalid CA certificate in {}: {}", ca_file, e)))?;
+    }
+    Ok(ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+}
+
+/// 配置错误
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// HMAC-SHA256
+fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+}
+
+/// 小写的十六进制字符串
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 按签名版本 4 的规则编码 URL 路径，保留非保留字符和路径分隔符 `/`
+fn uri_encode(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// 签名使用的 UTC 日期（`YYYYMMDD`）和时间（`YYYYMMDDTHHMMSSZ`）
+fn utc_timestamp(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, seconds) = (secs / 86400, secs % 86400);
+    // 由 1970-01-01 起的天数计算公历日期，以 0000-03-01 为纪元使闰日落在每年的最后
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!("{}T{:02}{:02}{:02}Z", date, seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    (date, time)
+}
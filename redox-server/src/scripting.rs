@@ -0,0 +1,376 @@
+//! Lua 脚本支持（EVAL / EVALSHA / SCRIPT）
+//! 每次执行都会创建独立的 Lua 虚拟机，脚本通过 `redis.call` / `redis.pcall` 调用数据命令。
+//! 脚本执行期间独占执行闸门，其他连接的命令会等待脚本结束，从而保证脚本的原子性。
+//! 脚本发起的每个命令都按调用脚本的连接的用户检查 ACL 权限，脚本不能执行用户本身不能执行的命令。
+
+use crate::acl::Acl;
+use crate::commands;
+use crate::storage::Storage;
+use mlua::{HookTriggers, Lua, LuaOptions, MultiValue, StdLib, Table, Value};
+use bytes::Bytes;
+use redox_protocol::{Command, Protocol, RedoxError, RedoxValue, Response};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// 脚本的最长执行时间，超时后脚本被中止
+const SCRIPT_TIME_LIMIT: Duration = Duration::from_secs(5);
+
+/// 每执行多少条虚拟机指令检查一次超时
+const HOOK_INSTRUCTIONS: u32 = 10_000;
+
+/// 脚本引擎
+/// 保存按 SHA1 索引的脚本缓存，以及保证脚本原子执行的闸门
+#[derive(Default)]
+pub struct Scripting {
+    /// SHA1（小写十六进制）到脚本源码的映射
+    cache: Mutex<HashMap<String, String>>,
+    /// 普通命令持有读锁，脚本持有写锁
+    gate: RwLock<()>,
+}
+
+impl Scripting {
+    /// 创建新的脚本引擎
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 执行普通命令前获取的共享锁，脚本运行期间会阻塞
+    pub async fn shared(&self) -> RwLockReadGuard<'_, ()> {
+        self.gate.read().await
+    }
+
+    /// 执行脚本或函数前获取的独占锁，等待其他命令执行完毕
+    pub async fn exclusive(&self) -> RwLockWriteGuard<'_, ()> {
+        self.gate.write().await
+    }
+
+    /// 缓存脚本
+    ///
+    /// # Returns
+    /// 脚本的 SHA1
+    pub async fn load(&self, script: String) -> String {
+        let sha = sha1_hex(&script);
+        self.cache.lock().await.insert(sha.clone(), script);
+        sha
+    }
+
+    /// 检查脚本是否已缓存
+    pub async fn exists(&self, shas: &[String]) -> Vec<bool> {
+        let cache = self.cache.lock().await;
+        shas.iter().map(|sha| cache.contains_key(sha)).collect()
+    }
+
+    /// 清空脚本缓存
+    pub async fn flush(&self) {
+        self.cache.lock().await.clear();
+    }
+
+    /// 执行脚本，并把脚本加入缓存
+    ///
+    /// # Arguments
+    /// * `storage` - 存储实例
+    /// * `caller` - 调用脚本的连接的用户，脚本中的命令按这个用户的权限检查
+    /// * `script` - 脚本源码
+    /// * `keys` - 通过 KEYS 表传给脚本的键
+    /// * `args` - 通过 ARGV 表传给脚本的参数
+    pub async fn eval(
+        &self,
+        storage: Arc<Storage>,
+        caller: Caller,
+        script: String,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+    ) -> Response {
+        self.load(script.clone()).await;
+        let _exclusive = self.exclusive().await;
+        run(storage, caller, &script, keys, args).await
+    }
+
+    /// 按 SHA1 执行已缓存的脚本
+    pub async fn eval_sha(
+        &self,
+        storage: Arc<Storage>,
+        caller: Caller,
+        sha: &str,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+    ) -> Response {
+        let script = match self.cache.lock().await.get(sha) {
+            Some(script) => script.clone(),
+            None => return Response::Error(RedoxError::NoScript),
+        };
+        let _exclusive = self.exclusive().await;
+        run(storage, caller, &script, keys, args).await
+    }
+}
+
+/// 调用脚本或函数的连接的用户，脚本和函数中发起的命令按这个用户的权限检查
+#[derive(Clone)]
+pub struct Caller {
+    /// 用户和权限
+    pub acl: Arc<Acl>,
+    /// 连接认证的用户名
+    pub user: String,
+}
+
+impl Caller {
+    /// 检查用户的权限后执行脚本或函数中发起的命令
+    ///
+    /// # Returns
+    /// 命令的响应；用户没有执行命令或访问键的权限时为 NOPERM 错误，命令不执行
+    pub async fn execute(&self, storage: &Storage, cmd: Command) -> Response {
+        match self.acl.check(&self.user, &cmd) {
+            Ok(()) => commands::execute(storage, cmd).await,
+            Err(e) => Response::Error(e),
+        }
+    }
+}
+
+/// 计算脚本的 SHA1（小写十六进制）
+fn sha1_hex(script: &str) -> String {
+    Sha1::digest(script.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 在新的 Lua 虚拟机中执行脚本
+/// 虚拟机不能跨线程共享，脚本在阻塞线程池中运行，同时避免占用异步工作线程
+async fn run(storage: Arc<Storage>, caller: Caller, script: &str, keys: Vec<Bytes>, args: Vec<Bytes>) -> Response {
+    let script = script.to_string();
+    let result = tokio::task::spawn_blocking(move || {
+        Handle::current().block_on(async {
+            let lua = new_vm(storage, caller)?;
+            lua.globals().set("KEYS", string_table(&lua, keys)?)?;
+            lua.globals().set("ARGV", string_table(&lua, args)?)?;
+            // 异步函数在协程中运行，超时检查需要设置在协程上
+            let thread = lua.create_thread(lua.load(&script).set_name("@user_script").into_function()?)?;
+            let started = Instant::now();
+            thread.set_hook(HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS), move |_, _| {
+                if started.elapsed() > SCRIPT_TIME_LIMIT {
+                    return Err(mlua::Error::runtime("Script killed by timeout"));
+                }
+                Ok(())
+            });
+            let value: Value = thread.into_async(()).await?;
+            Ok::<_, mlua::Error>(lua_to_response(value))
+        })
+    })
+    .await;
+    match result {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => Response::Error(format!("Error running script: {}", script_error_message(&e)).into()),
+        Err(e) => Response::Error(format!("Error running script: {}", e).into()),
+    }
+}
+
+/// 创建只加载安全标准库的虚拟机，并注册 `redis` 表
+fn new_vm(storage: Arc<Storage>, caller: Caller) -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8,
+        LuaOptions::default(),
+    )?;
+
+    let redis = lua.create_table()?;
+
+    let call_storage = storage.clone();
+    let call_caller = caller.clone();
+    redis.set("call", lua.create_async_function(move |lua, args: MultiValue| {
+        let storage = call_storage.clone();
+        let caller = call_caller.clone();
+        async move {
+            match call(&storage, &caller, args).await {
+                Response::Error(e) => Err(mlua::Error::runtime(e.to_string())),
+                response => response_to_lua(lua, response),
+            }
+        }
+    })?)?;
+
+    redis.set("pcall", lua.create_async_function(move |lua, args: MultiValue| {
+        let storage = storage.clone();
+        let caller = caller.clone();
+        async move { response_to_lua(lua, call(&storage, &caller, args).await) }
+    })?)?;
+
+    redis.set("status_reply", lua.create_function(|lua, status: String| {
+        let table = lua.create_table()?;
+        table.set("ok", status)?;
+        Ok(table)
+    })?)?;
+
+    redis.set("error_reply", lua.create_function(|lua, error: String| {
+        let table = lua.create_table()?;
+        table.set("err", error)?;
+        Ok(table)
+    })?)?;
+
+    redis.set("sha1hex", lua.create_function(|_, script: String| Ok(sha1_hex(&script)))?)?;
+
+    lua.globals().set("redis", redis)?;
+    Ok(lua)
+}
+
+/// 执行脚本中通过 `redis.call` 发起的命令
+async fn call(storage: &Storage, caller: &Caller, args: MultiValue<'_>) -> Response {
+    let mut words: Vec<Bytes> = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg {
+            Value::String(s) => words.push(Bytes::copy_from_slice(s.as_bytes())),
+            Value::Integer(n) => words.push(n.to_string().into()),
+            Value::Number(n) => words.push(n.to_string().into()),
+            _ => return Response::Error("Command arguments must be strings or integers".into()),
+        }
+    }
+    if words.is_empty() {
+        return Response::Error("Please specify at least one argument for this redis lib call".into());
+    }
+    match Protocol::decode_args(&words) {
+        Ok(cmd) => caller.execute(storage, cmd).await,
+        Err(e) => Response::Error(RedoxError::Syntax(e)),
+    }
+}
+
+/// 把命令的响应转换为 Lua 值
+/// 不存在的值转换为 false，状态回复转换为 `{ok = ...}`，错误转换为 `{err = ...}`
+fn response_to_lua(lua: &Lua, response: Response) -> mlua::Result<Value<'_>> {
+    let strings = |items: Vec<Bytes>| -> mlua::Result<Value<'_>> {
+        Ok(Value::Table(string_table(lua, items)?))
+    };
+    match response {
+        Response::Ok => {
+            let table = lua.create_table()?;
+            table.set("ok", "OK")?;
+            Ok(Value::Table(table))
+        }
+        Response::Error(e) => {
+            let table = lua.create_table()?;
+            table.set("err", e.to_string())?;
+            Ok(Value::Table(table))
+        }
+        Response::Nil => Ok(Value::Boolean(false)),
+        Response::Integer(n) => Ok(Value::Integer(n)),
+        Response::Array(items) => {
+            let table = lua.create_table()?;
+            for (i, item) in items.into_iter().enumerate() {
+                match item {
+                    Some(s) => table.raw_set(i + 1, lua.create_string(&s)?)?,
+                    None => table.raw_set(i + 1, false)?,
+                }
+            }
+            Ok(Value::Table(table))
+        }
+        Response::Info(info) => {
+            let mut pairs: Vec<(String, String)> = info.into_iter().collect();
+            pairs.sort();
+            strings(pairs.into_iter().flat_map(|(k, v)| [k.into(), v.into()]).collect())
+        }
+        Response::Map(fields) => {
+            let table = lua.create_table()?;
+            for (name, value) in fields {
+                table.raw_set(name, response_to_lua(lua, value)?)?;
+            }
+            Ok(Value::Table(table))
+        }
+        Response::Cursor(cursor, items) => {
+            // 与 Redis 相同，为 {游标, {元素 ...}}
+            let table = lua.create_table()?;
+            table.raw_set(1, cursor.to_string())?;
+            table.raw_set(2, string_table(lua, items)?)?;
+            Ok(Value::Table(table))
+        }
+        Response::Push { kind, items } => {
+            let table = lua.create_table()?;
+            table.raw_set(1, kind)?;
+            for (i, item) in items.into_iter().enumerate() {
+                table.raw_set(i + 2, response_to_lua(lua, item)?)?;
+            }
+            Ok(Value::Table(table))
+        }
+        Response::Replies(replies) => {
+            let table = lua.create_table()?;
+            for (i, reply) in replies.into_iter().enumerate() {
+                table.raw_set(i + 1, response_to_lua(lua, reply)?)?;
+            }
+            Ok(Value::Table(table))
+        }
+        Response::Value(value) => match value {
+            RedoxValue::String(s) => Ok(Value::String(lua.create_string(&s)?)),
+            RedoxValue::List(list) => strings(list.into()),
+            RedoxValue::Set(set) => strings(set.into_iter().collect()),
+            RedoxValue::Hash(hash) => strings(hash.into_iter().flat_map(|(k, v)| [k, v]).collect()),
+            RedoxValue::SortedSet(zset) => {
+                strings(zset.into_iter().flat_map(|(m, s)| [m, s.to_string().into()]).collect())
+            }
+            RedoxValue::Json(json) => Ok(Value::String(lua.create_string(json.to_string())?)),
+            RedoxValue::TimeSeries(ts) => strings(
+                ts.samples.into_iter().flat_map(|(t, v)| [t.to_string().into(), v.to_string().into()]).collect(),
+            ),
+        },
+    }
+}
+
+/// 把二进制数据转换为 Lua 字符串组成的数组，Lua 字符串可以包含任意字节
+fn string_table(lua: &Lua, items: Vec<Bytes>) -> mlua::Result<Table<'_>> {
+    let table = lua.create_table_with_capacity(items.len(), 0)?;
+    for (i, item) in items.into_iter().enumerate() {
+        table.raw_set(i + 1, lua.create_string(&item)?)?;
+    }
+    Ok(table)
+}
+
+/// 把脚本的返回值转换为响应
+/// 数字截断为整数，true 为 1，false 和 nil 为 Nil，表按数组处理（遇到 nil 截止），
+/// 带 `ok` 或 `err` 字段的表分别转换为状态回复和错误
+fn lua_to_response(value: Value) -> Response {
+    match value {
+        Value::Nil | Value::Boolean(false) => Response::Nil,
+        Value::Boolean(true) => Response::Integer(1),
+        Value::Integer(n) => Response::Integer(n),
+        Value::Number(n) => Response::Integer(n as i64),
+        Value::String(s) => Response::Value(RedoxValue::String(Bytes::copy_from_slice(s.as_bytes()))),
+        Value::Table(table) => table_to_response(table),
+        _ => Response::Nil,
+    }
+}
+
+/// 把脚本返回的表转换为响应
+fn table_to_response(table: Table) -> Response {
+    if let Ok(Some(err)) = table.raw_get::<_, Option<String>>("err") {
+        return Response::Error(RedoxError::parse(&err));
+    }
+    if let Ok(Some(ok)) = table.raw_get::<_, Option<String>>("ok") {
+        return match ok.as_str() {
+            "OK" => Response::Ok,
+            _ => Response::Value(RedoxValue::string(ok)),
+        };
+    }
+    let mut items = Vec::new();
+    for i in 1.. {
+        match table.raw_get::<_, Value>(i) {
+            Ok(Value::Nil) | Err(_) => break,
+            Ok(Value::Boolean(false)) => items.push(None),
+            Ok(Value::Boolean(true)) => items.push(Some(Bytes::from_static(b"1"))),
+            Ok(Value::Integer(n)) => items.push(Some(n.to_string().into())),
+            Ok(Value::Number(n)) => items.push(Some((n as i64).to_string().into())),
+            Ok(Value::String(s)) => items.push(Some(Bytes::copy_from_slice(s.as_bytes()))),
+            // 不支持嵌套数组，整体转换为空值
+            Ok(_) => items.push(None),
+        }
+    }
+    Response::Array(items)
+}
+
+/// 提取脚本错误的主要信息，去掉 Lua 的调用栈
+fn script_error_message(error: &mlua::Error) -> String {
+    match error {
+        mlua::Error::CallbackError { cause, .. } => script_error_message(cause),
+        mlua::Error::RuntimeError(msg) | mlua::Error::SyntaxError { message: msg, .. } => {
+            msg.lines().next().unwrap_or_default().to_string()
+        }
+        e => e.to_string(),
+    }
+}
@@ -0,0 +1,147 @@
+//! sled 持久化后端
+//! 数据文件只能整体写入和读取；sled 后端把每个键保存为数据库中的一项，值为 bincode 序列化的 `Entry`（值、过期时间和字段的过期时间），
+//! 保存时只写入上次保存之后修改过的键，启动时逐个读取键，不需要把全部数据同时放在内存中。
+//! 内存超过上限时存储把值写入数据库后从内存中移除，之后访问这个键时再通过 `get` 读回，数据集可以超过内存的容量。
+//! 每次写入的所有修改放在一个批次中原子地生效，进程崩溃时数据库中的每个键都是某次写入时的完整状态。
+
+use crate::persistence::{Changes, LoadedData, PersistenceBackend, Snapshot};
+use crate::storage::Entry;
+use bytes::Bytes;
+use redox_protocol::RedoxValue;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::Arc;
+
+/// 键的前缀，后接键，值为 bincode 序列化的 `Entry`
+const DATA_TAG: u8 = b'd';
+
+/// 函数库的前缀，后接库名，值为源码
+const FUNCTION_TAG: u8 = b'F';
+
+/// sled 数据库后端
+pub struct SledBackend {
+    /// 打开的数据库
+    db: sled::Db,
+}
+
+impl SledBackend {
+    /// 打开数据库，目录不存在时创建
+    ///
+    /// # Arguments
+    /// * `path` - 数据库目录
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Self { db: sled::open(path)? })
+    }
+
+    /// 在批次中删除数据库中以 `tag` 开头的所有项
+    fn remove_all(&self, batch: &mut sled::Batch, tag: u8) -> io::Result<()> {
+        for item in self.db.scan_prefix([tag]) {
+            batch.remove(item?.0);
+        }
+        Ok(())
+    }
+}
+
+impl PersistenceBackend for SledBackend {
+    fn load(&self) -> io::Result<LoadedData> {
+        let mut loaded = LoadedData::default();
+        self.iterate(&mut |key, entry| {
+            if let Some(when) = entry.expire_at {
+                loaded.expiry.insert(key.clone(), when);
+            }
+            loaded.field_expiry.extend(entry.field_expires.into_iter().map(|(field, when)| (key.clone(), field, when)));
+            loaded.data.insert(key, Arc::unwrap_or_clone(entry.value));
+        })?;
+        loaded.functions = self.load_functions()?;
+        Ok(loaded)
+    }
+
+    fn save(&self, snapshot: &Snapshot<Arc<RedoxValue>>) -> io::Result<()> {
+        let expiry: HashMap<&Bytes, u64> = snapshot.expiry_ms.0.iter().map(|(key, when)| (key, *when)).collect();
+        let field_expiry: HashMap<&Bytes, Vec<(Bytes, u64)>> = snapshot.field_expiry_ms.0.iter()
+            .map(|(key, fields)| (key, fields.0.clone()))
+            .collect();
+        let keys = snapshot.data.0.iter().map(|(key, value)| {
+            let entry = Entry {
+                value: value.clone(),
+                expire_at: expiry.get(key).copied(),
+                field_expires: field_expiry.get(key).cloned().unwrap_or_default(),
+            };
+            (key.clone(), Some(entry))
+        });
+        self.append(&Changes {
+            clear: true,
+            keys: keys.collect(),
+            functions: Some(snapshot.functions.clone()),
+            sync: true,
+        })
+    }
+
+    fn keyed(&self) -> bool {
+        true
+    }
+
+    fn append(&self, changes: &Changes) -> io::Result<()> {
+        let mut batch = sled::Batch::default();
+        if changes.clear {
+            self.remove_all(&mut batch, DATA_TAG)?;
+        }
+        for (key, entry) in &changes.keys {
+            match entry {
+                Some(entry) => batch.insert(tagged(DATA_TAG, key), bincode::serialize(entry).map_err(io::Error::other)?),
+                None => batch.remove(tagged(DATA_TAG, key)),
+            }
+        }
+        if let Some(functions) = &changes.functions {
+            self.remove_all(&mut batch, FUNCTION_TAG)?;
+            for (name, source) in functions {
+                batch.insert(tagged(FUNCTION_TAG, name.as_bytes()), source.as_bytes());
+            }
+        }
+        self.db.apply_batch(batch)?;
+        if changes.sync {
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &[u8]) -> io::Result<Option<Entry>> {
+        match self.db.get(tagged(DATA_TAG, key))? {
+            Some(value) => decode(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn iterate(&self, visit: &mut dyn FnMut(Bytes, Entry)) -> io::Result<()> {
+        for item in self.db.scan_prefix([DATA_TAG]) {
+            let (key, value) = item?;
+            visit(Bytes::copy_from_slice(&key[1..]), decode(&value)?);
+        }
+        Ok(())
+    }
+
+    fn load_functions(&self) -> io::Result<BTreeMap<String, String>> {
+        let mut functions = BTreeMap::new();
+        for item in self.db.scan_prefix([FUNCTION_TAG]) {
+            let (name, source) = item?;
+            functions.insert(
+                String::from_utf8_lossy(&name[1..]).into_owned(),
+                String::from_utf8_lossy(&source).into_owned(),
+            );
+        }
+        Ok(functions)
+    }
+}
+
+/// 解码数据库中保存的 `Entry`
+fn decode(value: &[u8]) -> io::Result<Entry> {
+    bincode::deserialize(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// 在键前加上表示种类的前缀
+fn tagged(tag: u8, key: &[u8]) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(key.len() + 1);
+    tagged.push(tag);
+    tagged.extend_from_slice(key);
+    tagged
+}
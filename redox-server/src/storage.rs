@@ -1,585 +1,2727 @@
-use std::collections::{HashMap, HashSet, BTreeMap};
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet, VecDeque};
+use std::hash::{BuildHasher, RandomState};
+use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use redox_protocol::RedoxValue;
-use crate::persistence::Persistence;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use bytes::Bytes;
+use indexmap::IndexMap;
+use redox_protocol::{ExpireCondition, GeoOrigin, GetExOption, GeoShape, RedoxError, RedoxValue, SortedSet, TimeSeries, TsAggregation};
+use crate::clock::{SharedClock, SystemClock};
+use crate::eviction::{self, Access, Policy};
+use crate::geo;
+use crate::json_path;
+use crate::lazyfree::LazyFree;
+use crate::logging::warning;
+use crate::memory::{self, MemoryStats};
+use crate::observer::{Observers, StorageObserver};
+use crate::quota::{Quota, QuotaReservation, Quotas};
+use crate::rdb;
+use crate::persistence::{self, Changes, LoadedData, Persistence, SaveRule};
+use crate::timeseries;
+use crate::task::spawn_named;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// 分片的数量，键按哈希值分配到各个分片
+const SHARD_COUNT: usize = 16;
+
+/// 后台任务检查到期键的间隔
+const EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 后台任务每次加锁时在一个分片上最多删除的键数，限制持有写锁的时间
+const EXPIRE_BATCH: usize = 64;
+
+/// 一个分片中的键，每个分片有自己的读写锁
+/// 访问不同分片的命令可以同时执行，只读命令在同一个分片上也可以同时执行
+struct Shard {
+    /// 键值数据，键是二进制安全的字节串，值是 RedoxValue 枚举
+    /// 使用 IndexMap 以便内存淘汰时按下标随机抽样
+    /// 值放在 Arc 中，读取时只在锁内增加引用计数，复制和编码回复都在锁外进行；
+    /// 写命令通过 `get_mut` 修改，值仍被读取者持有时才复制一份
+    data: IndexMap<Bytes, Arc<RedoxValue>>,
+    /// 键的过期时间，值为毫秒级 Unix 时间戳，volatile-* 淘汰策略从中随机抽样
+    expires: IndexMap<Bytes, u64>,
+    /// 按过期时间排序的 (过期时间, 键)，与 `expires` 同步修改，后台任务只需查看最前面已到期的部分
+    deadlines: BTreeSet<(u64, Bytes)>,
+    /// 哈希表字段的过期时间，键到 (字段, 毫秒级 Unix 时间戳) 的映射，键被删除或值被替换时一起移除
+    field_expires: HashMap<Bytes, HashMap<Bytes, u64>>,
+    /// 按过期时间排序的 (过期时间, 键, 字段)，与 `field_expires` 同步修改
+    field_deadlines: BTreeSet<(u64, Bytes, Bytes)>,
+    /// 值已经写入按键保存的持久化后端、不在内存中的键，内存超过上限时由 `Storage::spill_one` 移出
+    /// 这些键的过期时间和访问记录仍然在上面的各项中，读写这些键之前先通过 `Storage::page_in` 把值读回内存
+    spilled: IndexMap<Bytes, Spilled>,
+    /// 键的最后访问时间和访问频率，由读写操作和 TOUCH 更新，内存淘汰时据此选择淘汰的键
+    /// 只读命令只持有分片的读锁，访问记录由这个单独的锁保护，持有时间很短且不跨越 await
+    access: std::sync::Mutex<HashMap<Bytes, Access>>,
+    /// 分片中所有键的内存统计
+    memory: MemoryStats,
+    /// 所有分片估算的总字节数，各分片共享，不需要加锁就可以读取
+    used_memory: Arc<AtomicUsize>,
+    /// 过期和淘汰的键的值通过它释放，各分片共享
+    lazyfree: LazyFree,
+    /// 存储事件的观察者，各分片共享
+    observers: Observers,
+    /// 按键前缀的配额和用量，各分片共享
+    quotas: Quotas,
+    /// 判断键是否过期时读取当前时间，各分片共享
+    clock: SharedClock,
+}
+
+impl Shard {
+    /// 键是否设置了过期时间且已经过期
+    fn is_expired(&self, key: &[u8]) -> bool {
+        self.expires.get(key).is_some_and(|&when| self.clock.now_ms() >= when)
+    }
+
+    /// 获取未过期的值，只读命令使用；已过期的键留给写命令或后台任务删除
+    fn get(&self, key: &[u8]) -> Option<&RedoxValue> {
+        self.get_shared(key).map(|value| &**value)
+    }
+
+    /// 获取未过期的值的共享引用，可以在释放锁之后继续读取
+    fn get_shared(&self, key: &[u8]) -> Option<&Arc<RedoxValue>> {
+        if self.is_expired(key) {
+            return None;
+        }
+        self.data.get(key)
+    }
+
+    /// 获取值用于修改，写命令使用；值仍被读取者共享时先复制一份
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut RedoxValue> {
+        self.data.get_mut(key).map(Arc::make_mut)
+    }
+
+    /// 键是否存在且未过期，包括值不在内存中的键
+    fn contains(&self, key: &[u8]) -> bool {
+        !self.is_expired(key) && (self.data.contains_key(key) || self.spilled.contains_key(key))
+    }
+
+    /// 值不在内存中的键被移出时的序号，值在内存中或键不存在时为 None
+    fn spilled_seq(&self, key: &[u8]) -> Option<u64> {
+        self.spilled.get(key).map(|spilled| spilled.seq)
+    }
+
+    /// 把键的值移出内存，只保留键、过期时间和访问记录；内存统计减去值的字节数，前缀配额的用量不变
+    ///
+    /// # Arguments
+    /// * `seq` - 这次移出的序号
+    ///
+    /// # Returns
+    /// 移出的值，键不存在时为 None
+    fn spill(&mut self, key: &Bytes, seq: u64) -> Option<Arc<RedoxValue>> {
+        let usage = self.usage(key)?;
+        let value = self.data.swap_remove(key)?;
+        self.memory.sub(usage);
+        self.used_memory.fetch_sub(usage.1, Ordering::Relaxed);
+        self.spilled.insert(key.clone(), Spilled { kind: value.type_name(), bytes: usage.1, seq });
+        Some(value)
+    }
+
+    /// 把从持久化后端读回的值放回内存，键的过期时间仍以内存中的为准
+    fn unspill(&mut self, key: &[u8], value: Arc<RedoxValue>) {
+        let Some((key, spilled)) = self.spilled.swap_remove_entry(key) else {
+            return;
+        };
+        let usage = memory::measure(&key, &value);
+        self.memory.add(usage);
+        self.used_memory.fetch_add(usage.1, Ordering::Relaxed);
+        self.quotas.account(&key, Some(spilled.bytes), Some(usage.1));
+        self.data.insert(key, value);
+    }
+
+    /// 记录启动时从按键保存的后端读到的键
+    /// 值留在后端中，第一次读写时读回；设置了字段过期时间的哈希表留在内存中，与 `spill_candidate` 相同
+    fn load_entry(&mut self, key: Bytes, entry: Entry) {
+        if let Some(when) = entry.expire_at {
+            self.set_expire(key.clone(), when);
+        }
+        if !entry.field_expires.is_empty() {
+            self.insert(key.clone(), Arc::unwrap_or_clone(entry.value));
+            for (field, when) in entry.field_expires {
+                self.set_field_expire(key.clone(), field, when);
+            }
+            return;
+        }
+        let bytes = memory::measure(&key, &entry.value).1;
+        self.quotas.account(&key, None, Some(bytes));
+        self.spilled.insert(key, Spilled { kind: entry.value.type_name(), bytes, seq: 0 });
+    }
+
+    /// 键的值和过期时间，键不存在、已过期或值不在内存中时为 None
+    fn entry(&self, key: &[u8]) -> Option<Entry> {
+        let value = self.get_shared(key)?.clone();
+        let field_expires = self.field_expires.get(key)
+            .map(|fields| fields.iter().map(|(field, when)| (field.clone(), *when)).collect())
+            .unwrap_or_default();
+        Some(Entry {
+            value,
+            expire_at: self.expires.get(key).copied(),
+            field_expires,
+        })
+    }
+
+    /// 创建空的分片
+    fn new(used_memory: Arc<AtomicUsize>, lazyfree: LazyFree, observers: Observers, quotas: Quotas, clock: SharedClock) -> Self {
+        Self {
+            data: IndexMap::new(),
+            expires: IndexMap::new(),
+            deadlines: BTreeSet::new(),
+            field_expires: HashMap::new(),
+            field_deadlines: BTreeSet::new(),
+            spilled: IndexMap::new(),
+            access: std::sync::Mutex::new(HashMap::new()),
+            memory: MemoryStats::default(),
+            used_memory,
+            lazyfree,
+            observers,
+            quotas,
+            clock,
+        }
+    }
+
+    /// 键当前的值在内存统计中的统计项，键不存在时为 None
+    fn usage(&self, key: &[u8]) -> Option<(&'static str, usize)> {
+        self.data.get_key_value(key).map(|(key, value)| memory::measure(key, value))
+    }
+
+    /// 按键修改前后的统计项更新内存统计和键所属前缀的配额用量
+    fn account(&mut self, key: &[u8], before: Option<(&'static str, usize)>, after: Option<(&'static str, usize)>) {
+        if let Some(usage) = before {
+            self.memory.sub(usage);
+            self.used_memory.fetch_sub(usage.1, Ordering::Relaxed);
+        }
+        if let Some(usage) = after {
+            self.memory.add(usage);
+            self.used_memory.fetch_add(usage.1, Ordering::Relaxed);
+        }
+        self.quotas.account(key, before.map(|usage| usage.1), after.map(|usage| usage.1));
+    }
+
+    /// 写入键的值并更新内存统计，用于不经过 `KeyGuard` 的写入
+    fn insert(&mut self, key: Bytes, value: RedoxValue) {
+        let before = self.usage(&key);
+        let after = memory::measure(&key, &value);
+        self.touch(&key);
+        self.clear_field_expires(&key);
+        self.account(&key, before, Some(after));
+        self.data.insert(key, Arc::new(value));
+    }
+
+    /// 删除键并更新内存统计，用于不经过 `KeyGuard` 的删除；值不在内存中的键也一起删除
+    /// 
+    /// # Returns
+    /// 被删除的值，键不存在或值不在内存中时为 None
+    fn delete(&mut self, key: &[u8]) -> Option<Arc<RedoxValue>> {
+        if let Some(spilled) = self.spilled.swap_remove(key) {
+            self.remove(key);
+            self.quotas.account(key, Some(spilled.bytes), None);
+            return None;
+        }
+        let before = self.usage(key);
+        let value = self.remove(key);
+        self.account(key, before, None);
+        value
+    }
+
+    /// 删除键并按惰性释放的规则释放值，用于过期键清理和内存淘汰
+    fn delete_lazily(&mut self, key: &[u8]) {
+        if let Some(value) = self.delete(key) {
+            self.lazyfree.free(value);
+        }
+    }
+
+    /// 摘除分片中的所有键，返回原来的内容，由调用者在锁外释放
+    /// 前缀的配额用量由调用者在摘除所有分片后一起清零
+    fn take(&mut self) -> Shard {
+        let empty = Shard::new(
+            self.used_memory.clone(),
+            self.lazyfree.clone(),
+            self.observers.clone(),
+            self.quotas.clone(),
+            self.clock.clone(),
+        );
+        let taken = std::mem::replace(self, empty);
+        self.used_memory.fetch_sub(taken.memory.bytes, Ordering::Relaxed);
+        taken
+    }
+
+    /// 删除键及其过期时间和访问记录，不更新内存统计
+    /// 在 `KeyGuard` 中使用，释放时按键的新值统一更新
+    /// 
+    /// # Returns
+    /// 被删除的值，键不存在时为 None
+    fn remove(&mut self, key: &[u8]) -> Option<Arc<RedoxValue>> {
+        self.clear_expire(key);
+        self.clear_field_expires(key);
+        self.access.get_mut().unwrap().remove(key);
+        self.data.swap_remove(key)
+    }
+
+    /// 键已过期时删除它并通知观察者
+    /// 
+    /// # Returns
+    /// 键是否已过期并被删除
+    fn remove_if_expired(&mut self, key: &[u8]) -> bool {
+        if self.is_expired(key) {
+            self.delete_lazily(key);
+            self.observers.notify(|observer| observer.on_expire(key));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 设置键的过期时间，替换原有的过期时间
+    fn set_expire(&mut self, key: Bytes, when: u64) {
+        if let Some(old) = self.expires.insert(key.clone(), when) {
+            self.deadlines.remove(&(old, key.clone()));
+        }
+        self.deadlines.insert((when, key));
+    }
+
+    /// 移除键的过期时间
+    /// 
+    /// # Returns
+    /// 键原来是否有过期时间
+    fn clear_expire(&mut self, key: &[u8]) -> bool {
+        match self.expires.swap_remove_entry(key) {
+            Some((key, when)) => {
+                self.deadlines.remove(&(when, key));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 按过期时间从早到晚删除已到期的键并通知观察者
+    /// 
+    /// # Arguments
+    /// * `now` - 当前的毫秒级 Unix 时间戳
+    /// * `limit` - 最多删除的键数
+    /// 
+    /// # Returns
+    /// (删除的键数, 是否还有已到期的键没有删除)
+    fn expire_due(&mut self, now: u64, limit: usize) -> (usize, bool) {
+        let mut removed = 0;
+        while let Some((when, _)) = self.deadlines.first() {
+            if *when > now {
+                return (removed, false);
+            }
+            if removed == limit {
+                return (removed, true);
+            }
+            let (_, key) = self.deadlines.pop_first().expect("deadlines is not empty");
+            self.delete_lazily(&key);
+            self.observers.notify(|observer| observer.on_expire(&key));
+            removed += 1;
+        }
+        (removed, false)
+    }
+
+    /// 字段的过期时间（毫秒级 Unix 时间戳），没有设置时为 None
+    fn field_expire(&self, key: &[u8], field: &[u8]) -> Option<u64> {
+        self.field_expires.get(key)?.get(field).copied()
+    }
+
+    /// 字段是否设置了过期时间且已经过期
+    fn is_field_expired(&self, key: &[u8], field: &[u8]) -> bool {
+        self.field_expire(key, field).is_some_and(|when| self.clock.now_ms() >= when)
+    }
+
+    /// 键中已过期但还没有删除的字段，只读命令据此在锁外过滤哈希表
+    fn expired_fields(&self, key: &[u8]) -> HashSet<Bytes> {
+        let now = self.clock.now_ms();
+        self.field_expires.get(key)
+            .map(|fields| fields.iter().filter(|(_, &when)| now >= when).map(|(field, _)| field.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// 设置字段的过期时间，替换原有的过期时间
+    fn set_field_expire(&mut self, key: Bytes, field: Bytes, when: u64) {
+        let fields = self.field_expires.entry(key.clone()).or_default();
+        if let Some(old) = fields.insert(field.clone(), when) {
+            self.field_deadlines.remove(&(old, key.clone(), field.clone()));
+        }
+        self.field_deadlines.insert((when, key, field));
+    }
+
+    /// 移除字段的过期时间
+    /// 
+    /// # Returns
+    /// 字段原来是否有过期时间
+    fn clear_field_expire(&mut self, key: &[u8], field: &[u8]) -> bool {
+        let Some(fields) = self.field_expires.get_mut(key) else {
+            return false;
+        };
+        let Some((field, when)) = fields.remove_entry(field) else {
+            return false;
+        };
+        if fields.is_empty() {
+            self.field_expires.remove(key);
+        }
+        self.field_deadlines.remove(&(when, Bytes::copy_from_slice(key), field));
+        true
+    }
+
+    /// 移除键的所有字段的过期时间，键被删除或值被替换时调用
+    fn clear_field_expires(&mut self, key: &[u8]) {
+        if let Some((key, fields)) = self.field_expires.remove_entry(key) {
+            for (field, when) in fields {
+                self.field_deadlines.remove(&(when, key.clone(), field));
+            }
+        }
+    }
+
+    /// 删除哈希表的字段及其过期时间，删除最后一个字段时同时删除键，不更新内存统计
+    /// 在 `KeyGuard` 中使用，释放时按键的新值统一更新
+    /// 
+    /// # Returns
+    /// 字段是否存在
+    fn remove_field(&mut self, key: &[u8], field: &[u8]) -> bool {
+        self.clear_field_expire(key, field);
+        let (removed, emptied) = match self.data.get_mut(key).map(Arc::make_mut) {
+            Some(RedoxValue::Hash(hash)) => (hash.remove(field).is_some(), hash.is_empty()),
+            _ => return false,
+        };
+        if emptied {
+            self.remove(key);
+        }
+        removed
+    }
+
+    /// 字段已过期时删除它，写命令读取或修改字段前调用，不更新内存统计
+    /// 
+    /// # Returns
+    /// 字段是否已过期并被删除
+    fn remove_field_if_expired(&mut self, key: &[u8], field: &[u8]) -> bool {
+        if self.is_field_expired(key, field) {
+            self.remove_field(key, field);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 按过期时间从早到晚删除已到期的哈希表字段，哈希表的最后一个字段到期时同时删除键
+    /// 
+    /// # Arguments
+    /// * `now` - 当前的毫秒级 Unix 时间戳
+    /// * `limit` - 最多删除的字段数
+    /// 
+    /// # Returns
+    /// (删除的字段数, 是否还有已到期的字段没有删除)
+    fn expire_fields_due(&mut self, now: u64, limit: usize) -> (usize, bool) {
+        let mut removed = 0;
+        while let Some((when, key, field)) = self.field_deadlines.first().cloned() {
+            if when > now {
+                return (removed, false);
+            }
+            if removed == limit {
+                return (removed, true);
+            }
+            let before = self.usage(&key);
+            self.remove_field(&key, &field);
+            let after = self.usage(&key);
+            self.account(&key, before, after);
+            match after {
+                Some(_) => self.observers.notify(|observer| observer.on_set(&key, "hexpired")),
+                None => self.observers.notify(|observer| observer.on_expire(&key)),
+            }
+            removed += 1;
+        }
+        (removed, false)
+    }
+
+    /// 更新键的最后访问时间和访问频率
+    fn touch(&self, key: &[u8]) {
+        let now = self.clock.now_ms();
+        let mut access = self.access.lock().unwrap();
+        match access.get_mut(key) {
+            Some(record) => record.touch(now),
+            None => {
+                access.insert(Bytes::copy_from_slice(key), Access::new(now));
+            }
+        }
+    }
+
+    /// 键的最后访问时间（毫秒），没有访问记录时为 None
+    fn last_access(&self, key: &[u8]) -> Option<u64> {
+        self.access.lock().unwrap().get(key).map(|record| record.last_ms)
+    }
+
+    /// 按淘汰策略选出分片中要淘汰的键
+    /// LRU 和 LFU 策略随机抽取几个键，选出其中最久没有访问或访问频率最低的键
+    /// 
+    /// # Arguments
+    /// * `policy` - 淘汰策略
+    /// * `created_ms` - 没有访问记录的键使用的访问时间
+    /// 
+    /// # Returns
+    /// 淘汰的键，分片中没有符合策略的键时为 None
+    fn eviction_candidate(&self, policy: Policy, created_ms: u64) -> Option<Bytes> {
+        let now = self.clock.now_ms();
+        let access = self.access.lock().unwrap();
+        let record = |key: &Bytes| access.get(key).copied().unwrap_or_else(|| Access::new(created_ms));
+        let candidate = match policy {
+            Policy::NoEviction => None,
+            Policy::AllKeysLru => sample(&self.data, eviction::SAMPLES).min_by_key(|key| record(key).last_ms),
+            Policy::AllKeysLfu => sample(&self.data, eviction::SAMPLES).min_by_key(|key| {
+                let record = record(key);
+                (record.frequency(now), record.last_ms)
+            }),
+            Policy::VolatileLru => sample(&self.expires, eviction::SAMPLES).min_by_key(|key| record(key).last_ms),
+            Policy::VolatileTtl => self.deadlines.first().map(|(_, key)| key),
+            Policy::VolatileRandom => sample(&self.expires, 1).next(),
+        };
+        candidate.cloned()
+    }
+
+    /// 按 allkeys-lru 的方式选出要移出内存的键，随机抽取几个键，选出其中最久没有访问的键
+    /// 设置了字段过期时间的哈希表不移出：字段到期时要在内存中删除
+    fn spill_candidate(&self, created_ms: u64) -> Option<Bytes> {
+        let access = self.access.lock().unwrap();
+        sample(&self.data, eviction::SAMPLES)
+            .filter(|key| !self.field_expires.contains_key(*key))
+            .min_by_key(|key| access.get(*key).map_or(created_ms, |record| record.last_ms))
+            .cloned()
+    }
+}
+
+/// 值不在内存中的键的信息
+struct Spilled {
+    /// 值的类型名称，用于 INFO 按类型统计键数
+    kind: &'static str,
+    /// 值估算的字节数，前缀配额的用量仍然包括这部分
+    bytes: usize,
+    /// 移出的序号，读回值期间键被读回又再次移出时，先读到的旧值不放回内存
+    seq: u64,
+}
+
+/// 从键集合中随机抽取 `samples` 个键，可能重复，集合为空时不返回任何键
+fn sample<V>(map: &IndexMap<Bytes, V>, samples: usize) -> impl Iterator<Item = &Bytes> {
+    let len = map.len();
+    let samples = if len == 0 { 0 } else { samples };
+    (0..samples).filter_map(move |_| map.get_index(fastrand::usize(..len)).map(|(key, _)| key))
+}
+
+/// 单个键所在分片的写锁，释放时按键的新值更新分片的内存统计和键的访问时间
+/// 单键的写命令可以直接修改分片中的值，不需要自己维护统计；修改了键时调用 `changed`，
+/// 释放时按键是否还存在通知观察者键被修改或删除
+struct KeyGuard<'a> {
+    /// 分片的写锁
+    shard: RwLockWriteGuard<'a, Shard>,
+    /// 写入的键
+    key: Bytes,
+    /// 加锁时键的统计项
+    before: Option<(&'static str, usize)>,
+    /// 修改键的命令名，没有修改时为 None
+    event: Option<&'static str>,
+}
+
+impl KeyGuard<'_> {
+    /// 记录键已被修改，释放锁之前通知观察者
+    ///
+    /// # Arguments
+    /// * `event` - 修改的方式，为小写的命令名
+    fn changed(&mut self, event: &'static str) {
+        self.event = Some(event);
+    }
+}
+
+impl Deref for KeyGuard<'_> {
+    type Target = Shard;
+
+    fn deref(&self) -> &Shard {
+        &self.shard
+    }
+}
+
+impl DerefMut for KeyGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Shard {
+        &mut self.shard
+    }
+}
+
+impl Drop for KeyGuard<'_> {
+    fn drop(&mut self) {
+        let after = self.shard.usage(&self.key);
+        if after != self.before {
+            self.shard.account(&self.key, self.before, after);
+        }
+        if after.is_some() {
+            self.shard.touch(&self.key);
+        }
+        if let Some(event) = self.event {
+            let key = &self.key;
+            match after {
+                Some(_) => self.shard.observers.notify(|observer| observer.on_set(key, event)),
+                None => self.shard.observers.notify(|observer| observer.on_delete(key)),
+            }
+        }
+    }
+}
+
+/// 多键命令锁定的分片，`G` 为读锁或写锁
+/// 分片总是按编号从小到大加锁，两个同时执行的多键命令不会互相等待对方持有的锁
+struct ShardGuards<'a, G> {
+    /// 所属的存储，用于计算键所在的分片
+    storage: &'a Storage,
+    /// 分片编号到已获得的锁的映射
+    guards: BTreeMap<usize, G>,
+}
+
+impl<G: Deref<Target = Shard>> ShardGuards<'_, G> {
+    /// 获取键所在的分片
+    fn get(&self, key: &[u8]) -> &Shard {
+        &self.guards[&self.storage.shard_index(key)]
+    }
+
+    /// 值不在内存中的键及其移出的序号
+    fn spilled(&self, keys: &[&Bytes]) -> Vec<(Bytes, u64)> {
+        keys.iter()
+            .filter_map(|&key| self.get(key).spilled_seq(key).map(|seq| (key.clone(), seq)))
+            .collect()
+    }
+}
+
+impl ShardGuards<'_, RwLockWriteGuard<'_, Shard>> {
+    /// 获取键所在的分片用于修改，键已过期时先删除它
+    fn get_mut(&mut self, key: &[u8]) -> &mut Shard {
+        let index = self.storage.shard_index(key);
+        let shard = self.guards.get_mut(&index).expect("the shard of every key is locked");
+        shard.remove_if_expired(key);
+        shard
+    }
+}
+
+/// 逐个分片复制的快照，值与存储共享，用于保存数据文件、导出 RDB 文件和副本的全量同步
+#[derive(Default)]
+struct Snapshot {
+    /// 所有键值对
+    data: Vec<(Bytes, Arc<RedoxValue>)>,
+    /// 键的过期时间（毫秒）
+    expiry: Vec<(Bytes, u64)>,
+    /// 哈希表字段的过期时间（毫秒），按键分组
+    field_expiry: Vec<(Bytes, Vec<(Bytes, u64)>)>,
+}
+
+/// 一个键的完整状态，按键保存的持久化后端以这个形式保存键，主节点把修改过的键以这个形式发送给副本
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    /// 键的值
+    pub value: Arc<RedoxValue>,
+    /// 键的过期时间（毫秒级 Unix 时间戳），None 表示不过期
+    pub expire_at: Option<u64>,
+    /// 哈希表字段的过期时间（毫秒级 Unix 时间戳）
+    pub field_expires: Vec<(Bytes, u64)>,
+}
 
 /// 存储结构体，提供线程安全的数据存储和访问
 /// 支持多种数据类型：字符串、列表、集合、哈希表和有序集合
 #[derive(Clone)]
 pub struct Storage {
-    /// 核心数据存储，使用 Arc<Mutex> 实现线程安全
-    /// HashMap 的键是字符串，值是 RedoxValue 枚举
-    data: Arc<Mutex<HashMap<String, RedoxValue>>>,
+    /// 核心数据存储，按键的哈希值分成多个分片，每个分片使用独立的 RwLock 实现线程安全
+    shards: Arc<[RwLock<Shard>]>,
+    /// 计算键所在分片的哈希函数
+    hasher: RandomState,
+    /// 存储创建的时间（毫秒），没有访问记录的键从此时开始计算空闲时间
+    created_ms: u64,
+    /// FUNCTION LOAD 加载的函数库源码，随数据一起持久化
+    functions: Arc<Mutex<BTreeMap<String, String>>>,
     /// 持久化管理器，可选
     persistence: Option<Persistence>,
+    /// 后台任务是否定期清理过期的键，DEBUG SET-ACTIVE-EXPIRE 可以关闭
+    active_expire: Arc<AtomicBool>,
+    /// 所有键估算的总字节数，与各分片共享
+    used_memory: Arc<AtomicUsize>,
+    /// 内存上限（字节），0 表示不限制
+    maxmemory: Arc<AtomicUsize>,
+    /// 内存超过上限时的处理方式，保存 `Policy as u8`
+    maxmemory_policy: Arc<AtomicU8>,
+    /// 因内存超过上限而淘汰的键数
+    evicted_keys: Arc<AtomicU64>,
+    /// 把值移出内存的次数，同时作为每次移出的序号
+    spills: Arc<AtomicU64>,
+    /// 在后台释放删除的大值，与各分片共享
+    lazyfree: LazyFree,
+    /// 存储事件的观察者，与各分片共享
+    observers: Observers,
+    /// 按键前缀的配额和用量，与各分片共享
+    quotas: Quotas,
+    /// 是否拒绝客户端的写命令，只读副本上为 true
+    read_only: Arc<AtomicBool>,
+    /// 过期时间、TTL 和空闲时间使用的时钟，与各分片和持久化管理器共享
+    clock: SharedClock,
 }
 
 impl Storage {
     /// 创建新的存储实例
     /// 
     /// # Arguments
-    /// * `persistence` - 可选的持久化管理器
+    /// * `persistence` - 可选的持久化管理器
+    /// 
+    /// # Returns
+    /// 新的存储实例，如果提供了持久化管理器，会自动加载已保存的数据
+    pub fn new(persistence: Option<Persistence>) -> Self {
+        Self::with_clock(persistence, Arc::new(SystemClock))
+    }
+
+    /// 创建使用指定时钟的存储实例，测试中使用 `ManualClock` 可以不等待地让键过期
+    ///
+    /// # Arguments
+    /// * `persistence` - 可选的持久化管理器，同样改为使用这个时钟
+    /// * `clock` - 当前时间的来源
+    ///
+    /// # Returns
+    /// 新的存储实例，如果提供了持久化管理器，会自动加载已保存的数据
+    pub fn with_clock(persistence: Option<Persistence>, clock: SharedClock) -> Self {
+        let persistence = persistence.map(|p| p.with_clock(clock.clone()));
+        let hasher = RandomState::new();
+        let used_memory = Arc::new(AtomicUsize::new(0));
+        let lazyfree = LazyFree::new(crate::lazyfree::DEFAULT_THRESHOLD);
+        let observers = Observers::default();
+        let quotas = Quotas::default();
+        let mut shards: Vec<Shard> = (0..SHARD_COUNT)
+            .map(|_| Shard::new(used_memory.clone(), lazyfree.clone(), observers.clone(), quotas.clone(), clock.clone()))
+            .collect();
+
+        // 尝试从持久化存储加载数据，按键的哈希值分配到各个分片
+        let mut functions = BTreeMap::new();
+        match &persistence {
+            Some(p) if p.keyed() => {
+                // 按键保存的后端逐个读取键，只在内存中保留键和过期时间，值在第一次读写时读回
+                let result = tokio::task::block_in_place(|| {
+                    p.iterate(&mut |key, entry| shards[shard_of(&hasher, &key)].load_entry(key, entry))?;
+                    p.load_functions()
+                });
+                match result {
+                    Ok(loaded) => functions = loaded,
+                    Err(e) => warning!("Error loading data: {}", e),
+                }
+            }
+            Some(p) => {
+                let loaded = tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current().block_on(async {
+                        match p.load().await {
+                            Ok(loaded) => loaded,
+                            Err(e) => {
+                                warning!("Error loading data: {}", e);
+                                LoadedData::default()
+                            }
+                        }
+                    })
+                });
+                for (key, value) in loaded.data {
+                    shards[shard_of(&hasher, &key)].insert(key, value);
+                }
+                for (key, when) in loaded.expiry {
+                    shards[shard_of(&hasher, &key)].set_expire(key, when);
+                }
+                for (key, field, when) in loaded.field_expiry {
+                    shards[shard_of(&hasher, &key)].set_field_expire(key, field, when);
+                }
+                functions = loaded.functions;
+            }
+            None => {}
+        }
+
+        let storage = Storage {
+            shards: shards.into_iter().map(RwLock::new).collect(),
+            hasher,
+            created_ms: clock.now_ms(),
+            functions: Arc::new(Mutex::new(functions)),
+            persistence,
+            active_expire: Arc::new(AtomicBool::new(true)),
+            used_memory,
+            maxmemory: Arc::new(AtomicUsize::new(0)),
+            maxmemory_policy: Arc::new(AtomicU8::new(Policy::NoEviction as u8)),
+            evicted_keys: Arc::new(AtomicU64::new(0)),
+            spills: Arc::new(AtomicU64::new(0)),
+            lazyfree,
+            observers,
+            quotas,
+            read_only: Arc::new(AtomicBool::new(false)),
+            clock,
+        };
+
+        // 如果启用了持久化，注册为观察者以便记录键的每次修改，并启动自动保存任务
+        if let Some(p) = storage.persistence.clone() {
+            storage.register_observer(Arc::new(p.clone()));
+            let storage = storage.clone();
+            spawn_named("auto-save", async move {
+                p.start_auto_save(move || {
+                    let storage = storage.clone();
+                    async move { storage.flush().await }
+                }).await;
+            });
+        }
+
+        storage
+    }
+
+    /// 把尚未保存的修改写入数据文件，由自动保存任务在满足保存条件时调用
+    /// 
+    /// # Returns
+    /// * `Ok(true)` - 已保存
+    /// * `Ok(false)` - 未启用持久化或没有需要保存的修改
+    /// * `Err` - 保存过程中的错误
+    pub async fn flush(&self) -> std::io::Result<bool> {
+        let Some(p) = &self.persistence else {
+            return Ok(false);
+        };
+        match p.take_changes() {
+            0 => Ok(false),
+            changes => self.save_to(p, changes).await.map(|_| true),
+        }
+    }
+
+    /// 立即保存数据文件，不管数据是否有修改，用于 DEBUG QUICKSAVE 和关闭服务器
+    /// 
+    /// # Returns
+    /// * `Ok(true)` - 已保存
+    /// * `Ok(false)` - 未启用持久化
+    /// * `Err` - 保存过程中的错误
+    pub async fn save_now(&self) -> std::io::Result<bool> {
+        match &self.persistence {
+            Some(p) => {
+                let changes = p.take_changes();
+                self.save_to(p, changes).await.map(|_| true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// 生成所有分片的快照并写入数据文件，按键保存的后端只写入修改过的键
+    /// 快照时逐个分片持有读锁，但只复制值的引用（写时复制），不复制值本身，持有锁的时间很短；
+    /// 序列化和写文件时不持有锁，其间的写命令修改快照中的键时才复制出新的值
+    /// 
+    /// # Arguments
+    /// * `changes` - 保存前取出的修改次数，保存失败时加回去
+    async fn save_to(&self, p: &Persistence, changes: u64) -> std::io::Result<()> {
+        let result = if p.keyed() {
+            self.append_dirty(p).await
+        } else {
+            let snapshot = self.snapshot().await;
+            let functions = self.functions.lock().await.clone();
+            p.save(snapshot.data, snapshot.expiry, snapshot.field_expiry, functions).await
+        };
+        if result.is_err() {
+            // 保存失败时保留修改次数，下次满足保存条件时重试
+            p.restore_changes(changes);
+        }
+        result
+    }
+
+    /// 把上次保存之后修改过的键的当前状态写入按键保存的后端
+    /// 逐个键加读锁读取状态，不存在或已过期的键写入删除；值不在内存中的键在移出时已经写入了最新的状态，跳过
+    async fn append_dirty(&self, p: &Persistence) -> std::io::Result<()> {
+        let _appending = p.lock_appends().await;
+        let (clear, dirty) = p.take_dirty();
+        let mut keys = Vec::with_capacity(dirty.len());
+        for key in &dirty {
+            let shard = self.shards[self.shard_index(key)].read().await;
+            if !shard.spilled.contains_key(key) {
+                keys.push((key.clone(), shard.entry(key)));
+            }
+        }
+        let functions = Some(self.functions.lock().await.clone());
+        let result = p.append(Changes { clear, keys, functions, sync: true }).await;
+        if result.is_err() {
+            p.restore_dirty(clear, dirty);
+        }
+        result
+    }
+
+    /// 逐个分片复制所有键的值的引用和过期时间
+    /// 每次只持有一个分片的读锁，复制的是 `Arc` 而不是值本身，其他分片的写命令在复制期间继续执行；
+    /// 每个分片内部是一致的，不同分片的复制时刻不同。副本全量同步之后收到的修改包含键的完整状态，
+    /// 快照之后重新应用也会得到相同的结果。值不在内存中的键在复制完所有分片后从持久化后端读取，不放回内存
+    async fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        let mut spilled = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.read().await;
+            spilled.extend(shard.spilled.keys().cloned());
+            snapshot.data.extend(shard.data.iter().map(|(key, value)| (key.clone(), value.clone())));
+            snapshot.expiry.extend(shard.expires.iter().map(|(key, when)| (key.clone(), *when)));
+            snapshot.field_expiry.extend(shard.field_expires.iter().map(|(key, fields)| {
+                (key.clone(), fields.iter().map(|(field, when)| (field.clone(), *when)).collect())
+            }));
+        }
+        if let Some(p) = &self.persistence {
+            for key in spilled {
+                match p.get(key.clone()).await {
+                    Ok(Some(entry)) => snapshot.data.push((key, entry.value)),
+                    Ok(None) => {}
+                    Err(e) => warning!("Error reading a value from the persistence backend: {}", e),
+                }
+            }
+        }
+        snapshot
+    }
+
+    /// 生成包含函数库的完整快照，用于向副本发送全量同步，快照的方式与保存数据文件相同
+    pub async fn sync_snapshot(&self) -> persistence::Snapshot<Arc<RedoxValue>> {
+        let snapshot = self.snapshot().await;
+        let functions = self.functions.lock().await.clone();
+        persistence::Snapshot::new(snapshot.data, snapshot.expiry, snapshot.field_expiry, functions)
+    }
+
+    /// 把当前的数据导出为 Redis 的 RDB 文件，快照的方式与保存数据文件相同
+    /// 
+    /// # Arguments
+    /// * `path` - RDB 文件的路径
+    /// 
+    /// # Returns
+    /// * `Ok(usize)` - 导出的键数
+    /// * `Err(String)` - 写文件失败
+    pub async fn export_rdb(&self, path: String) -> Result<usize, String> {
+        let snapshot = self.snapshot().await;
+        let now = self.now_ms();
+        tokio::task::spawn_blocking(move || {
+            let expiry: HashMap<Bytes, u64> = snapshot.expiry.into_iter().collect();
+            rdb::save(&path, &snapshot.data, &expiry, now)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    /// 修改保存时的 zstd 压缩级别，0 表示不压缩，未启用持久化时忽略
+    pub fn set_compression_level(&self, level: u32) {
+        if let Some(p) = &self.persistence {
+            p.set_compression_level(level);
+        }
+    }
+
+    /// 存储使用的时钟，DEBUG SET-TIME 通过它修改时间
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    /// 存储的时钟的当前时间（毫秒级 Unix 时间戳）
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    /// 开启或关闭后台的过期键清理，关闭后过期的键只在访问时删除
+    pub fn set_active_expire(&self, enabled: bool) {
+        self.active_expire.store(enabled, Ordering::Relaxed);
+    }
+
+    /// 设置是否拒绝客户端的写命令，复制主节点应用的修改不受影响
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// 在写命令执行前调用，只读副本上拒绝客户端和脚本的写命令
+    /// 
+    /// # Returns
+    /// * `Ok(())` - 可以写入
+    /// * `Err(RedoxError::ReadOnly)` - 本身是只读副本
+    pub fn ensure_writable(&self) -> Result<(), RedoxError> {
+        if self.read_only.load(Ordering::Relaxed) {
+            return Err(RedoxError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// 修改自动保存的条件，未启用持久化时忽略
+    pub fn set_save_rules(&self, save_rules: Vec<SaveRule>) {
+        if let Some(p) = &self.persistence {
+            p.set_save_rules(save_rules);
+        }
+    }
+
+    /// 注册存储事件的观察者，之后键的写入、删除、过期和淘汰都会通知它
+    pub fn register_observer(&self, observer: Arc<dyn StorageObserver>) {
+        self.observers.register(observer);
+    }
+
+    /// 记录一次不属于任何键的修改（函数库）；键的修改通过观察者记录
+    fn mark_dirty(&self) {
+        if let Some(p) = &self.persistence {
+            p.mark_dirty();
+        }
+    }
+
+    /// 键所在的分片编号
+    fn shard_index(&self, key: &[u8]) -> usize {
+        shard_of(&self.hasher, key)
+    }
+
+    /// 以共享方式锁定键所在的分片，用于只读命令，键的值不在内存中时先读回
+    /// 已过期的键不会被删除，通过 `Shard::get` 读取时视为不存在
+    async fn read(&self, key: &[u8]) -> RwLockReadGuard<'_, Shard> {
+        let lock = &self.shards[self.shard_index(key)];
+        loop {
+            let shard = lock.read().await;
+            let Some(seq) = shard.spilled_seq(key) else {
+                return shard;
+            };
+            drop(shard);
+            self.page_in(key, seq).await;
+        }
+    }
+
+    /// 以独占方式锁定键所在的分片，键已过期时先删除它，之后的操作把它视为不存在；键的值不在内存中时先读回
+    /// 释放时按键的新值更新内存统计
+    async fn write(&self, key: &[u8]) -> KeyGuard<'_> {
+        let lock = &self.shards[self.shard_index(key)];
+        loop {
+            let mut shard = lock.write().await;
+            shard.remove_if_expired(key);
+            if let Some(seq) = shard.spilled_seq(key) {
+                drop(shard);
+                self.page_in(key, seq).await;
+                continue;
+            }
+            let before = shard.usage(key);
+            return KeyGuard { shard, key: Bytes::copy_from_slice(key), before, event: None };
+        }
+    }
+
+    /// 从持久化后端读回值不在内存中的键，在不持有锁时读取，读完后加写锁放回内存
+    /// 读取失败或后端中没有这个键时删除这个键并通知观察者，之后的读写把它视为不存在
+    ///
+    /// # Arguments
+    /// * `seq` - 加锁时看到的移出序号，读取期间键被删除或再次移出时不使用读到的值
+    async fn page_in(&self, key: &[u8], seq: u64) {
+        let Some(p) = &self.persistence else {
+            return;
+        };
+        let result = p.get(Bytes::copy_from_slice(key)).await;
+        let mut shard = self.shards[self.shard_index(key)].write().await;
+        if shard.spilled_seq(key) != Some(seq) {
+            return;
+        }
+        match result {
+            Ok(Some(entry)) => shard.unspill(key, entry.value),
+            result => {
+                match result {
+                    Err(e) => warning!("Error reading a value from the persistence backend, dropping the key: {}", e),
+                    _ => warning!("A key is missing from the persistence backend, dropping it"),
+                }
+                shard.delete(key);
+                shard.observers.notify(|observer| observer.on_delete(key));
+            }
+        }
+    }
+
+    /// 多个键所在分片的编号，从小到大排列
+    fn shard_indexes<'a>(&self, keys: impl IntoIterator<Item = &'a Bytes>) -> BTreeSet<usize> {
+        keys.into_iter().map(|key| self.shard_index(key)).collect()
+    }
+
+    /// 以共享方式锁定多个键所在的分片，按分片编号从小到大加锁；有键的值不在内存中时释放锁读回后重新加锁
+    async fn read_keys<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a Bytes>,
+    ) -> ShardGuards<'_, RwLockReadGuard<'_, Shard>> {
+        let keys: Vec<&Bytes> = keys.into_iter().collect();
+        loop {
+            let mut guards = BTreeMap::new();
+            for index in self.shard_indexes(keys.iter().copied()) {
+                guards.insert(index, self.shards[index].read().await);
+            }
+            let guards = ShardGuards { storage: self, guards };
+            let spilled = guards.spilled(&keys);
+            if spilled.is_empty() {
+                return guards;
+            }
+            drop(guards);
+            for (key, seq) in spilled {
+                self.page_in(&key, seq).await;
+            }
+        }
+    }
+
+    /// 以独占方式锁定多个键所在的分片，按分片编号从小到大加锁；有键的值不在内存中时释放锁读回后重新加锁
+    async fn write_keys<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a Bytes>,
+    ) -> ShardGuards<'_, RwLockWriteGuard<'_, Shard>> {
+        let keys: Vec<&Bytes> = keys.into_iter().collect();
+        loop {
+            let mut guards = BTreeMap::new();
+            for index in self.shard_indexes(keys.iter().copied()) {
+                guards.insert(index, self.shards[index].write().await);
+            }
+            let guards = ShardGuards { storage: self, guards };
+            let spilled = guards.spilled(&keys);
+            if spilled.is_empty() {
+                return guards;
+            }
+            drop(guards);
+            for (key, seq) in spilled {
+                self.page_in(&key, seq).await;
+            }
+        }
+    }
+
+    /// 按编号顺序以共享方式锁定所有分片
+    async fn read_all(&self) -> Vec<RwLockReadGuard<'_, Shard>> {
+        let mut guards = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            guards.push(shard.read().await);
+        }
+        guards
+    }
+
+    // 字符串操作
+    /// 设置字符串值
+    /// 
+    /// # Arguments
+    /// * `key` - 键
+    /// * `value` - 值
+    pub async fn set_string(&self, key: Bytes, value: Bytes) {
+        let mut shard = self.write(&key).await;
+        shard.clear_field_expires(&key);
+        shard.data.insert(key, Arc::new(RedoxValue::String(value)));
+        shard.changed("set");
+    }
+
+    /// 获取未过期的值，并更新键的最后访问时间
+    /// 锁内只增加值的引用计数，调用者在释放锁之后读取值，大的集合类型不会延长持有锁的时间
+    /// 
+    /// # Arguments
+    /// * `key` - 键
+    /// 
+    /// # Returns
+    /// * `Some(Arc<RedoxValue>)` - 找到的值
+    /// * `None` - 键不存在或已过期
+    async fn get_if_not_expired(&self, key: &[u8]) -> Option<Arc<RedoxValue>> {
+        let shard = self.read(key).await;
+        let value = shard.get_shared(key).cloned();
+        if value.is_some() {
+            shard.touch(key);
+        }
+        value
+    }
+
+    /// 获取字符串值
+    /// 
+    /// # Returns
+    /// * `Ok(Some(Bytes))` - 找到的值
+    /// * `Ok(None)` - 键不存在
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是字符串
+    pub async fn get_string(&self, key: &[u8]) -> Result<Option<Bytes>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// 字符串值的字节数，键不存在时为 0
+    pub async fn strlen(&self, key: &[u8]) -> Result<usize, RedoxError> {
+        let shard = self.read(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::String(s)) => Ok(s.len()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(0),
+        }
+    }
+
+    /// 获取字符串值并在同一次加锁中调整过期时间
+    /// 
+    /// # Arguments
+    /// * `key` - 键
+    /// * `option` - 过期时间的调整方式，None 表示只读取
+    /// 
+    /// # Returns
+    /// * `Ok(Some(Bytes))` - 找到的值
+    /// * `Ok(None)` - 键不存在，此时不会修改过期时间
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是字符串
+    pub async fn getex(&self, key: &[u8], option: Option<GetExOption>) -> Result<Option<Bytes>, RedoxError> {
+        let mut shard = self.write(key).await;
+        let value = match shard.get(key) {
+            Some(RedoxValue::String(s)) => s.clone(),
+            Some(_) => return Err(RedoxError::WrongType),
+            None => return Ok(None),
+        };
+        shard.touch(key);
+
+        if let Some(option) = option {
+            let deadline = match option {
+                GetExOption::Ex(seconds) => Some(self.now_ms().saturating_add(seconds.saturating_mul(1000))),
+                GetExOption::Px(ms) => Some(self.now_ms().saturating_add(ms)),
+                GetExOption::ExAt(ts) => Some(ts.saturating_mul(1000)),
+                GetExOption::PxAt(ts) => Some(ts),
+                GetExOption::Persist => None,
+            };
+            match deadline {
+                Some(deadline) => {
+                    shard.set_expire(Bytes::copy_from_slice(key), deadline);
+                    shard.changed("expire");
+                }
+                None => {
+                    if shard.clear_expire(key) {
+                        shard.changed("persist");
+                    }
+                }
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// 比较并设置字符串值，比较和写入在同一次加锁中完成
+    /// 键原有的过期时间保持不变
+    /// 
+    /// # Arguments
+    /// * `key` - 键
+    /// * `expected` - 期望的当前值
+    /// * `value` - 新值
+    /// 
+    /// # Returns
+    /// * `Ok(true)` - 当前值与期望值相同，已写入新值
+    /// * `Ok(false)` - 键不存在或当前值与期望值不同
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是字符串
+    pub async fn cas(&self, key: &[u8], expected: &[u8], value: Bytes) -> Result<bool, RedoxError> {
+        let mut shard = self.write(key).await;
+        match shard.get_mut(key) {
+            Some(RedoxValue::String(current)) if current == expected => {
+                *current = value;
+                shard.touch(key);
+                shard.changed("set");
+                Ok(true)
+            }
+            Some(RedoxValue::String(_)) | None => Ok(false),
+            Some(_) => Err(RedoxError::WrongType),
+        }
+    }
+
+    // 列表操作
+    /// 在列表左端插入元素
+    /// 
+    /// # Arguments
+    /// * `key` - 列表的键
+    /// * `value` - 要插入的值
+    /// 
+    /// # Returns
+    /// * `Ok(usize)` - 操作后列表的长度
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是列表
+    pub async fn lpush(&self, key: Bytes, value: Bytes) -> Result<usize, RedoxError> {
+        let mut shard = self.write(&key).await;
+        let result = match shard.get_mut(&key) {
+            Some(RedoxValue::List(list)) => {
+                list.push_front(value);
+                list.len()
+            }
+            None => {
+                let list = VecDeque::from([value]);
+                shard.data.insert(key, Arc::new(RedoxValue::List(list)));
+                1
+            }
+            Some(_) => return Err(RedoxError::WrongType),
+        };
+        shard.changed("lpush");
+        Ok(result)
+    }
+
+    pub async fn rpush(&self, key: Bytes, value: Bytes) -> Result<usize, RedoxError> {
+        let mut shard = self.write(&key).await;
+        let result = match shard.get_mut(&key) {
+            Some(RedoxValue::List(list)) => {
+                list.push_back(value);
+                list.len()
+            }
+            None => {
+                let list = VecDeque::from([value]);
+                shard.data.insert(key, Arc::new(RedoxValue::List(list)));
+                1
+            }
+            Some(_) => return Err(RedoxError::WrongType),
+        };
+        shard.changed("rpush");
+        Ok(result)
+    }
+
+    pub async fn lpop(&self, key: &[u8]) -> Result<Option<Bytes>, RedoxError> {
+        let mut shard = self.write(key).await;
+        let result = match shard.get_mut(key) {
+            Some(RedoxValue::List(list)) => list.pop_front(),
+            Some(_) => return Err(RedoxError::WrongType),
+            None => None,
+        };
+        if result.is_some() {
+            shard.changed("lpop");
+        }
+        Ok(result)
+    }
+
+    pub async fn rpop(&self, key: &[u8]) -> Result<Option<Bytes>, RedoxError> {
+        let mut shard = self.write(key).await;
+        let result = match shard.get_mut(key) {
+            Some(RedoxValue::List(list)) => list.pop_back(),
+            Some(_) => return Err(RedoxError::WrongType),
+            None => None,
+        };
+        if result.is_some() {
+            shard.changed("rpop");
+        }
+        Ok(result)
+    }
+
+    /// 获取列表指定范围内的元素，键不存在时返回空列表
+    pub async fn lrange(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<Bytes>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::List(list)) => {
+                if list.is_empty() {
+                    return Ok(vec![]);
+                }
+                let len = list.len() as i64;
+                let (start, stop) = normalize_range(start, stop, len);
+                Ok(list.range(start..=stop).cloned().collect())
+            }
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// 列表的长度，键不存在时为 0
+    pub async fn llen(&self, key: &[u8]) -> Result<usize, RedoxError> {
+        let shard = self.read(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::List(list)) => Ok(list.len()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(0),
+        }
+    }
+
+    // 集合操作
+    /// 向集合添加成员
+    /// 
+    /// # Arguments
+    /// * `key` - 集合的键
+    /// * `member` - 要添加的成员
     /// 
     /// # Returns
-    /// 新的存储实例，如果提供了持久化管理器，会自动加载已保存的数据
-    pub fn new(persistence: Option<Persistence>) -> Self {
-        // 尝试从持久化存储加载数据
-        let data = match &persistence {
-            Some(p) => {
-                tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::current().block_on(async {
-                        match p.load().await {
-                            Ok(data) => data,
-                            Err(e) => {
-                                eprintln!("Error loading data: {}", e);
-                                HashMap::new()
-                            }
-                        }
-                    })
-                })
+    /// * `Ok(true)` - 添加成功（成员是新的）
+    /// * `Ok(false)` - 成员已存在
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是集合
+    pub async fn sadd(&self, key: Bytes, member: Bytes) -> Result<bool, RedoxError> {
+        let mut shard = self.write(&key).await;
+        let result = match shard.get_mut(&key) {
+            Some(RedoxValue::Set(set)) => set.insert(member),
+            None => {
+                let mut set = HashSet::new();
+                let result = set.insert(member);
+                shard.data.insert(key, Arc::new(RedoxValue::Set(set)));
+                result
             }
-            None => HashMap::new(),
+            Some(_) => return Err(RedoxError::WrongType),
         };
+        if result {
+            shard.changed("sadd");
+        }
+        Ok(result)
+    }
 
-        let storage = Storage {
-            data: Arc::new(Mutex::new(data)),
-            persistence,
+    pub async fn srem(&self, key: &[u8], member: &[u8]) -> Result<bool, RedoxError> {
+        let mut shard = self.write(key).await;
+        let result = match shard.get_mut(key) {
+            Some(RedoxValue::Set(set)) => set.remove(member),
+            Some(_) => return Err(RedoxError::WrongType),
+            None => false,
         };
+        if result {
+            shard.changed("srem");
+        }
+        Ok(result)
+    }
 
-        // 如果启用了持久化，启动自动保存任务
-        if let Some(p) = storage.persistence.clone() {
-            let data = storage.data.clone();
-            tokio::spawn(async move {
-                p.start_auto_save(data).await;
-            });
+    /// 获取集合的所有成员，键不存在时返回空列表
+    pub async fn smembers(&self, key: &[u8]) -> Result<Vec<Bytes>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::Set(set)) => Ok(set.iter().cloned().collect()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(vec![]),
         }
+    }
 
-        storage
+    /// 集合的成员数，键不存在时为 0
+    pub async fn scard(&self, key: &[u8]) -> Result<usize, RedoxError> {
+        let shard = self.read(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::Set(set)) => Ok(set.len()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(0),
+        }
     }
 
-    /// 标记数据已修改
-    fn mark_dirty(&self) {
-        if let Some(p) = &self.persistence {
-            p.mark_dirty();
+    pub async fn sismember(&self, key: &[u8], member: &[u8]) -> Result<bool, RedoxError> {
+        let shard = self.read(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::Set(set)) => Ok(set.contains(member)),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(false),
         }
     }
 
-    // 字符串操作
-    /// 设置字符串值
+    // 哈希表操作
+    /// 设置哈希表一个或多个字段的值，所有字段在同一次加锁中写入
     /// 
     /// # Arguments
-    /// * `key` - 键
-    /// * `value` - 值
-    pub async fn set_string(&self, key: String, value: String) {
-        let mut data = self.data.lock().await;
-        data.insert(key, RedoxValue::String(value));
-        self.mark_dirty();
+    /// * `key` - 哈希表的键
+    /// * `fields` - 字段名和字段值，同一个字段出现多次时后面的值生效
+    /// 
+    /// # Returns
+    /// * `Ok(n)` - 新加入的字段数，更新已存在的字段不计入
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是哈希表
+    pub async fn hset(&self, key: Bytes, fields: Vec<(Bytes, Bytes)>) -> Result<usize, RedoxError> {
+        let mut shard = self.write(&key).await;
+        if !matches!(shard.get(&key), Some(RedoxValue::Hash(_)) | None) {
+            return Err(RedoxError::WrongType);
+        }
+        let mut added = 0;
+        for (field, value) in fields {
+            // 覆盖字段的值时同时移除它的过期时间，已过期的字段视为新字段
+            let expired = shard.is_field_expired(&key, &field);
+            shard.clear_field_expire(&key, &field);
+            let is_new = match shard.get_mut(&key) {
+                Some(RedoxValue::Hash(hash)) => hash.insert(field, value).is_none() || expired,
+                _ => {
+                    let mut hash = HashMap::new();
+                    hash.insert(field, value);
+                    shard.data.insert(key.clone(), Arc::new(RedoxValue::Hash(hash)));
+                    true
+                }
+            };
+            added += is_new as usize;
+        }
+        shard.changed("hset");
+        Ok(added)
     }
 
-    /// 获取字符串值
+    pub async fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Bytes>, RedoxError> {
+        let (value, expired) = self.get_hash_if_not_expired(key).await;
+        match value.as_deref() {
+            Some(RedoxValue::Hash(hash)) if !expired.contains(field) => Ok(hash.get(field).cloned()),
+            Some(RedoxValue::Hash(_)) => Ok(None),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn hdel(&self, key: &[u8], field: &[u8]) -> Result<bool, RedoxError> {
+        let mut shard = self.write(key).await;
+        let result = match shard.get(key) {
+            Some(RedoxValue::Hash(_)) => {
+                let expired = shard.is_field_expired(key, field);
+                shard.remove_field(key, field) && !expired
+            }
+            Some(_) => return Err(RedoxError::WrongType),
+            None => false,
+        };
+        if result {
+            shard.changed("hdel");
+        }
+        Ok(result)
+    }
+
+    /// 获取哈希表的所有字段，键不存在时返回空表
+    pub async fn hgetall(&self, key: &[u8]) -> Result<HashMap<Bytes, Bytes>, RedoxError> {
+        let (value, expired) = self.get_hash_if_not_expired(key).await;
+        match value.as_deref() {
+            Some(RedoxValue::Hash(hash)) if expired.is_empty() => Ok(hash.clone()),
+            Some(RedoxValue::Hash(hash)) => Ok(hash.iter()
+                .filter(|(field, _)| !expired.contains(*field))
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// 哈希表的字段数，不包括已过期但还没有删除的字段，键不存在时为 0
+    pub async fn hlen(&self, key: &[u8]) -> Result<usize, RedoxError> {
+        let shard = self.read(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::Hash(hash)) => Ok(hash.len().saturating_sub(shard.expired_fields(key).len())),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(0),
+        }
+    }
+
+    /// 获取未过期的值和其中已过期的哈希表字段，并更新键的最后访问时间
+    /// 已过期的字段留给写命令或后台任务删除，读取时在锁外过滤
+    async fn get_hash_if_not_expired(&self, key: &[u8]) -> (Option<Arc<RedoxValue>>, HashSet<Bytes>) {
+        let shard = self.read(key).await;
+        let value = shard.get_shared(key).cloned();
+        if value.is_some() {
+            shard.touch(key);
+        }
+        (value, shard.expired_fields(key))
+    }
+
+    /// 设置哈希表字段的过期时间
     /// 
     /// # Arguments
-    /// * `key` - 键
+    /// * `key` - 哈希表的键
+    /// * `seconds` - 过期秒数，0 表示立即删除字段
+    /// * `fields` - 字段名
     /// 
     /// # Returns
-    /// * `Some(String)` - 找到的值
-    /// * `None` - 键不存在或类型不匹配
-    async fn get_if_not_expired(&self, key: &str) -> Option<RedoxValue> {
-        if self.check_expired(key).await {
-            return None;
+    /// * `Ok(Vec<i64>)` - 每个字段的结果：1 已设置，2 已删除，-2 字段或键不存在
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是哈希表
+    pub async fn hexpire(&self, key: &[u8], seconds: u64, fields: &[Bytes]) -> Result<Vec<i64>, RedoxError> {
+        let mut shard = self.write(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::Hash(_)) => {}
+            Some(_) => return Err(RedoxError::WrongType),
+            None => return Ok(vec![-2; fields.len()]),
+        }
+        let when = self.now_ms().saturating_add(seconds.saturating_mul(1000));
+        let mut results = Vec::with_capacity(fields.len());
+        for field in fields {
+            shard.remove_field_if_expired(key, field);
+            let exists = matches!(shard.get(key), Some(RedoxValue::Hash(hash)) if hash.contains_key(field));
+            results.push(if !exists {
+                -2
+            } else if seconds == 0 {
+                shard.remove_field(key, field);
+                2
+            } else {
+                shard.set_field_expire(Bytes::copy_from_slice(key), field.clone(), when);
+                1
+            });
+        }
+        if results.iter().any(|&result| result > 0) {
+            shard.changed("hexpire");
         }
-        let data = self.data.lock().await;
-        data.get(key).cloned()
+        Ok(results)
+    }
+
+    /// 获取哈希表字段的剩余生存时间（秒，四舍五入）
+    /// 
+    /// # Returns
+    /// * `Ok(Vec<i64>)` - 每个字段的剩余秒数，没有过期时间为 -1，字段或键不存在为 -2
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是哈希表
+    pub async fn httl(&self, key: &[u8], fields: &[Bytes]) -> Result<Vec<i64>, RedoxError> {
+        let shard = self.read(key).await;
+        let hash = match shard.get(key) {
+            Some(RedoxValue::Hash(hash)) => hash,
+            Some(_) => return Err(RedoxError::WrongType),
+            None => return Ok(vec![-2; fields.len()]),
+        };
+        let now = self.now_ms();
+        Ok(fields.iter()
+            .map(|field| match shard.field_expire(key, field) {
+                _ if !hash.contains_key(field) => -2,
+                Some(when) if when <= now => -2,
+                Some(when) => ((when - now + 500) / 1000) as i64,
+                None => -1,
+            })
+            .collect())
     }
 
-    pub async fn get_string(&self, key: &str) -> Option<String> {
-        match self.get_if_not_expired(key).await {
-            Some(RedoxValue::String(s)) => Some(s),
-            _ => None,
+    /// 移除哈希表字段的过期时间
+    /// 
+    /// # Returns
+    /// * `Ok(Vec<i64>)` - 每个字段的结果：1 已移除，-1 没有过期时间，-2 字段或键不存在
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是哈希表
+    pub async fn hpersist(&self, key: &[u8], fields: &[Bytes]) -> Result<Vec<i64>, RedoxError> {
+        let mut shard = self.write(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::Hash(_)) => {}
+            Some(_) => return Err(RedoxError::WrongType),
+            None => return Ok(vec![-2; fields.len()]),
+        }
+        let mut results = Vec::with_capacity(fields.len());
+        for field in fields {
+            shard.remove_field_if_expired(key, field);
+            let exists = matches!(shard.get(key), Some(RedoxValue::Hash(hash)) if hash.contains_key(field));
+            results.push(if !exists {
+                -2
+            } else if shard.clear_field_expire(key, field) {
+                1
+            } else {
+                -1
+            });
         }
+        if results.contains(&1) {
+            shard.changed("hpersist");
+        }
+        Ok(results)
     }
 
-    // 列表操作
-    /// 在列表左端插入元素
+    // 有序集合操作
+    /// 向有序集合添加成员
     /// 
     /// # Arguments
-    /// * `key` - 列表的键
-    /// * `value` - 要插入的值
+    /// * `key` - 有序集合的键
+    /// * `score` - 成员的分数
+    /// * `member` - 成员名
     /// 
     /// # Returns
-    /// 操作后列表的长度
-    pub async fn lpush(&self, key: String, value: String) -> usize {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(&key) {
-            Some(RedoxValue::List(list)) => {
-                list.insert(0, value);
-                list.len()
+    /// * `Ok(true)` - 添加了新成员
+    /// * `Ok(false)` - 更新了已存在的成员
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是有序集合
+    pub async fn zadd(&self, key: Bytes, score: f64, member: Bytes) -> Result<bool, RedoxError> {
+        let mut shard = self.write(&key).await;
+        let result = match shard.get_mut(&key) {
+            Some(RedoxValue::SortedSet(zset)) => {
+                let is_new = !zset.contains_key(&member);
+                zset.insert(member, score);
+                is_new
             }
             None => {
-                let mut list = Vec::new();
-                list.push(value);
-                data.insert(key, RedoxValue::List(list));
-                1
+                let mut zset = SortedSet::new();
+                zset.insert(member, score);
+                shard.data.insert(key, Arc::new(RedoxValue::SortedSet(zset)));
+                true
             }
-            _ => 0
+            Some(_) => return Err(RedoxError::WrongType),
         };
-        if result > 0 {
-            self.mark_dirty();
+        shard.changed("zadd");
+        Ok(result)
+    }
+
+    pub async fn zrem(&self, key: &[u8], member: &[u8]) -> Result<bool, RedoxError> {
+        let mut shard = self.write(key).await;
+        let result = match shard.get_mut(key) {
+            Some(RedoxValue::SortedSet(zset)) => zset.remove(member).is_some(),
+            Some(_) => return Err(RedoxError::WrongType),
+            None => false,
+        };
+        if result {
+            shard.changed("zrem");
         }
-        result
+        Ok(result)
     }
 
-    pub async fn rpush(&self, key: String, value: String) -> usize {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(&key) {
-            Some(RedoxValue::List(list)) => {
-                list.push(value);
-                list.len()
+    /// 按排名获取有序集合的成员和分数，键不存在时返回空列表
+    pub async fn zrange(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<(Bytes, f64)>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::SortedSet(zset)) => {
+                let len = zset.len() as i64;
+                if len == 0 {
+                    return Ok(vec![]);
+                }
+                
+                // 索引已按分数排序，只遍历到范围所在的位置
+                let (start, stop) = normalize_range(start, stop, len);
+                Ok(zset.range_by_rank(start, stop)
+                    .into_iter()
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect())
             }
-            None => {
-                let mut list = Vec::new();
-                list.push(value);
-                data.insert(key, RedoxValue::List(list));
-                1
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// 有序集合的成员数，键不存在时为 0
+    pub async fn zcard(&self, key: &[u8]) -> Result<usize, RedoxError> {
+        let shard = self.read(key).await;
+        match shard.get(key) {
+            Some(RedoxValue::SortedSet(zset)) => Ok(zset.len()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(0),
+        }
+    }
+
+    /// 获取分数在 [min, max] 范围内的成员，键不存在时返回空列表
+    pub async fn zrangebyscore(&self, key: &[u8], min: f64, max: f64) -> Result<Vec<(Bytes, f64)>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::SortedSet(zset)) => {
+                // 在按分数排序的索引中直接定位到 min，分数相同时按成员字典序排列
+                Ok(zset.range_by_score(min, max)
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect())
             }
-            _ => 0
-        };
-        if result > 0 {
-            self.mark_dirty();
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(vec![]),
         }
-        result
     }
 
-    pub async fn lpop(&self, key: &str) -> Option<String> {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(key) {
-            Some(RedoxValue::List(list)) => {
-                if list.is_empty() {
-                    None
-                } else {
-                    Some(list.remove(0))
-                }
+    // 地理位置操作
+    /// 添加地理位置成员，坐标以 geohash 分数的形式存入有序集合
+    /// 
+    /// # Arguments
+    /// * `key` - 有序集合的键
+    /// * `members` - (经度, 纬度, 成员) 列表，坐标需已校验
+    /// 
+    /// # Returns
+    /// * `Ok(usize)` - 新添加的成员数量
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是有序集合
+    pub async fn geoadd(&self, key: Bytes, members: Vec<(f64, f64, Bytes)>) -> Result<usize, RedoxError> {
+        let mut shard = self.write(&key).await;
+        // 先检查类型，类型不符时不创建键，也不复制仍被读取者共享的值
+        match shard.data.get(&key).map(|value| &**value) {
+            Some(RedoxValue::SortedSet(_)) => {}
+            Some(_) => return Err(RedoxError::WrongType),
+            None => {
+                shard.data.insert(key.clone(), Arc::new(RedoxValue::SortedSet(SortedSet::new())));
             }
-            _ => None,
+        }
+        let Some(RedoxValue::SortedSet(zset)) = shard.get_mut(&key) else {
+            unreachable!("the key holds a sorted set");
         };
-        if result.is_some() {
-            self.mark_dirty();
+        let mut added = 0;
+        for (lon, lat, member) in members {
+            if zset.insert(member, geo::encode(lon, lat)).is_none() {
+                added += 1;
+            }
         }
-        result
+        shard.changed("geoadd");
+        Ok(added)
     }
 
-    pub async fn rpop(&self, key: &str) -> Option<String> {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(key) {
-            Some(RedoxValue::List(list)) => list.pop(),
-            _ => None,
-        };
-        if result.is_some() {
-            self.mark_dirty();
+    /// 获取成员的经纬度
+    /// 
+    /// # Returns
+    /// 与输入顺序一致的 (经度, 纬度) 列表，成员不存在时为 None
+    pub async fn geopos(&self, key: &[u8], members: &[Bytes]) -> Result<Vec<Option<(f64, f64)>>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::SortedSet(zset)) => Ok(members.iter()
+                .map(|member| zset.get(member).map(|score| geo::decode(*score)))
+                .collect()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(vec![None; members.len()]),
         }
-        result
     }
 
-    pub async fn lrange(&self, key: &str, start: i64, stop: i64) -> Option<Vec<String>> {
-        match self.get_if_not_expired(key).await {
-            Some(RedoxValue::List(list)) => {
-                let len = list.len() as i64;
-                let (start, stop) = normalize_range(start, stop, len);
-                Some(list[start..=stop].to_vec())
+    /// 计算两个成员之间的距离（米）
+    /// 
+    /// # Returns
+    /// * `Ok(Some(f64))` - 距离
+    /// * `Ok(None)` - 键或任一成员不存在
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是有序集合
+    pub async fn geodist(&self, key: &[u8], member1: &[u8], member2: &[u8]) -> Result<Option<f64>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::SortedSet(zset)) => {
+                let (Some(score1), Some(score2)) = (zset.get(member1), zset.get(member2)) else {
+                    return Ok(None);
+                };
+                let (lon1, lat1) = geo::decode(*score1);
+                let (lon2, lat2) = geo::decode(*score2);
+                Ok(Some(geo::distance(lon1, lat1, lon2, lat2)))
             }
-            _ => None,
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(None),
         }
     }
 
-    // 集合操作
-    /// 向集合添加成员
+    /// 搜索指定范围内的成员
     /// 
     /// # Arguments
-    /// * `key` - 集合的键
-    /// * `member` - 要添加的成员
+    /// * `key` - 有序集合的键
+    /// * `origin` - 搜索中心
+    /// * `shape` - 搜索范围
+    /// * `ascending` - 按距离排序的方向，None 表示不排序
+    /// * `count` - 最多返回的成员数量，指定时默认按距离升序
     /// 
     /// # Returns
-    /// * `true` - 添加成功（成员是新的）
-    /// * `false` - 添加失败（成员已存在或类型错误）
-    pub async fn sadd(&self, key: String, member: String) -> bool {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(&key) {
-            Some(RedoxValue::Set(set)) => set.insert(member),
-            None => {
-                let mut set = HashSet::new();
-                let result = set.insert(member);
-                data.insert(key, RedoxValue::Set(set));
-                result
+    /// * `Ok(Some(Vec))` - (成员, 距离（米）, (经度, 纬度)) 列表
+    /// * `Ok(None)` - FROMMEMBER 指定的成员不存在
+    /// * `Err(RedoxError::WrongType)` - 键的类型不是有序集合
+    pub async fn geosearch(
+        &self,
+        key: &[u8],
+        origin: &GeoOrigin,
+        shape: &GeoShape,
+        ascending: Option<bool>,
+        count: Option<usize>,
+    ) -> Result<Option<Vec<(Bytes, f64, (f64, f64))>>, RedoxError> {
+        let value = self.get_if_not_expired(key).await;
+        let empty = SortedSet::new();
+        let zset = match value.as_deref() {
+            Some(RedoxValue::SortedSet(zset)) => zset,
+            Some(_) => return Err(RedoxError::WrongType),
+            None => &empty,
+        };
+
+        let center = match origin {
+            GeoOrigin::Member(member) => match zset.get(member) {
+                Some(score) => geo::decode(*score),
+                None => return Ok(None),
+            },
+            GeoOrigin::LonLat(lon, lat) => (*lon, *lat),
+        };
+
+        let factor = shape.unit().to_meters();
+        let (half_width, half_height) = match shape {
+            GeoShape::Radius { radius, .. } => (radius * factor, radius * factor),
+            GeoShape::Box { width, height, .. } => (width * factor / 2.0, height * factor / 2.0),
+        };
+        // 只查找覆盖搜索范围的几个 geohash 单元格对应的分数区间，再按准确的距离过滤
+        let mut matches: Vec<(Bytes, f64, (f64, f64))> = geo::covering_ranges(center, half_width, half_height)
+            .into_iter()
+            .flat_map(|(min, max)| zset.range_by_score(min, max))
+            .filter_map(|(member, score)| {
+                let point = geo::decode(score);
+                let dist = match shape {
+                    GeoShape::Radius { radius, .. } => {
+                        let dist = geo::distance(center.0, center.1, point.0, point.1);
+                        (dist <= radius * factor).then_some(dist)
+                    }
+                    GeoShape::Box { width, height, .. } => {
+                        geo::within_box(center, width * factor, height * factor, point)
+                    }
+                }?;
+                Some((member.clone(), dist, point))
+            })
+            .collect();
+
+        match ascending.or(count.map(|_| true)) {
+            Some(true) => matches.sort_by(|a, b| a.1.total_cmp(&b.1)),
+            Some(false) => matches.sort_by(|a, b| b.1.total_cmp(&a.1)),
+            None => {}
+        }
+        if let Some(count) = count {
+            matches.truncate(count);
+        }
+        Ok(Some(matches))
+    }
+
+    // JSON 文档操作
+    /// 设置 JSON 文档中路径指向的值
+    /// 
+    /// # Arguments
+    /// * `key` - 文档的键，不存在时只能在根路径上创建
+    /// * `path` - JSON 路径
+    /// * `value` - 新的值
+    /// 
+    /// # Returns
+    /// * `Ok(true)` - 设置成功
+    /// * `Ok(false)` - 路径的父节点不存在
+    /// * `Err(RedoxError)` - 路径无效或键的类型不匹配
+    pub async fn json_set(&self, key: Bytes, path: &str, value: serde_json::Value) -> Result<bool, RedoxError> {
+        let segments = json_path::parse(path)?;
+        // 已过期的键视为不存在
+        let mut shard = self.write(&key).await;
+        let result = match shard.get_mut(&key) {
+            Some(RedoxValue::Json(doc)) => json_path::set(doc, &segments, value),
+            Some(_) => return Err(RedoxError::WrongType),
+            None if segments.is_empty() => {
+                shard.data.insert(key, Arc::new(RedoxValue::Json(value)));
+                true
             }
-            _ => false
+            None => return Err("New objects must be created at the root".into()),
         };
         if result {
-            self.mark_dirty();
+            shard.changed("json.set");
         }
-        result
+        Ok(result)
     }
 
-    pub async fn srem(&self, key: &str, member: &str) -> bool {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(key) {
-            Some(RedoxValue::Set(set)) => set.remove(member),
-            _ => false,
+    /// 获取 JSON 文档中路径指向的值
+    /// 
+    /// # Returns
+    /// * `Ok(Some(Value))` - 找到的值
+    /// * `Ok(None)` - 键或路径不存在
+    /// * `Err(RedoxError)` - 路径无效或键的类型不匹配
+    pub async fn json_get(&self, key: &[u8], path: &str) -> Result<Option<serde_json::Value>, RedoxError> {
+        let segments = json_path::parse(path)?;
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::Json(doc)) => Ok(json_path::get(doc, &segments).cloned()),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// 删除 JSON 文档中路径指向的值，根路径会删除整个键
+    /// 
+    /// # Returns
+    /// 删除的节点数量
+    pub async fn json_del(&self, key: &[u8], path: &str) -> Result<usize, RedoxError> {
+        let segments = json_path::parse(path)?;
+        let mut shard = self.write(key).await;
+        let deleted = match shard.get_mut(key) {
+            Some(RedoxValue::Json(_)) if segments.is_empty() => {
+                shard.remove(key);
+                true
+            }
+            Some(RedoxValue::Json(doc)) => json_path::delete(doc, &segments),
+            Some(_) => return Err(RedoxError::WrongType),
+            None => false,
         };
-        if result {
-            self.mark_dirty();
+        if deleted {
+            shard.changed("json.del");
         }
-        result
+        Ok(if deleted { 1 } else { 0 })
+    }
+
+    /// 给 JSON 文档中的数字加上增量
+    /// 
+    /// # Returns
+    /// * `Ok(Some(Value))` - 增加后的值
+    /// * `Ok(None)` - 键或路径不存在
+    /// * `Err(RedoxError)` - 目标不是数字、结果溢出、路径无效或键的类型不匹配
+    pub async fn json_numincrby(
+        &self,
+        key: &[u8],
+        path: &str,
+        increment: &serde_json::Number,
+    ) -> Result<Option<serde_json::Value>, RedoxError> {
+        let segments = json_path::parse(path)?;
+        let mut shard = self.write(key).await;
+        let target = match shard.get_mut(key) {
+            Some(RedoxValue::Json(doc)) => match json_path::get_mut(doc, &segments) {
+                Some(target) => target,
+                None => return Ok(None),
+            },
+            Some(_) => return Err(RedoxError::WrongType),
+            None => return Ok(None),
+        };
+        let current = match target {
+            serde_json::Value::Number(n) => n.clone(),
+            _ => return Err("Path does not point to a number".into()),
+        };
+
+        // 两个整数相加保持整数，否则按浮点数计算
+        let result = match (current.as_i64(), increment.as_i64()) {
+            (Some(a), Some(b)) => a.checked_add(b)
+                .map(serde_json::Number::from)
+                .ok_or("Increment would overflow")?,
+            _ => {
+                let sum = current.as_f64().unwrap_or(0.0) + increment.as_f64().unwrap_or(0.0);
+                serde_json::Number::from_f64(sum)
+                    .ok_or("Increment would produce NaN or Infinity")?
+            }
+        };
+        *target = serde_json::Value::Number(result.clone());
+        shard.changed("json.numincrby");
+        Ok(Some(serde_json::Value::Number(result)))
+    }
+
+    // 时间序列操作
+    /// 创建空的时间序列
+    /// 
+    /// # Arguments
+    /// * `key` - 时间序列的键
+    /// * `retention_ms` - 样本保留时长（毫秒），0 表示永久保留
+    /// 
+    /// # Returns
+    /// * `Ok(())` - 创建成功
+    /// * `Err(RedoxError)` - 键已存在
+    pub async fn ts_create(&self, key: Bytes, retention_ms: u64) -> Result<(), RedoxError> {
+        let mut shard = self.write(&key).await;
+        if shard.data.contains_key(&key) {
+            return Err("Key already exists".into());
+        }
+        shard.data.insert(key, Arc::new(RedoxValue::TimeSeries(TimeSeries {
+            retention_ms,
+            ..Default::default()
+        })));
+        shard.changed("ts.create");
+        Ok(())
+    }
+
+    /// 对键上的时间序列执行修改，不存在时自动创建
+    /// 指定了保留时长时会同时更新序列的保留策略；f 返回错误时不修改样本，
+    /// 这时删除自动创建的键、恢复原来的保留时长，键保持执行前的状态
+    async fn with_timeseries<T>(
+        &self,
+        key: Bytes,
+        retention_ms: Option<u64>,
+        event: &'static str,
+        f: impl FnOnce(&mut TimeSeries) -> Result<T, String>,
+    ) -> Result<T, RedoxError> {
+        let mut shard = self.write(&key).await;
+        // 先检查类型，类型不符时不复制仍被读取者共享的值
+        let created = match shard.data.get(&key).map(|value| &**value) {
+            Some(RedoxValue::TimeSeries(_)) => false,
+            Some(_) => return Err(RedoxError::WrongType),
+            None => true,
+        };
+        if created {
+            shard.data.insert(key.clone(), Arc::new(RedoxValue::TimeSeries(TimeSeries::default())));
+        }
+        let Some(RedoxValue::TimeSeries(series)) = shard.get_mut(&key) else {
+            unreachable!("the key holds a time series");
+        };
+        let previous = series.retention_ms;
+        if let Some(retention_ms) = retention_ms {
+            series.retention_ms = retention_ms;
+        }
+        match f(series) {
+            Ok(result) => {
+                shard.changed(event);
+                Ok(result)
+            }
+            Err(e) if created => {
+                shard.data.swap_remove(&key);
+                Err(e.into())
+            }
+            Err(e) => {
+                series.retention_ms = previous;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// 添加样本
+    /// 
+    /// # Arguments
+    /// * `key` - 时间序列的键，不存在时自动创建
+    /// * `timestamp` - 毫秒时间戳，None 表示当前时间
+    /// * `value` - 样本值
+    /// * `retention_ms` - 可选的保留时长
+    /// 
+    /// # Returns
+    /// 写入样本的时间戳
+    pub async fn ts_add(
+        &self,
+        key: Bytes,
+        timestamp: Option<u64>,
+        value: f64,
+        retention_ms: Option<u64>,
+    ) -> Result<u64, RedoxError> {
+        let timestamp = timestamp.unwrap_or_else(|| self.now_ms());
+        self.with_timeseries(key, retention_ms, "ts.add", |series| {
+            timeseries::add(series, timestamp, value).map(|_| timestamp)
+        }).await
+    }
+
+    /// 在最新样本的基础上累加
+    /// 
+    /// # Returns
+    /// 写入样本的时间戳
+    pub async fn ts_incrby(
+        &self,
+        key: Bytes,
+        value: f64,
+        timestamp: Option<u64>,
+        retention_ms: Option<u64>,
+    ) -> Result<u64, RedoxError> {
+        let timestamp = timestamp.unwrap_or_else(|| self.now_ms());
+        self.with_timeseries(key, retention_ms, "ts.incrby", |series| {
+            timeseries::incr_by(series, timestamp, value)
+        }).await
+    }
+
+    /// 查询时间范围内的样本
+    /// 
+    /// # Returns
+    /// (时间戳, 数值) 列表，键不存在时为空
+    pub async fn ts_range(
+        &self,
+        key: &[u8],
+        from: u64,
+        to: u64,
+        aggregation: Option<(TsAggregation, u64)>,
+    ) -> Result<Vec<(u64, f64)>, RedoxError> {
+        match self.get_if_not_expired(key).await.as_deref() {
+            Some(RedoxValue::TimeSeries(series)) => Ok(timeseries::range(series, from, to, aggregation)),
+            Some(_) => Err(RedoxError::WrongType),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// 批量设置字符串值
+    pub async fn mset(&self, pairs: Vec<(Bytes, Bytes)>) -> usize {
+        let mut shards = self.write_keys(pairs.iter().map(|(key, _)| key)).await;
+        let mut count = 0;
+        for (key, value) in pairs {
+            let shard = shards.get_mut(&key);
+            shard.insert(key.clone(), RedoxValue::String(value));
+            shard.observers.notify(|observer| observer.on_set(&key, "set"));
+            count += 1;
+        }
+        count
+    }
+
+    /// 批量获取字符串值
+    pub async fn mget(&self, keys: &[Bytes]) -> Vec<Option<Bytes>> {
+        let shards = self.read_keys(keys).await;
+        keys.iter().map(|key| {
+            match shards.get(key).get(key) {
+                Some(RedoxValue::String(s)) => Some(s.clone()),
+                _ => None,
+            }
+        }).collect()
+    }
+
+    /// 设置键的过期时间（秒）
+    pub async fn expire(&self, key: &[u8], seconds: u64, condition: Option<ExpireCondition>) -> bool {
+        let when = self.now_ms().saturating_add(seconds.saturating_mul(1000));
+        self.pexpire_at(key, when, condition).await
     }
 
-    pub async fn smembers(&self, key: &str) -> Option<Vec<String>> {
-        match self.get_if_not_expired(key).await {
-            Some(RedoxValue::Set(set)) => Some(set.iter().cloned().collect()),
-            _ => None,
-        }
+    /// 设置键的过期时间（毫秒）
+    pub async fn pexpire(&self, key: &[u8], milliseconds: u64, condition: Option<ExpireCondition>) -> bool {
+        self.pexpire_at(key, self.now_ms().saturating_add(milliseconds), condition).await
     }
 
-    pub async fn sismember(&self, key: &str, member: &str) -> bool {
-        let data = self.data.lock().await;
-        match data.get(key) {
-            Some(RedoxValue::Set(set)) => set.contains(member),
-            _ => false,
-        }
+    /// 设置键在指定的 Unix 时间（秒）过期
+    pub async fn expire_at(&self, key: &[u8], timestamp: u64, condition: Option<ExpireCondition>) -> bool {
+        self.pexpire_at(key, timestamp.saturating_mul(1000), condition).await
     }
 
-    // 哈希表操作
-    /// 设置哈希表字段的值
+    /// 设置键在指定的 Unix 时间（毫秒）过期
     /// 
     /// # Arguments
-    /// * `key` - 哈希表的键
-    /// * `field` - 字段名
-    /// * `value` - 字段值
-    /// 
-    /// # Returns
-    /// * `true` - 设置了新字段
-    /// * `false` - 更新了已存在的字段
-    pub async fn hset(&self, key: String, field: String, value: String) -> bool {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(&key) {
-            Some(RedoxValue::Hash(hash)) => {
-                let is_new = !hash.contains_key(&field);
-                hash.insert(field, value);
-                is_new
-            }
-            None => {
-                let mut hash = HashMap::new();
-                hash.insert(field, value);
-                data.insert(key, RedoxValue::Hash(hash));
-                true
-            }
-            _ => false
+    /// * `key` - 键
+    /// * `timestamp_ms` - 过期的毫秒级 Unix 时间戳
+    /// * `condition` - 可选的设置条件，没有过期时间的键视为永不过期
+    /// 
+    /// # Returns
+    /// * `true` - 设置成功
+    /// * `false` - 键不存在或条件不满足
+    pub async fn pexpire_at(&self, key: &[u8], timestamp_ms: u64, condition: Option<ExpireCondition>) -> bool {
+        let mut shard = self.write(key).await;
+        if !shard.data.contains_key(key) {
+            return false;
+        }
+        let current = shard.expires.get(key).copied();
+        let allowed = match condition {
+            None => true,
+            Some(ExpireCondition::Nx) => current.is_none(),
+            Some(ExpireCondition::Xx) => current.is_some(),
+            Some(ExpireCondition::Gt) => current.is_some_and(|c| timestamp_ms > c),
+            Some(ExpireCondition::Lt) => current.is_none_or(|c| timestamp_ms < c),
         };
-        if result {
-            self.mark_dirty();
+        if !allowed {
+            return false;
         }
-        result
+        shard.set_expire(Bytes::copy_from_slice(key), timestamp_ms);
+        shard.changed("expire");
+        true
     }
 
-    pub async fn hget(&self, key: &str, field: &str) -> Option<String> {
-        match self.get_if_not_expired(key).await {
-            Some(RedoxValue::Hash(hash)) => hash.get(field).cloned(),
-            _ => None,
+    /// 清理到期的键和哈希表字段，逐个分片加锁，清理一个分片时其他分片上的命令不受影响
+    /// 每个分片只查看按过期时间排序的索引中已到期的部分，且最多删除 `EXPIRE_BATCH` 个键和 `EXPIRE_BATCH` 个字段
+    /// 
+    /// # Returns
+    /// 是否还有已到期的键或字段没有清理，此时应当很快再清理一次
+    pub async fn cleanup_expired(&self) -> bool {
+        let mut more = false;
+        for shard in self.shards.iter() {
+            let mut shard = shard.write().await;
+            let now = self.now_ms();
+            let (_, keys_remaining) = shard.expire_due(now, EXPIRE_BATCH);
+            let (_, fields_remaining) = shard.expire_fields_due(now, EXPIRE_BATCH);
+            more |= keys_remaining || fields_remaining;
         }
+        more
     }
 
-    pub async fn hdel(&self, key: &str, field: &str) -> bool {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(key) {
-            Some(RedoxValue::Hash(hash)) => hash.remove(field).is_some(),
-            _ => false,
-        };
-        if result {
-            self.mark_dirty();
-        }
-        result
+    /// 值的类型名称，用于 TYPE
+    ///
+    /// # Returns
+    /// * `Some(&str)` - 类型名称
+    /// * `None` - 键不存在
+    pub async fn value_type(&self, key: &[u8]) -> Option<&'static str> {
+        let shard = self.read(key).await;
+        shard.get(key).map(RedoxValue::type_name)
     }
 
-    pub async fn hgetall(&self, key: &str) -> Option<HashMap<String, String>> {
-        let data = self.data.lock().await;
-        match data.get(key) {
-            Some(RedoxValue::Hash(hash)) => Some(hash.clone()),
-            _ => None,
-        }
+    /// 获取键的内部表示名称
+    /// 
+    /// # Returns
+    /// * `Some(&str)` - 内部表示，如 "hashmap"
+    /// * `None` - 键不存在
+    pub async fn object_encoding(&self, key: &[u8]) -> Option<&'static str> {
+        let shard = self.read(key).await;
+        shard.get(key).map(memory::encoding)
     }
 
-    // 有序集合操作
-    /// 向有序集合添加成员
+    /// 估算键占用的内存字节数
     /// 
     /// # Arguments
-    /// * `key` - 有序集合的键
-    /// * `score` - 成员的分数
-    /// * `member` - 成员名
+    /// * `key` - 键
+    /// * `samples` - 集合类型采样的元素数量，0 表示统计全部元素
     /// 
     /// # Returns
-    /// * `true` - 添加了新成员
-    /// * `false` - 更新了已存在的成员
-    pub async fn zadd(&self, key: String, score: f64, member: String) -> bool {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(&key) {
-            Some(RedoxValue::SortedSet(zset)) => {
-                let is_new = !zset.contains_key(&member);
-                zset.insert(member, score);
-                is_new
-            }
-            None => {
-                let mut zset = BTreeMap::new();
-                zset.insert(member, score);
-                data.insert(key, RedoxValue::SortedSet(zset));
-                true
-            }
-            _ => false
-        };
-        if result {
-            self.mark_dirty();
-        }
-        result
+    /// * `Some(usize)` - 估算的字节数
+    /// * `None` - 键不存在
+    pub async fn memory_usage(&self, key: &[u8], samples: usize) -> Option<usize> {
+        let shard = self.read(key).await;
+        let value = shard.get(key)?;
+        Some(memory::key_usage(key, value, samples))
     }
 
-    pub async fn zrem(&self, key: &str, member: &str) -> bool {
-        let mut data = self.data.lock().await;
-        let result = match data.get_mut(key) {
-            Some(RedoxValue::SortedSet(zset)) => zset.remove(member).is_some(),
-            _ => false,
-        };
-        if result {
-            self.mark_dirty();
-        }
-        result
+    /// 所有键估算的总字节数
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
     }
 
-    pub async fn zrange(&self, key: &str, start: i64, stop: i64) -> Option<Vec<(String, f64)>> {
-        match self.get_if_not_expired(key).await {
-            Some(RedoxValue::SortedSet(zset)) => {
-                let len = zset.len() as i64;
-                if len == 0 {
-                    return Some(vec![]);
+    /// 设置内存上限和超过上限时的处理方式，立即生效
+    /// 
+    /// # Arguments
+    /// * `maxmemory` - 内存上限（字节），0 表示不限制
+    /// * `policy` - 淘汰策略
+    pub fn set_maxmemory(&self, maxmemory: usize, policy: Policy) {
+        self.maxmemory.store(maxmemory, Ordering::Relaxed);
+        self.maxmemory_policy.store(policy as u8, Ordering::Relaxed);
+    }
+
+    /// 设置惰性释放阈值，元素数超过阈值的值在后台释放，立即生效
+    pub fn set_lazyfree_threshold(&self, threshold: usize) {
+        self.lazyfree.set_threshold(threshold);
+    }
+
+    /// 内存超过上限时的处理方式
+    fn maxmemory_policy(&self) -> Policy {
+        Policy::from_u8(self.maxmemory_policy.load(Ordering::Relaxed))
+    }
+
+    /// 在可能增加内存占用的命令执行前调用，内存超过上限时按策略淘汰键直到低于上限
+    /// 使用按键保存的持久化后端时不淘汰键，而是把最久没有访问的值移到后端中，淘汰策略不起作用
+    /// 
+    /// # Returns
+    /// * `Ok(())` - 没有设置上限、内存低于上限或已淘汰足够的键
+    /// * `Err(RedoxError::Oom)` - 策略为 noeviction，或者已经没有可以淘汰或移出的键
+    pub async fn ensure_memory(&self) -> Result<(), RedoxError> {
+        let maxmemory = self.maxmemory.load(Ordering::Relaxed);
+        if maxmemory == 0 {
+            return Ok(());
+        }
+        if let Some(p) = self.persistence.as_ref().filter(|p| p.keyed()) {
+            while self.used_memory() > maxmemory {
+                if !self.spill_one(p).await {
+                    return Err(RedoxError::Oom);
                 }
-                
-                // 按分数升序排序
-                let mut members: Vec<(String, f64)> = zset.iter()
-                    .map(|(k, v)| (k.clone(), *v))
-                    .collect();
-                members.sort_by(|a, b| {
-                    a.1.partial_cmp(&b.1)
-                        .unwrap_or(std::cmp::Ordering::Equal)
-                        .then(a.0.cmp(&b.0))
-                });
-                
-                let (start, stop) = normalize_range(start, stop, len);
-                Some(members.into_iter()
-                    .skip(start)
-                    .take(stop - start + 1)
-                    .collect())
             }
-            _ => None,
+            return Ok(());
+        }
+        let policy = self.maxmemory_policy();
+        while self.used_memory() > maxmemory {
+            if policy == Policy::NoEviction || !self.evict_one(policy).await {
+                return Err(RedoxError::Oom);
+            }
         }
+        Ok(())
     }
 
-    pub async fn zrangebyscore(&self, key: &str, min: f64, max: f64) -> Option<Vec<(String, f64)>> {
-        let data = self.data.lock().await;
-        match data.get(key) {
-            Some(RedoxValue::SortedSet(zset)) => {
-                // 先按分数排序，分数同时按成员字典序排序
-                let mut members: Vec<(String, f64)> = zset.iter()
-                    .map(|(k, v)| (k.clone(), *v))
-                    .collect();
-                members.sort_by(|a, b| {
-                    a.1.partial_cmp(&b.1)
-                        .unwrap_or(std::cmp::Ordering::Equal)
-                        .then(a.0.cmp(&b.0))
-                });
-                
-                Some(members.into_iter()
-                    .filter(|(_, score)| *score >= min && *score <= max)
-                    .collect())
+    /// 替换按键前缀的配额，立即生效
+    /// 持有所有分片的读锁重新统计每个前缀下已有的键数和字节数，其间写命令等待
+    pub async fn set_quotas(&self, quotas: Vec<Quota>) {
+        let shards = self.read_all().await;
+        let mut usages: Vec<(Quota, usize, usize)> = quotas.into_iter().map(|quota| (quota, 0, 0)).collect();
+        if !usages.is_empty() {
+            for shard in &shards {
+                let resident = shard.data.iter().map(|(key, value)| (key, memory::measure(key, value).1));
+                let spilled = shard.spilled.iter().map(|(key, spilled)| (key, spilled.bytes));
+                for (key, bytes) in resident.chain(spilled) {
+                    for (_, keys, total) in usages.iter_mut().filter(|(quota, ..)| key.starts_with(&quota.prefix)) {
+                        *keys += 1;
+                        *total += bytes;
+                    }
+                }
             }
-            _ => None,
         }
+        self.quotas.replace(usages);
     }
 
-    /// 批量设置字符串值
-    pub async fn mset(&self, pairs: Vec<(String, String)>) -> usize {
-        let mut data = self.data.lock().await;
-        let mut count = 0;
-        for (key, value) in pairs {
-            data.insert(key, RedoxValue::String(value));
-            count += 1;
+    /// 在可能增加内存占用的命令执行前预留写入的键所属前缀的配额
+    /// 
+    /// # Arguments
+    /// * `keys` - 命令写入的键
+    /// * `bytes` - 命令可能写入的字节数的估算
+    /// 
+    /// # Returns
+    /// * `Ok(Some(QuotaReservation))` - 所有键所属的前缀都没有达到配额，命令执行完后释放预留
+    /// * `Ok(None)` - 没有配置配额
+    /// * `Err(RedoxError::Quota)` - 某个键所属的前缀已达到键数或字节数配额
+    pub async fn reserve_quota(&self, keys: &[&Bytes], bytes: usize) -> Result<Option<QuotaReservation>, RedoxError> {
+        if self.quotas.is_empty() {
+            return Ok(None);
         }
-        if count > 0 {
-            self.mark_dirty();
+        let mut existing = Vec::with_capacity(keys.len());
+        for &key in keys {
+            let index = self.shard_index(key);
+            existing.push((key, self.shards[index].read().await.contains(key)));
         }
-        count
+        self.quotas.reserve(&existing, bytes).map(Some).map_err(RedoxError::Quota)
     }
 
-    /// 批量获取字符串值
-    pub async fn mget(&self, keys: &[String]) -> Vec<Option<String>> {
-        let data = self.data.lock().await;
-        keys.iter().map(|key| {
-            match data.get(key) {
-                Some(RedoxValue::String(s)) => Some(s.clone()),
-                _ => None,
+    /// 淘汰一个键，从随机的分片开始找到第一个有符合策略的键的分片，按策略从中选出淘汰的键
+    /// 
+    /// # Returns
+    /// 是否淘汰了键，所有分片都没有符合策略的键时为 false
+    async fn evict_one(&self, policy: Policy) -> bool {
+        let start = fastrand::usize(..SHARD_COUNT);
+        for offset in 0..SHARD_COUNT {
+            let mut shard = self.shards[(start + offset) % SHARD_COUNT].write().await;
+            if let Some(key) = shard.eviction_candidate(policy, self.created_ms) {
+                shard.delete_lazily(&key);
+                shard.observers.notify(|observer| observer.on_evict(&key));
+                self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                return true;
             }
-        }).collect()
-    }
-
-    /// 设置键的过期时间（秒）
-    pub async fn expire(&self, key: &str, seconds: u64) -> bool {
-        let data = self.data.lock().await;
-        if !data.contains_key(key) {
-            return false;
-        }
-        
-        let expires = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() + seconds;
-            
-        if let Some(p) = &self.persistence {
-            p.set_expiry(key.to_string(), expires).await;
-            self.mark_dirty();
         }
-        true
+        false
     }
 
-    /// 检查键是否过期
-    async fn is_expired(&self, key: &str) -> bool {
-        if let Some(p) = &self.persistence {
-            if let Some(expires) = p.get_expiry(key).await {
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                return now >= expires;
+    /// 把一个值移到按键保存的持久化后端，从随机的分片开始找到第一个有可以移出的键的分片，
+    /// 按 `Shard::spill_candidate` 选出键；在锁外写入后端，写入期间值被修改或删除时不移出
+    /// 
+    /// # Returns
+    /// 是否写入了后端，所有分片都没有可以移出的键或写入失败时为 false
+    async fn spill_one(&self, p: &Persistence) -> bool {
+        let _appending = p.lock_appends().await;
+        // 在读取键的状态之前取出 FLUSHALL 的标记，写入的状态总是在 FLUSHALL 之后读取的
+        let clear = p.take_cleared();
+        let start = fastrand::usize(..SHARD_COUNT);
+        for offset in 0..SHARD_COUNT {
+            let index = (start + offset) % SHARD_COUNT;
+            let shard = self.shards[index].read().await;
+            let Some((key, entry)) = shard.spill_candidate(self.created_ms)
+                .and_then(|key| shard.entry(&key).map(|entry| (key, entry))) else {
+                continue;
+            };
+            drop(shard);
+            let value = entry.value.clone();
+            let changes = Changes { clear, keys: vec![(key.clone(), Some(entry))], functions: None, sync: false };
+            if let Err(e) = p.append(changes).await {
+                warning!("Error writing a value to the persistence backend: {}", e);
+                p.restore_dirty(clear, []);
+                return false;
+            }
+            let mut shard = self.shards[index].write().await;
+            if shard.data.get(&key).is_some_and(|current| Arc::ptr_eq(current, &value)) {
+                let seq = self.spills.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Some(spilled) = shard.spill(&key, seq) {
+                    drop(value);
+                    self.lazyfree.free(spilled);
+                }
             }
+            return true;
         }
+        p.restore_dirty(clear, []);
         false
     }
 
-    /// 清理过期的键
-    pub async fn cleanup_expired(&self) {
-        let mut data = self.data.lock().await;
-        let mut expired_keys = Vec::new();
-        
-        // 收集过期键
-        for key in data.keys() {
-            if self.is_expired(key).await {
-                expired_keys.push(key.clone());
-            }
+    /// 汇总所有分片的内存统计，用于 MEMORY STATS
+    pub async fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats::default();
+        for shard in self.shards.iter() {
+            stats.merge(&shard.read().await.memory);
         }
-        
-        // 删除过期的键
-        if !expired_keys.is_empty() {
-            for key in expired_keys {
-                data.remove(&key);
-            }
+        stats
+    }
+
+    // 函数库操作
+    /// 获取所有函数库的源码
+    pub async fn function_libraries(&self) -> BTreeMap<String, String> {
+        self.functions.lock().await.clone()
+    }
+
+    /// 保存函数库源码，同名的库会被覆盖
+    /// 
+    /// # Arguments
+    /// * `library` - 库名
+    /// * `code` - 源码
+    pub async fn set_function_library(&self, library: String, code: String) {
+        self.functions.lock().await.insert(library, code);
+        self.mark_dirty();
+    }
+
+    /// 删除函数库
+    /// 
+    /// # Returns
+    /// 库是否存在
+    pub async fn remove_function_library(&self, library: &str) -> bool {
+        let removed = self.functions.lock().await.remove(library).is_some();
+        if removed {
             self.mark_dirty();
         }
+        removed
+    }
+
+    /// 删除所有函数库
+    pub async fn clear_function_libraries(&self) {
+        self.functions.lock().await.clear();
+        self.mark_dirty();
     }
 
     /// 获取存储统计信息
     pub async fn info(&self) -> HashMap<String, String> {
-        let data = self.data.lock().await;
         let mut info = HashMap::new();
         
         // 先统计所有类型的键数量
+        let mut keys = 0;
         let mut strings = 0;
         let mut lists = 0;
         let mut sets = 0;
         let mut hashes = 0;
         let mut zsets = 0;
+        let mut jsons = 0;
+        let mut timeseries = 0;
+        let mut spilled_keys = 0;
         
-        for value in data.values() {
-            match value {
-                RedoxValue::String(_) => strings += 1,
-                RedoxValue::List(_) => lists += 1,
-                RedoxValue::Set(_) => sets += 1,
-                RedoxValue::Hash(_) => hashes += 1,
-                RedoxValue::SortedSet(_) => zsets += 1,
+        for shard in self.shards.iter() {
+            let shard = shard.read().await;
+            keys += shard.data.len() + shard.spilled.len();
+            spilled_keys += shard.spilled.len();
+            let resident = shard.data.values().map(|value| value.type_name());
+            for kind in resident.chain(shard.spilled.values().map(|spilled| spilled.kind)) {
+                match kind {
+                    "string" => strings += 1,
+                    "list" => lists += 1,
+                    "set" => sets += 1,
+                    "hash" => hashes += 1,
+                    "zset" => zsets += 1,
+                    "ReJSON-RL" => jsons += 1,
+                    "TSDB-TYPE" => timeseries += 1,
+                    _ => {}
+                }
             }
         }
         
         // 按字母顺序插入统计信息
         info.insert("hashes".to_string(), hashes.to_string());
-        info.insert("keys".to_string(), data.len().to_string());
+        info.insert("jsons".to_string(), jsons.to_string());
+        info.insert("evicted_keys".to_string(), self.evicted_keys.load(Ordering::Relaxed).to_string());
+        info.insert("keys".to_string(), keys.to_string());
+        info.insert("lazyfree_pending_objects".to_string(), self.lazyfree.pending().to_string());
+        info.insert("lazyfreed_objects".to_string(), self.lazyfree.freed().to_string());
         info.insert("lists".to_string(), lists.to_string());
+        let maxmemory = self.maxmemory.load(Ordering::Relaxed);
+        info.insert("maxmemory".to_string(), maxmemory.to_string());
+        info.insert("maxmemory_human".to_string(), memory::format_bytes(maxmemory));
+        info.insert("maxmemory_policy".to_string(), self.maxmemory_policy().as_str().to_string());
         info.insert("sets".to_string(), sets.to_string());
+        info.insert("spilled_keys".to_string(), spilled_keys.to_string());
         info.insert("strings".to_string(), strings.to_string());
+        info.insert("timeseries".to_string(), timeseries.to_string());
+        let used_memory = self.used_memory();
+        info.insert("used_memory".to_string(), used_memory.to_string());
+        info.insert("used_memory_human".to_string(), memory::format_bytes(used_memory));
         info.insert("zsets".to_string(), zsets.to_string());
+        info.extend(self.quotas.info());
+        if let Some(p) = &self.persistence {
+            info.insert("rdb_changes_since_last_save".to_string(), p.changes().to_string());
+            info.insert("rdb_last_save_time".to_string(), p.last_save().to_string());
+        }
         
         info
     }
 
     /// 删除一个或多个键
     /// 返回实际删除的键的数量
-    pub async fn del(&self, keys: &[String]) -> usize {
-        let mut data = self.data.lock().await;
+    pub async fn del(&self, keys: &[Bytes]) -> usize {
+        let mut shards = self.write_keys(keys).await;
         let mut count = 0;
         
         for key in keys {
-            if data.remove(key).is_some() {
+            let shard = shards.get_mut(key);
+            if shard.delete(key).is_some() {
+                shard.observers.notify(|observer| observer.on_delete(key));
                 count += 1;
             }
         }
         
-        if count > 0 {
-            self.mark_dirty();
+        count
+    }
+
+    /// 异步删除一个或多个键
+    /// 在锁内只摘除键，元素数超过惰性释放阈值的值交给后台线程释放，避免删除大集合时阻塞其他客户端
+    /// 
+    /// # Returns
+    /// 实际删除的键的数量
+    pub async fn unlink(&self, keys: &[Bytes]) -> usize {
+        let mut shards = self.write_keys(keys).await;
+        let mut removed = Vec::new();
+        for key in keys {
+            let shard = shards.get_mut(key);
+            if let Some(value) = shard.delete(key) {
+                shard.observers.notify(|observer| observer.on_delete(key));
+                removed.push(value);
+            }
+        }
+        drop(shards);
+
+        let count = removed.len();
+        for value in removed {
+            self.lazyfree.free(value);
         }
-        
         count
     }
 
-    pub async fn ttl(&self, key: &str) -> Option<i64> {
-        if let Some(p) = &self.persistence {
-            if let Some(expires) = p.get_expiry(key).await {
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                if now >= expires {
-                    return Some(-1);  // 已过期
+    /// 删除所有键，函数库不受影响
+    /// 同时锁定所有分片，只把各分片的内容整体摘除，释放在锁外进行
+    /// 
+    /// # Arguments
+    /// * `lazy` - 为 true 时（FLUSHALL ASYNC）在后台线程释放，否则释放完成后才返回
+    pub async fn flushall(&self, lazy: bool) {
+        let mut guards = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            guards.push(shard.write().await);
+        }
+        let taken: Vec<Shard> = guards.iter_mut().map(|shard| shard.take()).collect();
+        self.quotas.reset();
+        self.observers.notify(|observer| observer.on_flush());
+        drop(guards);
+
+        if lazy {
+            self.lazyfree.free_in_background(Box::new(taken));
+        } else {
+            drop(taken);
+        }
+    }
+
+    /// 获取键的完整值，用于 DUMP
+    /// 
+    /// # Returns
+    /// * `Some(Arc<RedoxValue>)` - 键的值，在锁外序列化
+    /// * `None` - 键不存在
+    pub async fn dump(&self, key: &[u8]) -> Option<Arc<RedoxValue>> {
+        self.get_if_not_expired(key).await
+    }
+
+    /// 读取键的值，不更新最后访问时间，用于 DEBUG OBJECT
+    /// 
+    /// # Returns
+    /// * `Some(Arc<RedoxValue>)` - 键的值
+    /// * `None` - 键不存在
+    pub async fn peek(&self, key: &[u8]) -> Option<Arc<RedoxValue>> {
+        self.read(key).await.get_shared(key).cloned()
+    }
+
+    /// 用 DUMP 得到的值重建键，用于 RESTORE
+    /// 
+    /// # Arguments
+    /// * `key` - 目标键
+    /// * `value` - 要写入的值
+    /// * `ttl_ms` - 过期毫秒数，0 表示不过期
+    /// * `replace` - 键已存在时是否覆盖
+    /// 
+    /// # Returns
+    /// * `Ok(())` - 写入成功
+    /// * `Err(RedoxError::BusyKey)` - 键已存在且未指定 REPLACE
+    pub async fn restore(&self, key: Bytes, value: RedoxValue, ttl_ms: u64, replace: bool) -> Result<(), RedoxError> {
+        let mut shard = self.write(&key).await;
+        if !replace && shard.data.contains_key(&key) {
+            return Err(RedoxError::BusyKey);
+        }
+        if ttl_ms > 0 {
+            shard.set_expire(key.clone(), self.now_ms().saturating_add(ttl_ms));
+        } else {
+            shard.clear_expire(&key);
+        }
+        shard.clear_field_expires(&key);
+        shard.data.insert(key, Arc::new(value));
+        shard.changed("restore");
+        Ok(())
+    }
+
+    /// 导入从 Redis 的 RDB 文件、主节点的全量同步等外部来源读取的数据，同名的键被覆盖
+    /// 
+    /// # Arguments
+    /// * `data` - 要导入的键值、过期时间和哈希表字段的过期时间（毫秒），函数库不导入
+    /// 
+    /// # Returns
+    /// 导入的键数，已经过期的键不导入
+    pub async fn import(&self, data: LoadedData) -> usize {
+        let now = self.now_ms();
+        let mut field_expiry: HashMap<Bytes, Vec<(Bytes, u64)>> = HashMap::new();
+        for (key, field, when) in data.field_expiry {
+            field_expiry.entry(key).or_default().push((field, when));
+        }
+        let mut count = 0;
+        for (key, value) in data.data {
+            let when = data.expiry.get(&key).copied();
+            if when.is_some_and(|when| when <= now) {
+                continue;
+            }
+            let mut shard = self.write(&key).await;
+            match when {
+                Some(when) => shard.set_expire(key.clone(), when),
+                None => {
+                    shard.clear_expire(&key);
                 }
-                return Some((expires - now) as i64);
             }
+            shard.clear_field_expires(&key);
+            for (field, when) in field_expiry.remove(&key).unwrap_or_default() {
+                shard.set_field_expire(key.clone(), field, when);
+            }
+            shard.data.insert(key, Arc::new(value));
+            shard.changed("import");
+            count += 1;
         }
-        None  // 键不存在或没有设置过期时间
+        count
     }
-    
-    pub async fn persist(&self, key: &str) -> bool {
-        if let Some(p) = &self.persistence {
-            if p.remove_expiry(key).await {
-                self.mark_dirty();
-                return true;
+
+    /// 读取键的值和过期时间，不更新最后访问时间，用于把修改过的键发送给副本
+    /// 
+    /// # Returns
+    /// * `Some(Entry)` - 键的当前状态
+    /// * `None` - 键不存在或已过期
+    pub async fn entry(&self, key: &[u8]) -> Option<Entry> {
+        self.read(key).await.entry(key)
+    }
+
+    /// 用主节点发送的状态替换键，副本应用复制的修改时使用，不检查内存上限和配额
+    /// 
+    /// # Arguments
+    /// * `key` - 键
+    /// * `entry` - 键在主节点上的值和过期时间
+    pub async fn set_entry(&self, key: Bytes, entry: Entry) {
+        let mut shard = self.write(&key).await;
+        match entry.expire_at {
+            Some(when) => shard.set_expire(key.clone(), when),
+            None => {
+                shard.clear_expire(&key);
             }
         }
-        false
+        shard.clear_field_expires(&key);
+        for (field, when) in entry.field_expires {
+            shard.set_field_expire(key.clone(), field, when);
+        }
+        shard.data.insert(key, entry.value);
+        shard.changed("replicate");
     }
 
-    // 在每次访问键时检查过期
-    async fn check_expired(&self, key: &str) -> bool {
-        if self.is_expired(key).await {
-            let mut data = self.data.lock().await;
-            data.remove(key);
-            self.mark_dirty();
-            true
+    /// 更新键的最后访问时间
+    /// 
+    /// # Returns
+    /// 存在的键的数量
+    pub async fn touch(&self, keys: &[Bytes]) -> usize {
+        let shards = self.read_keys(keys).await;
+        let mut count = 0;
+        for key in keys {
+            let shard = shards.get(key);
+            if shard.get(key).is_some() {
+                shard.touch(key);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// 存在的键的数量，不更新最后访问时间，用于集群模式下判断迁移中的槽的键是否还在本节点
+    pub async fn count_existing(&self, keys: &[&Bytes]) -> usize {
+        let mut count = 0;
+        for key in keys {
+            if self.shards[self.shard_index(key)].read().await.contains(key) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// 满足条件的未过期的键，最多 `limit` 个，用于 CLUSTER GETKEYSINSLOT 和 COUNTKEYSINSLOT
+    ///
+    /// # Arguments
+    /// * `limit` - 最多返回的键数
+    /// * `filter` - 键是否需要返回
+    pub async fn keys_where(&self, limit: usize, filter: impl Fn(&[u8]) -> bool) -> Vec<Bytes> {
+        let mut keys = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.read().await;
+            for key in shard.data.keys().chain(shard.spilled.keys()) {
+                if keys.len() >= limit {
+                    return keys;
+                }
+                if !shard.is_expired(key) && filter(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        keys
+    }
+
+    /// 增量遍历键，用于 SCAN
+    /// 游标的高 32 位是分片编号，低 32 位是分片中还没有检查的位置数，每个分片从后往前检查；
+    /// 删除键时 IndexMap 把最后一个键移到被删除的位置，这个键已经检查过，
+    /// 因此遍历期间一直存在的键都会被返回，被移动的键可能返回两次。
+    /// 值不在内存中的键排在分片中值在内存中的键之后；遍历期间在两者之间移动的键可能被漏掉或返回两次
+    ///
+    /// # Arguments
+    /// * `cursor` - 上一次返回的游标，0 表示开始新的遍历
+    /// * `count` - 这一次最多检查的键数，至少为 1
+    /// * `filter` - 返回的键需要满足的条件
+    ///
+    /// # Returns
+    /// (下一次使用的游标，遍历结束时为 0, 这一次找到的键)
+    pub async fn scan(&self, cursor: u64, count: usize, filter: impl Fn(&[u8]) -> bool) -> (u64, Vec<Bytes>) {
+        const START: u64 = u32::MAX as u64;
+        let (mut index, mut remaining) = match cursor {
+            0 => (0, START),
+            cursor => ((cursor >> 32) as usize, cursor & START),
+        };
+        let mut budget = count.max(1);
+        let mut keys = Vec::new();
+        while index < SHARD_COUNT && budget > 0 {
+            let shard = self.shards[index].read().await;
+            let resident = shard.data.len();
+            let mut position = (remaining as usize).min(resident + shard.spilled.len());
+            while position > 0 && budget > 0 {
+                position -= 1;
+                budget -= 1;
+                let key = match position.checked_sub(resident) {
+                    Some(offset) => shard.spilled.get_index(offset).map(|(key, _)| key),
+                    None => shard.data.get_index(position).map(|(key, _)| key),
+                };
+                let key = key.expect("position is within the shard");
+                if !shard.is_expired(key) && filter(key) {
+                    keys.push(key.clone());
+                }
+            }
+            if position > 0 {
+                return (((index as u64) << 32) | position as u64, keys);
+            }
+            index += 1;
+            remaining = START;
+        }
+        if index >= SHARD_COUNT {
+            (0, keys)
         } else {
-            false
+            (((index as u64) << 32) | START, keys)
+        }
+    }
+
+    /// 获取键的空闲时间（秒），即距最后一次读写或 TOUCH 的时间
+    /// 
+    /// # Returns
+    /// * `Some(u64)` - 空闲秒数，没有访问记录时从存储创建时开始计算
+    /// * `None` - 键不存在
+    pub async fn idle_time(&self, key: &[u8]) -> Option<u64> {
+        let shard = self.read(key).await;
+        shard.get(key)?;
+        let last = shard.last_access(key).unwrap_or(self.created_ms);
+        Some(self.now_ms().saturating_sub(last) / 1000)
+    }
+
+    /// 获取键的剩余生存时间（秒，四舍五入）
+    /// 
+    /// # Returns
+    /// * `Some(Some(u64))` - 剩余秒数
+    /// * `Some(None)` - 键存在但没有设置过期时间
+    /// * `None` - 键不存在或已过期
+    pub async fn ttl(&self, key: &[u8]) -> Option<Option<u64>> {
+        self.pttl(key).await
+            .map(|ms| ms.map(|ms| (ms + 500) / 1000))
+    }
+
+    /// 获取键的剩余生存时间（毫秒）
+    /// 
+    /// # Returns
+    /// * `Some(Some(u64))` - 剩余毫秒数
+    /// * `Some(None)` - 键存在但没有设置过期时间
+    /// * `None` - 键不存在，或已过期但还没有被删除
+    pub async fn pttl(&self, key: &[u8]) -> Option<Option<u64>> {
+        let shard = self.read(key).await;
+        shard.get(key)?;
+        let now = self.now_ms();
+        Some(shard.expires.get(key).map(|&expires| expires.saturating_sub(now)))
+    }
+    
+    pub async fn persist(&self, key: &[u8]) -> bool {
+        let mut shard = self.write(key).await;
+        if shard.clear_expire(key) {
+            shard.changed("persist");
+            return true;
         }
+        false
     }
 
-    // 添加定期清理任务
+    /// 定期清理到期的键
+    /// 一次清理后仍有到期的键时让出执行权后继续清理，其间其他任务可以获得分片的锁
     pub async fn start_cleanup_task(self) {
-        let interval = tokio::time::Duration::from_secs(10); // 每10秒清理一次
-        let mut interval = tokio::time::interval(interval);
-        
+        let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
         loop {
             interval.tick().await;
-            self.cleanup_expired().await;
+            while self.active_expire.load(Ordering::Relaxed) && self.cleanup_expired().await {
+                tokio::task::yield_now().await;
+            }
         }
     }
 }
@@ -604,4 +2746,9 @@ fn normalize_range(start: i64, stop: i64, len: i64) -> (usize, usize) {
     
     // 转换为 usize
     (start as usize, stop as usize)
-}
\ No newline at end of file
+}
+
+/// 键所在的分片编号
+fn shard_of(hasher: &RandomState, key: &[u8]) -> usize {
+    (hasher.hash_one(key) % SHARD_COUNT as u64) as usize
+}
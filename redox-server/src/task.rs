@@ -0,0 +1,30 @@
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+/// 启动一个带名称的异步任务
+///
+/// 启用 `console` 特性并以 `--cfg tokio_unstable` 编译时，任务名称会
+/// 出现在 tokio-console 中，便于定位卡住的任务和锁竞争；否则等同于 `tokio::spawn`
+///
+/// # Arguments
+/// * `name` - 任务名称（如 "auto-save"、"connection 127.0.0.1:50000"）
+/// * `future` - 要执行的异步任务
+pub fn spawn_named<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(feature = "console", tokio_unstable))]
+    {
+        tokio::task::Builder::new()
+            .name(name)
+            .spawn(future)
+            .expect("failed to spawn task")
+    }
+
+    #[cfg(not(all(feature = "console", tokio_unstable)))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
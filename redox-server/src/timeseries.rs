@@ -0,0 +1,107 @@
+//! 时间序列的样本管理和降采样聚合
+
+use redox_protocol::{TimeSeries, TsAggregation};
+
+/// 添加或覆盖一个样本，并按保留时长清理旧样本
+///
+/// # Returns
+/// * `Ok(())` - 添加成功
+/// * `Err(String)` - 时间戳早于保留窗口
+pub fn add(series: &mut TimeSeries, timestamp: u64, value: f64) -> Result<(), String> {
+    if let Some(cutoff) = retention_cutoff(series) {
+        if timestamp < cutoff {
+            return Err("Timestamp is older than retention".to_string());
+        }
+    }
+    series.samples.insert(timestamp, value);
+    apply_retention(series);
+    Ok(())
+}
+
+/// 在最新样本的基础上累加
+/// 时间戳与最新样本相同时原地更新，更晚时以累加后的值新增样本
+///
+/// # Returns
+/// * `Ok(u64)` - 写入样本的时间戳
+/// * `Err(String)` - 时间戳早于最新样本
+pub fn incr_by(series: &mut TimeSeries, timestamp: u64, increment: f64) -> Result<u64, String> {
+    let (last_ts, last_value) = series.samples
+        .iter()
+        .next_back()
+        .map(|(ts, v)| (*ts, *v))
+        .unwrap_or((0, 0.0));
+    if timestamp < last_ts {
+        return Err("Timestamp must be equal to or higher than the maximum existing timestamp".to_string());
+    }
+    add(series, timestamp, last_value + increment)?;
+    Ok(timestamp)
+}
+
+/// 删除保留窗口之外的样本，窗口以最新样本为基准
+pub fn apply_retention(series: &mut TimeSeries) {
+    if let Some(cutoff) = retention_cutoff(series) {
+        series.samples = series.samples.split_off(&cutoff);
+    }
+}
+
+/// 保留窗口的起点，未设置保留时长时为 None
+fn retention_cutoff(series: &TimeSeries) -> Option<u64> {
+    if series.retention_ms == 0 {
+        return None;
+    }
+    let last = *series.samples.keys().next_back()?;
+    Some(last.saturating_sub(series.retention_ms))
+}
+
+/// 查询时间范围内的样本，可选按固定时长的桶聚合
+///
+/// # Arguments
+/// * `from` - 起始时间戳（包含）
+/// * `to` - 结束时间戳（包含）
+/// * `aggregation` - (聚合方式, 桶时长毫秒)，桶从 0 开始对齐
+///
+/// # Returns
+/// (时间戳, 数值) 列表，聚合时时间戳为桶的起点
+pub fn range(
+    series: &TimeSeries,
+    from: u64,
+    to: u64,
+    aggregation: Option<(TsAggregation, u64)>,
+) -> Vec<(u64, f64)> {
+    if from > to {
+        return vec![];
+    }
+    let samples = series.samples.range(from..=to).map(|(ts, v)| (*ts, *v));
+
+    let Some((agg, bucket)) = aggregation else {
+        return samples.collect();
+    };
+
+    // (桶起点, 累加值, 样本数, 最小值, 最大值)
+    let mut buckets: Vec<(u64, f64, u64, f64, f64)> = Vec::new();
+    for (ts, value) in samples {
+        let start = ts - ts % bucket;
+        match buckets.last_mut() {
+            Some(current) if current.0 == start => {
+                current.1 += value;
+                current.2 += 1;
+                current.3 = current.3.min(value);
+                current.4 = current.4.max(value);
+            }
+            _ => buckets.push((start, value, 1, value, value)),
+        }
+    }
+
+    buckets.into_iter()
+        .map(|(start, sum, count, min, max)| {
+            let value = match agg {
+                TsAggregation::Avg => sum / count as f64,
+                TsAggregation::Min => min,
+                TsAggregation::Max => max,
+                TsAggregation::Sum => sum,
+                TsAggregation::Count => count as f64,
+            };
+            (start, value)
+        })
+        .collect()
+}
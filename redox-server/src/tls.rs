@@ -0,0 +1,34 @@
+//! TLS 支持
+//! 使用 rustls 加载 PEM 格式的证书链和私钥，接受的连接在握手完成后按普通连接处理。
+
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::sync::Arc;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// 根据证书和私钥文件创建 TLS 接受器
+///
+/// # Arguments
+/// * `cert_path` - PEM 格式的证书链文件，第一个证书为服务器证书
+/// * `key_path` - PEM 格式的私钥文件，支持 PKCS#8、PKCS#1 和 SEC1
+///
+/// # Returns
+/// * `Ok(TlsAcceptor)` - 创建成功
+/// * `Err(String)` - 文件无法读取、格式错误或证书与私钥不匹配
+pub fn load_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Error reading TLS certificate {}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert_path));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("Error reading TLS private key {}: {}", key_path, e))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
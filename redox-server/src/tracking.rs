@@ -0,0 +1,191 @@
+//! 客户端缓存的键追踪（CLIENT TRACKING）
+//! 开启追踪的连接执行只读命令时记录读取的键，这些键之后被任何连接写入、删除、过期或淘汰时，
+//! 服务器向这个连接推送一次 `invalidate key`，并停止追踪这个键，直到连接再次读取它；FLUSHALL 推送不带键的 `invalidate`，表示所有键失效。
+//! 推送是带外消息，只能用于可以区分推送和回复的连接：RESP3（`>` 类型）和二进制格式（`Response::Push`）。
+//! 修改通过存储的观察者得到；连接读取太慢、积压的通知超过上限时连接被关闭，客户端重新连接后需要清空缓存。
+
+use crate::observer::StorageObserver;
+use bytes::Bytes;
+use redox_protocol::{RedoxValue, Response};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_util::sync::CancellationToken;
+
+/// 每个追踪连接最多积压的失效通知数，超过时关闭连接
+pub const INVALIDATION_BACKLOG: usize = 4096;
+
+/// 失效通知的类型
+const INVALIDATE: &str = "invalidate";
+
+/// 所有开启追踪的连接和它们读取过的键，注册为存储的观察者
+pub struct Tracking {
+    table: Mutex<Table>,
+    /// 开启追踪的连接数，为 0 时修改事件不需要加锁查找
+    clients: AtomicUsize,
+    /// 下一个追踪连接的编号
+    next_id: AtomicU64,
+}
+
+/// 追踪表
+#[derive(Default)]
+struct Table {
+    /// 键到读取过它的连接
+    keys: HashMap<Bytes, HashSet<u64>>,
+    /// 连接的编号到它的通知队列和追踪的键
+    clients: HashMap<u64, Client>,
+}
+
+/// 一个追踪连接
+struct Client {
+    /// 发送给连接的失效通知
+    invalidations: mpsc::Sender<Response>,
+    /// 通知积压超过上限时取消，连接随之关闭
+    overflowed: CancellationToken,
+    /// 这个连接正在追踪的键，连接关闭时从追踪表中移除
+    keys: HashSet<Bytes>,
+}
+
+impl Client {
+    /// 把失效通知放入队列，队列已满时标记连接需要关闭
+    fn invalidate(&self, keys: Vec<Bytes>) {
+        let items = keys.into_iter().map(|key| Response::Value(RedoxValue::String(key))).collect();
+        let push = Response::Push { kind: INVALIDATE.to_string(), items };
+        if let Err(TrySendError::Full(_)) = self.invalidations.try_send(push) {
+            self.overflowed.cancel();
+        }
+    }
+}
+
+impl Tracking {
+    /// 创建没有追踪连接的实例
+    pub fn new() -> Self {
+        Self { table: Mutex::new(Table::default()), clients: AtomicUsize::new(0), next_id: AtomicU64::new(1) }
+    }
+
+    /// 为一个连接开启追踪
+    pub fn tracker(self: &Arc<Self>) -> Tracker {
+        let (invalidations, receiver) = mpsc::channel(INVALIDATION_BACKLOG);
+        let overflowed = CancellationToken::new();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let client = Client { invalidations, overflowed: overflowed.clone(), keys: HashSet::new() };
+        self.table.lock().unwrap().clients.insert(id, client);
+        self.clients.fetch_add(1, Ordering::Relaxed);
+        Tracker { tracking: self.clone(), id, receiver, overflowed }
+    }
+
+    /// 通知读取过这个键的连接，并停止追踪这个键
+    fn touch(&self, key: &[u8]) {
+        if self.clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut table = self.table.lock().unwrap();
+        let Some((key, ids)) = table.keys.remove_entry(key) else {
+            return;
+        };
+        for id in ids {
+            if let Some(client) = table.clients.get_mut(&id) {
+                client.keys.remove(&key);
+                client.invalidate(vec![key.clone()]);
+            }
+        }
+    }
+}
+
+impl Default for Tracking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageObserver for Tracking {
+    fn on_set(&self, key: &[u8], _event: &str) {
+        self.touch(key);
+    }
+
+    fn on_delete(&self, key: &[u8]) {
+        self.touch(key);
+    }
+
+    fn on_expire(&self, key: &[u8]) {
+        self.touch(key);
+    }
+
+    fn on_evict(&self, key: &[u8]) {
+        self.touch(key);
+    }
+
+    fn on_flush(&self) {
+        if self.clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut table = self.table.lock().unwrap();
+        table.keys.clear();
+        for client in table.clients.values_mut() {
+            client.keys.clear();
+            client.invalidate(Vec::new());
+        }
+    }
+}
+
+/// 一个连接的追踪状态，丢弃时关闭追踪并从追踪表中移除
+pub struct Tracker {
+    tracking: Arc<Tracking>,
+    /// 连接在追踪表中的编号
+    id: u64,
+    /// 等待发送的失效通知
+    receiver: mpsc::Receiver<Response>,
+    /// 通知积压超过上限时取消
+    overflowed: CancellationToken,
+}
+
+impl Tracker {
+    /// 记录连接读取的键，需要在读取之前调用，否则读取和记录之间的修改不会产生通知
+    pub fn track<'a>(&self, keys: impl IntoIterator<Item = &'a Bytes>) {
+        let mut table = self.tracking.table.lock().unwrap();
+        let table = &mut *table;
+        let Some(client) = table.clients.get_mut(&self.id) else {
+            return;
+        };
+        for key in keys {
+            if client.keys.insert(key.clone()) {
+                table.keys.entry(key.clone()).or_default().insert(self.id);
+            }
+        }
+    }
+
+    /// 等待下一条失效通知
+    ///
+    /// # Returns
+    /// * `Some(Response)` - 失效通知
+    /// * `None` - 积压的通知超过上限，连接需要关闭
+    pub async fn next(&mut self) -> Option<Response> {
+        tokio::select! {
+            push = self.receiver.recv() => push,
+            _ = self.overflowed.cancelled() => None,
+        }
+    }
+
+    /// 取出已经产生的一条失效通知，没有时为 None
+    pub fn try_next(&mut self) -> Option<Response> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        let mut table = self.tracking.table.lock().unwrap();
+        if let Some(client) = table.clients.remove(&self.id) {
+            for key in client.keys {
+                if let Some(ids) = table.keys.get_mut(&key) {
+                    ids.remove(&self.id);
+                    if ids.is_empty() {
+                        table.keys.remove(&key);
+                    }
+                }
+            }
+        }
+        self.tracking.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
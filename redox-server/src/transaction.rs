@@ -0,0 +1,144 @@
+//! 事务（MULTI / EXEC / DISCARD / WATCH / UNWATCH）
+//! MULTI 之后的数据命令不立即执行，回复 QUEUED 并排队，EXEC 时独占执行闸门依次执行，其他连接的命令不会穿插其中。
+//! 排队时出错（如参数错误、没有权限）的事务在 EXEC 时整体放弃，回复 EXECABORT；执行中某个命令出错不影响其他命令，与 Redis 相同。
+//! WATCH 的键在 EXEC 之前被任何连接写入、删除、过期或淘汰时，EXEC 不执行任何命令并回复 nil，客户端可以重新读取后重试。
+//! 修改通过存储的观察者得到，与具体的写命令无关。
+
+use crate::observer::StorageObserver;
+use bytes::Bytes;
+use redox_protocol::Command;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// 所有连接 WATCH 的键，注册为存储的观察者，键被修改时标记 WATCH 了它的连接
+pub struct Watches {
+    /// 键到 WATCH 了它的连接，值为连接的编号和修改标记
+    keys: Mutex<HashMap<Bytes, HashMap<u64, Arc<AtomicBool>>>>,
+    /// WATCH 的键数，为 0 时修改事件不需要加锁查找
+    watched: AtomicUsize,
+    /// 下一个 WATCH 的编号
+    next_id: AtomicU64,
+}
+
+impl Watches {
+    /// 创建没有 WATCH 的实例
+    pub fn new() -> Self {
+        Self { keys: Mutex::new(HashMap::new()), watched: AtomicUsize::new(0), next_id: AtomicU64::new(1) }
+    }
+
+    /// 创建一个连接的 WATCH，之后用 `Watch::add` 加入键
+    pub fn watch(self: &Arc<Self>) -> Watch {
+        Watch {
+            watches: self.clone(),
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            keys: HashSet::new(),
+            dirty: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// 标记 WATCH 了这个键的连接
+    fn touch(&self, key: &[u8]) {
+        if self.watched.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        if let Some(watchers) = self.keys.lock().unwrap().get(key) {
+            for dirty in watchers.values() {
+                dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Default for Watches {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageObserver for Watches {
+    fn on_set(&self, key: &[u8], _event: &str) {
+        self.touch(key);
+    }
+
+    fn on_delete(&self, key: &[u8]) {
+        self.touch(key);
+    }
+
+    fn on_expire(&self, key: &[u8]) {
+        self.touch(key);
+    }
+
+    fn on_evict(&self, key: &[u8]) {
+        self.touch(key);
+    }
+
+    fn on_flush(&self) {
+        if self.watched.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        for watchers in self.keys.lock().unwrap().values() {
+            for dirty in watchers.values() {
+                dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// 一个连接 WATCH 的键，丢弃时从 `Watches` 中移除
+pub struct Watch {
+    watches: Arc<Watches>,
+    /// 这个 WATCH 的编号
+    id: u64,
+    /// WATCH 的键
+    keys: HashSet<Bytes>,
+    /// 任何一个键被修改后为 true
+    dirty: Arc<AtomicBool>,
+}
+
+impl Watch {
+    /// 加入要 WATCH 的键，已经 WATCH 的键忽略
+    pub fn add(&mut self, keys: Vec<Bytes>) {
+        let mut registry = self.watches.keys.lock().unwrap();
+        for key in keys {
+            if self.keys.insert(key.clone()) {
+                registry.entry(key).or_default().insert(self.id, self.dirty.clone());
+                self.watches.watched.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// WATCH 之后是否有键被修改
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let mut registry = self.watches.keys.lock().unwrap();
+        for key in &self.keys {
+            if let Some(watchers) = registry.get_mut(key) {
+                watchers.remove(&self.id);
+                if watchers.is_empty() {
+                    registry.remove(key);
+                }
+            }
+        }
+        self.watches.watched.fetch_sub(self.keys.len(), Ordering::Relaxed);
+    }
+}
+
+/// MULTI 之后排队的命令
+#[derive(Default)]
+pub struct Transaction {
+    /// 排队的命令，EXEC 时按顺序执行
+    pub queued: Vec<Command>,
+    /// 排队时有命令出错，EXEC 时放弃整个事务
+    pub failed: bool,
+}
+
+/// 命令是否可以在事务中排队：只有数据命令可以，连接、脚本、订阅、管理等命令（`noscript` 标志）不可以
+pub fn is_queueable(cmd: &Command) -> bool {
+    !cmd.spec().has_flag("noscript")
+}
@@ -0,0 +1,31 @@
+//! 手动时钟：推进时间后键立即过期，不需要等待
+//! 只在以 `--features test-clock` 编译时运行
+
+#![cfg(feature = "test-clock")]
+
+use bytes::Bytes;
+use redox_client::Client;
+use redox_protocol::Command;
+use redox_server::{ManualClock, Server, Storage};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn keys_expire_when_the_manual_clock_advances() {
+    let clock = Arc::new(ManualClock::from_system());
+    let storage = Storage::with_clock(None, clock.clone());
+    let handle = Server::builder().storage(storage).bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut client = Client::connect(handle.addr()).await.unwrap();
+    let pttl = |key: &'static str| Command::PTTL { key: Bytes::from(key) };
+
+    client.set("session", "token").await.unwrap();
+    assert!(client.pexpire("session", Duration::from_millis(1500), None).await.unwrap());
+    // 时钟没有推进，剩余时间不会减少
+    assert_eq!(client.query::<i64>(&pttl("session")).await.unwrap(), 1500);
+
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(client.get::<Option<String>>("session").await.unwrap(), None);
+    assert_eq!(client.query::<i64>(&pttl("session")).await.unwrap(), -2);
+
+    handle.shutdown().await.unwrap();
+}
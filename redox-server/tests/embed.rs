@@ -0,0 +1,22 @@
+//! 在进程内运行服务器：`Server::builder()` 绑定系统分配的端口，通过 redox-client 执行命令后关闭
+
+use redox_client::Client;
+use redox_server::Server;
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn spawned_server_serves_commands_until_shutdown() {
+    let handle = Server::builder().bind("127.0.0.1:0").spawn().await.unwrap();
+    let addr = handle.addr();
+    assert!(addr.ip().is_loopback());
+    assert_ne!(addr.port(), 0);
+
+    let mut client = Client::connect(addr).await.unwrap();
+    client.set("greeting", "hello").await.unwrap();
+    assert_eq!(client.get::<Option<String>>("greeting").await.unwrap().as_deref(), Some("hello"));
+    assert_eq!(handle.storage().get_string(b"greeting").await.unwrap().as_deref(), Some(&b"hello"[..]));
+
+    drop(client);
+    handle.shutdown().await.unwrap();
+    assert!(TcpStream::connect(addr).await.is_err());
+}
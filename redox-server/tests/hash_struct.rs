@@ -0,0 +1,29 @@
+//! `Client::hset_struct` 写入的结构体可以用 `Client::hget_struct` 原样读出，值为 None 的字段会删除哈希表中的旧值
+
+use redox_client::{Client, RedoxHash};
+use redox_server::Server;
+
+#[derive(RedoxHash, Debug, PartialEq)]
+struct Profile {
+    name: String,
+    #[redox(rename = "mail")]
+    email: Option<String>,
+}
+
+#[tokio::test]
+async fn none_fields_clear_previous_values() {
+    let handle = Server::builder().bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut client = Client::connect(handle.addr()).await.unwrap();
+
+    let full = Profile { name: "alice".to_string(), email: Some("alice@example.com".to_string()) };
+    assert_eq!(client.hset_struct("profile", &full).await.unwrap(), 2);
+    assert_eq!(client.hget_struct::<Profile>("profile").await.unwrap(), Some(full));
+
+    let cleared = Profile { name: "alice".to_string(), email: None };
+    assert_eq!(client.hset_struct("profile", &cleared).await.unwrap(), 0);
+    assert_eq!(client.hget::<Option<String>>("profile", "mail").await.unwrap(), None);
+    assert_eq!(client.hget_struct::<Profile>("profile").await.unwrap(), Some(cleared));
+
+    drop(client);
+    handle.shutdown().await.unwrap();
+}
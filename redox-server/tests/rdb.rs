@@ -0,0 +1,172 @@
+//! 解析手工构造的 Redis RDB 文件：完整的小文件按 Redis 的编码还原每个键，
+//! 截断的文件、校验和不符的文件以及损坏的 LZF、ziplist 数据都返回错误而不是 panic
+
+use bytes::Bytes;
+use redox_protocol::RedoxValue;
+use redox_server::rdb;
+
+/// Redis 使用的 CRC64（Jones 多项式，反射输入输出，初始值 0）
+fn crc64(data: &[u8]) -> u64 {
+    data.iter().fold(0, |mut crc, &byte| {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x95ac9329ac4bc9b5 } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+/// 长度小于 64 的字符串
+fn string(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(bytes);
+}
+
+/// 格式版本 9 的 RDB 文件：魔数、版本、内容、EOF 和校验和
+fn dump(body: &[u8]) -> Vec<u8> {
+    let mut out = b"REDIS0009".to_vec();
+    out.extend_from_slice(body);
+    out.push(0xff);
+    let checksum = crc64(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// 一个键值对，`value` 是按 `value_type` 编码的值
+fn entry(value_type: u8, key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut out = vec![value_type];
+    string(&mut out, key);
+    out.extend_from_slice(value);
+    out
+}
+
+/// 哈希表 {"f": "v", "n": 5} 的 ziplist
+fn hash_ziplist() -> Vec<u8> {
+    let mut blob = Vec::new();
+    blob.extend_from_slice(&22u32.to_le_bytes());
+    blob.extend_from_slice(&19u32.to_le_bytes());
+    blob.extend_from_slice(&4u16.to_le_bytes());
+    blob.extend_from_slice(&[0, 0x01, b'f', 3, 0x01, b'v', 3, 0x01, b'n', 3, 0xf6, 0xff]);
+    blob
+}
+
+/// LZF 压缩的 "aaaaaaaaaa"：一个字面字节，之后从前 1 个字节处复制 9 个字节
+const LZF_AAAA: [u8; 5] = [0x00, b'a', 0xe0, 0x00, 0x00];
+
+/// LZF 格式的字符串
+fn lzf_string(compressed: &[u8], len: u8) -> Vec<u8> {
+    let mut out = vec![0xc3, compressed.len() as u8, len];
+    out.extend_from_slice(compressed);
+    out
+}
+
+/// 包含各种编码的小文件
+fn sample() -> Vec<u8> {
+    let mut body = vec![0xfa];
+    string(&mut body, b"redis-ver");
+    string(&mut body, b"7.2.4");
+    body.extend_from_slice(&[0xfe, 0x00, 0xfb, 0x05, 0x01]);
+    body.push(0xfc);
+    body.extend_from_slice(&1_700_000_000_000u64.to_le_bytes());
+    body.extend(entry(0, b"session", b"\x03abc"));
+    body.extend(entry(0, b"counter", &[0xc0, 123]));
+    body.extend(entry(0, b"compressed", &lzf_string(&LZF_AAAA, 10)));
+    let mut ziplist = vec![22];
+    ziplist.extend(hash_ziplist());
+    body.extend(entry(13, b"hash", &ziplist));
+    let mut intset = vec![12];
+    intset.extend_from_slice(&2u32.to_le_bytes());
+    intset.extend_from_slice(&2u32.to_le_bytes());
+    intset.extend_from_slice(&[0xff, 0xff, 0x02, 0x00]);
+    body.extend(entry(11, b"ints", &intset));
+    dump(&body)
+}
+
+fn string_value(value: Option<&RedoxValue>) -> &[u8] {
+    match value {
+        Some(RedoxValue::String(bytes)) => bytes,
+        other => panic!("expected a string, got {:?}", other),
+    }
+}
+
+#[test]
+fn crc64_matches_redis() {
+    assert_eq!(crc64(b"123456789"), 0xe9c6d914c4b8d9ca);
+}
+
+#[test]
+fn parses_a_small_dump() {
+    let loaded = rdb::parse(&sample()).unwrap();
+    assert_eq!(loaded.data.len(), 5);
+    assert_eq!(string_value(loaded.data.get(&b"session"[..])), b"abc");
+    assert_eq!(loaded.expiry.get(&b"session"[..]), Some(&1_700_000_000_000));
+    assert_eq!(loaded.expiry.len(), 1);
+    assert_eq!(string_value(loaded.data.get(&b"counter"[..])), b"123");
+    assert_eq!(string_value(loaded.data.get(&b"compressed"[..])), b"aaaaaaaaaa");
+    match loaded.data.get(&b"hash"[..]) {
+        Some(RedoxValue::Hash(hash)) => {
+            assert_eq!(hash.len(), 2);
+            assert_eq!(hash.get(&b"f"[..]), Some(&Bytes::from_static(b"v")));
+            assert_eq!(hash.get(&b"n"[..]), Some(&Bytes::from_static(b"5")));
+        }
+        other => panic!("expected a hash, got {:?}", other),
+    }
+    match loaded.data.get(&b"ints"[..]) {
+        Some(RedoxValue::Set(set)) => {
+            assert!(set.contains(&b"-1"[..]) && set.contains(&b"2"[..]) && set.len() == 2, "{:?}", set);
+        }
+        other => panic!("expected a set, got {:?}", other),
+    }
+}
+
+#[test]
+fn zero_checksum_is_not_verified() {
+    let mut content = sample();
+    let len = content.len();
+    content[len - 8..].fill(0);
+    assert_eq!(rdb::parse(&content).unwrap().data.len(), 5);
+}
+
+#[test]
+fn rejects_truncated_dumps() {
+    let content = sample();
+    for len in [0, 5, 9, 20, content.len() / 2, content.len() - 9, content.len() - 1] {
+        assert!(rdb::parse(&content[..len]).is_err(), "truncated to {} bytes", len);
+    }
+}
+
+#[test]
+fn rejects_a_bad_checksum() {
+    let mut content = sample();
+    let value = content.windows(3).position(|w| w == b"abc").unwrap();
+    content[value] = b'x';
+    let error = rdb::parse(&content).err().unwrap();
+    assert!(error.contains("checksum mismatch"), "{}", error);
+}
+
+#[test]
+fn rejects_corrupt_lzf_strings() {
+    // 向前引用的位置在已解压的数据之前
+    let backreference = [0xe0, 0x00, 0x00];
+    // 字面数据超过压缩数据的结尾
+    let literal = [0x05, b'a'];
+    for (compressed, len) in [(&backreference[..], 9), (&literal[..], 6), (&LZF_AAAA[..], 11), (&LZF_AAAA[..], 9)] {
+        let error = rdb::parse(&dump(&entry(0, b"key", &lzf_string(compressed, len)))).err().unwrap();
+        assert!(error.contains("corrupted LZF data"), "{}", error);
+    }
+}
+
+#[test]
+fn rejects_corrupt_ziplists() {
+    let mut invalid_encoding = hash_ziplist();
+    invalid_encoding[20] = 0xc1;
+    let mut missing_end = hash_ziplist();
+    missing_end.pop();
+    let mut overlong_string = hash_ziplist();
+    overlong_string[11] = 0x3f;
+    for blob in [invalid_encoding, missing_end, overlong_string, vec![0; 4]] {
+        let mut value = vec![blob.len() as u8];
+        value.extend(blob);
+        assert!(rdb::parse(&dump(&entry(13, b"hash", &value))).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! 脚本和函数中发起的命令按调用者的 ACL 权限检查，不能借 EVAL 或 FCALL 执行用户本身不能执行的命令
+
+use bytes::Bytes;
+use redox_client::{Client, ClientError};
+use redox_protocol::Command;
+use redox_server::Server;
+
+/// 只能读取和调用脚本、函数的用户
+const LIMITED_RULES: [&str; 6] = ["on", ">limited-pass", "~*", "+@read", "+eval", "+fcall"];
+
+fn eval(script: &str, key: &str) -> Command {
+    Command::Eval { script: script.to_string(), keys: vec![Bytes::copy_from_slice(key.as_bytes())], args: Vec::new() }
+}
+
+/// 命令返回的服务器错误的文本
+async fn server_error(client: &mut Client, cmd: &Command) -> String {
+    match client.execute(cmd).await {
+        Err(ClientError::Server(e)) => e.to_string(),
+        other => panic!("expected a server error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn script_commands_are_checked_against_the_caller() {
+    let handle = Server::builder().password("admin-pass").bind("127.0.0.1:0").spawn().await.unwrap();
+    let mut admin = Client::connect(handle.addr()).await.unwrap();
+    admin.auth(None, "admin-pass").await.unwrap();
+    let rules = LIMITED_RULES.iter().map(|rule| rule.to_string()).collect();
+    admin.execute(&Command::AclSetUser { username: "limited".to_string(), rules }).await.unwrap();
+    admin.execute(&Command::FunctionLoad {
+        library: "lib".to_string(),
+        code: r#"fn setkey(keys, args) { redis_call(["SET", keys[0], "from-function"]) }"#.to_string(),
+        replace: false,
+    }).await.unwrap();
+    admin.set("readable", "value").await.unwrap();
+
+    let mut limited = Client::connect(handle.addr()).await.unwrap();
+    limited.auth(Some("limited"), "limited-pass").await.unwrap();
+
+    let error = server_error(&mut limited, &eval("return redis.call('SET', KEYS[1], 'from-script')", "written")).await;
+    assert!(error.contains("NOPERM"), "{}", error);
+    let error = server_error(&mut limited, &eval("return redis.pcall('SET', KEYS[1], 'from-script')", "written")).await;
+    assert!(error.contains("NOPERM"), "{}", error);
+    let fcall = Command::FCall { function: "setkey".to_string(), keys: vec![Bytes::from_static(b"written")], args: Vec::new() };
+    let error = server_error(&mut limited, &fcall).await;
+    assert!(error.contains("NOPERM"), "{}", error);
+    assert_eq!(admin.get::<Option<String>>("written").await.unwrap(), None);
+
+    // 用户本身可以执行的命令在脚本中照常执行
+    let value: Option<String> = limited.query(&eval("return redis.call('GET', KEYS[1])", "readable")).await.unwrap();
+    assert_eq!(value.as_deref(), Some("value"));
+    admin.execute(&eval("return redis.call('SET', KEYS[1], 'from-script')", "written")).await.unwrap();
+    assert_eq!(admin.get::<Option<String>>("written").await.unwrap().as_deref(), Some("from-script"));
+
+    drop((admin, limited));
+    handle.shutdown().await.unwrap();
+}
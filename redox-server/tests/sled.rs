@@ -0,0 +1,67 @@
+//! sled 持久化后端：内存超过上限时值移到数据库中，读写时读回，保存后重启从数据库加载
+
+use redox_client::Client;
+use redox_server::persistence::BackendKind;
+use redox_server::{Config, Persistence, Server, Storage};
+use std::path::Path;
+
+/// 写入的键数和每个值的字节数，总量远超过内存上限
+const KEYS: usize = 200;
+const VALUE_LEN: usize = 1024;
+
+/// 在数据库目录上打开使用 sled 后端的存储
+fn open_storage(path: &Path) -> Storage {
+    let config = Config::default();
+    let backend = BackendKind::Sled.open(path.display().to_string(), 0, &config.s3).unwrap();
+    Storage::new(Some(Persistence::new(backend, Vec::new())))
+}
+
+fn value(i: usize) -> String {
+    format!("{:0width$}", i, width = VALUE_LEN)
+}
+
+/// 每个阶段使用单独的运行时，运行时关闭时服务器的任务和存储一起释放，第二个阶段可以重新打开数据库
+fn run<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap().block_on(future)
+}
+
+#[test]
+fn values_above_maxmemory_move_to_sled_and_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("redox-sled-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+
+    run(async {
+        let config = Config { maxmemory: 32 * 1024, ..Config::default() };
+        let handle = Server::builder().config(config).storage(open_storage(&path)).bind("127.0.0.1:0").spawn().await.unwrap();
+        let mut client = Client::connect(handle.addr()).await.unwrap();
+        for i in 0..KEYS {
+            client.set(format!("key:{}", i), value(i)).await.unwrap();
+        }
+        let info = handle.storage().info().await;
+        assert_eq!(info["keys"], KEYS.to_string());
+        assert!(info["spilled_keys"].parse::<usize>().unwrap() > KEYS / 2);
+        assert!(handle.storage().used_memory() <= 32 * 1024 + 2 * VALUE_LEN);
+
+        // 值不在内存中的键在读取时读回
+        for i in 0..KEYS {
+            assert_eq!(client.get::<Option<String>>(format!("key:{}", i)).await.unwrap(), Some(value(i)));
+        }
+        client.del(&["key:0"]).await.unwrap();
+        assert!(handle.storage().save_now().await.unwrap());
+        handle.shutdown().await.unwrap();
+    });
+
+    run(async {
+        let storage = open_storage(&path);
+        let info = storage.info().await;
+        assert_eq!(info["keys"], (KEYS - 1).to_string());
+        assert_eq!(info["spilled_keys"], (KEYS - 1).to_string());
+        assert_eq!(storage.get_string(b"key:0").await.unwrap(), None);
+        for i in 1..KEYS {
+            let key = format!("key:{}", i);
+            assert_eq!(storage.get_string(key.as_bytes()).await.unwrap().as_deref(), Some(value(i).as_bytes()));
+        }
+    });
+
+    let _ = std::fs::remove_dir_all(&path);
+}